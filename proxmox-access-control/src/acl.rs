@@ -3,7 +3,7 @@ use std::io::Write;
 use std::path::Path;
 use std::sync::{Arc, OnceLock, RwLock};
 
-use anyhow::{bail, Error};
+use anyhow::{bail, format_err, Error};
 
 use proxmox_auth_api::types::{Authid, Userid};
 use proxmox_config_digest::ConfigDigest;
@@ -27,6 +27,9 @@ pub fn split_acl_path(path: &str) -> Vec<&str> {
 }
 
 /// Tree representing a parsed acl.cfg
+///
+/// A tree parsed from a config keeps its raw lines, so writing it back preserves comments and the
+/// order of the lines, see [rewrite_canonical()](AclTree::rewrite_canonical()).
 #[derive(Default)]
 pub struct AclTree {
     /// Root node of the tree.
@@ -34,6 +37,29 @@ pub struct AclTree {
     /// The rest of the tree is available via [find_node()](AclTree::find_node()) or an
     /// [`AclTreeNode`]'s [children](AclTreeNode::children) member.
     pub root: AclTreeNode,
+    raw: Option<RawAclConfig>,
+}
+
+/// A single ACL: the node path (empty for the root), the user, token or `@group`, the role and
+/// the propagate flag.
+type AclEntry = (String, String, String, bool);
+
+/// The raw lines of a parsed acl.cfg.
+struct RawAclConfig {
+    lines: Vec<RawAclLine>,
+    /// The ACLs of the tree as parsed.
+    entries: BTreeSet<AclEntry>,
+}
+
+struct RawAclLine {
+    text: String,
+    /// The node path and ACLs of an `acl` line, `None` for comments and blank lines.
+    acl: Option<(String, Vec<AclEntry>)>,
+}
+
+/// The `root@pam` user is not saved, because it always has the 'Administrator' role.
+fn is_implicit_root(auth_id: &Authid) -> bool {
+    !auth_id.is_token() && auth_id.user() == "root@pam"
 }
 
 /// Node representing ACLs for a certain ACL path.
@@ -195,9 +221,17 @@ impl AclTree {
     pub fn new() -> Self {
         Self {
             root: AclTreeNode::new(),
+            raw: None,
         }
     }
 
+    /// Write the whole tree sorted when saving it, instead of only updating the changed lines.
+    ///
+    /// This drops comments and merges lines where possible.
+    pub fn rewrite_canonical(&mut self) {
+        self.raw = None;
+    }
+
     /// Iterates over the tree looking for a node matching `path`.
     pub fn find_node(&mut self, path: &str) -> Option<&mut AclTreeNode> {
         let path = split_acl_path(path);
@@ -310,12 +344,24 @@ impl AclTree {
     }
 
     fn write_node_config(node: &AclTreeNode, path: &str, w: &mut dyn Write) -> Result<(), Error> {
+        Self::write_node_acls(node, path, w)?;
+
+        for (name, child) in node.children.iter() {
+            let child_path = format!("{}/{}", path, name);
+            Self::write_node_config(child, &child_path, w)?;
+        }
+
+        Ok(())
+    }
+
+    /// Write the ACLs of `node` itself, without its children.
+    fn write_node_acls(node: &AclTreeNode, path: &str, w: &mut dyn Write) -> Result<(), Error> {
         let mut role_ug_map0: HashMap<_, BTreeSet<_>> = HashMap::new();
         let mut role_ug_map1: HashMap<_, BTreeSet<_>> = HashMap::new();
 
         for (auth_id, roles) in &node.users {
             // no need to save, because root is always 'Administrator'
-            if !auth_id.is_token() && auth_id.user() == "root@pam" {
+            if is_implicit_root(auth_id) {
                 continue;
             }
             for (role, propagate) in roles {
@@ -401,19 +447,84 @@ impl AclTree {
             )?;
         }
 
-        for (name, child) in node.children.iter() {
-            let child_path = format!("{}/{}", path, name);
-            Self::write_node_config(child, &child_path, w)?;
+        Ok(())
+    }
+
+    /// Write the ACLs in `entries`, which must all be on the node at `path`.
+    fn write_entries<'a, I>(path: &str, entries: I, w: &mut dyn Write) -> Result<(), Error>
+    where
+        I: IntoIterator<Item = &'a AclEntry>,
+    {
+        let mut node = AclTreeNode::new();
+        for (_, user_or_group, role, propagate) in entries {
+            if let Some(group) = user_or_group.strip_prefix('@') {
+                let roles = node.groups.entry(group.to_string()).or_default();
+                roles.insert(role.clone(), *propagate);
+            } else {
+                let roles = node.users.entry(user_or_group.parse()?).or_default();
+                roles.insert(role.clone(), *propagate);
+            }
         }
+        Self::write_node_acls(&node, path, w)
+    }
 
-        Ok(())
+    fn collect_entries(node: &AclTreeNode, path: &str, entries: &mut BTreeSet<AclEntry>) {
+        for (auth_id, roles) in &node.users {
+            if is_implicit_root(auth_id) {
+                continue;
+            }
+            for (role, propagate) in roles {
+                entries.insert((
+                    path.to_string(),
+                    auth_id.to_string(),
+                    role.clone(),
+                    *propagate,
+                ));
+            }
+        }
+        for (group, roles) in &node.groups {
+            for (role, propagate) in roles {
+                entries.insert((
+                    path.to_string(),
+                    format!("@{group}"),
+                    role.clone(),
+                    *propagate,
+                ));
+            }
+        }
+        for (name, child) in &node.children {
+            Self::collect_entries(child, &format!("{path}/{name}"), entries);
+        }
+    }
+
+    fn entries(&self) -> BTreeSet<AclEntry> {
+        let mut entries = BTreeSet::new();
+        Self::collect_entries(&self.root, "", &mut entries);
+        entries
     }
 
     fn write_config(&self, w: &mut dyn Write) -> Result<(), Error> {
+        if let Some(raw) = &self.raw {
+            let entries = self.entries();
+            let mut data = Vec::new();
+            raw.write_updated(&entries, &mut data)?;
+
+            // removing an ACL can uncover ACLs of earlier lines which were overridden by it, fall
+            // back to a canonical rewrite if the updated lines do not match the tree
+            let written = std::str::from_utf8(&data)
+                .map_err(Error::from)
+                .and_then(AclTree::from_raw);
+            if matches!(written, Ok(written) if written.entries() == entries) {
+                w.write_all(&data)?;
+                return Ok(());
+            }
+        }
+
         Self::write_node_config(&self.root, "", w)
     }
 
-    fn parse_acl_line(&mut self, line: &str) -> Result<(), Error> {
+    /// Parse a single `acl` line into the tree, returns the node path and the parsed ACLs.
+    fn parse_acl_line(&mut self, line: &str) -> Result<(String, Vec<AclEntry>), Error> {
         let items: Vec<&str> = line.split(':').collect();
 
         if items.len() != 5 {
@@ -434,12 +545,14 @@ impl AclTree {
 
         let path_str = items[2];
         let path = split_acl_path(path_str);
+        let node_path: String = path.iter().map(|comp| format!("/{comp}")).collect();
         let node = self.get_or_insert_node(&path);
 
         let uglist: Vec<&str> = items[3].split(',').map(|v| v.trim()).collect();
 
         let rolelist: Vec<&str> = items[4].split(',').map(|v| v.trim()).collect();
 
+        let mut entries = Vec::new();
         for user_or_group in &uglist {
             for role in &rolelist {
                 if !access_conf().roles().contains_key(role) {
@@ -447,18 +560,57 @@ impl AclTree {
                 }
                 if let Some(group) = user_or_group.strip_prefix('@') {
                     node.insert_group_role(group.to_string(), role.to_string(), propagate);
+                    entries.push((
+                        node_path.clone(),
+                        user_or_group.to_string(),
+                        role.to_string(),
+                        propagate,
+                    ));
                 } else {
-                    node.insert_user_role(user_or_group.parse()?, role.to_string(), propagate);
+                    let auth_id: Authid = user_or_group.parse()?;
+                    if !is_implicit_root(&auth_id) {
+                        entries.push((
+                            node_path.clone(),
+                            auth_id.to_string(),
+                            role.to_string(),
+                            propagate,
+                        ));
+                    }
+                    node.insert_user_role(auth_id, role.to_string(), propagate);
                 }
             }
         }
 
-        Ok(())
+        Ok((node_path, entries))
     }
 
-    fn load(filename: &Path) -> Result<(Self, ConfigDigest), Error> {
+    /// Parse the config, keeping its raw lines. Errors contain the line number.
+    fn parse(raw: &str) -> Result<Self, Error> {
         let mut tree = Self::new();
+        let mut lines = Vec::new();
+
+        for (linenr, text) in raw.lines().enumerate() {
+            let line = text.trim();
+            let acl = if line.is_empty() || line.starts_with('#') {
+                None
+            } else {
+                let acl = tree
+                    .parse_acl_line(line)
+                    .map_err(|err| format_err!("line {} - {}", linenr + 1, err))?;
+                Some(acl)
+            };
+            lines.push(RawAclLine {
+                text: text.to_string(),
+                acl,
+            });
+        }
 
+        let entries = tree.entries();
+        tree.raw = Some(RawAclConfig { lines, entries });
+        Ok(tree)
+    }
+
+    fn load(filename: &Path) -> Result<(Self, ConfigDigest), Error> {
         let raw = match std::fs::read_to_string(filename) {
             Ok(v) => v,
             Err(err) => {
@@ -470,43 +622,18 @@ impl AclTree {
             }
         };
 
+        // computed over the raw bytes, as comments and ordering are preserved when saving
         let digest = ConfigDigest::from_slice(raw.as_bytes());
 
-        for (linenr, line) in raw.lines().enumerate() {
-            let line = line.trim();
-            if line.is_empty() {
-                continue;
-            }
-            if let Err(err) = tree.parse_acl_line(line) {
-                bail!(
-                    "unable to parse acl config {:?}, line {} - {}",
-                    filename,
-                    linenr + 1,
-                    err
-                );
-            }
-        }
+        let tree = Self::parse(&raw)
+            .map_err(|err| format_err!("unable to parse acl config {:?}, {}", filename, err))?;
 
         Ok((tree, digest))
     }
 
     /// This is used for testing
     pub fn from_raw(raw: &str) -> Result<Self, Error> {
-        let mut tree = Self::new();
-        for (linenr, line) in raw.lines().enumerate() {
-            let line = line.trim();
-            if line.is_empty() {
-                continue;
-            }
-            if let Err(err) = tree.parse_acl_line(line) {
-                bail!(
-                    "unable to parse acl config data, line {} - {}",
-                    linenr + 1,
-                    err
-                );
-            }
-        }
-        Ok(tree)
+        Self::parse(raw).map_err(|err| format_err!("unable to parse acl config data, {}", err))
     }
 
     /// Returns a map of role name and propagation status for a given `auth_id` and `path`.
@@ -547,6 +674,72 @@ impl AclTree {
         role_map
     }
 
+    /// Returns the role maps for many paths at once, in the same order as `paths`.
+    ///
+    /// The result is the same as calling [roles()](AclTree::roles()) for each path, but the tree
+    /// is only traversed once for paths sharing a common prefix.
+    pub fn roles_batch(&self, auth_id: &Authid, paths: &[Vec<&str>]) -> Vec<HashMap<String, bool>> {
+        // path components may contain multiple sub-components, e.g. for namespaces
+        let paths: Vec<Vec<&str>> = paths
+            .iter()
+            .map(|path| path.iter().flat_map(|comp| comp.split('/')).collect())
+            .collect();
+
+        let mut order: Vec<usize> = (0..paths.len()).collect();
+        order.sort_by(|a, b| paths[*a].cmp(&paths[*b]));
+
+        // traversal state for each component of the current prefix: the node (if it exists), its
+        // propagated roles and the depth of the node whose roles apply below it
+        let mut prefix: Vec<&str> = Vec::new();
+        let mut stack: Vec<(Option<&AclTreeNode>, HashMap<String, bool>, usize)> =
+            vec![(Some(&self.root), self.root.extract_roles(auth_id, false), 0)];
+
+        let mut result = vec![HashMap::new(); paths.len()];
+
+        for index in order {
+            let Some((last, parents)) = paths[index].split_last() else {
+                result[index] = self.root.extract_roles(auth_id, true);
+                continue;
+            };
+
+            let common = prefix
+                .iter()
+                .zip(parents.iter())
+                .take_while(|(a, b)| a == b)
+                .count();
+            prefix.truncate(common);
+            stack.truncate(common + 1);
+
+            for comp in &parents[common..] {
+                let (parent, _, parent_depth) = &stack[stack.len() - 1];
+                let node = parent.and_then(|node| node.children.get(*comp));
+                let roles = node
+                    .map(|node| node.extract_roles(auth_id, false))
+                    .unwrap_or_default();
+                let depth = if roles.is_empty() {
+                    *parent_depth
+                } else {
+                    stack.len()
+                };
+                prefix.push(comp);
+                stack.push((node, roles, depth));
+            }
+
+            let (parent, _, parent_depth) = &stack[stack.len() - 1];
+            let roles = parent
+                .and_then(|node| node.children.get(*last))
+                .map(|node| node.extract_roles(auth_id, true))
+                .unwrap_or_default();
+            result[index] = if roles.is_empty() {
+                stack[*parent_depth].1.clone()
+            } else {
+                roles
+            };
+        }
+
+        result
+    }
+
     pub fn get_child_paths(&self, auth_id: &Authid, path: &[&str]) -> Result<Vec<String>, Error> {
         let mut res = Vec::new();
 
@@ -559,6 +752,58 @@ impl AclTree {
     }
 }
 
+impl RawAclConfig {
+    /// Write the raw lines, updating only the lines with ACLs which are not in `entries` anymore.
+    ///
+    /// New ACLs are written in place of the first updated line of their path, or appended to the
+    /// last line of their path. ACLs on new paths are appended at the end.
+    fn write_updated(&self, entries: &BTreeSet<AclEntry>, w: &mut dyn Write) -> Result<(), Error> {
+        let removed: BTreeSet<&AclEntry> = self.entries.difference(entries).collect();
+
+        let mut added: BTreeMap<&str, Vec<&AclEntry>> = BTreeMap::new();
+        for entry in entries.difference(&self.entries) {
+            added.entry(entry.0.as_str()).or_default().push(entry);
+        }
+
+        let mut last_lines = HashMap::new();
+        for (index, line) in self.lines.iter().enumerate() {
+            if let Some((path, _)) = &line.acl {
+                last_lines.insert(path.as_str(), index);
+            }
+        }
+
+        for (index, line) in self.lines.iter().enumerate() {
+            let Some((path, line_entries)) = &line.acl else {
+                writeln!(w, "{}", line.text)?;
+                continue;
+            };
+
+            if line_entries.iter().any(|entry| removed.contains(entry)) {
+                let mut remaining: Vec<&AclEntry> = line_entries
+                    .iter()
+                    .filter(|entry| !removed.contains(entry))
+                    .collect();
+                remaining.extend(added.remove(path.as_str()).unwrap_or_default());
+                AclTree::write_entries(path, remaining, w)?;
+            } else {
+                writeln!(w, "{}", line.text)?;
+            }
+
+            if last_lines.get(path.as_str()) == Some(&index) {
+                if let Some(new_entries) = added.remove(path.as_str()) {
+                    AclTree::write_entries(path, new_entries, w)?;
+                }
+            }
+        }
+
+        for (path, new_entries) in added {
+            AclTree::write_entries(path, new_entries, w)?;
+        }
+
+        Ok(())
+    }
+}
+
 /// Get exclusive lock
 pub fn lock_config() -> Result<ApiLockGuard, Error> {
     open_api_lockfile(acl_config_lock(), None, true)
@@ -642,13 +887,14 @@ pub fn save_config(acl: &AclTree) -> Result<(), Error> {
 }
 
 #[cfg(test)]
-mod test {
+pub(crate) mod test {
     use std::{collections::HashMap, sync::OnceLock};
 
     use crate::init::{init_access_config, AccessControlConfig};
 
     use super::AclTree;
     use anyhow::Error;
+    use proxmox_config_digest::ConfigDigest;
 
     use proxmox_auth_api::types::Authid;
 
@@ -675,7 +921,7 @@ mod test {
         }
     }
 
-    fn setup_acl_tree_config() {
+    pub(crate) fn setup_acl_tree_config() {
         static ACL_CONFIG: OnceLock<TestAcmConfig> = OnceLock::new();
         let config = ACL_CONFIG.get_or_init(|| {
             let mut roles = HashMap::new();
@@ -712,7 +958,7 @@ mod test {
     fn test_acl_line_compression() {
         setup_acl_tree_config();
 
-        let tree = AclTree::from_raw(
+        let mut tree = AclTree::from_raw(
             "\
             acl:0:/store/store2:user1@pbs:Admin\n\
             acl:0:/store/store2:user2@pbs:Admin\n\
@@ -721,6 +967,7 @@ mod test {
             ",
         )
         .expect("failed to parse acl tree");
+        tree.rewrite_canonical();
 
         let mut raw: Vec<u8> = Vec::new();
         tree.write_config(&mut raw)
@@ -733,6 +980,172 @@ mod test {
         );
     }
 
+    const COMMENTED_CONFIG: &str = "\
+# datastore admins
+acl:1:/datastore:user1@pbs:Admin
+acl:1:/datastore:@backup-operators,user2@pbs:DatastoreBackup
+
+# read-only access for monitoring
+acl:0:/:monitor@pbs!token:DatastoreReader
+acl:1:/datastore/store1:user3@pbs:DatastoreReader
+";
+
+    fn write_to_string(tree: &AclTree) -> String {
+        let mut raw: Vec<u8> = Vec::new();
+        tree.write_config(&mut raw)
+            .expect("failed to write acl tree");
+        String::from_utf8(raw).expect("acl tree is not valid utf8")
+    }
+
+    /// The lines of `old` and `new` which differ, requires the same number of lines.
+    fn changed_lines<'a>(old: &'a str, new: &'a str) -> Vec<(&'a str, &'a str)> {
+        assert_eq!(old.lines().count(), new.lines().count(), "\n{new}");
+        old.lines()
+            .zip(new.lines())
+            .filter(|(old, new)| old != new)
+            .collect()
+    }
+
+    #[test]
+    fn test_preserve_unchanged() -> Result<(), Error> {
+        setup_acl_tree_config();
+
+        let tree = AclTree::from_raw(COMMENTED_CONFIG)?;
+        assert_eq!(write_to_string(&tree), COMMENTED_CONFIG);
+
+        // the root user is never saved, but kept in unchanged lines
+        let raw = "acl:1:/:root@pam,user1@pbs:Admin\n";
+        let mut tree = AclTree::from_raw(raw)?;
+        assert_eq!(write_to_string(&tree), raw);
+        tree.insert_user_role("/", &"user2@pbs".parse()?, "Admin", true);
+        assert_eq!(
+            write_to_string(&tree),
+            "acl:1:/:root@pam,user1@pbs:Admin\nacl:1:/:user2@pbs:Admin\n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_preserve_single_role_change() -> Result<(), Error> {
+        setup_acl_tree_config();
+
+        let mut tree = AclTree::from_raw(COMMENTED_CONFIG)?;
+        let user1: Authid = "user1@pbs".parse()?;
+        tree.delete_user_role("/datastore", &user1, "Admin");
+        tree.insert_user_role("/datastore", &user1, "DatastoreReader", true);
+
+        let raw = write_to_string(&tree);
+        assert_eq!(
+            changed_lines(COMMENTED_CONFIG, &raw),
+            [(
+                "acl:1:/datastore:user1@pbs:Admin",
+                "acl:1:/datastore:user1@pbs:DatastoreReader"
+            )]
+        );
+
+        // changing the propagate flag
+        let mut tree = AclTree::from_raw(COMMENTED_CONFIG)?;
+        let token: Authid = "monitor@pbs!token".parse()?;
+        tree.insert_user_role("/", &token, "DatastoreReader", true);
+        let raw = write_to_string(&tree);
+        assert_eq!(
+            changed_lines(COMMENTED_CONFIG, &raw),
+            [(
+                "acl:0:/:monitor@pbs!token:DatastoreReader",
+                "acl:1:/:monitor@pbs!token:DatastoreReader"
+            )]
+        );
+
+        // a line with multiple users is split up
+        let mut tree = AclTree::from_raw(COMMENTED_CONFIG)?;
+        tree.delete_group_role("/datastore", "backup-operators", "DatastoreBackup");
+        let raw = write_to_string(&tree);
+        assert_eq!(
+            changed_lines(COMMENTED_CONFIG, &raw),
+            [(
+                "acl:1:/datastore:@backup-operators,user2@pbs:DatastoreBackup",
+                "acl:1:/datastore:user2@pbs:DatastoreBackup"
+            )]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_preserve_add_and_delete() -> Result<(), Error> {
+        setup_acl_tree_config();
+
+        let mut tree = AclTree::from_raw(COMMENTED_CONFIG)?;
+        let user4: Authid = "user4@pbs".parse()?;
+        tree.insert_user_role("/datastore", &user4, "DatastoreReader", false);
+        tree.insert_user_role("/remote", &user4, "Admin", true);
+        tree.delete_user_role(
+            "/datastore/store1",
+            &"user3@pbs".parse()?,
+            "DatastoreReader",
+        );
+
+        assert_eq!(
+            write_to_string(&tree),
+            "\
+# datastore admins
+acl:1:/datastore:user1@pbs:Admin
+acl:1:/datastore:@backup-operators,user2@pbs:DatastoreBackup
+acl:0:/datastore:user4@pbs:DatastoreReader
+
+# read-only access for monitoring
+acl:0:/:monitor@pbs!token:DatastoreReader
+acl:1:/remote:user4@pbs:Admin
+"
+        );
+
+        // removing an ACL which overrides an earlier line requires a canonical rewrite
+        let mut tree = AclTree::from_raw(
+            "\
+            # overridden below\n\
+            acl:1:/:user1@pbs:Admin\n\
+            acl:1:/:user1@pbs:NoAccess\n\
+            ",
+        )?;
+        tree.delete_user_role("/", &"user1@pbs".parse()?, "NoAccess");
+        assert_eq!(write_to_string(&tree), "");
+
+        let mut tree = AclTree::from_raw(COMMENTED_CONFIG)?;
+        tree.rewrite_canonical();
+        assert_eq!(
+            write_to_string(&tree),
+            "\
+acl:0:/:monitor@pbs!token:DatastoreReader
+acl:1:/datastore:@backup-operators,user2@pbs:DatastoreBackup
+acl:1:/datastore:user1@pbs:Admin
+acl:1:/datastore/store1:user3@pbs:DatastoreReader
+"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_digest() -> Result<(), Error> {
+        setup_acl_tree_config();
+
+        let path = std::env::temp_dir().join(format!(
+            "proxmox-access-control-acl-test-{}.cfg",
+            std::process::id()
+        ));
+        std::fs::write(&path, COMMENTED_CONFIG)?;
+        let (tree, digest) = AclTree::load(&path)?;
+        std::fs::remove_file(&path)?;
+
+        assert_eq!(
+            digest,
+            ConfigDigest::from_slice(COMMENTED_CONFIG.as_bytes())
+        );
+        assert_eq!(write_to_string(&tree), COMMENTED_CONFIG);
+        Ok(())
+    }
+
     #[test]
     fn test_roles_1() -> Result<(), Error> {
         setup_acl_tree_config();
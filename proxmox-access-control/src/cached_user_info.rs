@@ -5,7 +5,7 @@ use std::sync::{Arc, OnceLock, RwLock};
 use anyhow::{bail, Error};
 
 use proxmox_auth_api::types::{Authid, Userid};
-use proxmox_router::UserInformation;
+use proxmox_router::{privs_match, UserInformation};
 use proxmox_section_config::SectionConfigData;
 use proxmox_time::epoch_i64;
 
@@ -180,6 +180,43 @@ impl CachedUserInfo {
         (privs, propagated_privs)
     }
 
+    /// Returns the privileges of `auth_id` on many paths at once, in the same order as `paths`.
+    ///
+    /// The ACL tree is only traversed once for paths sharing a common prefix, this is faster than
+    /// calling [`lookup_privs`](Self::lookup_privs) for each path of a large list.
+    pub fn lookup_privs_batch(&self, auth_id: &Authid, paths: &[Vec<&str>]) -> Vec<u64> {
+        if self.is_superuser(auth_id) {
+            let acm_config = access_conf();
+            if let Some(admin) = acm_config.role_admin() {
+                if let Some(admin) = acm_config.roles().get(admin) {
+                    return vec![*admin; paths.len()];
+                }
+            }
+        }
+
+        let mut privs: Vec<u64> = self
+            .acl_tree
+            .roles_batch(auth_id, paths)
+            .into_iter()
+            .map(|roles| {
+                roles.keys().fold(0, |privs, role| {
+                    privs | access_conf().roles().get(role.as_str()).unwrap_or(&0)
+                })
+            })
+            .collect();
+
+        if auth_id.is_token() {
+            // limit privs to that of owning user
+            let user_auth_id = Authid::from(auth_id.user().clone());
+            let owner_privs = self.lookup_privs_batch(&user_auth_id, paths);
+            for (privs, owner_privs) in privs.iter_mut().zip(owner_privs) {
+                *privs &= owner_privs;
+            }
+        }
+
+        privs
+    }
+
     /// Checks whether the `auth_id` has any of the privileges `privs` on any object below `path`.
     pub fn any_privs_below(
         &self,
@@ -231,6 +268,23 @@ impl UserInformation for CachedUserInfo {
             Err(_) => 0,
         }
     }
+
+    fn check_privs_batch(
+        &self,
+        auth_id: &str,
+        paths: &[Vec<&str>],
+        required: u64,
+        partial: bool,
+    ) -> Vec<bool> {
+        let privs = match auth_id.parse::<Authid>() {
+            Ok(auth_id) => self.lookup_privs_batch(&auth_id, paths),
+            Err(_) => vec![0; paths.len()],
+        };
+        privs
+            .into_iter()
+            .map(|privs| privs_match(privs, required, partial))
+            .collect()
+    }
 }
 
 pub fn privs_to_priv_names(privs: u64) -> Vec<&'static str> {
@@ -244,3 +298,96 @@ pub fn privs_to_priv_names(privs: u64) -> Vec<&'static str> {
             priv_names
         })
 }
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use proxmox_auth_api::types::Authid;
+    use proxmox_router::UserInformation;
+    use proxmox_section_config::SectionConfigData;
+
+    use super::CachedUserInfo;
+    use crate::acl::test::setup_acl_tree_config;
+    use crate::acl::AclTree;
+
+    /// Small xorshift generator, good enough to create random ACL trees.
+    struct Rng(u64);
+
+    impl Rng {
+        fn below(&mut self, n: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % n as u64) as usize
+        }
+
+        fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+            items[self.below(items.len())]
+        }
+    }
+
+    const AUTH_IDS: &[&str] = &[
+        "user1@pbs",
+        "user2@pbs",
+        "user1@pbs!token",
+        "user2@pbs!token",
+    ];
+    const ROLES: &[&str] = &["NoAccess", "Admin", "DatastoreBackup", "DatastoreReader"];
+    const COMPONENTS: &[&str] = &["a", "b", "c", "a/b", ""];
+
+    fn random_path<'a>(rng: &mut Rng) -> Vec<&'a str> {
+        (0..rng.below(4)).map(|_| rng.pick(COMPONENTS)).collect()
+    }
+
+    #[test]
+    fn test_check_privs_batch() -> Result<(), anyhow::Error> {
+        setup_acl_tree_config();
+
+        let mut rng = Rng(0x2545f4914f6cdd1d);
+        for _ in 0..200 {
+            let mut raw = String::new();
+            for _ in 0..rng.below(12) {
+                raw.push_str(&format!(
+                    "acl:{}:/{}:{}:{}\n",
+                    rng.below(2),
+                    random_path(&mut rng).join("/"),
+                    rng.pick(AUTH_IDS),
+                    rng.pick(ROLES),
+                ));
+            }
+
+            let info = CachedUserInfo {
+                user_cfg: Arc::new(SectionConfigData::new()),
+                acl_tree: Arc::new(AclTree::from_raw(&raw)?),
+            };
+            let paths: Vec<Vec<&str>> = (0..rng.below(20)).map(|_| random_path(&mut rng)).collect();
+
+            for auth_id in AUTH_IDS.iter().chain(&["invalid"]) {
+                for (required, partial) in [(4, false), (12, false), (12, true), (0, false)] {
+                    let expected: Vec<bool> = paths
+                        .iter()
+                        .map(|path| {
+                            let privs = UserInformation::lookup_privs(&info, auth_id, path);
+                            proxmox_router::privs_match(privs, required, partial)
+                        })
+                        .collect();
+                    assert_eq!(
+                        info.check_privs_batch(auth_id, &paths, required, partial),
+                        expected,
+                        "\nfor '{auth_id}' on {paths:?} with acl:\n{raw}",
+                    );
+                }
+            }
+
+            let auth_id: Authid = "user1@pbs!token".parse()?;
+            let expected: Vec<u64> = paths
+                .iter()
+                .map(|path| info.lookup_privs(&auth_id, path))
+                .collect();
+            assert_eq!(info.lookup_privs_batch(&auth_id, &paths), expected);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,290 @@
+//! The configuration of the authentication realms, stored in `domains.cfg`.
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use anyhow::{bail, Error};
+
+use proxmox_auth_api::types::{Authid, PROXMOX_AUTH_REALM_SCHEMA};
+use proxmox_config_digest::ConfigDigest;
+use proxmox_product_config::{open_api_lockfile, replace_privileged_config, ApiLockGuard};
+use proxmox_schema::ApiType;
+use proxmox_section_config::{SectionConfig, SectionConfigData, SectionConfigPlugin};
+
+use crate::init::{access_conf, domains_config, domains_config_lock};
+use crate::types::{BuiltinRealmConfig, LdapRealmConfig, OpenIdRealmConfig, RealmType};
+
+fn get_or_init_config() -> &'static SectionConfig {
+    static CONFIG: OnceLock<SectionConfig> = OnceLock::new();
+    CONFIG.get_or_init(|| {
+        let mut config = SectionConfig::new(&PROXMOX_AUTH_REALM_SCHEMA);
+
+        let builtin_schema = BuiltinRealmConfig::API_SCHEMA.unwrap_object_schema();
+        for realm_type in [RealmType::Pam, RealmType::Pbs, RealmType::Pve] {
+            config.register_plugin(SectionConfigPlugin::new(
+                realm_type.as_str().to_string(),
+                Some("realm".to_string()),
+                builtin_schema,
+            ));
+        }
+
+        config.register_plugin(SectionConfigPlugin::new(
+            RealmType::Ldap.as_str().to_string(),
+            Some("realm".to_string()),
+            LdapRealmConfig::API_SCHEMA.unwrap_object_schema(),
+        ));
+
+        config.register_plugin(SectionConfigPlugin::new(
+            RealmType::OpenId.as_str().to_string(),
+            Some("realm".to_string()),
+            OpenIdRealmConfig::API_SCHEMA.unwrap_object_schema(),
+        ));
+
+        config
+    })
+}
+
+/// Get exclusive lock
+pub fn lock_config() -> Result<ApiLockGuard, Error> {
+    open_api_lockfile(domains_config_lock(), None, true)
+}
+
+/// Read the realm config, including the built-in realms added by
+/// [`AccessControlConfig::init_realm_config`](crate::init::AccessControlConfig::init_realm_config).
+pub fn config() -> Result<(SectionConfigData, ConfigDigest), Error> {
+    let content = proxmox_sys::fs::file_read_optional_string(domains_config())?.unwrap_or_default();
+
+    let digest = ConfigDigest::from_slice(content.as_bytes());
+    let mut data = get_or_init_config().parse(domains_config(), &content)?;
+
+    access_conf().init_realm_config(&mut data)?;
+
+    Ok((data, digest))
+}
+
+/// Write the realm config, fails if more than one realm is marked as default.
+pub fn save_config(config: &SectionConfigData) -> Result<(), Error> {
+    check_default_realm(config)?;
+
+    let config_file = domains_config();
+    let raw = get_or_init_config().write(&config_file, config)?;
+    replace_privileged_config(config_file, raw.as_bytes())
+}
+
+/// Lock and read the realm config, let `func` modify it and save it.
+///
+/// Fails without calling `func` if the config was modified since `expected_digest` was read.
+pub fn update_config<F>(expected_digest: Option<&ConfigDigest>, func: F) -> Result<(), Error>
+where
+    F: FnOnce(&mut SectionConfigData) -> Result<(), Error>,
+{
+    let _lock = lock_config()?;
+
+    let (mut config, digest) = config()?;
+    digest.detect_modification(expected_digest)?;
+
+    func(&mut config)?;
+
+    save_config(&config)
+}
+
+/// Get the type of a realm, `None` if no such realm exists.
+pub fn lookup_realm(realm: &str) -> Result<Option<RealmType>, Error> {
+    let (config, _digest) = config()?;
+    lookup_realm_in(&config, realm)
+}
+
+fn lookup_realm_in(config: &SectionConfigData, realm: &str) -> Result<Option<RealmType>, Error> {
+    match config.sections.get(realm) {
+        Some((section_type, _)) => Ok(Some(section_type.parse()?)),
+        None => Ok(None),
+    }
+}
+
+/// Parse an [`Authid`] and check that its realm exists.
+pub fn parse_authid(auth_id: &str) -> Result<Authid, Error> {
+    let auth_id: Authid = auth_id.parse()?;
+    let realm = auth_id.user().realm();
+    if lookup_realm(realm.as_str())?.is_none() {
+        bail!("unknown realm '{realm}'");
+    }
+    Ok(auth_id)
+}
+
+/// The realms marked as default, sorted by name.
+fn default_realms(config: &SectionConfigData) -> Vec<&str> {
+    let mut realms: Vec<&str> = config
+        .sections
+        .iter()
+        .filter(|(_, (_, data))| data["default"].as_bool().unwrap_or(false))
+        .map(|(realm, _)| realm.as_str())
+        .collect();
+    realms.sort_unstable();
+    realms
+}
+
+/// Get the realm marked as default, if any.
+pub fn default_realm(config: &SectionConfigData) -> Option<&str> {
+    // a config edited by hand may contain several, pick one deterministically
+    default_realms(config).first().copied()
+}
+
+/// Remove the default marker from all realms, e.g. before setting a new default realm.
+pub fn unset_default_realm(config: &mut SectionConfigData) {
+    for (_, data) in config.sections.values_mut() {
+        if let Some(map) = data.as_object_mut() {
+            map.remove("default");
+        }
+    }
+}
+
+fn check_default_realm(config: &SectionConfigData) -> Result<(), Error> {
+    let realms = default_realms(config);
+    if realms.len() > 1 {
+        bail!(
+            "only one realm can be the default, found {}",
+            realms.join(", ")
+        );
+    }
+    Ok(())
+}
+
+// shell completion helper
+pub fn complete_realm_name(_arg: &str, _param: &HashMap<String, String>) -> Vec<String> {
+    match config() {
+        Ok((data, _digest)) => data.sections.keys().map(|id| id.to_string()).collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use anyhow::Error;
+
+    use super::{
+        check_default_realm, default_realm, get_or_init_config, lookup_realm_in,
+        unset_default_realm,
+    };
+    use crate::types::{
+        BuiltinRealmConfig, LdapMode, LdapRealmConfig, OpenIdRealmConfig, RealmType,
+    };
+
+    const MIXED_CONFIG: &str = "\
+pam: pam
+\tcomment Linux PAM standard authentication
+
+pbs: pbs
+\tcomment Proxmox Backup authentication server
+\tdefault true
+
+ldap: company
+\tbase-dn ou=people,dc=example,dc=com
+\tbind-dn cn=admin,dc=example,dc=com
+\tmode ldaps
+\tserver1 ldap1.example.com
+\tserver2 192.0.2.10
+\tuser-attr uid
+\tverify true
+
+openid: sso
+\tautocreate true
+\tclient-id proxmox
+\tissuer-url https://auth.example.com/realms/main
+\tusername-claim email
+";
+
+    #[test]
+    fn test_mixed_config_round_trip() -> Result<(), Error> {
+        let config = get_or_init_config();
+        let data = config.parse("domains.cfg", MIXED_CONFIG)?;
+
+        assert_eq!(config.write("domains.cfg", &data)?, MIXED_CONFIG);
+
+        let pbs: BuiltinRealmConfig = data.lookup("pbs", "pbs")?;
+        assert_eq!(pbs.default, Some(true));
+
+        let ldap: LdapRealmConfig = data.lookup("ldap", "company")?;
+        assert_eq!(ldap.base_dn, "ou=people,dc=example,dc=com");
+        assert_eq!(ldap.server2.as_deref(), Some("192.0.2.10"));
+        assert_eq!(ldap.mode, Some(LdapMode::Ldaps));
+
+        let openid: OpenIdRealmConfig = data.lookup("openid", "sso")?;
+        assert_eq!(openid.client_id, "proxmox");
+        assert_eq!(openid.autocreate, Some(true));
+
+        assert_eq!(lookup_realm_in(&data, "pam")?, Some(RealmType::Pam));
+        assert_eq!(lookup_realm_in(&data, "company")?, Some(RealmType::Ldap));
+        assert_eq!(lookup_realm_in(&data, "sso")?, Some(RealmType::OpenId));
+        assert_eq!(lookup_realm_in(&data, "pve")?, None);
+
+        // typed sections written back produce the same config
+        let mut typed = proxmox_section_config::SectionConfigData::new();
+        for (realm, (section_type, _)) in data.order.iter().map(|id| (id, &data.sections[id])) {
+            match section_type.parse::<RealmType>()? {
+                RealmType::Ldap => typed.set_data(
+                    realm,
+                    section_type,
+                    data.lookup::<LdapRealmConfig>("ldap", realm)?,
+                )?,
+                RealmType::OpenId => typed.set_data(
+                    realm,
+                    section_type,
+                    data.lookup::<OpenIdRealmConfig>("openid", realm)?,
+                )?,
+                _ => typed.set_data(
+                    realm,
+                    section_type,
+                    data.lookup::<BuiltinRealmConfig>(section_type, realm)?,
+                )?,
+            }
+            typed.record_order(realm);
+        }
+        assert_eq!(config.write("domains.cfg", &typed)?, MIXED_CONFIG);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_realm_schema_validation() {
+        let config = get_or_init_config();
+
+        for raw in [
+            // missing base-dn
+            "ldap: company\n\tserver1 ldap.example.com\n\tuser-attr uid\n",
+            // invalid base-dn
+            "ldap: company\n\tbase-dn example.com\n\tserver1 ldap.example.com\n\tuser-attr uid\n",
+            // invalid mode
+            "ldap: company\n\tbase-dn dc=com\n\tmode tls\n\tserver1 ldap\n\tuser-attr uid\n",
+            // missing client-id
+            "openid: sso\n\tissuer-url https://auth.example.com\n",
+            // invalid issuer url
+            "openid: sso\n\tclient-id proxmox\n\tissuer-url auth.example.com\n",
+            // openid properties in a built-in realm
+            "pam: pam\n\tclient-id proxmox\n",
+            // unknown realm type
+            "ad: company\n\tcomment test\n",
+        ] {
+            assert!(config.parse("domains.cfg", raw).is_err(), "{raw}");
+        }
+    }
+
+    #[test]
+    fn test_default_realm() -> Result<(), Error> {
+        let config = get_or_init_config();
+        let mut data = config.parse("domains.cfg", MIXED_CONFIG)?;
+
+        assert_eq!(default_realm(&data), Some("pbs"));
+        check_default_realm(&data)?;
+
+        data.sections.get_mut("sso").unwrap().1["default"] = true.into();
+        assert!(check_default_realm(&data).is_err());
+
+        unset_default_realm(&mut data);
+        assert_eq!(default_realm(&data), None);
+        data.sections.get_mut("sso").unwrap().1["default"] = true.into();
+        check_default_realm(&data)?;
+        assert_eq!(default_realm(&data), Some("sso"));
+
+        Ok(())
+    }
+}
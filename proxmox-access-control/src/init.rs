@@ -7,6 +7,8 @@ use anyhow::{format_err, Error};
 use proxmox_auth_api::types::{Authid, Userid};
 use proxmox_section_config::SectionConfigData;
 
+use crate::types::RealmType;
+
 static ACCESS_CONF: OnceLock<&'static dyn AccessControlConfig> = OnceLock::new();
 static ACCESS_CONF_DIR: OnceLock<PathBuf> = OnceLock::new();
 
@@ -72,6 +74,21 @@ pub trait AccessControlConfig: Send + Sync {
         let _ = config;
         Ok(())
     }
+
+    /// Called after the realm configuration is loaded to add the built-in realms which are not
+    /// in the config file, such as the product's own realm.
+    ///
+    /// Default: Adds the `pam` realm if it is missing.
+    fn init_realm_config(&self, config: &mut SectionConfigData) -> Result<(), Error> {
+        if !config.sections.contains_key("pam") {
+            config.set_data(
+                "pam",
+                RealmType::Pam.as_str(),
+                serde_json::json!({ "realm": "pam" }),
+            )?;
+        }
+        Ok(())
+    }
 }
 
 pub fn init<P: AsRef<Path>>(
@@ -122,6 +139,14 @@ pub(crate) fn user_config_lock() -> PathBuf {
     conf_dir().join(".user.lck")
 }
 
+pub(crate) fn domains_config() -> PathBuf {
+    conf_dir().join("domains.cfg")
+}
+
+pub(crate) fn domains_config_lock() -> PathBuf {
+    conf_dir().join(".domains.lck")
+}
+
 pub(crate) fn token_shadow() -> PathBuf {
     conf_dir().join("token.shadow")
 }
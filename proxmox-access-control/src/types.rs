@@ -1,10 +1,14 @@
 use serde::{Deserialize, Serialize};
 
-use proxmox_auth_api::types::{Authid, Userid, PROXMOX_TOKEN_ID_SCHEMA};
+use anyhow::{bail, Error};
+
+use proxmox_auth_api::types::{Authid, Realm, Userid, PROXMOX_TOKEN_ID_SCHEMA};
 use proxmox_schema::{
     api,
-    api_types::{COMMENT_SCHEMA, SINGLE_LINE_COMMENT_FORMAT},
-    BooleanSchema, IntegerSchema, Schema, StringSchema, Updater,
+    api_types::{
+        COMMENT_SCHEMA, DNS_NAME_OR_IP_SCHEMA, HTTP_URL_SCHEMA, SINGLE_LINE_COMMENT_FORMAT,
+    },
+    ApiStringFormat, BooleanSchema, IntegerSchema, Schema, StringSchema, Updater,
 };
 
 pub const ENABLE_USER_SCHEMA: Schema = BooleanSchema::new(
@@ -192,3 +196,289 @@ impl User {
         true
     }
 }
+
+pub const REALM_DEFAULT_SCHEMA: Schema =
+    BooleanSchema::new("Use this realm as the default for the login dialog.")
+        .default(false)
+        .schema();
+
+/// Check that a string is an LDAP distinguished name, like `dc=example,dc=com`.
+fn verify_ldap_dn(dn: &str) -> Result<(), Error> {
+    for component in dn.split(',') {
+        match component.split_once('=') {
+            Some((attr, value))
+                if !attr.trim().is_empty()
+                    && !value.trim().is_empty()
+                    && attr
+                        .trim()
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '-') => {}
+            _ => bail!("invalid distinguished name component '{component}'"),
+        }
+    }
+    Ok(())
+}
+
+pub const LDAP_DN_FORMAT: ApiStringFormat = ApiStringFormat::VerifyFn(verify_ldap_dn);
+
+pub const LDAP_DN_SCHEMA: Schema = StringSchema::new("LDAP distinguished name.")
+    .format(&LDAP_DN_FORMAT)
+    .max_length(256)
+    .schema();
+
+pub const LDAP_USER_ATTR_SCHEMA: Schema =
+    StringSchema::new("LDAP attribute containing the user name.")
+        .format(&SINGLE_LINE_COMMENT_FORMAT)
+        .min_length(1)
+        .max_length(64)
+        .schema();
+
+pub const OPENID_CLIENT_ID_SCHEMA: Schema = StringSchema::new("OpenID client ID.")
+    .format(&SINGLE_LINE_COMMENT_FORMAT)
+    .min_length(1)
+    .max_length(256)
+    .schema();
+
+pub const OPENID_CLIENT_KEY_SCHEMA: Schema = StringSchema::new("OpenID client key.")
+    .format(&SINGLE_LINE_COMMENT_FORMAT)
+    .min_length(1)
+    .max_length(256)
+    .schema();
+
+pub const OPENID_SCOPES_SCHEMA: Schema =
+    StringSchema::new("OpenID scopes to request, separated by spaces.")
+        .format(&SINGLE_LINE_COMMENT_FORMAT)
+        .max_length(256)
+        .default("email profile")
+        .schema();
+
+pub const OPENID_USERNAME_CLAIM_SCHEMA: Schema =
+    StringSchema::new("OpenID claim used to generate the unique user name.")
+        .format(&SINGLE_LINE_COMMENT_FORMAT)
+        .min_length(1)
+        .max_length(64)
+        .schema();
+
+#[api]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+/// The type of an authentication realm.
+pub enum RealmType {
+    /// The Linux PAM realm.
+    Pam,
+    /// The built-in realm of Proxmox Backup Server.
+    Pbs,
+    /// The built-in realm of Proxmox VE.
+    Pve,
+    /// An LDAP server.
+    Ldap,
+    /// An OpenID Connect provider.
+    #[serde(rename = "openid")]
+    OpenId,
+}
+
+impl RealmType {
+    /// The section type of realms of this type in the realm config.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RealmType::Pam => "pam",
+            RealmType::Pbs => "pbs",
+            RealmType::Pve => "pve",
+            RealmType::Ldap => "ldap",
+            RealmType::OpenId => "openid",
+        }
+    }
+}
+
+impl std::str::FromStr for RealmType {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        Ok(match s {
+            "pam" => RealmType::Pam,
+            "pbs" => RealmType::Pbs,
+            "pve" => RealmType::Pve,
+            "ldap" => RealmType::Ldap,
+            "openid" => RealmType::OpenId,
+            _ => bail!("unknown realm type '{s}'"),
+        })
+    }
+}
+
+#[api(
+    properties: {
+        realm: {
+            type: Realm,
+        },
+        comment: {
+            optional: true,
+            schema: COMMENT_SCHEMA,
+        },
+        default: {
+            optional: true,
+            schema: REALM_DEFAULT_SCHEMA,
+        },
+    },
+)]
+#[derive(Serialize, Deserialize, Updater, Clone, Debug, PartialEq)]
+/// Config of a built-in realm, like `pam` or `pbs`.
+pub struct BuiltinRealmConfig {
+    #[updater(skip)]
+    pub realm: Realm,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default: Option<bool>,
+}
+
+#[api]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+/// The connection mode of an LDAP realm.
+pub enum LdapMode {
+    /// Unencrypted connection.
+    #[default]
+    Ldap,
+    /// Upgrade to TLS via STARTTLS.
+    StartTls,
+    /// TLS via LDAPS.
+    Ldaps,
+}
+
+#[api(
+    properties: {
+        realm: {
+            type: Realm,
+        },
+        server1: {
+            schema: DNS_NAME_OR_IP_SCHEMA,
+        },
+        server2: {
+            optional: true,
+            schema: DNS_NAME_OR_IP_SCHEMA,
+        },
+        port: {
+            optional: true,
+            description: "The port of the LDAP servers, depends on the mode if not set.",
+        },
+        "base-dn": {
+            schema: LDAP_DN_SCHEMA,
+        },
+        "user-attr": {
+            schema: LDAP_USER_ATTR_SCHEMA,
+        },
+        "bind-dn": {
+            optional: true,
+            schema: LDAP_DN_SCHEMA,
+        },
+        mode: {
+            type: LdapMode,
+            optional: true,
+        },
+        verify: {
+            optional: true,
+            default: false,
+        },
+        comment: {
+            optional: true,
+            schema: COMMENT_SCHEMA,
+        },
+        default: {
+            optional: true,
+            schema: REALM_DEFAULT_SCHEMA,
+        },
+    },
+)]
+#[derive(Serialize, Deserialize, Updater, Clone, Debug, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+/// Config of an LDAP realm.
+///
+/// The bind password is not part of the realm config, products store it separately.
+pub struct LdapRealmConfig {
+    #[updater(skip)]
+    pub realm: Realm,
+    /// The first LDAP server.
+    pub server1: String,
+    /// The fallback LDAP server.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub server2: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    /// The base DN of the users.
+    pub base_dn: String,
+    /// The attribute containing the user name.
+    pub user_attr: String,
+    /// The DN to bind with for user lookups, an anonymous bind is used if not set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bind_dn: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mode: Option<LdapMode>,
+    /// Verify the TLS certificate of the server.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verify: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default: Option<bool>,
+}
+
+#[api(
+    properties: {
+        realm: {
+            type: Realm,
+        },
+        "issuer-url": {
+            schema: HTTP_URL_SCHEMA,
+        },
+        "client-id": {
+            schema: OPENID_CLIENT_ID_SCHEMA,
+        },
+        "client-key": {
+            optional: true,
+            schema: OPENID_CLIENT_KEY_SCHEMA,
+        },
+        scopes: {
+            optional: true,
+            schema: OPENID_SCOPES_SCHEMA,
+        },
+        "username-claim": {
+            optional: true,
+            schema: OPENID_USERNAME_CLAIM_SCHEMA,
+        },
+        autocreate: {
+            optional: true,
+            default: false,
+        },
+        comment: {
+            optional: true,
+            schema: COMMENT_SCHEMA,
+        },
+        default: {
+            optional: true,
+            schema: REALM_DEFAULT_SCHEMA,
+        },
+    },
+)]
+#[derive(Serialize, Deserialize, Updater, Clone, Debug, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+/// Config of an OpenID Connect realm.
+pub struct OpenIdRealmConfig {
+    #[updater(skip)]
+    pub realm: Realm,
+    /// The URL of the OpenID issuer.
+    pub issuer_url: String,
+    pub client_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scopes: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username_claim: Option<String>,
+    /// Automatically create users if they do not exist.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub autocreate: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default: Option<bool>,
+}
@@ -1,15 +1,14 @@
 //! ACME account configuration helpers (load/save config)
 
-use std::fs::OpenOptions;
 use std::ops::ControlFlow;
-use std::os::unix::fs::OpenOptionsExt;
 use std::path::{Path, PathBuf};
 
 use anyhow::{bail, format_err, Error};
 use serde::{Deserialize, Serialize};
 
-use proxmox_product_config::replace_secret_config;
+use proxmox_product_config::{replace_secret_config, secret_create_options};
 use proxmox_sys::error::SysError;
+use proxmox_sys::fs::TempFile;
 
 use proxmox_schema::api_types::SAFE_ID_REGEX;
 
@@ -84,17 +83,27 @@ pub fn account_config_filename(name: &str) -> PathBuf {
     acme_account_dir().join(name)
 }
 
-pub(crate) fn foreach_acme_account<F>(mut func: F) -> Result<(), Error>
+/// Prefix of the file names of accounts marked as deactivated, see [`mark_account_deactivated`].
+const DEACTIVATED_PREFIX: &str = "_deactivated_";
+
+/// Call `func` for each account file in `dir`, along with whether the account is marked as
+/// deactivated. Deactivated accounts are only included if `include_deactivated` is set.
+pub(crate) fn foreach_acme_account<F>(
+    dir: &Path,
+    include_deactivated: bool,
+    mut func: F,
+) -> Result<(), Error>
 where
-    F: FnMut(AcmeAccountName) -> ControlFlow<Result<(), Error>>,
+    F: FnMut(AcmeAccountName, bool) -> ControlFlow<Result<(), Error>>,
 {
-    match proxmox_sys::fs::scan_subdir(-1, acme_account_dir(), &SAFE_ID_REGEX) {
+    match proxmox_sys::fs::scan_subdir(-1, dir, &SAFE_ID_REGEX) {
         Ok(files) => {
             for file in files {
                 let file = file?;
                 let file_name = unsafe { file.file_name_utf8_unchecked() };
 
-                if file_name.starts_with('_') {
+                let deactivated = file_name.starts_with(DEACTIVATED_PREFIX);
+                if file_name.starts_with('_') && !(deactivated && include_deactivated) {
                     continue;
                 }
 
@@ -103,7 +112,7 @@ where
                     Err(_) => continue,
                 };
 
-                if let ControlFlow::Break(result) = func(account_name) {
+                if let ControlFlow::Break(result) = func(account_name, deactivated) {
                     return result;
                 }
             }
@@ -118,7 +127,7 @@ where
 pub(crate) fn mark_account_deactivated(account_name: &str) -> Result<(), Error> {
     let from = account_config_filename(account_name);
     for i in 0..100 {
-        let to = account_config_filename(&format!("_deactivated_{}_{}", account_name, i));
+        let to = account_config_filename(&format!("{DEACTIVATED_PREFIX}{account_name}_{i}"));
         if !Path::new(&to).exists() {
             return std::fs::rename(&from, &to).map_err(|err| {
                 format_err!(
@@ -151,15 +160,13 @@ pub(crate) async fn load_account_config(account_name: &str) -> Result<AccountDat
             err
         ),
     };
-    let data: AccountData = serde_json::from_slice(&data).map_err(|err| {
-        format_err!(
-            "failed to parse acme account from {:?} - {}",
-            account_config_filename,
-            err
-        )
-    })?;
+    parse_account_config(&account_config_filename, &data)
+}
 
-    Ok(data)
+// Parse the contents of an ACME account file.
+pub(crate) fn parse_account_config(filename: &Path, data: &[u8]) -> Result<AccountData, Error> {
+    serde_json::from_slice(data)
+        .map_err(|err| format_err!("failed to parse acme account from {:?} - {}", filename, err))
 }
 
 // Save an new ACME account (fails if the file already exists).
@@ -168,12 +175,8 @@ pub(crate) fn create_account_config(
     account: &AccountData,
 ) -> Result<(), Error> {
     let account_config_filename = account_config_filename(account_name.as_ref());
-    let file = OpenOptions::new()
-        .write(true)
-        .create_new(true)
-        .mode(0o600)
-        .open(&account_config_filename)
-        .map_err(|err| {
+    let mut file =
+        TempFile::new_in(acme_account_dir(), secret_create_options()).map_err(|err| {
             format_err!(
                 "failed to open {:?} for writing: {}",
                 account_config_filename,
@@ -181,7 +184,8 @@ pub(crate) fn create_account_config(
             )
         })?;
 
-    serde_json::to_writer_pretty(file, account).map_err(|err| {
+    // a partially written account is removed along with the temporary file
+    serde_json::to_writer_pretty(&mut file, account).map_err(|err| {
         format_err!(
             "failed to write acme account to {:?}: {}",
             account_config_filename,
@@ -189,6 +193,8 @@ pub(crate) fn create_account_config(
         )
     })?;
 
+    file.persist_new(&account_config_filename, true)?;
+
     Ok(())
 }
 
@@ -0,0 +1,351 @@
+//! Storing certificates with their private key, without leaving mismatched pairs behind.
+
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, format_err, Error};
+use nix::sys::stat::Mode;
+use openssl::pkey::PKey;
+use openssl::x509::X509;
+
+use proxmox_log::error;
+use proxmox_sys::fs::{make_tmp_file, replace_file, CreateOptions};
+
+use crate::CertificateInfo;
+
+/// Where [`store_certificate`] stores a certificate and its private key.
+#[derive(Clone)]
+pub struct CertPaths {
+    /// The private key file.
+    pub key: PathBuf,
+    /// The certificate file.
+    pub cert: PathBuf,
+    /// The file for the intermediate certificates, they are appended to the certificate if unset.
+    pub chain: Option<PathBuf>,
+    /// Options for the key file, only readable by root by default.
+    pub key_options: CreateOptions,
+    /// Options for the certificate and chain files.
+    pub cert_options: CreateOptions,
+}
+
+impl CertPaths {
+    pub fn new<K: Into<PathBuf>, C: Into<PathBuf>>(key: K, cert: C) -> Self {
+        Self {
+            key: key.into(),
+            cert: cert.into(),
+            chain: None,
+            key_options: CreateOptions::new()
+                .perm(Mode::from_bits_truncate(0o600))
+                .root_only(),
+            cert_options: CreateOptions::new().perm(Mode::from_bits_truncate(0o644)),
+        }
+    }
+
+    pub fn chain<P: Into<PathBuf>>(mut self, chain: P) -> Self {
+        self.chain = Some(chain.into());
+        self
+    }
+
+    pub fn key_options(mut self, options: CreateOptions) -> Self {
+        self.key_options = options;
+        self
+    }
+
+    pub fn cert_options(mut self, options: CreateOptions) -> Self {
+        self.cert_options = options;
+        self
+    }
+}
+
+/// A file written to a temporary location to be renamed into place, or a file to be removed.
+struct StagedFile<'a> {
+    path: &'a Path,
+    tmp_path: Option<PathBuf>,
+    committed: bool,
+    previous: Option<Vec<u8>>,
+    options: &'a CreateOptions,
+}
+
+impl<'a> StagedFile<'a> {
+    fn new(path: &'a Path, data: &[u8], options: &'a CreateOptions) -> Result<Self, Error> {
+        let (mut file, tmp_path) = make_tmp_file(path, options.clone())?;
+        // removes the temporary file if writing fails
+        let staged = Self {
+            path,
+            tmp_path: Some(tmp_path),
+            committed: false,
+            previous: None,
+            options,
+        };
+        file.write_all(data)
+            .and_then(|()| file.sync_all())
+            .map_err(|err| format_err!("unable to write {path:?} - {err}"))?;
+
+        Ok(staged)
+    }
+
+    /// Stage the removal of a file, a missing file is no error.
+    fn remove(path: &'a Path, options: &'a CreateOptions) -> Self {
+        Self {
+            path,
+            tmp_path: None,
+            committed: false,
+            previous: None,
+            options,
+        }
+    }
+
+    fn commit(&mut self) -> Result<(), Error> {
+        if self.committed {
+            return Ok(());
+        }
+        self.previous = match std::fs::read(self.path) {
+            Ok(previous) => Some(previous),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+            Err(err) => bail!("unable to read {:?} - {err}", self.path),
+        };
+        match self.tmp_path.take() {
+            Some(tmp_path) => {
+                if let Err(err) = std::fs::rename(&tmp_path, self.path) {
+                    self.tmp_path = Some(tmp_path);
+                    bail!("unable to replace {:?} - {err}", self.path);
+                }
+            }
+            None if self.previous.is_some() => std::fs::remove_file(self.path)
+                .map_err(|err| format_err!("unable to remove {:?} - {err}", self.path))?,
+            None => (),
+        }
+        self.committed = true;
+        Ok(())
+    }
+
+    /// Restore the previous content of a committed file.
+    fn restore(&self) -> Result<(), Error> {
+        if !self.committed {
+            return Ok(());
+        }
+        match &self.previous {
+            Some(previous) => replace_file(self.path, previous, self.options.clone(), true),
+            None => match std::fs::remove_file(self.path) {
+                Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+                    bail!("unable to remove {:?} - {err}", self.path)
+                }
+                _ => Ok(()),
+            },
+        }
+    }
+}
+
+impl Drop for StagedFile<'_> {
+    fn drop(&mut self) {
+        if let Some(tmp_path) = &self.tmp_path {
+            let _ = std::fs::remove_file(tmp_path);
+        }
+    }
+}
+
+/// Restore the previous content of the committed files, in reverse order.
+fn restore_files(files: &[StagedFile]) {
+    for file in files.iter().rev() {
+        if let Err(err) = file.restore() {
+            error!("{err}");
+        }
+    }
+}
+
+fn sync_parent_dir(path: &Path) -> Result<(), Error> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    File::open(dir)
+        .and_then(|dir| dir.sync_all())
+        .map_err(|err| format_err!("unable to sync directory {dir:?} - {err}"))
+}
+
+/// Store a certificate with its private key and optionally its intermediate certificates.
+///
+/// The key has to match the first certificate of `cert_pem`. All files are written to temporary
+/// files first and then renamed into place, the key first. A chain file left from a previous
+/// certificate is removed if no chain is given. If that fails, the files are restored to their
+/// previous content. `post_deploy`, e.g. reloading the services using the certificate, is only
+/// called once all files are in place. If it fails, the previous files are restored and it is
+/// called once more to deploy them again.
+pub fn store_certificate(
+    paths: &CertPaths,
+    cert_pem: &[u8],
+    key_pem: &[u8],
+    chain_pem: Option<&[u8]>,
+    post_deploy: Option<&dyn Fn() -> Result<(), Error>>,
+) -> Result<(), Error> {
+    let certs = X509::stack_from_pem(cert_pem)
+        .map_err(|err| format_err!("unable to parse certificate - {err}"))?;
+    let Some(leaf) = certs.first() else {
+        bail!("no certificate given");
+    };
+    let key = PKey::private_key_from_pem(key_pem)
+        .map_err(|err| format_err!("unable to parse private key - {err}"))?;
+    if !leaf.public_key()?.public_eq(&key) {
+        bail!("private key does not match the certificate");
+    }
+
+    let mut cert_data = cert_pem.to_vec();
+    if let Some(chain_pem) = chain_pem {
+        X509::stack_from_pem(chain_pem)
+            .map_err(|err| format_err!("unable to parse certificate chain - {err}"))?;
+        if paths.chain.is_none() {
+            if !cert_data.ends_with(b"\n") {
+                cert_data.push(b'\n');
+            }
+            cert_data.extend_from_slice(chain_pem);
+        }
+    }
+
+    let mut staged = vec![
+        StagedFile::new(&paths.key, key_pem, &paths.key_options)?,
+        StagedFile::new(&paths.cert, &cert_data, &paths.cert_options)?,
+    ];
+    match (&paths.chain, chain_pem) {
+        (Some(path), Some(chain_pem)) => {
+            staged.push(StagedFile::new(path, chain_pem, &paths.cert_options)?)
+        }
+        (Some(path), None) => staged.push(StagedFile::remove(path, &paths.cert_options)),
+        (None, _) => (),
+    }
+
+    for pos in 0..staged.len() {
+        if let Err(err) = staged[pos].commit() {
+            restore_files(&staged[..pos]);
+            return Err(err);
+        }
+    }
+
+    for file in &staged {
+        sync_parent_dir(file.path)?;
+    }
+
+    if let Some(post_deploy) = post_deploy {
+        if let Err(err) = post_deploy() {
+            restore_files(&staged);
+            if let Err(redeploy_err) = post_deploy() {
+                error!("unable to deploy the previous certificate - {redeploy_err}");
+            }
+            bail!("previous certificate restored, {err}");
+        }
+    }
+
+    Ok(())
+}
+
+/// Get the information about a certificate, e.g. for status endpoints.
+///
+/// The `filename` of the returned info is empty.
+pub fn certificate_info(cert_pem: &[u8]) -> Result<CertificateInfo, Error> {
+    CertificateInfo::from_pem("", cert_pem)
+}
+
+#[cfg(test)]
+mod test {
+    use proxmox_sys::fs::TempDir;
+
+    use super::*;
+    use crate::create_self_signed_cert;
+
+    fn test_paths(dir: &Path) -> CertPaths {
+        CertPaths::new(dir.join("proxy.key"), dir.join("proxy.pem"))
+            .chain(dir.join("chain.pem"))
+            .key_options(CreateOptions::new().perm(Mode::from_bits_truncate(0o600)))
+    }
+
+    #[test]
+    fn test_store_certificate() -> Result<(), Error> {
+        let dir = TempDir::new_in("/tmp", None)?;
+        let paths = test_paths(dir.path());
+
+        let (key, cert) = create_self_signed_cert("test", "node1", None)?;
+        let key_pem = key.private_key_to_pem_pkcs8()?;
+        let cert_pem = cert.to_pem()?;
+        let (other_key, other_cert) = create_self_signed_cert("test", "node2", None)?;
+        let other_key_pem = other_key.private_key_to_pem_pkcs8()?;
+        let other_cert_pem = other_cert.to_pem()?;
+
+        let deployed = std::cell::Cell::new(0);
+        let post_deploy = || {
+            deployed.set(deployed.get() + 1);
+            Ok(())
+        };
+
+        // mismatched key and certificate are rejected
+        assert!(
+            store_certificate(&paths, &cert_pem, &other_key_pem, None, Some(&post_deploy)).is_err()
+        );
+        assert!(!paths.key.exists());
+        assert!(!paths.cert.exists());
+        assert_eq!(deployed.get(), 0);
+
+        store_certificate(&paths, &cert_pem, &key_pem, None, Some(&post_deploy))?;
+        assert_eq!(std::fs::read(&paths.key)?, key_pem);
+        assert_eq!(std::fs::read(&paths.cert)?, cert_pem);
+        assert_eq!(deployed.get(), 1);
+
+        // replacing the chain fails, the previous key and certificate are restored
+        std::fs::create_dir(paths.chain.as_ref().unwrap())?;
+        assert!(store_certificate(
+            &paths,
+            &other_cert_pem,
+            &other_key_pem,
+            Some(&cert_pem),
+            Some(&post_deploy),
+        )
+        .is_err());
+        assert_eq!(std::fs::read(&paths.key)?, key_pem);
+        assert_eq!(std::fs::read(&paths.cert)?, cert_pem);
+        assert_eq!(deployed.get(), 1);
+
+        // a stale chain file is removed
+        std::fs::remove_dir(paths.chain.as_ref().unwrap())?;
+        store_certificate(
+            &paths,
+            &other_cert_pem,
+            &other_key_pem,
+            Some(&cert_pem),
+            Some(&post_deploy),
+        )?;
+        assert_eq!(std::fs::read(paths.chain.as_ref().unwrap())?, cert_pem);
+        store_certificate(&paths, &cert_pem, &key_pem, None, Some(&post_deploy))?;
+        assert!(!paths.chain.as_ref().unwrap().exists());
+        assert_eq!(deployed.get(), 3);
+
+        // a failed deployment restores the previous files and deploys them again
+        let failing_deploy = || {
+            deployed.set(deployed.get() + 1);
+            match deployed.get() {
+                4 => bail!("reload failed"),
+                _ => Ok(()),
+            }
+        };
+        let err = store_certificate(
+            &paths,
+            &other_cert_pem,
+            &other_key_pem,
+            Some(&cert_pem),
+            Some(&failing_deploy),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("reload failed"), "{err}");
+        assert_eq!(std::fs::read(&paths.key)?, key_pem);
+        assert_eq!(std::fs::read(&paths.cert)?, cert_pem);
+        assert!(!paths.chain.as_ref().unwrap().exists());
+        assert_eq!(deployed.get(), 5);
+
+        // no temporary files are left behind
+        assert_eq!(std::fs::read_dir(dir.path())?.count(), 2);
+
+        let info = certificate_info(&cert_pem)?;
+        assert_eq!(info.filename, "");
+        assert!(info.subject.contains("node1"));
+
+        Ok(())
+    }
+}
@@ -46,3 +46,8 @@ pub(crate) mod acme_plugin;
 mod certificate_helpers;
 #[cfg(feature = "impl")]
 pub use certificate_helpers::{create_self_signed_cert, order_certificate, revoke_certificate};
+
+#[cfg(feature = "impl")]
+mod certificate_store;
+#[cfg(feature = "impl")]
+pub use certificate_store::{certificate_info, store_certificate, CertPaths};
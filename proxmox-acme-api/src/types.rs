@@ -9,7 +9,7 @@ use serde_json::Value;
 use proxmox_schema::api_types::{DNS_ALIAS_FORMAT, DNS_NAME_FORMAT, SAFE_ID_FORMAT};
 use proxmox_schema::{api, ApiStringFormat, ApiType, Schema, StringSchema, Updater};
 
-use proxmox_acme::types::AccountData as AcmeAccountData;
+use proxmox_acme::types::{AccountData as AcmeAccountData, AccountStatus};
 
 #[api(
     properties: {
@@ -344,12 +344,64 @@ pub enum DeletablePluginProperty {
 #[api(
     properties: {
         name: { type: AcmeAccountName },
+        status: {
+            type: AccountStatus,
+            optional: true,
+        },
+        contact: {
+            type: Array,
+            optional: true,
+            items: {
+                description: "Contact address, usually a 'mailto:' URI.",
+                type: String,
+            },
+        },
     },
 )]
 /// An ACME Account entry.
 ///
-/// Currently only contains a 'name' property.
+/// Only the name is set unless a detailed listing was requested.
 #[derive(Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
 pub struct AccountEntry {
     pub name: AcmeAccountName,
+
+    /// The ACME directory URL the account was created at.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub directory: Option<String>,
+
+    /// The account status, `deactivated` for accounts marked as deactivated locally.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub status: Option<AccountStatus>,
+
+    /// The contact addresses of the account.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub contact: Option<Vec<String>>,
+
+    /// Creation time of the account file (epoch), if supported by the file system.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub created: Option<i64>,
+
+    /// Last modification time of the account file (epoch).
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub modified: Option<i64>,
+
+    /// Error reading or parsing the account file.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub error: Option<String>,
+}
+
+impl AccountEntry {
+    /// Create an entry containing only the account name.
+    pub fn new(name: AcmeAccountName) -> Self {
+        Self {
+            name,
+            directory: None,
+            status: None,
+            contact: None,
+            created: None,
+            modified: None,
+            error: None,
+        }
+    }
 }
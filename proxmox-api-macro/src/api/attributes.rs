@@ -73,6 +73,9 @@ impl UpdaterFieldAttributes {
 pub struct EnumFieldAttributes {
     /// Change the "type-key" for this entry type..
     type_key: Option<syn::LitStr>,
+
+    /// Format of the values of a variant with data in a string enum, e.g. `"ceph-{}-test"`.
+    template: Option<syn::LitStr>,
 }
 
 impl EnumFieldAttributes {
@@ -99,6 +102,9 @@ impl EnumFieldAttributes {
         if path.is_ident("type_key") {
             util::duplicate(&self.type_key, path);
             self.type_key = Some(meta.value()?.parse()?);
+        } else if path.is_ident("template") {
+            util::duplicate(&self.template, path);
+            self.template = Some(meta.value()?.parse()?);
         } else {
             return Err(meta.error(format!("invalid api attribute: {path:?}")));
         }
@@ -109,4 +115,82 @@ impl EnumFieldAttributes {
     pub fn type_key(&self) -> Option<&syn::LitStr> {
         self.type_key.as_ref()
     }
+
+    pub fn template(&self) -> Option<&syn::LitStr> {
+        self.template.as_ref()
+    }
+}
+
+#[derive(Default)]
+pub struct StructFieldAttributes {
+    /// Ordering weight of this property when printing the object.
+    weight: Option<syn::Expr>,
+
+    /// Properties which have to be set along with this property.
+    requires: Option<syn::ExprArray>,
+
+    /// Properties which must not be set along with this property.
+    conflicts: Option<syn::ExprArray>,
+
+    /// Lower bound of a numeric field, also beyond the range of an `i64` for `u64` fields.
+    minimum: Option<(syn::Ident, syn::Expr)>,
+
+    /// Upper bound of a numeric field, also beyond the range of an `i64` for `u64` fields.
+    maximum: Option<(syn::Ident, syn::Expr)>,
+}
+
+impl StructFieldAttributes {
+    pub fn from_attributes(input: &mut Vec<syn::Attribute>) -> Self {
+        let mut this = Self::default();
+
+        for attr in std::mem::take(input) {
+            if attr.style != syn::AttrStyle::Outer || !attr.path().is_ident("api") {
+                input.push(attr);
+                continue;
+            }
+            match attr.parse_nested_meta(|meta| this.parse(meta)) {
+                Ok(()) => (),
+                Err(err) => crate::add_error(err),
+            }
+        }
+
+        this
+    }
+
+    fn parse(&mut self, meta: ParseNestedMeta<'_>) -> Result<(), syn::Error> {
+        let path = &meta.path;
+
+        if path.is_ident("weight") {
+            util::duplicate(&self.weight, path);
+            self.weight = Some(meta.value()?.parse()?);
+        } else if path.is_ident("requires") {
+            util::duplicate(&self.requires, path);
+            self.requires = Some(meta.value()?.parse()?);
+        } else if path.is_ident("conflicts") {
+            util::duplicate(&self.conflicts, path);
+            self.conflicts = Some(meta.value()?.parse()?);
+        } else if path.is_ident("minimum") || path.is_ident("maximum") {
+            let bound = match path.is_ident("minimum") {
+                true => &mut self.minimum,
+                false => &mut self.maximum,
+            };
+            util::duplicate(bound, path);
+            let ident = path.get_ident().unwrap().clone();
+            *bound = Some((ident, meta.value()?.parse()?));
+        } else {
+            return Err(meta.error(format!("invalid api attribute: {path:?}")));
+        }
+
+        Ok(())
+    }
+
+    /// Store the attributes in the object entry of the field.
+    pub fn apply_to(&self, entry: &mut super::ObjectEntry) {
+        entry.weight = self.weight.clone();
+        entry.requires = self.requires.clone();
+        entry.conflicts = self.conflicts.clone();
+        for (key, value) in self.minimum.iter().chain(&self.maximum) {
+            entry.schema.add_property(key, value.clone());
+        }
+    }
 }
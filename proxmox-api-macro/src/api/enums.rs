@@ -11,8 +11,9 @@ use super::Schema;
 use crate::serde;
 use crate::util::{self, FieldName, JSONObject, JSONValue, Maybe};
 
-/// Enums, provided they're simple enums, simply get an enum string schema attached to them.
-pub fn handle_enum(attribs: JSONObject, enum_ty: syn::ItemEnum) -> Result<TokenStream, Error> {
+/// Enums with only unit variants get an enum string schema attached to them, as do enums with
+/// templated variants. Enums with only newtype variants are SectionConfig style enums.
+pub fn handle_enum(attribs: JSONObject, mut enum_ty: syn::ItemEnum) -> Result<TokenStream, Error> {
     let mut first_unit = None;
     let mut first_unnamed = None;
     let mut first_named = None;
@@ -24,31 +25,30 @@ pub fn handle_enum(attribs: JSONObject, enum_ty: syn::ItemEnum) -> Result<TokenS
         }
     }
 
-    if first_unit.is_some() {
-        if let Some(conflict) = first_unnamed.or(first_named) {
-            bail!(
-                conflict,
-                "enums must be either with only unit types or only newtypes"
-            );
-        }
-        return handle_string_enum(attribs, enum_ty);
-    }
-
-    if first_unnamed.is_some() {
-        if let Some(conflict) = first_unit.or(first_named) {
-            bail!(
-                conflict,
-                "enums must be either with only unit types or only newtypes"
-            );
-        }
-        return handle_section_config_enum(attribs, enum_ty);
-    }
-
     if let Some(bad) = first_named {
         bail!(bad, "api type enums with named fields are not allowed");
     }
 
-    bail!(enum_ty => "api type enums must not be empty");
+    let field_attrs: Vec<EnumFieldAttributes> = enum_ty
+        .variants
+        .iter_mut()
+        .map(|variant| EnumFieldAttributes::from_attributes(&mut variant.attrs))
+        .collect();
+    let has_template = field_attrs.iter().any(|attrs| attrs.template().is_some());
+
+    match (first_unit, first_unnamed) {
+        (Some(_), None) => {
+            if let Some(attrs) = field_attrs.iter().find_map(|attrs| attrs.type_key()) {
+                bail!(attrs => "'type_key' is only supported for SectionConfig style enums");
+            }
+            handle_string_enum(attribs, enum_ty)
+        }
+        (None, Some(_)) if !has_template => {
+            handle_section_config_enum(attribs, enum_ty, field_attrs)
+        }
+        (_, Some(_)) => handle_templated_enum(attribs, enum_ty, field_attrs),
+        (None, None) => bail!(enum_ty => "api type enums must not be empty"),
+    }
 }
 
 /// Enums, provided they're simple enums, simply get an enum string schema attached to them.
@@ -159,7 +159,8 @@ fn handle_string_enum(
 
 fn handle_section_config_enum(
     mut attribs: JSONObject,
-    mut enum_ty: syn::ItemEnum,
+    enum_ty: syn::ItemEnum,
+    field_attrs: Vec<EnumFieldAttributes>,
 ) -> Result<TokenStream, Error> {
     let name = &enum_ty.ident;
 
@@ -203,7 +204,7 @@ fn handle_section_config_enum(
     let mut variants = TokenStream::new();
     let mut register_sections = TokenStream::new();
     let mut to_type = TokenStream::new();
-    for variant in &mut enum_ty.variants {
+    for (variant, field_attrs) in enum_ty.variants.iter().zip(field_attrs) {
         let field = match &variant.fields {
             syn::Fields::Unnamed(field) if field.unnamed.len() == 1 => &field.unnamed[0],
             _ => bail!(variant => "SectionConfig style enum can only have newtype variants"),
@@ -220,7 +221,6 @@ fn handle_section_config_enum(
             syn::LitStr::new(&name.to_string(), name.span())
         };
 
-        let field_attrs = EnumFieldAttributes::from_attributes(&mut variant.attrs);
         let with_type_key = if let Some(key) = field_attrs.type_key() {
             quote_spanned!(key.span() => .with_type_key(#key))
         } else {
@@ -298,3 +298,229 @@ fn handle_section_config_enum(
         }
     })
 }
+
+/// Enums with variants carrying string enum data, like `CephTest(CephReleaseCodename)`, get an
+/// enum string schema listing every value of every variant. The values of a variant with data
+/// are built from a template (`#[api(template = "ceph-{}-test")]`, by default the variant name
+/// followed by `-{}`), so the `Serialize` and `Deserialize` implementations are generated too.
+fn handle_templated_enum(
+    mut attribs: JSONObject,
+    mut enum_ty: syn::ItemEnum,
+    field_attrs: Vec<EnumFieldAttributes>,
+) -> Result<TokenStream, Error> {
+    for derive in ["Serialize", "Deserialize"] {
+        if util::derives_trait(&enum_ty.attrs, derive) {
+            bail!(
+                &enum_ty.ident =>
+                "enums with templated variants get a generated {derive} implementation"
+            );
+        }
+    }
+
+    if !attribs.contains_key("type") {
+        attribs.insert(
+            FieldName::new("type".to_string(), Span::call_site()),
+            JSONValue::new_ident(Ident::new("String", enum_ty.enum_token.span)),
+        );
+    }
+
+    if let Some(fmt) = attribs.remove("format") {
+        error!(fmt.span(), "illegal key 'format', will be autogenerated");
+    }
+
+    let has_default_attrib = attribs.get("default").map(|def| def.span());
+
+    let schema = {
+        let mut schema: Schema = attribs.try_into()?;
+
+        if schema.description.is_none() {
+            let (comment, span) = util::get_doc_comments(&enum_ty.attrs)?;
+            schema.description = Maybe::Derived(syn::LitStr::new(comment.trim(), span));
+        }
+
+        let mut ts = TokenStream::new();
+        schema.to_typed_schema(&mut ts)?;
+        ts
+    };
+
+    let container_attrs = serde::ContainerAttrib::try_from(&enum_ty.attrs[..])?;
+    enum_ty.attrs.retain(|attr| !attr.path().is_ident("serde"));
+    let derives_default = util::derives_trait(&enum_ty.attrs, "Default");
+    let mut default_value = None;
+
+    let mut entry_lists = TokenStream::new();
+    let mut list_names = Vec::new();
+    let mut serialize = TokenStream::new();
+    let mut deserialize_unit = TokenStream::new();
+    let mut deserialize_data = TokenStream::new();
+    for (index, (variant, field_attrs)) in enum_ty.variants.iter_mut().zip(field_attrs).enumerate()
+    {
+        if let Some(key) = field_attrs.type_key() {
+            error!(key => "'type_key' is only supported for SectionConfig style enums");
+        }
+
+        let (mut comment, _doc_span) = util::get_doc_comments(&variant.attrs)?;
+        if comment.is_empty() {
+            error!(&variant => "enum variant needs a description");
+            comment = "<missing description>".to_string();
+        }
+
+        let attrs = serde::VariantAttrib::try_from(&variant.attrs[..])?;
+        variant.attrs.retain(|attr| !attr.path().is_ident("serde"));
+        let variant_string = if let Some(renamed) = attrs.rename {
+            renamed
+        } else if let Some(rename_all) = container_attrs.rename_all {
+            let name = rename_all.apply_to_variant(&variant.ident.to_string());
+            syn::LitStr::new(&name, variant.ident.span())
+        } else {
+            let name = &variant.ident;
+            syn::LitStr::new(&name.to_string(), name.span())
+        };
+
+        let variant_ident = &variant.ident;
+        let list = quote::format_ident!("V{index}");
+        let span = variant.ident.span();
+
+        let ty = match &variant.fields {
+            syn::Fields::Unit => {
+                if let Some(template) = field_attrs.template() {
+                    error!(template => "unit variants cannot have a template");
+                }
+
+                if derives_default {
+                    if let Some(attr) = variant.attrs.iter().find(|a| a.path().is_ident("default"))
+                    {
+                        if let Some(default_value) = &default_value {
+                            error!(attr => "multiple default values defined");
+                            error!(default_value => "default previously defined here");
+                        } else {
+                            default_value = Some(variant_string.clone());
+                            if let Some(span) = has_default_attrib {
+                                error!(attr => "#[default] attribute in use with 'default' #[api] key");
+                                error!(span, "'default' also defined here");
+                            }
+                        }
+                    }
+                }
+
+                entry_lists.extend(quote_spanned! { span =>
+                    const #list: &[::proxmox_schema::EnumEntry] =
+                        &[::proxmox_schema::EnumEntry::new(#variant_string, #comment)];
+                });
+                serialize.extend(quote_spanned! { span =>
+                    Self::#variant_ident => serializer.serialize_str(#variant_string),
+                });
+                deserialize_unit.extend(quote_spanned! { span =>
+                    if value == #variant_string {
+                        return Ok(Self::#variant_ident);
+                    }
+                });
+                list_names.push(list);
+                continue;
+            }
+            syn::Fields::Unnamed(fields) if fields.unnamed.len() == 1 => &fields.unnamed[0].ty,
+            _ => bail!(variant => "templated enum variants must have exactly one field"),
+        };
+
+        let template = match field_attrs.template() {
+            Some(template) => template.clone(),
+            None => syn::LitStr::new(&format!("{}-{{}}", variant_string.value()), span),
+        };
+        let template_value = template.value();
+        let (prefix, suffix) = match template_value.split_once("{}") {
+            Some((prefix, suffix)) if !suffix.contains("{}") => (prefix, suffix),
+            _ => bail!(&template => "template must contain exactly one '{{}}' placeholder"),
+        };
+        let prefix = syn::LitStr::new(prefix, template.span());
+        let suffix = syn::LitStr::new(suffix, template.span());
+
+        let source = quote::format_ident!("V{index}_SOURCE");
+        let len = quote::format_ident!("V{index}_LEN");
+        let values = quote::format_ident!("V{index}_VALUES");
+        entry_lists.extend(quote_spanned! { span =>
+            const #source: &[::proxmox_schema::EnumEntry] =
+                tmpl::enum_entries(&<#ty as ::proxmox_schema::ApiType>::API_SCHEMA);
+            const #len: usize = tmpl::values_len(#prefix, #suffix, #source);
+            const #values: &[u8] = &tmpl::values::<#len>(#prefix, #suffix, #source);
+            const #list: &[::proxmox_schema::EnumEntry] = &tmpl::entries::<{ #source.len() }>(
+                #values,
+                #prefix,
+                #suffix,
+                #source,
+                #comment,
+            );
+        });
+        serialize.extend(quote_spanned! { span =>
+            Self::#variant_ident(data) => serializer.serialize_str(
+                &::proxmox_schema::variant_template::format_value(#prefix, data, #suffix)
+                    .map_err(::serde::ser::Error::custom)?,
+            ),
+        });
+        deserialize_data.extend(quote_spanned! { span =>
+            if let Some(data) =
+                ::proxmox_schema::variant_template::parse_value(&value, #prefix, #suffix)
+            {
+                return Ok(Self::#variant_ident(data));
+            }
+        });
+        list_names.push(list);
+    }
+
+    let name = &enum_ty.ident;
+
+    let default_value = match default_value {
+        Some(value) => quote_spanned!(value.span() => .default(#value)),
+        None => TokenStream::new(),
+    };
+
+    Ok(quote_spanned! { name.span() =>
+        #enum_ty
+
+        impl ::proxmox_schema::ApiType for #name {
+            const API_SCHEMA: ::proxmox_schema::Schema = {
+                use ::proxmox_schema::variant_template as tmpl;
+
+                #entry_lists
+
+                const COUNT: usize = #(#list_names.len())+*;
+                const ENTRIES: &[::proxmox_schema::EnumEntry] =
+                    &tmpl::concat::<COUNT>(&[#(#list_names),*]);
+
+                #schema
+                    .format(&::proxmox_schema::ApiStringFormat::Enum(ENTRIES))
+                    #default_value
+                    .schema()
+            };
+        }
+
+        impl ::proxmox_schema::UpdaterType for #name {
+            type Updater = Option<Self>;
+        }
+
+        impl ::serde::Serialize for #name {
+            fn serialize<S>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error>
+            where
+                S: ::serde::Serializer,
+            {
+                match self {
+                    #serialize
+                }
+            }
+        }
+
+        impl<'de> ::serde::Deserialize<'de> for #name {
+            fn deserialize<D>(deserializer: D) -> ::std::result::Result<Self, D::Error>
+            where
+                D: ::serde::Deserializer<'de>,
+            {
+                let value =
+                    <::std::string::String as ::serde::Deserialize>::deserialize(deserializer)?;
+                #deserialize_unit
+                #deserialize_data
+                Err(::serde::de::Error::custom(::std::format!(
+                    "unknown variant '{value}'"
+                )))
+            }
+        }
+    })
+}
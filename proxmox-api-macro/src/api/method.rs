@@ -233,6 +233,30 @@ pub fn handle_method(mut attribs: JSONObject, func: syn::ItemFn) -> Result<Token
         None => TokenStream::new(),
     };
 
+    let cache_setter = match attribs.remove("cache") {
+        Some(cache) => {
+            let cache: syn::Expr = cache.try_into()?;
+            quote_spanned! { cache.span() => .cache(#cache) }
+        }
+        None => TokenStream::new(),
+    };
+
+    let expected_duration_setter = match attribs.remove("expected_duration") {
+        Some(duration) => {
+            let duration: syn::Expr = duration.try_into()?;
+            quote_spanned! { duration.span() => .expected_duration(#duration) }
+        }
+        None => TokenStream::new(),
+    };
+
+    let max_response_size_setter = match attribs.remove("max_response_size") {
+        Some(size) => {
+            let size: syn::Expr = size.try_into()?;
+            quote_spanned! { size.span() => .max_response_size(#size) }
+        }
+        None => TokenStream::new(),
+    };
+
     let reload_timezone: bool = attribs
         .remove("reload_timezone")
         .map(TryFrom::try_from)
@@ -328,6 +352,9 @@ pub fn handle_method(mut attribs: JSONObject, func: syn::ItemFn) -> Result<Token
             )
             #returns_schema_setter
             #access_setter
+            #cache_setter
+            #expected_duration_setter
+            #max_response_size_setter
             .reload_timezone(#reload_timezone)
             .protected(#protected);
 
@@ -41,9 +41,10 @@ pub const INTTYPES: &[IntType] = &[
     IntType { name: "u8",      minimum: Some("0"),           maximum: Some("0xff"),       },
     IntType { name: "u16",     minimum: Some("0"),           maximum: Some("0xffff"),     },
     IntType { name: "u32",     minimum: Some("0"),           maximum: Some("0xffffffff"), },
-    IntType { name: "u64",     minimum: Some("0"),           maximum: None,               },
     IntType { name: "usize",   minimum: Some("0"),           maximum: None,               },
 ];
+/// Unsigned 64 bit integers exceed the range of `IntegerSchema` and use a `Uint64Schema`.
+pub const UINT64NAMES: &[&str] = &["u64"];
 pub const NUMBERNAMES: &[&str] = &["Number", "f32", "f64"];
 
 /// The main `Schema` type.
@@ -222,6 +223,16 @@ impl Schema {
                 .push((Ident::new(key, Span::call_site()), value));
         }
     }
+
+    /// Add a builder-pattern property from a field attribute, which must not be set in the schema
+    /// already.
+    pub fn add_property(&mut self, key: &Ident, value: syn::Expr) {
+        if self.find_schema_property(&key.to_string()).is_some() {
+            error!(key => "'{}' is already set in the schema", key);
+        } else {
+            self.properties.push((key.clone(), value));
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -229,6 +240,7 @@ pub enum SchemaItem {
     Null(Span),
     Boolean(Span),
     Integer(Span),
+    Uint64(Span),
     Number(Span),
     String(Span),
     Object(SchemaObject),
@@ -244,6 +256,7 @@ impl SchemaItem {
             SchemaItem::Null(span) => *span,
             SchemaItem::Boolean(span) => *span,
             SchemaItem::Integer(span) => *span,
+            SchemaItem::Uint64(span) => *span,
             SchemaItem::Number(span) => *span,
             SchemaItem::String(span) => *span,
             SchemaItem::Object(inner) => inner.span,
@@ -296,6 +309,8 @@ impl SchemaItem {
             Ok(SchemaItem::Boolean(ty.span()))
         } else if INTTYPES.iter().any(|n| name == n.name) {
             Ok(SchemaItem::Integer(ty.span()))
+        } else if UINT64NAMES.iter().any(|n| name == n) {
+            Ok(SchemaItem::Uint64(ty.span()))
         } else if NUMBERNAMES.iter().any(|n| name == n) {
             Ok(SchemaItem::Number(ty.span()))
         } else if name == "String" {
@@ -338,6 +353,12 @@ impl SchemaItem {
                     ::proxmox_schema::IntegerSchema::new(#description)
                 });
             }
+            SchemaItem::Uint64(span) => {
+                let description = check_description()?;
+                ts.extend(quote_spanned! { *span =>
+                    ::proxmox_schema::Uint64Schema::new(#description)
+                });
+            }
             SchemaItem::Number(span) => {
                 let description = check_description()?;
                 ts.extend(quote_spanned! { *span =>
@@ -364,6 +385,21 @@ impl SchemaItem {
                 {
                     ts.extend(quote_spanned! { obj.span => .additional_properties(true) });
                 }
+                let mut weights = TokenStream::new();
+                obj.to_property_weights(&mut weights);
+                if !weights.is_empty() {
+                    ts.extend(quote_spanned! { obj.span => .property_weights(&[#weights]) });
+                }
+                let mut requires = TokenStream::new();
+                obj.to_property_lists(&mut requires, |entry| entry.requires.as_ref());
+                if !requires.is_empty() {
+                    ts.extend(quote_spanned! { obj.span => .requires(&[#requires]) });
+                }
+                let mut conflicts = TokenStream::new();
+                obj.to_property_lists(&mut conflicts, |entry| entry.conflicts.as_ref());
+                if !conflicts.is_empty() {
+                    ts.extend(quote_spanned! { obj.span => .conflicts(&[#conflicts]) });
+                }
             }
             SchemaItem::Array(array) => {
                 let description = check_description()?;
@@ -405,6 +441,21 @@ impl SchemaItem {
         for prop in properties {
             let key = &prop.0;
             let value = &prop.1;
+            if key == "secret" {
+                // flag-free builder method
+                match value {
+                    syn::Expr::Lit(syn::ExprLit {
+                        lit: syn::Lit::Bool(flag),
+                        ..
+                    }) => {
+                        if flag.value {
+                            ts.extend(quote! { .#key() });
+                        }
+                    }
+                    _ => error!(value => "'secret' must be a boolean literal"),
+                }
+                continue;
+            }
             ts.extend(quote! { .#key(#value) });
         }
 
@@ -499,6 +550,15 @@ pub struct ObjectEntry {
     /// This is used for structs. We mark flattened fields because we need them to be "skipped"
     /// when serializing inner the object schema.
     pub flatten_in_struct: bool,
+
+    /// Ordering weight from an `#[api(weight = ...)]` field attribute.
+    pub weight: Option<syn::Expr>,
+
+    /// Required properties from an `#[api(requires = [...])]` field attribute.
+    pub requires: Option<syn::ExprArray>,
+
+    /// Conflicting properties from an `#[api(conflicts = [...])]` field attribute.
+    pub conflicts: Option<syn::ExprArray>,
 }
 
 impl ObjectEntry {
@@ -509,6 +569,9 @@ impl ObjectEntry {
             schema,
             flatten: None,
             flatten_in_struct: false,
+            weight: None,
+            requires: None,
+            conflicts: None,
         }
     }
 
@@ -695,6 +758,37 @@ impl SchemaObject {
         Ok(())
     }
 
+    fn to_property_weights(&self, ts: &mut TokenStream) {
+        for element in self.properties_.iter() {
+            if element.flatten_in_struct {
+                continue;
+            }
+
+            if let Some(weight) = &element.weight {
+                let key = element.name.as_str();
+                ts.extend(quote! { (#key, #weight), });
+            }
+        }
+    }
+
+    /// Produce the `(name, &[...])` entries for `requires` or `conflicts` lists.
+    fn to_property_lists(
+        &self,
+        ts: &mut TokenStream,
+        list: impl Fn(&ObjectEntry) -> Option<&syn::ExprArray>,
+    ) {
+        for element in self.properties_.iter() {
+            if element.flatten_in_struct {
+                continue;
+            }
+
+            if let Some(list) = list(element) {
+                let key = element.name.as_str();
+                ts.extend(quote! { (#key, &#list), });
+            }
+        }
+    }
+
     fn find_property_by_ident(&self, key: &str) -> Option<&ObjectEntry> {
         self.properties_
             .iter()
@@ -18,7 +18,7 @@ use anyhow::Error;
 use proc_macro2::{Ident, Span, TokenStream};
 use quote::quote_spanned;
 
-use super::attributes::UpdaterFieldAttributes;
+use super::attributes::{StructFieldAttributes, UpdaterFieldAttributes};
 use super::Schema;
 use crate::api::{self, ObjectEntry, SchemaItem};
 use crate::serde;
@@ -164,8 +164,18 @@ fn handle_regular_struct(
     let mut all_of_schemas = TokenStream::new();
     let mut to_remove = Vec::new();
 
+    // strip our own `#[api(...)]` field attributes from the struct
+    let field_attrs: Vec<StructFieldAttributes> = match &mut stru.fields {
+        syn::Fields::Named(fields) => fields
+            .named
+            .iter_mut()
+            .map(|field| StructFieldAttributes::from_attributes(&mut field.attrs))
+            .collect(),
+        _ => Vec::new(),
+    };
+
     if let syn::Fields::Named(ref fields) = &stru.fields {
-        for field in &fields.named {
+        for (field, field_attrs) in fields.named.iter().zip(&field_attrs) {
             let attrs = serde::FieldAttrib::try_from(&field.attrs[..])?;
 
             let (name, span) = {
@@ -214,6 +224,7 @@ fn handle_regular_struct(
                     }
 
                     handle_regular_field(field_def, field, false, &attrs)?;
+                    field_attrs.apply_to(field_def);
 
                     if attrs.flatten {
                         all_of_schemas.extend(quote::quote! {&});
@@ -228,6 +239,7 @@ fn handle_regular_struct(
                         Schema::blank(span),
                     );
                     handle_regular_field(&mut field_def, field, true, &attrs)?;
+                    field_attrs.apply_to(&mut field_def);
 
                     if attrs.flatten {
                         all_of_schemas.extend(quote::quote! {&});
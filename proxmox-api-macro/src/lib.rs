@@ -230,6 +230,84 @@ fn router_do(item: TokenStream) -> Result<TokenStream, Error> {
     declarations. If it contains a `schema` key, this is expected to be the path to an existing
     schema. (Hence `type: Foo` is the same as `schema: Foo::API_SCHEMA`.)
 
+    Fields can be given an ordering weight via `#[api(weight = <isize>)]`. Property strings,
+    section config files and documentation print properties sorted by ascending weight (the
+    default weight is `0`), which can be used to force keys like `name` first:
+
+    ```
+    # use proxmox_api_macro::api;
+    # use serde::{Deserialize, Serialize};
+    #[api]
+    #[derive(Deserialize, Serialize)]
+    /// A struct printing `name` first.
+    pub struct Weighted {
+        /// A comment.
+        comment: String,
+
+        /// The name.
+        #[api(weight = -1)]
+        name: String,
+    }
+    ```
+
+    Dependencies between fields can be declared via `#[api(requires = [...])]` and
+    `#[api(conflicts = [...])]`, listing the (serialized) names of the properties which have to,
+    or must not, be set along with the field. These are checked during verification and when
+    deserializing property strings:
+
+    ```
+    # use proxmox_api_macro::api;
+    # use serde::{Deserialize, Serialize};
+    #[api]
+    #[derive(Deserialize, Serialize)]
+    /// A struct with mutually exclusive authentication methods.
+    pub struct Auth {
+        /// A keyring file.
+        #[api(conflicts = ["password"])]
+        keyring: Option<String>,
+
+        /// A password.
+        #[api(conflicts = ["keyring"])]
+        password: Option<String>,
+    }
+    ```
+
+    Numeric fields can be limited via `#[api(minimum = ..., maximum = ...)]`, which is a shorter
+    way of setting them in the `properties` of the struct. For `u64` fields the bounds may exceed
+    the range of an `i64`.
+
+    Enum variants may carry a string enum as data. Their values are built from a template given
+    via `#[api(template = "...")]`, where `{}` is replaced by each value of the data type. The
+    default template is the variant name followed by `-{}`. The generated string schema lists
+    every possible value, and since serde cannot express this, the `Serialize` and `Deserialize`
+    implementations are generated as well:
+
+    ```
+    # use proxmox_api_macro::api;
+    # use serde::{Deserialize, Serialize};
+    #[api]
+    #[derive(Deserialize, Serialize)]
+    #[serde(rename_all = "lowercase")]
+    /// A release.
+    pub enum Release {
+        /// The old release.
+        Quincy,
+        /// The new release.
+        Reef,
+    }
+
+    #[api]
+    #[serde(rename_all = "kebab-case")]
+    /// A repository, e.g. `ceph-reef-test`.
+    pub enum Repository {
+        /// The stable repository.
+        Stable,
+        /// The test repository of a release.
+        #[api(template = "ceph-{}-test")]
+        CephTest(Release),
+    }
+    ```
+
     # Deriving an `Updater`:
 
     An "Updater" struct can be generated automatically for a type. This affects the `UpdaterType`
@@ -501,6 +501,8 @@ pub fn infer_type(schema: &mut Schema, ty: &syn::Type) -> Result<bool, syn::Erro
                 if let Some(max) = info.maximum {
                     schema.add_default_property("maximum", syn::Expr::Verbatim(max.parse()?));
                 }
+            } else if api::UINT64NAMES.iter().any(|n| path.path.is_ident(n)) {
+                schema.item = SchemaItem::Uint64(ty.span());
             } else if api::NUMBERNAMES.iter().any(|n| path.path.is_ident(n)) {
                 schema.item = SchemaItem::Number(ty.span());
             } else {
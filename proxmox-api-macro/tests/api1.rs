@@ -12,10 +12,12 @@ use proxmox_router::Permission;
                 type: String,
                 description: "User name",
                 max_length: 64,
+                example: "root@pam",
             },
             password: {
                 type: String,
                 description: "The secret password or a valid ticket.",
+                secret: true,
             },
         }
     },
@@ -68,6 +70,7 @@ fn create_ticket_schema_check() {
                     "password",
                     false,
                     &::proxmox_schema::StringSchema::new("The secret password or a valid ticket.")
+                        .secret()
                         .schema(),
                 ),
                 (
@@ -75,6 +78,7 @@ fn create_ticket_schema_check() {
                     false,
                     &::proxmox_schema::StringSchema::new("User name")
                         .max_length(64)
+                        .example("root@pam")
                         .schema(),
                 ),
             ],
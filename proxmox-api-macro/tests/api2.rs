@@ -131,3 +131,45 @@ fn keyword_named_parameters_check() {
 
     assert_eq!(TEST_METHOD, API_METHOD_KEYWORD_NAMED_PARAMETERS);
 }
+
+#[api(
+    cache: ::proxmox_router::CachePolicy::new(60).vary_parameters(false),
+)]
+/// Return the version.
+pub fn version() -> Result<&'static str, Error> {
+    Ok("1.0")
+}
+
+#[test]
+fn cache_policy_check() {
+    const TEST_METHOD: ::proxmox_router::ApiMethod = ::proxmox_router::ApiMethod::new(
+        &::proxmox_router::ApiHandler::Sync(&api_function_version),
+        &::proxmox_schema::ObjectSchema::new("Return the version.", &[]),
+    )
+    .cache(::proxmox_router::CachePolicy::new(60).vary_parameters(false))
+    .protected(false);
+
+    assert_eq!(TEST_METHOD, API_METHOD_VERSION);
+}
+
+#[api(
+    expected_duration: ::proxmox_router::ExpectedDuration::Slow,
+    max_response_size: 64 * 1024,
+)]
+/// Query the peers.
+pub fn query_peers() -> Result<(), Error> {
+    Ok(())
+}
+
+#[test]
+fn expected_duration_check() {
+    const TEST_METHOD: ::proxmox_router::ApiMethod = ::proxmox_router::ApiMethod::new(
+        &::proxmox_router::ApiHandler::Sync(&api_function_query_peers),
+        &::proxmox_schema::ObjectSchema::new("Query the peers.", &[]),
+    )
+    .expected_duration(::proxmox_router::ExpectedDuration::Slow)
+    .max_response_size(64 * 1024)
+    .protected(false);
+
+    assert_eq!(TEST_METHOD, API_METHOD_QUERY_PEERS);
+}
@@ -88,9 +88,58 @@ pub struct AnU64(u64);
 #[test]
 fn test_an_u64_schema() {
     const TEST_SCHEMA: ::proxmox_schema::Schema =
-        ::proxmox_schema::IntegerSchema::new("Unsigned implies a minimum of zero.")
-            .minimum(0)
-            .schema();
+        ::proxmox_schema::Uint64Schema::new("Unsigned implies a minimum of zero.").schema();
 
     assert_eq!(TEST_SCHEMA, AnU64::API_SCHEMA);
 }
+
+#[api]
+#[derive(Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+/// A struct with values beyond the range of an i64.
+pub struct Counters {
+    /// A byte counter.
+    bytes: u64,
+
+    /// A limited counter.
+    #[api(minimum = 1, maximum = 0xffff_ffff_ffff_fff0)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    limited: Option<u64>,
+}
+
+#[test]
+fn test_u64_values() {
+    let counters = Counters {
+        bytes: u64::MAX,
+        limited: Some(0xffff_ffff_ffff_fff0),
+    };
+
+    let json = serde_json::to_value(&counters).unwrap();
+    Counters::API_SCHEMA.verify_json(&json).unwrap();
+    assert_eq!(serde_json::from_value::<Counters>(json).unwrap(), counters);
+
+    let text = proxmox_schema::property_string::print(&counters).unwrap();
+    assert_eq!(
+        text,
+        "bytes=18446744073709551615,limited=18446744073709551600"
+    );
+    assert_eq!(
+        proxmox_schema::property_string::parse::<Counters>(&text).unwrap(),
+        counters
+    );
+
+    for invalid in [
+        serde_json::json!({ "bytes": -1 }),
+        serde_json::json!({ "bytes": 1.5 }),
+        serde_json::json!({ "bytes": 0, "limited": 0 }),
+        serde_json::json!({ "bytes": 0, "limited": u64::MAX }),
+    ] {
+        assert!(
+            Counters::API_SCHEMA.verify_json(&invalid).is_err(),
+            "{invalid}"
+        );
+    }
+    assert!(proxmox_schema::property_string::parse::<Counters>("bytes=-1").is_err());
+    assert!(
+        proxmox_schema::property_string::parse::<Counters>("bytes=18446744073709551616").is_err()
+    );
+}
@@ -10,7 +10,7 @@ use proxmox_schema as schema;
 use proxmox_schema::{ApiType, EnumEntry};
 
 use anyhow::Error;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
 pub const TEXT_SCHEMA: schema::Schema = schema::StringSchema::new("Text.").schema();
@@ -37,6 +37,23 @@ fn ok_string() {
     assert_eq!(TEST_SCHEMA, OkString::API_SCHEMA);
 }
 
+#[api(
+    max_length: 32,
+    length_unit: schema::LengthUnit::Bytes,
+)]
+/// A label stored in a fixed size field.
+pub struct Label(String);
+
+#[test]
+fn label() {
+    const TEST_SCHEMA: ::proxmox_schema::Schema =
+        ::proxmox_schema::StringSchema::new("A label stored in a fixed size field.")
+            .max_length(32)
+            .length_unit(schema::LengthUnit::Bytes)
+            .schema();
+    assert_eq!(TEST_SCHEMA, Label::API_SCHEMA);
+}
+
 #[api]
 /// An example of a simple struct type.
 pub struct TestStruct {
@@ -137,6 +154,75 @@ fn selection_test() {
     assert_eq!(TEST_SCHEMA, Selection::API_SCHEMA);
 }
 
+#[api]
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+/// A release.
+pub enum Release {
+    /// The old release.
+    Quincy,
+    /// The new release.
+    Reef,
+}
+
+#[api]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+/// A repository.
+pub enum Repository {
+    /// The stable repository.
+    #[default]
+    Stable,
+    /// The test repository.
+    #[api(template = "ceph-{}-test")]
+    CephTest(Release),
+    /// A repository with the default template.
+    Nightly(Release),
+    /// The legacy repository.
+    #[serde(rename = "old")]
+    Legacy,
+}
+
+#[test]
+fn templated_enum_test() {
+    const TEST_SCHEMA: ::proxmox_schema::Schema =
+        ::proxmox_schema::StringSchema::new("A repository.")
+            .format(&::proxmox_schema::ApiStringFormat::Enum(&[
+                EnumEntry::new("stable", "The stable repository."),
+                EnumEntry::new("ceph-quincy-test", "The test repository."),
+                EnumEntry::new("ceph-reef-test", "The test repository."),
+                EnumEntry::new("nightly-quincy", "A repository with the default template."),
+                EnumEntry::new("nightly-reef", "A repository with the default template."),
+                EnumEntry::new("old", "The legacy repository."),
+            ]))
+            .default("stable")
+            .schema();
+
+    assert_eq!(TEST_SCHEMA, Repository::API_SCHEMA);
+
+    for (repo, value) in [
+        (Repository::Stable, "stable"),
+        (Repository::CephTest(Release::Reef), "ceph-reef-test"),
+        (Repository::Nightly(Release::Quincy), "nightly-quincy"),
+        (Repository::Legacy, "old"),
+    ] {
+        let json = serde_json::to_value(repo).unwrap();
+        assert_eq!(json, Value::String(value.to_string()));
+        Repository::API_SCHEMA.verify_json(&json).unwrap();
+        assert_eq!(serde_json::from_value::<Repository>(json).unwrap(), repo);
+    }
+
+    for value in ["ceph-test", "ceph-squid-test", "nightly-", "legacy"] {
+        let json = Value::String(value.to_string());
+        assert!(Repository::API_SCHEMA.verify_json(&json).is_err());
+        assert!(serde_json::from_value::<Repository>(json).is_err());
+    }
+
+    let doc = ::proxmox_schema::format::dump_enum_properties(&Repository::API_SCHEMA).unwrap();
+    assert!(doc.contains(":``ceph-quincy-test``: The test repository."));
+    assert!(doc.contains(":``nightly-reef``: A repository with the default template."));
+}
+
 // Initial test:
 #[api(
     input: {
@@ -214,3 +300,159 @@ fn additional_properties_test() {
 
     assert_eq!(TEST_UNSPECIFIED, UnspecifiedData::API_SCHEMA);
 }
+
+#[api]
+#[derive(Deserialize, Serialize)]
+/// A struct with weighted properties.
+pub struct Weighted {
+    /// A comment.
+    comment: String,
+
+    /// The name.
+    #[api(weight = -1)]
+    name: String,
+
+    /// The type.
+    #[api(weight = -2)]
+    #[serde(rename = "type")]
+    ty: String,
+}
+
+#[test]
+fn weighted_properties_test() {
+    const TEST_WEIGHTED: ::proxmox_schema::Schema = ::proxmox_schema::ObjectSchema::new(
+        "A struct with weighted properties.",
+        &[
+            (
+                "comment",
+                false,
+                &::proxmox_schema::StringSchema::new("A comment.").schema(),
+            ),
+            (
+                "name",
+                false,
+                &::proxmox_schema::StringSchema::new("The name.").schema(),
+            ),
+            (
+                "type",
+                false,
+                &::proxmox_schema::StringSchema::new("The type.").schema(),
+            ),
+        ],
+    )
+    .property_weights(&[("name", -1), ("type", -2)])
+    .schema();
+
+    assert_eq!(TEST_WEIGHTED, Weighted::API_SCHEMA);
+
+    let value = Weighted {
+        comment: "a comment".to_string(),
+        name: "foo".to_string(),
+        ty: "bar".to_string(),
+    };
+    assert_eq!(
+        proxmox_schema::property_string::print(&value).unwrap(),
+        "type=bar,name=foo,comment=a comment",
+    );
+}
+
+#[api]
+#[derive(Deserialize, Serialize)]
+/// A struct with property dependencies.
+pub struct Dependent {
+    /// A keyring.
+    #[api(conflicts = ["password"])]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    keyring: Option<String>,
+
+    /// A password.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    password: Option<String>,
+
+    /// The fallback server.
+    #[api(requires = ["server1"])]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    server2: Option<String>,
+
+    /// The first server.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    server1: Option<String>,
+}
+
+#[test]
+fn property_dependencies_test() {
+    const TEST_DEPENDENT: ::proxmox_schema::Schema = ::proxmox_schema::ObjectSchema::new(
+        "A struct with property dependencies.",
+        &[
+            (
+                "keyring",
+                true,
+                &::proxmox_schema::StringSchema::new("A keyring.").schema(),
+            ),
+            (
+                "password",
+                true,
+                &::proxmox_schema::StringSchema::new("A password.").schema(),
+            ),
+            (
+                "server1",
+                true,
+                &::proxmox_schema::StringSchema::new("The first server.").schema(),
+            ),
+            (
+                "server2",
+                true,
+                &::proxmox_schema::StringSchema::new("The fallback server.").schema(),
+            ),
+        ],
+    )
+    .requires(&[("server2", &["server1"])])
+    .conflicts(&[("keyring", &["password"])])
+    .schema();
+
+    assert_eq!(TEST_DEPENDENT, Dependent::API_SCHEMA);
+
+    assert!(Dependent::API_SCHEMA
+        .verify_json(&serde_json::json!({ "server2": "b" }))
+        .is_err());
+    assert!(proxmox_schema::property_string::parse::<Dependent>("keyring=a,password=b").is_err());
+}
+
+#[api(
+    properties: {
+        created: {
+            type: String,
+            format: &proxmox_schema::api_types::TIME_RFC3339_FORMAT,
+        },
+    },
+)]
+#[derive(Deserialize, Serialize)]
+/// A struct with a timestamp.
+pub struct Timestamped {
+    /// The creation time.
+    created: String,
+}
+
+#[test]
+fn rfc3339_format_test() {
+    const TEST_TIMESTAMPED: ::proxmox_schema::Schema = ::proxmox_schema::ObjectSchema::new(
+        "A struct with a timestamp.",
+        &[(
+            "created",
+            false,
+            &::proxmox_schema::StringSchema::new("The creation time.")
+                .format(&proxmox_schema::api_types::TIME_RFC3339_FORMAT)
+                .schema(),
+        )],
+    )
+    .schema();
+
+    assert_eq!(TEST_TIMESTAMPED, Timestamped::API_SCHEMA);
+
+    assert!(Timestamped::API_SCHEMA
+        .verify_json(&serde_json::json!({ "created": "2024-05-01T12:30:00.5+02:00" }))
+        .is_ok());
+    assert!(Timestamped::API_SCHEMA
+        .verify_json(&serde_json::json!({ "created": "2024-05-01T12:30:00" }))
+        .is_err());
+}
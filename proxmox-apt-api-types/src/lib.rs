@@ -164,6 +164,14 @@ pub struct APTRepository {
             type: ConfigDigest,
             optional: true,
         },
+        "entry-errors": {
+            description: "Entries which could not be parsed.",
+            type: Array,
+            optional: true,
+            items: {
+                type: APTRepositoryEntryError,
+            },
+        },
     },
 )]
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -188,6 +196,32 @@ pub struct APTRepositoryFile {
     // We cannot use ConfigDigest here for compatibility reasons.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub digest: Option<[u8; 32]>,
+
+    /// Entries which could not be parsed, they are written back unchanged.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub entry_errors: Vec<APTRepositoryEntryError>,
+}
+
+#[api]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+/// An entry of an APT repository file which could not be parsed.
+pub struct APTRepositoryEntryError {
+    /// Path to the defining file.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub path: String,
+
+    /// The line the entry starts on (starting from 1).
+    pub line: usize,
+
+    /// Index of the repository the entry precedes within the file (starting from 0).
+    pub index: usize,
+
+    /// The text of the entry.
+    pub text: String,
+
+    /// The error message.
+    pub error: String,
 }
 
 #[api]
@@ -266,6 +300,20 @@ pub struct APTStandardRepository {
 
 #[api]
 #[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+/// Ceph release codenames.
+pub enum CephReleaseCodename {
+    /// Ceph Quincy release.
+    Quincy,
+    /// Ceph Reef release.
+    Reef,
+}
+
+serde_plain::derive_display_from_serialize!(CephReleaseCodename);
+serde_plain::derive_fromstr_from_deserialize!(CephReleaseCodename);
+
+#[api]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 #[serde(rename_all = "kebab-case")]
 /// Handles for Proxmox repositories.
 pub enum APTRepositoryHandle {
@@ -275,25 +323,40 @@ pub enum APTRepositoryHandle {
     NoSubscription,
     /// The test repository.
     Test,
-    /// Ceph Quincy enterprise repository.
-    CephQuincyEnterprise,
-    /// Ceph Quincy no-subscription repository.
-    CephQuincyNoSubscription,
-    /// Ceph Quincy test repository.
-    CephQuincyTest,
-    // TODO: Add separate enum for ceph releases and use something like
-    // `CephTest(CephReleaseCodename),` once the API macro supports it.
-    /// Ceph Reef enterprise repository.
-    CephReefEnterprise,
-    /// Ceph Reef no-subscription repository.
-    CephReefNoSubscription,
-    /// Ceph Reef test repository.
-    CephReefTest,
+    /// Ceph enterprise repository.
+    #[api(template = "ceph-{}-enterprise")]
+    CephEnterprise(CephReleaseCodename),
+    /// Ceph no-subscription repository.
+    #[api(template = "ceph-{}-no-subscription")]
+    CephNoSubscription(CephReleaseCodename),
+    /// Ceph test repository.
+    #[api(template = "ceph-{}-test")]
+    CephTest(CephReleaseCodename),
 }
 
 serde_plain::derive_display_from_serialize!(APTRepositoryHandle);
 serde_plain::derive_fromstr_from_deserialize!(APTRepositoryHandle);
 
+#[api]
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+/// Classification of the repository an update comes from.
+pub enum APTUpdateOrigin {
+    /// A Proxmox enterprise repository.
+    ProxmoxEnterprise,
+    /// A Proxmox no-subscription repository.
+    ProxmoxNoSubscription,
+    /// A Proxmox test repository.
+    ProxmoxTest,
+    /// A Debian repository.
+    Debian,
+    /// Any other repository.
+    Other,
+}
+
+serde_plain::derive_display_from_serialize!(APTUpdateOrigin);
+serde_plain::derive_fromstr_from_deserialize!(APTUpdateOrigin);
+
 #[api()]
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "PascalCase")]
@@ -320,6 +383,12 @@ pub struct APTUpdateInfo {
     /// Custom extra field for additional package information
     #[serde(skip_serializing_if = "Option::is_none")]
     pub extra_info: Option<String>,
+    /// Classification of the repository the new version comes from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub origin_type: Option<APTUpdateOrigin>,
+    /// URL of the changelog of the new version
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub change_log_url: Option<String>,
 }
 
 #[api(
@@ -406,4 +475,116 @@ pub struct APTGetChangelogOptions {
 pub struct APTChangeRepositoryOptions {
     /// Whether the repository should be enabled or not.
     pub enabled: Option<bool>,
+    /// The new comment of the repository, written as `#`-prefixed lines above it. An empty
+    /// string removes the comment.
+    pub comment: Option<String>,
+}
+
+#[api(
+    properties: {
+        digest: {
+            type: ConfigDigest,
+            optional: true,
+        },
+        "dry-run": {
+            default: false,
+            optional: true,
+        },
+    },
+)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+/// Options for adding a standard repository.
+pub struct APTAddRepositoryOptions {
+    /// Assert that the configuration has not been modified.
+    pub digest: Option<ConfigDigest>,
+    /// Only compute the change, without writing anything.
+    pub dry_run: Option<bool>,
+}
+
+#[api]
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+/// How a repository file was changed.
+pub enum APTRepositoryChangeKind {
+    /// A new file was created for the repository.
+    Created,
+    /// The repository was appended to an existing file.
+    Appended,
+    /// The repository was already configured and got enabled.
+    Enabled,
+    /// The repository was already configured and enabled.
+    Unchanged,
+}
+
+serde_plain::derive_display_from_serialize!(APTRepositoryChangeKind);
+serde_plain::derive_fromstr_from_deserialize!(APTRepositoryChangeKind);
+
+#[api]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+/// Change of a repository file, e.g. by adding a standard repository.
+pub struct APTRepositoryChange {
+    /// Path of the changed file.
+    pub path: String,
+    /// Index of the repository in the file.
+    pub index: usize,
+    pub kind: APTRepositoryChangeKind,
+    /// Content of the file after the change, which was, or for dry-runs would be, written.
+    pub content: String,
+}
+
+#[api]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+/// An installed package.
+pub struct APTInstalledPackage {
+    /// Package name.
+    pub package: String,
+    /// Installed version.
+    pub version: String,
+    /// Package architecture.
+    pub arch: String,
+}
+
+#[api]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+/// Number of installed packages from an origin.
+pub struct APTPackageOriginCount {
+    /// The handle of a standard Proxmox repository, `debian` for Debian repositories, `other`
+    /// for any other configured repository, or `local` for packages not available from any
+    /// configured repository.
+    pub origin: String,
+    /// Number of installed packages.
+    pub count: usize,
+}
+
+#[api(
+    properties: {
+        origins: {
+            type: Array,
+            items: {
+                type: APTPackageOriginCount,
+            },
+        },
+        "local-packages": {
+            type: Array,
+            items: {
+                type: APTInstalledPackage,
+            },
+        },
+    },
+)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+/// The origins of the installed packages, e.g. for support bundles.
+pub struct APTPackageInventory {
+    /// Number of installed packages.
+    pub total: usize,
+    /// Number of installed packages per origin, sorted by origin.
+    pub origins: Vec<APTPackageOriginCount>,
+    /// Installed packages whose version is not available from any configured repository, e.g.
+    /// manually installed or obsolete packages.
+    pub local_packages: Vec<APTInstalledPackage>,
 }
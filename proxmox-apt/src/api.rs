@@ -4,12 +4,15 @@ use std::path::Path;
 use anyhow::{bail, Error};
 
 use proxmox_apt_api_types::{
-    APTChangeRepositoryOptions, APTGetChangelogOptions, APTRepositoriesResult, APTRepositoryFile,
-    APTRepositoryHandle,
+    APTAddRepositoryOptions, APTChangeRepositoryOptions, APTGetChangelogOptions,
+    APTPackageInventory, APTRepositoriesResult, APTRepositoryChange, APTRepositoryHandle,
 };
 use proxmox_config_digest::ConfigDigest;
 
-use crate::repositories::{APTRepositoryFileImpl, APTRepositoryImpl};
+use crate::repositories::{
+    APTRepositoryFileImpl, APTRepositoryImpl, DebianCodename, APT_SOURCES_LIST_DIRECTORY,
+    APT_SOURCES_LIST_FILENAME,
+};
 
 /// Retrieve the changelog of the specified package.
 pub fn get_changelog(options: &APTGetChangelogOptions) -> Result<String, Error> {
@@ -46,6 +49,20 @@ pub fn list_repositories(product: &str) -> Result<APTRepositoriesResult, Error>
     })
 }
 
+/// Get the origins of the installed packages, see [`package_inventory`].
+///
+/// [`package_inventory`]: crate::inventory::package_inventory
+pub fn get_package_inventory(product: &str) -> Result<APTPackageInventory, Error> {
+    let (files, _errors, _digest) = crate::repositories::repositories()?;
+
+    crate::inventory::package_inventory(
+        Path::new(crate::inventory::DPKG_STATUS_FILENAME),
+        Path::new("/var/lib/apt/lists"),
+        &files,
+        product,
+    )
+}
+
 /// Add the repository identified by the `handle`.
 /// If the repository is already configured, it will be set to enabled.
 ///
@@ -55,59 +72,41 @@ pub fn add_repository_handle(
     handle: APTRepositoryHandle,
     digest: Option<ConfigDigest>,
 ) -> Result<(), Error> {
-    let (mut files, errors, current_digest) = crate::repositories::repositories()?;
-
-    current_digest.detect_modification(digest.as_ref())?;
-
     let suite = crate::repositories::get_current_release_codename()?;
-
-    // check if it's already configured first
-    for file in files.iter_mut() {
-        for repo in file.repositories.iter_mut() {
-            if repo.is_referenced_repository(handle, "pbs", &suite.to_string()) {
-                if repo.enabled {
-                    return Ok(());
-                }
-
-                repo.set_enabled(true);
-                file.write()?;
-
-                return Ok(());
-            }
-        }
-    }
-
-    let (repo, path) = crate::repositories::get_standard_repository(handle, product, suite);
-
-    if let Some(error) = errors.iter().find(|error| error.path == path) {
-        bail!(
-            "unable to parse existing file {} - {}",
-            error.path,
-            error.error,
-        );
-    }
-
-    if let Some(file) = files
-        .iter_mut()
-        .find(|file| file.path.as_ref() == Some(&path))
-    {
-        file.repositories.push(repo);
-
-        file.write()?;
-    } else {
-        let mut file = match APTRepositoryFile::new(&path)? {
-            Some(file) => file,
-            None => bail!("invalid path - {}", path),
-        };
-
-        file.repositories.push(repo);
-
-        file.write()?;
-    }
-
+    let options = APTAddRepositoryOptions {
+        digest,
+        dry_run: None,
+    };
+    add_standard_repository(handle, product, suite, &options)?;
     Ok(())
 }
 
+/// Add the standard repository identified by the `handle` for the `suite`, or enable it if it is
+/// already configured, and return what changed.
+///
+/// New repositories are appended to the file they are usually configured in, see
+/// [`get_standard_repository`](crate::repositories::get_standard_repository), which is created if
+/// necessary. The rest of the file is preserved. With the `dry-run` option, the change is only
+/// computed, but not written.
+///
+/// The `digest` option asserts that the configuration has not been modified. The changed file is
+/// also only written if it was not modified in the meantime.
+pub fn add_standard_repository(
+    handle: APTRepositoryHandle,
+    product: &str,
+    suite: DebianCodename,
+    options: &APTAddRepositoryOptions,
+) -> Result<APTRepositoryChange, Error> {
+    crate::repositories::add_standard_repository_in(
+        Path::new(APT_SOURCES_LIST_FILENAME),
+        Path::new(APT_SOURCES_LIST_DIRECTORY),
+        handle,
+        product,
+        suite,
+        options,
+    )
+}
+
 /// Change the properties of the specified repository.
 ///
 /// The `digest` parameter asserts that the configuration has not been modified.
@@ -133,6 +132,9 @@ pub fn change_repository(
             if let Some(enabled) = options.enabled {
                 repo.set_enabled(enabled);
             }
+            if let Some(comment) = &options.comment {
+                repo.set_comment(comment)?;
+            }
 
             file.write()?;
         } else {
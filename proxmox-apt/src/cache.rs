@@ -284,6 +284,8 @@ where
                 priority: priority_res,
                 section: section_res,
                 extra_info: None,
+                origin_type: None,
+                change_log_url: None,
             });
         }
     }
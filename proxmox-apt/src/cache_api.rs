@@ -128,6 +128,8 @@ pub fn get_package_versions(
             priority: "unknown".into(),
             section: "unknown".into(),
             extra_info,
+            origin_type: None,
+            change_log_url: None,
         }
     }
 
@@ -0,0 +1,259 @@
+//! Retrieve the changelogs of packages from their repositories.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use anyhow::{bail, format_err, Error};
+
+use proxmox_http::client::Client;
+use proxmox_http::{HttpOptions, ProxyConfig};
+use proxmox_sys::fs::{create_path, file_read_optional_string, replace_file, CreateOptions};
+
+/// Default directory changelogs are cached in.
+pub const DEFAULT_CACHE_DIR: &str = "/var/cache/proxmox-apt/changelogs";
+
+const DEFAULT_CACHE_SIZE: u64 = 16 * 1024 * 1024;
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+const DEBIAN_CHANGELOG_URL: &str = "https://metadata.ftp-master.debian.org/changelogs";
+
+/// Hook returning the `Authorization` header value for a changelog URL of a Proxmox repository,
+/// e.g. to authenticate with the subscription key for the enterprise repository.
+pub type ChangelogAuthHook = fn(url: &str) -> Result<Option<String>, Error>;
+
+/// The origin of a package, determining where its changelog is published.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ChangelogOrigin {
+    /// A Proxmox repository, publishing changelogs next to its package index.
+    Proxmox {
+        /// The repository URL, e.g. `http://download.proxmox.com/debian/pve`.
+        repository: String,
+        /// The suite, e.g. `bookworm`.
+        suite: String,
+        /// The component, e.g. `pve-no-subscription`.
+        component: String,
+        /// The package architecture, e.g. `amd64`.
+        arch: String,
+    },
+    /// The Debian archive, served by Debian's changelog service.
+    Debian {
+        /// The component, e.g. `main`.
+        component: String,
+        /// The source package name, if it differs from the binary package name.
+        source: Option<String>,
+    },
+}
+
+impl ChangelogOrigin {
+    /// Get the URL of the changelog for `version` of `package`.
+    pub fn changelog_url(&self, package: &str, version: &str) -> Result<String, Error> {
+        check_package_version(package, version)?;
+        // published file names do not contain the epoch
+        let version = version.split_once(':').map_or(version, |(_epoch, v)| v);
+
+        match self {
+            ChangelogOrigin::Proxmox {
+                repository,
+                suite,
+                component,
+                arch,
+            } => Ok(format!(
+                "{}/dists/{suite}/{component}/binary-{arch}/{package}_{version}.changelog",
+                repository.trim_end_matches('/'),
+            )),
+            ChangelogOrigin::Debian { component, source } => {
+                let source = source.as_deref().unwrap_or(package);
+                check_package_version(source, version)?;
+                // the pool is split by the first letter, `lib*` packages by their first 4 letters
+                let pool_prefix = match source.strip_prefix("lib") {
+                    Some(rest) if !rest.is_empty() => &source[..4],
+                    _ => &source[..1],
+                };
+                Ok(format!(
+                    "{DEBIAN_CHANGELOG_URL}/{component}/{pool_prefix}/{source}/{source}_{version}_changelog"
+                ))
+            }
+        }
+    }
+}
+
+// package names and versions end up in URLs and file names
+fn check_package_version(package: &str, version: &str) -> Result<(), Error> {
+    let valid_package = package.len() >= 2
+        && package.starts_with(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit())
+        && package
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "+-.".contains(c));
+    if !valid_package {
+        bail!("invalid package name '{package}'");
+    }
+
+    let valid_version = version.starts_with(|c: char| c.is_ascii_digit())
+        && version
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || ".+~:-".contains(c));
+    if !valid_version {
+        bail!("invalid version '{version}' of package '{package}'");
+    }
+
+    Ok(())
+}
+
+/// Fetches changelogs and caches them on disk.
+///
+/// Cached changelogs are stored as `<package>_<version>.changelog` in the cache directory. When
+/// the cache grows larger than its maximum size, the least recently used changelogs are removed.
+pub struct ChangelogFetcher {
+    cache_dir: PathBuf,
+    cache_size: u64,
+    timeout: Duration,
+    proxy_config: Option<ProxyConfig>,
+    auth_hook: Option<ChangelogAuthHook>,
+}
+
+impl ChangelogFetcher {
+    /// Create a fetcher caching changelogs in `cache_dir`.
+    pub fn new<P: Into<PathBuf>>(cache_dir: P) -> Self {
+        Self {
+            cache_dir: cache_dir.into(),
+            cache_size: DEFAULT_CACHE_SIZE,
+            timeout: DEFAULT_TIMEOUT,
+            proxy_config: None,
+            auth_hook: None,
+        }
+    }
+
+    /// Set the maximum size of the cache in bytes, defaults to 16 MiB.
+    pub fn cache_size(mut self, cache_size: u64) -> Self {
+        self.cache_size = cache_size;
+        self
+    }
+
+    /// Set the timeout for fetching a changelog, defaults to 30 seconds.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Set the proxy used to fetch changelogs.
+    pub fn proxy(mut self, proxy_config: Option<ProxyConfig>) -> Self {
+        self.proxy_config = proxy_config;
+        self
+    }
+
+    /// Set the hook authenticating requests to Proxmox repositories.
+    pub fn auth_hook(mut self, hook: ChangelogAuthHook) -> Self {
+        self.auth_hook = Some(hook);
+        self
+    }
+
+    /// Get the changelog for `version` of `package` as plain text.
+    pub async fn get_changelog(
+        &self,
+        package: &str,
+        version: &str,
+        origin: &ChangelogOrigin,
+    ) -> Result<String, Error> {
+        let url = origin.changelog_url(package, version)?;
+        let cache_file = self
+            .cache_dir
+            .join(format!("{package}_{version}.changelog"));
+
+        if let Some(changelog) = file_read_optional_string(&cache_file)? {
+            // keep track of the last use for the cache eviction
+            let _ = File::options()
+                .write(true)
+                .open(&cache_file)
+                .and_then(|file| file.set_modified(SystemTime::now()));
+            return Ok(changelog);
+        }
+
+        let changelog = self.fetch(&url, origin).await?;
+
+        if let Err(err) = self.store(&cache_file, &changelog) {
+            log::warn!("unable to cache changelog of {package} {version} - {err}");
+        }
+
+        Ok(changelog)
+    }
+
+    async fn fetch(&self, url: &str, origin: &ChangelogOrigin) -> Result<String, Error> {
+        let options = HttpOptions {
+            proxy_config: self.proxy_config.clone(),
+            user_agent: Some(format!("proxmox-apt/{}", env!("CARGO_PKG_VERSION"))),
+            ..Default::default()
+        };
+        let client = Client::with_options(options);
+
+        let mut headers = HashMap::new();
+        if let (ChangelogOrigin::Proxmox { .. }, Some(hook)) = (origin, self.auth_hook) {
+            if let Some(authorization) = hook(url)? {
+                headers.insert("Authorization".to_string(), authorization);
+            }
+        }
+
+        tokio::time::timeout(self.timeout, client.get_string(url, Some(&headers)))
+            .await
+            .map_err(|_| format_err!("timeout fetching changelog from {url}"))?
+            .map_err(|err| format_err!("unable to fetch changelog from {url} - {err}"))
+    }
+
+    fn store(&self, cache_file: &Path, changelog: &str) -> Result<(), Error> {
+        create_path(&self.cache_dir, None, None)?;
+        replace_file(
+            cache_file,
+            changelog.as_bytes(),
+            CreateOptions::new(),
+            false,
+        )?;
+
+        let mut entries = Vec::new();
+        let mut total_size = 0;
+        for entry in std::fs::read_dir(&self.cache_dir)? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            if metadata.is_file() {
+                total_size += metadata.len();
+                entries.push((metadata.modified()?, metadata.len(), entry.path()));
+            }
+        }
+
+        // remove the least recently used changelogs, except the new one
+        entries.sort();
+        for (_, size, path) in entries {
+            if total_size <= self.cache_size {
+                break;
+            }
+            if path != cache_file {
+                std::fs::remove_file(&path)?;
+                total_size -= size;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for ChangelogFetcher {
+    fn default() -> Self {
+        Self::new(DEFAULT_CACHE_DIR)
+    }
+}
+
+/// Get the changelog for `version` of `package` as plain text.
+///
+/// Uses the proxy configured in the environment and caches changelogs in [`DEFAULT_CACHE_DIR`].
+/// Use a [`ChangelogFetcher`] with an [auth hook](ChangelogFetcher::auth_hook) for the changelogs
+/// of the enterprise repository.
+pub async fn get_changelog(
+    package: &str,
+    version: &str,
+    origin: ChangelogOrigin,
+) -> Result<String, Error> {
+    ChangelogFetcher::default()
+        .proxy(ProxyConfig::from_proxy_env()?)
+        .get_changelog(package, version, &origin)
+        .await
+}
@@ -0,0 +1,235 @@
+//! Classify the installed packages by the repositories providing them.
+//!
+//! The installed packages are read from the dpkg status file and matched against the cached
+//! `Packages` indices of the configured repositories, so this works without linking against
+//! libapt. Both files are read stanza by stanza, as they can be large.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use anyhow::{format_err, Error};
+
+use proxmox_apt_api_types::{
+    APTInstalledPackage, APTPackageInventory, APTPackageOriginCount, APTRepository,
+    APTRepositoryFile, APTRepositoryPackageType,
+};
+
+use crate::repositories::{packages_filename_prefix, APTRepositoryImpl};
+use crate::updates::standard_handle;
+
+/// The default location of the dpkg status file.
+pub const DPKG_STATUS_FILENAME: &str = "/var/lib/dpkg/status";
+
+/// Origin of packages from configured repositories which are neither Proxmox nor Debian ones.
+const ORIGIN_OTHER: &str = "other";
+/// Origin of packages not available from any configured repository.
+const ORIGIN_LOCAL: &str = "local";
+
+/// Call `f` with the values of the requested `fields` of each stanza of a deb822 style file.
+///
+/// Other fields, including multi-line ones, are skipped and invalid UTF-8 is replaced, so
+/// unusual fields do not make the whole file unreadable.
+fn for_each_stanza<R, F>(mut reader: R, fields: &[&str], mut f: F) -> std::io::Result<()>
+where
+    R: BufRead,
+    F: FnMut(&[Option<String>]),
+{
+    let mut values = vec![None; fields.len()];
+    let mut in_stanza = false;
+    let mut line = Vec::new();
+
+    loop {
+        line.clear();
+        let eof = reader.read_until(b'\n', &mut line)? == 0;
+        let text = String::from_utf8_lossy(&line);
+
+        if eof || text.trim().is_empty() {
+            if in_stanza {
+                f(&values);
+                values.iter_mut().for_each(|value| *value = None);
+                in_stanza = false;
+            }
+            if eof {
+                return Ok(());
+            }
+            continue;
+        }
+        in_stanza = true;
+
+        // continuation lines of multi-line fields start with whitespace
+        if text.starts_with([' ', '\t']) {
+            continue;
+        }
+        if let Some((name, value)) = text.split_once(':') {
+            if let Some(pos) = fields
+                .iter()
+                .position(|field| field.eq_ignore_ascii_case(name))
+            {
+                values[pos] = Some(value.trim().to_string());
+            }
+        }
+    }
+}
+
+/// Read the installed packages from a dpkg status file.
+fn read_installed_packages<R: BufRead>(reader: R) -> std::io::Result<Vec<APTInstalledPackage>> {
+    let mut packages = Vec::new();
+
+    for_each_stanza(
+        reader,
+        &["Package", "Version", "Architecture", "Status"],
+        |values| {
+            let [Some(package), Some(version), Some(arch), Some(status)] = values else {
+                return;
+            };
+            // e.g. `install ok installed`, removed packages may keep their configuration
+            if status.split_whitespace().nth(2) != Some("installed") {
+                return;
+            }
+            packages.push(APTInstalledPackage {
+                package: package.clone(),
+                version: version.clone(),
+                arch: arch.clone(),
+            });
+        },
+    )?;
+
+    Ok(packages)
+}
+
+/// Classify a component of a configured repository, with the rank of the origin.
+///
+/// Standard Proxmox repositories are preferred over Debian ones, which are preferred over any
+/// other repositories.
+fn classify_index(
+    repo: &APTRepository,
+    suite: &str,
+    component: &str,
+    product: &str,
+) -> (u8, String) {
+    if let Some((handle, _origin)) = standard_handle(repo, suite, component, product) {
+        return (0, handle.to_string());
+    }
+    match repo.origin_from_uris().as_deref() {
+        Some("Debian") => (1, "debian".to_string()),
+        _ => (2, ORIGIN_OTHER.to_string()),
+    }
+}
+
+/// The filename prefixes of the cached package indices of the enabled repositories in `files`.
+fn package_indices(files: &[APTRepositoryFile], product: &str) -> Vec<(String, (u8, String))> {
+    let mut indices = Vec::new();
+    for repo in files.iter().flat_map(|file| file.repositories.iter()) {
+        if !repo.enabled || !repo.types.contains(&APTRepositoryPackageType::Deb) {
+            continue;
+        }
+        for uri in &repo.uris {
+            for suite in &repo.suites {
+                for component in &repo.components {
+                    indices.push((
+                        packages_filename_prefix(uri, suite, component),
+                        classify_index(repo, suite, component, product),
+                    ));
+                }
+            }
+        }
+    }
+    indices
+}
+
+/// Classify the installed packages by the origin of their installed version.
+///
+/// `dpkg_status` is the dpkg status file, usually [`DPKG_STATUS_FILENAME`], and `apt_lists_dir`
+/// the directory with the package indices downloaded by `apt-get update`, usually
+/// `/var/lib/apt/lists`. Only uncompressed indices of the enabled repositories in `files` are
+/// considered. A package is classified as the handle of the standard Proxmox repository, as
+/// `debian` or as `other`, depending on the repositories providing its installed version, or as
+/// `local` if no configured repository provides it.
+pub fn package_inventory(
+    dpkg_status: &Path,
+    apt_lists_dir: &Path,
+    files: &[APTRepositoryFile],
+    product: &str,
+) -> Result<APTPackageInventory, Error> {
+    let open = |path: &Path| {
+        File::open(path)
+            .map(BufReader::new)
+            .map_err(|err| format_err!("unable to open {path:?} - {err}"))
+    };
+
+    let installed = read_installed_packages(open(dpkg_status)?)
+        .map_err(|err| format_err!("unable to read {dpkg_status:?} - {err}"))?;
+
+    let mut lookup: HashMap<(&str, &str), usize> = HashMap::with_capacity(installed.len());
+    for (index, package) in installed.iter().enumerate() {
+        lookup.insert((package.package.as_str(), package.arch.as_str()), index);
+    }
+
+    let indices = package_indices(files, product);
+    let mut index_files = Vec::new();
+    for entry in std::fs::read_dir(apt_lists_dir)
+        .map_err(|err| format_err!("unable to read {apt_lists_dir:?} - {err}"))?
+    {
+        let name = entry?.file_name().to_string_lossy().into_owned();
+        if !name.ends_with("_Packages") {
+            continue;
+        }
+        if let Some((_, origin)) = indices.iter().find(|(prefix, _)| name.starts_with(prefix)) {
+            index_files.push((name, origin));
+        }
+    }
+    index_files.sort();
+
+    // the best origin providing the installed version of each package, by index into `installed`
+    let mut origins: HashMap<usize, &(u8, String)> = HashMap::new();
+    for (name, origin) in index_files {
+        let path = apt_lists_dir.join(&name);
+        for_each_stanza(
+            open(&path)?,
+            &["Package", "Version", "Architecture"],
+            |values| {
+                let [Some(package), Some(version), Some(arch)] = values else {
+                    return;
+                };
+                let Some(&index) = lookup.get(&(package.as_str(), arch.as_str())) else {
+                    return;
+                };
+                if installed[index].version != *version {
+                    return;
+                }
+                let best = origins.entry(index).or_insert(origin);
+                if origin.0 < best.0 {
+                    *best = origin;
+                }
+            },
+        )
+        .map_err(|err| format_err!("unable to read {path:?} - {err}"))?;
+    }
+
+    let mut counts = BTreeMap::new();
+    let mut local_packages = Vec::new();
+    for (index, package) in installed.iter().enumerate() {
+        match origins.get(&index) {
+            Some((_rank, origin)) => *counts.entry(origin.as_str()).or_insert(0) += 1,
+            None => {
+                *counts.entry(ORIGIN_LOCAL).or_insert(0) += 1;
+                local_packages.push(package.clone());
+            }
+        }
+    }
+    local_packages.sort_by(|a, b| (&a.package, &a.arch).cmp(&(&b.package, &b.arch)));
+
+    Ok(APTPackageInventory {
+        total: installed.len(),
+        origins: counts
+            .into_iter()
+            .map(|(origin, count)| APTPackageOriginCount {
+                origin: origin.to_string(),
+                count,
+            })
+            .collect(),
+        local_packages,
+    })
+}
@@ -1,7 +1,10 @@
 #![cfg_attr(docsrs, feature(doc_cfg, doc_auto_cfg))]
 
 mod api;
-pub use api::{add_repository_handle, change_repository, get_changelog, list_repositories};
+pub use api::{
+    add_repository_handle, add_standard_repository, change_repository, get_changelog,
+    get_package_inventory, list_repositories,
+};
 
 #[cfg(feature = "cache")]
 pub mod cache;
@@ -10,5 +13,10 @@ mod cache_api;
 #[cfg(feature = "cache")]
 pub use cache_api::{get_package_versions, list_available_apt_update, update_database};
 
+#[cfg(feature = "changelog")]
+pub mod changelog;
+
 pub mod deb822;
+pub mod inventory;
 pub mod repositories;
+pub mod updates;
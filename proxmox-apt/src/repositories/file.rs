@@ -4,13 +4,14 @@ use anyhow::{format_err, Error};
 
 use crate::repositories::release::DebianCodename;
 use proxmox_apt_api_types::{
-    APTRepository, APTRepositoryFile, APTRepositoryFileError, APTRepositoryFileType,
-    APTRepositoryInfo, APTRepositoryPackageType,
+    APTRepository, APTRepositoryEntryError, APTRepositoryFile, APTRepositoryFileError,
+    APTRepositoryFileType, APTRepositoryInfo, APTRepositoryPackageType,
 };
 
 use crate::repositories::repository::APTRepositoryImpl;
 
 mod list_parser;
+pub(crate) use list_parser::is_commented_out_repository;
 use list_parser::APTListFileParser;
 
 mod sources_parser;
@@ -19,9 +20,13 @@ use sources_parser::APTSourcesFileParser;
 use proxmox_config_digest::ConfigDigest;
 
 trait APTRepositoryParser {
-    /// Parse all repositories including the disabled ones and push them onto
-    /// the provided vector.
-    fn parse_repositories(&mut self) -> Result<Vec<APTRepository>, Error>;
+    /// Parse all repositories including the disabled ones.
+    ///
+    /// Malformed entries, including those failing the basic check, do not abort parsing but are
+    /// returned along with the repositories, with an empty path.
+    fn parse_repositories(
+        &mut self,
+    ) -> Result<(Vec<APTRepository>, Vec<APTRepositoryEntryError>), Error>;
 }
 
 pub trait APTRepositoryFileImpl {
@@ -45,9 +50,16 @@ pub trait APTRepositoryFileImpl {
     /// Parses the APT repositories configured in the file on disk, including
     /// disabled ones.
     ///
-    /// Resets the current repositories and digest, even on failure.
+    /// Malformed entries are collected in `entry_errors`, only errors reading the file fail.
+    ///
+    /// Resets the current repositories, entry errors and digest, even on failure.
     fn parse(&mut self) -> Result<(), APTRepositoryFileError>;
 
+    /// Renders the repositories as they would be written to the file on disk.
+    ///
+    /// Malformed entries are written unchanged, before the repository they preceded.
+    fn render(&self) -> Result<Vec<u8>, APTRepositoryFileError>;
+
     /// Writes the repositories to the file on disk.
     ///
     /// If a digest is provided, checks that the current content of the file still
@@ -136,6 +148,7 @@ impl APTRepositoryFileImpl for APTRepositoryFile {
             repositories: vec![],
             digest: None,
             content: None,
+            entry_errors: vec![],
         }))
     }
 
@@ -146,6 +159,7 @@ impl APTRepositoryFileImpl for APTRepositoryFile {
             path: None,
             repositories: vec![],
             digest: None,
+            entry_errors: vec![],
         }
     }
 
@@ -183,6 +197,7 @@ impl APTRepositoryFileImpl for APTRepositoryFile {
 
     fn parse(&mut self) -> Result<(), APTRepositoryFileError> {
         self.repositories.clear();
+        self.entry_errors.clear();
         self.digest = None;
 
         let (content, digest) = self.read_with_digest()?;
@@ -192,19 +207,48 @@ impl APTRepositoryFileImpl for APTRepositoryFile {
             APTRepositoryFileType::Sources => Box::new(APTSourcesFileParser::new(&content[..])),
         };
 
-        let repos = parser.parse_repositories().map_err(|err| self.err(err))?;
+        let (repos, mut entry_errors) = parser.parse_repositories().map_err(|err| self.err(err))?;
 
-        for (n, repo) in repos.iter().enumerate() {
-            repo.basic_check()
-                .map_err(|err| self.err(format_err!("check for repository {} - {}", n + 1, err)))?;
+        for entry_error in entry_errors.iter_mut() {
+            entry_error.path = self.path.clone().unwrap_or_default();
         }
 
         self.repositories = repos;
+        self.entry_errors = entry_errors;
         self.digest = Some(*digest);
 
         Ok(())
     }
 
+    fn render(&self) -> Result<Vec<u8>, APTRepositoryFileError> {
+        let mut content = vec![];
+
+        let write_entry_errors = |content: &mut Vec<u8>, index: usize| {
+            let entries = self.entry_errors.iter().filter(|entry| match index {
+                index if index == self.repositories.len() => entry.index >= index,
+                index => entry.index == index,
+            });
+            for entry in entries {
+                content.extend_from_slice(entry.text.as_bytes());
+                content.extend_from_slice(b"\n\n");
+            }
+        };
+
+        for (n, repo) in self.repositories.iter().enumerate() {
+            write_entry_errors(&mut content, n);
+
+            repo.basic_check()
+                .map_err(|err| self.err(format_err!("check for repository {} - {}", n + 1, err)))?;
+
+            repo.write(&mut content)
+                .map_err(|err| self.err(format_err!("writing repository {} - {}", n + 1, err)))?;
+        }
+
+        write_entry_errors(&mut content, self.repositories.len());
+
+        Ok(content)
+    }
+
     fn write(&self) -> Result<(), APTRepositoryFileError> {
         let path = match &self.path {
             Some(path) => path,
@@ -226,20 +270,12 @@ impl APTRepositoryFileImpl for APTRepositoryFile {
             }
         }
 
-        if self.repositories.is_empty() {
+        if self.repositories.is_empty() && self.entry_errors.is_empty() {
             return std::fs::remove_file(path)
                 .map_err(|err| self.err(format_err!("unable to remove file - {}", err)));
         }
 
-        let mut content = vec![];
-
-        for (n, repo) in self.repositories.iter().enumerate() {
-            repo.basic_check()
-                .map_err(|err| self.err(format_err!("check for repository {} - {}", n + 1, err)))?;
-
-            repo.write(&mut content)
-                .map_err(|err| self.err(format_err!("writing repository {} - {}", n + 1, err)))?;
-        }
+        let content = self.render()?;
 
         let path = PathBuf::from(&path);
         let dir = match path.parent() {
@@ -5,7 +5,9 @@ use anyhow::{bail, format_err, Error};
 
 use super::APTRepositoryParser;
 use crate::repositories::APTRepositoryImpl;
-use crate::repositories::{APTRepository, APTRepositoryFileType, APTRepositoryOption};
+use crate::repositories::{
+    APTRepository, APTRepositoryEntryError, APTRepositoryFileType, APTRepositoryOption,
+};
 
 // TODO convert %-escape characters. Also adapt printing back accordingly,
 // because at least '%' needs to be re-escaped when printing.
@@ -79,6 +81,12 @@ impl Iterator for SplitQuoteWord {
     }
 }
 
+/// Check if a comment line, including its `#`, is read back as a commented out repository.
+pub(crate) fn is_commented_out_repository(line: &str) -> bool {
+    let mut parser = APTListFileParser::new(std::io::empty());
+    matches!(parser.parse_one_line(line), Ok(Some(_)))
+}
+
 pub struct APTListFileParser<R: BufRead> {
     input: R,
     line_nr: usize,
@@ -160,16 +168,21 @@ impl<R: BufRead> APTListFileParser<R> {
     /// If the line contains a repository, `self.comment` is added to the
     /// `comment` property.
     ///
-    /// If the line contains a comment, it is added to `self.comment`.
+    /// If the line contains a comment, it is added to `self.comment`. This includes a comment
+    /// following a repository on the same line, so it is associated with that repository, like
+    /// all comments since the previous repository, even if separated by blank lines.
     fn parse_one_line(&mut self, mut line: &str) -> Result<Option<APTRepository>, Error> {
         line = line.trim_matches(|c| char::is_ascii_whitespace(&c));
 
         // check for commented out repository first
         if let Some(commented_out) = line.strip_prefix('#') {
+            let comment = self.comment.clone();
             if let Ok(Some(mut repo)) = self.parse_one_line(commented_out) {
                 repo.set_enabled(false);
                 return Ok(Some(repo));
             }
+            // a comment containing '#' is added once and as a whole below
+            self.comment = comment;
         }
 
         let mut repo = APTRepository::new(APTRepositoryFileType::List);
@@ -226,8 +239,11 @@ impl<R: BufRead> APTListFileParser<R> {
 }
 
 impl<R: BufRead> APTRepositoryParser for APTListFileParser<R> {
-    fn parse_repositories(&mut self) -> Result<Vec<APTRepository>, Error> {
+    fn parse_repositories(
+        &mut self,
+    ) -> Result<(Vec<APTRepository>, Vec<APTRepositoryEntryError>), Error> {
         let mut repos = vec![];
+        let mut errors = vec![];
         let mut line = String::new();
 
         loop {
@@ -237,14 +253,33 @@ impl<R: BufRead> APTRepositoryParser for APTListFileParser<R> {
             match self.input.read_line(&mut line) {
                 Err(err) => bail!("input error - {}", err),
                 Ok(0) => break,
-                Ok(_) => match self.parse_one_line(&line) {
-                    Ok(Some(repo)) => repos.push(repo),
-                    Ok(None) => continue,
-                    Err(err) => bail!("malformed entry on line {} - {}", self.line_nr, err),
-                },
+                Ok(_) => {
+                    let comment = self.comment.clone();
+                    let result = self.parse_one_line(&line).and_then(|repo| {
+                        if let Some(repo) = &repo {
+                            repo.basic_check()?;
+                        }
+                        Ok(repo)
+                    });
+                    match result {
+                        Ok(Some(repo)) => repos.push(repo),
+                        Ok(None) => continue,
+                        Err(err) => {
+                            // the line is kept as is, including a trailing comment
+                            self.comment = comment;
+                            errors.push(APTRepositoryEntryError {
+                                path: String::new(),
+                                line: self.line_nr,
+                                index: repos.len(),
+                                text: line.trim_end_matches(['\r', '\n']).to_string(),
+                                error: err.to_string(),
+                            });
+                        }
+                    }
+                }
             }
         }
 
-        Ok(repos)
+        Ok((repos, errors))
     }
 }
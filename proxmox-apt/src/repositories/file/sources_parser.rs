@@ -5,14 +5,14 @@ use anyhow::{bail, Error};
 
 use crate::repositories::APTRepositoryImpl;
 use crate::repositories::{
-    APTRepository, APTRepositoryFileType, APTRepositoryOption, APTRepositoryPackageType,
+    APTRepository, APTRepositoryEntryError, APTRepositoryFileType, APTRepositoryOption,
+    APTRepositoryPackageType,
 };
 
 use super::APTRepositoryParser;
 
 pub struct APTSourcesFileParser<R: BufRead> {
     input: R,
-    stanza_nr: usize,
     comment: String,
 }
 
@@ -21,7 +21,6 @@ impl<R: BufRead> APTSourcesFileParser<R> {
     pub fn new(reader: R) -> Self {
         Self {
             input: reader,
-            stanza_nr: 1,
             comment: String::new(),
         }
     }
@@ -155,50 +154,80 @@ impl<R: BufRead> APTSourcesFileParser<R> {
     }
 
     /// Helper function for `parse_repositories`.
+    ///
+    /// `line_nr` is the number of the first line of `lines`.
     fn try_parse_stanza(
         &mut self,
         lines: &str,
+        line_nr: usize,
         repos: &mut Vec<APTRepository>,
-    ) -> Result<(), Error> {
-        match self.parse_stanza(lines) {
-            Ok(Some(repo)) => {
-                repos.push(repo);
-                self.stanza_nr += 1;
+        errors: &mut Vec<APTRepositoryEntryError>,
+    ) {
+        let comment = self.comment.clone();
+        let result = self.parse_stanza(lines).and_then(|repo| {
+            if let Some(repo) = &repo {
+                repo.basic_check()?;
             }
+            Ok(repo)
+        });
+
+        match result {
+            Ok(Some(repo)) => repos.push(repo),
             Ok(None) => (),
-            Err(err) => bail!("malformed entry in stanza {} - {}", self.stanza_nr, err),
-        }
+            Err(err) => {
+                // the stanza is kept as is, including its comments
+                self.comment = comment;
+
+                let mut text: Vec<&str> = lines.lines().collect();
+                while text.last().is_some_and(|line| line.trim().is_empty()) {
+                    text.pop();
+                }
 
-        Ok(())
+                errors.push(APTRepositoryEntryError {
+                    path: String::new(),
+                    line: line_nr,
+                    index: repos.len(),
+                    text: text.join("\n"),
+                    error: err.to_string(),
+                });
+            }
+        }
     }
 }
 
 impl<R: BufRead> APTRepositoryParser for APTSourcesFileParser<R> {
-    fn parse_repositories(&mut self) -> Result<Vec<APTRepository>, Error> {
+    fn parse_repositories(
+        &mut self,
+    ) -> Result<(Vec<APTRepository>, Vec<APTRepositoryEntryError>), Error> {
         let mut repos = vec![];
+        let mut errors = vec![];
         let mut lines = String::new();
+        let mut line_nr = 0;
+        let mut stanza_line_nr = 1;
 
         loop {
             let old_length = lines.len();
             match self.input.read_line(&mut lines) {
                 Err(err) => bail!("input error - {}", err),
                 Ok(0) => {
-                    self.try_parse_stanza(&lines[..], &mut repos)?;
+                    self.try_parse_stanza(&lines[..], stanza_line_nr, &mut repos, &mut errors);
                     break;
                 }
                 Ok(_) => {
+                    line_nr += 1;
                     if (lines[old_length..])
                         .trim_matches(|c| char::is_ascii_whitespace(&c))
                         .is_empty()
                     {
                         // detected end of stanza
-                        self.try_parse_stanza(&lines[..], &mut repos)?;
+                        self.try_parse_stanza(&lines[..], stanza_line_nr, &mut repos, &mut errors);
                         lines.clear();
+                        stanza_line_nr = line_nr + 1;
                     }
                 }
             }
         }
 
-        Ok(repos)
+        Ok((repos, errors))
     }
 }
@@ -1,15 +1,17 @@
 use std::collections::BTreeMap;
-use std::path::{Path, PathBuf};
+use std::path::Path;
 
 use anyhow::{bail, Error};
 
 mod repository;
 use proxmox_apt_api_types::{
-    APTRepository, APTRepositoryFile, APTRepositoryFileError, APTRepositoryFileType,
+    APTAddRepositoryOptions, APTRepository, APTRepositoryChange, APTRepositoryChangeKind,
+    APTRepositoryEntryError, APTRepositoryFile, APTRepositoryFileError, APTRepositoryFileType,
     APTRepositoryHandle, APTRepositoryInfo, APTRepositoryOption, APTRepositoryPackageType,
-    APTStandardRepository,
+    APTStandardRepository, CephReleaseCodename,
 };
 use proxmox_config_digest::ConfigDigest;
+pub(crate) use repository::packages_filename_prefix;
 pub use repository::APTRepositoryImpl;
 
 mod file;
@@ -21,8 +23,8 @@ pub use release::{get_current_release_codename, DebianCodename};
 mod standard;
 pub use standard::{APTRepositoryHandleImpl, APTStandardRepositoryImpl};
 
-const APT_SOURCES_LIST_FILENAME: &str = "/etc/apt/sources.list";
-const APT_SOURCES_LIST_DIRECTORY: &str = "/etc/apt/sources.list.d/";
+pub(crate) const APT_SOURCES_LIST_FILENAME: &str = "/etc/apt/sources.list";
+pub(crate) const APT_SOURCES_LIST_DIRECTORY: &str = "/etc/apt/sources.list.d/";
 
 /// Calculates a common digest for successfully parsed repository files.
 ///
@@ -80,6 +82,94 @@ pub fn get_standard_repository(
     (repo, path)
 }
 
+/// Add the standard repository identified by `handle` to the given `sources.list` file and
+/// `sources.list.d` directory, see [`add_standard_repository`](crate::add_standard_repository).
+pub fn add_standard_repository_in(
+    sources_list_path: &Path,
+    sources_list_d_path: &Path,
+    handle: APTRepositoryHandle,
+    product: &str,
+    suite: DebianCodename,
+    options: &APTAddRepositoryOptions,
+) -> Result<APTRepositoryChange, Error> {
+    let (mut files, errors, current_digest) =
+        repositories_in(sources_list_path, sources_list_d_path)?;
+
+    current_digest.detect_modification(options.digest.as_ref())?;
+
+    let dry_run = options.dry_run.unwrap_or(false);
+    let suite = suite.to_string();
+
+    let apply = |file: &APTRepositoryFile,
+                 index: usize,
+                 kind: APTRepositoryChangeKind|
+     -> Result<APTRepositoryChange, Error> {
+        let content = String::from_utf8(file.render()?)?;
+        if !dry_run && kind != APTRepositoryChangeKind::Unchanged {
+            file.write()?;
+        }
+        Ok(APTRepositoryChange {
+            path: file.path.clone().unwrap_or_default(),
+            index,
+            kind,
+            content,
+        })
+    };
+
+    // check if it's already configured first
+    for file in files.iter_mut() {
+        let index = file
+            .repositories
+            .iter()
+            .position(|repo| repo.is_referenced_repository(handle, product, &suite));
+        if let Some(index) = index {
+            let repo = &mut file.repositories[index];
+            if repo.enabled {
+                return apply(file, index, APTRepositoryChangeKind::Unchanged);
+            }
+            repo.set_enabled(true);
+            return apply(file, index, APTRepositoryChangeKind::Enabled);
+        }
+    }
+
+    let repo = handle.to_repository(product, &suite);
+    let path = match handle.path(product) {
+        path if path == APT_SOURCES_LIST_FILENAME => sources_list_path.to_path_buf(),
+        path => sources_list_d_path.join(Path::new(&path).file_name().unwrap()),
+    };
+    let path = path.display().to_string();
+
+    if let Some(error) = errors.iter().find(|error| error.path == path) {
+        bail!(
+            "unable to parse existing file {} - {}",
+            error.path,
+            error.error,
+        );
+    }
+
+    match files
+        .iter_mut()
+        .find(|file| file.path.as_ref() == Some(&path))
+    {
+        Some(file) => {
+            file.repositories.push(repo);
+            apply(
+                file,
+                file.repositories.len() - 1,
+                APTRepositoryChangeKind::Appended,
+            )
+        }
+        None => {
+            let mut file = match APTRepositoryFile::new(&path)? {
+                Some(file) => file,
+                None => bail!("invalid path - {}", path),
+            };
+            file.repositories.push(repo);
+            apply(&file, 0, APTRepositoryChangeKind::Created)
+        }
+    }
+}
+
 /// Return handles for standard Proxmox repositories and their status, where
 /// `None` means not configured, and `Some(bool)` indicates enabled or disabled.
 pub fn standard_repositories(
@@ -95,15 +185,27 @@ pub fn standard_repositories(
 
     if product == "pve" {
         result.append(&mut vec![
-            APTStandardRepository::from_handle(APTRepositoryHandle::CephQuincyEnterprise),
-            APTStandardRepository::from_handle(APTRepositoryHandle::CephQuincyNoSubscription),
-            APTStandardRepository::from_handle(APTRepositoryHandle::CephQuincyTest),
+            APTStandardRepository::from_handle(APTRepositoryHandle::CephEnterprise(
+                CephReleaseCodename::Quincy,
+            )),
+            APTStandardRepository::from_handle(APTRepositoryHandle::CephNoSubscription(
+                CephReleaseCodename::Quincy,
+            )),
+            APTStandardRepository::from_handle(APTRepositoryHandle::CephTest(
+                CephReleaseCodename::Quincy,
+            )),
         ]);
         if suite == DebianCodename::Bookworm {
             result.append(&mut vec![
-                APTStandardRepository::from_handle(APTRepositoryHandle::CephReefEnterprise),
-                APTStandardRepository::from_handle(APTRepositoryHandle::CephReefNoSubscription),
-                APTStandardRepository::from_handle(APTRepositoryHandle::CephReefTest),
+                APTStandardRepository::from_handle(APTRepositoryHandle::CephEnterprise(
+                    CephReleaseCodename::Reef,
+                )),
+                APTStandardRepository::from_handle(APTRepositoryHandle::CephNoSubscription(
+                    CephReleaseCodename::Reef,
+                )),
+                APTStandardRepository::from_handle(APTRepositoryHandle::CephTest(
+                    CephReleaseCodename::Reef,
+                )),
             ]);
         }
     }
@@ -138,6 +240,17 @@ pub type Repositories = (
 ///
 /// The digest is guaranteed to be set for each successfully parsed file.
 pub fn repositories() -> Result<Repositories, Error> {
+    repositories_in(
+        Path::new(APT_SOURCES_LIST_FILENAME),
+        Path::new(APT_SOURCES_LIST_DIRECTORY),
+    )
+}
+
+/// Like [`repositories`], but for the given `sources.list` file and `sources.list.d` directory.
+pub fn repositories_in(
+    sources_list_path: &Path,
+    sources_list_d_path: &Path,
+) -> Result<Repositories, Error> {
     let to_result = |files: Vec<APTRepositoryFile>, errors: Vec<APTRepositoryFileError>| {
         let common_digest = common_digest(&files);
 
@@ -147,10 +260,6 @@ pub fn repositories() -> Result<Repositories, Error> {
     let mut files = vec![];
     let mut errors = vec![];
 
-    let sources_list_path = PathBuf::from(APT_SOURCES_LIST_FILENAME);
-
-    let sources_list_d_path = PathBuf::from(APT_SOURCES_LIST_DIRECTORY);
-
     if sources_list_path.exists() {
         if sources_list_path.is_file() {
             match APTRepositoryFile::new(sources_list_path) {
@@ -158,11 +267,11 @@ pub fn repositories() -> Result<Repositories, Error> {
                     Ok(()) => files.push(file),
                     Err(err) => errors.push(err),
                 },
-                _ => bail!("internal error with '{}'", APT_SOURCES_LIST_FILENAME),
+                _ => bail!("internal error with '{}'", sources_list_path.display()),
             }
         } else {
             errors.push(APTRepositoryFileError {
-                path: APT_SOURCES_LIST_FILENAME.to_string(),
+                path: sources_list_path.display().to_string(),
                 error: "not a regular file!".to_string(),
             });
         }
@@ -174,7 +283,7 @@ pub fn repositories() -> Result<Repositories, Error> {
 
     if !sources_list_d_path.is_dir() {
         errors.push(APTRepositoryFileError {
-            path: APT_SOURCES_LIST_DIRECTORY.to_string(),
+            path: sources_list_d_path.display().to_string(),
             error: "not a directory!".to_string(),
         });
         return Ok(to_result(files, errors));
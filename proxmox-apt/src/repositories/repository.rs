@@ -3,9 +3,11 @@ use std::path::{Path, PathBuf};
 
 use anyhow::{bail, format_err, Error};
 
+use crate::repositories::file::is_commented_out_repository;
 use crate::repositories::standard::APTRepositoryHandleImpl;
 use proxmox_apt_api_types::{
     APTRepository, APTRepositoryFileType, APTRepositoryHandle, APTRepositoryOption,
+    CephReleaseCodename,
 };
 
 pub trait APTRepositoryImpl {
@@ -16,6 +18,16 @@ pub trait APTRepositoryImpl {
     /// `APTRepositoryPackageType::Sources` repositories is updated too.
     fn set_enabled(&mut self, enabled: bool);
 
+    /// Replaces the comment, an empty `comment` removes it.
+    ///
+    /// Each line is written as `#`-prefixed line above the repository. A space is inserted after
+    /// the `#` unless the line already starts with whitespace, so a comment as returned by the
+    /// parser is kept as is.
+    ///
+    /// Fails for one-line repositories if a line of the comment is itself a repository entry,
+    /// since it would be read back as a disabled repository.
+    fn set_comment(&mut self, comment: &str) -> Result<(), Error>;
+
     /// Makes sure that all basic properties of a repository are present and not obviously invalid.
     fn basic_check(&self) -> Result<(), Error>;
 
@@ -76,6 +88,31 @@ impl APTRepositoryImpl for APTRepository {
         }
     }
 
+    fn set_comment(&mut self, comment: &str) -> Result<(), Error> {
+        let comment: String = comment
+            .lines()
+            .map(|line| {
+                if line.is_empty() || line.starts_with(|c: char| c.is_ascii_whitespace()) {
+                    format!("{line}\n")
+                } else {
+                    format!(" {line}\n")
+                }
+            })
+            .collect();
+
+        if self.file_type == APTRepositoryFileType::List {
+            if let Some(line) = comment
+                .lines()
+                .find(|line| is_commented_out_repository(&format!("#{line}")))
+            {
+                bail!("comment line '{}' is a repository entry", line.trim());
+            }
+        }
+
+        self.comment = comment;
+        Ok(())
+    }
+
     fn basic_check(&self) -> Result<(), Error> {
         if self.types.is_empty() {
             bail!("missing package type(s)");
@@ -134,7 +171,9 @@ impl APTRepositoryImpl for APTRepository {
 
         // In the past it was main instead of enterprise/no-subscription, and main now maps to
         // no-subscription. Note this only applies for Quincy.
-        let found_component = if handle == APTRepositoryHandle::CephQuincyNoSubscription {
+        let found_component = if handle
+            == APTRepositoryHandle::CephNoSubscription(CephReleaseCodename::Quincy)
+        {
             self.components.contains(&component) || self.components.contains(&"main".to_string())
         } else {
             self.components.contains(&component)
@@ -224,6 +263,14 @@ fn release_filename(apt_lists_dir: &Path, uri: &str, suite: &str, detached: bool
     path
 }
 
+/// Get the common prefix of the cached `Packages` files of a repository component, followed by
+/// the architecture, e.g. `deb.debian.org_debian_dists_bookworm_main_binary-`.
+pub(crate) fn packages_filename_prefix(uri: &str, suite: &str, component: &str) -> String {
+    let encoded_uri = uri_to_filename(uri);
+    let normalized_suite = suite.replace('/', "_");
+    format!("{encoded_uri}_dists_{normalized_suite}_{component}_binary-")
+}
+
 /// See APT's URItoFileName in contrib/strutl.cc
 fn uri_to_filename(uri: &str) -> String {
     let mut filename = uri;
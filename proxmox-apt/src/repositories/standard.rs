@@ -1,6 +1,6 @@
 use proxmox_apt_api_types::{
     APTRepository, APTRepositoryFileType, APTRepositoryHandle, APTRepositoryPackageType,
-    APTStandardRepository,
+    APTStandardRepository, CephReleaseCodename,
 };
 
 pub trait APTStandardRepositoryImpl {
@@ -36,62 +36,71 @@ pub trait APTRepositoryHandleImpl {
     fn to_repository(self, product: &str, suite: &str) -> APTRepository;
 }
 
+/// Get the display name of a Ceph release.
+fn ceph_release_name(release: CephReleaseCodename) -> &'static str {
+    match release {
+        CephReleaseCodename::Quincy => "Quincy",
+        CephReleaseCodename::Reef => "Reef",
+    }
+}
+
 impl APTRepositoryHandleImpl for APTRepositoryHandle {
     fn description(self) -> String {
         match self {
             APTRepositoryHandle::Enterprise => {
                 "This is the default, stable, and recommended repository, available for all \
                 Proxmox subscription users."
+                    .to_string()
             }
             APTRepositoryHandle::NoSubscription => {
                 "This is the recommended repository for testing and non-production use. \
                 Its packages are not as heavily tested and validated as the production ready \
                 enterprise repository. You don't need a subscription key to access this repository."
+                    .to_string()
             }
             APTRepositoryHandle::Test => {
                 "This repository contains the latest packages and is primarily used for test labs \
                 and by developers to test new features."
+                    .to_string()
             }
-            APTRepositoryHandle::CephQuincyEnterprise => {
-                "This repository holds the production-ready Proxmox Ceph Quincy packages."
-            }
-            APTRepositoryHandle::CephQuincyNoSubscription => {
+            APTRepositoryHandle::CephEnterprise(release) => format!(
+                "This repository holds the production-ready Proxmox Ceph {} packages.",
+                ceph_release_name(release),
+            ),
+            APTRepositoryHandle::CephNoSubscription(CephReleaseCodename::Quincy) => {
                 "This repository holds the Proxmox Ceph Quincy packages intended for \
                 non-production use. The deprecated 'main' repository is an alias for this in \
                 Proxmox VE 8."
+                    .to_string()
             }
-            APTRepositoryHandle::CephQuincyTest => {
-                "This repository contains the Ceph Quincy packages before they are moved to the \
-                main repository."
-            }
-            APTRepositoryHandle::CephReefEnterprise => {
-                "This repository holds the production-ready Proxmox Ceph Reef packages."
-            }
-            APTRepositoryHandle::CephReefNoSubscription => {
-                "This repository holds the Proxmox Ceph Reef packages intended for \
-                non-production use."
-            }
-            APTRepositoryHandle::CephReefTest => {
-                "This repository contains the Ceph Reef packages before they are moved to the \
-                main repository."
-            }
+            APTRepositoryHandle::CephNoSubscription(release) => format!(
+                "This repository holds the Proxmox Ceph {} packages intended for \
+                non-production use.",
+                ceph_release_name(release),
+            ),
+            APTRepositoryHandle::CephTest(release) => format!(
+                "This repository contains the Ceph {} packages before they are moved to the \
+                main repository.",
+                ceph_release_name(release),
+            ),
         }
-        .to_string()
     }
 
     fn name(self) -> String {
         match self {
-            APTRepositoryHandle::Enterprise => "Enterprise",
-            APTRepositoryHandle::NoSubscription => "No-Subscription",
-            APTRepositoryHandle::Test => "Test",
-            APTRepositoryHandle::CephQuincyEnterprise => "Ceph Quincy Enterprise",
-            APTRepositoryHandle::CephQuincyNoSubscription => "Ceph Quincy No-Subscription",
-            APTRepositoryHandle::CephQuincyTest => "Ceph Quincy Test",
-            APTRepositoryHandle::CephReefEnterprise => "Ceph Reef Enterprise",
-            APTRepositoryHandle::CephReefNoSubscription => "Ceph Reef No-Subscription",
-            APTRepositoryHandle::CephReefTest => "Ceph Reef Test",
+            APTRepositoryHandle::Enterprise => "Enterprise".to_string(),
+            APTRepositoryHandle::NoSubscription => "No-Subscription".to_string(),
+            APTRepositoryHandle::Test => "Test".to_string(),
+            APTRepositoryHandle::CephEnterprise(release) => {
+                format!("Ceph {} Enterprise", ceph_release_name(release))
+            }
+            APTRepositoryHandle::CephNoSubscription(release) => {
+                format!("Ceph {} No-Subscription", ceph_release_name(release))
+            }
+            APTRepositoryHandle::CephTest(release) => {
+                format!("Ceph {} Test", ceph_release_name(release))
+            }
         }
-        .to_string()
     }
 
     fn path(self, product: &str) -> String {
@@ -101,12 +110,9 @@ impl APTRepositoryHandleImpl for APTRepositoryHandle {
             }
             APTRepositoryHandle::NoSubscription => "/etc/apt/sources.list".to_string(),
             APTRepositoryHandle::Test => "/etc/apt/sources.list".to_string(),
-            APTRepositoryHandle::CephQuincyEnterprise
-            | APTRepositoryHandle::CephQuincyNoSubscription
-            | APTRepositoryHandle::CephQuincyTest
-            | APTRepositoryHandle::CephReefEnterprise
-            | APTRepositoryHandle::CephReefNoSubscription
-            | APTRepositoryHandle::CephReefTest => "/etc/apt/sources.list.d/ceph.list".to_string(),
+            APTRepositoryHandle::CephEnterprise(_)
+            | APTRepositoryHandle::CephNoSubscription(_)
+            | APTRepositoryHandle::CephTest(_) => "/etc/apt/sources.list.d/ceph.list".to_string(),
         }
     }
 
@@ -145,34 +151,21 @@ impl APTRepositoryHandleImpl for APTRepositoryHandle {
                 },
                 format!("{}test", product),
             ),
-            APTRepositoryHandle::CephQuincyEnterprise => (
-                APTRepositoryPackageType::Deb,
-                vec!["https://enterprise.proxmox.com/debian/ceph-quincy".to_string()],
-                "enterprise".to_string(),
-            ),
-            APTRepositoryHandle::CephQuincyNoSubscription => (
-                APTRepositoryPackageType::Deb,
-                vec!["http://download.proxmox.com/debian/ceph-quincy".to_string()],
-                "no-subscription".to_string(),
-            ),
-            APTRepositoryHandle::CephQuincyTest => (
-                APTRepositoryPackageType::Deb,
-                vec!["http://download.proxmox.com/debian/ceph-quincy".to_string()],
-                "test".to_string(),
-            ),
-            APTRepositoryHandle::CephReefEnterprise => (
+            APTRepositoryHandle::CephEnterprise(release) => (
                 APTRepositoryPackageType::Deb,
-                vec!["https://enterprise.proxmox.com/debian/ceph-reef".to_string()],
+                vec![format!(
+                    "https://enterprise.proxmox.com/debian/ceph-{release}"
+                )],
                 "enterprise".to_string(),
             ),
-            APTRepositoryHandle::CephReefNoSubscription => (
+            APTRepositoryHandle::CephNoSubscription(release) => (
                 APTRepositoryPackageType::Deb,
-                vec!["http://download.proxmox.com/debian/ceph-reef".to_string()],
+                vec![format!("http://download.proxmox.com/debian/ceph-{release}")],
                 "no-subscription".to_string(),
             ),
-            APTRepositoryHandle::CephReefTest => (
+            APTRepositoryHandle::CephTest(release) => (
                 APTRepositoryPackageType::Deb,
-                vec!["http://download.proxmox.com/debian/ceph-reef".to_string()],
+                vec![format!("http://download.proxmox.com/debian/ceph-{release}")],
                 "test".to_string(),
             ),
         }
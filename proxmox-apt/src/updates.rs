@@ -0,0 +1,494 @@
+//! List the available package updates and classify the repositories they come from.
+//!
+//! The updates are determined from the output of APT's command line tools, so that this works
+//! without linking against libapt:
+//!
+//! * `apt-get -s dist-upgrade` for the packages to update and their versions,
+//! * `apt-cache policy <packages>` for the repositories providing the new versions,
+//! * `apt-cache show --no-all-versions <packages>` for the package metadata.
+
+use std::collections::HashMap;
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{bail, format_err, Error};
+use rfc822_like::de::Deserializer;
+use serde::Deserialize;
+
+use proxmox_apt_api_types::{
+    APTRepository, APTRepositoryFile, APTRepositoryHandle, APTUpdateInfo, APTUpdateOrigin,
+    CephReleaseCodename,
+};
+
+use crate::repositories::{APTRepositoryHandleImpl, APTRepositoryImpl};
+
+/// The handles of the Proxmox repositories and the origin class of their packages.
+const PROXMOX_HANDLES: &[(APTRepositoryHandle, APTUpdateOrigin)] = &[
+    (
+        APTRepositoryHandle::Enterprise,
+        APTUpdateOrigin::ProxmoxEnterprise,
+    ),
+    (
+        APTRepositoryHandle::NoSubscription,
+        APTUpdateOrigin::ProxmoxNoSubscription,
+    ),
+    (APTRepositoryHandle::Test, APTUpdateOrigin::ProxmoxTest),
+    (
+        APTRepositoryHandle::CephEnterprise(CephReleaseCodename::Quincy),
+        APTUpdateOrigin::ProxmoxEnterprise,
+    ),
+    (
+        APTRepositoryHandle::CephNoSubscription(CephReleaseCodename::Quincy),
+        APTUpdateOrigin::ProxmoxNoSubscription,
+    ),
+    (
+        APTRepositoryHandle::CephTest(CephReleaseCodename::Quincy),
+        APTUpdateOrigin::ProxmoxTest,
+    ),
+    (
+        APTRepositoryHandle::CephEnterprise(CephReleaseCodename::Reef),
+        APTUpdateOrigin::ProxmoxEnterprise,
+    ),
+    (
+        APTRepositoryHandle::CephNoSubscription(CephReleaseCodename::Reef),
+        APTUpdateOrigin::ProxmoxNoSubscription,
+    ),
+    (
+        APTRepositoryHandle::CephTest(CephReleaseCodename::Reef),
+        APTUpdateOrigin::ProxmoxTest,
+    ),
+];
+
+/// A package to be installed or upgraded, from the simulation of a `dist-upgrade`.
+#[derive(Debug, PartialEq)]
+struct SimulatedUpdate {
+    package: String,
+    arch: String,
+    old_version: Option<String>,
+    version: String,
+    /// The releases providing the new version, e.g. `Debian:12.7/stable`.
+    release: String,
+}
+
+/// Parse the `Inst` lines of `apt-get -s dist-upgrade`, e.g.
+/// `Inst bash [5.2.15-2+b2] (5.2.15-2+b7 Debian:12.7/stable [amd64])`.
+fn parse_simulation(output: &str) -> Result<Vec<SimulatedUpdate>, Error> {
+    let mut updates: Vec<SimulatedUpdate> = Vec::new();
+
+    for line in output.lines() {
+        let Some(rest) = line.strip_prefix("Inst ") else {
+            continue;
+        };
+        let parse_err = || format_err!("unable to parse simulated install '{line}'");
+
+        let (package, rest) = rest.split_once(' ').ok_or_else(parse_err)?;
+        let (old_version, rest) = match rest.strip_prefix('[') {
+            Some(rest) => {
+                let (old_version, rest) = rest.split_once("] ").ok_or_else(parse_err)?;
+                (Some(old_version.to_string()), rest)
+            }
+            None => (None, rest),
+        };
+
+        // anything after the new version, e.g. the packages an install breaks, is ignored
+        let new = rest.strip_prefix('(').ok_or_else(parse_err)?;
+        let (new, _rest) = new.split_once("])").ok_or_else(parse_err)?;
+        let (new, arch) = new.rsplit_once(" [").ok_or_else(parse_err)?;
+        let (version, release) = new.split_once(' ').unwrap_or((new, ""));
+
+        // multi-arch packages are qualified with their architecture
+        let package = package
+            .split_once(':')
+            .map_or(package, |(name, _arch)| name);
+
+        if updates
+            .iter()
+            .any(|u| u.package == package && u.arch == arch)
+        {
+            continue;
+        }
+        updates.push(SimulatedUpdate {
+            package: package.to_string(),
+            arch: arch.to_string(),
+            old_version,
+            version: version.to_string(),
+            release: release.to_string(),
+        });
+    }
+
+    Ok(updates)
+}
+
+/// A package index providing a version, from the version table of `apt-cache policy`.
+#[derive(Clone, Debug, PartialEq)]
+struct PackageSource {
+    uri: String,
+    suite: String,
+    component: String,
+    arch: String,
+}
+
+/// Parse the sources of the candidate versions from the output of `apt-cache policy`.
+///
+/// Returns the sources of the candidate version for each package.
+fn parse_policy(output: &str) -> Result<HashMap<String, Vec<PackageSource>>, Error> {
+    let mut result = HashMap::new();
+
+    let mut package: Option<&str> = None;
+    let mut candidate: Option<&str> = None;
+    let mut in_candidate = false;
+
+    for line in output.lines() {
+        if line.is_empty() {
+            continue;
+        }
+        if !line.starts_with(' ') {
+            let name = line
+                .strip_suffix(':')
+                .ok_or_else(|| format_err!("unexpected line in package policy '{line}'"))?;
+            package = Some(name.split_once(':').map_or(name, |(name, _arch)| name));
+            candidate = None;
+            in_candidate = false;
+            continue;
+        }
+        let Some(package) = package else {
+            bail!("package policy does not start with a package name");
+        };
+
+        if let Some(version) = line.trim_start().strip_prefix("Candidate: ") {
+            candidate = Some(version.trim());
+            result.insert(package.to_string(), Vec::new());
+            continue;
+        }
+
+        // version lines are indented by 5 characters, possibly starting with a ` *** ` marker for
+        // the installed version, their sources are indented further
+        let version_line = line
+            .strip_prefix(" *** ")
+            .or_else(|| line.strip_prefix("     "))
+            .filter(|rest| !rest.starts_with(' '));
+        if let Some(version_line) = version_line {
+            let version = version_line.split_whitespace().next();
+            in_candidate = candidate.is_some() && version == candidate;
+            continue;
+        }
+        if !in_candidate {
+            continue;
+        }
+
+        // e.g. `500 http://deb.debian.org/debian bookworm/main amd64 Packages`
+        let mut fields = line.split_whitespace();
+        let (Some(_priority), Some(uri), Some(dist)) =
+            (fields.next(), fields.next(), fields.next())
+        else {
+            continue;
+        };
+        // flat repositories have no architecture
+        let arch = fields.next().filter(|arch| *arch != "Packages");
+        if !uri.contains("://") {
+            // e.g. the dpkg status file of the installed version
+            continue;
+        }
+        let (suite, component) = dist.split_once('/').unwrap_or((dist, ""));
+
+        if let Some(sources) = result.get_mut(package) {
+            sources.push(PackageSource {
+                uri: uri.trim_end_matches('/').to_string(),
+                suite: suite.to_string(),
+                component: component.to_string(),
+                arch: arch.unwrap_or_default().to_string(),
+            });
+        }
+    }
+
+    Ok(result)
+}
+
+/// The metadata of a package version, from the output of `apt-cache show`.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct PackageMetadata {
+    package: String,
+    version: String,
+    // the deserializer does not support optional fields, so missing fields are empty instead
+    #[cfg(feature = "changelog")]
+    #[serde(default)]
+    source: String,
+    #[serde(default)]
+    section: String,
+    #[serde(default)]
+    priority: String,
+    #[serde(default)]
+    description: String,
+}
+
+fn parse_package_metadata(output: &str) -> Result<Vec<PackageMetadata>, Error> {
+    <Vec<PackageMetadata>>::deserialize(Deserializer::new(output.as_bytes()))
+        .map_err(|err| format_err!("unable to parse package metadata - {err}"))
+}
+
+/// Find the configured and enabled repository providing a package index.
+fn find_repository<'a>(
+    files: &'a [APTRepositoryFile],
+    source: &PackageSource,
+) -> Option<&'a APTRepository> {
+    files
+        .iter()
+        .flat_map(|file| file.repositories.iter())
+        .filter(|repo| repo.enabled)
+        .find(|repo| {
+            repo.uris
+                .iter()
+                .any(|uri| uri.trim_end_matches('/') == source.uri)
+                && repo.suites.contains(&source.suite)
+                && (source.component.is_empty() || repo.components.contains(&source.component))
+        })
+}
+
+/// Find the standard Proxmox repository a package index of `repo` belongs to.
+pub(crate) fn standard_handle(
+    repo: &APTRepository,
+    suite: &str,
+    component: &str,
+    product: &str,
+) -> Option<(APTRepositoryHandle, APTUpdateOrigin)> {
+    PROXMOX_HANDLES.iter().copied().find(|(handle, _origin)| {
+        let (_package_type, _uris, handle_component) = handle.info(product);
+        // the deprecated Ceph Quincy `main` component is an alias for no-subscription
+        let component_matches = component == handle_component
+            || (*handle == APTRepositoryHandle::CephNoSubscription(CephReleaseCodename::Quincy)
+                && component == "main");
+        component_matches && repo.is_referenced_repository(*handle, product, suite)
+    })
+}
+
+/// Classify the repository providing a package index.
+fn classify(repo: &APTRepository, source: &PackageSource, product: &str) -> APTUpdateOrigin {
+    if let Some((_handle, origin)) =
+        standard_handle(repo, &source.suite, &source.component, product)
+    {
+        return origin;
+    }
+
+    match repo.origin_from_uris().as_deref() {
+        Some("Debian") => APTUpdateOrigin::Debian,
+        _ => APTUpdateOrigin::Other,
+    }
+}
+
+#[cfg(feature = "changelog")]
+fn changelog_url(
+    origin: APTUpdateOrigin,
+    source: &PackageSource,
+    update: &SimulatedUpdate,
+    metadata: Option<&PackageMetadata>,
+) -> Option<String> {
+    use crate::changelog::ChangelogOrigin;
+
+    let changelog_origin = match origin {
+        APTUpdateOrigin::ProxmoxEnterprise
+        | APTUpdateOrigin::ProxmoxNoSubscription
+        | APTUpdateOrigin::ProxmoxTest => ChangelogOrigin::Proxmox {
+            repository: source.uri.clone(),
+            suite: source.suite.clone(),
+            component: source.component.clone(),
+            // packages for all architectures are published in the native package index
+            arch: source.arch.clone(),
+        },
+        APTUpdateOrigin::Debian => ChangelogOrigin::Debian {
+            component: source.component.clone(),
+            // binNMUs name the source version in parentheses, e.g. `systemd (252.26-1)`
+            source: metadata
+                .map(|m| m.source.as_str())
+                .filter(|s| !s.is_empty())
+                .map(|s| {
+                    s.split_once(' ')
+                        .map_or(s, |(name, _version)| name)
+                        .to_string()
+                }),
+        },
+        APTUpdateOrigin::Other => return None,
+    };
+
+    changelog_origin
+        .changelog_url(&update.package, &update.version)
+        .ok()
+}
+
+#[cfg(not(feature = "changelog"))]
+fn changelog_url(
+    _origin: APTUpdateOrigin,
+    _source: &PackageSource,
+    _update: &SimulatedUpdate,
+    _metadata: Option<&PackageMetadata>,
+) -> Option<String> {
+    None
+}
+
+/// Parse the available updates from the output of APT.
+///
+/// `simulation` is the output of `apt-get -s dist-upgrade`, `policy` and `packages` are the
+/// outputs of `apt-cache policy` and `apt-cache show --no-all-versions` for the packages to
+/// update. The origin of each update is classified by matching the repository of its new version
+/// against the configured repository `files` of `product`, packages from repositories which are
+/// not configured are classified as [`APTUpdateOrigin::Other`].
+///
+/// The changelog URL is only set with the `changelog` feature, and only for packages from
+/// Proxmox or Debian repositories.
+pub fn parse_apt_updates(
+    simulation: &str,
+    policy: &str,
+    packages: &str,
+    files: &[APTRepositoryFile],
+    product: &str,
+) -> Result<Vec<APTUpdateInfo>, Error> {
+    let updates = parse_simulation(simulation)?;
+    let sources = parse_policy(policy)?;
+    let metadata = parse_package_metadata(packages)?;
+
+    let mut result = Vec::with_capacity(updates.len());
+    for update in updates {
+        let metadata = metadata
+            .iter()
+            .find(|m| m.package == update.package && m.version == update.version)
+            .or_else(|| metadata.iter().find(|m| m.package == update.package));
+
+        let repository = sources.get(&update.package).and_then(|sources| {
+            sources
+                .iter()
+                .find_map(|source| Some((find_repository(files, source)?, source)))
+        });
+
+        let (origin, origin_type, change_log_url) = match repository {
+            Some((repo, source)) => {
+                let origin_type = classify(repo, source, product);
+                let change_log_url = changelog_url(origin_type, source, &update, metadata);
+                (repo.origin_from_uris(), origin_type, change_log_url)
+            }
+            None => (None, APTUpdateOrigin::Other, None),
+        };
+        // fall back to the label of the release, e.g. `Debian` for `Debian:12.7/stable`
+        let origin = origin.unwrap_or_else(|| match update.release.split_once(':') {
+            Some((label, _)) if !label.is_empty() => label.to_string(),
+            _ => "unknown".to_string(),
+        });
+
+        let description = metadata.map(|m| m.description.clone()).unwrap_or_default();
+        let title = description.lines().next().unwrap_or_default().to_string();
+
+        result.push(APTUpdateInfo {
+            package: update.package,
+            title,
+            arch: update.arch,
+            description,
+            version: update.version,
+            old_version: update.old_version.unwrap_or_default(),
+            origin,
+            priority: metadata_or_unknown(metadata.map(|m| &m.priority)),
+            section: metadata_or_unknown(metadata.map(|m| &m.section)),
+            extra_info: None,
+            origin_type: Some(origin_type),
+            change_log_url,
+        });
+    }
+
+    Ok(result)
+}
+
+fn metadata_or_unknown(value: Option<&String>) -> String {
+    match value {
+        Some(value) if !value.is_empty() => value.clone(),
+        _ => "unknown".to_string(),
+    }
+}
+
+fn run_apt(program: &str, args: &[&str]) -> Result<String, Error> {
+    let mut command = Command::new(program);
+    command.env("LC_ALL", "C").args(args);
+    proxmox_sys::command::run_command(command, None)
+}
+
+/// List the available updates by running APT, see [`parse_apt_updates`].
+///
+/// This does not update the package database, so the result reflects the last `apt-get update`.
+pub fn list_apt_updates(product: &str) -> Result<Vec<APTUpdateInfo>, Error> {
+    let simulation = run_apt("apt-get", &["-s", "-q", "dist-upgrade"])?;
+
+    // keep the architecture qualifiers of foreign packages
+    let mut packages: Vec<&str> = simulation
+        .lines()
+        .filter_map(|line| line.strip_prefix("Inst ")?.split(' ').next())
+        .collect();
+    packages.sort_unstable();
+    packages.dedup();
+    if packages.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let policy = run_apt("apt-cache", &[&["policy"], packages.as_slice()].concat())?;
+    let metadata = run_apt(
+        "apt-cache",
+        &[&["show", "--no-all-versions"], packages.as_slice()].concat(),
+    )?;
+    let (files, _errors, _digest) = crate::repositories::repositories()?;
+
+    parse_apt_updates(&simulation, &policy, &metadata, &files, product)
+}
+
+type UpdateLister = Box<dyn Fn() -> Result<Vec<APTUpdateInfo>, Error> + Send + Sync>;
+
+/// Caches the available updates, e.g. for API calls polled by user interfaces.
+///
+/// The updates are listed on first use and only listed again by [`refresh`](Self::refresh),
+/// which should be called after the package database or the repositories changed, e.g. after
+/// `apt-get update`.
+pub struct AptUpdateCache {
+    lister: UpdateLister,
+    updates: Mutex<Option<Arc<Vec<APTUpdateInfo>>>>,
+}
+
+impl AptUpdateCache {
+    /// Create a cache for the updates of `product`, listed with [`list_apt_updates`].
+    pub fn new(product: &str) -> Self {
+        let product = product.to_string();
+        Self::with_lister(move || list_apt_updates(&product))
+    }
+
+    /// Create a cache for the updates listed by `lister`.
+    pub fn with_lister<F>(lister: F) -> Self
+    where
+        F: Fn() -> Result<Vec<APTUpdateInfo>, Error> + Send + Sync + 'static,
+    {
+        Self {
+            lister: Box::new(lister),
+            updates: Mutex::new(None),
+        }
+    }
+
+    /// Get the cached updates, listing them if they were not listed yet.
+    pub fn get(&self) -> Result<Arc<Vec<APTUpdateInfo>>, Error> {
+        let mut updates = self.updates.lock().unwrap();
+        match updates.as_ref() {
+            Some(updates) => Ok(Arc::clone(updates)),
+            None => {
+                let listed = Arc::new((self.lister)()?);
+                *updates = Some(Arc::clone(&listed));
+                Ok(listed)
+            }
+        }
+    }
+
+    /// Get the cached updates without listing them.
+    pub fn cached(&self) -> Option<Arc<Vec<APTUpdateInfo>>> {
+        self.updates.lock().unwrap().clone()
+    }
+
+    /// List the updates again and replace the cached ones.
+    ///
+    /// On failure, the previously cached updates are kept.
+    pub fn refresh(&self) -> Result<Arc<Vec<APTUpdateInfo>>, Error> {
+        let listed = Arc::new((self.lister)()?);
+        *self.updates.lock().unwrap() = Some(Arc::clone(&listed));
+        Ok(listed)
+    }
+}
@@ -0,0 +1,114 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{bail, format_err, Error};
+
+use proxmox_apt::changelog::{ChangelogFetcher, ChangelogOrigin};
+
+fn create_clean_directory(path: &PathBuf) -> Result<(), Error> {
+    match std::fs::remove_dir_all(path) {
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => (),
+        Err(err) => bail!("unable to remove dir {path:?} - {err}"),
+        Ok(_) => (),
+    }
+    std::fs::create_dir_all(path)
+        .map_err(|err| format_err!("unable to create dir {path:?} - {err}"))
+}
+
+fn proxmox_origin(repository: &str, component: &str) -> ChangelogOrigin {
+    ChangelogOrigin::Proxmox {
+        repository: repository.to_string(),
+        suite: "bookworm".to_string(),
+        component: component.to_string(),
+        arch: "amd64".to_string(),
+    }
+}
+
+fn debian_origin(source: Option<&str>) -> ChangelogOrigin {
+    ChangelogOrigin::Debian {
+        component: "main".to_string(),
+        source: source.map(str::to_string),
+    }
+}
+
+#[test]
+fn test_changelog_url() -> Result<(), Error> {
+    let no_subscription = proxmox_origin(
+        "http://download.proxmox.com/debian/pve/",
+        "pve-no-subscription",
+    );
+    assert_eq!(
+        no_subscription.changelog_url("pve-manager", "8.2.4")?,
+        "http://download.proxmox.com/debian/pve/dists/bookworm/pve-no-subscription/binary-amd64/pve-manager_8.2.4.changelog",
+    );
+
+    let enterprise = proxmox_origin(
+        "https://enterprise.proxmox.com/debian/pbs",
+        "pbs-enterprise",
+    );
+    assert_eq!(
+        enterprise.changelog_url("proxmox-backup-server", "3.2.7-1")?,
+        "https://enterprise.proxmox.com/debian/pbs/dists/bookworm/pbs-enterprise/binary-amd64/proxmox-backup-server_3.2.7-1.changelog",
+    );
+
+    assert_eq!(
+        debian_origin(None).changelog_url("bash", "5.2.15-2+b7")?,
+        "https://metadata.ftp-master.debian.org/changelogs/main/b/bash/bash_5.2.15-2+b7_changelog",
+    );
+    assert_eq!(
+        debian_origin(Some("systemd")).changelog_url("libsystemd0", "252.26-1~deb12u2")?,
+        "https://metadata.ftp-master.debian.org/changelogs/main/s/systemd/systemd_252.26-1~deb12u2_changelog",
+    );
+    assert_eq!(
+        debian_origin(Some("libxml2")).changelog_url("libxml2", "2.9.14+dfsg-1.3~deb12u1")?,
+        "https://metadata.ftp-master.debian.org/changelogs/main/libx/libxml2/libxml2_2.9.14+dfsg-1.3~deb12u1_changelog",
+    );
+    // the epoch is not part of the published file names
+    assert_eq!(
+        debian_origin(Some("openssh")).changelog_url("openssh-server", "1:9.2p1-2+deb12u3")?,
+        "https://metadata.ftp-master.debian.org/changelogs/main/o/openssh/openssh_9.2p1-2+deb12u3_changelog",
+    );
+
+    assert!(no_subscription.changelog_url("../etc", "1.0").is_err());
+    assert!(no_subscription
+        .changelog_url("pve-manager", "8.2/../..")
+        .is_err());
+    assert!(debian_origin(Some("Bad"))
+        .changelog_url("bash", "1.0")
+        .is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_changelog_cache_hit() -> Result<(), Error> {
+    let test_dir = std::env::current_dir()?.join("tests").join("changelog");
+    let cache_dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("changelog-cache");
+    create_clean_directory(&cache_dir)?;
+
+    let file_name = "pve-manager_8.2.4.changelog";
+    let expected = std::fs::read_to_string(test_dir.join(file_name))?;
+    std::fs::write(cache_dir.join(file_name), &expected)?;
+
+    // nothing listens there, so only cached changelogs can be returned
+    let origin = proxmox_origin("http://127.0.0.1:1/debian/pve", "pve-no-subscription");
+    let fetcher = ChangelogFetcher::new(&cache_dir).timeout(Duration::from_secs(5));
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    runtime.block_on(async {
+        let changelog = fetcher
+            .get_changelog("pve-manager", "8.2.4", &origin)
+            .await?;
+        assert_eq!(changelog, expected);
+
+        assert!(fetcher
+            .get_changelog("pve-manager", "8.2.5", &origin)
+            .await
+            .is_err());
+        Ok::<_, Error>(())
+    })?;
+
+    Ok(())
+}
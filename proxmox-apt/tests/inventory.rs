@@ -0,0 +1,88 @@
+use std::path::PathBuf;
+
+use anyhow::Error;
+
+use proxmox_apt::inventory::package_inventory;
+use proxmox_apt::repositories::APTRepositoryFileImpl;
+use proxmox_apt_api_types::{
+    APTInstalledPackage, APTPackageOriginCount, APTRepositoryFile, APTRepositoryFileType,
+};
+
+fn fixture_dir() -> Result<PathBuf, Error> {
+    Ok(std::env::current_dir()?.join("tests/inventory"))
+}
+
+fn parse_sources() -> Result<Vec<APTRepositoryFile>, Error> {
+    let content = std::fs::read_to_string(fixture_dir()?.join("sources.list"))?;
+    let mut file = APTRepositoryFile::with_content(content, APTRepositoryFileType::List);
+    file.parse()?;
+    Ok(vec![file])
+}
+
+fn installed(package: &str, version: &str, arch: &str) -> APTInstalledPackage {
+    APTInstalledPackage {
+        package: package.to_string(),
+        version: version.to_string(),
+        arch: arch.to_string(),
+    }
+}
+
+#[test]
+fn test_package_inventory() -> Result<(), Error> {
+    let dir = fixture_dir()?;
+    let files = parse_sources()?;
+
+    let inventory = package_inventory(&dir.join("status"), &dir.join("lists"), &files, "pve")?;
+
+    // packages which are not installed any more are ignored
+    assert_eq!(inventory.total, 9);
+
+    let origins: Vec<(&str, usize)> = inventory
+        .origins
+        .iter()
+        .map(|APTPackageOriginCount { origin, count }| (origin.as_str(), *count))
+        .collect();
+    assert_eq!(
+        origins,
+        [
+            ("debian", 2),
+            ("local", 4),
+            ("no-subscription", 2),
+            ("other", 1),
+        ]
+    );
+
+    assert_eq!(
+        inventory.local_packages,
+        [
+            // the configured repository only provides an older version
+            installed("example-agent", "2.0-1", "amd64"),
+            // only the amd64 index is available
+            installed("libc6", "2.36-9+deb12u8", "i386"),
+            // the index belongs to a repository which is not configured
+            installed("my-custom-package", "0.1", "all"),
+            // the repository is disabled
+            installed("pve-test-package", "1.0-1", "all"),
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_package_inventory_errors() -> Result<(), Error> {
+    let dir = fixture_dir()?;
+    let files = parse_sources()?;
+
+    let err = package_inventory(&dir.join("missing"), &dir.join("lists"), &files, "pve")
+        .unwrap_err()
+        .to_string();
+    assert!(err.starts_with("unable to open"), "{err}");
+
+    let err = package_inventory(&dir.join("status"), &dir.join("missing"), &files, "pve")
+        .unwrap_err()
+        .to_string();
+    assert!(err.starts_with("unable to read"), "{err}");
+
+    Ok(())
+}
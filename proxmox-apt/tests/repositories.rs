@@ -3,13 +3,15 @@ use std::path::PathBuf;
 use anyhow::{bail, format_err, Error};
 
 use proxmox_apt::repositories::{
-    check_repositories, get_current_release_codename, standard_repositories, DebianCodename,
+    add_standard_repository_in, check_repositories, get_current_release_codename, repositories_in,
+    standard_repositories, DebianCodename,
 };
 use proxmox_apt::repositories::{
     APTRepositoryFileImpl, APTRepositoryImpl, APTStandardRepositoryImpl,
 };
 use proxmox_apt_api_types::{
-    APTRepositoryFile, APTRepositoryHandle, APTRepositoryInfo, APTStandardRepository,
+    APTAddRepositoryOptions, APTRepositoryChangeKind, APTRepositoryEntryError, APTRepositoryFile,
+    APTRepositoryHandle, APTRepositoryInfo, APTStandardRepository, CephReleaseCodename,
 };
 
 fn create_clean_directory(path: &PathBuf) -> Result<(), Error> {
@@ -144,6 +146,217 @@ fn test_digest() -> Result<(), Error> {
     Ok(())
 }
 
+#[test]
+fn test_parse_entry_errors() -> Result<(), Error> {
+    let read_dir = std::env::current_dir()?
+        .join("tests")
+        .join("sources.list.d");
+
+    let path = read_dir.join("malformed.list");
+    let mut file = APTRepositoryFile::new(&path)?.unwrap();
+    file.parse()?;
+
+    let suites: Vec<&str> = file
+        .repositories
+        .iter()
+        .map(|repo| repo.suites[0].as_str())
+        .collect();
+    assert_eq!(suites, ["bookworm", "bookworm-security"]);
+    assert_eq!(file.repositories[1].comment, " security updates\n");
+
+    let path_string = path.into_os_string().into_string().unwrap();
+    let entry_error = |line, index, text: &str, error: &str| APTRepositoryEntryError {
+        path: path_string.clone(),
+        line,
+        index,
+        text: text.to_string(),
+        error: error.to_string(),
+    };
+    assert_eq!(
+        file.entry_errors,
+        [
+            entry_error(
+                2,
+                1,
+                "deb [arch=amd64 http://ftp.debian.org/debian bookworm-updates main contrib",
+                "got invalid option - 'http://ftp.debian.org/debian'",
+            ),
+            entry_error(
+                3,
+                1,
+                "deb ftp.debian.org/debian bookworm-backports main # bad URI",
+                "invalid URI: 'ftp.debian.org/debian'",
+            ),
+            entry_error(
+                7,
+                2,
+                "deb http://download.proxmox.com/debian/pve",
+                "missing suite",
+            ),
+        ]
+    );
+
+    let path = read_dir.join("malformed.sources");
+    let mut file = APTRepositoryFile::new(&path)?.unwrap();
+    file.parse()?;
+
+    let suites: Vec<&str> = file
+        .repositories
+        .iter()
+        .map(|repo| repo.suites[0].as_str())
+        .collect();
+    assert_eq!(suites, ["bookworm", "bookworm-security"]);
+
+    let path_string = path.into_os_string().into_string().unwrap();
+    let entry_error = |line, index, text: &str, error: &str| APTRepositoryEntryError {
+        path: path_string.clone(),
+        line,
+        index,
+        text: text.to_string(),
+        error: error.to_string(),
+    };
+    assert_eq!(
+        file.entry_errors,
+        [
+            entry_error(
+                6,
+                1,
+                "# missing suite\nTypes: deb\nURIs: http://ftp.debian.org/debian\nComponents: main contrib",
+                "missing suite(s)",
+            ),
+            entry_error(
+                11,
+                1,
+                "Types: deb\nURIs: ftp.debian.org/debian\nSuites: bookworm-updates\nComponents: main contrib",
+                "invalid URI: 'ftp.debian.org/debian'",
+            ),
+        ]
+    );
+    // the comment of the malformed stanza is not attached to the next repository
+    assert!(file.repositories[1].comment.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_repository_comments() -> Result<(), Error> {
+    let test_dir = std::env::current_dir()?.join("tests");
+    let tmp_dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR").to_string());
+    let read_dir = test_dir.join("sources.list.d");
+    let write_dir = tmp_dir.join("sources.list.d.comments");
+
+    create_clean_directory(&write_dir)?;
+
+    let path = read_dir.join("comments.list");
+    let mut file = APTRepositoryFile::new(&path)?.unwrap();
+    file.parse()?;
+
+    // all comments since the previous entry belong to the next one, even across blank lines,
+    // including a comment following the entry on the same line
+    let comments: Vec<&str> = file
+        .repositories
+        .iter()
+        .map(|repo| repo.comment.as_str())
+        .collect();
+    assert_eq!(
+        comments,
+        [
+            " main repository\n the block above is separated by a blank line, but still belongs to the next entry\n same line\n",
+            "",
+            " belongs to the disabled entry\n backports\n",
+            " see https://www.debian.org/security/#keeping-secure\n",
+        ]
+    );
+    assert!(!file.repositories[2].enabled);
+
+    let new_path = write_dir.join(path.file_name().unwrap());
+    file.path = Some(new_path.clone().into_os_string().into_string().unwrap());
+    file.digest = None;
+
+    // toggling the enabled state keeps the comments
+    file.repositories[0].set_enabled(false);
+    file.repositories[2].set_enabled(true);
+    // a comment line which is a repository entry would be read back as disabled repository
+    for comment in [
+        "deb http://deb.debian.org/debian bookworm main",
+        "old entry:\n# deb [arch=amd64] http://deb.debian.org/debian bookworm main",
+    ] {
+        assert!(
+            file.repositories[1].set_comment(comment).is_err(),
+            "{comment}"
+        );
+    }
+    assert_eq!(file.repositories[1].comment, "");
+
+    // comments are replaced, added and removed
+    file.repositories[0].set_comment("first line\n  indented\n\nlast line")?;
+    file.repositories[1].set_comment("updates")?;
+    file.repositories[3].set_comment("")?;
+    file.write()?;
+
+    assert_eq!(
+        std::fs::read_to_string(&new_path)?,
+        "# first line\n\
+         #  indented\n\
+         #\n\
+         # last line\n\
+         # deb http://deb.debian.org/debian bookworm main\n\
+         \n\
+         # updates\n\
+         deb http://deb.debian.org/debian bookworm-updates main\n\
+         \n\
+         # belongs to the disabled entry\n\
+         # backports\n\
+         deb http://deb.debian.org/debian bookworm-backports main\n\
+         \n\
+         deb http://security.debian.org/debian-security bookworm-security main\n\
+         \n",
+    );
+
+    // setting the parsed comment again does not change it
+    let mut file = APTRepositoryFile::new(&new_path)?.unwrap();
+    file.parse()?;
+    let comment = file.repositories[0].comment.clone();
+    file.repositories[0].set_comment(&comment)?;
+    assert_eq!(file.repositories[0].comment, comment);
+
+    let path = read_dir.join("comments.sources");
+    let mut file = APTRepositoryFile::new(&path)?.unwrap();
+    file.parse()?;
+
+    let comments: Vec<&str> = file
+        .repositories
+        .iter()
+        .map(|repo| repo.comment.as_str())
+        .collect();
+    assert_eq!(
+        comments,
+        [
+            " main repository\n the block above is separated by a blank line, but still belongs to the next stanza\n",
+            "",
+            " disabled stanza\n",
+        ]
+    );
+
+    let new_path = write_dir.join(path.file_name().unwrap());
+    file.path = Some(new_path.clone().into_os_string().into_string().unwrap());
+    file.digest = None;
+
+    // there are no commented out stanzas in this format
+    file.repositories[1].set_comment("deb http://deb.debian.org/debian bookworm main")?;
+    file.repositories[1].set_comment("updates")?;
+    file.repositories[2].set_enabled(true);
+    file.write()?;
+
+    let mut file = APTRepositoryFile::new(&new_path)?.unwrap();
+    file.parse()?;
+    assert_eq!(file.repositories[1].comment, " updates\n");
+    assert_eq!(file.repositories[2].comment, " disabled stanza\n");
+    assert!(file.repositories[2].enabled);
+
+    Ok(())
+}
+
 #[test]
 fn test_empty_write() -> Result<(), Error> {
     let write_dir = PathBuf::from(
@@ -356,12 +569,24 @@ fn test_standard_repositories() -> Result<(), Error> {
         APTStandardRepository::from_handle(APTRepositoryHandle::Enterprise),
         APTStandardRepository::from_handle(APTRepositoryHandle::NoSubscription),
         APTStandardRepository::from_handle(APTRepositoryHandle::Test),
-        APTStandardRepository::from_handle(APTRepositoryHandle::CephQuincyEnterprise),
-        APTStandardRepository::from_handle(APTRepositoryHandle::CephQuincyNoSubscription),
-        APTStandardRepository::from_handle(APTRepositoryHandle::CephQuincyTest),
-        APTStandardRepository::from_handle(APTRepositoryHandle::CephReefEnterprise),
-        APTStandardRepository::from_handle(APTRepositoryHandle::CephReefNoSubscription),
-        APTStandardRepository::from_handle(APTRepositoryHandle::CephReefTest),
+        APTStandardRepository::from_handle(APTRepositoryHandle::CephEnterprise(
+            CephReleaseCodename::Quincy,
+        )),
+        APTStandardRepository::from_handle(APTRepositoryHandle::CephNoSubscription(
+            CephReleaseCodename::Quincy,
+        )),
+        APTStandardRepository::from_handle(APTRepositoryHandle::CephTest(
+            CephReleaseCodename::Quincy,
+        )),
+        APTStandardRepository::from_handle(APTRepositoryHandle::CephEnterprise(
+            CephReleaseCodename::Reef,
+        )),
+        APTStandardRepository::from_handle(APTRepositoryHandle::CephNoSubscription(
+            CephReleaseCodename::Reef,
+        )),
+        APTStandardRepository::from_handle(APTRepositoryHandle::CephTest(
+            CephReleaseCodename::Reef,
+        )),
     ];
 
     let absolute_suite_list = read_dir.join("absolute_suite.list");
@@ -460,6 +685,34 @@ fn test_standard_repositories() -> Result<(), Error> {
     Ok(())
 }
 
+#[test]
+fn test_repository_handle_strings() -> Result<(), Error> {
+    for (value, handle) in [
+        ("test", APTRepositoryHandle::Test),
+        (
+            "ceph-quincy-enterprise",
+            APTRepositoryHandle::CephEnterprise(CephReleaseCodename::Quincy),
+        ),
+        (
+            "ceph-quincy-no-subscription",
+            APTRepositoryHandle::CephNoSubscription(CephReleaseCodename::Quincy),
+        ),
+        (
+            "ceph-reef-test",
+            APTRepositoryHandle::CephTest(CephReleaseCodename::Reef),
+        ),
+    ] {
+        assert_eq!(value.parse::<APTRepositoryHandle>()?, handle);
+        assert_eq!(handle.to_string(), value);
+    }
+
+    for value in ["ceph-test", "ceph-squid-test", "ceph-reef-main"] {
+        assert!(value.parse::<APTRepositoryHandle>().is_err());
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_get_current_release_codename() -> Result<(), Error> {
     let codename = get_current_release_codename()?;
@@ -468,3 +721,111 @@ fn test_get_current_release_codename() -> Result<(), Error> {
 
     Ok(())
 }
+
+#[test]
+fn test_add_standard_repository() -> Result<(), Error> {
+    let test_dir = std::env::current_dir()?.join("tests");
+    let read_dir = test_dir.join("sources.list.d");
+    let tmp_dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR").to_string()).join("add-repository");
+    let sources_list = tmp_dir.join("sources.list");
+    let sources_list_d = tmp_dir.join("sources.list.d");
+
+    create_clean_directory(&sources_list_d)?;
+    std::fs::copy(read_dir.join("standard.list"), &sources_list)?;
+    std::fs::copy(read_dir.join("pve.list"), sources_list_d.join("pve.list"))?;
+
+    let pve_list = sources_list_d.join("pve.list");
+    let original = std::fs::read_to_string(&pve_list)?;
+
+    let add = |handle, dry_run| {
+        let options = APTAddRepositoryOptions {
+            digest: None,
+            dry_run: Some(dry_run),
+        };
+        add_standard_repository_in(
+            &sources_list,
+            &sources_list_d,
+            handle,
+            "pve",
+            DebianCodename::Bullseye,
+            &options,
+        )
+    };
+
+    // a dry-run re-enabling the commented out enterprise repository does not write anything
+    let preview = add(APTRepositoryHandle::Enterprise, true)?;
+    assert_eq!(preview.kind, APTRepositoryChangeKind::Enabled);
+    assert_eq!(preview.path, pve_list.display().to_string());
+    assert_eq!(preview.index, 3);
+    assert!(preview
+        .content
+        .contains("\ndeb https://enterprise.proxmox.com/debian/pve bullseye pve-enterprise\n"));
+    assert_eq!(std::fs::read_to_string(&pve_list)?, original);
+
+    let change = add(APTRepositoryHandle::Enterprise, false)?;
+    assert_eq!(change, preview);
+    assert_eq!(std::fs::read_to_string(&pve_list)?, change.content);
+    // the other entries and comments are preserved
+    for line in original
+        .lines()
+        .filter(|line| !line.contains("pve-enterprise"))
+    {
+        assert!(change.content.contains(line), "missing line '{line}'");
+    }
+
+    let change = add(APTRepositoryHandle::Enterprise, false)?;
+    assert_eq!(change.kind, APTRepositoryChangeKind::Unchanged);
+    assert_eq!(change.index, 3);
+
+    // the test repository is appended to sources.list
+    let original = std::fs::read_to_string(&sources_list)?;
+    let change = add(APTRepositoryHandle::Test, false)?;
+    assert_eq!(change.kind, APTRepositoryChangeKind::Appended);
+    assert_eq!(change.path, sources_list.display().to_string());
+    assert_eq!(change.index, 3);
+    assert!(change.content.starts_with(original.trim_end()));
+    assert!(change
+        .content
+        .trim_end()
+        .ends_with("\ndeb http://download.proxmox.com/debian/pve bullseye pvetest"));
+    assert_eq!(std::fs::read_to_string(&sources_list)?, change.content);
+
+    // Ceph repositories get their own file
+    let ceph_list = sources_list_d.join("ceph.list");
+    let preview = add(
+        APTRepositoryHandle::CephEnterprise(CephReleaseCodename::Quincy),
+        true,
+    )?;
+    assert_eq!(preview.kind, APTRepositoryChangeKind::Created);
+    assert_eq!(preview.path, ceph_list.display().to_string());
+    assert!(!ceph_list.exists());
+    let change = add(
+        APTRepositoryHandle::CephEnterprise(CephReleaseCodename::Quincy),
+        false,
+    )?;
+    assert_eq!(change, preview);
+    assert_eq!(std::fs::read_to_string(&ceph_list)?, change.content);
+    assert_eq!(
+        change.content.trim_end(),
+        "deb https://enterprise.proxmox.com/debian/ceph-quincy bullseye enterprise",
+    );
+
+    // the digest guards against concurrent modifications
+    let (_, _, digest) = repositories_in(&sources_list, &sources_list_d)?;
+    std::fs::write(&ceph_list, "")?;
+    let options = APTAddRepositoryOptions {
+        digest: Some(digest),
+        dry_run: None,
+    };
+    let result = add_standard_repository_in(
+        &sources_list,
+        &sources_list_d,
+        APTRepositoryHandle::CephEnterprise(CephReleaseCodename::Quincy),
+        "pve",
+        DebianCodename::Bullseye,
+        &options,
+    );
+    assert!(result.is_err());
+
+    Ok(())
+}
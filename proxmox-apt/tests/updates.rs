@@ -0,0 +1,209 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use anyhow::{bail, Error};
+
+use proxmox_apt::repositories::APTRepositoryFileImpl;
+use proxmox_apt::updates::{parse_apt_updates, AptUpdateCache};
+use proxmox_apt_api_types::{
+    APTRepositoryFile, APTRepositoryFileType, APTUpdateInfo, APTUpdateOrigin,
+};
+
+fn read_fixture(name: &str) -> Result<String, Error> {
+    let path = std::env::current_dir()?.join("tests/updates").join(name);
+    Ok(std::fs::read_to_string(path)?)
+}
+
+fn parse_sources(content: String) -> Result<Vec<APTRepositoryFile>, Error> {
+    let mut file = APTRepositoryFile::with_content(content, APTRepositoryFileType::List);
+    file.parse()?;
+    Ok(vec![file])
+}
+
+fn parse_fixture_updates(files: &[APTRepositoryFile]) -> Result<Vec<APTUpdateInfo>, Error> {
+    parse_apt_updates(
+        &read_fixture("simulate")?,
+        &read_fixture("policy")?,
+        &read_fixture("packages")?,
+        files,
+        "pve",
+    )
+}
+
+fn find<'a>(
+    updates: &'a [APTUpdateInfo],
+    package: &str,
+    arch: &str,
+) -> Result<&'a APTUpdateInfo, Error> {
+    match updates
+        .iter()
+        .find(|u| u.package == package && u.arch == arch)
+    {
+        Some(update) => Ok(update),
+        None => bail!("no update for {package} ({arch})"),
+    }
+}
+
+#[test]
+fn test_parse_updates() -> Result<(), Error> {
+    let files = parse_sources(read_fixture("sources.list")?)?;
+    let updates = parse_fixture_updates(&files)?;
+
+    let packages: Vec<(&str, &str)> = updates
+        .iter()
+        .map(|u| (u.package.as_str(), u.arch.as_str()))
+        .collect();
+    assert_eq!(
+        packages,
+        [
+            ("bash", "amd64"),
+            ("libsystemd0", "amd64"),
+            ("libsystemd0", "i386"),
+            ("proxmox-kernel-6.8.12-2-pve-signed", "amd64"),
+            ("proxmox-kernel-6.8", "all"),
+            ("pve-manager", "amd64"),
+            ("qemu-server", "amd64"),
+            ("ceph-common", "amd64"),
+            ("example-tool", "amd64"),
+        ]
+    );
+
+    let origins: Vec<Option<APTUpdateOrigin>> = updates.iter().map(|u| u.origin_type).collect();
+    assert_eq!(
+        origins,
+        [
+            Some(APTUpdateOrigin::Debian),
+            Some(APTUpdateOrigin::Debian),
+            Some(APTUpdateOrigin::Debian),
+            Some(APTUpdateOrigin::ProxmoxEnterprise),
+            // the first configured repository providing the candidate version
+            Some(APTUpdateOrigin::ProxmoxNoSubscription),
+            Some(APTUpdateOrigin::ProxmoxEnterprise),
+            Some(APTUpdateOrigin::ProxmoxNoSubscription),
+            Some(APTUpdateOrigin::ProxmoxTest),
+            Some(APTUpdateOrigin::Other),
+        ]
+    );
+
+    let bash = find(&updates, "bash", "amd64")?;
+    assert_eq!(bash.title, "GNU Bourne Again SHell");
+    assert!(bash.description.starts_with("GNU Bourne Again SHell\n"));
+    assert_eq!(bash.old_version, "5.2.15-2+b2");
+    assert_eq!(bash.version, "5.2.15-2+b7");
+    assert_eq!(bash.origin, "Debian");
+    assert_eq!(bash.priority, "required");
+    assert_eq!(bash.section, "shells");
+
+    let kernel = find(&updates, "proxmox-kernel-6.8.12-2-pve-signed", "amd64")?;
+    assert_eq!(kernel.old_version, "");
+    assert_eq!(kernel.version, "6.8.12-2");
+    assert_eq!(kernel.origin, "Proxmox");
+
+    let other = find(&updates, "example-tool", "amd64")?;
+    assert_eq!(other.title, "an example tool");
+    assert_eq!(other.origin, "Example");
+    assert_eq!(other.priority, "unknown");
+    assert_eq!(other.section, "unknown");
+    assert_eq!(other.change_log_url, None);
+
+    Ok(())
+}
+
+#[test]
+fn test_unconfigured_repository() -> Result<(), Error> {
+    // the enterprise repository providing the new pve-manager version is disabled
+    let sources = read_fixture("sources.list")?.replace(
+        "deb https://enterprise.proxmox.com",
+        "# deb https://enterprise.proxmox.com",
+    );
+    let files = parse_sources(sources)?;
+    let updates = parse_fixture_updates(&files)?;
+
+    let manager = find(&updates, "pve-manager", "amd64")?;
+    assert_eq!(manager.origin_type, Some(APTUpdateOrigin::Other));
+    assert_eq!(manager.origin, "Proxmox");
+    assert_eq!(manager.change_log_url, None);
+
+    // still provided by a configured repository
+    let kernel = find(&updates, "proxmox-kernel-6.8", "all")?;
+    assert_eq!(
+        kernel.origin_type,
+        Some(APTUpdateOrigin::ProxmoxNoSubscription)
+    );
+
+    Ok(())
+}
+
+#[cfg(feature = "changelog")]
+#[test]
+fn test_changelog_urls() -> Result<(), Error> {
+    let files = parse_sources(read_fixture("sources.list")?)?;
+    let updates = parse_fixture_updates(&files)?;
+
+    let urls = [
+        (
+            "bash",
+            "amd64",
+            "https://metadata.ftp-master.debian.org/changelogs/main/b/bash/bash_5.2.15-2+b7_changelog",
+        ),
+        (
+            "libsystemd0",
+            "i386",
+            "https://metadata.ftp-master.debian.org/changelogs/main/s/systemd/systemd_252.30-1~deb12u2_changelog",
+        ),
+        (
+            "pve-manager",
+            "amd64",
+            "https://enterprise.proxmox.com/debian/pve/dists/bookworm/pve-enterprise/binary-amd64/pve-manager_8.2.7.changelog",
+        ),
+        (
+            "proxmox-kernel-6.8",
+            "all",
+            "http://download.proxmox.com/debian/pve/dists/bookworm/pve-no-subscription/binary-amd64/proxmox-kernel-6.8_6.8.12-2.changelog",
+        ),
+        (
+            "ceph-common",
+            "amd64",
+            "http://download.proxmox.com/debian/ceph-reef/dists/bookworm/test/binary-amd64/ceph-common_18.2.4-pve3.changelog",
+        ),
+    ];
+    for (package, arch, url) in urls {
+        let update = find(&updates, package, arch)?;
+        assert_eq!(update.change_log_url.as_deref(), Some(url), "{package}");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_update_cache() -> Result<(), Error> {
+    let listed = Arc::new(AtomicUsize::new(0));
+    let cache = {
+        let listed = Arc::clone(&listed);
+        AptUpdateCache::with_lister(move || {
+            let count = listed.fetch_add(1, Ordering::SeqCst);
+            if count == 2 {
+                bail!("apt failed");
+            }
+            let files = parse_sources(read_fixture("sources.list")?)?;
+            let mut updates = parse_fixture_updates(&files)?;
+            updates.truncate(count + 1);
+            Ok(updates)
+        })
+    };
+
+    assert!(cache.cached().is_none());
+    assert_eq!(cache.get()?.len(), 1);
+    assert_eq!(cache.get()?.len(), 1);
+    assert_eq!(listed.load(Ordering::SeqCst), 1);
+
+    assert_eq!(cache.refresh()?.len(), 2);
+    assert_eq!(cache.get()?.len(), 2);
+
+    // failed refreshes keep the cached updates
+    assert!(cache.refresh().is_err());
+    assert_eq!(cache.cached().map(|u| u.len()), Some(2));
+    assert_eq!(listed.load(Ordering::SeqCst), 3);
+
+    Ok(())
+}
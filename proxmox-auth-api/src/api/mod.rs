@@ -6,7 +6,7 @@ use std::sync::Mutex;
 use anyhow::{format_err, Error};
 use percent_encoding::percent_decode_str;
 
-use proxmox_rest_server::{extract_cookie, AuthError};
+use proxmox_rest_server::{AuthError, CookiePolicy};
 use proxmox_tfa::api::{OpenUserChallengeData, TfaConfig};
 
 use crate::auth_key::{HMACKey, Keyring};
@@ -60,6 +60,16 @@ pub trait AuthContext: Send + Sync {
     /// Auth cookie name.
     fn auth_cookie_name(&self) -> &'static str;
 
+    /// The policy of the auth cookie, used to read the ticket from the request.
+    ///
+    /// Defaults to a policy for [`auth_cookie_name`](Self::auth_cookie_name). Return the policy
+    /// given to [`ApiConfig::auth_cookie_policy`] to also accept its legacy names.
+    ///
+    /// [`ApiConfig::auth_cookie_policy`]: proxmox_rest_server::ApiConfig::auth_cookie_policy
+    fn auth_cookie_policy(&self) -> CookiePolicy {
+        CookiePolicy::new(self.auth_cookie_name())
+    }
+
     /// Access the TFA config with an exclusive lock.
     fn tfa_config_write_lock(&self) -> Result<Box<dyn LockedTfaConfig>, Error>;
 
@@ -193,16 +203,12 @@ fn extract_auth_data(
     auth_context: &dyn AuthContext,
     headers: &http::HeaderMap,
 ) -> Option<AuthData> {
-    if let Some(raw_cookie) = headers.get(http::header::COOKIE) {
-        if let Ok(cookie) = raw_cookie.to_str() {
-            if let Some(ticket) = extract_cookie(cookie, auth_context.auth_cookie_name()) {
-                let csrf_token = match headers.get("CSRFPreventionToken").map(|v| v.to_str()) {
-                    Some(Ok(v)) => Some(v.to_owned()),
-                    _ => None,
-                };
-                return Some(AuthData::User(UserAuthData { ticket, csrf_token }));
-            }
-        }
+    if let Some(ticket) = auth_context.auth_cookie_policy().extract(headers) {
+        let csrf_token = match headers.get("CSRFPreventionToken").map(|v| v.to_str()) {
+            Some(Ok(v)) => Some(v.to_owned()),
+            _ => None,
+        };
+        return Some(AuthData::User(UserAuthData { ticket, csrf_token }));
     }
 
     let token_prefix = auth_context.auth_token_prefix();
@@ -1,9 +1,11 @@
 #![cfg_attr(docsrs, feature(doc_cfg, doc_auto_cfg))]
 
-use anyhow::{bail, Error};
+use anyhow::Error;
 
 #[cfg(feature = "openssl")]
 use openssl::sha;
+#[cfg(feature = "openssl")]
+use serde_json::Value;
 
 use proxmox_schema::api_types::SHA256_HEX_REGEX;
 use proxmox_schema::ApiStringFormat;
@@ -39,17 +41,31 @@ impl ConfigDigest {
 
     /// Detect modified configuration files
     ///
-    /// This function fails with a reasonable error message if checksums do not match.
+    /// This function fails with a [`DigestMismatchError`] if checksums do not match.
     pub fn detect_modification(&self, user_digest: Option<&Self>) -> Result<(), Error> {
         if let Some(user_digest) = user_digest {
             if user_digest != self {
-                bail!("detected modified configuration - file changed by other user? Try again.");
+                return Err(DigestMismatchError.into());
             }
         }
         Ok(())
     }
 }
 
+/// Error returned by [`ConfigDigest::detect_modification`] if the digests do not match.
+///
+/// The REST server maps this to a `412 Precondition Failed` response.
+#[derive(Debug)]
+pub struct DigestMismatchError;
+
+impl std::fmt::Display for DigestMismatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("detected modified configuration - file changed by other user? Try again.")
+    }
+}
+
+impl std::error::Error for DigestMismatchError {}
+
 impl ApiType for ConfigDigest {
     const API_SCHEMA: Schema = PROXMOX_CONFIG_DIGEST_SCHEMA;
 }
@@ -110,5 +126,16 @@ impl std::str::FromStr for ConfigDigest {
     }
 }
 
+/// Compute the digest of a schema-validated value.
+///
+/// The value is hashed in its canonical form (see
+/// [`to_canonical_bytes`](proxmox_schema::ser::to_canonical_bytes)), so key order, number
+/// formatting and explicitly set default values do not influence the result.
+#[cfg(feature = "openssl")]
+pub fn digest_object(value: &Value, schema: &Schema) -> Result<ConfigDigest, Error> {
+    let data = proxmox_schema::ser::to_canonical_bytes(value, schema)?;
+    Ok(ConfigDigest::from_slice(data))
+}
+
 serde_plain::derive_deserialize_from_fromstr!(ConfigDigest, "valid configuration digest");
 serde_plain::derive_serialize_from_display!(ConfigDigest);
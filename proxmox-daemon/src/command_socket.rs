@@ -1,18 +1,30 @@
 use anyhow::{bail, format_err, Error};
 
 use std::collections::HashMap;
+use std::fmt;
 use std::future::Future;
+use std::io;
 use std::os::unix::io::AsRawFd;
 use std::path::{Path, PathBuf};
 use std::pin::pin;
 use std::sync::Arc;
+use std::time::Duration;
 
 use nix::sys::socket;
 use nix::unistd::Gid;
+use serde::de::DeserializeOwned;
 use serde::Serialize;
-use serde_json::Value;
-use tokio::net::UnixListener;
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+use tokio::net::{UnixListener, UnixStream};
 use tokio::sync::watch;
+use tokio::time::Instant;
+
+/// The response to peers without the required credentials.
+const PERMISSION_DENIED: &str = "permission denied";
+
+/// The prefix of the response to unknown commands.
+const UNKNOWN_COMMAND: &str = "got unknown command ";
 
 /// Returns the control socket path for a specific process ID.
 ///
@@ -30,6 +42,26 @@ pub fn this_path() -> String {
     path_from_pid(unsafe { libc::getpid() })
 }
 
+/// Returns the control socket path for the process whose ID is stored in `pid_file`.
+///
+/// Fails if the process is not running anymore, e.g. if the pid file is left over from a
+/// crashed daemon.
+pub fn path_from_pid_file<P: AsRef<Path>>(pid_file: P) -> Result<String, Error> {
+    let pid_file = pid_file.as_ref();
+    // a PID file only contains a number and a newline
+    let pid = proxmox_sys::fs::file_get_contents_limited(pid_file, 64)?;
+    let pid: libc::pid_t = std::str::from_utf8(&pid)?
+        .trim()
+        .parse()
+        .map_err(|err| format_err!("could not parse pid from {pid_file:?} - {err}"))?;
+
+    if proxmox_sys::linux::procfs::check_process_running(pid).is_none() {
+        bail!("process {pid} from {pid_file:?} is not running");
+    }
+
+    Ok(path_from_pid(pid))
+}
+
 // Listens on a Unix Socket to handle simple command asynchronously
 fn create_control_socket<P, F, W>(
     path: P,
@@ -66,8 +98,6 @@ where
 
     let control_future = Box::pin(async move {
         loop {
-            use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
-
             let (conn, _addr) = match socket.accept().await {
                 Ok(data) => data,
                 Err(err) => {
@@ -89,6 +119,8 @@ where
             let mygid = Gid::current();
             if !(cred.uid() == 0 || cred.gid() == mygid.as_raw() || cred.gid() == gid) {
                 log::error!("no permissions for {:?}", cred);
+                // best effort, the short response fits into the empty socket buffer
+                let _ = conn.try_write(format!("ERROR: {PERMISSION_DENIED}\n").as_bytes());
                 continue;
             }
 
@@ -165,8 +197,6 @@ pub async fn send_raw<P>(path: P, command_string: &str) -> Result<Value, Error>
 where
     P: AsRef<Path>,
 {
-    use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
-
     let mut conn = tokio::net::UnixStream::connect(path)
         .await
         .map_err(move |err| format_err!("control socket connect failed - {}", err))?;
@@ -212,8 +242,14 @@ pub struct CommandSocket {
 impl CommandSocket {
     /// Creates a new instance.
     pub fn new(gid: Gid) -> Self {
+        Self::with_path(this_path(), gid)
+    }
+
+    /// Creates a new instance listening on `path` instead of the path for this process (see
+    /// [this_path]).
+    pub fn with_path<P: Into<PathBuf>>(path: P, gid: Gid) -> Self {
         CommandSocket {
-            socket: this_path().into(),
+            socket: path.into(),
             gid,
             commands: HashMap::new(),
         }
@@ -243,12 +279,8 @@ impl CommandSocket {
                     _ => bail!("unable to parse command"),
                 };
 
-                if !self.commands.contains_key(command) {
-                    bail!("got unknown command '{}'", command);
-                }
-
                 match self.commands.get(command) {
-                    None => bail!("got unknown command '{}'", command),
+                    None => bail!("{UNKNOWN_COMMAND}'{command}'"),
                     Some(handler) => {
                         let args = param.get("args"); //.unwrap_or(&Value::Null);
                         (handler)(args)
@@ -276,3 +308,250 @@ impl CommandSocket {
         Ok(())
     }
 }
+
+/// Errors of [`send_command_typed`] and [`CommandClient::send`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum CommandError {
+    /// The daemon does not know the command.
+    NoSuchCommand(String),
+
+    /// The daemon rejected the credentials of this process.
+    PermissionDenied,
+
+    /// The handler of the command failed with the contained message.
+    Handler(String),
+
+    /// The daemon did not respond within the timeout.
+    Timeout(Duration),
+
+    /// Connecting to or talking to the control socket failed.
+    Io(io::Error),
+
+    /// The daemon responded with something unexpected, or with a result of the wrong type.
+    BadResponse(String),
+}
+
+impl std::error::Error for CommandError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NoSuchCommand(command) => write!(f, "no such command '{command}'"),
+            Self::PermissionDenied => f.write_str("permission denied on control socket"),
+            Self::Handler(msg) => write!(f, "command failed - {msg}"),
+            Self::Timeout(timeout) => write!(f, "no response within {timeout:?}"),
+            Self::Io(err) => write!(f, "control socket error - {err}"),
+            Self::BadResponse(msg) => write!(f, "unexpected response - {msg}"),
+        }
+    }
+}
+
+impl From<io::Error> for CommandError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+/// Parse a response line of the control socket.
+fn parse_response(line: &str) -> Result<Value, CommandError> {
+    let line = line.trim_end_matches('\n');
+    if let Some(res) = line.strip_prefix("OK: ") {
+        res.parse::<Value>()
+            .map_err(|err| CommandError::BadResponse(format!("invalid json - {err}")))
+    } else if let Some(err) = line.strip_prefix("ERROR: ") {
+        Err(if err == PERMISSION_DENIED {
+            CommandError::PermissionDenied
+        } else if let Some(command) = err.strip_prefix(UNKNOWN_COMMAND) {
+            CommandError::NoSuchCommand(command.trim_matches('\'').to_string())
+        } else {
+            CommandError::Handler(err.to_string())
+        })
+    } else {
+        Err(CommandError::BadResponse(format!("{line:?}")))
+    }
+}
+
+/// A client for the control socket of a daemon, with timeouts and typed responses.
+///
+/// ```no_run
+/// # async fn example() -> Result<(), anyhow::Error> {
+/// use std::time::Duration;
+///
+/// use proxmox_daemon::command_socket::{path_from_pid_file, CommandClient};
+///
+/// let path = path_from_pid_file("/run/proxmox-backup/api.pid")?;
+/// let status: serde_json::Value = CommandClient::new(path)
+///     .timeout(Duration::from_secs(5))
+///     .retry_refused(Duration::from_secs(2))
+///     .send("api-access-log-reopen", None)
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct CommandClient {
+    path: PathBuf,
+    connect_timeout: Duration,
+    timeout: Duration,
+    retry_refused: Option<Duration>,
+}
+
+impl CommandClient {
+    /// Create a client for the control socket at `path`, see [`path_from_pid`].
+    ///
+    /// By default, connecting times out after 5 seconds and the whole command after 30 seconds.
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self {
+            path: path.into(),
+            connect_timeout: Duration::from_secs(5),
+            timeout: Duration::from_secs(30),
+            retry_refused: None,
+        }
+    }
+
+    /// Set the timeout for establishing the connection.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    /// Set the deadline for the whole command, including connecting and retries.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Retry refused connections for up to `window`, e.g. while the daemon restarts.
+    pub fn retry_refused(mut self, window: Duration) -> Self {
+        self.retry_refused = Some(window);
+        self
+    }
+
+    /// Send `command` with the optional `args` and deserialize the result.
+    pub async fn send<T: DeserializeOwned>(
+        &self,
+        command: &str,
+        args: Option<Value>,
+    ) -> Result<T, CommandError> {
+        let mut request = json!({ "command": command });
+        if let Some(args) = args {
+            request["args"] = args;
+        }
+        let mut request = request.to_string();
+        request.push('\n');
+
+        let deadline = Instant::now() + self.timeout;
+        let response = tokio::time::timeout_at(deadline, self.send_raw(&request))
+            .await
+            .map_err(|_| CommandError::Timeout(self.timeout))??;
+
+        serde_json::from_value(parse_response(&response)?)
+            .map_err(|err| CommandError::BadResponse(format!("invalid result - {err}")))
+    }
+
+    async fn connect(&self) -> Result<UnixStream, CommandError> {
+        let retry_until = self.retry_refused.map(|window| Instant::now() + window);
+        loop {
+            let err =
+                match tokio::time::timeout(self.connect_timeout, UnixStream::connect(&self.path))
+                    .await
+                {
+                    Ok(Ok(conn)) => return Ok(conn),
+                    Ok(Err(err)) => err,
+                    Err(_) => return Err(CommandError::Timeout(self.connect_timeout)),
+                };
+
+            match retry_until {
+                Some(until)
+                    if err.kind() == io::ErrorKind::ConnectionRefused && Instant::now() < until =>
+                {
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                }
+                _ => return Err(err.into()),
+            }
+        }
+    }
+
+    async fn send_raw(&self, request: &str) -> Result<String, CommandError> {
+        let mut conn = self.connect().await?;
+
+        // a daemon rejecting us may have answered and closed the connection already
+        match conn.write_all(request.as_bytes()).await {
+            Err(err) if err.kind() != io::ErrorKind::BrokenPipe => return Err(err.into()),
+            _ => {
+                let _ = AsyncWriteExt::shutdown(&mut conn).await;
+            }
+        }
+
+        let mut response = String::new();
+        if tokio::io::BufReader::new(conn)
+            .read_line(&mut response)
+            .await?
+            == 0
+        {
+            return Err(CommandError::BadResponse("no response".to_string()));
+        }
+        Ok(response)
+    }
+}
+
+/// Send `command` with the optional `args` to the control socket at `path` and deserialize
+/// the result, failing if the daemon does not respond within `timeout`.
+///
+/// See [`CommandClient`] for more options.
+pub async fn send_command_typed<T, P>(
+    path: P,
+    command: &str,
+    args: Option<Value>,
+    timeout: Duration,
+) -> Result<T, CommandError>
+where
+    T: DeserializeOwned,
+    P: Into<PathBuf>,
+{
+    CommandClient::new(path)
+        .timeout(timeout)
+        .send(command, args)
+        .await
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_response() {
+        assert_eq!(
+            parse_response("OK: {\"a\":1}\n").unwrap(),
+            json!({ "a": 1 })
+        );
+        assert!(matches!(
+            parse_response("ERROR: permission denied\n"),
+            Err(CommandError::PermissionDenied)
+        ));
+        assert!(matches!(
+            parse_response("ERROR: got unknown command 'foo'\n"),
+            Err(CommandError::NoSuchCommand(command)) if command == "foo"
+        ));
+        assert!(matches!(
+            parse_response("ERROR: no command\n"),
+            Err(CommandError::Handler(msg)) if msg == "no command"
+        ));
+        assert!(matches!(
+            parse_response("OK: {\n"),
+            Err(CommandError::BadResponse(_))
+        ));
+        assert!(matches!(
+            parse_response("garbage\n"),
+            Err(CommandError::BadResponse(_))
+        ));
+    }
+}
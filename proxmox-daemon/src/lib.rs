@@ -9,4 +9,9 @@ pub use state::{catch_reload_signal, reload_signal_task};
 pub use state::{catch_shutdown_signal, shutdown_signal_task};
 pub use state::{is_reload_requested, is_shutdown_requested, request_reload, request_shutdown};
 
+mod signals;
+pub use signals::{register_signal_handlers, SignalAction, SignalConfig};
+
 pub mod server;
+
+pub mod watchdog;
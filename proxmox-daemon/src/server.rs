@@ -1,17 +1,24 @@
 //! Helpers to implement restartable server listening for incoming connections.
 
 use std::ffi::CString;
+use std::fmt;
+use std::fs::File;
 use std::future::Future;
-use std::io::{self, Read, Write};
+use std::io::{self, Read, Seek, Write};
+use std::net::{Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
 use std::os::unix::ffi::OsStrExt;
 use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd, OwnedFd, RawFd};
 use std::panic::UnwindSafe;
 use std::path::PathBuf;
 use std::pin::{pin, Pin};
+use std::sync::Mutex;
+use std::time::Duration;
 
 use anyhow::{bail, format_err, Error};
 use futures::future::{self, Either};
+use nix::errno::Errno;
 use nix::unistd::{fork, ForkResult};
+use tokio::net::{TcpListener, TcpSocket};
 
 use proxmox_sys::fd::fd_change_cloexec;
 use proxmox_sys::fs::CreateOptions;
@@ -26,12 +33,93 @@ pub trait Reloadable: Sized {
     fn get_store_func(&self) -> Result<BoxedStoreFunc, Error>;
 }
 
+type StateFunc = Box<dyn Fn() -> Result<String, Error> + Send + Sync>;
+
+// The registered reload states by name. Entries without a function were taken over from the
+// previous process but are not passed on.
+static RELOAD_STATE: Mutex<Vec<(&'static str, Option<StateFunc>)>> = Mutex::new(Vec::new());
+
+/// Register a function providing state to pass on to the new process on a reload, where it can
+/// be taken over with [`take_reload_state`].
+///
+/// The function is called once when the daemon reloads, registering another function under the
+/// same `name` replaces the previous one. The state is passed on in an anonymous file, so there is
+/// no limit on its size.
+pub fn register_reload_state<F>(name: &'static str, func: F)
+where
+    F: Fn() -> Result<String, Error> + Send + Sync + 'static,
+{
+    let mut states = RELOAD_STATE.lock().unwrap();
+    match states.iter_mut().find(|(entry, _)| *entry == name) {
+        Some((_, entry)) => *entry = Some(Box::new(func)),
+        None => states.push((name, Some(Box::new(func)))),
+    }
+}
+
+/// Take the state passed on by the previous process under `name`, see
+/// [`register_reload_state`].
+///
+/// Returns `None` if there is none, or if it was taken already or a state was registered under
+/// this name in this process.
+pub fn take_reload_state(name: &'static str) -> Result<Option<String>, Error> {
+    let mut states = RELOAD_STATE.lock().unwrap();
+    if states.iter().any(|(entry, _)| *entry == name) {
+        return Ok(None);
+    }
+    states.push((name, None));
+
+    let var = match std::env::var(name) {
+        Ok(var) => var,
+        Err(std::env::VarError::NotPresent) => return Ok(None),
+        Err(_) => bail!("variable {} has invalid value", name),
+    };
+    let mut file: File = unsafe { fd_restore_func(&var) }?;
+    let mut state = String::new();
+    file.read_to_string(&mut state)?;
+    Ok(Some(state))
+}
+
+// Write the registered reload states into anonymous files, must be called before forking since
+// the functions may lock.
+fn store_reload_states() -> Vec<(&'static str, Option<File>)> {
+    let states = RELOAD_STATE.lock().unwrap();
+    states
+        .iter()
+        .map(|(name, func)| {
+            let file = func.as_ref().and_then(|func| {
+                match func().and_then(|state| state_file(name, &state)) {
+                    Ok(file) => Some(file),
+                    Err(err) => {
+                        log::error!("unable to store reload state {name} - {err}");
+                        None
+                    }
+                }
+            });
+            (*name, file)
+        })
+        .collect()
+}
+
+fn state_file(name: &str, state: &str) -> Result<File, Error> {
+    let fd = nix::sys::memfd::memfd_create(
+        &CString::new(name)?,
+        nix::sys::memfd::MemFdCreateFlag::MFD_CLOEXEC,
+    )?;
+    let mut file = unsafe { File::from_raw_fd(fd) };
+    file.write_all(state.as_bytes())?;
+    file.rewind()?;
+    Ok(file)
+}
+
 // Manages things to be stored and reloaded upon reexec.
 // Anything which should be restorable should be instantiated via this struct's `restore` method,
 #[derive(Default)]
 struct Reloader {
     pre_exec: Vec<PreExecEntry>,
+    // Variables of reload states which are not passed on.
+    stale_vars: Vec<&'static str>,
     self_exe: PathBuf,
+    watchdog_timeout: Option<Duration>,
 }
 
 // Currently we only need environment variables for storage, but in theory we could also add
@@ -45,9 +133,13 @@ impl Reloader {
     pub fn new() -> Result<Self, Error> {
         Ok(Self {
             pre_exec: Vec::new(),
+            stale_vars: Vec::new(),
 
             // Get the path to our executable as PathBuf
             self_exe: std::fs::read_link("/proc/self/exe")?,
+
+            // Remember whether we are responsible for the watchdog, the new process will be.
+            watchdog_timeout: crate::watchdog::watchdog_timeout()?,
         })
     }
 
@@ -75,6 +167,9 @@ impl Reloader {
     }
 
     fn pre_exec(self) -> Result<(), Error> {
+        for name in self.stale_vars {
+            std::env::remove_var(name);
+        }
         for item in self.pre_exec {
             std::env::set_var(item.name, (item.store_fn)()?);
         }
@@ -102,7 +197,17 @@ impl Reloader {
         Ok(())
     }
 
-    pub fn fork_restart(self, pid_fn: Option<&str>) -> Result<(), Error> {
+    pub fn fork_restart(mut self, pid_fn: Option<&str>) -> Result<(), Error> {
+        for (name, file) in store_reload_states() {
+            match file {
+                Some(file) => self.pre_exec.push(PreExecEntry {
+                    name,
+                    store_fn: fd_store_func(file.as_raw_fd())?,
+                }),
+                None => self.stale_vars.push(name),
+            }
+        }
+
         // Get our parameters as Vec<CString>
         let args = std::env::args_os();
         let mut new_args = Vec::with_capacity(args.len());
@@ -222,6 +327,7 @@ impl Reloader {
 
     fn do_reexec(self, args: Vec<CString>) -> Result<(), Error> {
         let exe = CString::new(self.self_exe.as_os_str().as_bytes())?;
+        crate::watchdog::reset_watchdog_env(self.watchdog_timeout);
         self.pre_exec()?;
         nix::unistd::setsid()?;
         let args: Vec<&std::ffi::CStr> = args.iter().map(|s| s.as_ref()).collect();
@@ -305,6 +411,128 @@ impl Listenable for tokio::net::UnixListener {
     }
 }
 
+/// Where a TCP daemon listens, see [`create_tcp_daemon`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ListenSpec {
+    /// Listen on all IPv6 and IPv4 addresses with a single socket. Falls back to IPv4 only, with
+    /// a warning, if IPv6 is disabled on the host.
+    DualStack(u16),
+    /// Listen on an IPv4 address.
+    V4(SocketAddrV4),
+    /// Listen on an IPv6 address. The flag sets `IPV6_V6ONLY`, if it is not set, listening on
+    /// the unspecified address `::` also accepts IPv4 connections.
+    V6(SocketAddrV6, bool),
+}
+
+impl fmt::Display for ListenSpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ListenSpec::DualStack(port) => write!(f, "*:{port}"),
+            ListenSpec::V4(addr) => write!(f, "{addr}"),
+            ListenSpec::V6(addr, true) => write!(f, "{addr} (v6only)"),
+            ListenSpec::V6(addr, false) => write!(f, "{addr}"),
+        }
+    }
+}
+
+const LISTEN_BACKLOG: u32 = 1024;
+
+impl ListenSpec {
+    /// Create a listening socket with `SO_REUSEADDR` set.
+    ///
+    /// Set `reuse_port` to also set `SO_REUSEPORT`, so multiple processes can listen on the same
+    /// address, e.g. during a reload or for load balancing.
+    ///
+    /// Must be called from within a tokio runtime.
+    pub fn bind(&self, reuse_port: bool) -> io::Result<TcpListener> {
+        match *self {
+            ListenSpec::DualStack(port) => {
+                let addr = SocketAddrV6::new(Ipv6Addr::UNSPECIFIED, port, 0, 0);
+                match bind_tcp(SocketAddr::V6(addr), Some(false), reuse_port) {
+                    Err(err) if is_ipv6_unavailable(&err) => {
+                        log::warn!("IPv6 is not available ({err}), listening on IPv4 only");
+                        let addr = SocketAddrV4::new(std::net::Ipv4Addr::UNSPECIFIED, port);
+                        bind_tcp(SocketAddr::V4(addr), None, reuse_port)
+                    }
+                    result => result,
+                }
+            }
+            ListenSpec::V4(addr) => bind_tcp(SocketAddr::V4(addr), None, reuse_port),
+            ListenSpec::V6(addr, v6only) => {
+                bind_tcp(SocketAddr::V6(addr), Some(v6only), reuse_port)
+            }
+        }
+    }
+}
+
+fn is_ipv6_unavailable(err: &io::Error) -> bool {
+    matches!(
+        err.raw_os_error().map(Errno::from_i32),
+        Some(Errno::EAFNOSUPPORT | Errno::EADDRNOTAVAIL)
+    )
+}
+
+fn bind_tcp(addr: SocketAddr, v6only: Option<bool>, reuse_port: bool) -> io::Result<TcpListener> {
+    let socket = match addr {
+        SocketAddr::V4(_) => TcpSocket::new_v4()?,
+        SocketAddr::V6(_) => TcpSocket::new_v6()?,
+    };
+    socket.set_reuseaddr(true)?;
+    if reuse_port {
+        socket.set_reuseport(true)?;
+    }
+    if let Some(v6only) = v6only {
+        nix::sys::socket::setsockopt(
+            socket.as_raw_fd(),
+            nix::sys::socket::sockopt::Ipv6V6Only,
+            &v6only,
+        )?;
+    }
+    socket.bind(addr)?;
+    socket.listen(LISTEN_BACKLOG)
+}
+
+/// A TCP listener remembering the [`ListenSpec`] it was created for across reloads.
+struct SpecListener(TcpListener, String);
+
+impl Reloadable for SpecListener {
+    fn get_store_func(&self) -> Result<BoxedStoreFunc, Error> {
+        let store_fd = fd_store_func(self.0.as_raw_fd())?;
+        let spec = self.1.clone();
+        Ok(Box::new(move || Ok(format!("{}:{spec}", store_fd()?))))
+    }
+
+    fn restore(var: &str) -> Result<Self, Error> {
+        let (fd, spec) = var.split_once(':').unwrap_or((var, ""));
+        let listener = TcpListener::restore(fd)?;
+        Ok(Self(listener, spec.to_string()))
+    }
+}
+
+/// Close a listening socket passed on by the previous process in the environment variable `var`
+/// if it was created for a different spec than `spec`, so a new one is created.
+///
+/// Sockets passed on by versions not recording the spec, e.g. by [`create_daemon`], are kept.
+fn drop_changed_listener(var: &str, spec: &str) -> Result<(), Error> {
+    let value = match std::env::var(var) {
+        Ok(value) => value,
+        Err(_) => return Ok(()),
+    };
+    match value.split_once(':') {
+        Some((_, old_spec)) if old_spec == spec => (),
+        Some((fd, old_spec)) => {
+            log::info!("listen address changed from {old_spec} to {spec}, creating a new socket");
+            let fd = fd
+                .parse::<u32>()
+                .map_err(|e| format_err!("invalid file descriptor: {}", e))?;
+            drop(unsafe { OwnedFd::from_raw_fd(fd as RawFd) });
+            std::env::remove_var(var);
+        }
+        None => std::env::set_var(var, format!("{value}:{spec}")),
+    }
+    Ok(())
+}
+
 /// This creates a future representing a daemon which reloads itself when receiving a SIGHUP.
 /// If this is started regularly, a listening socket is created. In this case, the file descriptor
 /// number will be remembered in `PROXMOX_BACKUP_LISTEN_FD`.
@@ -314,6 +542,10 @@ impl Listenable for tokio::net::UnixListener {
 /// [SystemdNotify::notify](proxmox_systemd::notify::SystemdNotify::notify) with
 /// [SystemdNotify::Ready](proxmox_systemd::notify::SystemdNotify) when the
 /// service is ready.
+///
+/// If the systemd watchdog is enabled, the product should spawn a
+/// [`watchdog_task`](crate::watchdog::watchdog_task) with a suitable health check, it is stopped on
+/// shutdown and reload and the re-executed process takes over the watchdog.
 pub async fn create_daemon<F, S, L>(
     address: L::Address,
     create_service: F,
@@ -327,13 +559,49 @@ where
     let mut reloader = Reloader::new()?;
 
     let listener: L = reloader
-        .restore("PROXMOX_BACKUP_LISTEN_FD", move || async move {
+        .restore(LISTEN_FD_VAR, move || async move {
             Ok(L::bind(&address).await?)
         })
         .await?;
 
     let service = create_service(listener)?;
+    run_daemon(reloader, service, pidfn).await
+}
 
+/// Like [`create_daemon`], but listen according to a [`ListenSpec`], see [`ListenSpec::bind`].
+///
+/// The spec is passed on along with the socket when reloading. If it changed, e.g. because the
+/// configuration changed, the socket is closed and a new one is created.
+pub async fn create_tcp_daemon<F, S>(
+    spec: ListenSpec,
+    reuse_port: bool,
+    create_service: F,
+    pidfn: Option<&str>,
+) -> Result<(), Error>
+where
+    F: FnOnce(TcpListener) -> Result<S, Error>,
+    S: Future<Output = Result<(), Error>>,
+{
+    let mut reloader = Reloader::new()?;
+
+    let spec_str = spec.to_string();
+    drop_changed_listener(LISTEN_FD_VAR, &spec_str)?;
+    let SpecListener(listener, _) = reloader
+        .restore(LISTEN_FD_VAR, move || async move {
+            Ok(SpecListener(spec.bind(reuse_port)?, spec_str))
+        })
+        .await?;
+
+    let service = create_service(listener)?;
+    run_daemon(reloader, service, pidfn).await
+}
+
+const LISTEN_FD_VAR: &str = "PROXMOX_BACKUP_LISTEN_FD";
+
+async fn run_daemon<S>(reloader: Reloader, service: S, pidfn: Option<&str>) -> Result<(), Error>
+where
+    S: Future<Output = Result<(), Error>>,
+{
     let service = async move {
         if let Err(err) = service.await {
             log::error!("server error: {}", err);
@@ -392,3 +660,120 @@ fn socketpair() -> Result<(OwnedFd, OwnedFd), Error> {
     )?;
     Ok(unsafe { (OwnedFd::from_raw_fd(pa), OwnedFd::from_raw_fd(pb)) })
 }
+
+#[cfg(test)]
+mod test {
+    use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+    use std::os::unix::io::{AsRawFd, IntoRawFd};
+
+    use tokio::net::{TcpListener, TcpStream};
+
+    use super::{drop_changed_listener, state_file, ListenSpec};
+
+    async fn connects(listener: &TcpListener, addr: SocketAddr) -> bool {
+        let connect = TcpStream::connect(addr);
+        let accept = tokio::time::timeout(std::time::Duration::from_secs(1), listener.accept());
+        matches!(
+            futures::future::join(connect, accept).await,
+            (Ok(_), Ok(Ok(_)))
+        )
+    }
+
+    fn runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_listen_spec() {
+        runtime().block_on(async {
+            let listener = ListenSpec::DualStack(0).bind(false).unwrap();
+            let addr = listener.local_addr().unwrap();
+            let port = addr.port();
+            assert!(connects(&listener, (Ipv4Addr::LOCALHOST, port).into()).await);
+            // IPv6 is only used where available
+            if addr.is_ipv6() {
+                assert!(connects(&listener, (Ipv6Addr::LOCALHOST, port).into()).await);
+            }
+
+            let spec = ListenSpec::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0));
+            let listener = spec.bind(true).unwrap();
+            let addr = listener.local_addr().unwrap();
+            assert!(connects(&listener, addr).await);
+
+            // the address is shared with other sockets setting SO_REUSEPORT
+            let spec = ListenSpec::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, addr.port()));
+            let other = spec.bind(true).unwrap();
+            assert_eq!(other.local_addr().unwrap(), addr);
+            drop(other);
+            assert!(
+                ListenSpec::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, addr.port()))
+                    .bind(false)
+                    .is_err()
+            );
+
+            if !ListenSpec::DualStack(0)
+                .bind(false)
+                .unwrap()
+                .local_addr()
+                .unwrap()
+                .is_ipv6()
+            {
+                return;
+            }
+            let spec = ListenSpec::V6(SocketAddrV6::new(Ipv6Addr::UNSPECIFIED, 0, 0, 0), true);
+            let listener = spec.bind(false).unwrap();
+            let port = listener.local_addr().unwrap().port();
+            assert!(connects(&listener, (Ipv6Addr::LOCALHOST, port).into()).await);
+            assert!(!connects(&listener, (Ipv4Addr::LOCALHOST, port).into()).await);
+        });
+    }
+
+    #[test]
+    fn test_listen_spec_change() {
+        const VAR: &str = "PROXMOX_TEST_LISTEN_FD";
+
+        runtime().block_on(async {
+            let spec = ListenSpec::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0));
+            assert_eq!(spec.to_string(), "127.0.0.1:0");
+            assert_eq!(ListenSpec::DualStack(8007).to_string(), "*:8007");
+            let v6 = SocketAddrV6::new(Ipv6Addr::LOCALHOST, 8007, 0, 0);
+            assert_eq!(ListenSpec::V6(v6, true).to_string(), "[::1]:8007 (v6only)");
+
+            let is_open = |fd| nix::fcntl::fcntl(fd, nix::fcntl::FcntlArg::F_GETFD).is_ok();
+
+            // unchanged specs keep the socket
+            let fd = spec.bind(false).unwrap().into_std().unwrap().into_raw_fd();
+            std::env::set_var(VAR, format!("{fd}:{spec}"));
+            drop_changed_listener(VAR, &spec.to_string()).unwrap();
+            assert_eq!(std::env::var(VAR).unwrap(), format!("{fd}:{spec}"));
+            assert!(is_open(fd));
+
+            // sockets passed on without a spec are kept and get the current one
+            std::env::set_var(VAR, fd.to_string());
+            drop_changed_listener(VAR, &spec.to_string()).unwrap();
+            assert_eq!(std::env::var(VAR).unwrap(), format!("{fd}:{spec}"));
+            assert!(is_open(fd));
+
+            // changed specs close the socket, so a new one is created
+            drop_changed_listener(VAR, "*:8007").unwrap();
+            assert!(std::env::var(VAR).is_err());
+            assert!(!is_open(fd));
+
+            let listener = spec.bind(false).unwrap();
+            assert!(is_open(listener.as_raw_fd()));
+        });
+    }
+
+    #[test]
+    fn test_state_file() {
+        use std::io::Read;
+
+        let mut state = String::new();
+        let mut file = state_file("test", "{\"key\":\"value\"}").unwrap();
+        file.read_to_string(&mut state).unwrap();
+        assert_eq!(state, "{\"key\":\"value\"}");
+    }
+}
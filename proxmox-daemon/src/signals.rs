@@ -0,0 +1,215 @@
+//! Configurable dispatching of process signals, see [`register_signal_handlers()`].
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Error;
+use nix::sys::signal::{pthread_sigmask, SigSet, SigmaskHow, Signal};
+use tokio::signal::unix::{signal, SignalKind};
+
+use proxmox_systemd::notify::SystemdNotify;
+
+/// What to do when a signal is received, see [`SignalConfig`].
+#[derive(Clone, Default)]
+pub enum SignalAction {
+    /// Do not register a handler, keeping the default disposition of the signal.
+    #[default]
+    Unhandled,
+    /// Request a shutdown, undoing a previous reload request.
+    Shutdown,
+    /// Request a reload, see [`request_reload()`](crate::request_reload).
+    Reload,
+    /// Call a function, e.g. to reopen log files. Errors are logged.
+    Custom(Arc<dyn Fn() -> Result<(), Error> + Send + Sync>),
+}
+
+impl SignalAction {
+    /// Create a [`SignalAction::Custom`] action calling `callback`.
+    pub fn custom<F>(callback: F) -> Self
+    where
+        F: Fn() -> Result<(), Error> + Send + Sync + 'static,
+    {
+        Self::Custom(Arc::new(callback))
+    }
+}
+
+impl fmt::Debug for SignalAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Unhandled => f.write_str("Unhandled"),
+            Self::Shutdown => f.write_str("Shutdown"),
+            Self::Reload => f.write_str("Reload"),
+            Self::Custom(_) => f.write_str("Custom"),
+        }
+    }
+}
+
+/// The actions for the signals handled by [`register_signal_handlers()`].
+///
+/// By default `SIGINT` and `SIGTERM` shut down, `SIGHUP` reloads and a second shutdown signal
+/// within 5 seconds exits immediately, like the `catch_*_signal` helpers plus `SIGTERM`.
+#[derive(Clone, Debug)]
+pub struct SignalConfig {
+    /// Action for `SIGINT`.
+    pub int: SignalAction,
+    /// Action for `SIGTERM`.
+    pub term: SignalAction,
+    /// Action for `SIGHUP`.
+    pub hup: SignalAction,
+    /// Action for `SIGUSR1`.
+    pub usr1: SignalAction,
+    /// Action for `SIGUSR2`.
+    pub usr2: SignalAction,
+    /// Exit immediately if a signal with a [`SignalAction::Shutdown`] action is received again
+    /// within this time after the first one. `None` never exits on repeated signals.
+    pub force_exit_within: Option<Duration>,
+}
+
+impl Default for SignalConfig {
+    fn default() -> Self {
+        Self {
+            int: SignalAction::Shutdown,
+            term: SignalAction::Shutdown,
+            hup: SignalAction::Reload,
+            usr1: SignalAction::Unhandled,
+            usr2: SignalAction::Unhandled,
+            force_exit_within: Some(Duration::from_secs(5)),
+        }
+    }
+}
+
+/// Tracks repeated shutdown signals to decide when to exit immediately.
+struct ForceExit {
+    within: Option<Duration>,
+    first: Option<Instant>,
+}
+
+impl ForceExit {
+    fn new(within: Option<Duration>) -> Self {
+        Self {
+            within,
+            first: None,
+        }
+    }
+
+    /// Record a shutdown signal received at `now`, returns true if the process should exit.
+    fn record(&mut self, now: Instant) -> bool {
+        let Some(within) = self.within else {
+            return false;
+        };
+        match self.first {
+            Some(first) if now.saturating_duration_since(first) <= within => true,
+            _ => {
+                self.first = Some(now);
+                false
+            }
+        }
+    }
+}
+
+/// Spawn tasks dispatching `SIGINT`, `SIGTERM`, `SIGHUP`, `SIGUSR1` and `SIGUSR2` to the
+/// actions of `config`.
+///
+/// Shutdowns update the systemd status with the name of the signal, so a shutdown requested by
+/// `SIGTERM` can be told apart from other shutdowns. The handled signals are unblocked for the
+/// calling thread, as a blocked signal mask is inherited over the re-execution of a reloading
+/// daemon. It should therefore be called before spawning further threads.
+///
+/// Must be called from within a tokio runtime.
+pub fn register_signal_handlers(config: SignalConfig) -> Result<(), Error> {
+    let SignalConfig {
+        int,
+        term,
+        hup,
+        usr1,
+        usr2,
+        force_exit_within,
+    } = config;
+
+    let signals = [
+        (Signal::SIGINT, int),
+        (Signal::SIGTERM, term),
+        (Signal::SIGHUP, hup),
+        (Signal::SIGUSR1, usr1),
+        (Signal::SIGUSR2, usr2),
+    ];
+
+    let mut mask = SigSet::empty();
+    for (signo, action) in &signals {
+        if !matches!(action, SignalAction::Unhandled) {
+            mask.add(*signo);
+        }
+    }
+    pthread_sigmask(SigmaskHow::SIG_UNBLOCK, Some(&mask), None)?;
+
+    for (signo, action) in signals {
+        if matches!(action, SignalAction::Unhandled) {
+            continue;
+        }
+
+        let mut stream = signal(SignalKind::from_raw(signo as i32))?;
+        let mut force_exit = ForceExit::new(force_exit_within);
+        tokio::spawn(async move {
+            while stream.recv().await.is_some() {
+                dispatch(signo, &action, &mut force_exit);
+            }
+        });
+    }
+
+    Ok(())
+}
+
+fn dispatch(signo: Signal, action: &SignalAction, force_exit: &mut ForceExit) {
+    let name = signo.as_str();
+    match action {
+        SignalAction::Unhandled => (),
+        SignalAction::Shutdown => {
+            if force_exit.record(Instant::now()) {
+                log::warn!("got repeated shutdown request ({name}) - exiting immediately");
+                std::process::exit(128 + signo as i32);
+            }
+
+            log::info!("got shutdown request ({name})");
+            if let Err(err) = SystemdNotify::Status(format!("shutting down ({name})")).notify() {
+                log::error!("failed to notify systemd about the shutdown - {err}");
+            }
+            crate::state::cancel_reload();
+            crate::request_shutdown();
+        }
+        SignalAction::Reload => {
+            log::info!("got reload request ({name})");
+            crate::request_reload();
+        }
+        SignalAction::Custom(callback) => {
+            log::info!("got {name}");
+            if let Err(err) = callback() {
+                log::error!("handling {name} failed - {err}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_force_exit() {
+        let start = Instant::now();
+
+        let mut force_exit = ForceExit::new(None);
+        assert!(!force_exit.record(start));
+        assert!(!force_exit.record(start));
+
+        let mut force_exit = ForceExit::new(Some(Duration::from_secs(5)));
+        assert!(!force_exit.record(start));
+        assert!(force_exit.record(start + Duration::from_secs(5)));
+
+        // a signal after the time frame starts a new one
+        let mut force_exit = ForceExit::new(Some(Duration::from_secs(5)));
+        assert!(!force_exit.record(start));
+        assert!(!force_exit.record(start + Duration::from_secs(6)));
+        assert!(force_exit.record(start + Duration::from_secs(7)));
+    }
+}
@@ -43,6 +43,11 @@ pub fn is_shutdown_requested() -> bool {
     SHUTDOWN_REQUESTED.load(Ordering::Acquire)
 }
 
+/// Undo a previous reload request, so a pending shutdown does not re-execute the daemon.
+pub(crate) fn cancel_reload() {
+    RELOAD_REQUESTED.store(false, Ordering::Release);
+}
+
 fn shutdown_listeners() -> &'static watch::Sender<bool> {
     SHUTDOWN_LISTENERS.get_or_init(|| watch::channel(false).0)
 }
@@ -72,7 +77,7 @@ pub fn shutdown_signal_task() -> Result<impl Future<Output = ()> + Send + 'stati
     Ok(async move {
         while stream.recv().await.is_some() {
             log::info!("got shutdown request (SIGINT)");
-            RELOAD_REQUESTED.store(false, Ordering::Release);
+            cancel_reload();
             request_shutdown();
         }
     })
@@ -0,0 +1,181 @@
+//! Systemd watchdog keep-alive handling (see `WatchdogSec=` in ``man systemd.service``).
+
+use std::future::Future;
+use std::pin::pin;
+use std::time::Duration;
+
+use anyhow::{bail, format_err, Error};
+use futures::future;
+
+use proxmox_systemd::notify::SystemdNotify;
+
+const WATCHDOG_USEC: &str = "WATCHDOG_USEC";
+const WATCHDOG_PID: &str = "WATCHDOG_PID";
+
+/// Returns the watchdog timeout systemd expects this process to send keep-alive pings within,
+/// or `None` if the watchdog is not enabled for this process.
+pub fn watchdog_timeout() -> Result<Option<Duration>, Error> {
+    parse_watchdog_env(
+        std::env::var(WATCHDOG_USEC).ok().as_deref(),
+        std::env::var(WATCHDOG_PID).ok().as_deref(),
+        std::process::id(),
+    )
+}
+
+fn parse_watchdog_env(
+    usec: Option<&str>,
+    pid: Option<&str>,
+    own_pid: u32,
+) -> Result<Option<Duration>, Error> {
+    let Some(usec) = usec else {
+        return Ok(None);
+    };
+
+    // without a pid the watchdog applies to the main process, which is us
+    if let Some(pid) = pid {
+        let pid: u32 = pid
+            .parse()
+            .map_err(|err| format_err!("invalid {WATCHDOG_PID} '{pid}' - {err}"))?;
+        if pid != own_pid {
+            return Ok(None);
+        }
+    }
+
+    let usec: u64 = usec
+        .parse()
+        .map_err(|err| format_err!("invalid {WATCHDOG_USEC} '{usec}' - {err}"))?;
+    if usec == 0 {
+        bail!("invalid {WATCHDOG_USEC} '0'");
+    }
+
+    Ok(Some(Duration::from_micros(usec)))
+}
+
+/// Keep-alive pings are sent at half the timeout, as recommended by ``man sd_watchdog_enabled``.
+fn ping_interval(timeout: Duration) -> Duration {
+    (timeout / 2).max(Duration::from_millis(1))
+}
+
+/// Replace the watchdog environment of the old main process before re-executing, so the new
+/// main process detects whether it is responsible for the watchdog on its own.
+pub(crate) fn reset_watchdog_env(timeout: Option<Duration>) {
+    std::env::remove_var(WATCHDOG_USEC);
+    std::env::remove_var(WATCHDOG_PID);
+
+    if let Some(timeout) = timeout {
+        std::env::set_var(WATCHDOG_USEC, timeout.as_micros().to_string());
+        std::env::set_var(WATCHDOG_PID, std::process::id().to_string());
+    }
+}
+
+/// Creates a task sending keep-alive pings to the systemd watchdog, or returns `None` if the
+/// watchdog is not enabled for this process.
+///
+/// Before each ping `health_check` is called, a ping is only sent if it succeeds. This way a
+/// daemon that is stuck (e.g. its event loop is not responsive or a lock is never released) stops
+/// pinging and gets restarted by systemd. The task finishes once a shutdown or reload has been
+/// requested.
+pub fn watchdog_task<F>(
+    health_check: F,
+) -> Result<Option<impl Future<Output = ()> + Send + 'static>, Error>
+where
+    F: Fn() -> Result<(), Error> + Send + 'static,
+{
+    let Some(timeout) = watchdog_timeout()? else {
+        return Ok(None);
+    };
+
+    let interval = ping_interval(timeout);
+    log::info!("systemd watchdog enabled, sending keep-alive every {interval:?}");
+
+    Ok(Some(async move {
+        let ping_loop = async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            let mut healthy = true;
+            loop {
+                ticker.tick().await;
+                match health_check() {
+                    Ok(()) => {
+                        if !healthy {
+                            log::info!("health check succeeded again, resuming watchdog pings");
+                            healthy = true;
+                        }
+                        if let Err(err) = SystemdNotify::Watchdog.notify() {
+                            log::error!("failed to send watchdog keep-alive: {err}");
+                        }
+                    }
+                    Err(err) => {
+                        if healthy {
+                            log::error!("health check failed, suspending watchdog pings - {err}");
+                            healthy = false;
+                        }
+                    }
+                }
+            }
+        };
+
+        future::select(pin!(ping_loop), pin!(crate::shutdown_future())).await;
+        log::debug!("stopping watchdog keep-alive task");
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_watchdog_env() {
+        assert_eq!(parse_watchdog_env(None, None, 42).unwrap(), None);
+        assert_eq!(parse_watchdog_env(None, Some("42"), 42).unwrap(), None);
+        assert_eq!(
+            parse_watchdog_env(Some("30000000"), None, 42).unwrap(),
+            Some(Duration::from_secs(30)),
+        );
+        assert_eq!(
+            parse_watchdog_env(Some("30000000"), Some("42"), 42).unwrap(),
+            Some(Duration::from_secs(30)),
+        );
+        // meant for another process, e.g. the previous main process before a reload
+        assert_eq!(
+            parse_watchdog_env(Some("30000000"), Some("41"), 42).unwrap(),
+            None,
+        );
+
+        assert!(parse_watchdog_env(Some("0"), None, 42).is_err());
+        assert!(parse_watchdog_env(Some("30s"), None, 42).is_err());
+        assert!(parse_watchdog_env(Some("30000000"), Some("abc"), 42).is_err());
+    }
+
+    #[test]
+    fn test_ping_interval() {
+        assert_eq!(
+            ping_interval(Duration::from_secs(30)),
+            Duration::from_secs(15),
+        );
+        assert_eq!(
+            ping_interval(Duration::from_micros(3)),
+            Duration::from_millis(1),
+        );
+        assert_eq!(
+            ping_interval(Duration::from_micros(1_500_001)),
+            Duration::from_nanos(750_000_500),
+        );
+    }
+
+    #[test]
+    fn test_reset_watchdog_env() {
+        std::env::set_var(WATCHDOG_USEC, "20000000");
+        std::env::set_var(WATCHDOG_PID, "1");
+        assert_eq!(watchdog_timeout().unwrap(), None);
+
+        let timeout = Some(Duration::from_secs(20));
+        reset_watchdog_env(timeout);
+        assert_eq!(watchdog_timeout().unwrap(), timeout);
+
+        reset_watchdog_env(None);
+        assert!(std::env::var_os(WATCHDOG_USEC).is_none());
+        assert!(std::env::var_os(WATCHDOG_PID).is_none());
+        assert_eq!(watchdog_timeout().unwrap(), None);
+    }
+}
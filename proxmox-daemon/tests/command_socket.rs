@@ -0,0 +1,157 @@
+use std::time::Duration;
+
+use anyhow::{bail, Error};
+use nix::unistd::Gid;
+use serde_json::{json, Value};
+use tokio::io::AsyncWriteExt;
+use tokio::net::UnixListener;
+
+use proxmox_daemon::command_socket::{
+    path_from_pid_file, send_command_typed, this_path, CommandClient, CommandError, CommandSocket,
+};
+
+/// A unique abstract socket path for each test.
+fn socket_path(name: &str) -> String {
+    format!("\0proxmox-daemon-test/{}/{name}.sock", std::process::id())
+}
+
+fn spawn_command_socket(path: &str) -> Result<(), Error> {
+    let mut socket = CommandSocket::with_path(path, Gid::current());
+    socket.register_command("echo".to_string(), |args| Ok(args.cloned().into()))?;
+    socket.register_command("count".to_string(), |_args| Ok(json!(3)))?;
+    socket.register_command("fail".to_string(), |_args| bail!("disk on fire"))?;
+    socket.spawn(std::future::pending())
+}
+
+fn run<F: std::future::Future<Output = Result<(), Error>>>(test: F) -> Result<(), Error> {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?
+        .block_on(test)
+}
+
+#[test]
+fn test_typed_responses() -> Result<(), Error> {
+    run(async {
+        let path = socket_path("typed");
+        spawn_command_socket(&path)?;
+        let timeout = Duration::from_secs(10);
+
+        let count: u64 = send_command_typed(&path, "count", None, timeout).await?;
+        assert_eq!(count, 3);
+
+        let args = json!({ "name": "test" });
+        let echo: Value = send_command_typed(&path, "echo", Some(args.clone()), timeout).await?;
+        assert_eq!(echo, args);
+
+        let err = send_command_typed::<Value, _>(&path, "missing", None, timeout)
+            .await
+            .unwrap_err();
+        assert!(matches!(&err, CommandError::NoSuchCommand(command) if command == "missing"));
+
+        let err = send_command_typed::<Value, _>(&path, "fail", None, timeout)
+            .await
+            .unwrap_err();
+        assert!(matches!(&err, CommandError::Handler(msg) if msg == "disk on fire"));
+
+        let err = send_command_typed::<String, _>(&path, "count", None, timeout)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, CommandError::BadResponse(_)));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_permission_denied() -> Result<(), Error> {
+    run(async {
+        // the real daemon only rejects peers which are neither root nor in its group
+        let path = socket_path("denied");
+        let listener = UnixListener::bind(&path)?;
+        tokio::spawn(async move {
+            while let Ok((mut conn, _)) = listener.accept().await {
+                let _ = conn.write_all(b"ERROR: permission denied\n").await;
+            }
+        });
+
+        let err = CommandClient::new(&path)
+            .send::<Value>("count", None)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, CommandError::PermissionDenied));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_timeout() -> Result<(), Error> {
+    run(async {
+        // a wedged daemon accepts connections but never answers
+        let path = socket_path("wedged");
+        let listener = UnixListener::bind(&path)?;
+        tokio::spawn(async move {
+            let mut connections = Vec::new();
+            while let Ok((conn, _)) = listener.accept().await {
+                connections.push(conn);
+            }
+        });
+
+        let timeout = Duration::from_millis(100);
+        let err = send_command_typed::<Value, _>(&path, "count", None, timeout)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, CommandError::Timeout(t) if t == timeout));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_retry_refused() -> Result<(), Error> {
+    run(async {
+        let path = socket_path("restarting");
+
+        let err = CommandClient::new(&path)
+            .send::<Value>("count", None)
+            .await
+            .unwrap_err();
+        match err {
+            CommandError::Io(err) => assert_eq!(err.kind(), std::io::ErrorKind::ConnectionRefused),
+            err => panic!("unexpected error {err:?}"),
+        }
+
+        // the daemon comes back while the client retries
+        let server_path = path.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            spawn_command_socket(&server_path).unwrap();
+        });
+        let count: u64 = CommandClient::new(&path)
+            .retry_refused(Duration::from_secs(10))
+            .send("count", None)
+            .await?;
+        assert_eq!(count, 3);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_path_from_pid_file() -> Result<(), Error> {
+    let pid_file =
+        std::env::temp_dir().join(format!("proxmox-daemon-test-{}.pid", std::process::id()));
+
+    std::fs::write(&pid_file, format!("{}\n", std::process::id()))?;
+    let path = path_from_pid_file(&pid_file);
+
+    // PIDs above the default pid_max of 2^22 are never in use
+    std::fs::write(&pid_file, "4194305\n")?;
+    let stale = path_from_pid_file(&pid_file);
+    std::fs::remove_file(&pid_file)?;
+
+    assert_eq!(path?, this_path());
+    assert!(stale.unwrap_err().to_string().contains("is not running"));
+    Ok(())
+}
@@ -0,0 +1,83 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Error;
+use nix::sys::signal::{raise, Signal};
+
+use proxmox_daemon::{
+    is_reload_requested, is_shutdown_requested, register_signal_handlers, SignalAction,
+    SignalConfig,
+};
+
+fn counter() -> (Arc<AtomicUsize>, SignalAction) {
+    let count = Arc::new(AtomicUsize::new(0));
+    let action = {
+        let count = Arc::clone(&count);
+        SignalAction::custom(move || {
+            count.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        })
+    };
+    (count, action)
+}
+
+/// Wait for the signal handler tasks to dispatch raised signals until `check` succeeds.
+async fn wait_for(check: impl Fn() -> bool) {
+    for _ in 0..500 {
+        if check() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("timeout waiting for signal dispatch");
+}
+
+#[test]
+fn test_signal_dispatch() -> Result<(), Error> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    runtime.block_on(async {
+        let (usr1_count, usr1) = counter();
+        let usr2_count = Arc::new(AtomicUsize::new(0));
+        let usr2 = {
+            let count = Arc::clone(&usr2_count);
+            SignalAction::custom(move || {
+                count.fetch_add(1, Ordering::SeqCst);
+                anyhow::bail!("errors of callbacks are only logged")
+            })
+        };
+        register_signal_handlers(SignalConfig {
+            usr1,
+            usr2,
+            // a repeated SIGTERM would exit the test
+            force_exit_within: None,
+            ..Default::default()
+        })?;
+
+        raise(Signal::SIGUSR1)?;
+        wait_for(|| usr1_count.load(Ordering::SeqCst) == 1).await;
+        raise(Signal::SIGUSR2)?;
+        raise(Signal::SIGUSR1)?;
+        wait_for(|| usr1_count.load(Ordering::SeqCst) == 2).await;
+        wait_for(|| usr2_count.load(Ordering::SeqCst) == 1).await;
+        assert!(!is_shutdown_requested());
+
+        raise(Signal::SIGHUP)?;
+        wait_for(is_reload_requested).await;
+        assert!(is_shutdown_requested());
+
+        // SIGTERM turns the pending reload into a shutdown
+        raise(Signal::SIGTERM)?;
+        wait_for(|| !is_reload_requested()).await;
+        assert!(is_shutdown_requested());
+
+        // handlers keep working after a shutdown request
+        raise(Signal::SIGTERM)?;
+        raise(Signal::SIGUSR1)?;
+        wait_for(|| usr1_count.load(Ordering::SeqCst) == 3).await;
+
+        Ok::<_, Error>(())
+    })
+}
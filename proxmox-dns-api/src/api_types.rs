@@ -1,7 +1,9 @@
 use serde::{Deserialize, Serialize};
 
 use proxmox_schema::api;
-use proxmox_schema::api_types::IP_FORMAT;
+use proxmox_schema::api_types::{
+    DNS_NAME_FORMAT, HOSTNAME_FORMAT, IP_FORMAT, SINGLE_LINE_COMMENT_FORMAT,
+};
 use proxmox_schema::Schema;
 use proxmox_schema::StringSchema;
 
@@ -92,3 +94,99 @@ pub enum DeletableResolvConfProperty {
     /// Delete third nameserver entry
     Dns3,
 }
+
+pub const HOSTNAME_SCHEMA: Schema = StringSchema::new("Static host name (a single DNS label).")
+    .format(&HOSTNAME_FORMAT)
+    .max_length(63)
+    .schema();
+
+pub const PRETTY_HOSTNAME_SCHEMA: Schema = StringSchema::new("Descriptive, free-form host name.")
+    .format(&SINGLE_LINE_COMMENT_FORMAT)
+    .max_length(255)
+    .schema();
+
+pub const HOST_DOMAIN_SCHEMA: Schema = StringSchema::new("Domain of the host.")
+    .format(&DNS_NAME_FORMAT)
+    .max_length(253)
+    .schema();
+
+pub const HOST_ADDRESS_SCHEMA: Schema =
+    StringSchema::new("Primary IP address of the host, as listed in '/etc/hosts'.")
+        .format(&IP_FORMAT)
+        .schema();
+
+#[api(
+    properties: {
+        hostname: {
+            schema: HOSTNAME_SCHEMA,
+        },
+        "pretty-hostname": {
+            schema: PRETTY_HOSTNAME_SCHEMA,
+            optional: true,
+        },
+        domain: {
+            schema: HOST_DOMAIN_SCHEMA,
+            optional: true,
+        },
+        address: {
+            schema: HOST_ADDRESS_SCHEMA,
+            optional: true,
+        },
+    }
+)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+/// Host name configuration from '/etc/hostname', '/etc/machine-info' and '/etc/hosts'.
+pub struct HostnameConfig {
+    pub hostname: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pretty_hostname: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub domain: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+}
+
+#[api(
+    properties: {
+        config: {
+            type: HostnameConfig,
+        },
+        digest: {
+            type: ConfigDigest,
+        },
+    }
+)]
+#[derive(Serialize, Deserialize)]
+/// Host name configuration with digest.
+pub struct HostnameConfigWithDigest {
+    #[serde(flatten)]
+    pub config: HostnameConfig,
+    pub digest: ConfigDigest,
+}
+
+#[api()]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// Deletable host name configuration property name
+pub enum DeletableHostnameProperty {
+    /// Delete the pretty host name
+    PrettyHostname,
+    /// Delete the domain from the '/etc/hosts' entry of the host
+    Domain,
+    /// Delete the '/etc/hosts' entry of the host, including the domain
+    Address,
+}
+
+#[api()]
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+/// Follow-up actions advisable after a host name update.
+pub struct HostnameUpdateResult {
+    /// The host name changed, running services and certificates may still use the old one, so a
+    /// reboot is recommended.
+    pub reboot_recommended: bool,
+    /// The host name, domain or address in '/etc/hosts' changed, services resolving the own
+    /// name should be restarted.
+    pub restart_services: bool,
+}
@@ -0,0 +1,561 @@
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock, Mutex};
+
+use anyhow::{bail, format_err, Error};
+use proxmox_config_digest::ConfigDigest;
+
+use proxmox_sys::fs::file_get_contents_limited;
+use proxmox_sys::fs::replace_file;
+use proxmox_sys::fs::CreateOptions;
+
+use super::DeletableHostnameProperty;
+use super::HostnameConfig;
+use super::HostnameConfigWithDigest;
+use super::HostnameUpdateResult;
+use super::{HOSTNAME_SCHEMA, HOST_ADDRESS_SCHEMA, HOST_DOMAIN_SCHEMA, PRETTY_HOSTNAME_SCHEMA};
+
+static HOSTNAME_FN: &str = "etc/hostname";
+static MACHINE_INFO_FN: &str = "etc/machine-info";
+static HOSTS_FN: &str = "etc/hosts";
+
+/// Like resolv.conf, these files are tiny, refuse to parse anything unreasonably large.
+const MAX_SIZE: u64 = 1024 * 1024;
+
+/// The raw contents of the files making up the host name configuration.
+struct HostnameFiles {
+    hostname: Vec<u8>,
+    machine_info: Option<Vec<u8>>,
+    hosts: Option<Vec<u8>>,
+}
+
+impl HostnameFiles {
+    fn read(root: &Path) -> Result<Self, Error> {
+        let read_optional = |name| {
+            let path = root.join(name);
+            if path.exists() {
+                file_get_contents_limited(path, MAX_SIZE).map(Some)
+            } else {
+                Ok(None)
+            }
+        };
+
+        Ok(Self {
+            hostname: file_get_contents_limited(root.join(HOSTNAME_FN), MAX_SIZE)?,
+            machine_info: read_optional(MACHINE_INFO_FN)?,
+            hosts: read_optional(HOSTS_FN)?,
+        })
+    }
+
+    fn digest(&self) -> ConfigDigest {
+        let mut data = self.hostname.clone();
+        for content in [&self.machine_info, &self.hosts] {
+            data.push(0);
+            if let Some(content) = content {
+                data.extend_from_slice(content);
+            }
+        }
+        ConfigDigest::from_slice(data)
+    }
+}
+
+/// An entry of '/etc/hosts', split into address and names.
+fn parse_hosts_line(line: &str) -> Option<(&str, Vec<&str>)> {
+    let data = match line.split_once('#') {
+        Some((data, _comment)) => data,
+        None => line,
+    };
+    let mut parts = data.split_whitespace();
+    let address = parts.next()?;
+    Some((address, parts.collect()))
+}
+
+/// Check whether an '/etc/hosts' entry lists the host name, either on its own or as FQDN.
+fn is_host_entry(names: &[&str], hostname: &str) -> bool {
+    names.iter().any(|name| {
+        *name == hostname
+            || name
+                .strip_prefix(hostname)
+                .is_some_and(|rest| rest.starts_with('.'))
+    })
+}
+
+fn parse_pretty_hostname(machine_info: &str) -> Option<String> {
+    let value = machine_info
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("PRETTY_HOSTNAME="))?;
+
+    let value = match value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+    {
+        Some(quoted) => {
+            let mut unescaped = String::new();
+            let mut chars = quoted.chars();
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => unescaped.extend(chars.next()),
+                    c => unescaped.push(c),
+                }
+            }
+            unescaped
+        }
+        None => value.to_string(),
+    };
+
+    (!value.is_empty()).then_some(value)
+}
+
+fn quote_pretty_hostname(pretty_hostname: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in pretty_hostname.chars() {
+        if matches!(c, '"' | '\\' | '$' | '`') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn parse_hostname_config(files: &HostnameFiles) -> Result<HostnameConfig, Error> {
+    let hostname = std::str::from_utf8(&files.hostname)?.trim().to_string();
+    if hostname.is_empty() {
+        bail!("no host name set in '/{HOSTNAME_FN}'");
+    }
+
+    let mut config = HostnameConfig {
+        hostname,
+        ..Default::default()
+    };
+
+    if let Some(machine_info) = &files.machine_info {
+        config.pretty_hostname = parse_pretty_hostname(std::str::from_utf8(machine_info)?);
+    }
+
+    if let Some(hosts) = &files.hosts {
+        let entry = std::str::from_utf8(hosts)?
+            .lines()
+            .filter_map(parse_hosts_line)
+            .find(|(_, names)| is_host_entry(names, &config.hostname));
+
+        if let Some((address, names)) = entry {
+            config.address = Some(address.to_string());
+            config.domain = names.iter().find_map(|name| {
+                name.strip_prefix(config.hostname.as_str())?
+                    .strip_prefix('.')
+                    .filter(|domain| !domain.is_empty())
+                    .map(str::to_string)
+            });
+        }
+    }
+
+    Ok(config)
+}
+
+/// Read the host name configuration below `root`.
+fn read_hostname_at(
+    root: &Path,
+    expected_digest: Option<&ConfigDigest>,
+) -> Result<HostnameConfigWithDigest, Error> {
+    let files = HostnameFiles::read(root)?;
+    let digest = files.digest();
+
+    digest.detect_modification(expected_digest)?;
+
+    let config = parse_hostname_config(&files)?;
+
+    Ok(HostnameConfigWithDigest { config, digest })
+}
+
+/// Read the host name configuration from '/etc/hostname', '/etc/machine-info' and '/etc/hosts'.
+///
+/// The address and domain are taken from the first '/etc/hosts' entry listing the host name.
+pub fn read_hostname(
+    expected_digest: Option<&ConfigDigest>,
+) -> Result<HostnameConfigWithDigest, Error> {
+    read_hostname_at(Path::new("/"), expected_digest)
+}
+
+fn verify_hostname_config(config: &HostnameConfig) -> Result<(), Error> {
+    let check = |schema: &proxmox_schema::Schema, name: &str, value: &str| {
+        schema
+            .unwrap_string_schema()
+            .check_constraints(value)
+            .map_err(|err| format_err!("invalid {name} '{value}' - {err}"))
+    };
+
+    check(&HOSTNAME_SCHEMA, "host name", &config.hostname)?;
+    if let Some(pretty_hostname) = &config.pretty_hostname {
+        check(&PRETTY_HOSTNAME_SCHEMA, "pretty host name", pretty_hostname)?;
+    }
+    if let Some(domain) = &config.domain {
+        check(&HOST_DOMAIN_SCHEMA, "domain", domain)?;
+        // 253 characters is the maximum length of a DNS name in text form
+        if config.hostname.len() + 1 + domain.len() > 253 {
+            bail!("fully qualified host name is too long");
+        }
+    }
+    if let Some(address) = &config.address {
+        check(&HOST_ADDRESS_SCHEMA, "address", address)?;
+    }
+
+    Ok(())
+}
+
+/// Rewrite the '/etc/hosts' entry of the host, preserving all other lines.
+///
+/// Only the host names are replaced, other aliases on the line are kept. Without address, the
+/// host names are removed from the line, and the line is removed if no alias is left. Returns
+/// `None` if there is neither an entry to update or remove nor an address to add one.
+fn update_hosts(hosts: &str, old_hostname: &str, config: &HostnameConfig) -> Option<String> {
+    let mut names = Vec::new();
+    if let Some(domain) = &config.domain {
+        names.push(format!("{}.{domain}", config.hostname));
+    }
+    names.push(config.hostname.clone());
+
+    let mut found = false;
+    let mut data = String::new();
+    for line in hosts.lines() {
+        match parse_hosts_line(line) {
+            Some((address, old_names)) if !found && is_host_entry(&old_names, old_hostname) => {
+                found = true;
+                let aliases: Vec<&str> = old_names
+                    .into_iter()
+                    .filter(|name| !is_host_entry(&[*name], old_hostname))
+                    .filter(|name| !names.iter().any(|new_name| new_name.as_str() == *name))
+                    .collect();
+                match &config.address {
+                    Some(address) => {
+                        data.push_str(address);
+                        for name in &names {
+                            data.push(' ');
+                            data.push_str(name);
+                        }
+                    }
+                    None if aliases.is_empty() => continue,
+                    None => data.push_str(address),
+                }
+                for alias in aliases {
+                    data.push(' ');
+                    data.push_str(alias);
+                }
+                if let Some((_, comment)) = line.split_once('#') {
+                    data.push_str(" #");
+                    data.push_str(comment);
+                }
+            }
+            _ => data.push_str(line),
+        }
+        data.push('\n');
+    }
+
+    if !found {
+        let address = config.address.as_ref()?;
+        data.push_str(address);
+        for name in &names {
+            data.push(' ');
+            data.push_str(name);
+        }
+        data.push('\n');
+    }
+
+    Some(data)
+}
+
+fn update_machine_info(machine_info: &str, pretty_hostname: Option<&str>) -> String {
+    let mut data = String::new();
+    for line in machine_info.lines() {
+        if !line.trim().starts_with("PRETTY_HOSTNAME=") {
+            data.push_str(line);
+            data.push('\n');
+        }
+    }
+    if let Some(pretty_hostname) = pretty_hostname {
+        data.push_str("PRETTY_HOSTNAME=");
+        data.push_str(&quote_pretty_hostname(pretty_hostname));
+        data.push('\n');
+    }
+    data
+}
+
+/// Update the host name configuration below `root`, without applying it to the running system.
+fn update_hostname_at(
+    root: &Path,
+    update: HostnameConfig,
+    delete: Option<Vec<DeletableHostnameProperty>>,
+    digest: Option<ConfigDigest>,
+) -> Result<HostnameUpdateResult, Error> {
+    static MUTEX: LazyLock<Arc<Mutex<()>>> = LazyLock::new(|| Arc::new(Mutex::new(())));
+
+    verify_hostname_config(&update)?;
+
+    let _guard = MUTEX.lock();
+
+    let files = HostnameFiles::read(root)?;
+    files.digest().detect_modification(digest.as_ref())?;
+    let current = parse_hostname_config(&files)?;
+
+    let mut config = current.clone();
+    config.hostname = update.hostname;
+
+    if let Some(delete) = delete {
+        for delete_prop in delete {
+            match delete_prop {
+                DeletableHostnameProperty::PrettyHostname => {
+                    config.pretty_hostname = None;
+                }
+                DeletableHostnameProperty::Domain => {
+                    config.domain = None;
+                }
+                DeletableHostnameProperty::Address => {
+                    config.address = None;
+                    config.domain = None;
+                }
+            }
+        }
+    }
+
+    if update.pretty_hostname.is_some() {
+        config.pretty_hostname = update.pretty_hostname;
+    }
+    if update.domain.is_some() {
+        config.domain = update.domain;
+    }
+    if update.address.is_some() {
+        config.address = update.address;
+    }
+
+    let path = |name: &str| -> PathBuf { root.join(name) };
+
+    // update the name resolution first, so the new name resolves once it is set
+    if config.hostname != current.hostname
+        || config.domain != current.domain
+        || config.address != current.address
+    {
+        let hosts = match &files.hosts {
+            Some(hosts) => std::str::from_utf8(hosts)?,
+            None => "",
+        };
+        if let Some(data) = update_hosts(hosts, &current.hostname, &config) {
+            replace_file(path(HOSTS_FN), data.as_bytes(), CreateOptions::new(), true)?;
+        }
+    }
+
+    if config.pretty_hostname != current.pretty_hostname {
+        let machine_info = match &files.machine_info {
+            Some(machine_info) => std::str::from_utf8(machine_info)?,
+            None => "",
+        };
+        let data = update_machine_info(machine_info, config.pretty_hostname.as_deref());
+        replace_file(
+            path(MACHINE_INFO_FN),
+            data.as_bytes(),
+            CreateOptions::new(),
+            true,
+        )?;
+    }
+
+    if config.hostname != current.hostname {
+        let data = format!("{}\n", config.hostname);
+        replace_file(
+            path(HOSTNAME_FN),
+            data.as_bytes(),
+            CreateOptions::new(),
+            true,
+        )?;
+    }
+
+    Ok(HostnameUpdateResult {
+        reboot_recommended: config.hostname != current.hostname,
+        restart_services: config.hostname != current.hostname
+            || config.domain != current.domain
+            || config.address != current.address,
+    })
+}
+
+/// Update the host name configuration and apply the new host name to the running system.
+///
+/// Optional values which are not set in `update` keep their current value, unless they are
+/// listed in `delete`. The '/etc/hosts' entry of the host is rewritten, added if an address is
+/// known or removed along with the address, preserving all other lines.
+pub fn update_hostname(
+    update: HostnameConfig,
+    delete: Option<Vec<DeletableHostnameProperty>>,
+    digest: Option<ConfigDigest>,
+) -> Result<HostnameUpdateResult, Error> {
+    let hostname = update.hostname.clone();
+    let result = update_hostname_at(Path::new("/"), update, delete, digest)?;
+
+    if result.reboot_recommended {
+        nix::unistd::sethostname(&hostname)
+            .map_err(|err| format_err!("unable to set host name to '{hostname}' - {err}"))?;
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod test {
+    use proxmox_sys::fs::TempDir;
+
+    use super::*;
+
+    const HOSTS: &str = "\
+127.0.0.1 localhost.localdomain localhost
+192.168.1.10 pve1.example.com pve1 # added by installer
+
+# IPv6
+::1     ip6-localhost ip6-loopback
+";
+
+    fn setup() -> Result<TempDir, Error> {
+        let root = TempDir::new_in("/tmp", None)?;
+        std::fs::create_dir(root.path().join("etc"))?;
+        std::fs::write(root.path().join(HOSTNAME_FN), "pve1\n")?;
+        std::fs::write(root.path().join(HOSTS_FN), HOSTS)?;
+        std::fs::write(
+            root.path().join(MACHINE_INFO_FN),
+            "PRETTY_HOSTNAME=\"Node \\\"one\\\"\"\nDEPLOYMENT=production\n",
+        )?;
+        Ok(root)
+    }
+
+    #[test]
+    fn test_read_hostname() -> Result<(), Error> {
+        let root = setup()?;
+
+        let HostnameConfigWithDigest { config, digest } = read_hostname_at(root.path(), None)?;
+        assert_eq!(
+            config,
+            HostnameConfig {
+                hostname: "pve1".to_string(),
+                pretty_hostname: Some("Node \"one\"".to_string()),
+                domain: Some("example.com".to_string()),
+                address: Some("192.168.1.10".to_string()),
+            }
+        );
+
+        assert!(read_hostname_at(root.path(), Some(&digest)).is_ok());
+        std::fs::write(root.path().join(HOSTNAME_FN), "pve2\n")?;
+        assert!(read_hostname_at(root.path(), Some(&digest)).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_update_hosts_keeps_aliases() {
+        let hosts = "127.0.0.1 localhost\n192.168.1.10 pve1.example.com pve1 backup # web\n";
+        let config = |address: Option<&str>| HostnameConfig {
+            hostname: "pve2".to_string(),
+            domain: Some("example.com".to_string()),
+            address: address.map(str::to_string),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            update_hosts(hosts, "pve1", &config(Some("10.0.0.1"))).as_deref(),
+            Some("127.0.0.1 localhost\n10.0.0.1 pve2.example.com pve2 backup # web\n"),
+        );
+
+        // without address only the host names are removed
+        assert_eq!(
+            update_hosts(hosts, "pve1", &config(None)).as_deref(),
+            Some("127.0.0.1 localhost\n192.168.1.10 backup # web\n"),
+        );
+    }
+
+    #[test]
+    fn test_update_hostname() -> Result<(), Error> {
+        let root = setup()?;
+        let digest = read_hostname_at(root.path(), None)?.digest;
+
+        let update = |hostname: &str| HostnameConfig {
+            hostname: hostname.to_string(),
+            ..Default::default()
+        };
+
+        assert!(update_hostname_at(root.path(), update("-pve"), None, None).is_err());
+        assert!(update_hostname_at(root.path(), update("pve.example"), None, None).is_err());
+        assert!(update_hostname_at(root.path(), update(&"a".repeat(64)), None, None).is_err());
+
+        let result = update_hostname_at(root.path(), update("pve2"), None, Some(digest.clone()))?;
+        assert!(result.reboot_recommended);
+        assert!(result.restart_services);
+
+        // the digest is outdated now
+        assert!(update_hostname_at(root.path(), update("pve3"), None, Some(digest)).is_err());
+
+        assert_eq!(
+            std::fs::read_to_string(root.path().join(HOSTNAME_FN))?,
+            "pve2\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(root.path().join(HOSTS_FN))?,
+            HOSTS.replace(
+                "192.168.1.10 pve1.example.com pve1 ",
+                "192.168.1.10 pve2.example.com pve2 ",
+            )
+        );
+
+        let result = update_hostname_at(
+            root.path(),
+            HostnameConfig {
+                address: Some("10.0.0.1".to_string()),
+                pretty_hostname: Some("Node two".to_string()),
+                ..update("pve2")
+            },
+            None,
+            None,
+        )?;
+        assert!(!result.reboot_recommended);
+        assert!(result.restart_services);
+
+        let config = read_hostname_at(root.path(), None)?.config;
+        assert_eq!(config.address.as_deref(), Some("10.0.0.1"));
+        assert_eq!(config.domain.as_deref(), Some("example.com"));
+        assert_eq!(config.pretty_hostname.as_deref(), Some("Node two"));
+        assert_eq!(
+            std::fs::read_to_string(root.path().join(MACHINE_INFO_FN))?,
+            "DEPLOYMENT=production\nPRETTY_HOSTNAME=\"Node two\"\n"
+        );
+
+        // optional values can be removed
+        let result = update_hostname_at(
+            root.path(),
+            update("pve2"),
+            Some(vec![
+                DeletableHostnameProperty::PrettyHostname,
+                DeletableHostnameProperty::Domain,
+            ]),
+            None,
+        )?;
+        assert!(!result.reboot_recommended);
+        assert!(result.restart_services);
+
+        let config = read_hostname_at(root.path(), None)?.config;
+        assert_eq!(config.address.as_deref(), Some("10.0.0.1"));
+        assert_eq!(config.domain, None);
+        assert_eq!(config.pretty_hostname, None);
+        assert_eq!(
+            std::fs::read_to_string(root.path().join(MACHINE_INFO_FN))?,
+            "DEPLOYMENT=production\n"
+        );
+
+        update_hostname_at(
+            root.path(),
+            update("pve2"),
+            Some(vec![DeletableHostnameProperty::Address]),
+            None,
+        )?;
+        assert_eq!(
+            std::fs::read_to_string(root.path().join(HOSTS_FN))?,
+            HOSTS.replace(
+                "192.168.1.10 pve1.example.com pve1 # added by installer\n",
+                ""
+            )
+        );
+
+        Ok(())
+    }
+}
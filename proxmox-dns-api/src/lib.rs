@@ -7,3 +7,8 @@ pub use api_types::*;
 mod resolv_conf;
 #[cfg(feature = "impl")]
 pub use resolv_conf::*;
+
+#[cfg(feature = "impl")]
+mod hostname;
+#[cfg(feature = "impl")]
+pub use hostname::*;
@@ -5,7 +5,7 @@ use const_format::concatcp;
 use proxmox_config_digest::ConfigDigest;
 use regex::Regex;
 
-use proxmox_sys::fs::file_get_contents;
+use proxmox_sys::fs::file_get_contents_limited;
 use proxmox_sys::fs::replace_file;
 use proxmox_sys::fs::CreateOptions;
 
@@ -17,6 +17,9 @@ use super::ResolvConfWithDigest;
 
 static RESOLV_CONF_FN: &str = "/etc/resolv.conf";
 
+/// resolv.conf files are tiny, refuse to parse anything unreasonably large.
+const RESOLV_CONF_MAX_SIZE: u64 = 64 * 1024;
+
 /// Read DNS configuration from '/etc/resolv.conf'.
 pub fn read_etc_resolv_conf(
     expected_digest: Option<&ConfigDigest>,
@@ -25,7 +28,7 @@ pub fn read_etc_resolv_conf(
 
     let mut nscount = 0;
 
-    let raw = file_get_contents(RESOLV_CONF_FN)?;
+    let raw = file_get_contents_limited(RESOLV_CONF_FN, RESOLV_CONF_MAX_SIZE)?;
     let digest = ConfigDigest::from_slice(&raw);
 
     digest.detect_modification(expected_digest)?;
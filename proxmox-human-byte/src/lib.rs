@@ -2,7 +2,9 @@
 
 use anyhow::{bail, Error};
 
-use proxmox_schema::{ApiStringFormat, ApiType, Schema, StringSchema, UpdaterType};
+use proxmox_schema::{
+    api_string_verifier, ApiStringFormat, ApiType, Schema, StringSchema, UpdaterType,
+};
 
 /// Size units for byte sizes
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -137,11 +139,16 @@ fn verify_human_byte(s: &str) -> Result<(), Error> {
         Err(err) => bail!("byte-size parse error for '{}': {}", s, err),
     }
 }
+
+api_string_verifier! {
+    HUMAN_BYTE_VERIFIER = "human-byte", verify_human_byte;
+}
+
 impl ApiType for HumanByte {
     const API_SCHEMA: Schema = StringSchema::new(
         "Byte size with optional unit (B, KB (base 10), MB, GB, ..., KiB (base 2), MiB, Gib, ...).",
     )
-    .format(&ApiStringFormat::VerifyFn(verify_human_byte))
+    .format(&ApiStringFormat::Verifier(&HUMAN_BYTE_VERIFIER))
     .min_length(1)
     .max_length(64)
     .schema();
@@ -1,4 +1,7 @@
 use std::io::Write;
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use anyhow::Error;
 use nix::fcntl::OFlag;
@@ -23,6 +26,146 @@ pub struct FileLogOptions {
     pub prefix_time: bool,
     /// File owner/group and mode
     pub file_opts: CreateOptions,
+    /// Compress the file, see [`LogCompression`].
+    pub compression: Option<LogCompression>,
+    /// The uncompressed size of a compression frame, 64 KiB by default.
+    pub compression_frame_size: Option<usize>,
+    /// The time after which a started compression frame is written even if it is not full,
+    /// 10 seconds by default.
+    pub compression_max_delay: Option<Duration>,
+}
+
+const DEFAULT_FRAME_SIZE: usize = 64 * 1024;
+const DEFAULT_MAX_DELAY: Duration = Duration::from_secs(10);
+/// The number of frames buffered while writing fails, before the buffered data is dropped.
+const MAX_PENDING_FRAMES: usize = 16;
+
+/// The compression of a log file.
+///
+/// Logged messages are collected and written as a complete gzip member or zstd frame once the
+/// [frame size](FileLogOptions::compression_frame_size) is reached or the first message of the
+/// frame is older than the [maximum delay](FileLogOptions::compression_max_delay), and when the
+/// logger is flushed, reopened or dropped. The resulting file is a concatenation of frames which
+/// `zcat` or `zstdcat` decompress as a whole, a crash loses at most the frame not written yet.
+/// If writing fails, the data is kept for the next frame, up to 16 frames, then it is dropped.
+///
+/// The delay is checked when logging, use [`FileLogger::spawn_flusher`] to also write frames of
+/// loggers which are idle, or which live until the process exits and are never dropped.
+///
+/// Since a frame is written with a single write, files opened in append mode can still be
+/// shared between processes. Log rotation must not compress such files again.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogCompression {
+    Gzip,
+    Zstd,
+}
+
+impl LogCompression {
+    fn compress(self, data: &[u8]) -> Result<Vec<u8>, std::io::Error> {
+        match self {
+            LogCompression::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+            LogCompression::Zstd => zstd::stream::encode_all(data, 0),
+        }
+    }
+}
+
+struct Compressor {
+    compression: LogCompression,
+    frame_size: usize,
+    max_delay: Duration,
+    pending: Vec<u8>,
+    /// When the first byte of the pending data was buffered.
+    pending_since: Option<Instant>,
+}
+
+impl Compressor {
+    /// Buffer `data`, there is no error to report.
+    ///
+    /// Errors writing a frame are reported by [`finish_frame`](Self::finish_frame) when the
+    /// logger is flushed, the data is kept and written with the next frame. If writing still
+    /// fails once [`MAX_PENDING_FRAMES`] frames are buffered, the buffered data is dropped.
+    fn write(&mut self, file: &mut std::fs::File, data: &[u8]) {
+        if self.pending.is_empty() {
+            self.pending_since = Some(Instant::now());
+        }
+        self.pending.extend_from_slice(data);
+        if self.pending.len() >= self.frame_size {
+            if self.finish_frame(file).is_err()
+                && self.pending.len() >= self.frame_size * MAX_PENDING_FRAMES
+            {
+                self.pending = Vec::new();
+                self.pending_since = None;
+            }
+        } else {
+            let _ = self.finish_due_frame(file);
+        }
+    }
+
+    /// Write the pending data if it was buffered longer than the maximum delay.
+    fn finish_due_frame(&mut self, file: &mut std::fs::File) -> Result<(), std::io::Error> {
+        match self.pending_since {
+            Some(since) if since.elapsed() >= self.max_delay => self.finish_frame(file),
+            _ => Ok(()),
+        }
+    }
+
+    /// Write the pending data as a frame, it is kept on errors to be written with the next one.
+    fn finish_frame(&mut self, file: &mut std::fs::File) -> Result<(), std::io::Error> {
+        if !self.pending.is_empty() {
+            file.write_all(&self.compression.compress(&self.pending)?)?;
+            self.pending.clear();
+        }
+        self.pending_since = None;
+        Ok(())
+    }
+}
+
+/// Receives a copy of each message logged with [`FileLogger::log`], e.g. to forward task logs.
+pub trait LogMirror: Send {
+    /// Called after `msg` was written to the file, with the number of its first line in the
+    /// file, counting the lines logged by this [`FileLogger`] from 1.
+    fn mirror(&mut self, line: u64, msg: &str);
+}
+
+/// Receives the lines logged with [`FileLogger::log`] in a thread of its own, e.g. to forward
+/// them to syslog or a remote collector, see [`FileLogger::set_sink`].
+pub trait LogSink: Send + Sync {
+    /// Called with each logged line as written to the file, without the trailing newline.
+    fn send(&self, line: &str);
+}
+
+struct SinkQueue {
+    sender: SyncSender<String>,
+    dropped: u64,
+    /// Lines dropped since the last notice sent to the sink.
+    unreported: u64,
+}
+
+impl SinkQueue {
+    fn send(&mut self, line: &str) {
+        if self.unreported > 0 {
+            let notice = format!("dropped {} lines", self.unreported);
+            match self.sender.try_send(notice) {
+                Ok(()) => self.unreported = 0,
+                Err(TrySendError::Full(_)) => {
+                    self.dropped += 1;
+                    self.unreported += 1;
+                    return;
+                }
+                Err(TrySendError::Disconnected(_)) => return,
+            }
+        }
+
+        if let Err(TrySendError::Full(_)) = self.sender.try_send(line.to_string()) {
+            self.dropped += 1;
+            self.unreported += 1;
+        }
+    }
 }
 
 /// Log messages with optional automatically added timestamps into files
@@ -46,6 +189,10 @@ pub struct FileLogger {
     file: std::fs::File,
     file_name: std::path::PathBuf,
     options: FileLogOptions,
+    lines: u64,
+    mirror: Option<Box<dyn LogMirror>>,
+    compressor: Option<Compressor>,
+    sink: Option<SinkQueue>,
 }
 
 impl FileLogger {
@@ -59,20 +206,108 @@ impl FileLogger {
 
         let file_name: std::path::PathBuf = file_name.as_ref().to_path_buf();
 
+        let compressor = options.compression.map(|compression| Compressor {
+            compression,
+            frame_size: options.compression_frame_size.unwrap_or(DEFAULT_FRAME_SIZE),
+            max_delay: options.compression_max_delay.unwrap_or(DEFAULT_MAX_DELAY),
+            pending: Vec::new(),
+            pending_since: None,
+        });
+
         Ok(Self {
             file,
             file_name,
             options,
+            lines: 0,
+            mirror: None,
+            compressor,
+            sink: None,
         })
     }
 
+    /// Mirror all messages logged from now on with [`log`](Self::log).
+    ///
+    /// The file stays authoritative, data written with the [`Write`](std::io::Write)
+    /// implementation is not mirrored.
+    pub fn set_mirror(&mut self, mirror: Box<dyn LogMirror>) {
+        self.mirror = Some(mirror);
+    }
+
+    /// Send all lines logged from now on with [`log`](Self::log) to `sink`.
+    ///
+    /// The lines are queued for a thread calling the sink, so a slow sink does not block logging.
+    /// If more than `queue_len` lines are pending, further lines are dropped and counted, see
+    /// [`dropped_sink_lines`](Self::dropped_sink_lines). Once the queue has room again, the sink
+    /// receives a `dropped <count> lines` notice first.
+    pub fn set_sink(&mut self, sink: Box<dyn LogSink>, queue_len: usize) -> Result<(), Error> {
+        let (sender, receiver) = mpsc::sync_channel::<String>(queue_len);
+        std::thread::Builder::new()
+            .name("log-sink".to_string())
+            .spawn(move || {
+                for line in receiver {
+                    sink.send(&line);
+                }
+            })?;
+        self.sink = Some(SinkQueue {
+            sender,
+            dropped: 0,
+            unreported: 0,
+        });
+        Ok(())
+    }
+
+    /// The number of lines dropped because the queue of the sink was full.
+    pub fn dropped_sink_lines(&self) -> u64 {
+        self.sink.as_ref().map_or(0, |sink| sink.dropped)
+    }
+
     /// Reopen logfile.
+    ///
+    /// The current compression frame is finished in the old file first, so rotating the file
+    /// and reopening it afterwards leaves complete frames in both files.
     pub fn reopen(&mut self) -> Result<&Self, Error> {
+        if let Some(compressor) = self.compressor.as_mut() {
+            // if this fails the data is written to the new file instead
+            let _ = compressor.finish_frame(&mut self.file);
+        }
         let file = Self::open(&self.file_name, &self.options)?;
         self.file = file;
         Ok(self)
     }
 
+    /// Returns a function reopening a shared logfile, e.g. for a `SIGUSR1` handler reopening
+    /// rotated logfiles.
+    pub fn reopener(
+        logger: &Arc<Mutex<Self>>,
+    ) -> impl Fn() -> Result<(), Error> + Send + Sync + 'static {
+        let logger = Arc::clone(logger);
+        move || {
+            logger.lock().unwrap().reopen()?;
+            Ok(())
+        }
+    }
+
+    /// Spawn a thread writing the pending compression frame of a shared logfile once it is due,
+    /// see [`LogCompression`]. The thread checks every `interval` and exits once the logger is
+    /// dropped.
+    pub fn spawn_flusher(logger: &Arc<Mutex<Self>>, interval: Duration) -> Result<(), Error> {
+        let logger = Arc::downgrade(logger);
+        std::thread::Builder::new()
+            .name("log-flusher".to_string())
+            .spawn(move || loop {
+                std::thread::sleep(interval);
+                let Some(logger) = logger.upgrade() else {
+                    break;
+                };
+                let mut logger = logger.lock().unwrap();
+                let logger = &mut *logger;
+                if let Some(compressor) = logger.compressor.as_mut() {
+                    let _ = compressor.finish_due_frame(&mut logger.file);
+                }
+            })?;
+        Ok(())
+    }
+
     fn open<P: AsRef<std::path::Path>>(
         file_name: P,
         options: &FileLogOptions,
@@ -122,7 +357,21 @@ impl FileLogger {
         // Note: we ignore the potential error here because log methods
         // shouldn't panic. We also can't log an error, because that
         // would lead to recursion.
-        let _ = self.file.write_all(line.as_bytes());
+        match self.compressor.as_mut() {
+            Some(compressor) => compressor.write(&mut self.file, line.as_bytes()),
+            None => {
+                let _ = self.file.write_all(line.as_bytes());
+            }
+        }
+
+        let first_line = self.lines + 1;
+        self.lines += 1 + msg.matches('\n').count() as u64;
+        if let Some(mirror) = self.mirror.as_mut() {
+            mirror.mirror(first_line, msg);
+        }
+        if let Some(sink) = self.sink.as_mut() {
+            sink.send(line.strip_suffix('\n').unwrap_or(&line));
+        }
     }
 }
 
@@ -131,13 +380,30 @@ impl std::io::Write for FileLogger {
         if self.options.to_stdout {
             let _ = std::io::stdout().write(buf);
         }
-        self.file.write(buf)
+        match self.compressor.as_mut() {
+            Some(compressor) => {
+                compressor.write(&mut self.file, buf);
+                Ok(buf.len())
+            }
+            None => self.file.write(buf),
+        }
     }
 
     fn flush(&mut self) -> Result<(), std::io::Error> {
         if self.options.to_stdout {
             let _ = std::io::stdout().flush();
         }
+        if let Some(compressor) = self.compressor.as_mut() {
+            compressor.finish_frame(&mut self.file)?;
+        }
         self.file.flush()
     }
 }
+
+impl Drop for FileLogger {
+    fn drop(&mut self) {
+        if let Some(compressor) = self.compressor.as_mut() {
+            let _ = compressor.finish_frame(&mut self.file);
+        }
+    }
+}
@@ -13,7 +13,10 @@ use tracing_subscriber::prelude::*;
 use tasklog_layer::TasklogLayer;
 
 mod file_logger;
-pub use file_logger::{FileLogOptions, FileLogger};
+pub use file_logger::{FileLogOptions, FileLogger, LogCompression, LogMirror, LogSink};
+
+mod log_level;
+pub use log_level::{init_daemon_logger, LogLevelControl, LogOutput};
 
 mod tasklog_layer;
 
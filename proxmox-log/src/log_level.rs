@@ -0,0 +1,186 @@
+use std::env;
+use std::os::unix::io::AsRawFd;
+use std::sync::{Mutex, OnceLock};
+
+use anyhow::{bail, format_err, Error};
+use tracing_log::{AsLog, LogTracer};
+use tracing_subscriber::filter::{filter_fn, LevelFilter, Targets};
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::{reload, Registry};
+
+use crate::tasklog_layer::TasklogLayer;
+use crate::{journald_or_stderr_layer, plain_stderr_layer, Level, LogContext};
+
+static LOG_LEVEL_CONTROL: OnceLock<LogLevelControl> = OnceLock::new();
+
+static INIT_LOCK: Mutex<()> = Mutex::new(());
+
+/// The name of the reload state keeping changed directives across a reload of the daemon.
+#[cfg(feature = "command-socket")]
+const LOG_LEVEL_STATE: &str = "PROXMOX_LOG_LEVEL_DIRECTIVES";
+
+/// The output of the daemon logger, see [`init_daemon_logger`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogOutput {
+    /// The journal if stderr is connected to it, e.g. when started by systemd, stderr otherwise.
+    #[default]
+    Auto,
+    /// The journal, or stderr if the journal is not available.
+    Journald,
+    /// Plain messages on stderr.
+    Stderr,
+}
+
+impl LogOutput {
+    /// Check whether stderr is the stream systemd set up to the journal, see `systemd.exec(5)`.
+    fn stderr_is_journal() -> bool {
+        let Ok(stream) = env::var("JOURNAL_STREAM") else {
+            return false;
+        };
+        let Some((dev, ino)) = stream.split_once(':') else {
+            return false;
+        };
+        match nix::sys::stat::fstat(std::io::stderr().as_raw_fd()) {
+            Ok(stat) => {
+                dev.parse().ok() == Some(stat.st_dev) && ino.parse().ok() == Some(stat.st_ino)
+            }
+            Err(_) => false,
+        }
+    }
+}
+
+fn parse_directives(directives: &str) -> Result<Targets, Error> {
+    if directives.trim().is_empty() {
+        bail!("no log level directives given");
+    }
+    directives
+        .parse()
+        .map_err(|err| format_err!("invalid log level directives '{directives}' - {err}"))
+}
+
+/// Changes the log level directives of the daemon logger at runtime.
+///
+/// Directives are a comma separated list of a default level and levels per module, e.g.
+/// `info,proxmox_rest_server=debug`. With the `command-socket` feature, the current directives
+/// are passed on to the new process on a reload of the daemon, so changes are kept across a
+/// reload.
+pub struct LogLevelControl {
+    handle: reload::Handle<Targets, Registry>,
+    directives: Mutex<String>,
+}
+
+impl LogLevelControl {
+    /// Get the control of the daemon logger, if it was set up.
+    pub fn get() -> Option<&'static Self> {
+        LOG_LEVEL_CONTROL.get()
+    }
+
+    /// The current log level directives.
+    pub fn directives(&self) -> String {
+        self.directives.lock().unwrap().clone()
+    }
+
+    /// Replace the log level directives.
+    pub fn set_directives(&self, directives: &str) -> Result<(), Error> {
+        let targets = parse_directives(directives)?;
+
+        let mut current = self.directives.lock().unwrap();
+        self.handle
+            .reload(targets)
+            .map_err(|err| format_err!("unable to change log level - {err}"))?;
+        // messages of the `log` crate are filtered before reaching tracing
+        tracing_log::log::set_max_level(LevelFilter::current().as_log());
+        *current = directives.to_string();
+        drop(current);
+
+        tracing::info!("changed log level directives to '{directives}'");
+        Ok(())
+    }
+
+    /// Register the `set-log-level` command, which replaces the directives if the `directives`
+    /// parameter is given and returns the current directives.
+    #[cfg(feature = "command-socket")]
+    pub fn register_command(
+        &'static self,
+        commando_sock: &mut proxmox_daemon::command_socket::CommandSocket,
+    ) -> Result<(), Error> {
+        commando_sock.register_command("set-log-level".into(), move |args| {
+            if let Some(directives) = args.and_then(|args| args.get("directives")) {
+                let directives = directives
+                    .as_str()
+                    .ok_or_else(|| format_err!("'directives' must be a string"))?;
+                self.set_directives(directives)?;
+            }
+            Ok(serde_json::Value::String(self.directives()))
+        })
+    }
+}
+
+/// Initialize the logger for daemons, with log level directives which can be changed at runtime.
+///
+/// The directives are read from the environment variable `env_var_name`, falling back to
+/// `default_directives`, unless they were passed on by the previous process on a reload, see
+/// [`LogLevelControl`]. Messages logged in a [`LogContext`] only go to its task log, except for
+/// errors.
+///
+/// Calling this again, e.g. in a forked worker, returns the already set up control, so it is
+/// safe to call from every entry point of a daemon.
+pub fn init_daemon_logger(
+    env_var_name: &str,
+    default_directives: &str,
+    output: LogOutput,
+) -> Result<&'static LogLevelControl, Error> {
+    let _guard = INIT_LOCK.lock().unwrap();
+    if let Some(control) = LOG_LEVEL_CONTROL.get() {
+        return Ok(control);
+    }
+
+    let mut directives = default_directives.to_string();
+    if let Ok(v) = env::var(env_var_name) {
+        match parse_directives(&v) {
+            Ok(_) => directives = v,
+            Err(e) => eprintln!("env variable {env_var_name} found, but parsing failed: {e}"),
+        }
+    }
+    #[cfg(feature = "command-socket")]
+    match proxmox_daemon::server::take_reload_state(LOG_LEVEL_STATE) {
+        Ok(Some(v)) => match parse_directives(&v) {
+            Ok(_) => directives = v,
+            Err(e) => eprintln!("log level directives passed on, but parsing failed: {e}"),
+        },
+        Ok(None) => (),
+        Err(e) => eprintln!("unable to restore log level directives - {e}"),
+    }
+    let targets = parse_directives(&directives)?;
+
+    let output_layer = match output {
+        LogOutput::Auto if LogOutput::stderr_is_journal() => journald_or_stderr_layer(),
+        LogOutput::Journald => journald_or_stderr_layer(),
+        LogOutput::Auto | LogOutput::Stderr => plain_stderr_layer().boxed(),
+    };
+
+    let (filter, handle) = reload::Layer::new(targets);
+    let registry = tracing_subscriber::registry()
+        .with(filter)
+        .with(output_layer.with_filter(filter_fn(|metadata| {
+            !LogContext::exists() || *metadata.level() >= Level::ERROR
+        })))
+        .with(TasklogLayer {});
+
+    tracing::subscriber::set_global_default(registry)?;
+    LogTracer::init()?;
+    tracing_log::log::set_max_level(LevelFilter::current().as_log());
+
+    let control = LOG_LEVEL_CONTROL.get_or_init(|| LogLevelControl {
+        handle,
+        directives: Mutex::new(directives),
+    });
+
+    #[cfg(feature = "command-socket")]
+    proxmox_daemon::server::register_reload_state(
+        LOG_LEVEL_STATE,
+        move || Ok(control.directives()),
+    );
+
+    Ok(control)
+}
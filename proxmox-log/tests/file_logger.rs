@@ -0,0 +1,143 @@
+use std::io::Read;
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Error;
+
+use proxmox_log::{FileLogOptions, FileLogger, LogCompression, LogSink};
+use proxmox_sys::fs::TempDir;
+
+fn decompress(compression: LogCompression, path: &Path) -> Result<String, Error> {
+    let file = std::fs::File::open(path)?;
+    let mut data = String::new();
+    match compression {
+        LogCompression::Gzip => {
+            flate2::read::MultiGzDecoder::new(file).read_to_string(&mut data)?
+        }
+        LogCompression::Zstd => zstd::stream::Decoder::new(file)?.read_to_string(&mut data)?,
+    };
+    Ok(data)
+}
+
+#[test]
+fn test_compressed_log() -> Result<(), Error> {
+    for compression in [LogCompression::Gzip, LogCompression::Zstd] {
+        let dir = TempDir::new_in("/tmp", None)?;
+        let path = dir.path().join("access.log");
+        let rotated = dir.path().join("access.log.1");
+
+        let options = FileLogOptions {
+            append: true,
+            compression: Some(compression),
+            compression_frame_size: Some(100),
+            ..Default::default()
+        };
+        let mut logger = FileLogger::new(&path, options)?;
+
+        let lines: Vec<String> = (0..50).map(|i| format!("request number {i}")).collect();
+        for line in &lines[..30] {
+            logger.log(line);
+        }
+        // rotate, the pending frame is finished in the old file
+        std::fs::rename(&path, &rotated)?;
+        logger.reopen()?;
+        for line in &lines[30..] {
+            logger.log(line);
+        }
+        drop(logger);
+
+        let old = decompress(compression, &rotated)?;
+        let new = decompress(compression, &path)?;
+        assert_eq!(old, format!("{}\n", lines[..30].join("\n")));
+        assert_eq!(new, format!("{}\n", lines[30..].join("\n")));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_compressed_log_delay() -> Result<(), Error> {
+    let dir = TempDir::new_in("/tmp", None)?;
+    let path = dir.path().join("access.log");
+
+    let options = FileLogOptions {
+        compression: Some(LogCompression::Zstd),
+        compression_max_delay: Some(Duration::from_millis(50)),
+        ..Default::default()
+    };
+    let logger = Arc::new(Mutex::new(FileLogger::new(&path, options)?));
+    logger.lock().unwrap().log("first");
+    assert_eq!(std::fs::metadata(&path)?.len(), 0);
+
+    // the idle logger's frame gets written without further messages or dropping it
+    FileLogger::spawn_flusher(&logger, Duration::from_millis(10))?;
+    let mut waited = Duration::ZERO;
+    while std::fs::metadata(&path)?.len() == 0 {
+        assert!(waited < Duration::from_secs(10), "frame not written");
+        std::thread::sleep(Duration::from_millis(10));
+        waited += Duration::from_millis(10);
+    }
+    assert_eq!(decompress(LogCompression::Zstd, &path)?, "first\n");
+
+    drop(logger);
+    assert_eq!(decompress(LogCompression::Zstd, &path)?, "first\n");
+    Ok(())
+}
+
+/// Forwards the lines to the test, blocking on the gate before returning from the first one.
+struct TestSink {
+    lines: Sender<String>,
+    gate: Mutex<Option<Receiver<()>>>,
+}
+
+impl LogSink for TestSink {
+    fn send(&self, line: &str) {
+        let _ = self.lines.send(line.to_string());
+        if let Some(gate) = self.gate.lock().unwrap().take() {
+            let _ = gate.recv();
+        }
+    }
+}
+
+#[test]
+fn test_log_sink() -> Result<(), Error> {
+    let dir = TempDir::new_in("/tmp", None)?;
+    let (lines_tx, lines) = mpsc::channel();
+    let (gate, gate_rx) = mpsc::channel();
+
+    let mut logger = FileLogger::new(dir.path().join("auth.log"), FileLogOptions::default())?;
+    let sink = TestSink {
+        lines: lines_tx,
+        gate: Mutex::new(Some(gate_rx)),
+    };
+    logger.set_sink(Box::new(sink), 2)?;
+
+    logger.log("line 1");
+    // the sink is now blocked with the first line
+    assert_eq!(lines.recv()?, "line 1");
+
+    for i in 2..=10 {
+        logger.log(format!("line {i}"));
+    }
+    assert_eq!(logger.dropped_sink_lines(), 7);
+
+    gate.send(())?;
+    assert_eq!(lines.recv()?, "line 2");
+    assert_eq!(lines.recv()?, "line 3");
+
+    logger.log("line 11");
+    assert_eq!(lines.recv()?, "dropped 7 lines");
+    assert_eq!(lines.recv()?, "line 11");
+    assert_eq!(logger.dropped_sink_lines(), 7);
+
+    // the sink thread ends with the logger
+    drop(logger);
+    assert!(lines.recv().is_err());
+
+    let file = std::fs::read_to_string(dir.path().join("auth.log"))?;
+    assert_eq!(file.lines().count(), 11);
+
+    Ok(())
+}
@@ -0,0 +1,52 @@
+use anyhow::Error;
+
+use proxmox_log::{
+    debug, init_daemon_logger, FileLogOptions, FileLogger, LogContext, LogLevelControl, LogOutput,
+};
+use proxmox_sys::fs::TempDir;
+
+const ENV_VAR: &str = "PROXMOX_LOG_LEVEL_TEST";
+
+#[test]
+fn test_runtime_log_level() -> Result<(), Error> {
+    std::env::set_var(ENV_VAR, "info,log_level=warn");
+    let control = init_daemon_logger(ENV_VAR, "info", LogOutput::Stderr)?;
+    assert_eq!(control.directives(), "info,log_level=warn");
+
+    // setting up again returns the same control
+    let again = init_daemon_logger(ENV_VAR, "debug", LogOutput::Stderr)?;
+    assert!(std::ptr::eq(control, again));
+    assert!(std::ptr::eq(control, LogLevelControl::get().unwrap()));
+
+    let dir = TempDir::new_in("/tmp", None)?;
+    let path = dir.path().join("task.log");
+    let logger = FileLogger::new(&path, FileLogOptions::default())?;
+    let context = LogContext::new(logger);
+
+    context.clone().sync_scope(|| {
+        debug!("suppressed tracing message");
+        tracing_log::log::debug!("suppressed log message");
+    });
+
+    assert!(control.set_directives("").is_err());
+    assert!(control.set_directives("info,log_level=bogus").is_err());
+    assert_eq!(control.directives(), "info,log_level=warn");
+
+    control.set_directives("warn,log_level=debug")?;
+    assert_eq!(control.directives(), "warn,log_level=debug");
+    // the environment is left alone, changed directives are passed on at a reload instead
+    assert_eq!(std::env::var(ENV_VAR)?, "info,log_level=warn");
+
+    context.sync_scope(|| {
+        debug!("emitted tracing message");
+        tracing_log::log::debug!("emitted log message");
+    });
+
+    let log = std::fs::read_to_string(&path)?;
+    assert_eq!(
+        log,
+        "DEBUG: emitted tracing message\nDEBUG: emitted log message\n"
+    );
+
+    Ok(())
+}
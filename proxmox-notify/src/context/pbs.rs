@@ -23,6 +23,9 @@ const DUMMY_USER_SCHEMA: ObjectSchema = ObjectSchema {
     ],
     additional_properties: true,
     default_key: None,
+    property_weights: &[],
+    property_requires: &[],
+    property_conflicts: &[],
 };
 
 #[derive(Deserialize)]
@@ -8,7 +8,9 @@ use regex::Regex;
 use serde::{Deserialize, Serialize};
 
 use proxmox_schema::api_types::{COMMENT_SCHEMA, SAFE_ID_REGEX_STR};
-use proxmox_schema::{api, const_regex, ApiStringFormat, Schema, StringSchema, Updater};
+use proxmox_schema::{
+    api, api_string_verifier, const_regex, ApiStringFormat, Schema, StringSchema, Updater,
+};
 use proxmox_time::{parse_daily_duration, DailyDuration};
 
 use crate::schema::ENTITY_NAME_SCHEMA;
@@ -49,8 +51,12 @@ const_regex! {
     pub MATCH_FIELD_ENTRY_REGEX = concatcp!(r"^(?:(exact|regex):)?(", SAFE_ID_REGEX_STR, r")=(.*)$");
 }
 
+api_string_verifier! {
+    MATCH_FIELD_ENTRY_VERIFIER = "notification-field-matcher", verify_field_matcher;
+}
+
 pub const MATCH_FIELD_ENTRY_FORMAT: ApiStringFormat =
-    ApiStringFormat::VerifyFn(verify_field_matcher);
+    ApiStringFormat::Verifier(&MATCH_FIELD_ENTRY_VERIFIER);
 
 fn verify_field_matcher(s: &str) -> Result<(), anyhow::Error> {
     let _: FieldMatcher = s.parse()?;
@@ -5,6 +5,7 @@ use std::path::PathBuf;
 use std::pin::Pin;
 use std::sync::{Arc, Mutex};
 use std::task::{Context, Poll};
+use std::time::Duration;
 
 use anyhow::{format_err, Error};
 use http::{HeaderMap, Method, Uri};
@@ -13,12 +14,21 @@ use hyper::{Body, Response};
 use tower_service::Service;
 
 use proxmox_daemon::command_socket::CommandSocket;
-use proxmox_log::{FileLogOptions, FileLogger};
-use proxmox_router::{Router, RpcEnvironmentType, UserInformation};
+use proxmox_log::{FileLogOptions, FileLogger, LogCompression, LogSink};
+use proxmox_router::{ApiMethod, Router, RpcEnvironmentType, UserInformation};
 use proxmox_sys::fs::{create_path, CreateOptions};
 
+use crate::audit::Auditor;
+use crate::formatter::{OutputFormat, OutputFormats, OutputFormatter};
 use crate::rest::Handler;
-use crate::RestEnvironment;
+use crate::{
+    AccessLogFormat, AuditHook, BodyRateLimiter, CookiePolicy, CsrfProtection, DefaultHeaders,
+    HandlerTimeouts, HealthOptions, Maintenance, ReplayProtection, ResponseCache, RestEnvironment,
+    TicketRenewal, TrustedProxies,
+};
+
+/// How often compressed access and auth logs are checked for a due frame.
+const LOG_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
 
 /// REST server configuration
 pub struct ApiConfig {
@@ -26,10 +36,29 @@ pub struct ApiConfig {
     aliases: HashMap<String, PathBuf>,
     env_type: RpcEnvironmentType,
     request_log: Option<Arc<Mutex<FileLogger>>>,
+    access_log_format: AccessLogFormat,
     auth_log: Option<Arc<Mutex<FileLogger>>>,
+    log_compression: Option<LogCompression>,
+    access_log_sink: Option<(Box<dyn LogSink>, usize)>,
+    auth_log_sink: Option<(Box<dyn LogSink>, usize)>,
     handlers: Vec<Handler>,
     auth_handler: Option<AuthHandler>,
     index_handler: Option<IndexHandler>,
+    auth_cookie_policy: Option<CookiePolicy>,
+    csrf_protection: Option<Arc<CsrfProtection>>,
+    response_cache: Option<Arc<ResponseCache>>,
+    default_headers: Option<DefaultHeaders>,
+    output_formats: OutputFormats,
+    self_description: Option<&'static [(&'static str, u64)]>,
+    audit_hook: Option<Box<dyn AuditHook>>,
+    strict_audit: bool,
+    health: Option<HealthOptions>,
+    body_rate_limiter: Option<Arc<BodyRateLimiter>>,
+    maintenance: Option<Arc<Maintenance>>,
+    replay_protection: Option<Arc<ReplayProtection>>,
+    ticket_renewal: Option<TicketRenewal>,
+    handler_timeouts: Option<HandlerTimeouts>,
+    trusted_proxies: Option<TrustedProxies>,
     pub(crate) privileged_addr: Option<PrivilegedAddr>,
 
     #[cfg(feature = "templates")]
@@ -55,10 +84,29 @@ impl ApiConfig {
             aliases: HashMap::new(),
             env_type,
             request_log: None,
+            access_log_format: AccessLogFormat::default(),
             auth_log: None,
+            log_compression: None,
+            access_log_sink: None,
+            auth_log_sink: None,
             handlers: Vec::new(),
             auth_handler: None,
             index_handler: None,
+            auth_cookie_policy: None,
+            csrf_protection: None,
+            response_cache: None,
+            default_headers: None,
+            output_formats: OutputFormats::default(),
+            self_description: None,
+            audit_hook: None,
+            strict_audit: false,
+            health: None,
+            body_rate_limiter: None,
+            maintenance: None,
+            replay_protection: None,
+            ticket_renewal: None,
+            handler_timeouts: None,
+            trusted_proxies: None,
             privileged_addr: None,
 
             #[cfg(feature = "templates")]
@@ -80,6 +128,270 @@ impl ApiConfig {
         self.auth_handler(AuthHandler::from_fn(func))
     }
 
+    /// Set the attributes of the authentication cookie.
+    ///
+    /// Fails if the policy contains an invalid attribute combination, see
+    /// [`CookiePolicy::validate`].
+    pub fn auth_cookie_policy(mut self, policy: CookiePolicy) -> Result<Self, Error> {
+        policy.validate()?;
+        self.auth_cookie_policy = Some(policy);
+        Ok(self)
+    }
+
+    /// The authentication cookie policy, used to set and read the authentication cookie.
+    pub fn get_auth_cookie_policy(&self) -> Option<&CookiePolicy> {
+        self.auth_cookie_policy.as_ref()
+    }
+
+    /// Require a valid CSRF prevention token for requests authenticated by cookie.
+    ///
+    /// Requests with a method other than `GET`, `HEAD` or `OPTIONS` which were authenticated by
+    /// the cookie are rejected unless their `CSRFPreventionToken` header is valid for the
+    /// authenticated user. Clients authenticating via the `Authorization` header are not
+    /// affected.
+    ///
+    /// The [`AuthSource`] reported by the auth handler decides how a request was authenticated,
+    /// see [`AuthHandler::from_fn_with_source`]. For handlers not reporting it, requests carrying
+    /// the authentication cookie (see [`auth_cookie_policy`](Self::auth_cookie_policy), without a
+    /// policy any cookie counts) are treated as authenticated by cookie.
+    pub fn csrf_protection(mut self, csrf: impl Into<Arc<CsrfProtection>>) -> Self {
+        self.csrf_protection = Some(csrf.into());
+        self
+    }
+
+    /// The CSRF protection, used to create tokens at login and to rotate the secret.
+    pub fn get_csrf_protection(&self) -> Option<&Arc<CsrfProtection>> {
+        self.csrf_protection.as_ref()
+    }
+
+    /// Cache the responses of API methods declaring a
+    /// [`CachePolicy`](proxmox_router::CachePolicy).
+    ///
+    /// Keep a reference to the cache to invalidate responses after changes, see
+    /// [`ResponseCache::invalidate_prefix`].
+    pub fn response_cache(mut self, cache: impl Into<Arc<ResponseCache>>) -> Self {
+        self.response_cache = Some(cache.into());
+        self
+    }
+
+    pub(crate) fn get_response_cache(&self) -> Option<&Arc<ResponseCache>> {
+        self.response_cache.as_ref()
+    }
+
+    /// Add headers to every response, including error responses, the index page and static
+    /// files. Headers set by the handler of a request take precedence.
+    pub fn default_headers(mut self, headers: DefaultHeaders) -> Self {
+        self.default_headers = Some(headers);
+        self
+    }
+
+    /// Add the recommended security headers to every response, see
+    /// [`DefaultHeaders::security`].
+    ///
+    /// Use [`default_headers`](Self::default_headers) to relax them for some paths.
+    pub fn with_security_headers(self) -> Self {
+        self.default_headers(DefaultHeaders::security())
+    }
+
+    pub(crate) fn get_default_headers(&self) -> Option<&DefaultHeaders> {
+        self.default_headers.as_ref()
+    }
+
+    /// Add an output format for formatted routers, replacing a format of the same `name`.
+    ///
+    /// The format is used for requests to the `name` prefix (e.g. `/api2/<name>/...`), and for
+    /// requests to any format prefix which accept `media_type`. The built-in formats are `json`
+    /// (`application/json`) and `extjs` (`application/x-extjs-json`).
+    pub fn output_format(
+        mut self,
+        name: &'static str,
+        media_type: &'static str,
+        formatter: &'static dyn OutputFormatter,
+    ) -> Self {
+        self.output_formats.register(OutputFormat {
+            name,
+            media_type: Some(media_type),
+            formatter,
+        });
+        self
+    }
+
+    /// Use `formatter` for the output format `name`, e.g. to replace the envelope of the built-in
+    /// `json` format with a product specific one.
+    ///
+    /// Unlike [`output_format`](Self::output_format), this keeps the media type of an existing
+    /// format. New formats are only used for requests to the `name` prefix.
+    pub fn register_formatter(
+        mut self,
+        name: &'static str,
+        formatter: &'static dyn OutputFormatter,
+    ) -> Self {
+        self.output_formats.set_formatter(name, formatter);
+        self
+    }
+
+    pub(crate) fn get_output_formats(&self) -> &OutputFormats {
+        &self.output_formats
+    }
+
+    /// Answer `OPTIONS` requests (and `GET` requests with a `schema=1` query) on API paths with
+    /// a JSON description of the router node: its methods with their parameter and return
+    /// schemas and permissions, and its child directories.
+    ///
+    /// Methods the caller has no permission for are omitted, so unauthenticated callers only see
+    /// methods with [`Permission::World`](proxmox_router::Permission::World). `privileges` maps
+    /// privilege names to their bits for the permission descriptions, see
+    /// [`permission_text`](proxmox_router::format::permission_text).
+    pub fn api_self_description(mut self, privileges: &'static [(&'static str, u64)]) -> Self {
+        self.self_description = Some(privileges);
+        self
+    }
+
+    pub(crate) fn get_self_description(&self) -> Option<&'static [(&'static str, u64)]> {
+        self.self_description
+    }
+
+    /// Pass every `PUT`, `POST` and `DELETE` API call to `hook`, e.g. a [`FileAuditLog`], after
+    /// the permission check. The hook is called again with the status once the call finished.
+    ///
+    /// [`FileAuditLog`]: crate::FileAuditLog
+    pub fn audit_hook(mut self, hook: Box<dyn AuditHook>) -> Self {
+        self.audit_hook = Some(hook);
+        self
+    }
+
+    /// Fail API calls if the [`audit_hook`](Self::audit_hook) fails before the call is executed,
+    /// instead of only logging the error.
+    pub fn strict_audit(mut self, strict: bool) -> Self {
+        self.strict_audit = strict;
+        self
+    }
+
+    pub(crate) fn get_auditor(&self) -> Option<Auditor<'_>> {
+        self.audit_hook.as_deref().map(|hook| Auditor {
+            hook,
+            strict: self.strict_audit,
+        })
+    }
+
+    /// Answer liveness and readiness probes on `/health` and `/ready`, outside of the API
+    /// handlers and without authentication, see [`HealthOptions`].
+    pub fn enable_health_endpoints(mut self, options: HealthOptions) -> Self {
+        self.health = Some(options);
+        self
+    }
+
+    pub(crate) fn get_health_options(&self) -> Option<&HealthOptions> {
+        self.health.as_ref()
+    }
+
+    /// Limit the bandwidth of request and response bodies, see [`BodyRateLimiter`].
+    ///
+    /// Keep a reference to the limiter to change the limits at runtime, or register its
+    /// [command](BodyRateLimiter::register_command) on the command socket.
+    pub fn body_rate_limiter(mut self, limiter: Arc<BodyRateLimiter>) -> Self {
+        self.body_rate_limiter = Some(limiter);
+        self
+    }
+
+    pub(crate) fn get_body_rate_limiter(&self) -> Option<&Arc<BodyRateLimiter>> {
+        self.body_rate_limiter.as_ref()
+    }
+
+    /// Reject requests while in maintenance, see [`Maintenance`]. The state is also reported by
+    /// the liveness endpoint.
+    ///
+    /// Keep a reference to the maintenance to change its state at runtime, or register its
+    /// [command](Maintenance::register_command) on the command socket.
+    pub fn maintenance(mut self, maintenance: Arc<Maintenance>) -> Self {
+        self.maintenance = Some(maintenance);
+        self
+    }
+
+    pub(crate) fn get_maintenance(&self) -> Option<&Arc<Maintenance>> {
+        self.maintenance.as_ref()
+    }
+
+    /// Require an increasing nonce for state changing requests, see [`ReplayProtection`].
+    ///
+    /// Clients must send the [`RequestNonce`](crate::REQUEST_NONCE_HEADER_NAME) header with
+    /// every request other than `GET` and `HEAD`, so enable this only if all clients do. Requests
+    /// proxied to the privileged API carry the same nonce, so only enable it on one of them.
+    pub fn replay_protection(mut self, replay: impl Into<Arc<ReplayProtection>>) -> Self {
+        self.replay_protection = Some(replay.into());
+        self
+    }
+
+    /// The replay protection, if enabled.
+    pub fn get_replay_protection(&self) -> Option<&Arc<ReplayProtection>> {
+        self.replay_protection.as_ref()
+    }
+
+    /// Answer ticket renewal requests outside of the API handlers, see [`TicketRenewal`].
+    ///
+    /// Renewed tickets are set as cookie according to the
+    /// [`auth_cookie_policy`](Self::auth_cookie_policy), so set it for clients using the cookie.
+    pub fn ticket_renewal(mut self, renewal: TicketRenewal) -> Self {
+        self.ticket_renewal = Some(renewal);
+        self
+    }
+
+    pub(crate) fn get_ticket_renewal(&self) -> Option<&TicketRenewal> {
+        self.ticket_renewal.as_ref()
+    }
+
+    /// Limit the time API handlers may take by the expected duration of their method, see
+    /// [`HandlerTimeouts`].
+    ///
+    /// The timeouts are merged into those set before, e.g. loaded by [`ApiConfig::builder`]:
+    /// classes not set in `timeouts` keep their previous timeout.
+    pub fn handler_timeouts(mut self, timeouts: HandlerTimeouts) -> Self {
+        self.handler_timeouts = Some(match self.handler_timeouts.take() {
+            Some(current) => current.merge(timeouts),
+            None => timeouts,
+        });
+        self
+    }
+
+    /// The timeouts of API handlers, see [`handler_timeouts`](Self::handler_timeouts).
+    pub fn get_handler_timeouts(&self) -> Option<&HandlerTimeouts> {
+        self.handler_timeouts.as_ref()
+    }
+
+    pub(crate) fn get_handler_timeout(
+        &self,
+        http_method: &Method,
+        method: &ApiMethod,
+    ) -> Option<Duration> {
+        self.handler_timeouts
+            .as_ref()
+            .and_then(|timeouts| timeouts.for_request(http_method, method))
+    }
+
+    fn is_cookie_authenticated(&self, headers: &HeaderMap) -> bool {
+        match &self.auth_cookie_policy {
+            Some(policy) => policy.extract(headers).is_some(),
+            None => headers.contains_key(http::header::COOKIE),
+        }
+    }
+
+    /// Only use the client address of the `Forwarded` header of requests from these proxies.
+    ///
+    /// By default the header of every request is used. The privileged service must trust the
+    /// address the unprivileged service connects from, see
+    /// [`privileged_addr`](Self::privileged_addr).
+    pub fn trusted_proxies(mut self, proxies: TrustedProxies) -> Self {
+        self.trusted_proxies = Some(proxies);
+        self
+    }
+
+    /// Check whether the client address reported by `peer` may be used.
+    pub(crate) fn is_trusted_proxy(&self, peer: &std::net::SocketAddr) -> bool {
+        self.trusted_proxies
+            .as_ref()
+            .map_or(true, |proxies| proxies.contains(peer.ip()))
+    }
+
     /// This is used for `protected` API calls to proxy to a more privileged service.
     pub fn privileged_addr(mut self, addr: impl Into<PrivilegedAddr>) -> Self {
         self.privileged_addr = Some(addr.into());
@@ -116,9 +428,34 @@ impl ApiConfig {
         headers: &HeaderMap,
         method: &Method,
     ) -> Result<(String, Box<dyn UserInformation + Sync + Send>), AuthError> {
-        match self.auth_handler.as_ref() {
-            Some(handler) => (handler.func)(headers, method).await,
-            None => Err(AuthError::NoData),
+        let (auth_id, user_info, source) = match self.auth_handler.as_ref() {
+            Some(handler) => handler.check(headers, method).await?,
+            None => return Err(AuthError::NoData),
+        };
+
+        if let Some(csrf) = &self.csrf_protection {
+            let cookie_authenticated = match source {
+                Some(source) => source == AuthSource::Cookie,
+                None => self.is_cookie_authenticated(headers),
+            };
+            if cookie_authenticated {
+                csrf.check_request(headers, method, &auth_id)?;
+            }
+        }
+
+        Ok((auth_id, user_info))
+    }
+
+    /// Check the request nonce of an authenticated request, if replay protection is enabled.
+    pub(crate) fn check_replay(
+        &self,
+        headers: &HeaderMap,
+        method: &Method,
+        auth_id: &str,
+    ) -> Result<(), Error> {
+        match &self.replay_protection {
+            Some(replay) => replay.check_request(headers, method, auth_id),
+            None => Ok(()),
         }
     }
 
@@ -197,6 +534,38 @@ impl ApiConfig {
         self.templates.render(name, data)
     }
 
+    /// Compress the access and auth log, see [`LogCompression`]. Frames are written by a
+    /// [flusher](FileLogger::spawn_flusher) thread once they are due.
+    ///
+    /// Must be set before enabling the logs. The rotation of the log files must not compress
+    /// them again.
+    pub fn log_compression(mut self, compression: LogCompression) -> Self {
+        self.log_compression = Some(compression);
+        self
+    }
+
+    /// Set the format of the access log lines, [`AccessLogFormat::Combined`] by default.
+    pub fn access_log_format(mut self, format: AccessLogFormat) -> Self {
+        self.access_log_format = format;
+        self
+    }
+
+    /// Send the access log lines to `sink` as well, see [`FileLogger::set_sink`].
+    ///
+    /// Must be set before enabling the access log.
+    pub fn access_log_sink(mut self, sink: Box<dyn LogSink>, queue_len: usize) -> Self {
+        self.access_log_sink = Some((sink, queue_len));
+        self
+    }
+
+    /// Send the auth log lines to `sink` as well, see [`FileLogger::set_sink`].
+    ///
+    /// Must be set before enabling the auth log.
+    pub fn auth_log_sink(mut self, sink: Box<dyn LogSink>, queue_len: usize) -> Self {
+        self.auth_log_sink = Some((sink, queue_len));
+        self
+    }
+
     /// Enable the access log feature
     ///
     /// When enabled, all requests are logged to the specified file.
@@ -222,9 +591,17 @@ impl ApiConfig {
         let logger_options = FileLogOptions {
             append: true,
             file_opts: file_opts.unwrap_or_default(),
+            compression: self.log_compression,
             ..Default::default()
         };
-        let request_log = Arc::new(Mutex::new(FileLogger::new(&path, logger_options)?));
+        let mut logger = FileLogger::new(&path, logger_options)?;
+        if let Some((sink, queue_len)) = self.access_log_sink.take() {
+            logger.set_sink(sink, queue_len)?;
+        }
+        let request_log = Arc::new(Mutex::new(logger));
+        if self.log_compression.is_some() {
+            FileLogger::spawn_flusher(&request_log, LOG_FLUSH_INTERVAL)?;
+        }
         self.request_log = Some(Arc::clone(&request_log));
 
         commando_sock.register_command("api-access-log-reopen".into(), move |_args| {
@@ -262,9 +639,17 @@ impl ApiConfig {
             append: true,
             prefix_time: true,
             file_opts: file_opts.unwrap_or_default(),
+            compression: self.log_compression,
             ..Default::default()
         };
-        let auth_log = Arc::new(Mutex::new(FileLogger::new(&path, logger_options)?));
+        let mut logger = FileLogger::new(&path, logger_options)?;
+        if let Some((sink, queue_len)) = self.auth_log_sink.take() {
+            logger.set_sink(sink, queue_len)?;
+        }
+        let auth_log = Arc::new(Mutex::new(logger));
+        if self.log_compression.is_some() {
+            FileLogger::spawn_flusher(&auth_log, LOG_FLUSH_INTERVAL)?;
+        }
         self.auth_log = Some(Arc::clone(&auth_log));
 
         commando_sock.register_command("api-auth-log-reopen".into(), move |_args| {
@@ -276,10 +661,30 @@ impl ApiConfig {
         Ok(self)
     }
 
+    /// Returns a function reopening the access and auth log, e.g. for a `SIGUSR1` handler, see
+    /// [`SignalAction::custom`](proxmox_daemon::SignalAction::custom).
+    ///
+    /// The logs must be enabled before, logs enabled afterwards are not reopened.
+    pub fn log_reopener(&self) -> impl Fn() -> Result<(), Error> + Send + Sync + 'static {
+        let reopeners: Vec<_> = [&self.request_log, &self.auth_log]
+            .into_iter()
+            .flatten()
+            .map(FileLogger::reopener)
+            .collect();
+        move || {
+            log::info!("re-opening log files");
+            reopeners.iter().try_for_each(|reopen| reopen())
+        }
+    }
+
     pub(crate) fn get_access_log(&self) -> Option<&Arc<Mutex<FileLogger>>> {
         self.request_log.as_ref()
     }
 
+    pub(crate) fn get_access_log_format(&self) -> AccessLogFormat {
+        self.access_log_format
+    }
+
     pub(crate) fn get_auth_log(&self) -> Option<&Arc<Mutex<FileLogger>>> {
         self.auth_log.as_ref()
     }
@@ -435,13 +840,44 @@ pub type CheckAuthFuture<'a> = Pin<Box<dyn Future<Output = CheckAuthOutput> + Se
 pub type CheckAuthFunc =
     Box<dyn for<'a> Fn(&'a HeaderMap, &'a Method) -> CheckAuthFuture<'a> + Send + Sync>;
 
+pub type CheckAuthSourceOutput =
+    Result<(String, Box<dyn UserInformation + Send + Sync>, AuthSource), AuthError>;
+pub type CheckAuthSourceFuture<'a> =
+    Pin<Box<dyn Future<Output = CheckAuthSourceOutput> + Send + 'a>>;
+pub type CheckAuthSourceFunc =
+    Box<dyn for<'a> Fn(&'a HeaderMap, &'a Method) -> CheckAuthSourceFuture<'a> + Send + Sync>;
+
+/// How a request was authenticated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AuthSource {
+    /// By the authentication cookie, such requests need a CSRF prevention token.
+    Cookie,
+    /// By a request header, like an API token in the `Authorization` header.
+    Header,
+}
+
+enum AuthFunc {
+    Plain(CheckAuthFunc),
+    WithSource(CheckAuthSourceFunc),
+}
+
 pub struct AuthHandler {
-    func: CheckAuthFunc,
+    func: AuthFunc,
 }
 
 impl From<CheckAuthFunc> for AuthHandler {
     fn from(func: CheckAuthFunc) -> Self {
-        Self { func }
+        Self {
+            func: AuthFunc::Plain(func),
+        }
+    }
+}
+
+impl From<CheckAuthSourceFunc> for AuthHandler {
+    fn from(func: CheckAuthSourceFunc) -> Self {
+        Self {
+            func: AuthFunc::WithSource(func),
+        }
     }
 }
 
@@ -452,6 +888,42 @@ impl AuthHandler {
     {
         Self::from(Box::new(func) as CheckAuthFunc)
     }
+
+    /// Create a handler which also reports how it authenticated the request, see
+    /// [`ApiConfig::csrf_protection`].
+    pub fn from_fn_with_source<Func>(func: Func) -> Self
+    where
+        Func: for<'a> Fn(&'a HeaderMap, &'a Method) -> CheckAuthSourceFuture<'a>
+            + Send
+            + Sync
+            + 'static,
+    {
+        Self::from(Box::new(func) as CheckAuthSourceFunc)
+    }
+
+    async fn check(
+        &self,
+        headers: &HeaderMap,
+        method: &Method,
+    ) -> Result<
+        (
+            String,
+            Box<dyn UserInformation + Send + Sync>,
+            Option<AuthSource>,
+        ),
+        AuthError,
+    > {
+        match &self.func {
+            AuthFunc::Plain(func) => {
+                let (auth_id, user_info) = func(headers, method).await?;
+                Ok((auth_id, user_info, None))
+            }
+            AuthFunc::WithSource(func) => {
+                let (auth_id, user_info, source) = func(headers, method).await?;
+                Ok((auth_id, user_info, Some(source)))
+            }
+        }
+    }
 }
 
 /// Authentication Error
@@ -0,0 +1,146 @@
+//! Auditing of state-changing API calls.
+
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use anyhow::{format_err, Error};
+use http::{Method, StatusCode};
+use serde_json::{json, Value};
+
+use proxmox_log::{FileLogOptions, FileLogger};
+use proxmox_sys::fs::CreateOptions;
+
+/// A state-changing API call, passed to an [`AuditHook`].
+///
+/// Secret parameters, see [`StringSchema::secret`], are already redacted.
+///
+/// [`StringSchema::secret`]: proxmox_schema::StringSchema::secret
+#[derive(Debug)]
+pub struct AuditCall<'a> {
+    /// The HTTP method, `PUT`, `POST` or `DELETE`.
+    pub method: &'a Method,
+    /// The path of the request.
+    pub path: &'a str,
+    /// The authenticated user, `None` for calls not requiring authentication.
+    pub auth_id: Option<&'a str>,
+    /// The address of the client.
+    pub client_ip: Option<SocketAddr>,
+    /// The parameters, verified against the schema of the API method.
+    pub params: &'a Value,
+}
+
+/// Hook called for every `PUT`, `POST` and `DELETE` API call, see [`ApiConfig::audit_hook`].
+///
+/// Errors of the hook are logged, but do not fail the call, unless the strict mode is enabled
+/// with [`ApiConfig::strict_audit`].
+///
+/// [`ApiConfig::audit_hook`]: crate::ApiConfig::audit_hook
+/// [`ApiConfig::strict_audit`]: crate::ApiConfig::strict_audit
+pub trait AuditHook: Send + Sync {
+    /// Called after the permission check, before the API method is executed.
+    fn before(&self, call: &AuditCall) -> Result<(), Error>;
+
+    /// Called after the call finished with the status of the response.
+    fn after(&self, call: &AuditCall, status: StatusCode) -> Result<(), Error>;
+}
+
+/// An [`AuditHook`] writing each call to a file, as one JSON object per line and phase.
+///
+/// The objects contain the `phase` (`before` or `after`), the `time` as epoch and the members of
+/// the [`AuditCall`] in kebab-case. Objects of the `after` phase also contain the `status` code.
+pub struct FileAuditLog {
+    log: Mutex<FileLogger>,
+}
+
+impl FileAuditLog {
+    /// Open the log file `path` for appending.
+    pub fn new<P: Into<PathBuf>>(path: P, file_opts: Option<CreateOptions>) -> Result<Self, Error> {
+        let path = path.into();
+        let options = FileLogOptions {
+            append: true,
+            file_opts: file_opts.unwrap_or_default(),
+            ..Default::default()
+        };
+        let log = FileLogger::new(&path, options)
+            .map_err(|err| format_err!("unable to open audit log {path:?} - {err}"))?;
+        Ok(Self {
+            log: Mutex::new(log),
+        })
+    }
+
+    /// Reopen the log file, e.g. after it was rotated.
+    pub fn reopen(&self) -> Result<(), Error> {
+        self.log.lock().unwrap().reopen()?;
+        Ok(())
+    }
+
+    fn write(&self, phase: &str, call: &AuditCall, status: Option<StatusCode>) {
+        let mut entry = json!({
+            "phase": phase,
+            "time": proxmox_time::epoch_i64(),
+            "method": call.method.as_str(),
+            "path": call.path,
+            "auth-id": call.auth_id,
+            "client-ip": call.client_ip.map(|addr| addr.ip().to_string()),
+            "params": call.params,
+        });
+        if let Some(status) = status {
+            entry["status"] = status.as_u16().into();
+        }
+        self.log.lock().unwrap().log(entry.to_string());
+    }
+}
+
+impl AuditHook for FileAuditLog {
+    fn before(&self, call: &AuditCall) -> Result<(), Error> {
+        self.write("before", call, None);
+        Ok(())
+    }
+
+    fn after(&self, call: &AuditCall, status: StatusCode) -> Result<(), Error> {
+        self.write("after", call, Some(status));
+        Ok(())
+    }
+}
+
+/// The configured audit hook, see [`ApiConfig::audit_hook`](crate::ApiConfig::audit_hook).
+#[derive(Clone, Copy)]
+pub(crate) struct Auditor<'a> {
+    pub(crate) hook: &'a dyn AuditHook,
+    pub(crate) strict: bool,
+}
+
+impl Auditor<'_> {
+    /// Whether calls with `method` are audited.
+    pub(crate) fn audits(method: &Method) -> bool {
+        matches!(*method, Method::PUT | Method::POST | Method::DELETE)
+    }
+
+    /// Call the hook before the API method is executed. Fails only in strict mode.
+    pub(crate) fn before(&self, call: &AuditCall) -> Result<(), Error> {
+        match self.hook.before(call) {
+            Err(err) if self.strict => Err(format_err!("audit failed - {err}")),
+            Err(err) => {
+                log::error!(
+                    "audit hook failed for {} {} - {err}",
+                    call.method,
+                    call.path
+                );
+                Ok(())
+            }
+            Ok(()) => Ok(()),
+        }
+    }
+
+    /// Call the hook after the API call finished.
+    pub(crate) fn after(&self, call: &AuditCall, status: StatusCode) {
+        if let Err(err) = self.hook.after(call, status) {
+            log::error!(
+                "audit hook failed for {} {} - {err}",
+                call.method,
+                call.path
+            );
+        }
+    }
+}
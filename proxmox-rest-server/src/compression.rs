@@ -37,3 +37,36 @@ impl std::str::FromStr for CompressionMethod {
         }
     }
 }
+
+/// Encodings of pre-compressed static files, order determines preference on equal quality
+/// values (earlier is preferred).
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum PrecompressedEncoding {
+    Zstd,
+    Gzip,
+}
+
+impl PrecompressedEncoding {
+    pub const ALL: [PrecompressedEncoding; 2] =
+        [PrecompressedEncoding::Zstd, PrecompressedEncoding::Gzip];
+
+    /// The content coding as used in the `Accept-Encoding` and `Content-Encoding` headers.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            PrecompressedEncoding::Zstd => "zstd",
+            PrecompressedEncoding::Gzip => "gzip",
+        }
+    }
+
+    pub fn content_encoding(&self) -> header::HeaderValue {
+        header::HeaderValue::from_static(self.name())
+    }
+
+    /// The file extension appended to the name of the identity file.
+    pub fn extension(&self) -> &'static str {
+        match *self {
+            PrecompressedEncoding::Zstd => "zst",
+            PrecompressedEncoding::Gzip => "gz",
+        }
+    }
+}
@@ -2,29 +2,41 @@
 //!
 //! Hyper building block.
 
+use std::collections::hash_map::{DefaultHasher, Entry};
+use std::collections::{HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
 use std::io;
 use std::mem::ManuallyDrop;
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
 use std::os::fd::FromRawFd;
 use std::os::unix::io::AsRawFd;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::pin::{pin, Pin};
+use std::sync::atomic::{AtomicI64, AtomicU64, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
 use std::time::Duration;
 
-use anyhow::{format_err, Context as _, Error};
+use anyhow::{bail, format_err, Context as _, Error};
 use futures::FutureExt;
 use hyper::server::accept;
+use nix::sys::socket::{setsockopt, sockopt};
 use openssl::ec::{EcGroup, EcKey};
 use openssl::nid::Nid;
-use openssl::pkey::{PKey, Private};
-use openssl::ssl::{SslAcceptor, SslFiletype, SslMethod};
-use openssl::x509::X509;
+use openssl::pkey::{PKey, PKeyRef, Private};
+use openssl::ssl::{ErrorCode, NameType, SslAcceptor, SslFiletype, SslMethod, SslRef};
+use openssl::x509::{X509Ref, X509};
+use serde::Serialize;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
 use tokio::net::{TcpListener, TcpStream};
 use tokio::sync::mpsc;
+use tokio::time::Instant;
 use tokio_openssl::SslStream;
 use tokio_stream::wrappers::ReceiverStream;
 
+use proxmox_daemon::command_socket::CommandSocket;
+use proxmox_sys::fs::{replace_file, CreateOptions};
+
 #[cfg(feature = "rate-limited-stream")]
 use proxmox_http::{RateLimitedStream, ShareableRateLimit};
 
@@ -138,15 +150,607 @@ impl TlsAcceptorBuilder {
     }
 }
 
+/// Parameters of the certificate created by [`ensure_self_signed_cert`].
+#[derive(Clone)]
+pub struct SelfSignedParams {
+    common_name: Option<String>,
+    organization: Option<String>,
+    dns_names: Vec<String>,
+    ip_addresses: Vec<IpAddr>,
+    validity_days: u32,
+    key_options: CreateOptions,
+    cert_options: CreateOptions,
+}
+
+impl Default for SelfSignedParams {
+    fn default() -> Self {
+        Self {
+            common_name: None,
+            organization: None,
+            dns_names: Vec::new(),
+            ip_addresses: Vec::new(),
+            validity_days: 2 * 365,
+            key_options: CreateOptions::new().perm(nix::sys::stat::Mode::from_bits_truncate(0o600)),
+            cert_options: CreateOptions::new()
+                .perm(nix::sys::stat::Mode::from_bits_truncate(0o644)),
+        }
+    }
+}
+
+impl SelfSignedParams {
+    /// Create the parameters for a certificate for the host's FQDN, valid for 2 years.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the common name, the host's FQDN by default. It is also added as DNS name.
+    pub fn common_name(mut self, name: impl Into<String>) -> Self {
+        self.common_name = Some(name.into());
+        self
+    }
+
+    /// Set the organization of the subject.
+    pub fn organization(mut self, organization: impl Into<String>) -> Self {
+        self.organization = Some(organization.into());
+        self
+    }
+
+    /// Add a DNS name to the subject alternative names.
+    pub fn dns_name(mut self, name: impl Into<String>) -> Self {
+        self.dns_names.push(name.into());
+        self
+    }
+
+    /// Add an IP address to the subject alternative names.
+    pub fn ip_address(mut self, addr: IpAddr) -> Self {
+        self.ip_addresses.push(addr);
+        self
+    }
+
+    pub fn validity_days(mut self, days: u32) -> Self {
+        self.validity_days = days;
+        self
+    }
+
+    /// Set the options for the key file, only readable by its owner by default.
+    pub fn key_options(mut self, options: CreateOptions) -> Self {
+        self.key_options = options;
+        self
+    }
+
+    /// Set the options for the certificate file, readable by everyone by default.
+    pub fn cert_options(mut self, options: CreateOptions) -> Self {
+        self.cert_options = options;
+        self
+    }
+}
+
+/// The fully qualified domain name of the host, or its node name if it cannot be resolved.
+fn host_fqdn() -> String {
+    let nodename = proxmox_sys::nodename();
+    let Ok(name) = std::ffi::CString::new(nodename) else {
+        return nodename.to_string();
+    };
+
+    // SAFETY: `addrinfo` is a plain C struct, for which all zeroes are valid hints
+    let mut hints: libc::addrinfo = unsafe { std::mem::zeroed() };
+    hints.ai_flags = libc::AI_CANONNAME;
+    let mut res = std::ptr::null_mut();
+    // SAFETY: all pointers are valid, `res` is freed below on success
+    if unsafe { libc::getaddrinfo(name.as_ptr(), std::ptr::null(), &hints, &mut res) } != 0 {
+        return nodename.to_string();
+    }
+    // SAFETY: `res` points to at least one result
+    let canonname = unsafe { (*res).ai_canonname };
+    let fqdn = if canonname.is_null() {
+        nodename.to_string()
+    } else {
+        // SAFETY: the canonical name is a NUL terminated string owned by `res`
+        unsafe { std::ffi::CStr::from_ptr(canonname) }
+            .to_string_lossy()
+            .into_owned()
+    };
+    // SAFETY: `res` was allocated by `getaddrinfo`
+    unsafe { libc::freeaddrinfo(res) };
+
+    fqdn
+}
+
+/// Generate an EC P-256 key and a self-signed certificate for it.
+fn generate_self_signed(params: &SelfSignedParams) -> Result<(PKey<Private>, X509), Error> {
+    use openssl::asn1::Asn1Time;
+    use openssl::bn::{BigNum, MsbOption};
+    use openssl::x509::extension::{
+        BasicConstraints, ExtendedKeyUsage, KeyUsage, SubjectAlternativeName, SubjectKeyIdentifier,
+    };
+    use openssl::x509::X509Name;
+
+    let key = EcKey::generate(EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)?.as_ref())
+        .and_then(PKey::from_ec_key)
+        .context("failed to generate ec key")?;
+
+    let common_name = match &params.common_name {
+        Some(name) => name.clone(),
+        None => host_fqdn(),
+    };
+
+    let mut name = X509Name::builder()?;
+    if let Some(organization) = &params.organization {
+        name.append_entry_by_nid(Nid::ORGANIZATIONNAME, organization)?;
+    }
+    name.append_entry_by_nid(Nid::COMMONNAME, &common_name)
+        .with_context(|| format!("invalid common name '{common_name}'"))?;
+    let name = name.build();
+
+    let mut serial = BigNum::new()?;
+    serial.rand(128, MsbOption::MAYBE_ZERO, false)?;
+
+    let mut cert = X509::builder()?;
+    cert.set_version(2)?;
+    cert.set_serial_number(serial.to_asn1_integer()?.as_ref())?;
+    cert.set_subject_name(&name)?;
+    cert.set_issuer_name(&name)?;
+    cert.set_pubkey(&key)?;
+    cert.set_not_before(Asn1Time::days_from_now(0)?.as_ref())?;
+    cert.set_not_after(Asn1Time::days_from_now(params.validity_days)?.as_ref())?;
+
+    cert.append_extension(BasicConstraints::new().critical().build()?)?;
+    cert.append_extension(
+        KeyUsage::new()
+            .critical()
+            .digital_signature()
+            .key_agreement()
+            .build()?,
+    )?;
+    cert.append_extension(ExtendedKeyUsage::new().server_auth().build()?)?;
+    let key_id = SubjectKeyIdentifier::new().build(&cert.x509v3_context(None, None))?;
+    cert.append_extension(key_id)?;
+
+    let mut alt_names = SubjectAlternativeName::new();
+    alt_names.dns(&common_name);
+    for dns_name in params.dns_names.iter().filter(|name| **name != common_name) {
+        alt_names.dns(dns_name);
+    }
+    for addr in &params.ip_addresses {
+        alt_names.ip(&addr.to_string());
+    }
+    let alt_names = alt_names.build(&cert.x509v3_context(None, None))?;
+    cert.append_extension(alt_names)?;
+
+    cert.sign(&key, openssl::hash::MessageDigest::sha256())?;
+
+    Ok((key, cert.build()))
+}
+
+/// Create a self-signed certificate and its key, unless both files already exist.
+///
+/// This is meant for the first start of a daemon. The key is an EC P-256 key and the certificate
+/// is issued for the host's FQDN, or the configured common name, and the names and addresses of
+/// `params`. If either file is missing, both are created, so that they always match. The files
+/// are replaced atomically while holding a lock next to the certificate, so daemons starting
+/// concurrently do not create different certificates.
+///
+/// Returns whether the files were created.
+pub fn ensure_self_signed_cert(
+    cert_path: &Path,
+    key_path: &Path,
+    params: SelfSignedParams,
+) -> Result<bool, Error> {
+    let mut lock_path = cert_path.as_os_str().to_owned();
+    lock_path.push(".lock");
+    let _lock = proxmox_sys::fs::open_file_locked(
+        &lock_path,
+        Duration::from_secs(10),
+        true,
+        params.cert_options.clone(),
+    )?;
+
+    if cert_path.exists() && key_path.exists() {
+        return Ok(false);
+    }
+
+    let (key, cert) = generate_self_signed(&params)?;
+
+    let key_pem = key.private_key_to_pem_pkcs8()?;
+    replace_file(key_path, &key_pem, params.key_options, true)
+        .with_context(|| format!("failed to write key {key_path:?}"))?;
+    replace_file(cert_path, &cert.to_pem()?, params.cert_options, true)
+        .with_context(|| format!("failed to write certificate {cert_path:?}"))?;
+
+    Ok(true)
+}
+
+/// Check that `key` is the private key of the certificate `cert`.
+pub fn check_cert_key_match(cert: &X509Ref, key: &PKeyRef<Private>) -> Result<(), Error> {
+    if !cert.public_key()?.public_eq(key) {
+        bail!("certificate does not match the private key");
+    }
+    Ok(())
+}
+
+/// The time the certificate expires, as seconds since the epoch.
+pub fn cert_expiry(cert: &X509Ref) -> Result<i64, Error> {
+    let diff = openssl::asn1::Asn1Time::from_unix(0)?.diff(cert.not_after())?;
+    Ok(diff.days as i64 * 24 * 60 * 60 + diff.secs as i64)
+}
+
+/// Whether the certificate expires within `duration`, or already expired.
+pub fn cert_expires_within(cert: &X509Ref, duration: Duration) -> Result<bool, Error> {
+    let now = proxmox_time::epoch_i64();
+    Ok(cert_expiry(cert)? <= now.saturating_add(duration.as_secs() as i64))
+}
+
 #[cfg(not(feature = "rate-limited-stream"))]
-type InsecureClientStream = TcpStream;
+type InsecureClientStream = TrackedStream<TcpStream>;
 #[cfg(feature = "rate-limited-stream")]
-type InsecureClientStream = RateLimitedStream<TcpStream>;
+type InsecureClientStream = TrackedStream<RateLimitedStream<TcpStream>>;
 
 type InsecureClientStreamResult = Pin<Box<InsecureClientStream>>;
 
 type ClientStreamResult = Pin<Box<SslStream<InsecureClientStream>>>;
 
+/// Number of shards the per address connection counts are spread over.
+const CONNECTION_SHARDS: usize = 16;
+
+/// Minimum number of seconds between two log messages about rejected connections or failed TLS
+/// handshakes.
+const LOG_INTERVAL: i64 = 10;
+
+/// Limits a kind of log message to one per [`LOG_INTERVAL`], counting the suppressed ones.
+#[derive(Default)]
+struct LogLimiter {
+    last: AtomicI64,
+    suppressed: AtomicU64,
+}
+
+impl LogLimiter {
+    /// Returns the number of messages suppressed since the last one, or `None` if this message
+    /// should be suppressed too.
+    fn check(&self) -> Option<u64> {
+        let now = proxmox_time::epoch_i64();
+        let last = self.last.load(Ordering::Relaxed);
+        if now < last + LOG_INTERVAL
+            || self
+                .last
+                .compare_exchange(last, now, Ordering::Relaxed, Ordering::Relaxed)
+                .is_err()
+        {
+            self.suppressed.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+        Some(self.suppressed.swap(0, Ordering::Relaxed))
+    }
+}
+
+/// The reason a TLS handshake failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HandshakeFailureClass {
+    /// The client did not finish the handshake in time.
+    Timeout,
+    /// The handshake was aborted because of a TLS error, e.g. an unsupported protocol version, no
+    /// common cipher, a certificate rejected by the client or a client not speaking TLS at all.
+    Protocol,
+    /// The connection was closed or failed during the handshake.
+    Connection,
+}
+
+impl HandshakeFailureClass {
+    const ALL: [Self; 3] = [Self::Timeout, Self::Protocol, Self::Connection];
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Timeout => "timeout",
+            Self::Protocol => "protocol",
+            Self::Connection => "connection",
+        }
+    }
+}
+
+impl std::fmt::Display for HandshakeFailureClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A failed TLS handshake, see [`AcceptBuilder::keep_handshake_failures`].
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct HandshakeFailure {
+    /// The time of the failure as epoch.
+    pub time: i64,
+    pub peer: SocketAddr,
+    pub class: HandshakeFailureClass,
+    /// The server name requested by the client.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sni: Option<String>,
+    /// The protocol version negotiated before the failure.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub protocol: Option<String>,
+    pub error: String,
+}
+
+impl HandshakeFailure {
+    fn new(peer: SocketAddr, class: HandshakeFailureClass, error: String) -> Self {
+        Self {
+            time: proxmox_time::epoch_i64(),
+            peer,
+            class,
+            sni: None,
+            protocol: None,
+            error,
+        }
+    }
+
+    /// Add the details the client sent before the failure.
+    fn with_ssl(mut self, ssl: &SslRef) -> Self {
+        self.sni = ssl.servername(NameType::HOST_NAME).map(str::to_string);
+        self.protocol = Some(ssl.version_str())
+            .filter(|version| *version != "unknown")
+            .map(str::to_string);
+        self
+    }
+}
+
+impl std::fmt::Display for HandshakeFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "[{}] TLS handshake failed ({})", self.peer, self.class)?;
+        if let Some(sni) = &self.sni {
+            write!(f, ", server name '{sni}'")?;
+        }
+        if let Some(protocol) = &self.protocol {
+            write!(f, ", protocol {protocol}")?;
+        }
+        write!(f, " - {}", self.error)
+    }
+}
+
+/// Connection counters of an [`AcceptBuilder`].
+///
+/// The counters are shared by all connections accepted by the builder, see
+/// [`AcceptBuilder::connection_stats`].
+#[derive(Default)]
+pub struct ConnectionStats {
+    active: AtomicUsize,
+    accepted: AtomicU64,
+    rejected: AtomicU64,
+    handshake_failures: [AtomicU64; HandshakeFailureClass::ALL.len()],
+    per_address: [Mutex<HashMap<IpAddr, usize>>; CONNECTION_SHARDS],
+    reject_log: LogLimiter,
+    handshake_log: LogLimiter,
+    keep_handshake_failures: AtomicUsize,
+    recent_handshake_failures: Mutex<VecDeque<HandshakeFailure>>,
+}
+
+impl ConnectionStats {
+    /// Number of currently open connections, including those still in the TLS handshake.
+    pub fn active_connections(&self) -> usize {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    /// Number of currently open connections from `addr`.
+    ///
+    /// Connections are only counted per address if a per address limit is configured.
+    pub fn active_connections_from(&self, addr: IpAddr) -> usize {
+        let addr = addr.to_canonical();
+        let counts = self.shard(&addr).lock().unwrap();
+        counts.get(&addr).copied().unwrap_or(0)
+    }
+
+    /// Total number of accepted connections.
+    pub fn accepted_connections(&self) -> u64 {
+        self.accepted.load(Ordering::Relaxed)
+    }
+
+    /// Total number of connections closed right away because a limit was reached.
+    pub fn rejected_connections(&self) -> u64 {
+        self.rejected.load(Ordering::Relaxed)
+    }
+
+    /// Total number of connections closed because the client did not finish the TLS handshake in
+    /// time.
+    pub fn handshake_timeouts(&self) -> u64 {
+        self.handshake_failures(HandshakeFailureClass::Timeout)
+    }
+
+    /// Total number of failed TLS handshakes of a class.
+    pub fn handshake_failures(&self, class: HandshakeFailureClass) -> u64 {
+        self.handshake_failures[class as usize].load(Ordering::Relaxed)
+    }
+
+    /// The most recent failed TLS handshakes, oldest first.
+    ///
+    /// This is empty unless enabled with [`AcceptBuilder::keep_handshake_failures`].
+    pub fn recent_handshake_failures(&self) -> Vec<HandshakeFailure> {
+        let failures = self.recent_handshake_failures.lock().unwrap();
+        failures.iter().cloned().collect()
+    }
+
+    /// Register the `connection-stats` command on the [CommandSocket], returning the counters,
+    /// and the `handshake-failures` command, returning the recent failed TLS handshakes.
+    pub fn register_command(
+        self: Arc<Self>,
+        commando_sock: &mut CommandSocket,
+    ) -> Result<(), Error> {
+        let stats = Arc::clone(&self);
+        commando_sock.register_command("connection-stats".into(), move |_args| {
+            let failures: serde_json::Map<String, serde_json::Value> = HandshakeFailureClass::ALL
+                .into_iter()
+                .map(|class| (class.to_string(), stats.handshake_failures(class).into()))
+                .collect();
+            Ok(serde_json::json!({
+                "active": stats.active_connections(),
+                "accepted": stats.accepted_connections(),
+                "rejected": stats.rejected_connections(),
+                "handshake-timeouts": stats.handshake_timeouts(),
+                "handshake-failures": failures,
+            }))
+        })?;
+        commando_sock.register_command("handshake-failures".into(), move |_args| {
+            Ok(serde_json::to_value(self.recent_handshake_failures())?)
+        })
+    }
+
+    fn shard(&self, addr: &IpAddr) -> &Mutex<HashMap<IpAddr, usize>> {
+        let mut hasher = DefaultHasher::new();
+        addr.hash(&mut hasher);
+        &self.per_address[hasher.finish() as usize % CONNECTION_SHARDS]
+    }
+
+    fn try_reserve(
+        self: &Arc<Self>,
+        addr: IpAddr,
+        max_connections: Option<usize>,
+        max_connections_per_address: Option<usize>,
+    ) -> Result<ConnectionSlot, &'static str> {
+        let active = self.active.fetch_add(1, Ordering::AcqRel) + 1;
+        // from here on dropping the slot gives the connection back
+        let mut slot = ConnectionSlot {
+            stats: Arc::clone(self),
+            addr: None,
+        };
+
+        if max_connections.is_some_and(|max| active > max) {
+            return Err("too many open connections");
+        }
+
+        if let Some(max) = max_connections_per_address {
+            let mut counts = self.shard(&addr).lock().unwrap();
+            let count = counts.entry(addr).or_default();
+            if *count >= max {
+                if *count == 0 {
+                    counts.remove(&addr);
+                }
+                return Err("too many open connections from this address");
+            }
+            *count += 1;
+            slot.addr = Some(addr);
+        }
+
+        self.accepted.fetch_add(1, Ordering::Relaxed);
+        Ok(slot)
+    }
+
+    fn log_rejected(&self, peer: SocketAddr, reason: &str) {
+        self.rejected.fetch_add(1, Ordering::Relaxed);
+
+        match self.reject_log.check() {
+            None => (),
+            Some(0) => log::warn!("[{peer}] connection rejected - {reason}"),
+            Some(suppressed) => log::warn!(
+                "[{peer}] connection rejected - {reason} ({suppressed} more rejected since the \
+                last message)"
+            ),
+        }
+    }
+
+    /// Count and log a failed handshake, in debug mode without rate limit.
+    fn handshake_failed(&self, failure: HandshakeFailure, is_debug: bool) {
+        self.handshake_failures[failure.class as usize].fetch_add(1, Ordering::Relaxed);
+
+        if is_debug {
+            log::warn!("{failure}");
+        } else {
+            match self.handshake_log.check() {
+                None => (),
+                Some(0) => log::warn!("{failure}"),
+                Some(suppressed) => {
+                    log::warn!("{failure} ({suppressed} more failed since the last message)")
+                }
+            }
+        }
+
+        let keep = self.keep_handshake_failures.load(Ordering::Relaxed);
+        if keep > 0 {
+            let mut failures = self.recent_handshake_failures.lock().unwrap();
+            while failures.len() >= keep {
+                failures.pop_front();
+            }
+            failures.push_back(failure);
+        }
+    }
+}
+
+/// A connection counted in the [`ConnectionStats`], released on drop.
+struct ConnectionSlot {
+    stats: Arc<ConnectionStats>,
+    addr: Option<IpAddr>,
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        self.stats.active.fetch_sub(1, Ordering::AcqRel);
+
+        if let Some(addr) = self.addr {
+            let mut counts = self.stats.shard(&addr).lock().unwrap();
+            if let Entry::Occupied(mut entry) = counts.entry(addr) {
+                *entry.get_mut() -= 1;
+                if *entry.get() == 0 {
+                    entry.remove();
+                }
+            }
+        }
+    }
+}
+
+/// A client connection which stays counted in the [`ConnectionStats`] until it is dropped.
+pub struct TrackedStream<S> {
+    inner: S,
+    _slot: ConnectionSlot,
+}
+
+impl<S> TrackedStream<S> {
+    /// Access the underlying stream.
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Mutably access the underlying stream.
+    pub fn inner_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for TrackedStream<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_read(cx, buf)
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for TrackedStream<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().inner).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().inner).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
 #[cfg(feature = "rate-limited-stream")]
 type LookupRateLimiter = dyn Fn(std::net::SocketAddr) -> (Option<SharedRateLimit>, Option<SharedRateLimit>)
     + Send
@@ -157,6 +761,11 @@ pub struct AcceptBuilder {
     debug: bool,
     tcp_keepalive_time: u32,
     max_pending_accepts: usize,
+    max_connections: Option<usize>,
+    max_connections_per_address: Option<usize>,
+    handshake_timeout: Duration,
+    reset_rejected: bool,
+    stats: Arc<ConnectionStats>,
 
     #[cfg(feature = "rate-limited-stream")]
     lookup_rate_limiter: Option<Arc<LookupRateLimiter>>,
@@ -168,6 +777,11 @@ impl Default for AcceptBuilder {
             debug: false,
             tcp_keepalive_time: 120,
             max_pending_accepts: 1024,
+            max_connections: None,
+            max_connections_per_address: None,
+            handshake_timeout: Duration::from_secs(10),
+            reset_rejected: false,
+            stats: Arc::new(ConnectionStats::default()),
 
             #[cfg(feature = "rate-limited-stream")]
             lookup_rate_limiter: None,
@@ -195,6 +809,51 @@ impl AcceptBuilder {
         self
     }
 
+    /// Limit the number of open connections.
+    ///
+    /// Further connections are accepted and closed right away.
+    pub fn max_connections(mut self, count: Option<usize>) -> Self {
+        self.max_connections = count;
+        self
+    }
+
+    /// Limit the number of open connections per client address.
+    ///
+    /// The limit applies to the peer address of the TCP connection. Further connections from the
+    /// same address are accepted and closed right away.
+    pub fn max_connections_per_address(mut self, count: Option<usize>) -> Self {
+        self.max_connections_per_address = count;
+        self
+    }
+
+    /// Set the time a client has to complete the TLS handshake after connecting, defaults to 10
+    /// seconds.
+    pub fn handshake_timeout(mut self, timeout: Duration) -> Self {
+        self.handshake_timeout = timeout;
+        self
+    }
+
+    /// Keep the last `count` failed TLS handshakes, see
+    /// [`ConnectionStats::recent_handshake_failures`], disabled by default.
+    pub fn keep_handshake_failures(self, count: usize) -> Self {
+        self.stats
+            .keep_handshake_failures
+            .store(count, Ordering::Relaxed);
+        self
+    }
+
+    /// Close connections rejected because of a limit with a TCP reset instead of a regular
+    /// shutdown.
+    pub fn reset_rejected_connections(mut self, reset: bool) -> Self {
+        self.reset_rejected = reset;
+        self
+    }
+
+    /// Get the counters of the connections accepted by this builder.
+    pub fn connection_stats(&self) -> Arc<ConnectionStats> {
+        Arc::clone(&self.stats)
+    }
+
     #[cfg(feature = "rate-limited-stream")]
     pub fn rate_limiter_lookup(mut self, lookup_rate_limiter: Arc<LookupRateLimiter>) -> Self {
         self.lookup_rate_limiter = Some(lookup_rate_limiter);
@@ -264,6 +923,8 @@ struct AcceptState {
     peer: SocketAddr,
     acceptor: Arc<Mutex<SslAcceptor>>,
     accept_counter: Arc<()>,
+    handshake_deadline: Instant,
+    stats: Arc<ConnectionStats>,
 }
 
 struct AcceptFlags {
@@ -282,8 +943,9 @@ impl AcceptBuilder {
 
         loop {
             let (socket, peer) = futures::select! {
-                res = self.try_setup_socket(&listener).fuse() => match res {
-                    Ok(socket_peer) => socket_peer,
+                res = self.try_setup_socket(&listener, &accept_counter).fuse() => match res {
+                    Ok(Some(socket_peer)) => socket_peer,
+                    Ok(None) => continue,
                     Err(err) => {
                         log::error!("couldn't set up TCP socket: {err}");
                         continue;
@@ -292,19 +954,13 @@ impl AcceptBuilder {
                 _ = shutdown_future => break,
             };
 
-            let acceptor = Arc::clone(&acceptor);
-            let accept_counter = Arc::clone(&accept_counter);
-
-            if Arc::strong_count(&accept_counter) > self.max_pending_accepts {
-                log::error!("[{peer}] connection rejected - too many open connections");
-                continue;
-            }
-
             let state = AcceptState {
                 socket,
                 peer,
-                acceptor,
-                accept_counter,
+                acceptor: Arc::clone(&acceptor),
+                accept_counter: Arc::clone(&accept_counter),
+                handshake_deadline: Instant::now() + self.handshake_timeout,
+                stats: Arc::clone(&self.stats),
             };
 
             let flags = AcceptFlags {
@@ -331,10 +987,12 @@ impl AcceptBuilder {
         }
     }
 
+    /// Accept the next connection, returns `None` if it was rejected because of a limit.
     async fn try_setup_socket(
         &self,
         listener: &TcpListener,
-    ) -> Result<(InsecureClientStream, SocketAddr), Error> {
+        accept_counter: &Arc<()>,
+    ) -> Result<Option<(InsecureClientStream, SocketAddr)>, Error> {
         let (socket, peer) = match listener.accept().await {
             Ok(connection) => connection,
             Err(error) => {
@@ -342,6 +1000,35 @@ impl AcceptBuilder {
             }
         };
 
+        // keep accepting and close right away when a limit is reached, so clients don't pile up
+        // in the listen backlog
+        let slot = if Arc::strong_count(accept_counter) > self.max_pending_accepts {
+            Err("too many pending connections")
+        } else {
+            self.stats.try_reserve(
+                peer.ip().to_canonical(),
+                self.max_connections,
+                self.max_connections_per_address,
+            )
+        };
+        let slot = match slot {
+            Ok(slot) => slot,
+            Err(reason) => {
+                self.stats.log_rejected(peer, reason);
+                if self.reset_rejected {
+                    // a zero linger time makes close() send a RST
+                    let linger = libc::linger {
+                        l_onoff: 1,
+                        l_linger: 0,
+                    };
+                    if let Err(err) = setsockopt(socket.as_raw_fd(), sockopt::Linger, &linger) {
+                        log::debug!("[{peer}] failed to set SO_LINGER on socket - {err}");
+                    }
+                }
+                return Ok(None);
+            }
+        };
+
         socket
             .set_nodelay(true)
             .with_context(|| format!("[{peer}] error while setting TCP_NODELAY on socket"))?;
@@ -355,7 +1042,12 @@ impl AcceptBuilder {
             None => RateLimitedStream::with_limiter(socket, None, None),
         };
 
-        Ok((socket, peer))
+        let socket = TrackedStream {
+            inner: socket,
+            _slot: slot,
+        };
+
+        Ok(Some((socket, peer)))
     }
 
     async fn do_accept_tls(state: AcceptState, flags: AcceptFlags, secure_sender: ClientSender) {
@@ -390,7 +1082,7 @@ impl AcceptBuilder {
         let mut secure_stream = Box::pin(secure_stream);
 
         let accept_future =
-            tokio::time::timeout(Duration::new(10, 0), secure_stream.as_mut().accept());
+            tokio::time::timeout_at(state.handshake_deadline, secure_stream.as_mut().accept());
 
         let result = accept_future.await;
 
@@ -401,14 +1093,24 @@ impl AcceptBuilder {
                 }
             }
             Ok(Err(err)) => {
-                if flags.is_debug {
-                    log::error!("[{peer}] https handshake failed - {err}");
-                }
+                let class = match err.code() {
+                    ErrorCode::SYSCALL | ErrorCode::ZERO_RETURN => {
+                        HandshakeFailureClass::Connection
+                    }
+                    _ => HandshakeFailureClass::Protocol,
+                };
+                let failure = HandshakeFailure::new(peer, class, err.to_string())
+                    .with_ssl(secure_stream.ssl());
+                state.stats.handshake_failed(failure, flags.is_debug);
             }
             Err(_) => {
-                if flags.is_debug {
-                    log::error!("[{peer}] https handshake timeout");
-                }
+                let failure = HandshakeFailure::new(
+                    peer,
+                    HandshakeFailureClass::Timeout,
+                    "client did not finish the handshake in time".to_string(),
+                )
+                .with_ssl(secure_stream.ssl());
+                state.stats.handshake_failed(failure, flags.is_debug);
             }
         }
 
@@ -421,79 +1123,84 @@ impl AcceptBuilder {
         secure_sender: ClientSender,
         insecure_sender: InsecureClientSender,
     ) {
-        const CLIENT_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
-
         let peer = state.peer;
 
         #[cfg(feature = "rate-limited-stream")]
-        let socket_ref = state.socket.inner();
+        let socket_ref = state.socket.inner().inner();
 
         #[cfg(not(feature = "rate-limited-stream"))]
-        let socket_ref = &state.socket;
+        let socket_ref = state.socket.inner();
 
-        let handshake_res =
-            Self::wait_for_client_tls_handshake(socket_ref, CLIENT_HANDSHAKE_TIMEOUT).await;
+        let handshake_res = tokio::time::timeout_at(
+            state.handshake_deadline,
+            Self::wait_for_client_tls_handshake(socket_ref),
+        )
+        .await;
 
         match handshake_res {
-            Ok(true) => {
+            Ok(Ok(true)) => {
                 Self::do_accept_tls(state, flags, secure_sender).await;
             }
-            Ok(false) => {
+            Ok(Ok(false)) => {
                 let insecure_stream = Box::pin(state.socket);
 
                 if let Err(send_err) = insecure_sender.send(Ok(insecure_stream)).await {
                     log::error!("[{peer}] failed to accept connection - connection channel closed: {send_err}");
                 }
             }
-            Err(err) => {
-                log::error!("[{peer}] failed to check for TLS handshake: {err}");
+            Ok(Err(err)) => {
+                let failure = HandshakeFailure::new(
+                    peer,
+                    HandshakeFailureClass::Connection,
+                    format!("failed to check for TLS handshake: {err:#}"),
+                );
+                state.stats.handshake_failed(failure, flags.is_debug);
+            }
+            Err(_) => {
+                let failure = HandshakeFailure::new(
+                    peer,
+                    HandshakeFailureClass::Timeout,
+                    "timed out while waiting for client to initiate TLS handshake".to_string(),
+                );
+                state.stats.handshake_failed(failure, flags.is_debug);
             }
         }
     }
 
-    async fn wait_for_client_tls_handshake(
-        incoming_stream: &TcpStream,
-        timeout: Duration,
-    ) -> Result<bool, Error> {
+    async fn wait_for_client_tls_handshake(incoming_stream: &TcpStream) -> Result<bool, Error> {
         const HANDSHAKE_BYTES_LEN: usize = 5;
 
-        let future = async {
-            incoming_stream
-                .async_io(tokio::io::Interest::READABLE, || {
-                    let mut buf = [0; HANDSHAKE_BYTES_LEN];
-
-                    // Convert to standard lib TcpStream so we can peek without interfering
-                    // with tokio's internals. Wrap the stream in ManuallyDrop in order to prevent
-                    // the destructor from being called, closing the connection and messing up
-                    // invariants.
-                    let raw_fd = incoming_stream.as_raw_fd();
-                    let std_stream =
-                        unsafe { ManuallyDrop::new(std::net::TcpStream::from_raw_fd(raw_fd)) };
-
-                    let peek_res = std_stream.peek(&mut buf);
-
-                    match peek_res {
-                        // If we didn't get enough bytes, raise an EAGAIN / EWOULDBLOCK which tells
-                        // tokio to await the readiness of the socket again. This should normally
-                        // only be used if the socket isn't actually ready, but is fine to do here
-                        // in our case.
-                        //
-                        // This means we will peek into the stream's queue until we got
-                        // HANDSHAKE_BYTE_LEN bytes or an error.
-                        Ok(peek_len) if peek_len < HANDSHAKE_BYTES_LEN => {
-                            Err(io::ErrorKind::WouldBlock.into())
-                        }
-                        // Either we got Ok(HANDSHAKE_BYTES_LEN) or some error.
-                        res => res.map(|_| contains_tls_handshake_fragment(&buf)),
+        incoming_stream
+            .async_io(tokio::io::Interest::READABLE, || {
+                let mut buf = [0; HANDSHAKE_BYTES_LEN];
+
+                // Convert to standard lib TcpStream so we can peek without interfering
+                // with tokio's internals. Wrap the stream in ManuallyDrop in order to prevent
+                // the destructor from being called, closing the connection and messing up
+                // invariants.
+                let raw_fd = incoming_stream.as_raw_fd();
+                let std_stream =
+                    unsafe { ManuallyDrop::new(std::net::TcpStream::from_raw_fd(raw_fd)) };
+
+                let peek_res = std_stream.peek(&mut buf);
+
+                match peek_res {
+                    // If we didn't get enough bytes, raise an EAGAIN / EWOULDBLOCK which tells
+                    // tokio to await the readiness of the socket again. This should normally
+                    // only be used if the socket isn't actually ready, but is fine to do here
+                    // in our case.
+                    //
+                    // This means we will peek into the stream's queue until we got
+                    // HANDSHAKE_BYTE_LEN bytes or an error.
+                    Ok(peek_len) if peek_len < HANDSHAKE_BYTES_LEN => {
+                        Err(io::ErrorKind::WouldBlock.into())
                     }
-                })
-                .await
-                .context("couldn't peek into incoming TCP stream")
-        };
-
-        tokio::time::timeout(timeout, future)
+                    // Either we got Ok(HANDSHAKE_BYTES_LEN) or some error.
+                    res => res.map(|_| contains_tls_handshake_fragment(&buf)),
+                }
+            })
             .await
-            .context("timed out while waiting for client to initiate TLS handshake")?
+            .context("couldn't peek into incoming TCP stream")
     }
 }
 
@@ -529,3 +1236,230 @@ fn contains_tls_handshake_fragment(buf: &[u8]) -> bool {
 
     buf[0] == 0x16 && buf[1] == 0x3 && (((buf[3] as u16) << 8) + buf[4] as u16) <= CONTENT_SIZE
 }
+
+#[cfg(test)]
+mod test {
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio::sync::mpsc;
+
+    use super::{
+        cert_expires_within, cert_expiry, check_cert_key_match, ensure_self_signed_cert,
+        AcceptBuilder, ConnectionStats, HandshakeFailureClass, SelfSignedParams,
+        TlsAcceptorBuilder,
+    };
+
+    #[test]
+    fn test_self_signed_cert() -> Result<(), anyhow::Error> {
+        use std::os::unix::fs::PermissionsExt;
+
+        use openssl::pkey::PKey;
+        use openssl::x509::X509;
+
+        let dir = proxmox_sys::fs::TempDir::new_in("/tmp", None)?;
+        let cert_path = dir.path().join("proxy.pem");
+        let key_path = dir.path().join("proxy.key");
+
+        let params = SelfSignedParams::new()
+            .common_name("node1.example.com")
+            .dns_name("node1")
+            .dns_name("node1.example.com")
+            .ip_address(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)))
+            .ip_address("2001:db8::1".parse()?);
+        assert!(ensure_self_signed_cert(
+            &cert_path,
+            &key_path,
+            params.clone()
+        )?);
+
+        let cert = X509::from_pem(&std::fs::read(&cert_path)?)?;
+        let key = PKey::private_key_from_pem(&std::fs::read(&key_path)?)?;
+        check_cert_key_match(&cert, &key)?;
+        assert_eq!(
+            std::fs::metadata(&key_path)?.permissions().mode() & 0o777,
+            0o600
+        );
+
+        let names = cert.subject_alt_names().unwrap();
+        let dns: Vec<&str> = names.iter().filter_map(|name| name.dnsname()).collect();
+        assert_eq!(dns, ["node1.example.com", "node1"]);
+        let ips: Vec<&[u8]> = names.iter().filter_map(|name| name.ipaddress()).collect();
+        assert_eq!(ips.len(), 2);
+        assert_eq!(ips[0], [192, 0, 2, 1]);
+        assert_eq!(
+            ips[1],
+            "2001:db8::1".parse::<std::net::Ipv6Addr>()?.octets()
+        );
+
+        let now = proxmox_time::epoch_i64();
+        let days = (cert_expiry(&cert)? - now) / (24 * 60 * 60);
+        assert!((729..=730).contains(&days));
+        assert!(!cert_expires_within(
+            &cert,
+            Duration::from_secs(365 * 24 * 60 * 60)
+        )?);
+        assert!(cert_expires_within(
+            &cert,
+            Duration::from_secs(731 * 24 * 60 * 60)
+        )?);
+
+        // existing files are kept
+        assert!(!ensure_self_signed_cert(
+            &cert_path,
+            &key_path,
+            params.clone()
+        )?);
+        assert_eq!(
+            X509::from_pem(&std::fs::read(&cert_path)?)?.to_der()?,
+            cert.to_der()?
+        );
+
+        // both are recreated if one is missing, so they always match
+        std::fs::remove_file(&key_path)?;
+        assert!(ensure_self_signed_cert(&cert_path, &key_path, params)?);
+        let new_cert = X509::from_pem(&std::fs::read(&cert_path)?)?;
+        let new_key = PKey::private_key_from_pem(&std::fs::read(&key_path)?)?;
+        check_cert_key_match(&new_cert, &new_key)?;
+        assert!(check_cert_key_match(&cert, &new_key).is_err());
+
+        Ok(())
+    }
+
+    /// Wait until the accept task counted a failed handshake of `class`.
+    async fn wait_for_failure(stats: &ConnectionStats, class: HandshakeFailureClass) {
+        for _ in 0..100 {
+            if stats.handshake_failures(class) > 0 {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("no {class} handshake failure was counted");
+    }
+
+    #[test]
+    fn test_handshake_failures() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        rt.block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+
+            let builder = AcceptBuilder::new()
+                .handshake_timeout(Duration::from_millis(200))
+                .keep_handshake_failures(1);
+            let stats = builder.connection_stats();
+
+            let acceptor = Arc::new(Mutex::new(TlsAcceptorBuilder::new().build().unwrap()));
+            let (secure_sender, mut secure_receiver) = mpsc::channel(16);
+            tokio::spawn(builder.accept_connections(listener, acceptor, secure_sender.into()));
+
+            // a plain HTTP client talking to the TLS port
+            let mut client = TcpStream::connect(addr).await.unwrap();
+            let peer = client.local_addr().unwrap();
+            client
+                .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+                .await
+                .unwrap();
+            let mut buf = Vec::new();
+            let _ = client.read_to_end(&mut buf).await;
+            wait_for_failure(&stats, HandshakeFailureClass::Protocol).await;
+
+            assert_eq!(stats.handshake_failures(HandshakeFailureClass::Protocol), 1);
+            assert_eq!(stats.handshake_timeouts(), 0);
+            let failures = stats.recent_handshake_failures();
+            assert_eq!(failures.len(), 1);
+            assert_eq!(failures[0].peer, peer);
+            assert_eq!(failures[0].class, HandshakeFailureClass::Protocol);
+            assert_eq!(failures[0].sni, None);
+            assert!(!failures[0].error.is_empty());
+
+            // a client not sending anything is a distinct class
+            let mut client = TcpStream::connect(addr).await.unwrap();
+            let _ = client.read_to_end(&mut buf).await;
+            wait_for_failure(&stats, HandshakeFailureClass::Timeout).await;
+
+            assert_eq!(stats.handshake_failures(HandshakeFailureClass::Protocol), 1);
+            assert_eq!(stats.handshake_timeouts(), 1);
+            // only the most recent failure is kept
+            let failures = stats.recent_handshake_failures();
+            assert_eq!(failures.len(), 1);
+            assert_eq!(failures[0].class, HandshakeFailureClass::Timeout);
+
+            assert!(secure_receiver.try_recv().is_err());
+            assert_eq!(stats.accepted_connections(), 2);
+        });
+    }
+
+    #[test]
+    fn test_connection_limit_per_address() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        rt.block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let localhost = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+            let builder = AcceptBuilder::new()
+                .max_connections_per_address(Some(4))
+                .reset_rejected_connections(true);
+            let stats = builder.connection_stats();
+
+            let acceptor = Arc::new(Mutex::new(TlsAcceptorBuilder::new().build().unwrap()));
+            let (secure_sender, _secure_receiver) = mpsc::channel(16);
+            let (insecure_sender, mut insecure_receiver) = mpsc::channel(16);
+            tokio::spawn(builder.accept_connections(
+                listener,
+                acceptor,
+                (secure_sender, insecure_sender).into(),
+            ));
+
+            let mut accepted = Vec::new();
+            for i in 0..10 {
+                let mut client = TcpStream::connect(addr).await.unwrap();
+                client.write_all(b"GET / HTTP/1.0\r\n\r\n").await.unwrap();
+
+                if i < 4 {
+                    let conn = insecure_receiver.recv().await.unwrap().unwrap();
+                    accepted.push((client, conn));
+                } else {
+                    // rejected connections are closed without being handed out
+                    let mut buf = [0u8; 1];
+                    assert!(!matches!(client.read(&mut buf).await, Ok(n) if n > 0));
+                }
+            }
+
+            assert_eq!(stats.active_connections(), 4);
+            assert_eq!(stats.active_connections_from(localhost), 4);
+            assert_eq!(stats.accepted_connections(), 4);
+            assert_eq!(stats.rejected_connections(), 6);
+            assert!(insecure_receiver.try_recv().is_err());
+
+            // closing a connection makes room for a new one
+            drop(accepted.pop());
+            assert_eq!(stats.active_connections_from(localhost), 3);
+
+            let mut client = TcpStream::connect(addr).await.unwrap();
+            client.write_all(b"GET / HTTP/1.0\r\n\r\n").await.unwrap();
+            let conn = insecure_receiver.recv().await.unwrap().unwrap();
+            accepted.push((client, conn));
+
+            assert_eq!(stats.active_connections(), 4);
+            assert_eq!(stats.accepted_connections(), 5);
+            assert_eq!(stats.rejected_connections(), 6);
+
+            drop(accepted);
+            assert_eq!(stats.active_connections(), 0);
+            assert_eq!(stats.active_connections_from(localhost), 0);
+        });
+    }
+}
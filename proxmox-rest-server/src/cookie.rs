@@ -0,0 +1,301 @@
+//! Authentication cookie policy.
+
+use std::fmt;
+
+use anyhow::{bail, format_err, Error};
+use http::{HeaderMap, HeaderValue};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+
+/// Characters percent encoded in cookie values, like JavaScript's `encodeURIComponent`.
+const COOKIE_VALUE_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'!')
+    .remove(b'~')
+    .remove(b'*')
+    .remove(b'\'')
+    .remove(b'(')
+    .remove(b')');
+
+/// The `SameSite` attribute of a cookie.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SameSite {
+    /// Only send the cookie for same-site requests.
+    Strict,
+    /// Also send the cookie for top-level navigation from other sites.
+    Lax,
+    /// Send the cookie for cross-site requests too, requires the cookie to be `Secure`.
+    None,
+}
+
+impl fmt::Display for SameSite {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            SameSite::Strict => "Strict",
+            SameSite::Lax => "Lax",
+            SameSite::None => "None",
+        })
+    }
+}
+
+/// Attributes of the authentication cookie.
+///
+/// The default attributes are `Path=/; SameSite=Lax; Secure`. Use [`validate`](Self::validate)
+/// to check for combinations browsers would reject, [`ApiConfig::auth_cookie_policy`] does this
+/// automatically.
+///
+/// [`ApiConfig::auth_cookie_policy`]: crate::ApiConfig::auth_cookie_policy
+#[derive(Clone, Debug)]
+pub struct CookiePolicy {
+    name: String,
+    legacy_names: Vec<String>,
+    same_site: Option<SameSite>,
+    secure: bool,
+    http_only: bool,
+    path: String,
+}
+
+impl CookiePolicy {
+    /// Create a policy for a cookie called `name`.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            legacy_names: Vec::new(),
+            same_site: Some(SameSite::Lax),
+            secure: true,
+            http_only: false,
+            path: "/".to_string(),
+        }
+    }
+
+    /// Also accept the cookie under a previous name, for example during a rename transition.
+    pub fn legacy_name(mut self, name: impl Into<String>) -> Self {
+        self.legacy_names.push(name.into());
+        self
+    }
+
+    /// Set the `SameSite` attribute, `None` omits the attribute.
+    pub fn same_site(mut self, same_site: Option<SameSite>) -> Self {
+        self.same_site = same_site;
+        self
+    }
+
+    /// Set the `Secure` flag.
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+
+    /// Set the `HttpOnly` flag.
+    pub fn http_only(mut self, http_only: bool) -> Self {
+        self.http_only = http_only;
+        self
+    }
+
+    /// Set the `Path` attribute.
+    pub fn path(mut self, path: impl Into<String>) -> Self {
+        self.path = path.into();
+        self
+    }
+
+    /// The cookie name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Check the policy for invalid attribute combinations.
+    ///
+    /// This rejects `SameSite=None` without `Secure`, and enforces the rules for the `__Host-`
+    /// and `__Secure-` name prefixes.
+    pub fn validate(&self) -> Result<(), Error> {
+        for name in std::iter::once(&self.name).chain(&self.legacy_names) {
+            if name.is_empty() || !name.bytes().all(is_cookie_name_char) {
+                bail!("invalid cookie name {name:?}");
+            }
+        }
+
+        if !self.path.starts_with('/') || !self.path.bytes().all(is_cookie_path_char) {
+            bail!("invalid cookie path {:?}", self.path);
+        }
+
+        if self.same_site == Some(SameSite::None) && !self.secure {
+            bail!(
+                "cookie '{}' with 'SameSite=None' must be 'Secure'",
+                self.name
+            );
+        }
+
+        if self.name.starts_with("__Host-") {
+            if !self.secure {
+                bail!(
+                    "cookie '{}' with '__Host-' prefix must be 'Secure'",
+                    self.name
+                );
+            }
+            if self.path != "/" {
+                bail!(
+                    "cookie '{}' with '__Host-' prefix must use 'Path=/'",
+                    self.name
+                );
+            }
+        } else if self.name.starts_with("__Secure-") && !self.secure {
+            bail!(
+                "cookie '{}' with '__Secure-' prefix must be 'Secure'",
+                self.name
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Build a `Set-Cookie` header value setting the cookie to `value`.
+    pub fn set_cookie(&self, value: &str) -> Result<HeaderValue, Error> {
+        let value = utf8_percent_encode(value, COOKIE_VALUE_ENCODE_SET);
+        self.header_value(&format!("{}={value}", self.name))
+    }
+
+    /// Build a `Set-Cookie` header value removing the cookie.
+    pub fn clear_cookie(&self) -> Result<HeaderValue, Error> {
+        self.header_value(&format!("{}=; Max-Age=0", self.name))
+    }
+
+    fn header_value(&self, cookie: &str) -> Result<HeaderValue, Error> {
+        let mut header = format!("{cookie}; Path={}", self.path);
+        if let Some(same_site) = self.same_site {
+            header.push_str(&format!("; SameSite={same_site}"));
+        }
+        if self.secure {
+            header.push_str("; Secure");
+        }
+        if self.http_only {
+            header.push_str("; HttpOnly");
+        }
+
+        HeaderValue::from_str(&header).map_err(|err| format_err!("invalid cookie header - {err}"))
+    }
+
+    /// Extract the cookie from the request headers, trying the legacy names after the current
+    /// name.
+    pub fn extract(&self, headers: &HeaderMap) -> Option<String> {
+        let cookie = headers.get(http::header::COOKIE)?.to_str().ok()?;
+        let names: Vec<&str> = std::iter::once(&self.name)
+            .chain(&self.legacy_names)
+            .map(String::as_str)
+            .collect();
+        crate::extract_cookie_any(cookie, &names)
+    }
+}
+
+// token characters as defined in RFC 9110
+fn is_cookie_name_char(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b)
+}
+
+fn is_cookie_path_char(b: u8) -> bool {
+    (0x20..0x7f).contains(&b) && b != b';'
+}
+
+#[cfg(test)]
+mod test {
+    use http::{header, HeaderMap, HeaderValue};
+
+    use proxmox_router::RpcEnvironmentType;
+
+    use super::{CookiePolicy, SameSite};
+    use crate::ApiConfig;
+
+    fn set_cookie(policy: &CookiePolicy) -> String {
+        policy.validate().unwrap();
+        policy
+            .set_cookie("PBS:root@pam:1234::a+b/c=")
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn test_set_cookie_header() {
+        let policy = CookiePolicy::new("PBSAuthCookie");
+        assert_eq!(
+            set_cookie(&policy),
+            "PBSAuthCookie=PBS%3Aroot%40pam%3A1234%3A%3Aa%2Bb%2Fc%3D; Path=/; SameSite=Lax; Secure",
+        );
+
+        let policy = CookiePolicy::new("__Host-PBSAuthCookie")
+            .same_site(Some(SameSite::Strict))
+            .http_only(true);
+        assert_eq!(
+            set_cookie(&policy),
+            "__Host-PBSAuthCookie=PBS%3Aroot%40pam%3A1234%3A%3Aa%2Bb%2Fc%3D; Path=/; \
+            SameSite=Strict; Secure; HttpOnly",
+        );
+
+        let policy = CookiePolicy::new("auth").same_site(Some(SameSite::None));
+        assert_eq!(
+            set_cookie(&policy),
+            "auth=PBS%3Aroot%40pam%3A1234%3A%3Aa%2Bb%2Fc%3D; Path=/; SameSite=None; Secure",
+        );
+
+        let policy = CookiePolicy::new("auth")
+            .same_site(None)
+            .secure(false)
+            .path("/api2");
+        assert_eq!(
+            set_cookie(&policy),
+            "auth=PBS%3Aroot%40pam%3A1234%3A%3Aa%2Bb%2Fc%3D; Path=/api2",
+        );
+        assert_eq!(
+            policy.clear_cookie().unwrap(),
+            "auth=; Max-Age=0; Path=/api2"
+        );
+    }
+
+    #[test]
+    fn test_invalid_policies() {
+        let invalid = [
+            CookiePolicy::new("auth")
+                .same_site(Some(SameSite::None))
+                .secure(false),
+            CookiePolicy::new("__Host-auth").secure(false),
+            CookiePolicy::new("__Host-auth").path("/api2"),
+            CookiePolicy::new("__Secure-auth").secure(false),
+            CookiePolicy::new(""),
+            CookiePolicy::new("auth;x"),
+            CookiePolicy::new("auth").legacy_name("old name"),
+            CookiePolicy::new("auth").path("api2"),
+            CookiePolicy::new("auth").path("/a;b"),
+        ];
+        for policy in invalid {
+            assert!(policy.validate().is_err(), "{policy:?} should be invalid");
+            let config = ApiConfig::new("/", RpcEnvironmentType::PUBLIC);
+            assert!(config.auth_cookie_policy(policy).is_err());
+        }
+
+        assert!(CookiePolicy::new("__Secure-auth")
+            .path("/api2")
+            .validate()
+            .is_ok());
+    }
+
+    #[test]
+    fn test_extract_cookie_names() {
+        let policy = CookiePolicy::new("__Host-auth").legacy_name("auth");
+
+        let mut headers = HeaderMap::new();
+        assert_eq!(policy.extract(&headers), None);
+
+        headers.insert(
+            header::COOKIE,
+            HeaderValue::from_static("other=1; auth=old%3Aticket"),
+        );
+        assert_eq!(policy.extract(&headers).as_deref(), Some("old:ticket"));
+
+        headers.insert(
+            header::COOKIE,
+            HeaderValue::from_static("auth=old%3Aticket; __Host-auth=new%3Aticket"),
+        );
+        assert_eq!(policy.extract(&headers).as_deref(), Some("new:ticket"));
+    }
+}
@@ -0,0 +1,342 @@
+//! CSRF prevention tokens.
+//!
+//! Browsers send the authentication cookie along with every request, so state changing requests
+//! authenticated by a cookie must also carry a `CSRFPreventionToken` header. The token is handed
+//! out at login and binds the user to a server secret and the time it was created.
+//!
+//! Enable the check with [`ApiConfig::csrf_protection`](crate::ApiConfig::csrf_protection) and
+//! mint tokens at login with [`CsrfProtection::assemble_token`].
+
+use std::sync::Mutex;
+
+use anyhow::{bail, format_err, Error};
+use http::{HeaderMap, Method};
+use openssl::hash::MessageDigest;
+use openssl::pkey::PKey;
+use openssl::sign::Signer;
+
+/// Name of the header carrying the CSRF prevention token.
+pub const CSRF_HEADER_NAME: &str = "CSRFPreventionToken";
+
+struct CsrfSecrets {
+    current: Vec<u8>,
+    /// The previous secret and the epoch until which tokens created with it are accepted.
+    previous: Option<(Vec<u8>, i64)>,
+}
+
+/// Creates and verifies CSRF prevention tokens.
+///
+/// A token is valid for [`validity`](Self::validity) seconds after its creation (two hours by
+/// default), and tokens up to [`clock_skew`](Self::clock_skew) seconds from the future (five
+/// minutes by default) are accepted to tolerate clock differences between cluster nodes.
+pub struct CsrfProtection {
+    secrets: Mutex<CsrfSecrets>,
+    validity: i64,
+    clock_skew: i64,
+}
+
+impl CsrfProtection {
+    /// Create a new instance using `secret` to sign tokens.
+    pub fn new(secret: impl Into<Vec<u8>>) -> Self {
+        Self {
+            secrets: Mutex::new(CsrfSecrets {
+                current: secret.into(),
+                previous: None,
+            }),
+            validity: 2 * 3600,
+            clock_skew: 300,
+        }
+    }
+
+    /// Set for how many seconds tokens are valid.
+    pub fn validity(mut self, seconds: i64) -> Self {
+        self.validity = seconds;
+        self
+    }
+
+    /// Set how many seconds a token's timestamp may lie in the future.
+    pub fn clock_skew(mut self, seconds: i64) -> Self {
+        self.clock_skew = seconds;
+        self
+    }
+
+    /// Replace the secret used to sign new tokens.
+    ///
+    /// Tokens signed with the previous secret are still accepted for `grace_period` seconds.
+    pub fn rotate_secret(&self, secret: impl Into<Vec<u8>>, grace_period: i64) {
+        self.rotate_secret_at(secret.into(), grace_period, proxmox_time::epoch_i64())
+    }
+
+    fn rotate_secret_at(&self, secret: Vec<u8>, grace_period: i64, now: i64) {
+        let mut secrets = self.secrets.lock().unwrap();
+        let previous = std::mem::replace(&mut secrets.current, secret);
+        secrets.previous = Some((previous, now + grace_period));
+    }
+
+    /// Create a token for `userid`, to be handed out at login.
+    pub fn assemble_token(&self, userid: &str) -> Result<String, Error> {
+        self.assemble_token_at(userid, proxmox_time::epoch_i64())
+    }
+
+    fn assemble_token_at(&self, userid: &str, now: i64) -> Result<String, Error> {
+        let secrets = self.secrets.lock().unwrap();
+        let signature = sign(&secrets.current, now, userid)?;
+        Ok(format!(
+            "{now:08X}:{}",
+            base64::encode_config(signature, base64::STANDARD_NO_PAD)
+        ))
+    }
+
+    /// Verify that `token` was created for `userid` and is still valid.
+    pub fn verify_token(&self, userid: &str, token: &str) -> Result<(), Error> {
+        self.verify_token_at(userid, token, proxmox_time::epoch_i64())
+            .map_err(|err| format_err!("invalid CSRF prevention token - {err}"))
+    }
+
+    fn verify_token_at(&self, userid: &str, token: &str, now: i64) -> Result<(), Error> {
+        let (timestamp, signature) = token
+            .split_once(':')
+            .filter(|(_, signature)| !signature.contains(':'))
+            .ok_or_else(|| format_err!("format error"))?;
+
+        let timestamp = i64::from_str_radix(timestamp, 16)
+            .map_err(|err| format_err!("timestamp format error - {err}"))?;
+        let signature = base64::decode_config(signature, base64::STANDARD_NO_PAD)
+            .map_err(|err| format_err!("signature format error - {err}"))?;
+
+        let secrets = self.secrets.lock().unwrap();
+        let mut valid = signature_matches(&secrets.current, timestamp, userid, &signature)?;
+        if !valid {
+            if let Some((previous, valid_until)) = &secrets.previous {
+                valid = now <= *valid_until
+                    && signature_matches(previous, timestamp, userid, &signature)?;
+            }
+        }
+        if !valid {
+            bail!("invalid signature");
+        }
+
+        let age = now - timestamp;
+        if age < -self.clock_skew {
+            bail!("timestamp newer than expected");
+        }
+        if age > self.validity {
+            bail!("timestamp too old");
+        }
+
+        Ok(())
+    }
+
+    /// Check the token of a request authenticated by cookie, methods other than the safe `GET`,
+    /// `HEAD` and `OPTIONS` need one.
+    pub(crate) fn check_request(
+        &self,
+        headers: &HeaderMap,
+        method: &Method,
+        userid: &str,
+    ) -> Result<(), Error> {
+        if matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS) {
+            return Ok(());
+        }
+
+        match headers.get(CSRF_HEADER_NAME).map(|value| value.to_str()) {
+            Some(Ok(token)) => self.verify_token(userid, token),
+            Some(Err(_)) => bail!("invalid CSRF prevention token header"),
+            None => bail!("missing CSRF prevention token"),
+        }
+    }
+}
+
+fn sign(secret: &[u8], timestamp: i64, userid: &str) -> Result<Vec<u8>, Error> {
+    let key = PKey::hmac(secret)?;
+    let mut signer = Signer::new(MessageDigest::sha3_256(), &key)?;
+    signer.update(format!("{timestamp:08X}:{userid}:").as_bytes())?;
+    Ok(signer.sign_to_vec()?)
+}
+
+fn signature_matches(
+    secret: &[u8],
+    timestamp: i64,
+    userid: &str,
+    signature: &[u8],
+) -> Result<bool, Error> {
+    let expected = sign(secret, timestamp, userid)?;
+    Ok(expected.len() == signature.len() && openssl::memcmp::eq(&expected, signature))
+}
+
+#[cfg(test)]
+mod test {
+    use http::{header, HeaderMap, HeaderValue, Method};
+
+    use proxmox_router::{RpcEnvironmentType, UserInformation};
+
+    use super::{CsrfProtection, CSRF_HEADER_NAME};
+    use crate::rest::EmptyUserInformation;
+    use crate::{ApiConfig, AuthError, AuthHandler, AuthSource, CookiePolicy};
+
+    const NOW: i64 = 1_700_000_000;
+
+    #[test]
+    fn test_csrf_token_expiry() {
+        let csrf = CsrfProtection::new(b"secret".to_vec()).validity(3600);
+        let token = csrf.assemble_token_at("user@pam", NOW).unwrap();
+
+        csrf.verify_token_at("user@pam", &token, NOW).unwrap();
+        csrf.verify_token_at("user@pam", &token, NOW + 3600)
+            .unwrap();
+        assert!(csrf
+            .verify_token_at("user@pam", &token, NOW + 3601)
+            .is_err());
+
+        // tolerate clock skew between nodes
+        csrf.verify_token_at("user@pam", &token, NOW - 300).unwrap();
+        assert!(csrf.verify_token_at("user@pam", &token, NOW - 301).is_err());
+
+        assert!(csrf.verify_token_at("user@pam", "garbage", NOW).is_err());
+        assert!(csrf
+            .verify_token_at("user@pam", "6553F100:AAAA", NOW)
+            .is_err());
+    }
+
+    #[test]
+    fn test_csrf_token_wrong_user() {
+        let csrf = CsrfProtection::new(b"secret".to_vec());
+        let token = csrf.assemble_token_at("user@pam", NOW).unwrap();
+
+        assert!(csrf.verify_token_at("other@pam", &token, NOW).is_err());
+        assert!(csrf.verify_token_at("user@pve", &token, NOW).is_err());
+
+        let other = CsrfProtection::new(b"other secret".to_vec());
+        assert!(other.verify_token_at("user@pam", &token, NOW).is_err());
+    }
+
+    #[test]
+    fn test_csrf_token_rotated_secret() {
+        let csrf = CsrfProtection::new(b"old secret".to_vec());
+        let old_token = csrf.assemble_token_at("user@pam", NOW).unwrap();
+
+        csrf.rotate_secret_at(b"new secret".to_vec(), 600, NOW + 60);
+        let new_token = csrf.assemble_token_at("user@pam", NOW + 60).unwrap();
+        assert_ne!(old_token, new_token);
+
+        csrf.verify_token_at("user@pam", &old_token, NOW + 660)
+            .unwrap();
+        assert!(csrf
+            .verify_token_at("user@pam", &old_token, NOW + 661)
+            .is_err());
+        csrf.verify_token_at("user@pam", &new_token, NOW + 661)
+            .unwrap();
+
+        // only the directly preceding secret is kept
+        csrf.rotate_secret_at(b"newest secret".to_vec(), 600, NOW + 120);
+        assert!(csrf
+            .verify_token_at("user@pam", &old_token, NOW + 120)
+            .is_err());
+        csrf.verify_token_at("user@pam", &new_token, NOW + 120)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_csrf_check_request() {
+        let csrf = CsrfProtection::new(b"secret".to_vec());
+        let token = csrf.assemble_token("user@pam").unwrap();
+
+        let mut headers = HeaderMap::new();
+        for method in [Method::GET, Method::HEAD, Method::OPTIONS] {
+            csrf.check_request(&headers, &method, "user@pam").unwrap();
+        }
+        assert!(csrf
+            .check_request(&headers, &Method::POST, "user@pam")
+            .is_err());
+
+        headers.insert(CSRF_HEADER_NAME, HeaderValue::from_str(&token).unwrap());
+        csrf.check_request(&headers, &Method::POST, "user@pam")
+            .unwrap();
+        assert!(csrf
+            .check_request(&headers, &Method::DELETE, "other@pam")
+            .is_err());
+    }
+
+    #[test]
+    fn test_csrf_api_config() {
+        let csrf = CsrfProtection::new(b"secret".to_vec());
+        let token = csrf.assemble_token("user@pam").unwrap();
+
+        let config = ApiConfig::new("/", RpcEnvironmentType::PUBLIC)
+            .auth_handler_func(|_headers, _method| {
+                Box::pin(async {
+                    let info: Box<dyn UserInformation + Send + Sync> =
+                        Box::new(EmptyUserInformation {});
+                    Ok(("user@pam".to_string(), info))
+                })
+            })
+            .auth_cookie_policy(CookiePolicy::new("auth"))
+            .unwrap()
+            .csrf_protection(csrf);
+
+        let check = |headers: &HeaderMap, method: Method| {
+            futures::executor::block_on(config.check_auth(headers, &method))
+                .map(|(auth_id, _)| auth_id)
+        };
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, HeaderValue::from_static("ticket"));
+        assert!(check(&headers, Method::POST).is_ok());
+
+        headers.insert(header::COOKIE, HeaderValue::from_static("auth=ticket"));
+        assert!(check(&headers, Method::GET).is_ok());
+        assert!(matches!(
+            check(&headers, Method::POST),
+            Err(AuthError::Generic(_))
+        ));
+
+        headers.insert(CSRF_HEADER_NAME, HeaderValue::from_str(&token).unwrap());
+        assert_eq!(
+            check(&headers, Method::PUT).ok().as_deref(),
+            Some("user@pam")
+        );
+    }
+
+    #[test]
+    fn test_csrf_auth_source() {
+        let csrf = CsrfProtection::new(b"secret".to_vec());
+
+        // authenticates by the header if present, even if the cookie is sent too
+        let config = ApiConfig::new("/", RpcEnvironmentType::PUBLIC)
+            .auth_handler(AuthHandler::from_fn_with_source(|headers, _method| {
+                let source = match headers.contains_key(header::AUTHORIZATION) {
+                    true => AuthSource::Header,
+                    false => AuthSource::Cookie,
+                };
+                Box::pin(async move {
+                    let info: Box<dyn UserInformation + Send + Sync> =
+                        Box::new(EmptyUserInformation {});
+                    Ok(("user@pam".to_string(), info, source))
+                })
+            }))
+            .csrf_protection(csrf);
+
+        let check = |headers: &HeaderMap, method: Method| {
+            futures::executor::block_on(config.check_auth(headers, &method))
+                .map(|(auth_id, _)| auth_id)
+        };
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::COOKIE, HeaderValue::from_static("auth=ticket"));
+        headers.insert(header::AUTHORIZATION, HeaderValue::from_static("token"));
+        assert!(check(&headers, Method::POST).is_ok());
+
+        headers.remove(header::AUTHORIZATION);
+        assert!(matches!(
+            check(&headers, Method::POST),
+            Err(AuthError::Generic(_))
+        ));
+
+        // the source counts, not the presence of a cookie
+        headers.remove(header::COOKIE);
+        assert!(matches!(
+            check(&headers, Method::POST),
+            Err(AuthError::Generic(_))
+        ));
+    }
+}
@@ -0,0 +1,220 @@
+//! Headers added to every response, e.g. security headers.
+
+use http::header::{self, HeaderMap, HeaderName, HeaderValue};
+
+#[derive(Clone, Debug)]
+struct DefaultHeader {
+    name: HeaderName,
+    value: HeaderValue,
+    tls_only: bool,
+}
+
+#[derive(Clone, Debug)]
+struct PathOverride {
+    prefix: String,
+    name: HeaderName,
+    value: Option<HeaderValue>,
+}
+
+/// Headers added to every response of the server, including error responses, the index page and
+/// static files, see [`ApiConfig::default_headers`].
+///
+/// Headers set by the handler of a request are never replaced. Per-path overrides allow to relax
+/// headers for parts of the server, e.g. to allow framing an embedded console:
+///
+/// ```
+/// # use proxmox_rest_server::DefaultHeaders;
+/// let headers = DefaultHeaders::security().allow_framing("/novnc");
+/// ```
+///
+/// [`ApiConfig::default_headers`]: crate::ApiConfig::default_headers
+#[derive(Clone, Debug, Default)]
+pub struct DefaultHeaders {
+    headers: Vec<DefaultHeader>,
+    overrides: Vec<PathOverride>,
+}
+
+impl DefaultHeaders {
+    /// Create an empty set of headers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The recommended security headers:
+    ///
+    /// * `X-Content-Type-Options: nosniff`
+    /// * `X-Frame-Options: SAMEORIGIN` and `Content-Security-Policy: frame-ancestors 'self'`
+    /// * `Referrer-Policy: same-origin`
+    /// * `Strict-Transport-Security: max-age=63072000`, only on TLS connections
+    pub fn security() -> Self {
+        Self::new()
+            .header(
+                header::X_CONTENT_TYPE_OPTIONS,
+                HeaderValue::from_static("nosniff"),
+            )
+            .header(
+                header::X_FRAME_OPTIONS,
+                HeaderValue::from_static("SAMEORIGIN"),
+            )
+            .header(
+                header::CONTENT_SECURITY_POLICY,
+                HeaderValue::from_static("frame-ancestors 'self'"),
+            )
+            .header(
+                header::REFERRER_POLICY,
+                HeaderValue::from_static("same-origin"),
+            )
+            .tls_header(
+                header::STRICT_TRANSPORT_SECURITY,
+                HeaderValue::from_static("max-age=63072000"),
+            )
+    }
+
+    /// Add a header to all responses, replacing a previously added header of the same name.
+    pub fn header(self, name: HeaderName, value: HeaderValue) -> Self {
+        self.add(name, value, false)
+    }
+
+    /// Add a header to responses on TLS connections, e.g. `Strict-Transport-Security`.
+    pub fn tls_header(self, name: HeaderName, value: HeaderValue) -> Self {
+        self.add(name, value, true)
+    }
+
+    fn add(mut self, name: HeaderName, value: HeaderValue, tls_only: bool) -> Self {
+        self.headers.retain(|header| header.name != name);
+        self.headers.push(DefaultHeader {
+            name,
+            value,
+            tls_only,
+        });
+        self
+    }
+
+    /// Use `value` for the header `name` on responses for `prefix` and the paths below it, or
+    /// omit the header if `value` is `None`.
+    ///
+    /// Overrides apply regardless of the connection type. If several overrides match a path,
+    /// the one added last wins.
+    pub fn path_override<P: Into<String>>(
+        mut self,
+        prefix: P,
+        name: HeaderName,
+        value: Option<HeaderValue>,
+    ) -> Self {
+        self.overrides.push(PathOverride {
+            prefix: prefix.into(),
+            name,
+            value,
+        });
+        self
+    }
+
+    /// Omit the headers preventing framing (`X-Frame-Options` and `Content-Security-Policy`) on
+    /// responses for `prefix` and the paths below it.
+    pub fn allow_framing<P: Into<String>>(self, prefix: P) -> Self {
+        let prefix = prefix.into();
+        self.path_override(prefix.clone(), header::X_FRAME_OPTIONS, None)
+            .path_override(prefix, header::CONTENT_SECURITY_POLICY, None)
+    }
+
+    /// Add the headers for a response to a request for `path`, keeping headers which are
+    /// already set.
+    pub(crate) fn apply(&self, path: &str, tls: bool, headers: &mut HeaderMap) {
+        let names = self
+            .headers
+            .iter()
+            .map(|header| &header.name)
+            .chain(self.overrides.iter().map(|o| &o.name));
+
+        let mut seen: Vec<&HeaderName> = Vec::new();
+        for name in names {
+            if seen.contains(&name) {
+                continue;
+            }
+            seen.push(name);
+
+            if headers.contains_key(name) {
+                continue;
+            }
+            if let Some(value) = self.lookup(name, path, tls) {
+                headers.insert(name.clone(), value.clone());
+            }
+        }
+    }
+
+    fn lookup(&self, name: &HeaderName, path: &str, tls: bool) -> Option<&HeaderValue> {
+        let path_override = self
+            .overrides
+            .iter()
+            .rev()
+            .find(|o| o.name == name && is_below(path, &o.prefix));
+        if let Some(path_override) = path_override {
+            return path_override.value.as_ref();
+        }
+
+        self.headers
+            .iter()
+            .find(|header| header.name == name && (tls || !header.tls_only))
+            .map(|header| &header.value)
+    }
+}
+
+fn is_below(path: &str, prefix: &str) -> bool {
+    let prefix = prefix.trim_end_matches('/');
+    path.starts_with(prefix) && matches!(path.as_bytes().get(prefix.len()), None | Some(b'/'))
+}
+
+#[cfg(test)]
+mod test {
+    use http::header::{self, HeaderMap, HeaderValue};
+
+    use super::DefaultHeaders;
+
+    fn apply(headers: &DefaultHeaders, path: &str, tls: bool) -> HeaderMap {
+        let mut map = HeaderMap::new();
+        headers.apply(path, tls, &mut map);
+        map
+    }
+
+    #[test]
+    fn test_default_headers() {
+        let headers = DefaultHeaders::security()
+            .allow_framing("/novnc/")
+            .path_override(
+                "/novnc/public",
+                header::REFERRER_POLICY,
+                Some(HeaderValue::from_static("no-referrer")),
+            );
+
+        let map = apply(&headers, "/api2/json/version", false);
+        assert_eq!(map[header::X_CONTENT_TYPE_OPTIONS], "nosniff");
+        assert_eq!(map[header::X_FRAME_OPTIONS], "SAMEORIGIN");
+        assert_eq!(
+            map[header::CONTENT_SECURITY_POLICY],
+            "frame-ancestors 'self'"
+        );
+        assert_eq!(map[header::REFERRER_POLICY], "same-origin");
+        assert!(!map.contains_key(header::STRICT_TRANSPORT_SECURITY));
+
+        let map = apply(&headers, "/api2/json/version", true);
+        assert_eq!(map[header::STRICT_TRANSPORT_SECURITY], "max-age=63072000");
+
+        for path in ["/novnc", "/novnc/app.js", "/novnc/public/index.html"] {
+            let map = apply(&headers, path, true);
+            assert!(!map.contains_key(header::X_FRAME_OPTIONS), "{path}");
+            assert!(!map.contains_key(header::CONTENT_SECURITY_POLICY), "{path}");
+            assert_eq!(map[header::X_CONTENT_TYPE_OPTIONS], "nosniff");
+        }
+        assert!(apply(&headers, "/novnc-other", false).contains_key(header::X_FRAME_OPTIONS));
+
+        let map = apply(&headers, "/novnc/public/index.html", false);
+        assert_eq!(map[header::REFERRER_POLICY], "no-referrer");
+
+        // headers set by handlers win
+        let mut map = HeaderMap::new();
+        map.insert(header::X_FRAME_OPTIONS, HeaderValue::from_static("DENY"));
+        headers.apply("/", false, &mut map);
+        assert_eq!(map[header::X_FRAME_OPTIONS], "DENY");
+        assert_eq!(map.get_all(header::X_FRAME_OPTIONS).iter().count(), 1);
+    }
+}
@@ -0,0 +1,119 @@
+//! Detection of clients going away before receiving their response.
+
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use anyhow::{format_err, Error};
+use futures::future::{self, Either};
+use tokio::sync::watch;
+
+struct DisconnectState {
+    disconnected: watch::Sender<bool>,
+    keep_running: AtomicBool,
+}
+
+/// Signals that the client of a request disconnected before its response was ready.
+///
+/// The handlers of `AsyncHttp` API methods are dropped when their client disconnects, unless
+/// they call [`keep_running`](ClientDisconnect::keep_running), e.g. because they spawned a
+/// worker task and still have to store its UPID. For all handlers, hyper drops the request and
+/// thus the handler future when it notices that the connection is gone, and streamed responses
+/// end with the connection. Work running elsewhere, e.g. in a blocking thread, can use the
+/// signal to stop early.
+///
+/// API handlers get the signal of their request with [`RestEnvironment::client_disconnected`],
+/// `AsyncHttp` handlers also from the extensions of the request parts.
+///
+/// [`RestEnvironment::client_disconnected`]: crate::RestEnvironment::client_disconnected
+#[derive(Clone)]
+pub struct ClientDisconnect {
+    state: Arc<DisconnectState>,
+}
+
+impl ClientDisconnect {
+    /// Create a signal which is only raised by its [`DisconnectGuard`]s.
+    pub(crate) fn new() -> Self {
+        Self {
+            state: Arc::new(DisconnectState {
+                disconnected: watch::channel(false).0,
+                keep_running: AtomicBool::new(false),
+            }),
+        }
+    }
+
+    /// Whether the client disconnected.
+    pub fn is_disconnected(&self) -> bool {
+        *self.state.disconnected.borrow()
+    }
+
+    /// Wait until the client disconnected, which may never happen.
+    pub async fn disconnected(&self) {
+        let mut receiver = self.state.disconnected.subscribe();
+        let _ = receiver.wait_for(|&disconnected| disconnected).await;
+    }
+
+    /// Keep the handler running when the client disconnects.
+    pub fn keep_running(&self) {
+        self.state.keep_running.store(true, Ordering::Release);
+    }
+
+    /// Whether the handler keeps running when the client disconnects.
+    pub fn keeps_running(&self) -> bool {
+        self.state.keep_running.load(Ordering::Acquire)
+    }
+
+    /// Get a guard raising the signal when it is dropped before being disarmed.
+    pub(crate) fn guard(&self) -> DisconnectGuard {
+        DisconnectGuard {
+            disconnect: Some(self.clone()),
+        }
+    }
+
+    /// Run a handler in its own task, so it can outlive the request if it keeps running.
+    ///
+    /// Otherwise it is dropped as soon as the client disconnected.
+    pub(crate) async fn run<F, T>(&self, handler: F) -> Result<T, Error>
+    where
+        F: Future<Output = Result<T, Error>> + Send + 'static,
+        T: Send + 'static,
+    {
+        let disconnect = self.clone();
+        let task = tokio::spawn(async move {
+            let handler = std::pin::pin!(handler);
+            let disconnected = std::pin::pin!(disconnect.disconnected());
+            match future::select(handler, disconnected).await {
+                Either::Left((result, _)) => Some(result),
+                Either::Right(((), handler)) if disconnect.keeps_running() => Some(handler.await),
+                Either::Right(_) => None,
+            }
+        });
+
+        match task.await {
+            Ok(Some(result)) => result,
+            Ok(None) => Err(format_err!("client disconnected")),
+            Err(err) if err.is_panic() => std::panic::resume_unwind(err.into_panic()),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+/// Raises the [`ClientDisconnect`] signal when dropped, unless it was disarmed.
+pub(crate) struct DisconnectGuard {
+    disconnect: Option<ClientDisconnect>,
+}
+
+impl DisconnectGuard {
+    /// The response was delivered, so the client did not disconnect prematurely.
+    pub fn disarm(mut self) {
+        self.disconnect = None;
+    }
+}
+
+impl Drop for DisconnectGuard {
+    fn drop(&mut self) {
+        if let Some(disconnect) = self.disconnect.take() {
+            disconnect.state.disconnected.send_replace(true);
+        }
+    }
+}
@@ -3,9 +3,9 @@ use std::sync::Arc;
 
 use serde_json::{json, Value};
 
-use proxmox_router::{RpcEnvironment, RpcEnvironmentType};
+use proxmox_router::{RpcEnvironment, RpcEnvironmentType, UserInformation};
 
-use crate::ApiConfig;
+use crate::{ApiConfig, ClientDisconnect};
 
 /// Encapsulates information about the runtime environment
 pub struct RestEnvironment {
@@ -13,6 +13,8 @@ pub struct RestEnvironment {
     result_attributes: Value,
     auth_id: Option<String>,
     client_ip: Option<SocketAddr>,
+    user_info: Option<Arc<dyn UserInformation + Send + Sync>>,
+    client_disconnect: ClientDisconnect,
     api: Arc<ApiConfig>,
 }
 
@@ -22,6 +24,8 @@ impl RestEnvironment {
             result_attributes: json!({}),
             auth_id: None,
             client_ip: None,
+            user_info: None,
+            client_disconnect: ClientDisconnect::new(),
             env_type,
             api,
         }
@@ -31,6 +35,15 @@ impl RestEnvironment {
         &self.api
     }
 
+    pub(crate) fn set_client_disconnect(&mut self, client_disconnect: ClientDisconnect) {
+        self.client_disconnect = client_disconnect;
+    }
+
+    /// The signal raised when the client disconnects before the response is ready.
+    pub fn client_disconnected(&self) -> &ClientDisconnect {
+        &self.client_disconnect
+    }
+
     pub fn log_auth(&self, auth_id: &str) {
         let msg = format!("successful auth for user '{}'", auth_id);
         log::debug!("{}", msg); // avoid noisy syslog, admins can already check the auth log
@@ -39,6 +52,17 @@ impl RestEnvironment {
         }
     }
 
+    pub(crate) fn log_ticket_renewal(&self, auth_id: &str) {
+        let msg = match self.client_ip {
+            Some(peer) => format!("renewed ticket for user '{auth_id}'; rhost={peer}"),
+            None => format!("renewed ticket for user '{auth_id}'; rhost=unknown"),
+        };
+        log::debug!("{}", msg);
+        if let Some(auth_logger) = self.api.get_auth_log() {
+            auth_logger.lock().unwrap().log(&msg);
+        }
+    }
+
     pub fn log_failed_auth(&self, failed_auth_id: Option<String>, msg: &str) {
         let msg = match (self.client_ip, failed_auth_id) {
             (Some(peer), Some(user)) => {
@@ -95,4 +119,12 @@ impl RpcEnvironment for RestEnvironment {
     fn get_client_ip(&self) -> Option<SocketAddr> {
         self.client_ip
     }
+
+    fn set_user_info(&mut self, user_info: Option<Arc<dyn UserInformation + Send + Sync>>) {
+        self.user_info = user_info;
+    }
+
+    fn get_user_info(&self) -> Option<Arc<dyn UserInformation + Send + Sync>> {
+        self.user_info.clone()
+    }
 }
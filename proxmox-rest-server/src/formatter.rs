@@ -1,4 +1,8 @@
 //! Helpers to format response data
+//!
+//! Products can implement their own response envelope with the [`OutputFormatter`] trait and
+//! the helpers in this module, and register it with
+//! [`ApiConfig::register_formatter`](crate::ApiConfig::register_formatter).
 use std::collections::HashMap;
 
 use anyhow::Error;
@@ -8,12 +12,54 @@ use hyper::header;
 use hyper::{Body, Response, StatusCode};
 
 use proxmox_router::{HttpError, RpcEnvironment, SerializableReturn};
-use proxmox_schema::ParameterError;
+use proxmox_schema::i18n::translate;
+use proxmox_schema::{ParameterError, ParameterErrorDetail};
 
 /// Extension to set error message for server side logging
 pub(crate) struct ErrorMessageExtension(pub String);
 
 /// Methods to format data and errors
+///
+/// A formatter which adds a field to the responses of the built-in `json` format:
+///
+/// ```
+/// # use anyhow::Error;
+/// # use hyper::{Body, Response};
+/// # use serde_json::{json, Value};
+/// # use proxmox_router::{RpcEnvironment, SerializableReturn};
+/// use proxmox_rest_server::formatter::{
+///     add_etag_header, add_result_attributes, error_to_response, json_data_response,
+///     json_data_response_streaming, OutputFormatter,
+/// };
+///
+/// struct ProductFormatter;
+///
+/// impl OutputFormatter for ProductFormatter {
+///     fn format_data(&self, data: Value, rpcenv: &dyn RpcEnvironment) -> Response<Body> {
+///         let mut result = json!({ "data": data, "product": "example" });
+///         add_result_attributes(&mut result, rpcenv);
+///         let mut response = json_data_response(result);
+///         add_etag_header(&mut response, rpcenv);
+///         response
+///     }
+///
+///     fn format_data_streaming(
+///         &self,
+///         data: Box<dyn SerializableReturn + Send>,
+///         rpcenv: &dyn RpcEnvironment,
+///     ) -> Result<Response<Body>, Error> {
+///         let mut value = json!({ "product": "example" });
+///         add_result_attributes(&mut value, rpcenv);
+///         let mut response = json_data_response_streaming(value, data)?;
+///         add_etag_header(&mut response, rpcenv);
+///         Ok(response)
+///     }
+///
+///     fn format_error(&self, err: Error) -> Response<Body> {
+///         error_to_response(err)
+///     }
+/// }
+/// ```
 pub trait OutputFormatter: Send + Sync {
     /// Transform json data into a http response
     fn format_data(&self, data: Value, rpcenv: &dyn RpcEnvironment) -> Response<Body>;
@@ -43,7 +89,16 @@ pub trait OutputFormatter: Send + Sync {
 
 static JSON_CONTENT_TYPE: &str = "application/json;charset=UTF-8";
 
-fn json_data_response(data: Value) -> Response<Body> {
+/// Set the error message logged for the request, for responses to failed requests which are not
+/// created by [`error_to_response`].
+pub fn set_error_message(response: &mut Response<Body>, message: impl Into<String>) {
+    response
+        .extensions_mut()
+        .insert(ErrorMessageExtension(message.into()));
+}
+
+/// Create an ``application/json`` response.
+pub fn json_data_response(data: Value) -> Response<Body> {
     let json_str = data.to_string();
 
     let raw = json_str.into_bytes();
@@ -57,7 +112,16 @@ fn json_data_response(data: Value) -> Response<Body> {
     response
 }
 
-fn json_data_response_streaming(body: Body) -> Result<Response<Body>, Error> {
+/// Create a streaming ``application/json`` response.
+///
+/// `data` is serialized as `data` property of the `value` object, or on its own if `value` is
+/// `null`.
+pub fn json_data_response_streaming(
+    value: Value,
+    data: Box<dyn SerializableReturn + Send>,
+) -> Result<Response<Body>, Error> {
+    let reader = start_data_streaming(value, data);
+    let body = Body::wrap_stream(tokio_stream::wrappers::ReceiverStream::new(reader));
     let response = Response::builder()
         .header(
             header::CONTENT_TYPE,
@@ -67,7 +131,23 @@ fn json_data_response_streaming(body: Body) -> Result<Response<Body>, Error> {
     Ok(response)
 }
 
-fn add_result_attributes(result: &mut Value, rpcenv: &dyn RpcEnvironment) {
+/// Emit the `digest` result attribute set by the handler as a strong `ETag`.
+pub fn add_etag_header(response: &mut Response<Body>, rpcenv: &dyn RpcEnvironment) {
+    let digest = match rpcenv.result_attrib()["digest"].as_str() {
+        Some(digest) => digest,
+        None => return,
+    };
+
+    match header::HeaderValue::from_str(&format!("\"{digest}\"")) {
+        Ok(value) => {
+            response.headers_mut().insert(header::ETAG, value);
+        }
+        Err(err) => log::warn!("unable to use digest {digest:?} as ETag - {err}"),
+    }
+}
+
+/// Add the result attributes set on `rpcenv` to the `result` object.
+pub fn add_result_attributes(result: &mut Value, rpcenv: &dyn RpcEnvironment) {
     let attributes = match rpcenv.result_attrib().as_object() {
         Some(attr) => attr,
         None => return,
@@ -98,24 +178,27 @@ struct DirectJsonFormatter;
 
 /// Format data directly as ``application/json``.
 ///
-/// This does not support result attributes set on `rpcenv`.
+/// This does not support result attributes set on `rpcenv`, except for the `digest` attribute,
+/// which is emitted as `ETag` header.
 ///
 /// Errors generates a BAD_REQUEST containing the error message as string.
 pub static DIRECT_JSON_FORMATTER: &'static dyn OutputFormatter = &DirectJsonFormatter;
 
 impl OutputFormatter for DirectJsonFormatter {
-    fn format_data(&self, data: Value, _rpcenv: &dyn RpcEnvironment) -> Response<Body> {
-        json_data_response(data)
+    fn format_data(&self, data: Value, rpcenv: &dyn RpcEnvironment) -> Response<Body> {
+        let mut response = json_data_response(data);
+        add_etag_header(&mut response, rpcenv);
+        response
     }
 
     fn format_data_streaming(
         &self,
         data: Box<dyn SerializableReturn + Send>,
-        _rpcenv: &dyn RpcEnvironment,
+        rpcenv: &dyn RpcEnvironment,
     ) -> Result<Response<Body>, Error> {
-        let reader = start_data_streaming(Value::Null, data);
-        let stream = tokio_stream::wrappers::ReceiverStream::new(reader);
-        json_data_response_streaming(Body::wrap_stream(stream))
+        let mut response = json_data_response_streaming(Value::Null, data)?;
+        add_etag_header(&mut response, rpcenv);
+        Ok(response)
     }
 
     fn format_error(&self, err: Error) -> Response<Body> {
@@ -131,7 +214,8 @@ struct JsonFormatter();
 ///
 /// * ``data``: The result data (on success)
 ///
-/// Any result attributes set on ``rpcenv`` are also added to the object.
+/// Any result attributes set on ``rpcenv`` are also added to the object. A ``digest`` attribute
+/// is additionally emitted as ``ETag`` header.
 ///
 /// Errors generates a BAD_REQUEST containing the error
 /// message as string.
@@ -143,7 +227,9 @@ impl OutputFormatter for JsonFormatter {
 
         add_result_attributes(&mut result, rpcenv);
 
-        json_data_response(result)
+        let mut response = json_data_response(result);
+        add_etag_header(&mut response, rpcenv);
+        response
     }
 
     fn format_data_streaming(
@@ -155,10 +241,9 @@ impl OutputFormatter for JsonFormatter {
 
         add_result_attributes(&mut value, rpcenv);
 
-        let reader = start_data_streaming(value, data);
-        let stream = tokio_stream::wrappers::ReceiverStream::new(reader);
-
-        json_data_response_streaming(Body::wrap_stream(stream))
+        let mut response = json_data_response_streaming(value, data)?;
+        add_etag_header(&mut response, rpcenv);
+        Ok(response)
     }
 
     fn format_error(&self, err: Error) -> Response<Body> {
@@ -166,7 +251,9 @@ impl OutputFormatter for JsonFormatter {
     }
 }
 
-pub(crate) fn error_to_response(err: Error) -> Response<Body> {
+/// Create a plain error response: the error message as body, with the status code of a
+/// [`HttpError`] or `400 Bad Request` otherwise.
+pub fn error_to_response(err: Error) -> Response<Body> {
     let mut response = if let Some(apierr) = err.downcast_ref::<HttpError>() {
         let mut resp = Response::new(Body::from(apierr.message.clone()));
         *resp.status_mut() = apierr.code;
@@ -182,13 +269,103 @@ pub(crate) fn error_to_response(err: Error) -> Response<Body> {
         header::HeaderValue::from_static(JSON_CONTENT_TYPE),
     );
 
-    response
-        .extensions_mut()
-        .insert(ErrorMessageExtension(err.to_string()));
+    set_error_message(&mut response, err.to_string());
 
     response
 }
 
+/// The details of an API error for formatting it.
+pub struct ErrorDetails {
+    /// The status of a [`HttpError`], `400 Bad Request` otherwise.
+    pub status: StatusCode,
+    /// The error message, translated if a schema translator is registered.
+    pub message: String,
+    /// The errors of the individual parameters of a [`ParameterError`], translated like the
+    /// message.
+    pub errors: HashMap<String, String>,
+    english: String,
+    details: Vec<ParameterErrorDetail>,
+}
+
+impl ErrorDetails {
+    /// The untranslated English message.
+    pub fn english(&self) -> &str {
+        &self.english
+    }
+
+    /// The individual parameter errors with their English message and identifier.
+    pub fn parameter_details(&self) -> &[ParameterErrorDetail] {
+        &self.details
+    }
+
+    /// Whether the message or any parameter error differs from its English text.
+    pub fn is_translated(&self) -> bool {
+        self.message != self.english
+            || self
+                .details
+                .iter()
+                .any(|detail| detail.message != detail.english)
+    }
+
+    /// The untranslated message and parameter errors, with the identifiers of built-in
+    /// validation errors, so clients can match errors independent of the language:
+    ///
+    /// ```json
+    /// { "message": "...", "errors": { "name": { "message": "...", "id": "too-long" } } }
+    /// ```
+    pub fn untranslated(&self) -> Value {
+        let errors: serde_json::Map<String, Value> = self
+            .details
+            .iter()
+            .map(|detail| {
+                let mut error = json!({ "message": detail.english });
+                if let Some(id) = detail.id {
+                    error["id"] = id.into();
+                }
+                (detail.name.clone(), error)
+            })
+            .collect();
+        json!({ "message": self.english, "errors": errors })
+    }
+}
+
+impl From<Error> for ErrorDetails {
+    fn from(err: Error) -> Self {
+        let err = match err.downcast::<ParameterError>() {
+            Ok(param_err) => {
+                let details = param_err.details();
+                return Self {
+                    status: StatusCode::BAD_REQUEST,
+                    message: translate(PARAMETER_ERRORS_MESSAGE).into_owned(),
+                    errors: details
+                        .iter()
+                        .map(|detail| (detail.name.clone(), detail.message.clone()))
+                        .collect(),
+                    english: PARAMETER_ERRORS_MESSAGE.to_string(),
+                    details,
+                };
+            }
+            Err(err) => err,
+        };
+
+        let status = match err.downcast_ref::<HttpError>() {
+            Some(apierr) => apierr.code,
+            None => StatusCode::BAD_REQUEST,
+        };
+
+        let message = err.to_string();
+        Self {
+            status,
+            english: message.clone(),
+            message,
+            errors: HashMap::new(),
+            details: Vec::new(),
+        }
+    }
+}
+
+const PARAMETER_ERRORS_MESSAGE: &str = "parameter verification errors";
+
 /// Format data as ExtJS compatible ``application/json``
 ///
 /// The returned json object contains the following properties:
@@ -203,6 +380,9 @@ pub(crate) fn error_to_response(err: Error) -> Response<Body> {
 ///
 /// * ``errors``: detailed list of errors (if available)
 ///
+/// * ``untranslated``: The untranslated message and errors, see [`ErrorDetails::untranslated`]
+///   (on failure, if translated)
+///
 /// Any result attributes set on ``rpcenv`` are also added to the object.
 ///
 /// Please note that errors return a HTTP response with status code OK, but setting success
@@ -222,7 +402,9 @@ impl OutputFormatter for ExtJsFormatter {
 
         add_result_attributes(&mut result, rpcenv);
 
-        json_data_response(result)
+        let mut response = json_data_response(result);
+        add_etag_header(&mut response, rpcenv);
+        response
     }
 
     fn format_data_streaming(
@@ -237,50 +419,202 @@ impl OutputFormatter for ExtJsFormatter {
 
         add_result_attributes(&mut value, rpcenv);
 
-        let reader = start_data_streaming(value, data);
-        let stream = tokio_stream::wrappers::ReceiverStream::new(reader);
-
-        json_data_response_streaming(Body::wrap_stream(stream))
+        let mut response = json_data_response_streaming(value, data)?;
+        add_etag_header(&mut response, rpcenv);
+        Ok(response)
     }
 
     fn format_error(&self, err: Error) -> Response<Body> {
-        let mut errors = HashMap::new();
-
-        let (message, status) = if err.is::<ParameterError>() {
-            match err.downcast::<ParameterError>() {
-                Ok(param_err) => {
-                    for (name, err) in param_err {
-                        errors.insert(name, err.to_string());
-                    }
-                    (
-                        String::from("parameter verification errors"),
-                        StatusCode::BAD_REQUEST,
-                    )
-                }
-                Err(err) => (err.to_string(), StatusCode::BAD_REQUEST),
-            }
-        } else {
-            let status = if let Some(apierr) = err.downcast_ref::<HttpError>() {
-                apierr.code
-            } else {
-                StatusCode::BAD_REQUEST
-            };
-            (err.to_string(), status)
-        };
+        let details = ErrorDetails::from(err);
 
-        let result = json!({
-            "message": message,
-            "errors": errors,
+        let mut result = json!({
+            "message": details.message,
+            "errors": details.errors,
             "success": false,
-            "status": status.as_u16(),
+            "status": details.status.as_u16(),
         });
+        if details.is_translated() {
+            result["untranslated"] = details.untranslated();
+        }
 
         let mut response = json_data_response(result);
-
+        // the server log is not translated
+        set_error_message(&mut response, details.english);
         response
-            .extensions_mut()
-            .insert(ErrorMessageExtension(message));
+    }
+}
 
-        response
+/// An output format of formatted routers, selected by the path prefix (e.g. `/api2/json`) or by
+/// the `Accept` header of the request.
+#[derive(Clone)]
+pub(crate) struct OutputFormat {
+    pub name: &'static str,
+    pub media_type: Option<&'static str>,
+    pub formatter: &'static dyn OutputFormatter,
+}
+
+/// The output formats known to the server, see
+/// [`ApiConfig::output_format`](crate::ApiConfig::output_format).
+#[derive(Clone)]
+pub(crate) struct OutputFormats(Vec<OutputFormat>);
+
+impl Default for OutputFormats {
+    fn default() -> Self {
+        Self(vec![
+            OutputFormat {
+                name: "json",
+                media_type: Some("application/json"),
+                formatter: JSON_FORMATTER,
+            },
+            OutputFormat {
+                name: "extjs",
+                media_type: Some("application/x-extjs-json"),
+                formatter: EXTJS_FORMATTER,
+            },
+        ])
+    }
+}
+
+impl OutputFormats {
+    /// Add a format, replacing a previously added format of the same name.
+    pub fn register(&mut self, format: OutputFormat) {
+        self.0.retain(|known| known.name != format.name);
+        self.0.push(format);
+    }
+
+    /// Replace the formatter of the format `name`, or add a format without media type.
+    pub fn set_formatter(&mut self, name: &'static str, formatter: &'static dyn OutputFormatter) {
+        match self.0.iter_mut().find(|known| known.name == name) {
+            Some(known) => known.formatter = formatter,
+            None => self.0.push(OutputFormat {
+                name,
+                media_type: None,
+                formatter,
+            }),
+        }
+    }
+
+    /// Select the output format for a request to the format `prefix`.
+    ///
+    /// The media types in the `Accept` header are matched against the known formats, the one with
+    /// the highest quality wins. Without a match, e.g. for unknown or wildcard media types, the
+    /// format of the prefix is used. Returns `None` if the prefix is no known format.
+    pub fn negotiate(
+        &self,
+        prefix: &str,
+        accept: Option<&header::HeaderValue>,
+    ) -> Option<&OutputFormat> {
+        let default = self.0.iter().find(|format| format.name == prefix)?;
+
+        let accept = match accept.map(|accept| accept.to_str()) {
+            Some(Ok(accept)) => accept,
+            _ => return Some(default),
+        };
+
+        let mut best: Option<(f32, &OutputFormat)> = None;
+        for (media_type, quality) in parse_accept(accept) {
+            let format = match self
+                .0
+                .iter()
+                .find(|format| format.media_type == Some(media_type.as_str()))
+            {
+                Some(format) => format,
+                None => continue,
+            };
+            let better = match best {
+                None => true,
+                Some((best_quality, best_format)) => {
+                    quality > best_quality
+                        || (quality == best_quality
+                            && format.name == default.name
+                            && best_format.name != default.name)
+                }
+            };
+            if better {
+                best = Some((quality, format));
+            }
+        }
+
+        Some(best.map(|(_, format)| format).unwrap_or(default))
+    }
+}
+
+/// Parse the media types of an `Accept` header along with their quality, skipping unacceptable
+/// ones.
+fn parse_accept(accept: &str) -> impl Iterator<Item = (String, f32)> + '_ {
+    accept.split(',').filter_map(|range| {
+        let mut parts = range.split(';');
+        let media_type = parts.next()?.trim().to_ascii_lowercase();
+
+        let mut quality = 1.0;
+        for param in parts {
+            if let Some((key, value)) = param.split_once('=') {
+                if key.trim().eq_ignore_ascii_case("q") {
+                    quality = value.trim().parse().ok()?;
+                }
+            }
+        }
+
+        (quality > 0.0 && !media_type.is_empty()).then_some((media_type, quality))
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use hyper::header::HeaderValue;
+
+    use super::{OutputFormat, OutputFormats, JSON_FORMATTER};
+
+    fn negotiate(
+        formats: &OutputFormats,
+        prefix: &str,
+        accept: Option<&str>,
+    ) -> Option<&'static str> {
+        let accept = accept.map(HeaderValue::from_str).transpose().unwrap();
+        formats
+            .negotiate(prefix, accept.as_ref())
+            .map(|format| format.name)
+    }
+
+    #[test]
+    fn test_negotiate_output_format() {
+        let mut formats = OutputFormats::default();
+        formats.register(OutputFormat {
+            name: "compact",
+            media_type: Some("application/vnd.proxmox.compact+json"),
+            formatter: JSON_FORMATTER,
+        });
+
+        // the prefix is the default
+        assert_eq!(negotiate(&formats, "json", None), Some("json"));
+        assert_eq!(negotiate(&formats, "extjs", None), Some("extjs"));
+        assert_eq!(negotiate(&formats, "xml", None), None);
+        assert_eq!(negotiate(&formats, "xml", Some("application/json")), None);
+
+        // known media types win over the prefix
+        let accept = Some("application/json");
+        assert_eq!(negotiate(&formats, "extjs", accept), Some("json"));
+        let accept = Some("application/x-extjs-json");
+        assert_eq!(negotiate(&formats, "json", accept), Some("extjs"));
+        let accept = Some("application/vnd.proxmox.compact+json; charset=utf-8");
+        assert_eq!(negotiate(&formats, "json", accept), Some("compact"));
+
+        // unknown and wildcard media types fall back to the prefix
+        for accept in [
+            "text/html",
+            "*/*",
+            "application/*",
+            "application/json;q=0",
+            "b;q=x",
+        ] {
+            assert_eq!(negotiate(&formats, "extjs", Some(accept)), Some("extjs"));
+        }
+
+        // the quality decides, ties prefer the prefix
+        let accept = Some("application/json;q=0.5, application/x-extjs-json");
+        assert_eq!(negotiate(&formats, "json", accept), Some("extjs"));
+        let accept = Some("application/json, application/x-extjs-json, text/html");
+        assert_eq!(negotiate(&formats, "extjs", accept), Some("extjs"));
+        assert_eq!(negotiate(&formats, "compact", accept), Some("json"));
     }
 }
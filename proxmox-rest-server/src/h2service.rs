@@ -58,18 +58,25 @@ impl<E: RpcEnvironment + Clone> H2Service<E> {
 
         let formatter = JSON_FORMATTER;
 
-        match self.router.find_method(&components, method, &mut uri_param) {
+        match self
+            .router
+            .find_method_with_hooks(&components, method, &mut uri_param)
+        {
             None => {
                 let err = http_err!(NOT_FOUND, "Path '{}' not found.", path);
                 future::ok(formatter.format_error(err)).boxed()
             }
-            Some(api_method) => crate::rest::handle_api_request(
+            Some((api_method, hooks)) => crate::rest::handle_api_request(
                 self.rpcenv.clone(),
                 api_method,
                 Some(formatter),
                 parts,
                 body,
                 uri_param,
+                hooks,
+                None,
+                None,
+                None,
             )
             .boxed(),
         }
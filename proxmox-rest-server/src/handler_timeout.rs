@@ -0,0 +1,149 @@
+//! Timeouts of API handlers by the expected duration of their method.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use http::Method;
+
+use proxmox_router::{ApiMethod, ExpectedDuration};
+
+/// Timeouts of API handlers by the [`ExpectedDuration`] of their method.
+///
+/// Asynchronous handlers not finishing in time are cancelled and the request fails with status
+/// 503. Synchronous handlers cannot be interrupted and are not limited. Requests which may change
+/// something, i.e. all but `GET` and `HEAD` requests, are not limited either, so that their
+/// handlers are never cancelled half way through. Classes without a timeout are not limited.
+#[derive(Clone, Debug, Default)]
+pub struct HandlerTimeouts {
+    // `None` for classes explicitly set to be unlimited
+    timeouts: HashMap<ExpectedDuration, Option<Duration>>,
+}
+
+impl HandlerTimeouts {
+    /// Create the timeouts without any limits.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The default timeouts: 30 seconds for [`Fast`](ExpectedDuration::Fast) and
+    /// [`Task`](ExpectedDuration::Task) methods, which only have to start their worker task, and 5
+    /// minutes for [`Slow`](ExpectedDuration::Slow) methods. Streaming methods, including those
+    /// handling the HTTP request themselves, are not limited.
+    pub fn with_defaults() -> Self {
+        Self::new()
+            .timeout(ExpectedDuration::Fast, Some(Duration::from_secs(30)))
+            .timeout(ExpectedDuration::Slow, Some(Duration::from_secs(300)))
+            .timeout(ExpectedDuration::Task, Some(Duration::from_secs(30)))
+    }
+
+    /// Set the timeout of a class, `None` to not limit it.
+    pub fn timeout(mut self, class: ExpectedDuration, timeout: Option<Duration>) -> Self {
+        self.timeouts.insert(class, timeout);
+        self
+    }
+
+    /// The timeout of a class.
+    pub fn get(&self, class: ExpectedDuration) -> Option<Duration> {
+        self.timeouts.get(&class).copied().flatten()
+    }
+
+    /// Override the timeouts with those of the classes set in `other`, including those set to
+    /// be unlimited.
+    pub fn merge(mut self, other: HandlerTimeouts) -> Self {
+        self.timeouts.extend(other.timeouts);
+        self
+    }
+
+    /// The timeout for calls of `method`, see [`ApiMethod::duration_class`].
+    pub fn for_method(&self, method: &ApiMethod) -> Option<Duration> {
+        self.get(method.duration_class())
+    }
+
+    /// The timeout for a request calling `method`, none for requests which may change something.
+    pub fn for_request(&self, http_method: &Method, method: &ApiMethod) -> Option<Duration> {
+        match *http_method {
+            Method::GET | Method::HEAD => self.for_method(method),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use anyhow::Error;
+    use hyper::http::request::Parts;
+    use hyper::Body;
+    use serde_json::Value;
+
+    use proxmox_router::{ApiHandler, ApiResponseFuture, RpcEnvironment};
+    use proxmox_schema::upid::UPID_SCHEMA;
+    use proxmox_schema::{ObjectSchema, ReturnType};
+
+    use super::*;
+
+    fn handler(_: Value, _: &ApiMethod, _: &mut dyn RpcEnvironment) -> Result<Value, Error> {
+        Ok(Value::Null)
+    }
+
+    fn upload(
+        _parts: Parts,
+        _body: Body,
+        _param: Value,
+        _info: &ApiMethod,
+        _rpcenv: Box<dyn RpcEnvironment>,
+    ) -> ApiResponseFuture {
+        Box::pin(async { Ok(hyper::Response::new(Body::empty())) })
+    }
+
+    const PARAMETERS: ObjectSchema = ObjectSchema::new("Test method.", &[]);
+
+    #[test]
+    fn test_timeout_selection() {
+        let timeouts = HandlerTimeouts::with_defaults()
+            .timeout(ExpectedDuration::Task, Some(Duration::from_secs(10)))
+            .timeout(ExpectedDuration::Fast, None);
+
+        let fast = ApiMethod::new(&ApiHandler::Sync(&handler), &PARAMETERS);
+        assert_eq!(timeouts.for_method(&fast), None);
+
+        let task = ApiMethod::new(&ApiHandler::Sync(&handler), &PARAMETERS)
+            .returns(ReturnType::new(false, &UPID_SCHEMA));
+        assert_eq!(timeouts.for_method(&task), Some(Duration::from_secs(10)));
+
+        let slow = task.expected_duration(ExpectedDuration::Slow);
+        assert_eq!(timeouts.for_method(&slow), Some(Duration::from_secs(300)));
+        assert_eq!(
+            timeouts.for_request(&Method::GET, &slow),
+            Some(Duration::from_secs(300))
+        );
+        // handlers which may change something are never cancelled
+        for http_method in [Method::POST, Method::PUT, Method::DELETE] {
+            assert_eq!(timeouts.for_request(&http_method, &slow), None);
+        }
+
+        let streaming = fast.expected_duration(ExpectedDuration::Streaming);
+        assert_eq!(timeouts.for_method(&streaming), None);
+
+        let merged = timeouts.clone().merge(
+            HandlerTimeouts::new()
+                .timeout(ExpectedDuration::Slow, None)
+                .timeout(ExpectedDuration::Streaming, Some(Duration::from_secs(600))),
+        );
+        assert_eq!(merged.get(ExpectedDuration::Fast), None);
+        assert_eq!(merged.get(ExpectedDuration::Slow), None);
+        assert_eq!(
+            merged.get(ExpectedDuration::Task),
+            Some(Duration::from_secs(10))
+        );
+        assert_eq!(
+            merged.get(ExpectedDuration::Streaming),
+            Some(Duration::from_secs(600))
+        );
+
+        // reading the request body may take a while
+        let upload = ApiMethod::new(&ApiHandler::AsyncHttp(&upload), &PARAMETERS);
+        assert_eq!(upload.duration_class(), ExpectedDuration::Streaming);
+        let timeouts = HandlerTimeouts::with_defaults();
+        assert_eq!(timeouts.for_method(&upload), None);
+    }
+}
@@ -0,0 +1,196 @@
+//! Built-in liveness and readiness endpoints for load balancers and service probes.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Error;
+use hyper::header;
+use hyper::{Body, Method, Response, StatusCode};
+use serde_json::{json, Value};
+
+use proxmox_sys::linux::procfs::{read_proc_uptime, CLOCK_TICKS};
+
+use crate::rest::NoLogExtension;
+use crate::MaintenanceState;
+
+type CheckFuture = Pin<Box<dyn Future<Output = Result<(), Error>> + Send>>;
+type CheckFn = Arc<dyn Fn() -> CheckFuture + Send + Sync>;
+
+struct ReadinessCheck {
+    name: String,
+    timeout: Duration,
+    check: CheckFn,
+}
+
+/// Options for the health endpoints, see [`ApiConfig::enable_health_endpoints`].
+///
+/// The liveness endpoint (`/health` by default) answers with the version and the uptime of the
+/// process in seconds as long as the server is responsive. The readiness endpoint (`/ready` by
+/// default) answers with `503 Service Unavailable` while a shutdown or reload drains the server,
+/// or if one of the readiness checks fails. Both never require authentication and are not
+/// access-logged by default.
+///
+/// The liveness endpoint also reports the number of worker tasks, see
+/// [`worker_task_stats`](crate::worker_task_stats). If a [`Maintenance`](crate::Maintenance) is
+/// configured, it reports its state as well.
+///
+/// [`ApiConfig::enable_health_endpoints`]: crate::ApiConfig::enable_health_endpoints
+pub struct HealthOptions {
+    version: String,
+    liveness_path: String,
+    readiness_path: String,
+    access_log: bool,
+    checks: Vec<ReadinessCheck>,
+}
+
+impl HealthOptions {
+    /// Create the options with the `version` reported by the liveness endpoint.
+    pub fn new(version: impl Into<String>) -> Self {
+        Self {
+            version: version.into(),
+            liveness_path: "/health".to_string(),
+            readiness_path: "/ready".to_string(),
+            access_log: false,
+            checks: Vec::new(),
+        }
+    }
+
+    /// Change the paths of the liveness and readiness endpoints.
+    pub fn paths(mut self, liveness: &str, readiness: &str) -> Self {
+        // compared with normalized request paths
+        let normalize = |path: &str| {
+            path.split('/')
+                .filter(|comp| !comp.is_empty())
+                .map(|comp| format!("/{comp}"))
+                .collect()
+        };
+        self.liveness_path = normalize(liveness);
+        self.readiness_path = normalize(readiness);
+        self
+    }
+
+    /// Write requests to the health endpoints to the access log.
+    pub fn access_log(mut self, enabled: bool) -> Self {
+        self.access_log = enabled;
+        self
+    }
+
+    /// Add a readiness check, e.g. for a database connection. The server is only ready if all
+    /// checks succeed within their `timeout`.
+    ///
+    /// The checks run concurrently on every request to the readiness endpoint, so they should be
+    /// cheap.
+    pub fn readiness_check<F, Fut>(mut self, name: &str, timeout: Duration, check: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), Error>> + Send + 'static,
+    {
+        self.checks.push(ReadinessCheck {
+            name: name.to_string(),
+            timeout,
+            check: Arc::new(move || Box::pin(check())),
+        });
+        self
+    }
+
+    /// Answer `GET` and `HEAD` requests to the health endpoints, `None` for other requests.
+    pub(crate) async fn handle_request(
+        &self,
+        method: &Method,
+        path: &str,
+        maintenance: Option<MaintenanceState>,
+    ) -> Option<Response<Body>> {
+        if *method != Method::GET && *method != Method::HEAD {
+            return None;
+        }
+
+        let (status, data) = if path == self.liveness_path {
+            (StatusCode::OK, self.liveness(maintenance))
+        } else if path == self.readiness_path {
+            self.readiness().await
+        } else {
+            return None;
+        };
+
+        let body = match *method {
+            Method::HEAD => Body::empty(),
+            _ => data.to_string().into(),
+        };
+        let mut response = Response::builder()
+            .status(status)
+            .header(header::CONTENT_TYPE, "application/json;charset=UTF-8")
+            .header(header::CACHE_CONTROL, "no-store")
+            .body(body)
+            .unwrap();
+        if !self.access_log {
+            response.extensions_mut().insert(NoLogExtension());
+        }
+        Some(response)
+    }
+
+    fn liveness(&self, maintenance: Option<MaintenanceState>) -> Value {
+        let mut data = json!({
+            "status": "ok",
+            "version": self.version,
+            "uptime": process_uptime(),
+            "tasks": crate::worker_task_stats(),
+        });
+        if let Some(maintenance) = maintenance {
+            data["maintenance"] = serde_json::to_value(maintenance).unwrap_or_default();
+        }
+        data
+    }
+
+    async fn readiness(&self) -> (StatusCode, Value) {
+        let results = futures::future::join_all(self.checks.iter().map(run_check)).await;
+        let failed = results.iter().any(|result| result["status"] != "ok");
+
+        let status = if proxmox_daemon::is_shutdown_requested() {
+            "draining"
+        } else if failed {
+            "unavailable"
+        } else {
+            "ready"
+        };
+        let code = match status {
+            "ready" => StatusCode::OK,
+            _ => StatusCode::SERVICE_UNAVAILABLE,
+        };
+
+        (code, json!({ "status": status, "checks": results }))
+    }
+}
+
+async fn run_check(check: &ReadinessCheck) -> Value {
+    let start = Instant::now();
+    let result = tokio::time::timeout(check.timeout, (check.check)()).await;
+    let duration_ms = start.elapsed().as_millis() as u64;
+
+    let (status, message) = match result {
+        Ok(Ok(())) => ("ok", None),
+        Ok(Err(err)) => ("failed", Some(err.to_string())),
+        Err(_) => (
+            "timeout",
+            Some(format!("no result within {}ms", check.timeout.as_millis())),
+        ),
+    };
+
+    let mut result = json!({
+        "name": check.name,
+        "status": status,
+        "duration-ms": duration_ms,
+    });
+    if let Some(message) = message {
+        result["message"] = message.into();
+    }
+    result
+}
+
+/// Seconds since the start of the process, `None` if `/proc/uptime` cannot be read.
+fn process_uptime() -> Option<u64> {
+    let (uptime, _idle) = read_proc_uptime().ok()?;
+    let started = crate::pstart() as f64 / *CLOCK_TICKS;
+    Some((uptime - started).max(0.0) as u64)
+}
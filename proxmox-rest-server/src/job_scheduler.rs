@@ -0,0 +1,330 @@
+//! Periodic background jobs, run as worker tasks.
+//!
+//! Jobs are registered with a [`JobSchedule`] and a function starting a [`WorkerTask`], so each
+//! run shows up in the task log. The time and result of the last run are stored in a state file,
+//! so schedules survive restarts: jobs missed while the daemon was down run once on startup.
+//!
+//! [`WorkerTask`]: crate::WorkerTask
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{bail, format_err, Error};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use proxmox_daemon::command_socket::CommandSocket;
+use proxmox_schema::upid::UPID;
+use proxmox_sys::fs::{file_read_optional_string, replace_file, CreateOptions};
+use proxmox_time::CalendarEvent;
+
+use crate::{upid_read_status, worker_is_active_local};
+
+#[derive(Clone, Debug)]
+enum ScheduleKind {
+    Interval(Duration),
+    Calendar(String, CalendarEvent),
+}
+
+/// When to run a job: in fixed intervals or on calendar events like `daily` or `mon..fri 02:30`.
+#[derive(Clone, Debug)]
+pub struct JobSchedule(ScheduleKind);
+
+impl JobSchedule {
+    /// Run a job every `interval`, with a resolution of one second.
+    pub fn interval(interval: Duration) -> Self {
+        Self(ScheduleKind::Interval(interval))
+    }
+
+    /// Run a job on a calendar event, see [`CalendarEvent`].
+    pub fn calendar(event: &str) -> Result<Self, Error> {
+        let parsed = event
+            .parse()
+            .map_err(|err| format_err!("invalid calendar event {event:?} - {err}"))?;
+        Ok(Self(ScheduleKind::Calendar(event.to_string(), parsed)))
+    }
+
+    /// The first run after `last`, as epoch. `None` if the job never runs again.
+    pub fn next_run(&self, last: i64) -> Result<Option<i64>, Error> {
+        match &self.0 {
+            ScheduleKind::Interval(interval) => Ok(Some(last + (interval.as_secs() as i64).max(1))),
+            ScheduleKind::Calendar(_, event) => event.compute_next_event(last),
+        }
+    }
+}
+
+impl fmt::Display for JobSchedule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.0 {
+            ScheduleKind::Interval(interval) => write!(f, "every {}s", interval.as_secs()),
+            ScheduleKind::Calendar(event, _) => f.write_str(event),
+        }
+    }
+}
+
+/// The persisted state of a job.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct JobState {
+    /// Start time of the last run.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_run: Option<i64>,
+    /// The worker task of the last run.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_upid: Option<String>,
+    /// The final state of the last run, once it finished.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_state: Option<String>,
+}
+
+type JobFn = Box<dyn Fn() -> Result<String, Error> + Send + Sync>;
+
+struct Job {
+    schedule: JobSchedule,
+    start: JobFn,
+    state: JobState,
+    /// `None` if the job never runs again.
+    next_run: Option<i64>,
+}
+
+impl Job {
+    fn running_upid(&self) -> Option<UPID> {
+        let upid: UPID = self.state.last_upid.as_ref()?.parse().ok()?;
+        worker_is_active_local(&upid).then_some(upid)
+    }
+
+    /// Record the final state of the last run once it finished. Returns true if it changed.
+    fn update_last_state(&mut self) -> bool {
+        if self.state.last_state.is_some() {
+            return false;
+        }
+        let upid: UPID = match self.state.last_upid.as_ref().map(|upid| upid.parse()) {
+            Some(Ok(upid)) => upid,
+            _ => return false,
+        };
+        if worker_is_active_local(&upid) {
+            return false;
+        }
+        let state = upid_read_status(&upid)
+            .map(|state| state.to_string())
+            .unwrap_or_else(|err| format!("unable to read task status - {err}"));
+        self.state.last_state = Some(state);
+        true
+    }
+
+    fn run(&mut self, id: &str, now: i64) -> Result<String, Error> {
+        if let Some(upid) = self.running_upid() {
+            bail!("job '{id}' is still running ({upid})");
+        }
+
+        let result = (self.start)();
+        self.state = JobState {
+            last_run: Some(now),
+            last_upid: result.as_ref().ok().cloned(),
+            last_state: result
+                .as_ref()
+                .err()
+                .map(|err| format!("unable to start - {err}")),
+        };
+        result
+    }
+}
+
+/// Runs registered jobs according to their schedule.
+///
+/// Jobs which are still running when they are due again are skipped until their next scheduled
+/// run. Jobs which never ran, or missed runs while the daemon was not running, are due
+/// immediately and run once.
+pub struct JobScheduler {
+    state_path: PathBuf,
+    file_opts: CreateOptions,
+    clock: Box<dyn Fn() -> i64 + Send + Sync>,
+    saved_state: BTreeMap<String, JobState>,
+    jobs: Mutex<BTreeMap<String, Job>>,
+}
+
+impl JobScheduler {
+    /// Create a scheduler storing the state of its jobs in `state_path`.
+    pub fn new<P: Into<PathBuf>>(state_path: P, file_opts: CreateOptions) -> Result<Self, Error> {
+        let state_path = state_path.into();
+        let saved_state = match file_read_optional_string(&state_path)? {
+            Some(data) => serde_json::from_str(&data)
+                .map_err(|err| format_err!("unable to parse job state {state_path:?} - {err}"))?,
+            None => BTreeMap::new(),
+        };
+
+        Ok(Self {
+            state_path,
+            file_opts,
+            clock: Box::new(proxmox_time::epoch_i64),
+            saved_state,
+            jobs: Mutex::new(BTreeMap::new()),
+        })
+    }
+
+    /// Use `clock` instead of the system time to get the current epoch, e.g. for tests.
+    pub fn with_clock<F>(mut self, clock: F) -> Self
+    where
+        F: Fn() -> i64 + Send + Sync + 'static,
+    {
+        self.clock = Box::new(clock);
+        self
+    }
+
+    /// Register a job.
+    ///
+    /// `start` is called when the job is due and must start a worker task, e.g. with
+    /// [`WorkerTask::spawn`](crate::WorkerTask::spawn), returning its UPID.
+    pub fn register_job<F>(&self, id: &str, schedule: JobSchedule, start: F) -> Result<(), Error>
+    where
+        F: Fn() -> Result<String, Error> + Send + Sync + 'static,
+    {
+        let mut jobs = self.jobs.lock().unwrap();
+        if jobs.contains_key(id) {
+            bail!("job '{id}' is already registered");
+        }
+
+        let state = self.saved_state.get(id).cloned().unwrap_or_default();
+        let next_run = match state.last_run {
+            Some(last_run) => schedule.next_run(last_run)?,
+            None => Some((self.clock)()),
+        };
+
+        jobs.insert(
+            id.to_string(),
+            Job {
+                schedule,
+                start: Box::new(start),
+                state,
+                next_run,
+            },
+        );
+        Ok(())
+    }
+
+    /// Start all due jobs, returning the ids of the started jobs.
+    pub fn run_pending(&self) -> Vec<String> {
+        let now = (self.clock)();
+        let mut jobs = self.jobs.lock().unwrap();
+
+        let mut changed = false;
+        let mut started = Vec::new();
+        for (id, job) in jobs.iter_mut() {
+            changed |= job.update_last_state();
+
+            match job.next_run {
+                Some(next_run) if next_run <= now => (),
+                _ => continue,
+            }
+
+            if let Some(upid) = job.running_upid() {
+                log::info!("skipping job '{id}' - still running ({upid})");
+            } else {
+                match job.run(id, now) {
+                    Ok(_) => started.push(id.clone()),
+                    Err(err) => log::error!("unable to start job '{id}' - {err}"),
+                }
+                changed = true;
+            }
+
+            job.next_run = job.schedule.next_run(now).unwrap_or_else(|err| {
+                log::error!("unable to compute next run of job '{id}' - {err}");
+                None
+            });
+        }
+
+        if changed {
+            if let Err(err) = self.save_state(&jobs) {
+                log::error!("{err}");
+            }
+        }
+
+        started
+    }
+
+    /// Start a job now, regardless of its schedule, returning the UPID of its task.
+    ///
+    /// Fails if the job is still running.
+    pub fn run_now(&self, id: &str) -> Result<String, Error> {
+        let now = (self.clock)();
+        let mut jobs = self.jobs.lock().unwrap();
+
+        let job = jobs
+            .get_mut(id)
+            .ok_or_else(|| format_err!("no such job '{id}'"))?;
+        job.update_last_state();
+        let upid = job.run(id, now);
+
+        self.save_state(&jobs)?;
+        upid
+    }
+
+    /// List the jobs with their schedule, last and next run.
+    pub fn list(&self) -> Value {
+        let jobs = self.jobs.lock().unwrap();
+
+        let list: Vec<Value> = jobs
+            .iter()
+            .map(|(id, job)| {
+                json!({
+                    "id": id,
+                    "schedule": job.schedule.to_string(),
+                    "last-run": job.state.last_run,
+                    "last-upid": job.state.last_upid,
+                    "last-state": job.state.last_state,
+                    "next-run": job.next_run,
+                    "running": job.running_upid().is_some(),
+                })
+            })
+            .collect();
+
+        Value::Array(list)
+    }
+
+    fn save_state(&self, jobs: &BTreeMap<String, Job>) -> Result<(), Error> {
+        // keep the state of jobs not registered (anymore), e.g. of disabled features
+        let mut state = self.saved_state.clone();
+        for (id, job) in jobs {
+            state.insert(id.clone(), job.state.clone());
+        }
+
+        let data = serde_json::to_vec_pretty(&state)?;
+        replace_file(&self.state_path, &data, self.file_opts.clone(), false)
+            .map_err(|err| format_err!("unable to save job state {:?} - {err}", self.state_path))
+    }
+
+    /// Run due jobs every `tick` until a shutdown is requested.
+    ///
+    /// Must be called from within a tokio runtime.
+    pub fn spawn(self: Arc<Self>, tick: Duration) {
+        tokio::spawn(async move {
+            while !proxmox_daemon::is_shutdown_requested() {
+                self.run_pending();
+                tokio::time::sleep(tick).await;
+            }
+        });
+    }
+
+    /// Register the `job-list` and `job-run` commands on the [CommandSocket].
+    ///
+    /// `job-list` returns the jobs with their schedule, last and next run. `job-run` takes the
+    /// `id` of a job as argument, starts it immediately and returns the UPID of its task.
+    pub fn register_commands(
+        self: Arc<Self>,
+        commando_sock: &mut CommandSocket,
+    ) -> Result<(), Error> {
+        let scheduler = Arc::clone(&self);
+        commando_sock.register_command("job-list".into(), move |_args| Ok(scheduler.list()))?;
+
+        commando_sock.register_command("job-run".into(), move |args| {
+            let id = args
+                .and_then(|args| args.get("id"))
+                .and_then(Value::as_str)
+                .ok_or_else(|| format_err!("missing 'id' argument"))?;
+            self.run_now(id).map(Value::from)
+        })
+    }
+}
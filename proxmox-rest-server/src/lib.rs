@@ -9,6 +9,14 @@
 //! * static API definitions using schemas
 //! * support for long running worker tasks (threads or async tokio tasks)
 //! * supports separate access and authentication log files
+//! * auditing of state-changing API calls
+//! * liveness and readiness endpoints for load balancers
+//! * bandwidth limits for request and response bodies
+//! * cancellation of handlers when their client disconnects
+//! * handler timeouts by the expected duration of API methods
+//! * optional replay protection for requests authenticated by ticket
+//! * ticket renewal endpoint with a sliding expiry
+//! * tunables loaded from a configuration file and the environment
 //! * extra control socket to trigger management operations
 //!   - logfile rotation
 //!   - worker task management
@@ -33,17 +41,67 @@ pub mod formatter;
 mod environment;
 pub use environment::*;
 
+mod cookie;
+pub use cookie::{CookiePolicy, SameSite};
+
+mod csrf;
+pub use csrf::{CsrfProtection, CSRF_HEADER_NAME};
+
+mod response_cache;
+pub use response_cache::ResponseCache;
+
+mod default_headers;
+pub use default_headers::DefaultHeaders;
+
+mod audit;
+pub use audit::{AuditCall, AuditHook, FileAuditLog};
+
+mod health;
+pub use health::HealthOptions;
+
+mod rate_limit;
+pub use rate_limit::{BodyRateLimiter, BodyRateLimits, RequestRateLimit};
+
+mod disconnect;
+pub use disconnect::ClientDisconnect;
+
+mod handler_timeout;
+pub use handler_timeout::HandlerTimeouts;
+
+mod trusted_proxies;
+pub use trusted_proxies::TrustedProxies;
+
+mod maintenance;
+pub use maintenance::{Maintenance, MaintenanceMode, MaintenanceState};
+
+mod replay;
+pub use replay::{ReplayProtection, REQUEST_NONCE_HEADER_NAME};
+
+mod ticket_renewal;
+pub use ticket_renewal::{TicketRenewal, TicketSigner, TICKET_HEADER_NAME};
+
 mod api_config;
-pub use api_config::{ApiConfig, AuthError, AuthHandler, IndexHandler, UnixAcceptor};
+pub use api_config::{ApiConfig, AuthError, AuthHandler, AuthSource, IndexHandler, UnixAcceptor};
+
+mod settings;
+pub use settings::{ApiConfigBuilder, LogCompressionSetting, RestServerSettings};
 
 mod rest;
-pub use rest::{Redirector, RestServer};
+pub use rest::{AccessLogFormat, Redirector, RestServer};
 
 pub mod connection;
 
 mod worker_task;
 pub use worker_task::*;
 
+mod task_journal;
+pub use task_journal::{
+    set_task_journal, JournaldSink, TaskJournal, TaskJournalEntry, TaskJournalSink,
+};
+
+mod job_scheduler;
+pub use job_scheduler::{JobSchedule, JobScheduler};
+
 mod h2service;
 pub use h2service::*;
 
@@ -76,7 +134,8 @@ pub fn write_pid(pid_fn: &str) -> Result<(), Error> {
 
 /// Helper to read the PID from a file
 pub fn read_pid(pid_fn: &str) -> Result<i32, Error> {
-    let pid = proxmox_sys::fs::file_get_contents(pid_fn)?;
+    // a PID file only contains a number and a newline
+    let pid = proxmox_sys::fs::file_get_contents_limited(pid_fn, 64)?;
     let pid = std::str::from_utf8(&pid)?.trim();
     pid.parse()
         .map_err(|err| format_err!("could not parse pid - {}", err))
@@ -104,6 +163,16 @@ pub fn extract_cookie(cookie: &str, cookie_name: &str) -> Option<String> {
     None
 }
 
+/// Extract the first cookie found from a list of acceptable cookie names.
+///
+/// The names are tried in order, which allows accepting an old name while renaming a cookie.
+/// We assume the cookie names are already url encoded.
+pub fn extract_cookie_any(cookie: &str, cookie_names: &[&str]) -> Option<String> {
+    cookie_names
+        .iter()
+        .find_map(|cookie_name| extract_cookie(cookie, cookie_name))
+}
+
 /// Extract a specific cookie from a HeaderMap's "COOKIE" entry.
 /// We assume cookie_name is already url encoded.
 pub fn cookie_from_header(headers: &http::HeaderMap, cookie_name: &str) -> Option<String> {
@@ -0,0 +1,212 @@
+//! Maintenance mode, rejecting requests while e.g. an upgrade is in progress.
+
+use std::sync::{Arc, Mutex};
+
+use anyhow::Error;
+use hyper::header;
+use hyper::{Body, Method, Response};
+use serde::{Deserialize, Serialize};
+
+use proxmox_daemon::command_socket::CommandSocket;
+use proxmox_router::http_err;
+
+use crate::formatter::error_to_response;
+
+/// The name of the reload state keeping the state across a reload of the daemon.
+const MAINTENANCE_STATE: &str = "PROXMOX_REST_SERVER_MAINTENANCE";
+
+const DEFAULT_MESSAGE: &str = "node is in maintenance";
+const DEFAULT_RETRY_AFTER: u64 = 60;
+
+/// The maintenance mode of the API.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MaintenanceMode {
+    /// All requests are handled.
+    #[default]
+    Off,
+    /// Only `GET`, `HEAD` and `OPTIONS` requests are handled.
+    ReadOnly,
+    /// No requests are handled.
+    Full,
+}
+
+/// The maintenance mode with the message returned for rejected requests.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MaintenanceState {
+    pub mode: MaintenanceMode,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+/// Rejects requests with `503 Service Unavailable` depending on the [`MaintenanceMode`], which
+/// can be changed at any time.
+///
+/// Requests to allowed paths, e.g. for the login, and to the health endpoints are always
+/// handled. Running worker tasks are not affected.
+///
+/// The state is passed on to the new process on a reload of the daemon, see
+/// [`register_reload_state`](proxmox_daemon::server::register_reload_state), so it is restored
+/// when the maintenance is created in the new process.
+pub struct Maintenance {
+    state: Arc<Mutex<MaintenanceState>>,
+    allowed_paths: Vec<String>,
+    retry_after: u64,
+}
+
+impl Maintenance {
+    /// Create the maintenance, with the state from before a reload or turned off.
+    ///
+    /// The state of this instance is passed on at the next reload.
+    pub fn new() -> Self {
+        let state =
+            proxmox_daemon::server::take_reload_state(MAINTENANCE_STATE).unwrap_or_else(|err| {
+                log::error!("unable to restore maintenance state - {err}");
+                None
+            });
+        let this = Self::with_state(state.as_deref());
+
+        let state = Arc::clone(&this.state);
+        proxmox_daemon::server::register_reload_state(MAINTENANCE_STATE, move || {
+            Ok(serde_json::to_string(&*state.lock().unwrap())?)
+        });
+
+        this
+    }
+
+    fn with_state(state: Option<&str>) -> Self {
+        let state = state
+            .map(|state| {
+                serde_json::from_str(state).unwrap_or_else(|err| {
+                    log::error!("unable to restore maintenance state - {err}");
+                    MaintenanceState::default()
+                })
+            })
+            .unwrap_or_default();
+        Self {
+            state: Arc::new(Mutex::new(state)),
+            allowed_paths: Vec::new(),
+            retry_after: DEFAULT_RETRY_AFTER,
+        }
+    }
+
+    /// Always handle requests to `path` and the paths below it.
+    pub fn allow_path(mut self, path: &str) -> Self {
+        // compared with normalized request paths
+        self.allowed_paths.push(
+            path.split('/')
+                .filter(|comp| !comp.is_empty())
+                .map(|comp| format!("/{comp}"))
+                .collect(),
+        );
+        self
+    }
+
+    /// The number of seconds returned in the `Retry-After` header of rejected requests, 60 by
+    /// default.
+    pub fn retry_after(mut self, seconds: u64) -> Self {
+        self.retry_after = seconds;
+        self
+    }
+
+    /// The current state.
+    pub fn state(&self) -> MaintenanceState {
+        self.state.lock().unwrap().clone()
+    }
+
+    /// Change the state, applies to requests received afterwards.
+    pub fn set_state(&self, state: MaintenanceState) {
+        *self.state.lock().unwrap() = state;
+    }
+
+    /// Register an `api-maintenance` command on the [`CommandSocket`] to change the state at
+    /// runtime.
+    ///
+    /// The optional `mode` and `message` arguments replace the current state, the command returns
+    /// the state in effect afterwards.
+    pub fn register_command(
+        self: &Arc<Self>,
+        commando_sock: &mut CommandSocket,
+    ) -> Result<(), Error> {
+        let maintenance = Arc::clone(self);
+        commando_sock.register_command("api-maintenance".into(), move |args| {
+            let state = match args {
+                Some(args) if args.get("mode").is_some() => {
+                    let state: MaintenanceState = serde_json::from_value(args.clone())?;
+                    log::info!("changing api maintenance mode to {state:?}");
+                    maintenance.set_state(state.clone());
+                    state
+                }
+                _ => maintenance.state(),
+            };
+            Ok(serde_json::to_value(state)?)
+        })
+    }
+
+    /// Check whether the request is rejected, returns the response in that case.
+    pub(crate) fn check_request(&self, method: &Method, path: &str) -> Option<Response<Body>> {
+        let state = self.state.lock().unwrap().clone();
+        let rejected = match state.mode {
+            MaintenanceMode::Off => false,
+            MaintenanceMode::ReadOnly => {
+                !matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+            }
+            MaintenanceMode::Full => true,
+        };
+        if !rejected || self.is_allowed(path) {
+            return None;
+        }
+
+        let message = state.message.as_deref().unwrap_or(DEFAULT_MESSAGE);
+        let mut response = error_to_response(http_err!(SERVICE_UNAVAILABLE, "{message}"));
+        response
+            .headers_mut()
+            .insert(header::RETRY_AFTER, self.retry_after.into());
+        Some(response)
+    }
+
+    fn is_allowed(&self, path: &str) -> bool {
+        self.allowed_paths.iter().any(|allowed| {
+            path.strip_prefix(allowed.as_str())
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+        })
+    }
+}
+
+impl Default for Maintenance {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use hyper::{Method, StatusCode};
+
+    use super::{Maintenance, MaintenanceMode, MaintenanceState};
+
+    #[test]
+    fn test_state_across_reload() {
+        let maintenance = Maintenance::with_state(None);
+        assert_eq!(maintenance.state(), MaintenanceState::default());
+        maintenance.set_state(MaintenanceState {
+            mode: MaintenanceMode::ReadOnly,
+            message: Some("upgrade in progress".to_string()),
+        });
+        let state = serde_json::to_string(&*maintenance.state.lock().unwrap()).unwrap();
+
+        // the new process restores the state passed on
+        let reloaded = Maintenance::with_state(Some(&state));
+        assert_eq!(reloaded.state(), maintenance.state());
+        assert!(reloaded.check_request(&Method::GET, "/config").is_none());
+        assert!(reloaded
+            .check_request(&Method::OPTIONS, "/config")
+            .is_none());
+        let response = reloaded.check_request(&Method::POST, "/config").unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        // a broken state is dropped
+        let reloaded = Maintenance::with_state(Some("garbage"));
+        assert_eq!(reloaded.state(), MaintenanceState::default());
+    }
+}
@@ -0,0 +1,394 @@
+//! Bandwidth limits for request and response bodies.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{ready, Context, Poll};
+use std::time::{Duration, Instant};
+
+use anyhow::Error;
+use futures::Stream;
+use hyper::body::{Bytes, HttpBody};
+use hyper::{Body, Response};
+use serde::{Deserialize, Serialize};
+use tokio::time::Sleep;
+
+use proxmox_daemon::command_socket::CommandSocket;
+use proxmox_http::{RateLimit, RateLimiter};
+
+/// Chunks are split into pieces of at most this size, so that concurrent bodies sharing a
+/// limiter take turns in small steps.
+const MAX_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Rate limits in bytes per second, `None` or `0` means unlimited.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BodyRateLimits {
+    /// The limit for request bodies.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upload: Option<u64>,
+    /// The limit for response bodies.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub download: Option<u64>,
+}
+
+/// The arguments and result of the `api-rate-limit` command.
+#[derive(Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct RateLimitSettings {
+    global: Option<BodyRateLimits>,
+    per_request: Option<BodyRateLimits>,
+}
+
+/// A token bucket limiter which can be shared between bodies and reconfigured at any time.
+#[derive(Default)]
+struct Limiter(Mutex<Option<RateLimiter>>);
+
+impl Limiter {
+    fn new(rate: Option<u64>) -> Self {
+        let limiter = Self::default();
+        limiter.set_rate(rate);
+        limiter
+    }
+
+    /// Set the rate, allowing bursts of up to one second of traffic after idle periods.
+    ///
+    /// A rate of `0` means unlimited, like `None`.
+    fn set_rate(&self, rate: Option<u64>) {
+        self.set_rate_at(rate, Instant::now())
+    }
+
+    fn set_rate_at(&self, rate: Option<u64>, now: Instant) {
+        let mut limiter = self.0.lock().unwrap();
+        match (rate.filter(|rate| *rate > 0), limiter.as_mut()) {
+            (None, _) => *limiter = None,
+            (Some(rate), Some(limiter)) => limiter.update_rate(rate, rate),
+            (Some(rate), None) => *limiter = Some(RateLimiter::with_start_time(rate, rate, now)),
+        }
+    }
+
+    fn register_traffic(&self, now: Instant, len: usize) -> Duration {
+        match self.0.lock().unwrap().as_mut() {
+            Some(limiter) => limiter.register_traffic(now, len as u64),
+            None => Duration::ZERO,
+        }
+    }
+}
+
+/// Limits the bandwidth of request and response bodies globally and per request.
+///
+/// The global limits are shared by all bodies, the per-request limits apply to each body on its
+/// own and can be changed for a single request with its [`RequestRateLimit`]. Bodies are
+/// delayed, not rejected, so that concurrent bodies get a fair share of the bandwidth.
+///
+/// Changes of the global limits apply immediately to all bodies, changes of the per-request
+/// limits apply to requests received afterwards.
+pub struct BodyRateLimiter {
+    global_upload: Arc<Limiter>,
+    global_download: Arc<Limiter>,
+    limits: Mutex<(BodyRateLimits, BodyRateLimits)>,
+}
+
+impl BodyRateLimiter {
+    /// Create a limiter with the `global` limits shared by all bodies and the default
+    /// `per_request` limits.
+    pub fn new(global: BodyRateLimits, per_request: BodyRateLimits) -> Self {
+        Self {
+            global_upload: Arc::new(Limiter::new(global.upload)),
+            global_download: Arc::new(Limiter::new(global.download)),
+            limits: Mutex::new((global, per_request)),
+        }
+    }
+
+    /// Change the limits, see [`new`](Self::new).
+    pub fn update(&self, global: BodyRateLimits, per_request: BodyRateLimits) {
+        let mut limits = self.limits.lock().unwrap();
+        self.global_upload.set_rate(global.upload);
+        self.global_download.set_rate(global.download);
+        *limits = (global, per_request);
+    }
+
+    /// Returns the global and the default per-request limits.
+    pub fn limits(&self) -> (BodyRateLimits, BodyRateLimits) {
+        *self.limits.lock().unwrap()
+    }
+
+    /// Register an `api-rate-limit` command on the [`CommandSocket`] to change the limits at
+    /// runtime.
+    ///
+    /// The optional `global` and `per-request` arguments replace the respective limits, the
+    /// command returns the limits in effect afterwards.
+    pub fn register_command(
+        self: &Arc<Self>,
+        commando_sock: &mut CommandSocket,
+    ) -> Result<(), Error> {
+        let limiter = Arc::clone(self);
+        commando_sock.register_command("api-rate-limit".into(), move |args| {
+            let args: RateLimitSettings = match args {
+                Some(args) => serde_json::from_value(args.clone())?,
+                None => RateLimitSettings::default(),
+            };
+
+            let (global, per_request) = limiter.limits();
+            let global = args.global.unwrap_or(global);
+            let per_request = args.per_request.unwrap_or(per_request);
+            if args.global.is_some() || args.per_request.is_some() {
+                log::info!("changing api rate limits to {global:?}, per request {per_request:?}");
+                limiter.update(global, per_request);
+            }
+
+            Ok(serde_json::to_value(RateLimitSettings {
+                global: Some(global),
+                per_request: Some(per_request),
+            })?)
+        })
+    }
+
+    /// Create the limits of a new request.
+    pub(crate) fn request_limits(&self) -> RequestRateLimit {
+        let (_global, per_request) = self.limits();
+        RequestRateLimit {
+            upload: BodyLimiter {
+                own: Arc::new(Limiter::new(per_request.upload)),
+                global: Arc::clone(&self.global_upload),
+            },
+            download: BodyLimiter {
+                own: Arc::new(Limiter::new(per_request.download)),
+                global: Arc::clone(&self.global_download),
+            },
+        }
+    }
+}
+
+#[derive(Clone)]
+struct BodyLimiter {
+    own: Arc<Limiter>,
+    global: Arc<Limiter>,
+}
+
+impl BodyLimiter {
+    fn register_traffic(&self, now: Instant, len: usize) -> Duration {
+        let own = self.own.register_traffic(now, len);
+        let global = self.global.register_traffic(now, len);
+        own.max(global)
+    }
+
+    fn limit(&self, body: Body) -> Body {
+        if body.is_end_stream() {
+            return body;
+        }
+        Body::wrap_stream(RateLimitedBody {
+            body,
+            limiter: self.clone(),
+            pending: None,
+            delayed: None,
+            delay: None,
+        })
+    }
+}
+
+/// The bandwidth limits of a single request, available as extension of the request's
+/// [`Parts`](hyper::http::request::Parts) if a [`BodyRateLimiter`] is configured.
+///
+/// Handlers can change the limits of their request at any time, e.g. based on the limits
+/// configured for the authenticated user:
+///
+/// ```
+/// # use hyper::http::request::Parts;
+/// # use proxmox_rest_server::RequestRateLimit;
+/// fn apply_user_limits(parts: &Parts) {
+///     if let Some(limit) = parts.extensions.get::<RequestRateLimit>() {
+///         limit.set_upload(Some(10 * 1024 * 1024));
+///     }
+/// }
+/// ```
+#[derive(Clone)]
+pub struct RequestRateLimit {
+    upload: BodyLimiter,
+    download: BodyLimiter,
+}
+
+impl RequestRateLimit {
+    /// Change the limit for the request body in bytes per second, `None` means unlimited.
+    ///
+    /// The global limit still applies.
+    pub fn set_upload(&self, rate: Option<u64>) {
+        self.upload.own.set_rate(rate);
+    }
+
+    /// Change the limit for the response body in bytes per second, `None` means unlimited.
+    ///
+    /// The global limit still applies.
+    pub fn set_download(&self, rate: Option<u64>) {
+        self.download.own.set_rate(rate);
+    }
+
+    pub(crate) fn limit_request_body(&self, body: Body) -> Body {
+        self.upload.limit(body)
+    }
+
+    pub(crate) fn limit_response_body(&self, response: Response<Body>) -> Response<Body> {
+        response.map(|body| self.download.limit(body))
+    }
+}
+
+/// Delays the chunks of a body according to its limiter.
+struct RateLimitedBody {
+    body: Body,
+    limiter: BodyLimiter,
+    /// The rest of a chunk larger than `MAX_CHUNK_SIZE`.
+    pending: Option<Bytes>,
+    /// The chunk to return once the delay elapsed.
+    delayed: Option<Bytes>,
+    delay: Option<Pin<Box<Sleep>>>,
+}
+
+impl Stream for RateLimitedBody {
+    type Item = Result<Bytes, hyper::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        if let Some(delay) = this.delay.as_mut() {
+            ready!(delay.as_mut().poll(cx));
+            this.delay = None;
+            if let Some(chunk) = this.delayed.take() {
+                return Poll::Ready(Some(Ok(chunk)));
+            }
+        }
+
+        let mut chunk = match this.pending.take() {
+            Some(chunk) => chunk,
+            None => match ready!(Pin::new(&mut this.body).poll_data(cx)) {
+                Some(Ok(chunk)) => chunk,
+                other => return Poll::Ready(other),
+            },
+        };
+        if chunk.len() > MAX_CHUNK_SIZE {
+            this.pending = Some(chunk.split_off(MAX_CHUNK_SIZE));
+        }
+
+        let delay = this.limiter.register_traffic(Instant::now(), chunk.len());
+        if delay.is_zero() {
+            return Poll::Ready(Some(Ok(chunk)));
+        }
+
+        let mut delay = Box::pin(tokio::time::sleep(delay));
+        match delay.as_mut().poll(cx) {
+            Poll::Ready(()) => Poll::Ready(Some(Ok(chunk))),
+            Poll::Pending => {
+                this.delay = Some(delay);
+                this.delayed = Some(chunk);
+                Poll::Pending
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const MIB: u64 = 1024 * 1024;
+
+    fn new_limiter(rate: Option<u64>, start: Instant) -> Arc<Limiter> {
+        let limiter = Limiter::default();
+        limiter.set_rate_at(rate, start);
+        Arc::new(limiter)
+    }
+
+    /// Send `sizes` bytes through the limiters in chunks, always continuing with the body whose
+    /// delay elapses first, and return the time each body finished.
+    fn transfer(limiters: &[BodyLimiter], sizes: &[u64], start: Instant) -> Vec<Duration> {
+        let mut remaining = sizes.to_vec();
+        let mut next = vec![start; limiters.len()];
+        let mut finished = vec![Duration::ZERO; limiters.len()];
+
+        while let Some(index) = (0..limiters.len())
+            .filter(|index| remaining[*index] > 0)
+            .min_by_key(|index| next[*index])
+        {
+            let len = remaining[index].min(MAX_CHUNK_SIZE as u64);
+            remaining[index] -= len;
+            let delay = limiters[index].register_traffic(next[index], len as usize);
+            next[index] += delay;
+            finished[index] = next[index] - start;
+        }
+
+        finished
+    }
+
+    fn abs_diff(a: Duration, b: Duration) -> Duration {
+        a.max(b) - a.min(b)
+    }
+
+    fn assert_about(elapsed: Duration, expected: Duration) {
+        let diff = abs_diff(elapsed, expected);
+        assert!(
+            diff < Duration::from_millis(1),
+            "took {elapsed:?}, expected {expected:?}"
+        );
+    }
+
+    #[test]
+    fn test_zero_rate_is_unlimited() {
+        let start = Instant::now();
+        let limiter = new_limiter(Some(0), start);
+        assert!(limiter.0.lock().unwrap().is_none());
+        assert_eq!(
+            limiter.register_traffic(start, MAX_CHUNK_SIZE),
+            Duration::ZERO
+        );
+
+        let limiter = new_limiter(Some(MIB), start);
+        limiter.set_rate_at(Some(0), start);
+        assert_eq!(
+            limiter.register_traffic(start, MAX_CHUNK_SIZE),
+            Duration::ZERO
+        );
+    }
+
+    #[test]
+    fn test_request_limit() {
+        let start = Instant::now();
+        let body = BodyLimiter {
+            own: new_limiter(Some(MIB), start),
+            global: new_limiter(None, start),
+        };
+
+        let finished = transfer(&[body], &[5 * MIB], start);
+        assert_about(finished[0], Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_global_limit_is_shared_fairly() {
+        let start = Instant::now();
+        let global = new_limiter(Some(2 * MIB), start);
+        let bodies: Vec<_> = (0..2)
+            .map(|_| BodyLimiter {
+                own: new_limiter(None, start),
+                global: Arc::clone(&global),
+            })
+            .collect();
+
+        // two concurrent bodies share the bandwidth and finish at about the same time
+        let finished = transfer(&bodies, &[2 * MIB, 2 * MIB], start);
+        let tolerance = Duration::from_secs_f64(2.0 * MAX_CHUNK_SIZE as f64 / (2 * MIB) as f64);
+        for elapsed in finished {
+            assert!(
+                abs_diff(elapsed, Duration::from_secs(2)) <= tolerance,
+                "took {elapsed:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_stricter_limit_wins() {
+        let start = Instant::now();
+        let body = BodyLimiter {
+            own: new_limiter(Some(MIB), start),
+            global: new_limiter(Some(4 * MIB), start),
+        };
+        let finished = transfer(&[body], &[2 * MIB], start);
+        assert_about(finished[0], Duration::from_secs(2));
+    }
+}
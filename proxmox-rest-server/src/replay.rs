@@ -0,0 +1,284 @@
+//! Replay protection for state changing requests authenticated by ticket.
+//!
+//! A ticket found in a log file can be used by anyone until it expires. With replay protection,
+//! state changing requests must also carry a nonce in the `RequestNonce` header, which must be
+//! greater than the nonce of the previous request of the same user. A request can therefore not
+//! be sent again, and a stolen ticket is useless without a nonce greater than all which were used
+//! before.
+//!
+//! Enable the check with [`ApiConfig::replay_protection`](crate::ApiConfig::replay_protection).
+//! Since clients must send the header, this is opt-in.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use anyhow::Error;
+use http::{HeaderMap, Method};
+use serde::{Deserialize, Serialize};
+
+use proxmox_router::{http_bail, http_err};
+
+/// Name of the header carrying the request nonce.
+pub const REQUEST_NONCE_HEADER_NAME: &str = "RequestNonce";
+
+/// The name of the reload state keeping the nonces across a reload of the daemon.
+const REPLAY_PROTECTION_STATE: &str = "PROXMOX_REST_SERVER_REQUEST_NONCES";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct NonceEntry {
+    /// The nonce of the last accepted request.
+    nonce: u64,
+    /// The epoch of the last accepted request.
+    last_used: i64,
+}
+
+/// Tracks the last request nonce of each user and rejects requests reusing a nonce.
+///
+/// Nonces are tracked per auth id, so all clients of a user must share a monotonic source, e.g.
+/// the time in milliseconds. The entry of a user is dropped once it was not used for
+/// [`ticket_lifetime`](Self::ticket_lifetime) seconds (two hours by default), since all tickets
+/// it could protect have expired by then. At most [`max_entries`](Self::max_entries) users are
+/// tracked (4096 by default), dropping the least recently used entries first.
+///
+/// Requests authenticated by API token are exempt by default, see
+/// [`exempt_tokens`](Self::exempt_tokens).
+///
+/// The nonces are passed on to the new process on a reload of the daemon, see
+/// [`register_reload_state`](proxmox_daemon::server::register_reload_state), so they are restored
+/// when the replay protection is created in the new process.
+pub struct ReplayProtection {
+    nonces: Arc<Mutex<HashMap<String, NonceEntry>>>,
+    ticket_lifetime: i64,
+    max_entries: usize,
+    exempt_tokens: bool,
+}
+
+impl ReplayProtection {
+    /// Create the replay protection, with the nonces from before a reload.
+    ///
+    /// The nonces of this instance are passed on at the next reload.
+    pub fn new() -> Self {
+        let state = proxmox_daemon::server::take_reload_state(REPLAY_PROTECTION_STATE)
+            .unwrap_or_else(|err| {
+                log::error!("unable to restore request nonces - {err}");
+                None
+            });
+        let this = Self::with_state(state.as_deref());
+
+        let nonces = Arc::clone(&this.nonces);
+        proxmox_daemon::server::register_reload_state(REPLAY_PROTECTION_STATE, move || {
+            Ok(serde_json::to_string(&*nonces.lock().unwrap())?)
+        });
+
+        this
+    }
+
+    fn with_state(state: Option<&str>) -> Self {
+        Self {
+            nonces: Arc::new(Mutex::new(state.map(restore_nonces).unwrap_or_default())),
+            ticket_lifetime: 2 * 3600,
+            max_entries: 4096,
+            exempt_tokens: true,
+        }
+    }
+
+    /// Set for how many seconds tickets are valid.
+    pub fn ticket_lifetime(mut self, seconds: i64) -> Self {
+        self.ticket_lifetime = seconds;
+        self
+    }
+
+    /// Set the maximum number of tracked users.
+    pub fn max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = max_entries.max(1);
+        self
+    }
+
+    /// Set whether requests authenticated by API token (auth ids containing a `!`) are exempt.
+    pub fn exempt_tokens(mut self, exempt: bool) -> Self {
+        self.exempt_tokens = exempt;
+        self
+    }
+
+    /// Check the nonce of a request, methods other than `GET` and `HEAD` need one.
+    ///
+    /// Fails with `400 Bad Request` if the nonce is missing and with `409 Conflict` if it is not
+    /// greater than the previous nonce of `auth_id`.
+    pub(crate) fn check_request(
+        &self,
+        headers: &HeaderMap,
+        method: &Method,
+        auth_id: &str,
+    ) -> Result<(), Error> {
+        if method == Method::GET || method == Method::HEAD {
+            return Ok(());
+        }
+        if self.exempt_tokens && auth_id.contains('!') {
+            return Ok(());
+        }
+
+        let nonce = match headers.get(REQUEST_NONCE_HEADER_NAME) {
+            Some(value) => value
+                .to_str()
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .ok_or_else(|| http_err!(BAD_REQUEST, "invalid request nonce header"))?,
+            None => http_bail!(BAD_REQUEST, "missing request nonce"),
+        };
+
+        self.check_nonce_at(auth_id, nonce, proxmox_time::epoch_i64())
+    }
+
+    fn check_nonce_at(&self, auth_id: &str, nonce: u64, now: i64) -> Result<(), Error> {
+        let mut nonces = self.nonces.lock().unwrap();
+
+        let oldest = now - self.ticket_lifetime;
+        nonces.retain(|_, entry| entry.last_used >= oldest);
+
+        if let Some(entry) = nonces.get_mut(auth_id) {
+            if nonce <= entry.nonce {
+                http_bail!(CONFLICT, "request nonce {nonce} was already used");
+            }
+            entry.nonce = nonce;
+            entry.last_used = now;
+        } else {
+            while nonces.len() >= self.max_entries {
+                let lru = nonces
+                    .iter()
+                    .min_by_key(|(_, entry)| entry.last_used)
+                    .map(|(auth_id, _)| auth_id.clone());
+                match lru {
+                    Some(auth_id) => nonces.remove(&auth_id),
+                    None => break,
+                };
+            }
+            nonces.insert(
+                auth_id.to_string(),
+                NonceEntry {
+                    nonce,
+                    last_used: now,
+                },
+            );
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for ReplayProtection {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn restore_nonces(state: &str) -> HashMap<String, NonceEntry> {
+    serde_json::from_str(state).unwrap_or_else(|err| {
+        log::error!("unable to restore request nonces - {err}");
+        HashMap::new()
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use http::{HeaderMap, HeaderValue, Method, StatusCode};
+
+    use proxmox_router::HttpError;
+
+    use super::{ReplayProtection, REQUEST_NONCE_HEADER_NAME};
+
+    const NOW: i64 = 1_700_000_000;
+
+    fn status(result: Result<(), anyhow::Error>) -> Option<StatusCode> {
+        result
+            .err()
+            .map(|err| err.downcast_ref::<HttpError>().unwrap().code)
+    }
+
+    #[test]
+    fn test_nonce_sequence() {
+        let replay = ReplayProtection::new().ticket_lifetime(3600).max_entries(2);
+        let check = |auth_id: &str, nonce: u64, now: i64| {
+            status(replay.check_nonce_at(auth_id, nonce, now))
+        };
+
+        assert_eq!(check("user@pam", 10, NOW), None);
+        assert_eq!(check("user@pam", 11, NOW), None);
+        assert_eq!(check("user@pam", 11, NOW), Some(StatusCode::CONFLICT));
+        assert_eq!(check("user@pam", 5, NOW), Some(StatusCode::CONFLICT));
+        assert_eq!(check("user@pam", 20, NOW + 10), None);
+
+        // nonces are tracked per user
+        assert_eq!(check("other@pam", 1, NOW + 20), None);
+        assert_eq!(check("other@pam", 1, NOW + 20), Some(StatusCode::CONFLICT));
+
+        // the entry is dropped once all tickets it could protect have expired
+        assert_eq!(
+            check("user@pam", 1, NOW + 10 + 3600),
+            Some(StatusCode::CONFLICT)
+        );
+        assert_eq!(check("user@pam", 1, NOW + 10 + 3601), None);
+
+        // the least recently used entry is dropped if there are too many users
+        assert_eq!(check("third@pam", 1, NOW + 3700), None);
+        assert_eq!(check("other@pam", 1, NOW + 3700), None);
+        assert_eq!(check("user@pam", 1, NOW + 3700), Some(StatusCode::CONFLICT));
+    }
+
+    #[test]
+    fn test_check_request() {
+        let replay = ReplayProtection::new();
+        let mut headers = HeaderMap::new();
+
+        replay
+            .check_request(&headers, &Method::GET, "user@pam")
+            .unwrap();
+        assert_eq!(
+            status(replay.check_request(&headers, &Method::POST, "user@pam")),
+            Some(StatusCode::BAD_REQUEST)
+        );
+        replay
+            .check_request(&headers, &Method::POST, "user@pam!token")
+            .unwrap();
+
+        headers.insert(REQUEST_NONCE_HEADER_NAME, HeaderValue::from_static("abc"));
+        assert_eq!(
+            status(replay.check_request(&headers, &Method::PUT, "user@pam")),
+            Some(StatusCode::BAD_REQUEST)
+        );
+
+        headers.insert(REQUEST_NONCE_HEADER_NAME, HeaderValue::from_static("42"));
+        replay
+            .check_request(&headers, &Method::PUT, "user@pam")
+            .unwrap();
+        assert_eq!(
+            status(replay.check_request(&headers, &Method::DELETE, "user@pam")),
+            Some(StatusCode::CONFLICT)
+        );
+
+        let replay = ReplayProtection::new().exempt_tokens(false);
+        headers.remove(REQUEST_NONCE_HEADER_NAME);
+        assert_eq!(
+            status(replay.check_request(&headers, &Method::POST, "user@pam!token")),
+            Some(StatusCode::BAD_REQUEST)
+        );
+    }
+
+    #[test]
+    fn test_nonces_across_reload() {
+        let replay = ReplayProtection::with_state(None);
+        replay.check_nonce_at("reload@pam", 7, NOW).unwrap();
+        let state = serde_json::to_string(&*replay.nonces.lock().unwrap()).unwrap();
+
+        // the new process restores the nonces passed on
+        let reloaded = ReplayProtection::with_state(Some(&state));
+        assert_eq!(
+            status(reloaded.check_nonce_at("reload@pam", 7, NOW + 1)),
+            Some(StatusCode::CONFLICT)
+        );
+        reloaded.check_nonce_at("reload@pam", 8, NOW + 1).unwrap();
+
+        // a broken state is dropped
+        let reloaded = ReplayProtection::with_state(Some("garbage"));
+        reloaded.check_nonce_at("reload@pam", 1, NOW + 2).unwrap();
+    }
+}
@@ -0,0 +1,377 @@
+//! In-memory cache for the responses of expensive read-only API methods.
+//!
+//! API methods opt in by declaring a [`CachePolicy`] via
+//! [`ApiMethod::cache`](proxmox_router::ApiMethod::cache), the cache is enabled with
+//! [`ApiConfig::response_cache`](crate::ApiConfig::response_cache). Only successful `GET`
+//! responses are cached, authentication and permission checks still run on every request.
+//! Responses of streaming and HTTP handlers are never cached.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::{format_err, Error};
+use hyper::header::{self, HeaderMap, HeaderValue};
+use hyper::{Body, Response, StatusCode};
+use serde_json::Value;
+
+use proxmox_daemon::command_socket::CommandSocket;
+use proxmox_router::CachePolicy;
+
+/// The cache and location of an API request.
+pub(crate) struct CacheTarget<'a> {
+    pub cache: &'a ResponseCache,
+    /// The API path, without the output format.
+    pub path: String,
+    pub format: &'a str,
+}
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub(crate) struct CacheKey {
+    /// The API path, without the output format.
+    path: String,
+    /// Output format, user and parameters, depending on the cache policy.
+    variant: String,
+}
+
+struct CacheEntry {
+    created: Instant,
+    max_age: Duration,
+    last_used: u64,
+    status: StatusCode,
+    headers: HeaderMap,
+    body: hyper::body::Bytes,
+}
+
+struct CacheState {
+    entries: HashMap<CacheKey, CacheEntry>,
+    use_counter: u64,
+}
+
+/// A bounded cache for API responses, evicting the least recently used responses.
+///
+/// Mutating API handlers should call [`invalidate_prefix`](Self::invalidate_prefix) for the paths
+/// whose responses they change, or register the `api-cache-invalidate` command with
+/// [`register_command`](Self::register_command).
+pub struct ResponseCache {
+    state: Mutex<CacheState>,
+    capacity: usize,
+}
+
+impl ResponseCache {
+    /// Create a cache holding at most `capacity` responses.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            state: Mutex::new(CacheState {
+                entries: HashMap::new(),
+                use_counter: 0,
+            }),
+            capacity,
+        }
+    }
+
+    /// Drop the cached responses of `path` and all paths below it.
+    ///
+    /// Paths are API paths without the output format, e.g. `/nodes/localhost/subscription`. The
+    /// root path `/` clears the whole cache.
+    pub fn invalidate_prefix(&self, path: &str) {
+        let prefix = path.trim_end_matches('/');
+        self.state.lock().unwrap().entries.retain(|key, _| {
+            !(key.path.starts_with(prefix)
+                && matches!(key.path.as_bytes().get(prefix.len()), None | Some(b'/')))
+        });
+    }
+
+    /// The number of cached responses, including expired ones not yet evicted.
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().entries.len()
+    }
+
+    /// Returns true if no responses are cached.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Register the `api-cache-invalidate` command on the [CommandSocket].
+    ///
+    /// The command takes the API path to invalidate as `path` argument, see
+    /// [`invalidate_prefix`](Self::invalidate_prefix).
+    pub fn register_command(
+        self: std::sync::Arc<Self>,
+        commando_sock: &mut CommandSocket,
+    ) -> Result<(), Error> {
+        commando_sock.register_command("api-cache-invalidate".into(), move |args| {
+            let path = args
+                .and_then(|args| args.get("path"))
+                .and_then(Value::as_str)
+                .ok_or_else(|| format_err!("missing 'path' argument"))?;
+            self.invalidate_prefix(path);
+            Ok(Value::Null)
+        })
+    }
+
+    pub(crate) fn key(
+        policy: &CachePolicy,
+        path: &str,
+        format: &str,
+        auth_id: Option<&str>,
+        params: &Value,
+    ) -> CacheKey {
+        let mut variant = format.to_string();
+        if policy.vary_user {
+            variant.push('\n');
+            variant.push_str(auth_id.unwrap_or(""));
+        }
+        if policy.vary_parameters {
+            variant.push('\n');
+            variant.push_str(&params.to_string());
+        }
+
+        CacheKey {
+            path: format!("/{}", path.trim_matches('/')),
+            variant,
+        }
+    }
+
+    /// Get a cached response along with its `Age` header.
+    pub(crate) fn get(&self, key: &CacheKey) -> Option<Response<Body>> {
+        self.get_at(key, Instant::now())
+    }
+
+    fn get_at(&self, key: &CacheKey, now: Instant) -> Option<Response<Body>> {
+        let mut state = self.state.lock().unwrap();
+        state.use_counter += 1;
+        let use_counter = state.use_counter;
+
+        let entry = state.entries.get_mut(key)?;
+        let age = now.saturating_duration_since(entry.created);
+        if age >= entry.max_age {
+            state.entries.remove(key);
+            return None;
+        }
+        entry.last_used = use_counter;
+
+        let mut response = Response::new(Body::from(entry.body.clone()));
+        *response.status_mut() = entry.status;
+        *response.headers_mut() = entry.headers.clone();
+        response
+            .headers_mut()
+            .insert(header::AGE, HeaderValue::from(age.as_secs()));
+        Some(response)
+    }
+
+    /// Store a successful response, returning it with the collected body.
+    pub(crate) async fn insert(
+        &self,
+        key: CacheKey,
+        policy: &CachePolicy,
+        response: Response<Body>,
+    ) -> Result<Response<Body>, Error> {
+        if !response.status().is_success() {
+            return Ok(response);
+        }
+
+        let (parts, body) = response.into_parts();
+        let body = hyper::body::to_bytes(body).await?;
+        self.insert_at(
+            key,
+            policy,
+            &parts.headers,
+            parts.status,
+            body.clone(),
+            Instant::now(),
+        );
+
+        Ok(Response::from_parts(parts, Body::from(body)))
+    }
+
+    fn insert_at(
+        &self,
+        key: CacheKey,
+        policy: &CachePolicy,
+        headers: &HeaderMap,
+        status: StatusCode,
+        body: hyper::body::Bytes,
+        now: Instant,
+    ) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        state.use_counter += 1;
+        let last_used = state.use_counter;
+
+        if !state.entries.contains_key(&key) && state.entries.len() >= self.capacity {
+            // drop expired responses first, then the least recently used one
+            state
+                .entries
+                .retain(|_, entry| now.saturating_duration_since(entry.created) < entry.max_age);
+            if state.entries.len() >= self.capacity {
+                let lru = state
+                    .entries
+                    .iter()
+                    .min_by_key(|(_, entry)| entry.last_used)
+                    .map(|(key, _)| key.clone());
+                if let Some(lru) = lru {
+                    state.entries.remove(&lru);
+                }
+            }
+        }
+
+        state.entries.insert(
+            key,
+            CacheEntry {
+                created: now,
+                max_age: Duration::from_secs(policy.max_age),
+                last_used,
+                status,
+                headers: headers.clone(),
+                body,
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use hyper::header::{self, HeaderMap};
+    use hyper::StatusCode;
+    use serde_json::{json, Value};
+
+    use proxmox_router::CachePolicy;
+
+    use super::{CacheKey, ResponseCache};
+
+    const POLICY: CachePolicy = CachePolicy::new(60);
+
+    fn key(policy: &CachePolicy, path: &str, auth_id: &str, params: Value) -> CacheKey {
+        ResponseCache::key(policy, path, "json", Some(auth_id), &params)
+    }
+
+    fn insert(cache: &ResponseCache, key: &CacheKey, body: &'static str, now: Instant) {
+        cache.insert_at(
+            key.clone(),
+            &POLICY,
+            &HeaderMap::new(),
+            StatusCode::OK,
+            body.into(),
+            now,
+        );
+    }
+
+    fn get(cache: &ResponseCache, key: &CacheKey, now: Instant) -> Option<(String, String)> {
+        let response = cache.get_at(key, now)?;
+        let age = response.headers()[header::AGE]
+            .to_str()
+            .unwrap()
+            .to_string();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let body = runtime
+            .block_on(hyper::body::to_bytes(response.into_body()))
+            .unwrap();
+        Some((String::from_utf8(body.to_vec()).unwrap(), age))
+    }
+
+    #[test]
+    fn test_response_cache_expiry() {
+        let cache = ResponseCache::new(8);
+        let now = Instant::now();
+        let key = key(&POLICY, "/version", "root@pam", json!({}));
+
+        assert!(get(&cache, &key, now).is_none());
+        insert(&cache, &key, "1.0", now);
+
+        assert_eq!(
+            get(&cache, &key, now + Duration::from_secs(10)),
+            Some(("1.0".to_string(), "10".to_string())),
+        );
+        assert!(get(&cache, &key, now + Duration::from_secs(60)).is_none());
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_response_cache_vary() {
+        let cache = ResponseCache::new(8);
+        let now = Instant::now();
+
+        let root = key(&POLICY, "/status", "root@pam", json!({}));
+        insert(&cache, &root, "root", now);
+        assert!(get(&cache, &key(&POLICY, "/status", "user@pve", json!({})), now).is_none());
+        assert!(get(
+            &cache,
+            &key(&POLICY, "/status", "root@pam", json!({ "a": 1 })),
+            now
+        )
+        .is_none());
+        assert!(get(&cache, &root, now).is_some());
+
+        // without vary, users and parameters share the response
+        let shared = POLICY.vary_user(false).vary_parameters(false);
+        insert(
+            &cache,
+            &key(&shared, "/status", "root@pam", json!({})),
+            "shared",
+            now,
+        );
+        assert_eq!(
+            get(
+                &cache,
+                &key(&shared, "/status", "user@pve", json!({ "a": 1 })),
+                now
+            ),
+            Some(("shared".to_string(), "0".to_string())),
+        );
+
+        // output formats are cached separately
+        let extjs = ResponseCache::key(&shared, "/status", "extjs", None, &Value::Null);
+        assert!(get(&cache, &extjs, now).is_none());
+    }
+
+    #[test]
+    fn test_response_cache_invalidation() {
+        let cache = ResponseCache::new(8);
+        let now = Instant::now();
+
+        let nodes = key(&POLICY, "/nodes", "root@pam", json!({}));
+        let node = key(&POLICY, "/nodes/node1/status", "root@pam", json!({}));
+        let sibling = key(&POLICY, "/nodes-list", "root@pam", json!({}));
+        let version = key(&POLICY, "/version", "root@pam", json!({}));
+        for key in [&nodes, &node, &sibling, &version] {
+            insert(&cache, key, "data", now);
+        }
+
+        cache.invalidate_prefix("/nodes/");
+        assert!(get(&cache, &nodes, now).is_none());
+        assert!(get(&cache, &node, now).is_none());
+        assert!(get(&cache, &sibling, now).is_some());
+        assert!(get(&cache, &version, now).is_some());
+
+        cache.invalidate_prefix("/");
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_response_cache_eviction() {
+        let cache = ResponseCache::new(2);
+        let now = Instant::now();
+
+        let first = key(&POLICY, "/first", "root@pam", json!({}));
+        let second = key(&POLICY, "/second", "root@pam", json!({}));
+        let third = key(&POLICY, "/third", "root@pam", json!({}));
+
+        insert(&cache, &first, "1", now);
+        insert(&cache, &second, "2", now);
+        assert!(get(&cache, &first, now).is_some());
+        insert(&cache, &third, "3", now);
+
+        assert_eq!(cache.len(), 2);
+        assert!(get(&cache, &second, now).is_none());
+        assert!(get(&cache, &first, now).is_some());
+        assert!(get(&cache, &third, now).is_some());
+    }
+}
@@ -1,3 +1,4 @@
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::future::Future;
 use std::hash::BuildHasher;
@@ -11,29 +12,35 @@ use anyhow::{bail, format_err, Error};
 use futures::future::FutureExt;
 use futures::stream::TryStreamExt;
 use hyper::body::HttpBody;
-use hyper::header::{self, HeaderMap};
+use hyper::header::{self, HeaderMap, HeaderValue};
 use hyper::http::request::Parts;
 use hyper::{Body, Request, Response, StatusCode};
 use regex::Regex;
-use serde_json::Value;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 use tokio::fs::File;
 use tokio::time::Instant;
 use tower_service::Service;
 use url::form_urlencoded;
 
+use proxmox_router::format::router_node_to_json;
 use proxmox_router::{
-    check_api_permission, ApiHandler, ApiMethod, HttpError, Permission, RpcEnvironment,
-    RpcEnvironmentType, UserInformation,
+    check_api_permission, ApiHandler, ApiMethod, DispatchHooks, HookRequest, HttpError, Permission,
+    RawBody, RawResponse, RpcEnvironment, RpcEnvironmentType, UserInformation, DRY_RUN_PARAMETER,
 };
 use proxmox_router::{http_bail, http_err};
-use proxmox_schema::{ObjectSchemaType, ParameterSchema};
+use proxmox_schema::{api, ObjectSchemaType, ParameterSchema, REDACTED};
 
 use proxmox_async::stream::AsyncReaderStream;
 use proxmox_compression::DeflateEncoder;
+use proxmox_config_digest::DigestMismatchError;
 use proxmox_log::FileLogger;
 
+use crate::audit::Auditor;
+use crate::response_cache::CacheTarget;
 use crate::{
-    formatter::*, normalize_path, ApiConfig, AuthError, CompressionMethod, RestEnvironment,
+    formatter::*, normalize_path, ApiConfig, AuditCall, AuthError, ClientDisconnect,
+    CompressionMethod, PrecompressedEncoding, ResponseCache, RestEnvironment,
 };
 
 extern "C" {
@@ -42,6 +49,9 @@ extern "C" {
 
 struct AuthStringExtension(String);
 
+/// The path and query of the request to log, with the values of secret parameters redacted.
+struct RedactedUriExtension(String);
+
 pub(crate) struct EmptyUserInformation {}
 
 impl UserInformation for EmptyUserInformation {
@@ -90,6 +100,7 @@ impl<T: PeerAddress> Service<&T> for RestServer {
             Err(err) => Err(format_err!("unable to get peer address - {}", err)),
             Ok(peer) => Ok(ApiService {
                 peer,
+                tls: ctx.is_tls(),
                 api_config: Arc::clone(&self.api_config),
             }),
         })
@@ -171,6 +182,11 @@ impl Service<Request<Body>> for RedirectService {
 
 pub trait PeerAddress {
     fn peer_addr(&self) -> Result<std::net::SocketAddr, Error>;
+
+    /// Whether the connection is encrypted with TLS.
+    fn is_tls(&self) -> bool {
+        false
+    }
 }
 
 // tokio_openssl's SslStream requires the stream to be pinned in order to accept it, and we need to
@@ -180,12 +196,20 @@ impl<T: PeerAddress> PeerAddress for Pin<Box<T>> {
     fn peer_addr(&self) -> Result<std::net::SocketAddr, Error> {
         T::peer_addr(&**self)
     }
+
+    fn is_tls(&self) -> bool {
+        T::is_tls(&**self)
+    }
 }
 
 impl<T: PeerAddress> PeerAddress for tokio_openssl::SslStream<T> {
     fn peer_addr(&self) -> Result<std::net::SocketAddr, Error> {
         self.get_ref().peer_addr()
     }
+
+    fn is_tls(&self) -> bool {
+        true
+    }
 }
 
 impl PeerAddress for tokio::net::TcpStream {
@@ -213,6 +237,20 @@ impl<T: PeerAddress> PeerAddress for proxmox_http::RateLimitedStream<T> {
     fn peer_addr(&self) -> Result<std::net::SocketAddr, Error> {
         self.inner().peer_addr()
     }
+
+    fn is_tls(&self) -> bool {
+        self.inner().is_tls()
+    }
+}
+
+impl<T: PeerAddress> PeerAddress for crate::connection::TrackedStream<T> {
+    fn peer_addr(&self) -> Result<std::net::SocketAddr, Error> {
+        self.inner().peer_addr()
+    }
+
+    fn is_tls(&self) -> bool {
+        self.inner().is_tls()
+    }
 }
 
 // Helper [Service] containing the peer Address
@@ -225,11 +263,25 @@ impl<T: PeerAddress> PeerAddress for proxmox_http::RateLimitedStream<T> {
 // not export it.
 pub struct ApiService {
     pub peer: std::net::SocketAddr,
+    pub tls: bool,
     pub api_config: Arc<ApiConfig>,
 }
 
+#[api]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+/// Format of the access log lines.
+pub enum AccessLogFormat {
+    /// The common log format with the user agent appended, like pve-http-server writes it.
+    #[default]
+    Combined,
+    /// One JSON object per line.
+    Json,
+}
+
 fn log_response(
     logfile: Option<&Arc<Mutex<FileLogger>>>,
+    format: AccessLogFormat,
     peer: &std::net::SocketAddr,
     method: hyper::Method,
     path_query: &str,
@@ -240,6 +292,11 @@ fn log_response(
         return;
     };
 
+    let path_query = match resp.extensions().get::<RedactedUriExtension>() {
+        Some(RedactedUriExtension(redacted)) => redacted.as_str(),
+        None => path_query,
+    };
+
     // we also log URL-to-long requests, so avoid message bigger than PIPE_BUF (4k on Linux)
     // to profit from atomicty guarantees for O_APPEND opened logfiles
     let path = &path_query[..MAX_URI_QUERY_LENGTH.min(path_query.len())];
@@ -269,24 +326,83 @@ fn log_response(
             None => "-".to_string(),
         };
         let now = proxmox_time::epoch_i64();
-        // time format which apache/nginx use (by default), copied from pve-http-server
-        let datetime = proxmox_time::strftime_local("%d/%m/%Y:%H:%M:%S %z", now)
-            .unwrap_or_else(|_| "-".to_string());
-
-        logfile.lock().unwrap().log(format!(
-            "{} - {} [{}] \"{} {}\" {} {} {}",
-            peer.ip(),
-            auth_id,
-            datetime,
-            method.as_str(),
-            path,
-            status.as_str(),
-            resp.body().size_hint().lower(),
-            user_agent.unwrap_or_else(|| "-".to_string()),
-        ));
+        let size = resp.body().size_hint().lower();
+
+        let line = match format {
+            AccessLogFormat::Combined => {
+                // time format which apache/nginx use (by default), copied from pve-http-server
+                let datetime = proxmox_time::strftime_local("%d/%m/%Y:%H:%M:%S %z", now)
+                    .unwrap_or_else(|_| "-".to_string());
+
+                format!(
+                    "{} - {} [{}] \"{} {}\" {} {} {}",
+                    peer.ip(),
+                    auth_id,
+                    datetime,
+                    method.as_str(),
+                    path,
+                    status.as_str(),
+                    size,
+                    user_agent.unwrap_or_else(|| "-".to_string()),
+                )
+            }
+            AccessLogFormat::Json => json!({
+                "client": peer.ip().to_string(),
+                "user": auth_id,
+                "time": now,
+                "method": method.as_str(),
+                "path": path,
+                "status": status.as_u16(),
+                "size": size,
+                "user-agent": user_agent,
+            })
+            .to_string(),
+        };
+        logfile.lock().unwrap().log(line);
     }
 }
 
+/// Get the path and query of `uri` with the values of secret parameters in the query replaced by
+/// [`REDACTED`], `None` if the query contains no secret parameter.
+fn redact_query_secrets(param_schema: ParameterSchema, uri: &hyper::Uri) -> Option<String> {
+    let query = uri.query()?;
+
+    let mut redacted = false;
+    let pairs: Vec<Cow<str>> = query
+        .split('&')
+        .map(|pair| {
+            // keep the encoding of the other parameters as sent by the client
+            let raw_name = pair.split_once('=').map_or(pair, |(name, _)| name);
+            let name = form_urlencoded::parse(raw_name.as_bytes()).next();
+            match name.and_then(|(name, _)| param_schema.lookup(&name)) {
+                Some((_, schema)) if schema.is_secret() => {
+                    redacted = true;
+                    Cow::Owned(format!("{raw_name}={REDACTED}"))
+                }
+                _ => Cow::Borrowed(pair),
+            }
+        })
+        .collect();
+
+    redacted.then(|| format!("{}?{}", uri.path(), pairs.join("&")))
+}
+
+/// Attach the redacted URI for the access log to the response of a request, errors are turned
+/// into a response for this.
+fn with_redacted_uri(
+    result: Result<Response<Body>, Error>,
+    redacted_uri: Option<String>,
+) -> Result<Response<Body>, Error> {
+    let Some(redacted_uri) = redacted_uri else {
+        return result;
+    };
+    let mut response = result.unwrap_or_else(crate::formatter::error_to_response);
+    response
+        .extensions_mut()
+        .insert(RedactedUriExtension(redacted_uri));
+    Ok(response)
+}
+
 fn get_proxied_peer(headers: &HeaderMap) -> Option<std::net::SocketAddr> {
     static RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"for="([^"]+)""#).unwrap());
     let forwarded = headers.get(header::FORWARDED)?.to_str().ok()?;
@@ -317,18 +433,26 @@ impl Service<Request<Body>> for ApiService {
         Poll::Ready(Ok(()))
     }
 
-    fn call(&mut self, req: Request<Body>) -> Self::Future {
+    fn call(&mut self, mut req: Request<Body>) -> Self::Future {
         let path = req.uri().path_and_query().unwrap().as_str().to_owned();
+        let uri_path = req.uri().path().to_owned();
         let method = req.method().clone();
         let user_agent = get_user_agent(req.headers());
+        let tls = self.tls;
 
         let config = Arc::clone(&self.api_config);
         let peer = match get_proxied_peer(req.headers()) {
-            Some(proxied_peer) => proxied_peer,
-            None => self.peer,
+            Some(proxied_peer) if config.is_trusted_proxy(&self.peer) => proxied_peer,
+            _ => self.peer,
         };
+
+        // hyper drops the request when the client disconnects, which raises the signal
+        let disconnect = ClientDisconnect::new();
+        let disconnect_guard = disconnect.guard();
+        req.extensions_mut().insert(disconnect);
+
         async move {
-            let response = match Arc::clone(&config).handle_request(req, &peer).await {
+            let mut response = match Arc::clone(&config).handle_request(req, &peer).await {
                 Ok(response) => response,
                 Err(err) => {
                     let (err, code) = match err.downcast_ref::<HttpError>() {
@@ -341,16 +465,53 @@ impl Service<Request<Body>> for ApiService {
                         .body(err.into())?
                 }
             };
+            disconnect_guard.disarm();
+            if let Some(headers) = config.get_default_headers() {
+                headers.apply(&uri_path, tls, response.headers_mut());
+            }
             let logger = config.get_access_log();
-            log_response(logger, &peer, method, &path, &response, user_agent);
+            let format = config.get_access_log_format();
+            log_response(logger, format, &peer, method, &path, &response, user_agent);
             Ok(response)
         }
         .boxed()
     }
 }
 
+/// Collect the parameters of the query string and the path.
+fn query_and_path_parameters<S: 'static + BuildHasher + Send>(
+    param_list: &mut Vec<(String, String)>,
+    parts: &Parts,
+    uri_param: &HashMap<String, String, S>,
+) {
+    if let Some(query_str) = parts.uri.query() {
+        for (k, v) in form_urlencoded::parse(query_str.as_bytes()).into_owned() {
+            if k == "_dc" {
+                continue;
+            } // skip extjs "disable cache" parameter
+            param_list.push((k, v));
+        }
+    }
+
+    for (k, v) in uri_param {
+        param_list.push((k.clone(), v.clone()));
+    }
+}
+
+/// Reject the dry-run parameter for methods not declaring it, instead of just failing the
+/// parameter verification or ignoring it with additional properties.
+fn check_dry_run_support(info: &ApiMethod, requested: bool) -> Result<(), Error> {
+    if requested && !info.supports_dry_run() {
+        http_bail!(
+            BAD_REQUEST,
+            "parameter '{DRY_RUN_PARAMETER}' is not supported by this method"
+        );
+    }
+    Ok(())
+}
+
 fn parse_query_parameters<S: 'static + BuildHasher + Send>(
-    param_schema: ParameterSchema,
+    info: &ApiMethod,
     form: &str, // x-www-form-urlencoded body data
     parts: &Parts,
     uri_param: &HashMap<String, String, S>,
@@ -363,43 +524,104 @@ fn parse_query_parameters<S: 'static + BuildHasher + Send>(
         }
     }
 
-    if let Some(query_str) = parts.uri.query() {
-        for (k, v) in form_urlencoded::parse(query_str.as_bytes()).into_owned() {
-            if k == "_dc" {
-                continue;
-            } // skip extjs "disable cache" parameter
-            param_list.push((k, v));
+    query_and_path_parameters(&mut param_list, parts, uri_param);
+    check_dry_run_support(info, param_list.iter().any(|(k, _)| k == DRY_RUN_PARAMETER))?;
+
+    let params = info.parameters.parse_parameter_strings(&param_list, true)?;
+
+    Ok(params)
+}
+
+/// Merge the parameters of the query string and the path into a JSON request body.
+///
+/// Path parameters take precedence over the query string, which takes precedence over the body:
+/// a parameter given in more than one place must have the same value everywhere, otherwise the
+/// request is rejected.
+fn parse_json_parameters<S: 'static + BuildHasher + Send>(
+    info: &ApiMethod,
+    body: &str,
+    parts: &Parts,
+    uri_param: &HashMap<String, String, S>,
+) -> Result<Value, Error> {
+    // treat empty body as empty parameter hash
+    let mut params = if body.is_empty() {
+        serde_json::Map::new()
+    } else {
+        match serde_json::from_str(body) {
+            Ok(Value::Object(params)) => params,
+            Ok(_) => http_bail!(BAD_REQUEST, "request body is not a JSON object"),
+            Err(err) => http_bail!(BAD_REQUEST, "unable to parse JSON request body - {err}"),
         }
-    }
+    };
 
-    for (k, v) in uri_param {
-        param_list.push((k.clone(), v.clone()));
+    let mut param_list = Vec::new();
+    query_and_path_parameters(&mut param_list, parts, uri_param);
+    check_dry_run_support(
+        info,
+        params.contains_key(DRY_RUN_PARAMETER)
+            || param_list.iter().any(|(k, _)| k == DRY_RUN_PARAMETER),
+    )?;
+    if !param_list.is_empty() {
+        let url_params = info
+            .parameters
+            .parse_parameter_strings(&param_list, false)?;
+        for (name, value) in url_params.as_object().into_iter().flatten() {
+            match params.get(name) {
+                None => {
+                    params.insert(name.clone(), value.clone());
+                }
+                Some(body_value) if body_value == value => (),
+                Some(_) => http_bail!(
+                    BAD_REQUEST,
+                    "parameter '{name}' conflicts with the value in the request body"
+                ),
+            }
+        }
     }
 
-    let params = param_schema.parse_parameter_strings(&param_list, true)?;
-
+    let mut params = Value::Object(params);
+    info.parameters.normalize_booleans(&mut params);
+    info.parameters.verify_json(&params)?;
     Ok(params)
 }
 
+/// Request body encodings supported for API parameters.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum BodyEncoding {
+    FormUrlEncoded,
+    Json,
+}
+
+fn request_body_encoding(headers: &HeaderMap) -> Result<BodyEncoding, Error> {
+    let value = match headers.get(header::CONTENT_TYPE) {
+        Some(value) => value,
+        None => return Ok(BodyEncoding::FormUrlEncoded),
+    };
+
+    let media_type = value
+        .to_str()
+        .ok()
+        .and_then(|value| value.split(';').next())
+        .map(|media_type| media_type.trim().to_ascii_lowercase());
+
+    match media_type.as_deref() {
+        Some("application/x-www-form-urlencoded") => Ok(BodyEncoding::FormUrlEncoded),
+        Some("application/json") => Ok(BodyEncoding::Json),
+        Some("multipart/form-data") => http_bail!(
+            UNSUPPORTED_MEDIA_TYPE,
+            "multipart/form-data is only supported by upload handlers"
+        ),
+        _ => http_bail!(UNSUPPORTED_MEDIA_TYPE, "unsupported content type {value:?}"),
+    }
+}
+
 async fn get_request_parameters<S: 'static + BuildHasher + Send>(
-    param_schema: ParameterSchema,
+    info: &ApiMethod,
     parts: Parts,
     req_body: Body,
     uri_param: HashMap<String, String, S>,
 ) -> Result<Value, Error> {
-    let mut is_json = false;
-
-    if let Some(value) = parts.headers.get(header::CONTENT_TYPE) {
-        match value.to_str().map(|v| v.split(';').next()) {
-            Ok(Some("application/x-www-form-urlencoded")) => {
-                is_json = false;
-            }
-            Ok(Some("application/json")) => {
-                is_json = true;
-            }
-            _ => bail!("unsupported content type {:?}", value.to_str()),
-        }
-    }
+    let encoding = request_body_encoding(&parts.headers)?;
 
     let body = TryStreamExt::map_err(req_body, |err| {
         http_err!(BAD_REQUEST, "Problems reading request body: {}", err)
@@ -418,26 +640,58 @@ async fn get_request_parameters<S: 'static + BuildHasher + Send>(
     let utf8_data =
         std::str::from_utf8(&body).map_err(|err| format_err!("Request body not uft8: {}", err))?;
 
-    if is_json {
-        // treat empty body as empty parameter hash
-        let mut params: Value = if utf8_data.is_empty() {
-            Value::Object(serde_json::Map::new())
-        } else {
-            serde_json::from_str(utf8_data)?
-        };
-        for (k, v) in uri_param {
-            if let Some((_optional, prop_schema)) = param_schema.lookup(&k) {
-                params[&k] = prop_schema.parse_simple_value(&v)?;
-            }
-        }
-        param_schema.verify_json(&params)?;
-        Ok(params)
-    } else {
-        parse_query_parameters(param_schema, utf8_data, &parts, &uri_param)
+    match encoding {
+        BodyEncoding::Json => parse_json_parameters(info, utf8_data, &parts, &uri_param),
+        BodyEncoding::FormUrlEncoded => parse_query_parameters(info, utf8_data, &parts, &uri_param),
+    }
+}
+
+/// Name of the parameter used for optimistic locking via configuration digests.
+const DIGEST_PARAM: &str = "digest";
+
+/// Translate an `If-Match` header into the `digest` parameter if the method declares one.
+///
+/// Only a single strong entity tag is supported, `*` matches any digest.
+fn if_match_to_digest_param<S: BuildHasher>(
+    param_schema: ParameterSchema,
+    parts: &Parts,
+    uri_param: &mut HashMap<String, String, S>,
+) -> Result<(), Error> {
+    let value = match parts.headers.get(header::IF_MATCH) {
+        Some(value) => value,
+        None => return Ok(()),
+    };
+
+    if param_schema.lookup(DIGEST_PARAM).is_none() {
+        return Ok(());
+    }
+
+    let value = value
+        .to_str()
+        .map_err(|err| http_err!(BAD_REQUEST, "invalid If-Match header - {err}"))?
+        .trim();
+
+    if value == "*" {
+        return Ok(());
     }
+
+    let digest = value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .filter(|digest| !digest.contains('"'))
+        .ok_or_else(|| {
+            http_err!(
+                BAD_REQUEST,
+                "If-Match header must contain a single strong entity tag"
+            )
+        })?;
+
+    uri_param.insert(DIGEST_PARAM.to_string(), digest.to_string());
+
+    Ok(())
 }
 
-struct NoLogExtension();
+pub(crate) struct NoLogExtension();
 
 async fn proxy_protected_request(
     config: &ApiConfig,
@@ -482,7 +736,7 @@ async fn proxy_protected_request(
     Ok(resp)
 }
 
-fn delay_unauth_time() -> std::time::Instant {
+pub(crate) fn delay_unauth_time() -> std::time::Instant {
     std::time::Instant::now() + std::time::Duration::from_millis(3000)
 }
 
@@ -490,6 +744,75 @@ fn access_forbidden_time() -> std::time::Instant {
     std::time::Instant::now() + std::time::Duration::from_millis(500)
 }
 
+/// Check whether a request asks for the self-description of an API node, either via `OPTIONS` or
+/// via a `schema=1` query on a `GET` method which has no `schema` parameter itself.
+fn is_self_description_request(
+    parts: &Parts,
+    router: &'static proxmox_router::Router,
+    components: &[&str],
+) -> bool {
+    match parts.method {
+        hyper::Method::OPTIONS => true,
+        hyper::Method::GET => {
+            let query = parts.uri.query().unwrap_or_default();
+            if !form_urlencoded::parse(query.as_bytes()).any(|(k, v)| k == "schema" && v == "1") {
+                return false;
+            }
+            match router.find_method(components, hyper::Method::GET, &mut HashMap::new()) {
+                Some(api_method) => api_method.parameters.lookup("schema").is_none(),
+                None => true,
+            }
+        }
+        _ => false,
+    }
+}
+
+/// Describe the router node at `components`, omitting methods the caller has no permission for.
+///
+/// Returns `None` if there is no such node.
+async fn describe_api_node(
+    config: &ApiConfig,
+    router: &'static proxmox_router::Router,
+    components: &[&str],
+    headers: &HeaderMap,
+    rpcenv: &mut RestEnvironment,
+) -> Result<Option<Value>, Error> {
+    let privileges = config.get_self_description().unwrap_or_default();
+
+    let mut uri_param = HashMap::new();
+    let node = match router.find_route(components, &mut uri_param) {
+        Some(node) => node,
+        None => return Ok(None),
+    };
+
+    // the description is read-only, so authenticate like a GET request (no CSRF token needed)
+    let user_info: Box<dyn UserInformation + Send + Sync> =
+        match config.check_auth(headers, &hyper::Method::GET).await {
+            Ok((authid, info)) => {
+                rpcenv.set_auth_id(Some(authid));
+                info
+            }
+            Err(AuthError::NoData) => Box::new(EmptyUserInformation {}),
+            Err(AuthError::Generic(err)) => {
+                rpcenv.log_failed_auth(None, &err.to_string());
+
+                let err = http_err!(UNAUTHORIZED, "authentication failed - {}", err);
+                tokio::time::sleep_until(Instant::from_std(delay_unauth_time())).await;
+                return Err(err);
+            }
+        };
+
+    let auth_id = rpcenv.get_auth_id();
+    Ok(Some(router_node_to_json(node, privileges, |api_method| {
+        check_api_permission(
+            api_method.access.permission,
+            auth_id.as_deref(),
+            &uri_param,
+            user_info.as_ref(),
+        )
+    })))
+}
+
 fn handle_stream_as_json_seq(stream: proxmox_router::Stream) -> Result<Response<Body>, Error> {
     let (mut send, body) = hyper::Body::channel();
     tokio::spawn(async move {
@@ -522,18 +845,66 @@ fn handle_sync_stream_as_json_seq(
         .body(Body::wrap_stream(futures::stream::iter(iter)))?)
 }
 
+// raw data is passed on as is, without an output formatter
+fn raw_response(info: &'static ApiMethod, data: RawResponse) -> Result<Response<Body>, Error> {
+    let content_type = data
+        .content_type
+        .as_deref()
+        .or(info.returns.content_type)
+        .unwrap_or("application/octet-stream");
+
+    let mut response = Response::builder()
+        .status(http::StatusCode::OK)
+        .header(http::header::CONTENT_TYPE, content_type);
+    for (name, value) in &data.extra_headers {
+        response = response.header(name.as_str(), value.as_str());
+    }
+
+    let body = match data.body {
+        RawBody::Bytes(data) => Body::from(data),
+        RawBody::Stream(stream) => Body::wrap_stream(stream),
+    };
+
+    Ok(response.body(body)?)
+}
+
+/// Drop the body of the response to a `HEAD` request, keeping the headers of a `GET` response.
+///
+/// The `Content-Length` header is set to the body length, unless it is unknown (e.g. for streamed
+/// responses, which are sent chunked) or already set.
+fn discard_body(mut resp: Response<Body>) -> Response<Body> {
+    if !resp.headers().contains_key(header::CONTENT_LENGTH) {
+        if let Some(len) = resp.body().size_hint().exact() {
+            resp.headers_mut()
+                .insert(header::CONTENT_LENGTH, HeaderValue::from(len));
+        }
+    }
+    resp.map(|_| Body::empty())
+}
+
+#[allow(clippy::too_many_arguments)]
 pub(crate) async fn handle_api_request<Env: RpcEnvironment, S: 'static + BuildHasher + Send>(
     mut rpcenv: Env,
     info: &'static ApiMethod,
     formatter: Option<&'static dyn OutputFormatter>,
     parts: Parts,
     req_body: Body,
-    uri_param: HashMap<String, String, S>,
+    mut uri_param: HashMap<String, String, S>,
+    hooks: DispatchHooks,
+    cache: Option<CacheTarget<'_>>,
+    auditor: Option<Auditor<'_>>,
+    handler_timeout: Option<std::time::Duration>,
 ) -> Result<Response<Body>, Error> {
     let formatter = formatter.unwrap_or(crate::formatter::DIRECT_JSON_FORMATTER);
 
     let compression = extract_compression_method(&parts.headers);
 
+    let is_head = parts.method == hyper::Method::HEAD;
+    let is_get = parts.method == hyper::Method::GET || is_head;
+    if !is_get {
+        if_match_to_digest_param(info.parameters, &parts, &mut uri_param)?;
+    }
+
     let accept_json_seq = parts.headers.get_all(http::header::ACCEPT).iter().any(|h| {
         h.as_ref()
             .split(|&b| b == b',')
@@ -541,76 +912,192 @@ pub(crate) async fn handle_api_request<Env: RpcEnvironment, S: 'static + BuildHa
             .any(|e| e == b"application/json-seq" || e.starts_with(b"application/json-seq;"))
     });
 
-    let result = match info.handler {
-        ApiHandler::AsyncHttp(handler) => {
-            let params = parse_query_parameters(info.parameters, "", &parts, &uri_param)?;
-            (handler)(parts, req_body, params, info, Box::new(rpcenv)).await
-        }
-        ApiHandler::StreamSync(handler) => {
-            let params =
-                get_request_parameters(info.parameters, parts, req_body, uri_param).await?;
-            match (handler)(params, info, &mut rpcenv) {
-                Ok(iter) if accept_json_seq => handle_sync_stream_as_json_seq(iter),
-                Ok(iter) => iter
-                    .try_collect()
-                    .map(|data| formatter.format_data(data, &rpcenv)),
-                Err(err) => Err(err),
-            }
-        }
-        ApiHandler::StreamAsync(handler) => {
-            let params =
-                get_request_parameters(info.parameters, parts, req_body, uri_param).await?;
-            match (handler)(params, info, &mut rpcenv).await {
-                Ok(stream) if accept_json_seq => handle_stream_as_json_seq(stream),
-                Ok(stream) => stream
-                    .try_collect()
-                    .await
-                    .map(|data| formatter.format_data(data, &rpcenv)),
-                Err(err) => Err(err),
-            }
+    let method = parts.method.clone();
+    let uri = parts.uri.clone();
+    let disconnect = parts.extensions.get::<ClientDisconnect>().cloned();
+    let (params, http_request) = match info.handler {
+        ApiHandler::AsyncHttp(_) => {
+            let params = parse_query_parameters(info, "", &parts, &uri_param)?;
+            (params, Some((parts, req_body)))
         }
-        ApiHandler::SerializingSync(handler) => {
-            let params =
-                get_request_parameters(info.parameters, parts, req_body, uri_param).await?;
-            (handler)(params, info, &mut rpcenv)
-                .and_then(|data| formatter.format_data_streaming(data, &rpcenv))
+        _ => {
+            let params = get_request_parameters(info, parts, req_body, uri_param).await?;
+            (params, None)
         }
-        ApiHandler::SerializingAsync(handler) => {
-            let params =
-                get_request_parameters(info.parameters, parts, req_body, uri_param).await?;
-            (handler)(params, info, &mut rpcenv)
-                .await
-                .and_then(|data| formatter.format_data_streaming(data, &rpcenv))
+    };
+
+    if log::log_enabled!(log::Level::Debug) {
+        let mut logged_params = params.clone();
+        info.parameters.redact_secrets(&mut logged_params);
+        log::debug!("{method} {}: parameters {logged_params}", uri.path());
+    }
+
+    // a response to a HEAD request may come from a dedicated handler without a body
+    let cacheable = is_get
+        && !is_head
+        && !matches!(
+            info.handler,
+            ApiHandler::AsyncHttp(_) | ApiHandler::StreamSync(_) | ApiHandler::StreamAsync(_)
+        );
+    let cache = match (cache, info.cache) {
+        (Some(target), Some(policy)) if cacheable => {
+            let auth_id = rpcenv.get_auth_id();
+            let key = ResponseCache::key(
+                &policy,
+                &target.path,
+                target.format,
+                auth_id.as_deref(),
+                &params,
+            );
+            Some((target.cache, policy, key))
         }
-        ApiHandler::Sync(handler) => {
-            let params =
-                get_request_parameters(info.parameters, parts, req_body, uri_param).await?;
-            (handler)(params, info, &mut rpcenv).map(|data| formatter.format_data(data, &rpcenv))
+        _ => None,
+    };
+
+    let hook_params = (!hooks.is_empty()).then(|| params.clone());
+    let request = HookRequest {
+        method: Some(method.as_str()),
+        api_method: info,
+        params: hook_params.as_ref().unwrap_or(&Value::Null),
+        auth_id: rpcenv.get_auth_id(),
+    };
+
+    let audit_params = auditor.filter(|_| Auditor::audits(&method)).map(|_| {
+        let mut params = params.clone();
+        info.parameters.redact_secrets(&mut params);
+        params
+    });
+    let audit = auditor.zip(audit_params.as_ref()).map(|(auditor, params)| {
+        let call = AuditCall {
+            method: &method,
+            path: uri.path(),
+            auth_id: request.auth_id.as_deref(),
+            client_ip: rpcenv.get_client_ip(),
+            params,
+        };
+        (auditor, call)
+    });
+    let audited = match &audit {
+        Some((auditor, call)) => auditor
+            .before(call)
+            .map_err(|err| http_err!(INTERNAL_SERVER_ERROR, "{err}")),
+        None => Ok(()),
+    };
+
+    // the hooks run on every request, a cached response only replaces the handler call
+    let mut cache_hit = false;
+    let result = match audited
+        .and_then(|()| hooks.pre_dispatch(&request, &mut rpcenv))
+        .map(|()| cache.as_ref().and_then(|(cache, _, key)| cache.get(key)))
+    {
+        Err(err) => Err(err),
+        Ok(Some(response)) => {
+            cache_hit = true;
+            Ok(response)
         }
-        ApiHandler::Async(handler) => {
-            let params =
-                get_request_parameters(info.parameters, parts, req_body, uri_param).await?;
-            (handler)(params, info, &mut rpcenv)
-                .await
-                .map(|data| formatter.format_data(data, &rpcenv))
+        Ok(None) => {
+            let call = async move {
+                match (info.handler, http_request) {
+                    (ApiHandler::AsyncHttp(handler), Some((parts, req_body))) => {
+                        let handler = (handler)(parts, req_body, params, info, Box::new(rpcenv));
+                        match disconnect {
+                            Some(disconnect) => disconnect.run(handler).await,
+                            None => handler.await,
+                        }
+                    }
+                    (ApiHandler::StreamSync(handler), _) => {
+                        match (handler)(params, info, &mut rpcenv) {
+                            Ok(iter) if accept_json_seq => handle_sync_stream_as_json_seq(iter),
+                            Ok(iter) => iter
+                                .try_collect()
+                                .map(|data| formatter.format_data(data, &rpcenv)),
+                            Err(err) => Err(err),
+                        }
+                    }
+                    (ApiHandler::StreamAsync(handler), _) => {
+                        match (handler)(params, info, &mut rpcenv).await {
+                            Ok(stream) if accept_json_seq => handle_stream_as_json_seq(stream),
+                            Ok(stream) => stream
+                                .try_collect()
+                                .await
+                                .map(|data| formatter.format_data(data, &rpcenv)),
+                            Err(err) => Err(err),
+                        }
+                    }
+                    (ApiHandler::SerializingSync(handler), _) => {
+                        (handler)(params, info, &mut rpcenv)
+                            .and_then(|data| formatter.format_data_streaming(data, &rpcenv))
+                    }
+                    (ApiHandler::SerializingAsync(handler), _) => {
+                        (handler)(params, info, &mut rpcenv)
+                            .await
+                            .and_then(|data| formatter.format_data_streaming(data, &rpcenv))
+                    }
+                    (ApiHandler::Sync(handler), _) => (handler)(params, info, &mut rpcenv)
+                        .map(|data| formatter.format_data(data, &rpcenv)),
+                    (ApiHandler::Async(handler), _) => (handler)(params, info, &mut rpcenv)
+                        .await
+                        .map(|data| formatter.format_data(data, &rpcenv)),
+                    (ApiHandler::Raw(handler), _) => (handler)(params, info, &mut rpcenv)
+                        .and_then(|data| raw_response(info, data)),
+                    (ApiHandler::RawAsync(handler), _) => (handler)(params, info, &mut rpcenv)
+                        .await
+                        .and_then(|data| raw_response(info, data)),
+                    _ => {
+                        bail!("Unknown API handler type");
+                    }
+                }
+            };
+            match handler_timeout {
+                Some(timeout) => tokio::time::timeout(timeout, call)
+                    .await
+                    .unwrap_or_else(|_| {
+                        Err(http_err!(
+                            SERVICE_UNAVAILABLE,
+                            "API handler did not finish within {}s",
+                            timeout.as_secs_f64(),
+                        ))
+                    }),
+                None => call.await,
+            }
         }
-        _ => {
-            bail!("Unknown API handler type");
+    };
+    hooks.post_dispatch(&request, result.as_ref().map(|_| ()));
+
+    let result = match (result, cache) {
+        (Ok(resp), Some((cache, policy, key))) if !cache_hit => {
+            cache.insert(key, &policy, resp).await
         }
+        (result, _) => result,
     };
 
     let mut resp = match result {
-        Ok(resp) => resp,
+        Ok(mut resp) => {
+            // only GET responses describe the current state of the resource
+            if !is_get {
+                resp.headers_mut().remove(header::ETAG);
+            }
+            resp
+        }
         Err(err) => {
             if let Some(httperr) = err.downcast_ref::<HttpError>() {
                 if httperr.code == StatusCode::UNAUTHORIZED {
                     tokio::time::sleep_until(Instant::from_std(delay_unauth_time())).await;
                 }
             }
+            let err = if err.is::<DigestMismatchError>() {
+                http_err!(PRECONDITION_FAILED, "{err}")
+            } else {
+                err
+            };
             formatter.format_error(err)
         }
     };
 
+    if let Some((auditor, call)) = &audit {
+        auditor.after(call, resp.status());
+    }
+
     let is_streaming = accept_json_seq
         && resp
             .headers()
@@ -637,6 +1124,8 @@ pub(crate) async fn handle_api_request<Env: RpcEnvironment, S: 'static + BuildHa
         None => resp,
     };
 
+    let resp = if is_head { discard_body(resp) } else { resp };
+
     if info.reload_timezone {
         unsafe {
             tzset();
@@ -739,10 +1228,100 @@ async fn chunked_static_file_download(
     Ok(resp.body(body).unwrap())
 }
 
+/// Get the quality value the client assigned to `coding` in its `Accept-Encoding` header.
+///
+/// Returns `None` if the coding is not acceptable, either because it is not listed (and there is
+/// no `*` entry) or because its quality value is zero.
+fn accepted_encoding_quality(headers: &HeaderMap, coding: &str) -> Option<f32> {
+    let encodings = headers.get(header::ACCEPT_ENCODING)?.to_str().ok()?;
+
+    let mut wildcard = None;
+    for entry in encodings.split(',') {
+        let mut parts = entry.split(';');
+        let name = parts.next().unwrap_or("").trim();
+        let quality = parts
+            .find_map(|param| param.trim().strip_prefix("q="))
+            .map(|q| q.trim().parse::<f32>().unwrap_or(0.0))
+            .unwrap_or(1.0);
+
+        if name.eq_ignore_ascii_case(coding) {
+            return (quality > 0.0).then_some(quality);
+        } else if name == "*" {
+            wildcard = Some(quality);
+        }
+    }
+
+    wildcard.filter(|quality| *quality > 0.0)
+}
+
+/// Find the best pre-compressed sibling of `filename` (e.g. `file.js.zst`) the client accepts.
+///
+/// Siblings older than the file itself are left from a previous version and ignored.
+async fn find_precompressed_variant(
+    filename: &Path,
+    identity: &std::fs::Metadata,
+    headers: &HeaderMap,
+) -> Option<(PathBuf, std::fs::Metadata, PrecompressedEncoding)> {
+    let mut candidates: Vec<(PrecompressedEncoding, f32)> = PrecompressedEncoding::ALL
+        .into_iter()
+        .filter_map(|encoding| {
+            accepted_encoding_quality(headers, encoding.name()).map(|q| (encoding, q))
+        })
+        .collect();
+    // stable sort, so the order of `ALL` decides on equal quality values
+    candidates.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    for (encoding, _) in candidates {
+        let mut path = filename.as_os_str().to_owned();
+        path.push(".");
+        path.push(encoding.extension());
+        let path = PathBuf::from(path);
+
+        match tokio::fs::metadata(&path).await {
+            Ok(metadata) if metadata.is_file() => {
+                if let (Ok(modified), Ok(identity_modified)) =
+                    (metadata.modified(), identity.modified())
+                {
+                    if modified < identity_modified {
+                        continue;
+                    }
+                }
+                return Some((path, metadata, encoding));
+            }
+            _ => continue,
+        }
+    }
+
+    None
+}
+
+/// Build an entity tag from the metadata of the served file, distinct per content encoding.
+fn static_file_etag(metadata: &std::fs::Metadata, encoding: Option<&str>) -> HeaderValue {
+    use std::os::unix::fs::MetadataExt;
+
+    let etag = match encoding {
+        Some(encoding) => format!("\"{:x}-{:x}-{encoding}\"", metadata.mtime(), metadata.len()),
+        None => format!("\"{:x}-{:x}\"", metadata.mtime(), metadata.len()),
+    };
+    HeaderValue::from_str(&etag).unwrap()
+}
+
+/// Check the `If-None-Match` header against an entity tag, using the weak comparison.
+fn if_none_match(headers: &HeaderMap, etag: &HeaderValue) -> bool {
+    let Some(Ok(tags)) = headers.get(header::IF_NONE_MATCH).map(|v| v.to_str()) else {
+        return false;
+    };
+    let etag = etag.to_str().unwrap_or("");
+
+    tags.split(',')
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == etag)
+}
+
 async fn handle_static_file_download(
     components: &[&str],
     filename: PathBuf,
-    compression: Option<CompressionMethod>,
+    headers: &HeaderMap,
 ) -> Result<Response<Body>, Error> {
     let metadata = match tokio::fs::metadata(filename.clone()).await {
         Ok(metadata) => metadata,
@@ -758,7 +1337,39 @@ async fn handle_static_file_download(
     };
 
     let (content_type, nocomp) = extension_to_content_type(&filename);
-    let compression = if nocomp { None } else { compression };
+
+    let variant = if nocomp {
+        None
+    } else {
+        find_precompressed_variant(&filename, &metadata, headers).await
+    };
+
+    let (filename, metadata, precompressed, compression) = match variant {
+        Some((path, metadata, encoding)) => (path, metadata, Some(encoding), None),
+        None if nocomp => (filename, metadata, None, None),
+        None => (
+            filename,
+            metadata,
+            None,
+            extract_compression_method(headers),
+        ),
+    };
+
+    let etag = match (&precompressed, &compression) {
+        (Some(encoding), _) => static_file_etag(&metadata, Some(encoding.extension())),
+        (None, Some(method)) => static_file_etag(&metadata, Some(method.extension())),
+        (None, None) => static_file_etag(&metadata, None),
+    };
+
+    if if_none_match(headers, &etag) {
+        let mut response = Response::builder()
+            .status(StatusCode::NOT_MODIFIED)
+            .header(header::ETAG, etag);
+        if !nocomp {
+            response = response.header(header::VARY, "Accept-Encoding");
+        }
+        return Ok(response.body(Body::empty())?);
+    }
 
     let file = File::open(filename).await.map_err(|err| {
         http_err!(
@@ -769,11 +1380,22 @@ async fn handle_static_file_download(
         )
     })?;
 
-    if metadata.len() < CHUNK_SIZE_LIMIT {
-        simple_static_file_download(file, content_type, compression).await
+    let mut response = if metadata.len() < CHUNK_SIZE_LIMIT {
+        simple_static_file_download(file, content_type, compression).await?
     } else {
-        chunked_static_file_download(file, content_type, compression).await
+        chunked_static_file_download(file, content_type, compression).await?
+    };
+
+    let response_headers = response.headers_mut();
+    if let Some(encoding) = precompressed {
+        response_headers.insert(header::CONTENT_ENCODING, encoding.content_encoding());
     }
+    if !nocomp {
+        response_headers.insert(header::VARY, HeaderValue::from_static("Accept-Encoding"));
+    }
+    response_headers.insert(header::ETAG, etag);
+
+    Ok(response)
 }
 
 // FIXME: support handling multiple compression methods
@@ -794,9 +1416,30 @@ impl ApiConfig {
         req: Request<Body>,
         peer: &std::net::SocketAddr,
     ) -> Result<Response<Body>, Error> {
-        let (parts, body) = req.into_parts();
-        let method = parts.method.clone();
-        let path = normalize_path(parts.uri.path())?;
+        let Some(limiter) = self.get_body_rate_limiter() else {
+            return self.handle_request_do(req, peer).await;
+        };
+
+        // handlers may change the limits through the request extension
+        let rate_limit = limiter.request_limits();
+        let (mut parts, body) = req.into_parts();
+        let body = rate_limit.limit_request_body(body);
+        parts.extensions.insert(rate_limit.clone());
+
+        let response = self
+            .handle_request_do(Request::from_parts(parts, body), peer)
+            .await?;
+        Ok(rate_limit.limit_response_body(response))
+    }
+
+    async fn handle_request_do(
+        self: Arc<ApiConfig>,
+        req: Request<Body>,
+        peer: &std::net::SocketAddr,
+    ) -> Result<Response<Body>, Error> {
+        let (parts, body) = req.into_parts();
+        let method = parts.method.clone();
+        let path = normalize_path(parts.uri.path())?;
         let components: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
 
         let query = parts.uri.query().unwrap_or_default();
@@ -811,6 +1454,28 @@ impl ApiConfig {
         let mut rpcenv = RestEnvironment::new(env_type, Arc::clone(&self));
 
         rpcenv.set_client_ip(Some(*peer));
+        if let Some(disconnect) = parts.extensions.get::<ClientDisconnect>() {
+            rpcenv.set_client_disconnect(disconnect.clone());
+        }
+
+        let maintenance = self.get_maintenance();
+
+        if let Some(health) = self.get_health_options() {
+            let state = maintenance.map(|maintenance| maintenance.state());
+            if let Some(response) = health.handle_request(&method, &path, state).await {
+                return Ok(response);
+            }
+        }
+
+        if let Some(renewal) = self.get_ticket_renewal() {
+            if renewal.matches(&method, &path) {
+                return renewal.handle_request(&self, &parts.headers, &rpcenv).await;
+            }
+        }
+
+        if let Some(response) = maintenance.and_then(|m| m.check_request(&method, &path)) {
+            return Ok(response);
+        }
 
         if let Some(handler) = self.find_handler(&components) {
             let relative_path_components = &components[handler.prefix.len()..];
@@ -845,8 +1510,7 @@ impl ApiConfig {
             Ok(self.get_index(rpcenv, parts).await)
         } else {
             let filename = self.find_alias(&components);
-            let compression = extract_compression_method(&parts.headers);
-            handle_static_file_download(&components, filename, compression).await
+            handle_static_file_download(&components, filename, &parts.headers).await
         }
     }
 }
@@ -894,7 +1558,14 @@ pub(crate) enum Action {
 impl Action {
     async fn handle_request(&self, data: ApiRequestData<'_>) -> Result<Response<Body>, Error> {
         match self {
-            Action::Formatted(a) => a.handle_request(data).await,
+            Action::Formatted(a) => {
+                // the output format may be negotiated via the `Accept` header
+                let mut response = a.handle_request(data).await?;
+                response
+                    .headers_mut()
+                    .append(header::VARY, header::HeaderValue::from_static("Accept"));
+                Ok(response)
+            }
             Action::Unformatted(a) => a.handle_request(data).await,
         }
     }
@@ -931,98 +1602,152 @@ impl Formatted {
             http_bail!(NOT_FOUND, "invalid api path '{}'", full_path);
         }
 
-        let format = relative_path_components[0];
-
-        let formatter: &dyn OutputFormatter = match format {
-            "json" => JSON_FORMATTER,
-            "extjs" => EXTJS_FORMATTER,
-            _ => bail!("Unsupported output format '{}'.", format),
+        let prefix = relative_path_components[0];
+        let (format, formatter) = match config
+            .get_output_formats()
+            .negotiate(prefix, parts.headers.get(header::ACCEPT))
+        {
+            Some(format) => (format.name, format.formatter),
+            None => bail!("Unsupported output format '{}'.", prefix),
         };
 
+        if config.get_self_description().is_some()
+            && is_self_description_request(&parts, self.router, &relative_path_components[1..])
+        {
+            return match describe_api_node(
+                config,
+                self.router,
+                &relative_path_components[1..],
+                &parts.headers,
+                &mut rpcenv,
+            )
+            .await?
+            {
+                Some(data) => Ok(formatter.format_data(data, &rpcenv)),
+                None => {
+                    let err = http_err!(NOT_FOUND, "Path '{}' not found.", full_path);
+                    Ok(formatter.format_error(err))
+                }
+            };
+        }
+
         let mut uri_param = HashMap::new();
-        let api_method = self.router.find_method(
+        let (api_method, hooks) = match self.router.find_method_with_hooks(
             &relative_path_components[1..],
             parts.method.clone(),
             &mut uri_param,
-        );
-
-        let mut auth_required = true;
-        if let Some(api_method) = api_method {
-            if let Permission::World = *api_method.access.permission {
-                auth_required = false; // no auth for endpoints with World permission
-            }
-        }
+        ) {
+            Some((api_method, hooks)) => (Some(api_method), hooks),
+            None => (None, DispatchHooks::default()),
+        };
 
-        let mut user_info: Box<dyn UserInformation + Send + Sync> =
-            Box::new(EmptyUserInformation {});
+        // before any check, so that the responses of failed requests are logged without secrets
+        let redacted_uri = api_method
+            .and_then(|api_method| redact_query_secrets(api_method.parameters, &parts.uri));
 
-        if auth_required {
-            match config.check_auth(&parts.headers, &parts.method).await {
-                Ok((authid, info)) => {
-                    rpcenv.set_auth_id(Some(authid));
-                    user_info = info;
+        let result = async move {
+            let mut auth_required = true;
+            if let Some(api_method) = api_method {
+                if let Permission::World = *api_method.access.permission {
+                    auth_required = false; // no auth for endpoints with World permission
                 }
-                Err(auth_err) => {
-                    let err = match auth_err {
-                        AuthError::Generic(err) => err,
-                        AuthError::NoData => {
-                            format_err!("no authentication credentials provided.")
-                        }
-                    };
-                    // fixme: log Username??
-                    rpcenv.log_failed_auth(None, &err.to_string());
+            }
 
-                    // always delay unauthorized calls by 3 seconds (from start of request)
-                    let err = http_err!(UNAUTHORIZED, "authentication failed - {}", err);
-                    tokio::time::sleep_until(Instant::from_std(delay_unauth_time())).await;
-                    return Err(err);
+            let mut user_info: Box<dyn UserInformation + Send + Sync> =
+                Box::new(EmptyUserInformation {});
+
+            if auth_required {
+                match config.check_auth(&parts.headers, &parts.method).await {
+                    Ok((authid, info)) => {
+                        config.check_replay(&parts.headers, &parts.method, &authid)?;
+                        rpcenv.set_auth_id(Some(authid));
+                        user_info = info;
+                    }
+                    Err(auth_err) => {
+                        let err = match auth_err {
+                            AuthError::Generic(err) => err,
+                            AuthError::NoData => {
+                                format_err!("no authentication credentials provided.")
+                            }
+                        };
+                        // fixme: log Username??
+                        rpcenv.log_failed_auth(None, &err.to_string());
+
+                        // always delay unauthorized calls by 3 seconds (from start of request)
+                        let err = http_err!(UNAUTHORIZED, "authentication failed - {}", err);
+                        tokio::time::sleep_until(Instant::from_std(delay_unauth_time())).await;
+                        return Err(err);
+                    }
                 }
             }
-        }
 
-        match api_method {
-            None => {
-                let err = http_err!(NOT_FOUND, "Path '{}' not found.", full_path);
-                Ok(formatter.format_error(err))
-            }
-            Some(api_method) => {
-                let auth_id = rpcenv.get_auth_id();
-                let user_info = user_info;
-
-                if !check_api_permission(
-                    api_method.access.permission,
-                    auth_id.as_deref(),
-                    &uri_param,
-                    user_info.as_ref(),
-                ) {
-                    let err = http_err!(FORBIDDEN, "permission check failed");
-                    tokio::time::sleep_until(Instant::from_std(access_forbidden_time())).await;
-                    return Ok(formatter.format_error(err));
+            match api_method {
+                None => {
+                    let err = http_err!(NOT_FOUND, "Path '{}' not found.", full_path);
+                    Ok(formatter.format_error(err))
                 }
+                Some(api_method) => {
+                    let auth_id = rpcenv.get_auth_id();
+                    let user_info: Arc<dyn UserInformation + Send + Sync> = user_info.into();
+                    rpcenv.set_user_info(Some(Arc::clone(&user_info)));
+
+                    if let Err(err) = api_method.normalize_path_params(&mut uri_param) {
+                        return Ok(formatter.format_error(err.into()));
+                    }
+
+                    if !check_api_permission(
+                        api_method.access.permission,
+                        auth_id.as_deref(),
+                        &uri_param,
+                        user_info.as_ref(),
+                    ) {
+                        let err = http_err!(FORBIDDEN, "permission check failed");
+                        tokio::time::sleep_until(Instant::from_std(access_forbidden_time())).await;
+                        return Ok(formatter.format_error(err));
+                    }
+
+                    let handler_timeout = config.get_handler_timeout(&parts.method, api_method);
+                    let result =
+                        if api_method.protected && rpcenv.env_type == RpcEnvironmentType::PUBLIC {
+                            proxy_protected_request(config, api_method, parts, body, peer).await
+                        } else {
+                            let cache = config.get_response_cache().map(|cache| CacheTarget {
+                                cache,
+                                path: relative_path_components[1..].join("/"),
+                                format,
+                            });
+                            handle_api_request(
+                                rpcenv,
+                                api_method,
+                                Some(formatter),
+                                parts,
+                                body,
+                                uri_param,
+                                hooks,
+                                cache,
+                                config.get_auditor(),
+                                handler_timeout,
+                            )
+                            .await
+                        };
+
+                    let mut response = match result {
+                        Ok(resp) => resp,
+                        Err(err) => formatter.format_error(err),
+                    };
 
-                let result = if api_method.protected
-                    && rpcenv.env_type == RpcEnvironmentType::PUBLIC
-                {
-                    proxy_protected_request(config, api_method, parts, body, peer).await
-                } else {
-                    handle_api_request(rpcenv, api_method, Some(formatter), parts, body, uri_param)
-                        .await
-                };
-
-                let mut response = match result {
-                    Ok(resp) => resp,
-                    Err(err) => formatter.format_error(err),
-                };
-
-                if let Some(auth_id) = auth_id {
-                    response
-                        .extensions_mut()
-                        .insert(AuthStringExtension(auth_id));
+                    if let Some(auth_id) = auth_id {
+                        response
+                            .extensions_mut()
+                            .insert(AuthStringExtension(auth_id));
+                    }
+                    Ok(response)
                 }
-
-                Ok(response)
             }
         }
+        .await;
+
+        with_redacted_uri(result, redacted_uri)
     }
 }
 
@@ -1047,85 +1772,1637 @@ impl Unformatted {
             http_bail!(NOT_FOUND, "invalid api path '{}'", full_path);
         }
 
+        if config.get_self_description().is_some()
+            && is_self_description_request(&parts, self.router, relative_path_components)
+        {
+            return match describe_api_node(
+                config,
+                self.router,
+                relative_path_components,
+                &parts.headers,
+                &mut rpcenv,
+            )
+            .await?
+            {
+                Some(data) => Ok(DIRECT_JSON_FORMATTER.format_data(data, &rpcenv)),
+                None => http_bail!(NOT_FOUND, "Path '{}' not found.", full_path),
+            };
+        }
+
         let mut uri_param = HashMap::new();
-        let api_method = self.router.find_method(
+        let (api_method, hooks) = match self.router.find_method_with_hooks(
             relative_path_components,
             parts.method.clone(),
             &mut uri_param,
-        );
+        ) {
+            Some((api_method, hooks)) => (Some(api_method), hooks),
+            None => (None, DispatchHooks::default()),
+        };
 
-        let mut auth_required = true;
-        if let Some(api_method) = api_method {
-            if let Permission::World = *api_method.access.permission {
-                auth_required = false; // no auth for endpoints with World permission
-            }
-        }
+        // before any check, so that the responses of failed requests are logged without secrets
+        let redacted_uri = api_method
+            .and_then(|api_method| redact_query_secrets(api_method.parameters, &parts.uri));
 
-        let user_info: Box<dyn UserInformation + Send + Sync>;
+        let result = async move {
+            let mut auth_required = true;
+            if let Some(api_method) = api_method {
+                if let Permission::World = *api_method.access.permission {
+                    auth_required = false; // no auth for endpoints with World permission
+                }
+            }
 
-        if auth_required {
-            match config.check_auth(&parts.headers, &parts.method).await {
-                Ok((authid, info)) => {
-                    rpcenv.set_auth_id(Some(authid));
-                    user_info = info;
+            let user_info: Box<dyn UserInformation + Send + Sync>;
+
+            if auth_required {
+                match config.check_auth(&parts.headers, &parts.method).await {
+                    Ok((authid, info)) => {
+                        config.check_replay(&parts.headers, &parts.method, &authid)?;
+                        rpcenv.set_auth_id(Some(authid));
+                        user_info = info;
+                    }
+                    Err(auth_err) => {
+                        let err = match auth_err {
+                            AuthError::Generic(err) => err,
+                            AuthError::NoData => {
+                                format_err!("no authentication credentials provided.")
+                            }
+                        };
+                        // fixme: log Username??
+                        rpcenv.log_failed_auth(None, &err.to_string());
+
+                        // always delay unauthorized calls by 3 seconds (from start of request)
+                        let err = http_err!(UNAUTHORIZED, "authentication failed - {}", err);
+                        tokio::time::sleep_until(Instant::from_std(delay_unauth_time())).await;
+                        return Err(err);
+                    }
                 }
-                Err(auth_err) => {
-                    let err = match auth_err {
-                        AuthError::Generic(err) => err,
-                        AuthError::NoData => {
-                            format_err!("no authentication credentials provided.")
-                        }
+            } else {
+                user_info = Box::new(EmptyUserInformation {});
+            }
+
+            match api_method {
+                None => http_bail!(NOT_FOUND, "Path '{}' not found.", full_path),
+                Some(api_method) => {
+                    let auth_id = rpcenv.get_auth_id();
+                    let user_info: Arc<dyn UserInformation + Send + Sync> = user_info.into();
+                    rpcenv.set_user_info(Some(Arc::clone(&user_info)));
+
+                    api_method.normalize_path_params(&mut uri_param)?;
+
+                    if !check_api_permission(
+                        api_method.access.permission,
+                        auth_id.as_deref(),
+                        &uri_param,
+                        user_info.as_ref(),
+                    ) {
+                        let err = http_err!(FORBIDDEN, "permission check failed");
+                        tokio::time::sleep_until(Instant::from_std(access_forbidden_time())).await;
+                        return Err(err);
+                    }
+
+                    let handler_timeout = config.get_handler_timeout(&parts.method, api_method);
+                    let result =
+                        if api_method.protected && rpcenv.env_type == RpcEnvironmentType::PUBLIC {
+                            proxy_protected_request(config, api_method, parts, body, peer).await
+                        } else {
+                            let cache = config.get_response_cache().map(|cache| CacheTarget {
+                                cache,
+                                path: relative_path_components.join("/"),
+                                format: "unformatted",
+                            });
+                            handle_api_request(
+                                rpcenv,
+                                api_method,
+                                None,
+                                parts,
+                                body,
+                                uri_param,
+                                hooks,
+                                cache,
+                                config.get_auditor(),
+                                handler_timeout,
+                            )
+                            .await
+                        };
+
+                    let mut response = match result {
+                        Ok(resp) => resp,
+                        Err(err) => crate::formatter::error_to_response(err),
                     };
-                    // fixme: log Username??
-                    rpcenv.log_failed_auth(None, &err.to_string());
 
-                    // always delay unauthorized calls by 3 seconds (from start of request)
-                    let err = http_err!(UNAUTHORIZED, "authentication failed - {}", err);
-                    tokio::time::sleep_until(Instant::from_std(delay_unauth_time())).await;
-                    return Err(err);
+                    if let Some(auth_id) = auth_id {
+                        response
+                            .extensions_mut()
+                            .insert(AuthStringExtension(auth_id));
+                    }
+                    Ok(response)
                 }
             }
-        } else {
-            user_info = Box::new(EmptyUserInformation {});
-        }
-
-        match api_method {
-            None => http_bail!(NOT_FOUND, "Path '{}' not found.", full_path),
-            Some(api_method) => {
-                let auth_id = rpcenv.get_auth_id();
-                let user_info = user_info;
-
-                if !check_api_permission(
-                    api_method.access.permission,
-                    auth_id.as_deref(),
-                    &uri_param,
-                    user_info.as_ref(),
-                ) {
-                    let err = http_err!(FORBIDDEN, "permission check failed");
-                    tokio::time::sleep_until(Instant::from_std(access_forbidden_time())).await;
-                    return Err(err);
-                }
+        }
+        .await;
 
-                let result =
-                    if api_method.protected && rpcenv.env_type == RpcEnvironmentType::PUBLIC {
-                        proxy_protected_request(config, api_method, parts, body, peer).await
-                    } else {
-                        handle_api_request(rpcenv, api_method, None, parts, body, uri_param).await
-                    };
+        with_redacted_uri(result, redacted_uri)
+    }
+}
 
-                let mut response = match result {
-                    Ok(resp) => resp,
-                    Err(err) => crate::formatter::error_to_response(err),
-                };
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use anyhow::Error;
+    use hyper::{header, Body, Method, Request, Response, StatusCode};
+    use serde_json::{json, Value};
+
+    use proxmox_config_digest::{ConfigDigest, PROXMOX_CONFIG_DIGEST_SCHEMA};
+    use proxmox_router::{
+        http_bail, ApiFuture, ApiHandler, ApiMethod, CachePolicy, DispatchHooks, ExpectedDuration,
+        HookRequest, Permission, RawApiFuture, RawBody, RawResponse, Router, RouterHooks,
+        RpcEnvironment, RpcEnvironmentType, UserInformation, DRY_RUN_SCHEMA,
+    };
+    use proxmox_schema::format::{parameter_schema_to_json, return_type_to_json, schema_to_json};
+    use proxmox_schema::{
+        ApiStringFormat, ArraySchema, BooleanSchema, EnumEntry, IntegerSchema, ObjectSchema,
+        ReturnType, Schema, StringSchema,
+    };
 
-                if let Some(auth_id) = auth_id {
-                    response
-                        .extensions_mut()
-                        .insert(AuthStringExtension(auth_id));
-                }
+    use super::{
+        get_request_parameters, handle_api_request, handle_static_file_download,
+        parse_query_parameters, redact_query_secrets, ApiService, EmptyUserInformation,
+        RedactedUriExtension,
+    };
+    use crate::{
+        ApiConfig, AuditCall, AuditHook, AuthError, DefaultHeaders, FileAuditLog, HandlerTimeouts,
+        IndexHandler, ResponseCache,
+    };
 
-                Ok(response)
+    const CURRENT_DIGEST: &str = "0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
+
+    const STALE_DIGEST: &str = "fedcba9876543210fedcba9876543210fedcba9876543210fedcba9876543210";
+
+    #[derive(Default)]
+    struct TestEnvironment {
+        result_attributes: Value,
+    }
+
+    impl RpcEnvironment for TestEnvironment {
+        fn result_attrib_mut(&mut self) -> &mut Value {
+            &mut self.result_attributes
+        }
+
+        fn result_attrib(&self) -> &Value {
+            &self.result_attributes
+        }
+
+        fn env_type(&self) -> RpcEnvironmentType {
+            RpcEnvironmentType::PUBLIC
+        }
+
+        fn set_auth_id(&mut self, _auth_id: Option<String>) {}
+
+        fn get_auth_id(&self) -> Option<String> {
+            None
+        }
+    }
+
+    fn get_config(
+        _param: Value,
+        _info: &ApiMethod,
+        rpcenv: &mut dyn RpcEnvironment,
+    ) -> Result<Value, Error> {
+        rpcenv["digest"] = CURRENT_DIGEST.into();
+        Ok(json!({ "key": "value" }))
+    }
+
+    fn update_config(
+        param: Value,
+        _info: &ApiMethod,
+        rpcenv: &mut dyn RpcEnvironment,
+    ) -> Result<Value, Error> {
+        let current: ConfigDigest = CURRENT_DIGEST.parse()?;
+        let digest: Option<ConfigDigest> = param["digest"].as_str().map(str::parse).transpose()?;
+        current.detect_modification(digest.as_ref())?;
+
+        rpcenv["digest"] = CURRENT_DIGEST.into();
+        Ok(Value::Null)
+    }
+
+    const API_METHOD_GET_CONFIG: ApiMethod = ApiMethod::new(
+        &ApiHandler::Sync(&get_config),
+        &ObjectSchema::new("Get the configuration.", &[]),
+    );
+
+    const API_METHOD_UPDATE_CONFIG: ApiMethod = ApiMethod::new(
+        &ApiHandler::Sync(&update_config),
+        &ObjectSchema::new(
+            "Update the configuration.",
+            &[("digest", true, &PROXMOX_CONFIG_DIGEST_SCHEMA)],
+        ),
+    );
+
+    fn slow_query<'a>(
+        _param: Value,
+        _info: &'static ApiMethod,
+        _rpcenv: &'a mut dyn RpcEnvironment,
+    ) -> ApiFuture<'a> {
+        Box::pin(async {
+            tokio::time::sleep(std::time::Duration::from_secs(10)).await;
+            Ok(Value::Null)
+        })
+    }
+
+    const API_METHOD_SLOW_QUERY: ApiMethod = ApiMethod::new(
+        &ApiHandler::Async(&slow_query),
+        &ObjectSchema::new("Query something slowly.", &[]),
+    )
+    .expected_duration(ExpectedDuration::Slow);
+
+    fn call(
+        method: Method,
+        info: &'static ApiMethod,
+        if_match: Option<&str>,
+    ) -> Result<Response<Body>, Error> {
+        call_with_timeouts(method, info, if_match, None)
+    }
+
+    fn call_with_timeouts(
+        method: Method,
+        info: &'static ApiMethod,
+        if_match: Option<&str>,
+        timeouts: Option<&HandlerTimeouts>,
+    ) -> Result<Response<Body>, Error> {
+        let mut request = Request::builder().method(method).uri("/config");
+        if let Some(if_match) = if_match {
+            request = request.header(header::IF_MATCH, if_match);
+        }
+        let (parts, body) = request.body(Body::empty())?.into_parts();
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()?;
+        runtime.block_on(handle_api_request(
+            TestEnvironment::default(),
+            info,
+            Some(crate::formatter::JSON_FORMATTER),
+            parts,
+            body,
+            HashMap::<String, String>::new(),
+            DispatchHooks::default(),
+            None,
+            None,
+            timeouts.and_then(|timeouts| timeouts.for_method(info)),
+        ))
+    }
+
+    #[test]
+    fn test_handler_timeouts() -> Result<(), Error> {
+        let short = Some(std::time::Duration::from_millis(20));
+        let timeouts = HandlerTimeouts::new().timeout(ExpectedDuration::Slow, short);
+
+        let resp = call_with_timeouts(Method::GET, &API_METHOD_SLOW_QUERY, None, Some(&timeouts))?;
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        // the timeouts of other classes do not apply
+        let timeouts = HandlerTimeouts::new().timeout(ExpectedDuration::Fast, short);
+        let resp = call_with_timeouts(Method::GET, &API_METHOD_GET_CONFIG, None, Some(&timeouts))?;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_etag_and_if_match() -> Result<(), Error> {
+        let resp = call(Method::GET, &API_METHOD_GET_CONFIG, None)?;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let etag = resp.headers()[header::ETAG].to_str()?.to_string();
+        assert_eq!(etag, format!("\"{CURRENT_DIGEST}\""));
+
+        let resp = call(Method::PUT, &API_METHOD_UPDATE_CONFIG, Some(&etag))?;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(resp.headers().get(header::ETAG).is_none());
+
+        let resp = call(Method::PUT, &API_METHOD_UPDATE_CONFIG, Some("*"))?;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_if_match_precondition_failed() -> Result<(), Error> {
+        let stale = format!("\"{STALE_DIGEST}\"");
+        let resp = call(Method::PUT, &API_METHOD_UPDATE_CONFIG, Some(&stale))?;
+        assert_eq!(resp.status(), StatusCode::PRECONDITION_FAILED);
+
+        let err = call(Method::PUT, &API_METHOD_UPDATE_CONFIG, Some("W/\"abc\"")).unwrap_err();
+        let err = err.downcast_ref::<proxmox_router::HttpError>().unwrap();
+        assert_eq!(err.code, StatusCode::BAD_REQUEST);
+
+        Ok(())
+    }
+
+    const TAG_PARAMETERS: ObjectSchema = ObjectSchema::new(
+        "Parameters.",
+        &[
+            ("force", true, &BooleanSchema::new("Force.").schema()),
+            ("name", true, &StringSchema::new("Name.").schema()),
+            (
+                "size",
+                true,
+                &ArraySchema::new("Sizes.", &IntegerSchema::new("Size.").schema()).schema(),
+            ),
+            (
+                "tag",
+                true,
+                &ArraySchema::new("Tags.", &StringSchema::new("Tag.").schema()).schema(),
+            ),
+        ],
+    );
+
+    const API_METHOD_TAGS: ApiMethod =
+        ApiMethod::new(&ApiHandler::Sync(&remove_disk), &TAG_PARAMETERS);
+
+    fn parse_query(uri: &str, form: &str) -> Result<Value, Error> {
+        let (parts, _body) = Request::builder().uri(uri).body(())?.into_parts();
+        parse_query_parameters(
+            &API_METHOD_TAGS,
+            form,
+            &parts,
+            &HashMap::<String, String>::new(),
+        )
+    }
+
+    #[test]
+    fn test_query_repeated_keys() -> Result<(), Error> {
+        assert_eq!(
+            parse_query("/tags?tag=a&name=n&tag=b&size=1", "tag=c&size=2")?,
+            json!({ "name": "n", "size": [2, 1], "tag": ["c", "a", "b"] }),
+        );
+        assert_eq!(parse_query("/tags?tag=a", "")?, json!({ "tag": ["a"] }));
+
+        let err = parse_query("/tags?name=a&tag=b&name=c", "").unwrap_err();
+        assert!(err.to_string().contains("name"), "{err}");
+        assert!(parse_query("/tags?name=a", "name=b").is_err());
+        assert!(parse_query("/tags?size=1&size=x", "").is_err());
+
+        Ok(())
+    }
+
+    fn parse_body(content_type: Option<&str>, uri: &str, body: &str) -> Result<Value, Error> {
+        let mut request = Request::builder().method(Method::POST).uri(uri);
+        if let Some(content_type) = content_type {
+            request = request.header(header::CONTENT_TYPE, content_type);
+        }
+        let (parts, body) = request.body(Body::from(body.to_string()))?.into_parts();
+
+        let mut uri_param = HashMap::new();
+        uri_param.insert("name".to_string(), "n".to_string());
+
+        let runtime = tokio::runtime::Builder::new_current_thread().build()?;
+        runtime.block_on(get_request_parameters(
+            &API_METHOD_TAGS,
+            parts,
+            body,
+            uri_param,
+        ))
+    }
+
+    fn status_code(err: &Error) -> Option<StatusCode> {
+        err.downcast_ref::<proxmox_router::HttpError>()
+            .map(|err| err.code)
+    }
+
+    #[test]
+    fn test_request_body_content_types() -> Result<(), Error> {
+        let form = "application/x-www-form-urlencoded; charset=UTF-8";
+        assert_eq!(
+            parse_body(Some(form), "/tags?size=1", "tag=a&size=2")?,
+            json!({ "name": "n", "size": [2, 1], "tag": ["a"] }),
+        );
+        assert_eq!(
+            parse_body(None, "/tags", "tag=a")?,
+            json!({ "name": "n", "tag": ["a"] }),
+        );
+
+        assert_eq!(
+            parse_body(
+                Some("application/json"),
+                "/tags?tag=b",
+                r#"{ "name": "n", "size": [1, 2] }"#,
+            )?,
+            json!({ "name": "n", "size": [1, 2], "tag": ["b"] }),
+        );
+        assert_eq!(
+            parse_body(Some("Application/JSON"), "/tags", "")?,
+            json!({ "name": "n" }),
+        );
+
+        // booleans sent as strings
+        assert_eq!(
+            parse_body(Some("application/json"), "/tags", r#"{ "force": "yes" }"#)?,
+            json!({ "force": true, "name": "n" }),
+        );
+        assert_eq!(
+            parse_body(Some(form), "/tags", "force=Off")?,
+            json!({ "force": false, "name": "n" }),
+        );
+        let err =
+            parse_body(Some("application/json"), "/tags", r#"{ "force": "maybe" }"#).unwrap_err();
+        assert!(err.to_string().contains("force"), "{err}");
+
+        let err =
+            parse_body(Some("application/json"), "/tags", r#"{ "size": ["x"] }"#).unwrap_err();
+        assert!(err.to_string().contains("size"), "{err}");
+        for body in ["[1]", "{"] {
+            let err = parse_body(Some("application/json"), "/tags", body).unwrap_err();
+            assert_eq!(status_code(&err), Some(StatusCode::BAD_REQUEST));
+        }
+
+        for content_type in ["multipart/form-data; boundary=x", "text/plain"] {
+            let err = parse_body(Some(content_type), "/tags", "").unwrap_err();
+            assert_eq!(status_code(&err), Some(StatusCode::UNSUPPORTED_MEDIA_TYPE),);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_request_parameter_conflicts() -> Result<(), Error> {
+        let json = Some("application/json");
+
+        // the path parameter wins over the body, unless both agree
+        let err = parse_body(json, "/tags", r#"{ "name": "other" }"#).unwrap_err();
+        assert_eq!(status_code(&err), Some(StatusCode::BAD_REQUEST));
+        assert!(err.to_string().contains("'name'"), "{err}");
+        assert_eq!(
+            parse_body(json, "/tags", r#"{ "name": "n" }"#)?,
+            json!({ "name": "n" }),
+        );
+
+        let err = parse_body(json, "/tags?tag=a", r#"{ "tag": ["b"] }"#).unwrap_err();
+        assert_eq!(status_code(&err), Some(StatusCode::BAD_REQUEST));
+        assert!(err.to_string().contains("'tag'"), "{err}");
+
+        assert!(parse_body(None, "/tags", "name=other").is_err());
+        assert!(parse_body(json, "/tags?name=other", "").is_err());
+
+        Ok(())
+    }
+
+    fn remove_disk(
+        param: Value,
+        _info: &ApiMethod,
+        _rpcenv: &mut dyn RpcEnvironment,
+    ) -> Result<Value, Error> {
+        Ok(json!({ "removed": !param["dry-run"].as_bool().unwrap_or(false) }))
+    }
+
+    const API_METHOD_REMOVE_DISK: ApiMethod = ApiMethod::new(
+        &ApiHandler::Sync(&remove_disk),
+        &ObjectSchema::new("Remove a disk.", &[("dry-run", true, &DRY_RUN_SCHEMA)]),
+    )
+    .access(None, &Permission::World);
+
+    const API_METHOD_WIPE_DISK: ApiMethod = ApiMethod::new(
+        &ApiHandler::Sync(&remove_disk),
+        &ObjectSchema::new("Wipe a disk.", &[]),
+    )
+    .access(None, &Permission::World);
+
+    const DRY_RUN_API_ROUTER: Router = Router::new().subdirs(&[
+        ("remove", &Router::new().post(&API_METHOD_REMOVE_DISK)),
+        ("wipe", &Router::new().post(&API_METHOD_WIPE_DISK)),
+    ]);
+
+    #[test]
+    fn test_dry_run_parameter() -> Result<(), Error> {
+        assert!(API_METHOD_REMOVE_DISK.supports_dry_run());
+        assert!(!API_METHOD_WIPE_DISK.supports_dry_run());
+
+        let request = |uri| api_request(&DRY_RUN_API_ROUTER, Method::POST, uri, false);
+        let (status, data) = request("/api2/json/remove?dry-run=1")?;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(data["data"], json!({ "removed": false }));
+        let (status, data) = request("/api2/json/remove")?;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(data["data"], json!({ "removed": true }));
+        let (status, _) = request("/api2/json/wipe")?;
+        assert_eq!(status, StatusCode::OK);
+
+        let (parts, body) = raw_api_request(
+            &DRY_RUN_API_ROUTER,
+            Method::POST,
+            "/api2/json/wipe?dry-run=1",
+            false,
+        )?;
+        assert_eq!(parts.status, StatusCode::BAD_REQUEST);
+        let body = String::from_utf8(body)?;
+        assert!(body.contains("'dry-run' is not supported"), "{body}");
+
+        // also in a JSON body, and for methods allowing additional properties
+        let json = Some("application/json");
+        let err = parse_body(json, "/tags", r#"{ "dry-run": true }"#).unwrap_err();
+        assert_eq!(status_code(&err), Some(StatusCode::BAD_REQUEST));
+        assert!(err.to_string().contains("'dry-run'"), "{err}");
+
+        const API_METHOD_ANY: ApiMethod = ApiMethod::new(
+            &ApiHandler::Sync(&remove_disk),
+            &ObjectSchema::new("Anything.", &[]).additional_properties(true),
+        );
+        let (parts, body) = Request::builder()
+            .uri("/any?dry-run=1")
+            .body(Body::empty())?
+            .into_parts();
+        let runtime = tokio::runtime::Builder::new_current_thread().build()?;
+        let err = runtime
+            .block_on(get_request_parameters(
+                &API_METHOD_ANY,
+                parts,
+                body,
+                HashMap::<String, String>::new(),
+            ))
+            .unwrap_err();
+        assert_eq!(status_code(&err), Some(StatusCode::BAD_REQUEST));
+
+        Ok(())
+    }
+
+    const NODE_SCHEMA: Schema = StringSchema::new("Node name.").schema();
+
+    const DISK_TYPE_SCHEMA: Schema = StringSchema::new("Disk type.")
+        .format(&ApiStringFormat::Enum(&[
+            EnumEntry::new("hdd", "Hard disk."),
+            EnumEntry::new("ssd", "Solid state disk."),
+        ]))
+        .schema();
+
+    const DISK_LIST_SCHEMA: Schema =
+        ArraySchema::new("List of disks.", &StringSchema::new("Disk path.").schema()).schema();
+
+    const API_METHOD_LIST_DISKS: ApiMethod = ApiMethod::new(
+        &ApiHandler::Sync(&get_config),
+        &ObjectSchema::new(
+            "List disks.",
+            &[
+                ("node", false, &NODE_SCHEMA),
+                ("type", true, &DISK_TYPE_SCHEMA),
+            ],
+        ),
+    )
+    .returns(ReturnType::new(false, &DISK_LIST_SCHEMA))
+    .access(None, &Permission::World);
+
+    const API_METHOD_CREATE_DISK: ApiMethod = ApiMethod::new(
+        &ApiHandler::Sync(&get_config),
+        &ObjectSchema::new("Create a disk.", &[("node", false, &NODE_SCHEMA)]),
+    )
+    .access(Some("Any authenticated user."), &Permission::Anybody);
+
+    const DISKS_ROUTER: Router = Router::new()
+        .get(&API_METHOD_LIST_DISKS)
+        .post(&API_METHOD_CREATE_DISK);
+
+    const NODE_ROUTER: Router = Router::new().subdirs(&[("disks", &DISKS_ROUTER)]);
+
+    const NODES_ROUTER: Router = Router::new().match_all("node", &NODE_ROUTER);
+
+    const API_ROUTER: Router = Router::new().subdirs(&[("nodes", &NODES_ROUTER)]);
+
+    fn describe(method: Method, uri: &str, auth: bool) -> Result<(StatusCode, Value), Error> {
+        api_request(&API_ROUTER, method, uri, auth)
+    }
+
+    fn api_request(
+        router: &'static Router,
+        method: Method,
+        uri: &str,
+        auth: bool,
+    ) -> Result<(StatusCode, Value), Error> {
+        let (parts, body) = raw_api_request(router, method, uri, auth)?;
+        // errors are plain text
+        Ok((
+            parts.status,
+            serde_json::from_slice(&body).unwrap_or(Value::Null),
+        ))
+    }
+
+    fn raw_api_request(
+        router: &'static Router,
+        method: Method,
+        uri: &str,
+        auth: bool,
+    ) -> Result<(hyper::http::response::Parts, Vec<u8>), Error> {
+        let config = Arc::new(test_config(router).api_self_description(&[]));
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()?;
+        runtime.block_on(send_request(&config, method, uri, auth))
+    }
+
+    /// Configuration authenticating requests carrying an `Authorization` header as `user@pam`.
+    fn test_config(router: &'static Router) -> ApiConfig {
+        ApiConfig::new("/", RpcEnvironmentType::PUBLIC)
+            .default_api2_handler(router)
+            .auth_handler_func(|headers, _method| {
+                let authenticated = headers.contains_key(header::AUTHORIZATION);
+                Box::pin(async move {
+                    if !authenticated {
+                        return Err(AuthError::NoData);
+                    }
+                    let info: Box<dyn UserInformation + Send + Sync> =
+                        Box::new(EmptyUserInformation {});
+                    Ok(("user@pam".to_string(), info))
+                })
+            })
+    }
+
+    async fn send_request(
+        config: &Arc<ApiConfig>,
+        method: Method,
+        uri: &str,
+        auth: bool,
+    ) -> Result<(hyper::http::response::Parts, Vec<u8>), Error> {
+        let mut request = Request::builder().method(method).uri(uri);
+        if auth {
+            request = request.header(header::AUTHORIZATION, "ticket");
+        }
+        let request = request.body(Body::empty())?;
+
+        let peer = "127.0.0.1:8007".parse()?;
+        let response = Arc::clone(config).handle_request(request, &peer).await?;
+        let (parts, body) = response.into_parts();
+        let body = hyper::body::to_bytes(body).await?;
+        Ok((parts, body.to_vec()))
+    }
+
+    #[test]
+    fn test_api_self_description() -> Result<(), Error> {
+        let (status, data) = describe(Method::OPTIONS, "/api2/json/nodes/node1/disks", true)?;
+        assert_eq!(status, StatusCode::OK);
+        let data = &data["data"];
+
+        let get = &data["methods"]["GET"];
+        assert_eq!(get["description"], "List disks.");
+        assert_eq!(
+            get["parameters"],
+            parameter_schema_to_json(API_METHOD_LIST_DISKS.parameters)
+        );
+        assert_eq!(
+            get["parameters"]["properties"]["type"],
+            json!({
+                "type": "string",
+                "description": "Disk type.",
+                "enum": ["hdd", "ssd"],
+                "optional": true,
+            }),
+        );
+        assert_eq!(
+            get["returns"],
+            return_type_to_json(&API_METHOD_LIST_DISKS.returns)
+        );
+        assert_eq!(
+            get["returns"]["items"],
+            schema_to_json(&DISK_LIST_SCHEMA)["items"]
+        );
+        assert_eq!(get["permissions"]["check"], json!({ "type": "world" }));
+
+        let post = &data["methods"]["POST"];
+        assert_eq!(
+            post["parameters"],
+            parameter_schema_to_json(API_METHOD_CREATE_DISK.parameters)
+        );
+        assert_eq!(
+            post["permissions"]["description"],
+            "Any authenticated user."
+        );
+        assert_eq!(data["children"], json!([]));
+
+        // unauthenticated callers only see methods with `Permission::World`
+        let (status, data) = describe(Method::GET, "/api2/json/nodes/node1/disks?schema=1", false)?;
+        assert_eq!(status, StatusCode::OK);
+        let methods = data["data"]["methods"].as_object().unwrap();
+        assert_eq!(methods.keys().collect::<Vec<_>>(), ["GET"]);
+
+        let (_, data) = describe(Method::OPTIONS, "/api2/json/nodes", false)?;
+        assert_eq!(
+            data["data"],
+            json!({ "methods": {}, "children": ["{node}"] })
+        );
+
+        let (status, _) = describe(Method::OPTIONS, "/api2/json/nodes/node1/missing", true)?;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        Ok(())
+    }
+
+    fn reject_writes(request: &HookRequest, _: &mut dyn RpcEnvironment) -> Result<(), Error> {
+        if request.method != Some("GET") {
+            http_bail!(
+                SERVICE_UNAVAILABLE,
+                "node {} is in maintenance mode",
+                request.params["node"].as_str().unwrap_or_default(),
+            );
+        }
+        Ok(())
+    }
+
+    const MAINTENANCE_HOOKS: RouterHooks = RouterHooks::new().pre_dispatch(reject_writes);
+
+    const HOOKED_NODES_ROUTER: Router = Router::new()
+        .match_all("node", &NODE_ROUTER)
+        .hooks(&MAINTENANCE_HOOKS);
+
+    const HOOKED_API_ROUTER: Router = Router::new().subdirs(&[("nodes", &HOOKED_NODES_ROUTER)]);
+
+    #[test]
+    fn test_router_hook_veto() -> Result<(), Error> {
+        let uri = "/api2/json/nodes/node1/disks";
+
+        let (status, data) = api_request(&HOOKED_API_ROUTER, Method::POST, uri, true)?;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(data, Value::Null);
+
+        let (status, data) = api_request(&HOOKED_API_ROUTER, Method::GET, uri, true)?;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(data["data"], json!({ "key": "value" }));
+
+        let (status, _) = api_request(&API_ROUTER, Method::POST, uri, true)?;
+        assert_eq!(status, StatusCode::OK);
+
+        Ok(())
+    }
+
+    const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+    fn get_image(
+        param: Value,
+        _info: &ApiMethod,
+        _rpcenv: &mut dyn RpcEnvironment,
+    ) -> Result<RawResponse, Error> {
+        let name = param["name"].as_str().unwrap();
+        Ok(RawResponse::new(PNG_SIGNATURE.to_vec()).header(
+            "Content-Disposition",
+            format!("inline; filename=\"{name}.png\""),
+        ))
+    }
+
+    fn get_log<'a>(
+        _param: Value,
+        _info: &ApiMethod,
+        _rpcenv: &'a mut dyn RpcEnvironment,
+    ) -> RawApiFuture<'a> {
+        Box::pin(async move {
+            let lines = ["first line\n", "second line\n"].map(|line| Ok(line.as_bytes().to_vec()));
+            Ok(
+                RawResponse::new(RawBody::stream(futures::stream::iter(lines)))
+                    .content_type("text/plain; charset=utf-8"),
+            )
+        })
+    }
+
+    const API_METHOD_GET_IMAGE: ApiMethod = ApiMethod::new(
+        &ApiHandler::Raw(&get_image),
+        &ObjectSchema::new(
+            "Get an image.",
+            &[(
+                "name",
+                false,
+                &StringSchema::new("Image name.").max_length(8).schema(),
+            )],
+        ),
+    )
+    .returns(ReturnType::raw("image/png"))
+    .access(None, &Permission::World);
+
+    const API_METHOD_GET_LOG: ApiMethod = ApiMethod::new(
+        &ApiHandler::RawAsync(&get_log),
+        &ObjectSchema::new("Get the log.", &[]),
+    )
+    .returns(ReturnType::raw("text/plain"))
+    .access(None, &Permission::Anybody);
+
+    const RAW_API_ROUTER: Router = Router::new().subdirs(&[
+        ("image", &Router::new().get(&API_METHOD_GET_IMAGE)),
+        ("log", &Router::new().get(&API_METHOD_GET_LOG)),
+    ]);
+
+    #[test]
+    fn test_raw_response() -> Result<(), Error> {
+        let (parts, body) = raw_api_request(
+            &RAW_API_ROUTER,
+            Method::GET,
+            "/api2/json/image?name=logo",
+            false,
+        )?;
+        assert_eq!(parts.status, StatusCode::OK);
+        assert_eq!(parts.headers[header::CONTENT_TYPE], "image/png");
+        assert_eq!(
+            parts.headers[header::CONTENT_DISPOSITION],
+            "inline; filename=\"logo.png\"",
+        );
+        assert_eq!(body, PNG_SIGNATURE);
+
+        // parameters are still verified
+        let uri = "/api2/json/image?name=much-too-long";
+        let (parts, _) = raw_api_request(&RAW_API_ROUTER, Method::GET, uri, false)?;
+        assert_eq!(parts.status, StatusCode::BAD_REQUEST);
+
+        // and so is the authentication
+        assert!(raw_api_request(&RAW_API_ROUTER, Method::GET, "/api2/json/log", false).is_err());
+
+        let (parts, body) = raw_api_request(&RAW_API_ROUTER, Method::GET, "/api2/json/log", true)?;
+        assert_eq!(parts.status, StatusCode::OK);
+        assert_eq!(
+            parts.headers[header::CONTENT_TYPE],
+            "text/plain; charset=utf-8"
+        );
+        assert_eq!(body, b"first line\nsecond line\n");
+
+        let mut docs = Vec::new();
+        proxmox_router::format::dump_api(&mut docs, &RAW_API_ROUTER, "", 0)?;
+        let docs = String::from_utf8(docs)?;
+        assert!(docs.contains("**GET /image**\n\nGet an image.\n"), "{docs}");
+        assert!(
+            docs.contains("*Returns*: **raw data** (``image/png``)"),
+            "{docs}"
+        );
+        assert!(
+            docs.contains("*Returns*: **raw data** (``text/plain``)"),
+            "{docs}"
+        );
+
+        assert_eq!(
+            return_type_to_json(&API_METHOD_GET_IMAGE.returns),
+            json!({ "type": "raw", "contentType": "image/png" }),
+        );
+
+        Ok(())
+    }
+
+    static REPORT_CALLS: AtomicUsize = AtomicUsize::new(0);
+    const REPORT: &str = "a report which is expensive to generate\n";
+
+    fn get_report(
+        _param: Value,
+        _info: &ApiMethod,
+        _rpcenv: &mut dyn RpcEnvironment,
+    ) -> Result<RawResponse, Error> {
+        REPORT_CALLS.fetch_add(1, Ordering::SeqCst);
+        Ok(RawResponse::new(REPORT.to_string()))
+    }
+
+    fn head_report(
+        _param: Value,
+        _info: &ApiMethod,
+        _rpcenv: &mut dyn RpcEnvironment,
+    ) -> Result<RawResponse, Error> {
+        Ok(RawResponse::new(Vec::new()).header("Content-Length", REPORT.len().to_string()))
+    }
+
+    const API_METHOD_GET_REPORT: ApiMethod = ApiMethod::new(
+        &ApiHandler::Raw(&get_report),
+        &ObjectSchema::new("Get the report.", &[]),
+    )
+    .returns(ReturnType::raw("text/plain"))
+    .access(None, &Permission::World);
+
+    const API_METHOD_HEAD_REPORT: ApiMethod = ApiMethod::new(
+        &ApiHandler::Raw(&head_report),
+        &ObjectSchema::new("Get the report headers.", &[]),
+    )
+    .returns(ReturnType::raw("text/plain"))
+    .access(None, &Permission::World);
+
+    const API_METHOD_GET_CONFIG_ANYBODY: ApiMethod = ApiMethod::new(
+        &ApiHandler::Sync(&get_config),
+        &ObjectSchema::new("Get the configuration.", &[]),
+    )
+    .access(None, &Permission::Anybody);
+
+    const HEAD_API_ROUTER: Router = Router::new().subdirs(&[
+        ("config", &Router::new().get(&API_METHOD_GET_CONFIG_ANYBODY)),
+        ("image", &Router::new().get(&API_METHOD_GET_IMAGE)),
+        ("log", &Router::new().get(&API_METHOD_GET_LOG)),
+        (
+            "report",
+            &Router::new()
+                .get(&API_METHOD_GET_REPORT)
+                .head(&API_METHOD_HEAD_REPORT),
+        ),
+    ]);
+
+    #[test]
+    fn test_head_requests() -> Result<(), Error> {
+        let request = |method, uri| raw_api_request(&HEAD_API_ROUTER, method, uri, true);
+
+        // HEAD runs the GET handler and only drops the body
+        for uri in [
+            "/api2/json/config",
+            "/api2/json/image?name=logo",
+            "/api2/json/log",
+        ] {
+            let (get, body) = request(Method::GET, uri)?;
+            let (mut head, head_body) = request(Method::HEAD, uri)?;
+            assert_eq!(head.status, get.status, "{uri}");
+            assert!(head_body.is_empty(), "{uri}");
+
+            // streamed responses are sent chunked, without a length
+            if uri == "/api2/json/log" {
+                assert!(!head.headers.contains_key(header::CONTENT_LENGTH));
+            } else {
+                let len = head.headers.remove(header::CONTENT_LENGTH).unwrap();
+                assert_eq!(len, body.len().to_string().as_str(), "{uri}");
+            }
+            assert_eq!(head.headers, get.headers, "{uri}");
+        }
+
+        let (head, _) = request(Method::HEAD, "/api2/json/config")?;
+        assert_eq!(head.headers[header::ETAG], format!("\"{CURRENT_DIGEST}\""));
+        assert_eq!(
+            head.headers[header::CONTENT_TYPE],
+            "application/json;charset=UTF-8"
+        );
+
+        // permissions and parameters are still checked
+        let (head, _) = request(Method::HEAD, "/api2/json/image?name=much-too-long")?;
+        assert_eq!(head.status, StatusCode::BAD_REQUEST);
+        let uri = "/api2/json/config";
+        assert!(raw_api_request(&HEAD_API_ROUTER, Method::HEAD, uri, false).is_err());
+
+        // a dedicated HEAD handler replaces the expensive GET handler
+        let (head, body) = request(Method::HEAD, "/api2/json/report")?;
+        assert_eq!(head.status, StatusCode::OK);
+        assert!(body.is_empty());
+        assert_eq!(head.headers[header::CONTENT_TYPE], "text/plain");
+        assert_eq!(
+            head.headers[header::CONTENT_LENGTH],
+            REPORT.len().to_string().as_str()
+        );
+        assert_eq!(REPORT_CALLS.load(Ordering::SeqCst), 0);
+
+        let (get, body) = request(Method::GET, "/api2/json/report")?;
+        assert_eq!(get.status, StatusCode::OK);
+        assert_eq!(body, REPORT.as_bytes());
+        assert_eq!(REPORT_CALLS.load(Ordering::SeqCst), 1);
+
+        Ok(())
+    }
+
+    static CACHED_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+    fn get_calls(
+        param: Value,
+        _info: &ApiMethod,
+        _rpcenv: &mut dyn RpcEnvironment,
+    ) -> Result<Value, Error> {
+        let calls = CACHED_CALLS.fetch_add(1, Ordering::SeqCst) + 1;
+        if param["fail"].as_bool() == Some(true) {
+            http_bail!(BAD_REQUEST, "failed as requested");
+        }
+        Ok(json!(calls))
+    }
+
+    const API_METHOD_GET_CALLS: ApiMethod = ApiMethod::new(
+        &ApiHandler::Sync(&get_calls),
+        &ObjectSchema::new(
+            "Count the handler calls.",
+            &[("fail", true, &BooleanSchema::new("Fail the call.").schema())],
+        ),
+    )
+    .access(None, &Permission::Anybody)
+    .cache(CachePolicy::new(60));
+
+    const CACHED_API_ROUTER: Router =
+        Router::new().subdirs(&[("calls", &Router::new().get(&API_METHOD_GET_CALLS))]);
+
+    #[test]
+    fn test_response_cache() -> Result<(), Error> {
+        let cache = Arc::new(ResponseCache::new(16));
+        let config = Arc::new(test_config(&CACHED_API_ROUTER).response_cache(Arc::clone(&cache)));
+        let calls = || CACHED_CALLS.load(Ordering::SeqCst);
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()?;
+        runtime.block_on(async {
+            let get = |uri: &'static str, auth| send_request(&config, Method::GET, uri, auth);
+
+            let (parts, first) = get("/api2/json/calls", true).await?;
+            assert_eq!(parts.status, StatusCode::OK);
+            assert!(!parts.headers.contains_key(header::AGE));
+            assert_eq!(calls(), 1);
+
+            let (parts, body) = get("/api2/json/calls", true).await?;
+            assert_eq!(parts.status, StatusCode::OK);
+            assert_eq!(parts.headers[header::AGE], "0");
+            assert_eq!(body, first);
+            assert_eq!(calls(), 1);
+
+            // authentication is still required for cached responses
+            assert!(get("/api2/json/calls", false).await.is_err());
+
+            // each output format has its own response
+            get("/api2/extjs/calls", true).await?;
+            assert_eq!(calls(), 2);
+
+            // errors are never cached
+            for expected_calls in [3, 4] {
+                let (parts, _) = get("/api2/json/calls?fail=1", true).await?;
+                assert_eq!(parts.status, StatusCode::BAD_REQUEST);
+                assert_eq!(calls(), expected_calls);
+            }
+
+            cache.invalidate_prefix("/calls");
+            let (parts, _) = get("/api2/json/calls", true).await?;
+            assert!(!parts.headers.contains_key(header::AGE));
+            assert_eq!(calls(), 5);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_default_headers() -> Result<(), Error> {
+        use tower_service::Service;
+
+        let headers = DefaultHeaders::security().allow_framing("/novnc").header(
+            header::CONTENT_DISPOSITION,
+            header::HeaderValue::from_static("attachment"),
+        );
+        let config = Arc::new(
+            test_config(&HEAD_API_ROUTER)
+                .index_handler(IndexHandler::new_static_body("index"))
+                .default_headers(headers),
+        );
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()?;
+        let request = |method: Method, uri: &str, tls: bool| {
+            let mut service = ApiService {
+                peer: "127.0.0.1:8007".parse().unwrap(),
+                tls,
+                api_config: Arc::clone(&config),
+            };
+            let request = Request::builder()
+                .method(method)
+                .uri(uri)
+                .header(header::AUTHORIZATION, "ticket")
+                .body(Body::empty())
+                .unwrap();
+            runtime.block_on(service.call(request)).unwrap()
+        };
+
+        let is_secured = |response: &Response<Body>| {
+            let headers = response.headers();
+            headers[header::X_CONTENT_TYPE_OPTIONS] == "nosniff"
+                && headers[header::X_FRAME_OPTIONS] == "SAMEORIGIN"
+                && headers[header::CONTENT_SECURITY_POLICY] == "frame-ancestors 'self'"
+                && headers[header::REFERRER_POLICY] == "same-origin"
+        };
+
+        let response = request(Method::GET, "/api2/json/config", false);
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(is_secured(&response));
+        assert!(!response
+            .headers()
+            .contains_key(header::STRICT_TRANSPORT_SECURITY));
+        assert_eq!(
+            response.headers()[header::CONTENT_DISPOSITION],
+            "attachment"
+        );
+
+        let response = request(Method::GET, "/", false);
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(is_secured(&response));
+
+        // error responses, both from handlers and from the server itself
+        let response = request(Method::GET, "/api2/json/image?name=much-too-long", false);
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert!(is_secured(&response));
+        let response = request(Method::POST, "/missing", false);
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert!(is_secured(&response));
+
+        // HSTS is only sent over TLS
+        let response = request(Method::GET, "/api2/json/config", true);
+        assert!(is_secured(&response));
+        assert_eq!(
+            response.headers()[header::STRICT_TRANSPORT_SECURITY],
+            "max-age=63072000"
+        );
+
+        // relaxed framing for the console
+        let response = request(Method::POST, "/novnc/vnc.html", true);
+        let headers = response.headers();
+        assert!(!headers.contains_key(header::X_FRAME_OPTIONS));
+        assert!(!headers.contains_key(header::CONTENT_SECURITY_POLICY));
+        assert_eq!(headers[header::X_CONTENT_TYPE_OPTIONS], "nosniff");
+        assert!(headers.contains_key(header::STRICT_TRANSPORT_SECURITY));
+
+        // headers set by the handler win
+        let response = request(Method::GET, "/api2/json/image?name=logo", false);
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::CONTENT_DISPOSITION],
+            "inline; filename=\"logo.png\""
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_output_format_negotiation() -> Result<(), Error> {
+        let config = Arc::new(test_config(&HEAD_API_ROUTER).output_format(
+            "direct",
+            "application/vnd.proxmox.direct+json",
+            crate::formatter::DIRECT_JSON_FORMATTER,
+        ));
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()?;
+        let request = |uri: &str, accept: Option<&str>| {
+            let mut request = Request::builder()
+                .uri(uri)
+                .header(header::AUTHORIZATION, "ticket");
+            if let Some(accept) = accept {
+                request = request.header(header::ACCEPT, accept);
+            }
+            let request = request.body(Body::empty()).unwrap();
+            let peer = "127.0.0.1:8007".parse().unwrap();
+            runtime.block_on(async {
+                let response = Arc::clone(&config)
+                    .handle_request(request, &peer)
+                    .await
+                    .unwrap();
+                let (parts, body) = response.into_parts();
+                let body = hyper::body::to_bytes(body).await.unwrap();
+                let data: Value = serde_json::from_slice(&body).unwrap();
+                (parts, data)
+            })
+        };
+
+        let json = json!({ "data": { "key": "value" }, "digest": CURRENT_DIGEST });
+        let extjs = json!({
+            "data": { "key": "value" },
+            "digest": CURRENT_DIGEST,
+            "success": true,
+            "status": 200,
+        });
+        let direct = json!({ "key": "value" });
+
+        for (uri, accept, expected) in [
+            // the prefix is the default
+            ("/api2/json/config", None, &json),
+            ("/api2/extjs/config", None, &extjs),
+            ("/api2/direct/config", None, &direct),
+            // the Accept header wins
+            ("/api2/extjs/config", Some("application/json"), &json),
+            (
+                "/api2/json/config",
+                Some("application/x-extjs-json"),
+                &extjs,
+            ),
+            (
+                "/api2/json/config",
+                Some("application/vnd.proxmox.direct+json"),
+                &direct,
+            ),
+            // unknown types fall back to the prefix instead of failing
+            ("/api2/extjs/config", Some("text/html, */*;q=0.8"), &extjs),
+            (
+                "/api2/json/config",
+                Some("application/json;q=0.5, application/x-extjs-json"),
+                &extjs,
+            ),
+        ] {
+            let (parts, data) = request(uri, accept);
+            assert_eq!(parts.status, StatusCode::OK, "{uri} {accept:?}");
+            assert_eq!(&data, expected, "{uri} {accept:?}");
+            assert_eq!(parts.headers[header::VARY], "Accept", "{uri} {accept:?}");
+        }
+
+        // errors are formatted by the negotiated format as well
+        let (parts, data) = request("/api2/json/missing", Some("application/x-extjs-json"));
+        assert_eq!(parts.status, StatusCode::OK);
+        assert_eq!(data["success"], false);
+        assert_eq!(data["status"], 404);
+        assert_eq!(parts.headers[header::VARY], "Accept");
+
+        Ok(())
+    }
+
+    fn echo_params(
+        param: Value,
+        _info: &ApiMethod,
+        _rpcenv: &mut dyn RpcEnvironment,
+    ) -> Result<Value, Error> {
+        Ok(param)
+    }
+
+    const VMID_SCHEMA: Schema = IntegerSchema::new("Guest ID.")
+        .minimum(100)
+        .maximum(999_999_999)
+        .schema();
+
+    const API_METHOD_VM_STATUS: ApiMethod = ApiMethod::new(
+        &ApiHandler::Sync(&echo_params),
+        &ObjectSchema::new(
+            "Get the guest status.",
+            &[
+                ("full", true, &BooleanSchema::new("Full status.").schema()),
+                ("vmid", false, &VMID_SCHEMA),
+            ],
+        ),
+    )
+    .access(None, &Permission::Privilege(&["vms", "{vmid}"], 1, false));
+
+    const VM_ROUTER: Router =
+        Router::new().subdirs(&[("status", &Router::new().get(&API_METHOD_VM_STATUS))]);
+
+    const VMS_API_ROUTER: Router =
+        Router::new().subdirs(&[("vms", &Router::new().match_all("vmid", &VM_ROUTER))]);
+
+    /// Grants privileges on `/vms/100` only.
+    struct VmUserInformation;
+
+    impl UserInformation for VmUserInformation {
+        fn is_superuser(&self, _userid: &str) -> bool {
+            false
+        }
+        fn is_group_member(&self, _userid: &str, _group: &str) -> bool {
+            false
+        }
+        fn lookup_privs(&self, _userid: &str, path: &[&str]) -> u64 {
+            if path == ["vms", "100"] {
+                1
+            } else {
+                0
             }
         }
     }
+
+    #[test]
+    fn test_typed_path_parameters() -> Result<(), Error> {
+        let config = Arc::new(
+            ApiConfig::new("/", RpcEnvironmentType::PUBLIC)
+                .default_api2_handler(&VMS_API_ROUTER)
+                .auth_handler_func(|_headers, _method| {
+                    Box::pin(async move {
+                        let info: Box<dyn UserInformation + Send + Sync> =
+                            Box::new(VmUserInformation);
+                        Ok(("user@pam".to_string(), info))
+                    })
+                }),
+        );
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()?;
+        let get = |uri: &str| -> Result<(StatusCode, String), Error> {
+            let (parts, body) = runtime.block_on(send_request(&config, Method::GET, uri, true))?;
+            Ok((parts.status, String::from_utf8(body)?))
+        };
+
+        // the handler gets the parsed value
+        let (status, body) = get("/api2/json/vms/100/status?full=1")?;
+        assert_eq!(status, StatusCode::OK);
+        let data: Value = serde_json::from_str(&body)?;
+        assert_eq!(data["data"], json!({ "vmid": 100, "full": true }));
+
+        // permissions are checked on the normalized value
+        let (status, body) = get("/api2/json/vms/0100/status")?;
+        assert_eq!(status, StatusCode::OK);
+        let data: Value = serde_json::from_str(&body)?;
+        assert_eq!(data["data"], json!({ "vmid": 100 }));
+
+        for uri in [
+            "/api2/json/vms/99/status",
+            "/api2/json/vms/1000000000/status",
+            "/api2/json/vms/abc/status",
+        ] {
+            let (status, body) = get(uri)?;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{uri}");
+            assert!(body.contains("'vmid'"), "{uri}: {body}");
+        }
+
+        // the extjs formatter reports the parameter
+        let (status, body) = get("/api2/extjs/vms/99/status")?;
+        assert_eq!(status, StatusCode::OK);
+        let data: Value = serde_json::from_str(&body)?;
+        assert_eq!(data["status"], 400);
+        assert!(data["errors"]["vmid"].is_string(), "{data}");
+
+        Ok(())
+    }
+
+    static AUDIT_EVENTS: std::sync::Mutex<Vec<String>> = std::sync::Mutex::new(Vec::new());
+
+    fn create_user(
+        _param: Value,
+        _info: &ApiMethod,
+        _rpcenv: &mut dyn RpcEnvironment,
+    ) -> Result<Value, Error> {
+        AUDIT_EVENTS.lock().unwrap().push("handler".to_string());
+        Ok(Value::Null)
+    }
+
+    const API_METHOD_CREATE_USER: ApiMethod = ApiMethod::new(
+        &ApiHandler::Sync(&create_user),
+        &ObjectSchema::new(
+            "Create a user.",
+            &[
+                (
+                    "password",
+                    false,
+                    &StringSchema::new("Password.").secret().schema(),
+                ),
+                ("userid", false, &StringSchema::new("User ID.").schema()),
+            ],
+        ),
+    )
+    .access(None, &Permission::Anybody);
+
+    const USERS_API_ROUTER: Router = Router::new().subdirs(&[(
+        "users",
+        &Router::new()
+            .get(&API_METHOD_GET_CONFIG_ANYBODY)
+            .post(&API_METHOD_CREATE_USER),
+    )]);
+
+    /// Records the calls in [`AUDIT_EVENTS`], failing if `fail` is set.
+    struct RecordingAuditHook {
+        fail: bool,
+    }
+
+    impl AuditHook for RecordingAuditHook {
+        fn before(&self, call: &AuditCall) -> Result<(), Error> {
+            AUDIT_EVENTS.lock().unwrap().push(format!(
+                "before {} {} {} {} {}",
+                call.method,
+                call.path,
+                call.auth_id.unwrap_or("-"),
+                call.client_ip.unwrap(),
+                call.params,
+            ));
+            if self.fail {
+                anyhow::bail!("audit storage unavailable");
+            }
+            Ok(())
+        }
+
+        fn after(&self, call: &AuditCall, status: StatusCode) -> Result<(), Error> {
+            AUDIT_EVENTS
+                .lock()
+                .unwrap()
+                .push(format!("after {} {}", call.path, status.as_u16()));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_audit_hook() -> Result<(), Error> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()?;
+        let request = |config: ApiConfig, method: Method| {
+            let config = Arc::new(config);
+            let uri = match method {
+                Method::GET => "/api2/json/users",
+                _ => "/api2/json/users?userid=root%40pam&password=secret",
+            };
+            let (parts, _) = runtime.block_on(send_request(&config, method, uri, true))?;
+            let events = std::mem::take(&mut *AUDIT_EVENTS.lock().unwrap());
+            Ok::<_, Error>((parts.status, events))
+        };
+        let config = |fail: bool| {
+            test_config(&USERS_API_ROUTER).audit_hook(Box::new(RecordingAuditHook { fail }))
+        };
+
+        // the hook is called before and after the handler, with the secrets redacted
+        let (status, events) = request(config(false), Method::POST)?;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            events,
+            [
+                r#"before POST /api2/json/users user@pam 127.0.0.1:8007 {"password":"<redacted>","userid":"root@pam"}"#,
+                "handler",
+                "after /api2/json/users 200",
+            ]
+        );
+
+        // reading calls are not audited
+        let (status, events) = request(config(false), Method::GET)?;
+        assert_eq!(status, StatusCode::OK);
+        assert!(events.is_empty());
+
+        // failures of the hook are only logged...
+        let (status, events) = request(config(true), Method::POST)?;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(events[1..], ["handler", "after /api2/json/users 200"]);
+
+        // ...unless the strict mode is enabled
+        let (status, events) = request(config(true).strict_audit(true), Method::POST)?;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(events[1..], ["after /api2/json/users 500"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_redact_query_secrets() -> Result<(), Error> {
+        let params = API_METHOD_CREATE_USER.parameters;
+        let redact = |uri: &str| redact_query_secrets(params, &uri.parse().unwrap());
+
+        assert_eq!(
+            redact("/api2/json/users?userid=root%40pam&password=Hunter2%21").as_deref(),
+            Some("/api2/json/users?userid=root%40pam&password=<redacted>"),
+        );
+        assert_eq!(
+            redact("/api2/json/users?pass%77ord=Hunter2&password").as_deref(),
+            Some("/api2/json/users?pass%77ord=<redacted>&password=<redacted>"),
+        );
+        assert_eq!(redact("/api2/json/users?userid=root%40pam"), None);
+        assert_eq!(redact("/api2/json/users"), None);
+
+        // the value of a secret is neither logged nor echoed in the error of a failed request
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()?;
+        let config = Arc::new(test_config(&USERS_API_ROUTER));
+        let uri = "/api2/json/users?password=Hunter2%21";
+        let (parts, body) = runtime.block_on(send_request(&config, Method::POST, uri, true))?;
+        assert_eq!(parts.status, StatusCode::BAD_REQUEST);
+        assert!(!String::from_utf8(body)?.contains("Hunter2"));
+        let logged = parts.extensions.get::<RedactedUriExtension>().unwrap();
+        assert_eq!(logged.0, "/api2/json/users?password=<redacted>");
+
+        // also if the request is rejected before reaching the handler
+        let (parts, _) = runtime.block_on(send_request(&config, Method::POST, uri, false))?;
+        assert_eq!(parts.status, StatusCode::UNAUTHORIZED);
+        let logged = parts.extensions.get::<RedactedUriExtension>().unwrap();
+        assert_eq!(logged.0, "/api2/json/users?password=<redacted>");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_audit_log() -> Result<(), Error> {
+        let path = std::env::temp_dir().join(format!(
+            "proxmox-rest-server-audit-test-{}.log",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let log = FileAuditLog::new(&path, None)?;
+
+        let params = json!({ "userid": "root@pam", "password": "<redacted>" });
+        let call = AuditCall {
+            method: &Method::DELETE,
+            path: "/api2/json/users/root@pam",
+            auth_id: Some("admin@pam"),
+            client_ip: Some("[::1]:8007".parse()?),
+            params: &params,
+        };
+        log.before(&call)?;
+        log.after(&call, StatusCode::FORBIDDEN)?;
+
+        let content = std::fs::read_to_string(&path)?;
+        let _ = std::fs::remove_file(&path);
+        let entries = content
+            .lines()
+            .map(serde_json::from_str)
+            .collect::<Result<Vec<Value>, _>>()?;
+        assert_eq!(entries.len(), 2);
+        for (entry, phase) in entries.iter().zip(["before", "after"]) {
+            assert_eq!(entry["phase"], phase);
+            assert_eq!(entry["method"], "DELETE");
+            assert_eq!(entry["path"], "/api2/json/users/root@pam");
+            assert_eq!(entry["auth-id"], "admin@pam");
+            assert_eq!(entry["client-ip"], "::1");
+            assert_eq!(entry["params"], params);
+            assert!(entry["time"].is_i64());
+        }
+        assert!(entries[0].get("status").is_none());
+        assert_eq!(entries[1]["status"], 403);
+
+        Ok(())
+    }
+
+    fn static_file(
+        dir: &std::path::Path,
+        headers: &[(header::HeaderName, &str)],
+    ) -> Result<(hyper::http::response::Parts, Vec<u8>), Error> {
+        let mut map = header::HeaderMap::new();
+        for (name, value) in headers {
+            map.insert(name, value.parse()?);
+        }
+
+        let runtime = tokio::runtime::Builder::new_current_thread().build()?;
+        runtime.block_on(async {
+            let response =
+                handle_static_file_download(&["app.js"], dir.join("app.js"), &map).await?;
+            let (parts, body) = response.into_parts();
+            let body = hyper::body::to_bytes(body).await?;
+            Ok((parts, body.to_vec()))
+        })
+    }
+
+    #[test]
+    fn test_precompressed_static_files() -> Result<(), Error> {
+        let dir = proxmox_sys::fs::make_tmp_dir("/tmp", None)?;
+        std::fs::write(dir.join("app.js"), b"identity")?;
+        std::fs::write(dir.join("app.js.gz"), b"gzip")?;
+        std::fs::write(dir.join("app.js.zst"), b"zstd")?;
+
+        // zstd is preferred on equal quality values
+        let (parts, body) = static_file(&dir, &[(header::ACCEPT_ENCODING, "gzip, zstd, br")])?;
+        assert_eq!(parts.status, StatusCode::OK);
+        assert_eq!(body, b"zstd");
+        assert_eq!(parts.headers[header::CONTENT_ENCODING], "zstd");
+        assert_eq!(parts.headers[header::VARY], "Accept-Encoding");
+        assert_eq!(
+            parts.headers[header::CONTENT_TYPE],
+            "application/javascript"
+        );
+        let zstd_etag = parts.headers[header::ETAG].to_str()?.to_string();
+
+        let (parts, body) = static_file(&dir, &[(header::ACCEPT_ENCODING, "zstd;q=0.5, gzip")])?;
+        assert_eq!(body, b"gzip");
+        assert_eq!(parts.headers[header::CONTENT_ENCODING], "gzip");
+        assert_ne!(parts.headers[header::ETAG], zstd_etag.as_str());
+
+        // identity fallback
+        for accept in ["identity", "zstd;q=0, gzip;q=0", "*;q=0"] {
+            let (parts, body) = static_file(&dir, &[(header::ACCEPT_ENCODING, accept)])?;
+            assert_eq!(body, b"identity", "{accept}");
+            assert!(parts.headers.get(header::CONTENT_ENCODING).is_none());
+            assert_eq!(parts.headers[header::VARY], "Accept-Encoding");
+        }
+        let (parts, body) = static_file(&dir, &[])?;
+        assert_eq!(body, b"identity");
+        let identity_etag = parts.headers[header::ETAG].to_str()?.to_string();
+        assert_ne!(identity_etag, zstd_etag);
+
+        std::fs::remove_file(dir.join("app.js.zst"))?;
+        std::fs::remove_file(dir.join("app.js.gz"))?;
+        let (parts, body) = static_file(&dir, &[(header::ACCEPT_ENCODING, "zstd, gzip")])?;
+        assert_eq!(body, b"identity");
+        assert!(parts.headers.get(header::CONTENT_ENCODING).is_none());
+        std::fs::write(dir.join("app.js.zst"), b"zstd")?;
+
+        // conditional requests against the encoded variant
+        let zstd_etag = static_file(&dir, &[(header::ACCEPT_ENCODING, "zstd")])?
+            .0
+            .headers[header::ETAG]
+            .to_str()?
+            .to_string();
+        let weak_etag = format!("W/{zstd_etag}");
+        for if_none_match in [zstd_etag.as_str(), weak_etag.as_str(), "\"other\", *"] {
+            let (parts, body) = static_file(
+                &dir,
+                &[
+                    (header::ACCEPT_ENCODING, "zstd"),
+                    (header::IF_NONE_MATCH, if_none_match),
+                ],
+            )?;
+            assert_eq!(parts.status, StatusCode::NOT_MODIFIED, "{if_none_match}");
+            assert!(body.is_empty());
+            assert_eq!(parts.headers[header::ETAG], zstd_etag.as_str());
+            assert_eq!(parts.headers[header::VARY], "Accept-Encoding");
+        }
+
+        // the tag of the encoded variant does not match the identity file, and vice versa
+        let (parts, body) = static_file(&dir, &[(header::IF_NONE_MATCH, zstd_etag.as_str())])?;
+        assert_eq!(parts.status, StatusCode::OK);
+        assert_eq!(body, b"identity");
+        let (parts, body) = static_file(
+            &dir,
+            &[
+                (header::ACCEPT_ENCODING, "zstd"),
+                (header::IF_NONE_MATCH, identity_etag.as_str()),
+            ],
+        )?;
+        assert_eq!(parts.status, StatusCode::OK);
+        assert_eq!(body, b"zstd");
+
+        // variants older than the file are stale and skipped
+        let modified = std::fs::metadata(dir.join("app.js"))?.modified()?;
+        std::fs::write(dir.join("app.js.gz"), b"gzip")?;
+        for (name, age) in [("app.js.zst", 60), ("app.js.gz", 0)] {
+            std::fs::File::options()
+                .write(true)
+                .open(dir.join(name))?
+                .set_modified(modified - std::time::Duration::from_secs(age))?;
+        }
+        let (parts, body) = static_file(&dir, &[(header::ACCEPT_ENCODING, "zstd, gzip")])?;
+        assert_eq!(body, b"gzip");
+        assert_eq!(parts.headers[header::CONTENT_ENCODING], "gzip");
+        let (_parts, body) = static_file(&dir, &[(header::ACCEPT_ENCODING, "zstd")])?;
+        assert_eq!(body, b"identity");
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
 }
@@ -0,0 +1,449 @@
+//! Tunables of the server loaded from a configuration file and the environment.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{format_err, Error};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use proxmox_log::LogCompression;
+use proxmox_router::{ExpectedDuration, RpcEnvironmentType};
+use proxmox_schema::{api, ApiType};
+
+use crate::api_config::PrivilegedAddr;
+use crate::connection::AcceptBuilder;
+use crate::{
+    AccessLogFormat, ApiConfig, BodyRateLimiter, BodyRateLimits, HandlerTimeouts, TrustedProxies,
+};
+
+#[api]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+/// Compression of the access and auth log.
+pub enum LogCompressionSetting {
+    /// Compress with gzip.
+    Gzip,
+    /// Compress with zstd.
+    Zstd,
+}
+
+impl From<LogCompressionSetting> for LogCompression {
+    fn from(setting: LogCompressionSetting) -> Self {
+        match setting {
+            LogCompressionSetting::Gzip => LogCompression::Gzip,
+            LogCompressionSetting::Zstd => LogCompression::Zstd,
+        }
+    }
+}
+
+#[api(
+    properties: {
+        "log-compression": {
+            type: LogCompressionSetting,
+            optional: true,
+        },
+        "log-format": {
+            type: AccessLogFormat,
+            optional: true,
+        },
+        "upload-rate": {
+            minimum: 1,
+            optional: true,
+        },
+        "download-rate": {
+            minimum: 1,
+            optional: true,
+        },
+        "max-worker-tasks": {
+            minimum: 1,
+            optional: true,
+        },
+        "handshake-timeout": {
+            minimum: 1,
+            optional: true,
+        },
+        "tcp-keepalive-time": {
+            minimum: 1,
+            optional: true,
+        },
+        "max-pending-accepts": {
+            minimum: 1,
+            optional: true,
+        },
+        "max-connections": {
+            minimum: 1,
+            optional: true,
+        },
+        "max-connections-per-address": {
+            minimum: 1,
+            optional: true,
+        },
+        "keep-handshake-failures": {
+            optional: true,
+        },
+        "fast-handler-timeout": {
+            minimum: 1,
+            optional: true,
+        },
+        "slow-handler-timeout": {
+            minimum: 1,
+            optional: true,
+        },
+        "task-handler-timeout": {
+            minimum: 1,
+            optional: true,
+        },
+        "streaming-handler-timeout": {
+            minimum: 1,
+            optional: true,
+        },
+    },
+)]
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// Tunables of the REST server, see [`ApiConfigBuilder`].
+pub struct RestServerSettings {
+    /// Reject state-changing API calls if their audit record cannot be written.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub strict_audit: Option<bool>,
+    /// Add the default security headers to all responses.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub security_headers: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub log_compression: Option<LogCompressionSetting>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub log_format: Option<AccessLogFormat>,
+    /// Global limit for request bodies in bytes per second.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upload_rate: Option<u64>,
+    /// Global limit for response bodies in bytes per second.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub download_rate: Option<u64>,
+    /// Maximum number of concurrently existing worker tasks.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_worker_tasks: Option<usize>,
+    /// Fail to spawn worker tasks of unregistered types.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub strict_task_types: Option<bool>,
+    /// Record the resources used by worker tasks.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub task_resource_accounting: Option<bool>,
+    /// Time in seconds a client has to complete the TLS handshake.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub handshake_timeout: Option<u64>,
+    /// TCP keepalive time of client connections in seconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tcp_keepalive_time: Option<u32>,
+    /// Maximum number of accepted connections waiting for their TLS handshake.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_pending_accepts: Option<usize>,
+    /// Maximum number of open connections.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_connections: Option<usize>,
+    /// Maximum number of open connections per client address.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_connections_per_address: Option<usize>,
+    /// Number of failed TLS handshakes to keep for the connection statistics.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_handshake_failures: Option<usize>,
+    /// Close connections rejected because of a limit with a TCP reset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reset_rejected_connections: Option<bool>,
+    /// Address of the privileged service, a `host:port` pair or the absolute path of a unix
+    /// socket.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub privileged_addr: Option<String>,
+    /// Addresses and networks of the proxies whose `Forwarded` header is trusted, separated by
+    /// commas, e.g. `127.0.0.1, 10.0.0.0/8`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trusted_proxies: Option<String>,
+    /// Timeout of fast API handlers in seconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fast_handler_timeout: Option<u64>,
+    /// Timeout of slow API handlers in seconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub slow_handler_timeout: Option<u64>,
+    /// Timeout of API handlers starting a worker task in seconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub task_handler_timeout: Option<u64>,
+    /// Timeout of streaming API handlers in seconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub streaming_handler_timeout: Option<u64>,
+}
+
+impl RestServerSettings {
+    /// The handler timeout of a class in seconds.
+    fn handler_timeout(&self, class: ExpectedDuration) -> Option<u64> {
+        match class {
+            ExpectedDuration::Fast => self.fast_handler_timeout,
+            ExpectedDuration::Slow => self.slow_handler_timeout,
+            ExpectedDuration::Task => self.task_handler_timeout,
+            ExpectedDuration::Streaming => self.streaming_handler_timeout,
+        }
+    }
+
+    /// The handler timeouts, if any class has one configured.
+    ///
+    /// Classes without a configured timeout are not limited.
+    pub fn handler_timeouts(&self) -> Option<HandlerTimeouts> {
+        let mut timeouts = None;
+        for class in ExpectedDuration::ALL {
+            if let Some(secs) = self.handler_timeout(class) {
+                timeouts = Some(
+                    timeouts
+                        .unwrap_or_else(HandlerTimeouts::new)
+                        .timeout(class, Some(Duration::from_secs(secs))),
+                );
+            }
+        }
+        timeouts
+    }
+
+    /// Parse the [`privileged_addr`](Self::privileged_addr) setting.
+    pub(crate) fn privileged_addr(&self) -> Result<Option<PrivilegedAddr>, Error> {
+        let Some(addr) = self.privileged_addr.as_deref() else {
+            return Ok(None);
+        };
+        let addr: PrivilegedAddr = if addr.starts_with('/') {
+            std::os::unix::net::SocketAddr::from_pathname(addr)?.into()
+        } else {
+            addr.parse::<std::net::SocketAddr>()?.into()
+        };
+        Ok(Some(addr))
+    }
+
+    /// Parse the [`trusted_proxies`](Self::trusted_proxies) setting.
+    pub(crate) fn trusted_proxies(&self) -> Result<Option<TrustedProxies>, Error> {
+        self.trusted_proxies.as_deref().map(str::parse).transpose()
+    }
+}
+
+/// Assembles an [`ApiConfig`] from layered [`RestServerSettings`].
+///
+/// Settings are read from a TOML file with [`load_file`](Self::load_file) and from environment
+/// variables with [`load_env`](Self::load_env), later layers override earlier ones. Values set
+/// on the resulting [`ApiConfig`] and [`AcceptBuilder`] afterwards override the loaded settings,
+/// so products keep the final say. The settings for worker tasks are global to the process and
+/// applied separately.
+///
+/// ```no_run
+/// # use anyhow::Error;
+/// # use proxmox_rest_server::ApiConfig;
+/// # use proxmox_router::{ExpectedDuration, RpcEnvironmentType};
+/// # fn example() -> Result<(), Error> {
+/// let builder = ApiConfig::builder()
+///     .load_file("/etc/proxmox/rest-server.toml")?
+///     .load_env("PROXMOX_REST_")?;
+/// builder.apply_worker_task_settings();
+/// let acceptor = builder.accept_builder();
+/// let config = builder.build("/usr/share/javascript", RpcEnvironmentType::PUBLIC);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Default)]
+pub struct ApiConfigBuilder {
+    values: Map<String, Value>,
+    settings: RestServerSettings,
+}
+
+impl ApiConfigBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load settings from a TOML file, a missing file is ignored.
+    ///
+    /// Keys are the kebab-case names of the [`RestServerSettings`], e.g.
+    /// `max-worker-tasks = 256`. Unknown keys are logged and ignored.
+    pub fn load_file<P: AsRef<Path>>(self, path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(self),
+            Err(err) => return Err(format_err!("unable to read {path:?} - {err}")),
+        };
+
+        let table: toml::Table = toml::from_str(&content)
+            .map_err(|err| format_err!("unable to parse {path:?} - {err}"))?;
+
+        let mut values = Map::new();
+        for (key, value) in table {
+            if RestServerSettings::API_SCHEMA
+                .unwrap_object_schema()
+                .lookup(&key)
+                .is_none()
+            {
+                log::warn!("ignoring unknown setting '{key}' in {path:?}");
+                continue;
+            }
+            values.insert(key, serde_json::to_value(value)?);
+        }
+
+        self.merge(values)
+            .map_err(|err| format_err!("invalid settings in {path:?} - {err}"))
+    }
+
+    /// Load settings from the environment variables starting with `prefix`.
+    ///
+    /// The rest of the variable name is the upper case name of the setting with underscores,
+    /// e.g. `PROXMOX_REST_MAX_WORKER_TASKS=256` for the prefix `PROXMOX_REST_`. Unknown
+    /// variables with the prefix are logged and ignored.
+    pub fn load_env(self, prefix: &str) -> Result<Self, Error> {
+        self.load_vars(prefix, std::env::vars())
+    }
+
+    /// Load settings from variables like [`load_env`](Self::load_env), but from `vars` instead
+    /// of the process environment.
+    pub fn load_vars<I>(self, prefix: &str, vars: I) -> Result<Self, Error>
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        let schema = RestServerSettings::API_SCHEMA.unwrap_object_schema();
+
+        let mut values = Map::new();
+        for (name, value) in vars {
+            let Some(key) = name.strip_prefix(prefix) else {
+                continue;
+            };
+            let key = key.to_ascii_lowercase().replace('_', "-");
+            let Some((_optional, property_schema)) = schema.lookup(&key) else {
+                log::warn!("ignoring unknown setting in environment variable {name}");
+                continue;
+            };
+            let value = property_schema.parse_simple_value(&value).map_err(|err| {
+                format_err!("invalid value in environment variable {name} - {err}")
+            })?;
+            values.insert(key, value);
+        }
+
+        self.merge(values)
+    }
+
+    /// Validate the values of a layer and merge them into the current settings.
+    fn merge(mut self, values: Map<String, Value>) -> Result<Self, Error> {
+        let mut values = Value::Object(values);
+        RestServerSettings::API_SCHEMA.normalize_booleans(&mut values);
+        RestServerSettings::API_SCHEMA.verify_json(&values)?;
+
+        let Value::Object(values) = values else {
+            unreachable!();
+        };
+        self.values.extend(values);
+        let settings: RestServerSettings =
+            serde_json::from_value(Value::Object(self.values.clone()))?;
+        settings
+            .privileged_addr()
+            .map_err(|err| format_err!("privileged-addr: {err}"))?;
+        settings
+            .trusted_proxies()
+            .map_err(|err| format_err!("trusted-proxies: {err}"))?;
+        self.settings = settings;
+        Ok(self)
+    }
+
+    /// The settings loaded so far.
+    pub fn settings(&self) -> &RestServerSettings {
+        &self.settings
+    }
+
+    /// Create an [`AcceptBuilder`] with the connection settings.
+    pub fn accept_builder(&self) -> AcceptBuilder {
+        let settings = &self.settings;
+        let mut builder = AcceptBuilder::new();
+        if let Some(timeout) = settings.handshake_timeout {
+            builder = builder.handshake_timeout(Duration::from_secs(timeout));
+        }
+        if let Some(time) = settings.tcp_keepalive_time {
+            builder = builder.tcp_keepalive_time(time);
+        }
+        if let Some(count) = settings.max_pending_accepts {
+            builder = builder.max_pending_accepts(count);
+        }
+        if settings.max_connections.is_some() {
+            builder = builder.max_connections(settings.max_connections);
+        }
+        if settings.max_connections_per_address.is_some() {
+            builder = builder.max_connections_per_address(settings.max_connections_per_address);
+        }
+        if let Some(count) = settings.keep_handshake_failures {
+            builder = builder.keep_handshake_failures(count);
+        }
+        if let Some(reset) = settings.reset_rejected_connections {
+            builder = builder.reset_rejected_connections(reset);
+        }
+        builder
+    }
+
+    /// Create the [`ApiConfig`] with the loaded settings, see [`ApiConfig::new`].
+    ///
+    /// The settings for worker tasks are not applied, see
+    /// [`apply_worker_task_settings`](Self::apply_worker_task_settings).
+    pub fn build<B: Into<PathBuf>>(self, basedir: B, env_type: RpcEnvironmentType) -> ApiConfig {
+        let settings = self.settings;
+        let mut config = ApiConfig::new(basedir, env_type);
+
+        if let Some(strict) = settings.strict_audit {
+            config = config.strict_audit(strict);
+        }
+        if settings.security_headers == Some(true) {
+            config = config.with_security_headers();
+        }
+        if let Some(compression) = settings.log_compression {
+            config = config.log_compression(compression.into());
+        }
+        if let Some(format) = settings.log_format {
+            config = config.access_log_format(format);
+        }
+        if settings.upload_rate.is_some() || settings.download_rate.is_some() {
+            let global = BodyRateLimits {
+                upload: settings.upload_rate,
+                download: settings.download_rate,
+            };
+            config = config.body_rate_limiter(Arc::new(BodyRateLimiter::new(
+                global,
+                BodyRateLimits::default(),
+            )));
+        }
+        if let Some(timeouts) = settings.handler_timeouts() {
+            config = config.handler_timeouts(timeouts);
+        }
+        // validated when merging the layer
+        if let Ok(Some(addr)) = settings.privileged_addr() {
+            config = config.privileged_addr(addr);
+        }
+        if let Ok(Some(proxies)) = settings.trusted_proxies() {
+            config = config.trusted_proxies(proxies);
+        }
+
+        config
+    }
+
+    /// Apply the settings for worker tasks, like [`set_max_worker_tasks`].
+    ///
+    /// These are global to the process, so unlike the other settings they are not part of the
+    /// [`ApiConfig`] and have to be applied explicitly.
+    ///
+    /// [`set_max_worker_tasks`]: crate::set_max_worker_tasks
+    pub fn apply_worker_task_settings(&self) {
+        let settings = &self.settings;
+        if let Some(max) = settings.max_worker_tasks {
+            crate::set_max_worker_tasks(max);
+        }
+        if let Some(strict) = settings.strict_task_types {
+            crate::set_strict_task_types(strict);
+        }
+        if let Some(enabled) = settings.task_resource_accounting {
+            crate::set_task_resource_accounting(enabled);
+        }
+    }
+}
+
+impl ApiConfig {
+    /// Create a builder loading the configuration from a file and the environment, see
+    /// [`ApiConfigBuilder`].
+    pub fn builder() -> ApiConfigBuilder {
+        ApiConfigBuilder::new()
+    }
+}
@@ -0,0 +1,184 @@
+//! Optional forwarding of worker task logs to the system journal.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::Error;
+use tracing::Dispatch;
+use tracing_subscriber::prelude::*;
+
+use proxmox_log::LogMirror;
+use proxmox_schema::upid::UPID;
+
+const DEFAULT_MAX_LINES_PER_SECOND: u32 = 100;
+
+static TASK_JOURNAL: Mutex<Option<Arc<TaskJournal>>> = Mutex::new(None);
+
+/// A task log line forwarded to the journal.
+#[derive(Debug)]
+pub struct TaskJournalEntry<'a> {
+    pub upid: &'a str,
+    pub worker_type: &'a str,
+    pub worker_id: Option<&'a str>,
+    pub auth_id: &'a str,
+    /// The number of the line in the task log, starting at 1.
+    pub line: u64,
+    pub message: &'a str,
+}
+
+/// Receives the task log lines forwarded to the journal.
+pub trait TaskJournalSink: Send + Sync {
+    fn send(&self, entry: &TaskJournalEntry);
+}
+
+/// Sends task log lines to journald, with the `UPID`, `WORKER_TYPE`, `WORKER_ID`, `AUTHID` and
+/// `TASK_LINE` fields.
+pub struct JournaldSink {
+    dispatch: Dispatch,
+}
+
+impl JournaldSink {
+    /// Connect to journald.
+    pub fn new() -> Result<Self, Error> {
+        let layer = tracing_journald::layer()?.with_field_prefix(None);
+        Ok(Self {
+            dispatch: Dispatch::new(tracing_subscriber::registry().with(layer)),
+        })
+    }
+}
+
+impl TaskJournalSink for JournaldSink {
+    fn send(&self, entry: &TaskJournalEntry) {
+        // a dispatcher of its own, so the line does not end up in the task log a second time
+        tracing::dispatcher::with_default(&self.dispatch, || {
+            tracing::info!(
+                upid = entry.upid,
+                worker_type = entry.worker_type,
+                worker_id = entry.worker_id.unwrap_or_default(),
+                authid = entry.auth_id,
+                task_line = entry.line,
+                "{}",
+                entry.message,
+            );
+        });
+    }
+}
+
+/// Configuration for forwarding worker task logs, see [`set_task_journal`].
+pub struct TaskJournal {
+    sink: Box<dyn TaskJournalSink>,
+    max_lines_per_second: Option<u32>,
+}
+
+impl TaskJournal {
+    /// Forward task logs to `sink`, at most 100 lines per second and task by default.
+    pub fn new(sink: Box<dyn TaskJournalSink>) -> Self {
+        Self {
+            sink,
+            max_lines_per_second: Some(DEFAULT_MAX_LINES_PER_SECOND),
+        }
+    }
+
+    /// Forward task logs to journald, see [`JournaldSink`].
+    pub fn journald() -> Result<Self, Error> {
+        Ok(Self::new(Box::new(JournaldSink::new()?)))
+    }
+
+    /// Limit the number of lines forwarded per second and task, `None` means unlimited.
+    ///
+    /// Lines exceeding the limit are dropped, their number is forwarded in a notice instead.
+    pub fn max_lines_per_second(mut self, max: Option<u32>) -> Self {
+        self.max_lines_per_second = max;
+        self
+    }
+}
+
+/// Forward the logs of worker tasks started from now on to the journal, or stop it with `None`.
+///
+/// The task log files are written as before.
+pub fn set_task_journal(journal: Option<TaskJournal>) {
+    *TASK_JOURNAL.lock().unwrap() = journal.map(Arc::new);
+}
+
+/// Get the mirror forwarding the log of a new task, if enabled.
+pub(crate) fn task_journal_mirror(upid: &UPID) -> Option<Box<dyn LogMirror>> {
+    let journal = TASK_JOURNAL.lock().unwrap().clone()?;
+    Some(Box::new(TaskJournalMirror {
+        journal,
+        upid: upid.to_string(),
+        worker_type: upid.worker_type.clone(),
+        worker_id: upid.worker_id.clone(),
+        auth_id: upid.auth_id.clone(),
+        window_start: None,
+        window_lines: 0,
+        suppressed: 0,
+        last_suppressed_line: 0,
+    }))
+}
+
+struct TaskJournalMirror {
+    journal: Arc<TaskJournal>,
+    upid: String,
+    worker_type: String,
+    worker_id: Option<String>,
+    auth_id: String,
+    /// Start of the current one second window of the rate limit.
+    window_start: Option<Instant>,
+    window_lines: u32,
+    suppressed: u64,
+    last_suppressed_line: u64,
+}
+
+impl TaskJournalMirror {
+    fn send(&self, line: u64, message: &str) {
+        self.journal.sink.send(&TaskJournalEntry {
+            upid: &self.upid,
+            worker_type: &self.worker_type,
+            worker_id: self.worker_id.as_deref(),
+            auth_id: &self.auth_id,
+            line,
+            message,
+        });
+    }
+
+    fn flush_suppressed(&mut self) {
+        if self.suppressed > 0 {
+            let notice = format!("suppressed {} lines", self.suppressed);
+            self.send(self.last_suppressed_line, &notice);
+            self.suppressed = 0;
+        }
+    }
+}
+
+impl LogMirror for TaskJournalMirror {
+    fn mirror(&mut self, line: u64, msg: &str) {
+        let Some(max) = self.journal.max_lines_per_second else {
+            self.send(line, msg);
+            return;
+        };
+
+        let now = Instant::now();
+        let window_expired = self.window_start.map_or(true, |start| {
+            now.duration_since(start) >= Duration::from_secs(1)
+        });
+        if window_expired {
+            self.flush_suppressed();
+            self.window_start = Some(now);
+            self.window_lines = 0;
+        }
+
+        if self.window_lines < max {
+            self.window_lines += 1;
+            self.send(line, msg);
+        } else {
+            self.suppressed += 1;
+            self.last_suppressed_line = line;
+        }
+    }
+}
+
+impl Drop for TaskJournalMirror {
+    fn drop(&mut self) {
+        self.flush_suppressed();
+    }
+}
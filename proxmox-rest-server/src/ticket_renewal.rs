@@ -0,0 +1,477 @@
+//! Renewal of authentication tickets with a sliding expiry.
+//!
+//! Tickets are only valid for a fixed lifetime after they were issued, so clients keep their
+//! session by exchanging their ticket for a fresh one from time to time. [`TicketRenewal`]
+//! implements an endpoint for this on top of the product's [`TicketSigner`], so all products
+//! renew tickets by the same rules.
+//!
+//! Enable the endpoint with [`ApiConfig::ticket_renewal`](crate::ApiConfig::ticket_renewal).
+
+use anyhow::{format_err, Error};
+use http::{header, HeaderMap, Method};
+use hyper::{Body, Response};
+use serde_json::json;
+use tokio::time::Instant;
+
+use proxmox_router::{http_bail, http_err};
+
+use crate::rest::delay_unauth_time;
+use crate::{ApiConfig, AuthError, RestEnvironment};
+
+/// Name of the header carrying the ticket of clients not using the authentication cookie.
+pub const TICKET_HEADER_NAME: &str = "AuthTicket";
+
+/// Signs and verifies the authentication tickets of a product.
+pub trait TicketSigner: Send + Sync {
+    /// Verify the signature of `ticket`, returns the user it was issued for and the epoch it was
+    /// issued at.
+    ///
+    /// This must not check the age of the ticket, [`TicketRenewal`] does this to allow renewing
+    /// recently expired tickets if a grace period is configured.
+    fn verify_ticket(&self, ticket: &str) -> Result<(String, i64), Error>;
+
+    /// Issue a ticket for `userid` at the epoch `timestamp`.
+    fn sign_ticket(&self, userid: &str, timestamp: i64) -> Result<String, Error>;
+}
+
+/// Endpoint exchanging a valid ticket for a fresh one once it reaches a certain age.
+///
+/// Clients `POST` to the endpoint (`/api2/json/access/renew-ticket` by default) with their
+/// current ticket, either in the authentication cookie or in the
+/// [`AuthTicket`](TICKET_HEADER_NAME) header. Tickets expire [`lifetime`](Self::lifetime)
+/// seconds after they were issued (two hours by default), and are renewed once less than
+/// [`renewal_threshold`](Self::renewal_threshold) seconds are left (half of the lifetime by
+/// default). A ticket which has not reached this age is kept, so clients can call the endpoint
+/// as often as they like.
+///
+/// Requests to the endpoint are authenticated by the [`AuthHandler`](crate::AuthHandler) like
+/// any other request, including the [CSRF protection](crate::CsrfProtection) for clients using
+/// the cookie, and the ticket must belong to the authenticated user. This way, tickets of
+/// disabled users or tickets which the handler does not accept for regular requests, e.g. those
+/// waiting for a second factor, are not renewed. Failed renewals are delayed like other failed
+/// authentication attempts.
+///
+/// A renewed ticket is set as cookie according to the [`CookiePolicy`] of the [`ApiConfig`] if
+/// the old ticket was sent as cookie, and returned in the `ticket` member of the response
+/// otherwise. Clients using the cookie also get a fresh `CSRFPreventionToken` if
+/// [CSRF protection](crate::CsrfProtection) is enabled. Renewals are written to the auth log.
+///
+/// Expired tickets are rejected, unless a [`grace_period`](Self::grace_period) is set and the
+/// authentication handler accepts them as well.
+///
+/// [`CookiePolicy`]: crate::CookiePolicy
+pub struct TicketRenewal {
+    signer: Box<dyn TicketSigner>,
+    path: String,
+    lifetime: i64,
+    renewal_threshold: Option<i64>,
+    grace_period: i64,
+    clock_skew: i64,
+}
+
+impl TicketRenewal {
+    /// Create the endpoint, verifying and issuing tickets with `signer`.
+    pub fn new(signer: impl TicketSigner + 'static) -> Self {
+        Self {
+            signer: Box::new(signer),
+            path: "/api2/json/access/renew-ticket".to_string(),
+            lifetime: 2 * 3600,
+            renewal_threshold: None,
+            grace_period: 0,
+            clock_skew: 300,
+        }
+    }
+
+    /// Change the path of the endpoint.
+    pub fn path(mut self, path: &str) -> Self {
+        // compared with normalized request paths
+        self.path = path
+            .split('/')
+            .filter(|comp| !comp.is_empty())
+            .map(|comp| format!("/{comp}"))
+            .collect();
+        self
+    }
+
+    /// Set for how many seconds tickets are valid.
+    pub fn lifetime(mut self, seconds: i64) -> Self {
+        self.lifetime = seconds;
+        self
+    }
+
+    /// Renew tickets once they are valid for less than `seconds`.
+    pub fn renewal_threshold(mut self, seconds: i64) -> Self {
+        self.renewal_threshold = Some(seconds);
+        self
+    }
+
+    /// Also renew tickets which expired at most `seconds` ago, disabled by default.
+    pub fn grace_period(mut self, seconds: i64) -> Self {
+        self.grace_period = seconds.max(0);
+        self
+    }
+
+    /// Set how many seconds a ticket's timestamp may lie in the future.
+    pub fn clock_skew(mut self, seconds: i64) -> Self {
+        self.clock_skew = seconds;
+        self
+    }
+
+    /// Check if a request is for the renewal endpoint.
+    pub(crate) fn matches(&self, method: &Method, path: &str) -> bool {
+        *method == Method::POST && path == self.path
+    }
+
+    /// Answer a request to the renewal endpoint.
+    pub(crate) async fn handle_request(
+        &self,
+        config: &ApiConfig,
+        headers: &HeaderMap,
+        rpcenv: &RestEnvironment,
+    ) -> Result<Response<Body>, Error> {
+        let result = self
+            .handle_request_at(config, headers, rpcenv, proxmox_time::epoch_i64())
+            .await;
+        if result.is_err() {
+            tokio::time::sleep_until(Instant::from_std(delay_unauth_time())).await;
+        }
+        result
+    }
+
+    async fn handle_request_at(
+        &self,
+        config: &ApiConfig,
+        headers: &HeaderMap,
+        rpcenv: &RestEnvironment,
+        now: i64,
+    ) -> Result<Response<Body>, Error> {
+        let cookie_policy = config.get_auth_cookie_policy();
+
+        let (ticket, from_cookie) = match headers.get(TICKET_HEADER_NAME) {
+            Some(value) => match value.to_str() {
+                Ok(ticket) => (ticket.to_string(), false),
+                Err(_) => http_bail!(BAD_REQUEST, "invalid ticket header"),
+            },
+            None => match cookie_policy.and_then(|policy| policy.extract(headers)) {
+                Some(ticket) => (ticket, true),
+                None => http_bail!(UNAUTHORIZED, "missing ticket"),
+            },
+        };
+
+        let auth_id = match config.check_auth(headers, &Method::POST).await {
+            Ok((auth_id, _)) => auth_id,
+            Err(err) => {
+                let err = match err {
+                    AuthError::Generic(err) => err,
+                    AuthError::NoData => format_err!("no authentication credentials provided."),
+                };
+                rpcenv.log_failed_auth(None, &format!("ticket renewal failed - {err}"));
+                http_bail!(UNAUTHORIZED, "authentication failed - {err}");
+            }
+        };
+
+        let (userid, new_ticket, expires) = match self.renew_at(&ticket, now) {
+            Ok((userid, ..)) if userid != auth_id => {
+                rpcenv.log_failed_auth(
+                    Some(auth_id.clone()),
+                    &format!("ticket renewal failed - ticket was issued for {userid}"),
+                );
+                http_bail!(
+                    UNAUTHORIZED,
+                    "ticket does not belong to the authenticated user"
+                );
+            }
+            Ok(result) => result,
+            Err(err) => {
+                rpcenv.log_failed_auth(Some(auth_id), &format!("ticket renewal failed - {err}"));
+                return Err(err);
+            }
+        };
+
+        let mut data = json!({
+            "username": userid,
+            "renewed": new_ticket.is_some(),
+            "expires": expires,
+        });
+
+        let mut response = Response::builder()
+            .header(header::CONTENT_TYPE, "application/json;charset=UTF-8")
+            .header(header::CACHE_CONTROL, "no-store");
+
+        if from_cookie {
+            if let Some(csrf) = config.get_csrf_protection() {
+                data["CSRFPreventionToken"] = csrf.assemble_token(&userid)?.into();
+            }
+        }
+
+        if let Some(ticket) = new_ticket {
+            rpcenv.log_ticket_renewal(&userid);
+            match cookie_policy {
+                Some(policy) if from_cookie => {
+                    response = response.header(header::SET_COOKIE, policy.set_cookie(&ticket)?);
+                }
+                _ => data["ticket"] = ticket.into(),
+            }
+        }
+
+        Ok(response
+            .body(json!({ "data": data }).to_string().into())
+            .unwrap())
+    }
+
+    /// Check `ticket` and issue a new one if it is due.
+    ///
+    /// Returns the user, the new ticket if one was issued and the epoch the current ticket
+    /// expires at.
+    fn renew_at(&self, ticket: &str, now: i64) -> Result<(String, Option<String>, i64), Error> {
+        let (userid, issued) = self
+            .signer
+            .verify_ticket(ticket)
+            .map_err(|err| http_err!(UNAUTHORIZED, "invalid ticket - {err}"))?;
+
+        let age = now - issued;
+        if age < -self.clock_skew {
+            http_bail!(UNAUTHORIZED, "ticket timestamp newer than expected");
+        }
+        if age > self.lifetime + self.grace_period {
+            http_bail!(UNAUTHORIZED, "ticket expired");
+        }
+
+        let threshold = self.renewal_threshold.unwrap_or(self.lifetime / 2);
+        if self.lifetime - age > threshold {
+            return Ok((userid, None, issued + self.lifetime));
+        }
+
+        let new_ticket = self.signer.sign_ticket(&userid, now)?;
+        Ok((userid, Some(new_ticket), now + self.lifetime))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use anyhow::{bail, format_err, Error};
+    use http::{header, HeaderMap, HeaderValue, Response, StatusCode};
+    use hyper::Body;
+    use serde_json::Value;
+
+    use proxmox_router::{HttpError, RpcEnvironmentType, UserInformation};
+
+    use super::{TicketRenewal, TicketSigner, TICKET_HEADER_NAME};
+    use crate::rest::EmptyUserInformation;
+    use crate::{
+        ApiConfig, AuthError, CookiePolicy, CsrfProtection, RestEnvironment, CSRF_HEADER_NAME,
+    };
+
+    const NOW: i64 = 1_700_000_000;
+
+    /// Tickets are `<userid>:<issued>:signed`.
+    struct MockSigner;
+
+    impl TicketSigner for MockSigner {
+        fn verify_ticket(&self, ticket: &str) -> Result<(String, i64), Error> {
+            match ticket.rsplitn(3, ':').collect::<Vec<_>>()[..] {
+                ["signed", issued, userid] => Ok((userid.to_string(), issued.parse()?)),
+                _ => bail!("bad signature"),
+            }
+        }
+
+        fn sign_ticket(&self, userid: &str, timestamp: i64) -> Result<String, Error> {
+            Ok(format!("{userid}:{timestamp}:signed"))
+        }
+    }
+
+    fn ticket(age: i64) -> String {
+        format!("user@pam:{}:signed", NOW - age)
+    }
+
+    fn status(err: Error) -> StatusCode {
+        err.downcast_ref::<HttpError>().unwrap().code
+    }
+
+    #[test]
+    fn test_renewal_by_age() {
+        let renewal = TicketRenewal::new(MockSigner).lifetime(7200);
+        let renew = |age: i64| renewal.renew_at(&ticket(age), NOW);
+
+        // young tickets are kept
+        for age in [-300, 0, 3599] {
+            let (userid, new_ticket, expires) = renew(age).unwrap();
+            assert_eq!(userid, "user@pam");
+            assert_eq!(new_ticket, None, "age {age}");
+            assert_eq!(expires, NOW - age + 7200);
+        }
+
+        // from half of the lifetime on they are renewed
+        for age in [3600, 7000, 7200] {
+            let (_, new_ticket, expires) = renew(age).unwrap();
+            assert_eq!(new_ticket.as_deref(), Some("user@pam:1700000000:signed"));
+            assert_eq!(expires, NOW + 7200);
+        }
+
+        // expired tickets and tickets from the future are rejected
+        for age in [7201, 100_000, -301] {
+            assert_eq!(status(renew(age).unwrap_err()), StatusCode::UNAUTHORIZED);
+        }
+        let err = renewal.renew_at("user@pam:1:forged", NOW).unwrap_err();
+        assert_eq!(status(err), StatusCode::UNAUTHORIZED);
+    }
+
+    #[test]
+    fn test_renewal_threshold_and_grace_period() {
+        let renewal = TicketRenewal::new(MockSigner)
+            .lifetime(7200)
+            .renewal_threshold(600)
+            .grace_period(300);
+        let renewed = |age: i64| {
+            renewal
+                .renew_at(&ticket(age), NOW)
+                .map(|(_, new_ticket, _)| new_ticket.is_some())
+                .map_err(status)
+        };
+
+        assert_eq!(renewed(6599), Ok(false));
+        assert_eq!(renewed(6600), Ok(true));
+        // expired, but within the grace period
+        assert_eq!(renewed(7500), Ok(true));
+        assert_eq!(renewed(7501), Err(StatusCode::UNAUTHORIZED));
+    }
+
+    /// Authenticates by the ticket in the header or cookie, like the products do, but rejects
+    /// the disabled user.
+    fn config() -> ApiConfig {
+        ApiConfig::new("/", RpcEnvironmentType::PUBLIC)
+            .auth_handler_func(|headers, _method| {
+                let ticket = match headers.get(TICKET_HEADER_NAME) {
+                    Some(value) => value.to_str().ok().map(str::to_string),
+                    None => CookiePolicy::new("auth").extract(headers),
+                };
+                Box::pin(async move {
+                    let ticket = ticket.ok_or(AuthError::NoData)?;
+                    let (userid, _) = MockSigner.verify_ticket(&ticket)?;
+                    if userid == "disabled@pam" {
+                        return Err(AuthError::Generic(format_err!("user disabled")));
+                    }
+                    let info: Box<dyn UserInformation + Send + Sync> =
+                        Box::new(EmptyUserInformation {});
+                    Ok((userid, info))
+                })
+            })
+            .auth_cookie_policy(CookiePolicy::new("auth").http_only(true))
+            .unwrap()
+            .csrf_protection(CsrfProtection::new(b"secret".to_vec()))
+    }
+
+    fn try_request(
+        renewal: &TicketRenewal,
+        config: ApiConfig,
+        headers: &HeaderMap,
+    ) -> Result<Response<Body>, Error> {
+        let rpcenv = RestEnvironment::new(RpcEnvironmentType::PUBLIC, Arc::new(config));
+        futures::executor::block_on(renewal.handle_request_at(
+            rpcenv.api_config(),
+            headers,
+            &rpcenv,
+            NOW,
+        ))
+    }
+
+    fn request(renewal: &TicketRenewal, headers: &HeaderMap) -> Response<Body> {
+        try_request(renewal, config(), headers).unwrap()
+    }
+
+    fn data(response: Response<Body>) -> Value {
+        let body = futures::executor::block_on(hyper::body::to_bytes(response.into_body()));
+        let data: Value = serde_json::from_slice(&body.unwrap()).unwrap();
+        data["data"].clone()
+    }
+
+    fn cookie(ticket: &str) -> HeaderValue {
+        let cookie = format!("auth={}", ticket.replace(':', "%3A"));
+        HeaderValue::from_str(&cookie).unwrap()
+    }
+
+    #[test]
+    fn test_renewal_response() {
+        let renewal = TicketRenewal::new(MockSigner).lifetime(7200);
+
+        // header clients get the new ticket in the body
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            TICKET_HEADER_NAME,
+            HeaderValue::from_str(&ticket(4000)).unwrap(),
+        );
+        let response = request(&renewal, &headers);
+        assert!(response.headers().get(header::SET_COOKIE).is_none());
+        let body = data(response);
+        assert_eq!(body["renewed"], true);
+        assert_eq!(body["ticket"], "user@pam:1700000000:signed");
+        assert!(body.get("CSRFPreventionToken").is_none());
+
+        // cookie clients get the new ticket as cookie according to the policy
+        let csrf = CsrfProtection::new(b"secret".to_vec());
+        let mut headers = HeaderMap::new();
+        headers.insert(header::COOKIE, cookie(&ticket(4000)));
+        headers.insert(
+            CSRF_HEADER_NAME,
+            HeaderValue::from_str(&csrf.assemble_token("user@pam").unwrap()).unwrap(),
+        );
+        let response = request(&renewal, &headers);
+        assert_eq!(
+            response.headers()[header::SET_COOKIE],
+            "auth=user%40pam%3A1700000000%3Asigned; Path=/; SameSite=Lax; Secure; HttpOnly",
+        );
+        let body = data(response);
+        assert_eq!(body["username"], "user@pam");
+        assert!(body.get("ticket").is_none());
+        assert!(body["CSRFPreventionToken"].is_string());
+
+        // tickets which are not due are kept
+        headers.insert(header::COOKIE, cookie(&ticket(60)));
+        let response = request(&renewal, &headers);
+        assert!(response.headers().get(header::SET_COOKIE).is_none());
+        let body = data(response);
+        assert_eq!(body["renewed"], false);
+        assert_eq!(body["expires"], NOW - 60 + 7200);
+
+        // without a cookie policy only the header is accepted
+        let config = ApiConfig::new("/", RpcEnvironmentType::PUBLIC);
+        let err = try_request(&renewal, config, &headers).unwrap_err();
+        assert_eq!(status(err), StatusCode::UNAUTHORIZED);
+    }
+
+    #[test]
+    fn test_renewal_is_authenticated() {
+        let renewal = TicketRenewal::new(MockSigner).lifetime(7200);
+        let rejected =
+            |headers: &HeaderMap| status(try_request(&renewal, config(), headers).unwrap_err());
+
+        // cookie clients need a CSRF token
+        let mut headers = HeaderMap::new();
+        headers.insert(header::COOKIE, cookie(&ticket(4000)));
+        assert_eq!(rejected(&headers), StatusCode::UNAUTHORIZED);
+
+        // tickets the authentication handler rejects are not renewed
+        let mut headers = HeaderMap::new();
+        let disabled = format!("disabled@pam:{}:signed", NOW - 4000);
+        headers.insert(
+            TICKET_HEADER_NAME,
+            HeaderValue::from_str(&disabled).unwrap(),
+        );
+        assert_eq!(rejected(&headers), StatusCode::UNAUTHORIZED);
+
+        // the ticket must belong to the authenticated user
+        let other = format!("other@pam:{}:signed", NOW - 4000);
+        let config = config().auth_handler_func(|_headers, _method| {
+            Box::pin(async {
+                let info: Box<dyn UserInformation + Send + Sync> =
+                    Box::new(EmptyUserInformation {});
+                Ok(("user@pam".to_string(), info))
+            })
+        });
+        headers.insert(TICKET_HEADER_NAME, HeaderValue::from_str(&other).unwrap());
+        let err = try_request(&renewal, config, &headers).unwrap_err();
+        assert_eq!(status(err), StatusCode::UNAUTHORIZED);
+    }
+}
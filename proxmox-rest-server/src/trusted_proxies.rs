@@ -0,0 +1,140 @@
+//! Proxies allowed to report the client address of a request.
+
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use anyhow::{bail, format_err, Error};
+
+/// Addresses and networks of proxies whose `Forwarded` header is trusted, see
+/// [`ApiConfig::trusted_proxies`](crate::ApiConfig::trusted_proxies).
+///
+/// Parsed from a list of addresses or networks in CIDR notation, separated by commas or
+/// whitespace, e.g. `127.0.0.1, 10.0.0.0/8, fd00::/8`. IPv4-mapped IPv6 addresses of peers are
+/// matched as IPv4 addresses.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TrustedProxies {
+    networks: Vec<(IpAddr, u8)>,
+}
+
+impl TrustedProxies {
+    /// Create an empty list, trusting no proxy at all.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Trust the proxies in the network `addr/prefix_len`.
+    pub fn network(mut self, addr: IpAddr, prefix_len: u8) -> Result<Self, Error> {
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        if prefix_len > max {
+            bail!("invalid prefix length {prefix_len} for {addr}");
+        }
+        // peers are matched with IPv4-mapped addresses converted
+        let network = match addr {
+            IpAddr::V6(v6) if prefix_len >= 96 => match v6.to_ipv4_mapped() {
+                Some(v4) => (IpAddr::V4(v4), prefix_len - 96),
+                None => (addr, prefix_len),
+            },
+            _ => (addr, prefix_len),
+        };
+        self.networks.push(network);
+        Ok(self)
+    }
+
+    /// Trust the proxy with the address `addr`.
+    pub fn address(self, addr: IpAddr) -> Self {
+        let prefix_len = if addr.is_ipv4() { 32 } else { 128 };
+        // the prefix length matches the address family
+        self.network(addr, prefix_len).unwrap()
+    }
+
+    /// Check whether a peer is a trusted proxy.
+    pub fn contains(&self, peer: IpAddr) -> bool {
+        let peer = peer.to_canonical();
+        self.networks
+            .iter()
+            .any(|&(network, prefix_len)| match (network, peer) {
+                (IpAddr::V4(network), IpAddr::V4(peer)) => prefix_matches(
+                    u32::from(network).into(),
+                    u32::from(peer).into(),
+                    32 - prefix_len,
+                ),
+                (IpAddr::V6(network), IpAddr::V6(peer)) => {
+                    prefix_matches(network.into(), peer.into(), 128 - prefix_len)
+                }
+                _ => false,
+            })
+    }
+}
+
+fn prefix_matches(network: u128, peer: u128, host_bits: u8) -> bool {
+    // shifting by the full width overflows, everything matches a zero length prefix
+    host_bits >= 128 || (network ^ peer) >> host_bits == 0
+}
+
+impl FromStr for TrustedProxies {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let mut proxies = Self::new();
+        for entry in s.split(|c: char| c == ',' || c.is_ascii_whitespace()) {
+            if entry.is_empty() {
+                continue;
+            }
+            proxies = match entry.split_once('/') {
+                Some((addr, prefix_len)) => {
+                    let addr: IpAddr = addr
+                        .parse()
+                        .map_err(|err| format_err!("invalid address '{addr}' - {err}"))?;
+                    let prefix_len: u8 = prefix_len
+                        .parse()
+                        .map_err(|err| format_err!("invalid prefix length in '{entry}' - {err}"))?;
+                    proxies.network(addr, prefix_len)?
+                }
+                None => {
+                    let addr: IpAddr = entry
+                        .parse()
+                        .map_err(|err| format_err!("invalid address '{entry}' - {err}"))?;
+                    proxies.address(addr)
+                }
+            };
+        }
+        Ok(proxies)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_trusted_proxies() -> Result<(), Error> {
+        let proxies: TrustedProxies =
+            "127.0.0.1, 10.0.0.0/8 fd00::/8,0.0.0.0/32 ::ffff:192.168.0.0/112".parse()?;
+
+        for trusted in [
+            "127.0.0.1",
+            "192.168.1.1",
+            "::ffff:127.0.0.1",
+            "10.1.2.3",
+            "fd12::1",
+            "0.0.0.0",
+        ] {
+            assert!(proxies.contains(trusted.parse()?), "{trusted}");
+        }
+        for untrusted in ["127.0.0.2", "11.0.0.1", "fe80::1", "::1", "::"] {
+            assert!(!proxies.contains(untrusted.parse()?), "{untrusted}");
+        }
+
+        let all: TrustedProxies = "0.0.0.0/0".parse()?;
+        assert!(all.contains("192.168.1.1".parse()?));
+        assert!(!all.contains("::2".parse()?));
+
+        assert!(!TrustedProxies::new().contains("127.0.0.1".parse()?));
+
+        assert!("10.0.0.0/33".parse::<TrustedProxies>().is_err());
+        assert!("localhost".parse::<TrustedProxies>().is_err());
+        assert!("10.0.0.0/x".parse::<TrustedProxies>().is_err());
+
+        Ok(())
+    }
+}
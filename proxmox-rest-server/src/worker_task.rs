@@ -1,29 +1,32 @@
-use std::collections::{HashMap, VecDeque};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::fs::File;
-use std::io::{BufRead, BufReader, Read, Write};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
 use std::panic::UnwindSafe;
-use std::path::PathBuf;
-use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::{Arc, LazyLock, Mutex, OnceLock};
-use std::time::{Duration, SystemTime};
+use std::time::{Duration, Instant, SystemTime};
 
 use anyhow::{bail, format_err, Error};
 use futures::*;
 use nix::fcntl::OFlag;
+use nix::unistd::Pid;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use tokio::signal::unix::SignalKind;
 use tokio::sync::{oneshot, watch};
-use tracing::{info, warn};
+use tracing::{error, info, warn};
 
 use proxmox_daemon::command_socket::CommandSocket;
 use proxmox_lang::try_block;
 use proxmox_log::{FileLogOptions, FileLogger, LogContext};
-use proxmox_schema::upid::UPID;
+use proxmox_router::RpcEnvironment;
+use proxmox_schema::upid::{check_task_type, lookup_task_type, TaskTypeInfo, UPID};
 use proxmox_sys::fs::{atomic_open_or_create_file, create_path, replace_file, CreateOptions};
+use proxmox_sys::linux::process::{self, CpuSet};
 use proxmox_sys::linux::procfs;
 use proxmox_sys::logrotate::{LogRotate, LogRotateFiles};
-use proxmox_worker_task::WorkerTaskContext;
+use proxmox_worker_task::{ConcurrencyLimit, QueuePolicy, ResourceUsage, WorkerTaskContext};
 
 static LAST_WORKER_LISTENERS: OnceLock<watch::Sender<bool>> = OnceLock::new();
 static WORKER_COUNT: AtomicUsize = AtomicUsize::new(0);
@@ -86,12 +89,223 @@ pub fn set_worker_count(count: usize) {
     check_last_worker();
 }
 
+static STRICT_TASK_TYPES: AtomicBool = AtomicBool::new(false);
+
+/// Make spawning workers of unregistered types or with invalid worker IDs fail instead of only
+/// logging a warning.
+///
+/// Worker types are registered with [`register_task_type`](proxmox_schema::upid::register_task_type),
+/// as long as none are registered no checks are done.
+pub fn set_strict_task_types(strict: bool) {
+    STRICT_TASK_TYPES.store(strict, Ordering::Release);
+}
+
+/// Default of [`set_max_worker_tasks`].
+pub const DEFAULT_MAX_WORKER_TASKS: usize = 512;
+
+static MAX_WORKER_TASKS: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_WORKER_TASKS);
+static LIMIT_EXEMPT_TASK_TYPES: LazyLock<Mutex<HashSet<String>>> = LazyLock::new(Default::default);
+
+/// Set the maximum number of concurrently existing worker tasks, including queued ones.
+///
+/// Creating more tasks fails with a "too many running tasks" error, protecting the host against
+/// clients spawning tasks without bounds. This applies to all worker types, in addition to their
+/// [`ConcurrencyLimit`], except for those exempted with [`set_task_limit_exempt`].
+pub fn set_max_worker_tasks(max: usize) {
+    MAX_WORKER_TASKS.store(max, Ordering::Release);
+}
+
+/// Exempt a worker type from the limit set with [`set_max_worker_tasks`].
+///
+/// This is meant for critical internal tasks, e.g. those run on shutdown, which must not fail
+/// because clients exhausted the limit. Exempted tasks are still counted.
+pub fn set_task_limit_exempt(worker_type: &str, exempt: bool) {
+    let mut exempt_types = LIMIT_EXEMPT_TASK_TYPES.lock().unwrap();
+    if exempt {
+        exempt_types.insert(worker_type.to_string());
+    } else {
+        exempt_types.remove(worker_type);
+    }
+}
+
+static THREAD_AFFINITY: LazyLock<Mutex<HashMap<String, CpuSet>>> = LazyLock::new(Default::default);
+
+/// Pin the threads of thread based tasks ([`WorkerTask::new_thread`]) of a worker type to a set of
+/// CPUs, or remove the affinity with `None`.
+///
+/// This is meant for IO heavy tasks, which should be kept off the CPUs handling network
+/// interrupts. It applies to tasks started afterwards. If the affinity cannot be set, e.g. since
+/// none of the CPUs is available, the task logs a warning and runs without it.
+pub fn set_worker_thread_affinity(worker_type: &str, cpus: Option<CpuSet>) {
+    let mut affinity = THREAD_AFFINITY.lock().unwrap();
+    match cpus {
+        Some(cpus) => affinity.insert(worker_type.to_string(), cpus),
+        None => affinity.remove(worker_type),
+    };
+}
+
+fn worker_thread_affinity(worker_type: &str) -> Option<CpuSet> {
+    THREAD_AFFINITY.lock().unwrap().get(worker_type).cloned()
+}
+
+/// Check whether a new task fits into the limit set with [`set_max_worker_tasks`], with `count`
+/// tasks in the worker task list.
+fn check_task_limit(worker_type: &str, count: usize) -> Result<(), Error> {
+    if LIMIT_EXEMPT_TASK_TYPES
+        .lock()
+        .unwrap()
+        .contains(worker_type)
+    {
+        return Ok(());
+    }
+
+    let max = MAX_WORKER_TASKS.load(Ordering::Acquire);
+    if count >= max {
+        bail!("too many running tasks ({count}, limit is {max})");
+    }
+    Ok(())
+}
+
+/// Number of running and queued worker tasks of this process.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct WorkerTaskStats {
+    /// Number of all tasks.
+    pub running: usize,
+    /// The limit set with [`set_max_worker_tasks`].
+    pub limit: usize,
+    /// Number of tasks per worker type.
+    pub per_type: BTreeMap<String, usize>,
+}
+
+/// Get the number of running and queued worker tasks, e.g. to display them or for metrics.
+pub fn worker_task_stats() -> WorkerTaskStats {
+    let list = WORKER_TASK_LIST.lock().unwrap();
+    let mut per_type = BTreeMap::new();
+    for worker in list.values() {
+        *per_type.entry(worker.upid.worker_type.clone()).or_default() += 1;
+    }
+
+    WorkerTaskStats {
+        running: list.len(),
+        limit: MAX_WORKER_TASKS.load(Ordering::Acquire),
+        per_type,
+    }
+}
+
+static RESOURCE_ACCOUNTING: AtomicBool = AtomicBool::new(false);
+
+/// Enable resource accounting for worker tasks started from now on.
+///
+/// The [`TaskResourceUsage`] of finished tasks is recorded in the task log and the task archive,
+/// see [`TaskListInfo::resource_usage`] and [`TaskStatus::resource_usage`].
+pub fn set_task_resource_accounting(enabled: bool) {
+    RESOURCE_ACCOUNTING.store(enabled, Ordering::Release);
+}
+
+/// Resources used by a finished worker task.
+///
+/// For thread based tasks ([`WorkerTask::new_thread`]) the CPU times are those of the task's
+/// thread, for async tasks ([`WorkerTask::spawn`]) the time spent polling the task's future is
+/// recorded instead. In both cases, resources reported with
+/// [`WorkerTaskContext::add_resource_usage`], e.g. of external commands, are added.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct TaskResourceUsage {
+    /// Time between the start and the end of the task in milliseconds.
+    #[serde(default)]
+    pub wall_time_ms: u64,
+    /// Time spent polling the future of an async task in milliseconds, an estimate of the time
+    /// it kept a thread of the runtime busy.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub poll_time_ms: Option<u64>,
+    /// CPU time spent in user mode in milliseconds.
+    #[serde(default)]
+    pub user_cpu_ms: u64,
+    /// CPU time spent in kernel mode in milliseconds.
+    #[serde(default)]
+    pub system_cpu_ms: u64,
+    /// Maximum resident set size in KiB.
+    #[serde(default)]
+    pub max_rss_kib: u64,
+}
+
+impl TaskResourceUsage {
+    fn add(&mut self, usage: &ResourceUsage) {
+        self.user_cpu_ms += usage.user_time.as_millis() as u64;
+        self.system_cpu_ms += usage.system_time.as_millis() as u64;
+        self.max_rss_kib = self.max_rss_kib.max(usage.max_rss_kib);
+    }
+}
+
+/// The resource usage of the calling thread.
+fn thread_resource_usage() -> Option<ResourceUsage> {
+    use nix::sys::resource::{getrusage, UsageWho};
+    use nix::sys::time::TimeValLike;
+
+    let usage = getrusage(UsageWho::RUSAGE_THREAD).ok()?;
+    Some(ResourceUsage {
+        user_time: Duration::from_micros(usage.user_time().num_microseconds() as u64),
+        system_time: Duration::from_micros(usage.system_time().num_microseconds() as u64),
+        max_rss_kib: usage.max_rss() as u64,
+    })
+}
+
 #[allow(dead_code)]
 struct TaskListLockGuard(File);
 
+/// Options for [`init_worker_tasks_with_options`].
+#[derive(Clone)]
+pub struct WorkerTaskOptions {
+    file_opts: CreateOptions,
+    log_file_opts: Option<CreateOptions>,
+    log_dir_opts: Option<CreateOptions>,
+    sharded_logs: bool,
+}
+
+impl WorkerTaskOptions {
+    /// Create the options with the `file_opts` for the task list files.
+    ///
+    /// By default these are also used for the task logs, with mode `0755` for their directories.
+    pub fn new(file_opts: CreateOptions) -> Self {
+        Self {
+            file_opts,
+            log_file_opts: None,
+            log_dir_opts: None,
+            sharded_logs: false,
+        }
+    }
+
+    /// Set the options for task log files, e.g. if workers run as a different user.
+    pub fn log_file_options(mut self, options: CreateOptions) -> Self {
+        self.log_file_opts = Some(options);
+        self
+    }
+
+    /// Set the options for the task log directories.
+    pub fn log_dir_options(mut self, options: CreateOptions) -> Self {
+        self.log_dir_opts = Some(options);
+        self
+    }
+
+    /// Store new task logs in two levels of directories by a hash of their UPID.
+    ///
+    /// Otherwise there is one directory per lower byte of the process start time, which contain
+    /// too many files on systems with many archived tasks. Logs are looked up in both layouts, so
+    /// this can be enabled on existing installations, see [`migrate_task_logs`].
+    pub fn sharded_logs(mut self, sharded: bool) -> Self {
+        self.sharded_logs = sharded;
+        self
+    }
+}
+
 struct WorkerTaskSetup {
     file_opts: CreateOptions,
+    log_file_opts: CreateOptions,
+    log_dir_opts: CreateOptions,
+    sharded_logs: bool,
     taskdir: PathBuf,
+    shard_dir: PathBuf,
     task_lock_fn: PathBuf,
     active_tasks_fn: PathBuf,
     task_index_fn: PathBuf,
@@ -107,10 +321,13 @@ fn worker_task_setup() -> Result<&'static WorkerTaskSetup, Error> {
 }
 
 impl WorkerTaskSetup {
-    fn new(basedir: PathBuf, file_opts: CreateOptions) -> Self {
+    fn new(basedir: PathBuf, options: WorkerTaskOptions) -> Self {
         let mut taskdir = basedir;
         taskdir.push("tasks");
 
+        let mut shard_dir = taskdir.clone();
+        shard_dir.push("logs");
+
         let mut task_lock_fn = taskdir.clone();
         task_lock_fn.push(".active.lock");
 
@@ -123,9 +340,23 @@ impl WorkerTaskSetup {
         let mut task_archive_fn = taskdir.clone();
         task_archive_fn.push("archive");
 
+        let log_file_opts = options
+            .log_file_opts
+            .unwrap_or_else(|| options.file_opts.clone());
+        let log_dir_opts = options.log_dir_opts.unwrap_or_else(|| {
+            options
+                .file_opts
+                .clone()
+                .perm(nix::sys::stat::Mode::from_bits_truncate(0o755))
+        });
+
         Self {
-            file_opts,
+            file_opts: options.file_opts,
+            log_file_opts,
+            log_dir_opts,
+            sharded_logs: options.sharded_logs,
             taskdir,
+            shard_dir,
             task_lock_fn,
             active_tasks_fn,
             task_index_fn,
@@ -147,31 +378,144 @@ impl WorkerTaskSetup {
         Ok(TaskListLockGuard(file))
     }
 
-    fn log_directory(&self, upid: &UPID) -> std::path::PathBuf {
+    // the directory of the legacy layout, by the lower byte of the process start time
+    fn legacy_log_directory(&self, upid: &UPID) -> PathBuf {
         let mut path = self.taskdir.clone();
         path.push(format!("{:02X}", upid.pstart & 255));
         path
     }
 
-    fn log_path(&self, upid: &UPID) -> std::path::PathBuf {
-        let mut path = self.log_directory(upid);
-        path.push(upid.to_string());
+    fn sharded_log_directory(&self, upid: &UPID) -> PathBuf {
+        let hash = openssl::sha::sha256(upid.to_string().as_bytes());
+        let mut path = self.shard_dir.clone();
+        path.push(format!("{:02X}", hash[0]));
+        path.push(format!("{:02X}", hash[1]));
         path
     }
 
-    fn create_and_get_log_path(&self, upid: &UPID) -> Result<std::path::PathBuf, Error> {
+    fn log_directory(&self, upid: &UPID) -> PathBuf {
+        if self.sharded_logs {
+            self.sharded_log_directory(upid)
+        } else {
+            self.legacy_log_directory(upid)
+        }
+    }
+
+    // the path in the configured layout first, then the one in the other layout
+    fn log_paths(&self, upid: &UPID) -> [PathBuf; 2] {
+        let name = upid.to_string();
+        let legacy = self.legacy_log_directory(upid).join(&name);
+        let sharded = self.sharded_log_directory(upid).join(&name);
+        if self.sharded_logs {
+            [sharded, legacy]
+        } else {
+            [legacy, sharded]
+        }
+    }
+
+    fn log_path(&self, upid: &UPID) -> PathBuf {
+        let [path, other] = self.log_paths(upid);
+        if !path.exists() && other.exists() {
+            other
+        } else {
+            path
+        }
+    }
+
+    // Open a task log in either layout. A concurrent migration may move it between the two
+    // attempts, so the configured layout is tried again.
+    fn open_log(&self, upid: &UPID) -> Result<File, Error> {
+        let [path, other] = self.log_paths(upid);
+        for path in [&path, &other, &path] {
+            match File::open(path) {
+                Ok(file) => return Ok(file),
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(err) => bail!("unable to open task log {path:?} - {err}"),
+            }
+        }
+        bail!("unable to open task log {path:?} - not found");
+    }
+
+    fn create_and_get_log_path(&self, upid: &UPID) -> Result<PathBuf, Error> {
         let mut path = self.log_directory(upid);
-        let dir_opts = self
-            .file_opts
-            .clone()
-            .perm(nix::sys::stat::Mode::from_bits_truncate(0o755));
 
-        create_path(&path, None, Some(dir_opts))?;
+        create_path(
+            &path,
+            Some(self.log_dir_opts.clone()),
+            Some(self.log_dir_opts.clone()),
+        )?;
 
         path.push(upid.to_string());
         Ok(path)
     }
 
+    // All existing task log directories of both layouts.
+    fn log_directories(&self) -> Vec<PathBuf> {
+        let mut dirs: Vec<PathBuf> = (0..256)
+            .map(|i| self.taskdir.join(format!("{i:02X}")))
+            .filter(|dir| dir.is_dir())
+            .collect();
+
+        let read_subdirs = |dir: &Path| -> Vec<PathBuf> {
+            let entries = match std::fs::read_dir(dir) {
+                Ok(entries) => entries,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Vec::new(),
+                Err(err) => {
+                    warn!("could not check task logs in {dir:?}: {err}");
+                    return Vec::new();
+                }
+            };
+            let mut subdirs: Vec<PathBuf> = entries
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| entry.file_type().is_ok_and(|ty| ty.is_dir()))
+                .map(|entry| entry.path())
+                .collect();
+            subdirs.sort();
+            subdirs
+        };
+        for dir in read_subdirs(&self.shard_dir) {
+            dirs.extend(read_subdirs(&dir));
+        }
+
+        dirs
+    }
+
+    // Move the logs of the legacy layout into the sharded layout, returns the number of moved
+    // logs.
+    fn migrate_legacy_logs(&self) -> Result<usize, Error> {
+        let mut moved = 0;
+        for i in 0..256 {
+            let dir = self.taskdir.join(format!("{i:02X}"));
+            let entries = match std::fs::read_dir(&dir) {
+                Ok(entries) => entries,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(err) => bail!("unable to read {dir:?} - {err}"),
+            };
+            for entry in entries {
+                let entry = entry?;
+                let name = entry.file_name();
+                let Some(upid) = name.to_str().and_then(|name| name.parse::<UPID>().ok()) else {
+                    warn!("skipping unexpected file {:?}", entry.path());
+                    continue;
+                };
+                let target = self.sharded_log_directory(&upid);
+                create_path(
+                    &target,
+                    Some(self.log_dir_opts.clone()),
+                    Some(self.log_dir_opts.clone()),
+                )?;
+                let target = target.join(&name);
+                std::fs::rename(entry.path(), &target).map_err(|err| {
+                    format_err!("unable to move {:?} to {target:?} - {err}", entry.path())
+                })?;
+                moved += 1;
+            }
+            // only removed if empty
+            let _ = std::fs::remove_dir(&dir);
+        }
+        Ok(moved)
+    }
+
     // atomically read/update the task list, update status of finished tasks
     // new_upid is added to the list when specified.
     fn update_active_workers(&self, new_upid: Option<&UPID>) -> Result<(), Error> {
@@ -186,7 +530,7 @@ impl WorkerTaskSetup {
         #[allow(clippy::unnecessary_filter_map)]
         let mut active_list: Vec<TaskListInfo> = read_task_file_from_path(&self.active_tasks_fn)?
             .into_iter()
-            .filter_map(|info| {
+            .filter_map(|mut info| {
                 if info.state.is_some() {
                     // this can happen when the active file still includes finished tasks
                     finish_list.push(info);
@@ -196,16 +540,29 @@ impl WorkerTaskSetup {
                 if !worker_is_active_local(&info.upid) {
                     // println!("Detected stopped task '{}'", &info.upid_str);
                     let now = proxmox_time::epoch_i64();
-                    let status =
-                        upid_read_status(&info.upid).unwrap_or(TaskState::Unknown { endtime: now });
+                    // local tasks always log their result, even if they never left the queue
+                    let result = if info.queued && !is_local_worker(&info.upid) {
+                        TaskLogResult::new(self.fail_stale_queued_task(&info.upid, now))
+                    } else {
+                        upid_read_result(&info.upid).unwrap_or_else(|_| {
+                            TaskLogResult::new(TaskState::Unknown { endtime: now })
+                        })
+                    };
                     finish_list.push(TaskListInfo {
                         upid: info.upid,
                         upid_str: info.upid_str,
-                        state: Some(status),
+                        state: Some(result.state),
+                        queued: false,
+                        summary: result.summary,
+                        resource_usage: result.resource_usage,
                     });
                     return None;
                 }
 
+                if is_local_worker(&info.upid) {
+                    info.queued = worker_is_queued_local(&info.upid);
+                }
+
                 Some(info)
             })
             .collect();
@@ -215,6 +572,9 @@ impl WorkerTaskSetup {
                 upid: upid.clone(),
                 upid_str: upid.to_string(),
                 state: None,
+                queued: false,
+                summary: Vec::new(),
+                resource_usage: None,
             });
         }
 
@@ -263,6 +623,27 @@ impl WorkerTaskSetup {
         Ok(())
     }
 
+    // A queued task whose process is gone never started, record that in its log.
+    fn fail_stale_queued_task(&self, upid: &UPID, now: i64) -> TaskState {
+        let state = TaskState::Error {
+            message: "task was still queued when the daemon stopped".to_string(),
+            endtime: now,
+        };
+
+        let logger_options = FileLogOptions {
+            append: true,
+            prefix_time: true,
+            file_opts: self.log_file_opts.clone(),
+            ..Default::default()
+        };
+        match FileLogger::new(self.log_path(upid), logger_options) {
+            Ok(mut logger) => logger.log(state.result_text()),
+            Err(err) => log::warn!("unable to update log of queued task {upid} - {err}"),
+        }
+
+        state
+    }
+
     // Create task log directory with correct permissions
     fn create_task_log_dirs(&self) -> Result<(), Error> {
         try_block!({
@@ -281,7 +662,15 @@ impl WorkerTaskSetup {
 
 /// Initialize the WorkerTask library
 pub fn init_worker_tasks(basedir: PathBuf, file_opts: CreateOptions) -> Result<(), Error> {
-    let setup = WorkerTaskSetup::new(basedir, file_opts);
+    init_worker_tasks_with_options(basedir, WorkerTaskOptions::new(file_opts))
+}
+
+/// Initialize the WorkerTask library with separate options for the task logs.
+pub fn init_worker_tasks_with_options(
+    basedir: PathBuf,
+    options: WorkerTaskOptions,
+) -> Result<(), Error> {
+    let setup = WorkerTaskSetup::new(basedir, options);
     setup.create_task_log_dirs()?;
     WORKER_TASK_SETUP
         .set(setup)
@@ -335,8 +724,8 @@ pub fn rotate_task_log_archive(
                     None => bail!("unexpected error: files do not match file_names"),
                 };
                 if let Some(Ok(line)) = reader.lines().next() {
-                    if let Ok((_, _, Some(state))) = parse_worker_status_line(&line) {
-                        if state.endtime() < cutoff_time {
+                    if let Ok((_, _, Some(result))) = parse_worker_status_line(&line) {
+                        if result.state.endtime() < cutoff_time {
                             // found first file with the oldest entry being cut-off, so next older
                             // ones are all up for deletion.
                             cutoff = true;
@@ -367,8 +756,8 @@ pub fn cleanup_old_tasks(compressed: bool) -> Result<(), Error> {
         let reader = BufReader::new(last_file);
         for line in reader.lines() {
             let line = line?;
-            if let Ok((_, _, Some(state))) = parse_worker_status_line(&line) {
-                timestamp = Some(state.endtime());
+            if let Ok((_, _, Some(result))) = parse_worker_status_line(&line) {
+                timestamp = Some(result.state.endtime());
                 break;
             }
         }
@@ -386,14 +775,12 @@ pub fn cleanup_old_tasks(compressed: bool) -> Result<(), Error> {
         }
         .ok_or_else(|| format_err!("could not calculate cutoff time"))?;
 
-        for i in 0..256 {
-            let mut path = setup.taskdir.clone();
-            path.push(format!("{:02X}", i));
-            let files = match std::fs::read_dir(path) {
+        for dir in setup.log_directories() {
+            let files = match std::fs::read_dir(&dir) {
                 Ok(files) => files,
                 Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
                 Err(err) => {
-                    warn!("could not check task logs in '{i:02X}': {err}");
+                    warn!("could not check task logs in {dir:?}: {err}");
                     continue;
                 }
             };
@@ -401,7 +788,7 @@ pub fn cleanup_old_tasks(compressed: bool) -> Result<(), Error> {
                 let file = match file {
                     Ok(file) => file,
                     Err(err) => {
-                        warn!("could not check some task log in '{i:02X}': {err}");
+                        warn!("could not check some task log in {dir:?}: {err}");
                         continue;
                     }
                 };
@@ -432,11 +819,42 @@ pub fn cleanup_old_tasks(compressed: bool) -> Result<(), Error> {
 }
 
 /// Path to the worker log file
+///
+/// This is the path in the configured layout, unless only the one in the other layout exists,
+/// see [`WorkerTaskOptions::sharded_logs`].
 pub fn upid_log_path(upid: &UPID) -> Result<std::path::PathBuf, Error> {
     let setup = worker_task_setup()?;
     Ok(setup.log_path(upid))
 }
 
+/// The worker type of the task started by [`migrate_task_logs`].
+pub const MIGRATE_TASK_LOGS_WORKER_TYPE: &str = "tasklogmigrate";
+
+/// Move the task logs of the legacy layout into the sharded layout in a worker task.
+///
+/// Fails if the task logs are not sharded, see [`WorkerTaskOptions::sharded_logs`]. Logs are
+/// found in both layouts while this runs, so it can run in the background. Products with strict
+/// task types need to register [`MIGRATE_TASK_LOGS_WORKER_TYPE`]. Returns the UPID of the task.
+pub fn migrate_task_logs(auth_id: String) -> Result<String, Error> {
+    let setup = worker_task_setup()?;
+    if !setup.sharded_logs {
+        bail!("task logs are not sharded");
+    }
+
+    WorkerTask::new_thread(
+        MIGRATE_TASK_LOGS_WORKER_TYPE,
+        None,
+        auth_id,
+        false,
+        move |_worker| {
+            info!("moving task logs to {:?}", setup.shard_dir);
+            let moved = setup.migrate_legacy_logs()?;
+            info!("moved {moved} task logs");
+            Ok(())
+        },
+    )
+}
+
 /// Parse the time and exit status from the last log line in a worker task log file. Works only
 /// correctly on finished tasks.
 ///
@@ -452,10 +870,38 @@ pub fn upid_log_path(upid: &UPID) -> Result<std::path::PathBuf, Error> {
 /// because the task was (unsafely) interrupted, e.g., due to a power loss. In that case the
 /// end-time is also set to the start-time.
 pub fn upid_read_status(upid: &UPID) -> Result<TaskState, Error> {
+    upid_read_result(upid).map(|result| result.state)
+}
+
+/// Read the summary a task attached with [`WorkerTask::final_summary`].
+///
+/// Like [`upid_read_status`] this only reads the tail of the task log. Tasks without a summary,
+/// including all tasks which finished before summaries existed, return an empty list.
+pub fn upid_read_summary(upid: &UPID) -> Result<Vec<String>, Error> {
+    upid_read_result(upid).map(|result| result.summary)
+}
+
+/// The result of a finished task, read from the tail of its log.
+struct TaskLogResult {
+    state: TaskState,
+    summary: Vec<String>,
+    resource_usage: Option<TaskResourceUsage>,
+}
+
+impl TaskLogResult {
+    fn new(state: TaskState) -> Self {
+        Self {
+            state,
+            summary: Vec::new(),
+            resource_usage: None,
+        }
+    }
+}
+
+fn upid_read_result(upid: &UPID) -> Result<TaskLogResult, Error> {
     let setup = worker_task_setup()?;
 
-    let path = setup.log_path(upid);
-    let mut file = File::open(path)?;
+    let mut file = setup.open_log(upid)?;
 
     /// speedup - only read tail
     use std::io::Seek;
@@ -465,16 +911,17 @@ pub fn upid_read_status(upid: &UPID) -> Result<TaskState, Error> {
     let mut data = Vec::with_capacity(8192);
     file.read_to_end(&mut data)?;
 
+    parse_task_log_tail(upid, data)
+}
+
+fn parse_task_log_tail(upid: &UPID, mut data: Vec<u8>) -> Result<TaskLogResult, Error> {
     // strip newlines at the end of the task logs
     while data.last() == Some(&b'\n') {
         data.pop();
     }
 
-    let last_line = match data.iter().rposition(|c| *c == b'\n') {
-        Some(start) if data.len() > (start + 1) => &data[start + 1..],
-        Some(_) => &data, // should not happen, since we removed all trailing newlines
-        None => &data,
-    };
+    let mut lines = data.rsplit(|c| *c == b'\n');
+    let last_line = lines.next().unwrap_or_default();
 
     let last_line = std::str::from_utf8(last_line)
         .map_err(|err| format_err!("upid_read_status: utf8 parse failed: {}", err))?;
@@ -484,15 +931,219 @@ pub fn upid_read_status(upid: &UPID) -> Result<TaskState, Error> {
     if let Some(time_str) = iter.next() {
         if let Ok(parsed_endtime) = proxmox_time::parse_rfc3339(time_str) {
             endtime = parsed_endtime; // save last found time for when the state cannot be parsed
-            if let Some(rest) = iter.next().and_then(|rest| rest.strip_prefix("TASK ")) {
+
+            // a summary line last means the task did not get to log its state
+            if let Some(rest) = iter
+                .next()
+                .filter(|rest| {
+                    !rest.starts_with(TASK_SUMMARY_PREFIX)
+                        && !rest.starts_with(TASK_RESOURCES_PREFIX)
+                })
+                .and_then(|rest| rest.strip_prefix("TASK "))
+            {
                 if let Ok(state) = TaskState::from_endtime_and_message(parsed_endtime, rest) {
-                    return Ok(state);
+                    let mut result = TaskLogResult::new(state);
+
+                    // the summary lines directly precede the final state line, preceded by the
+                    // resource usage
+                    for line in lines {
+                        let Some(rest) = std::str::from_utf8(line)
+                            .ok()
+                            .and_then(|line| line.split_once(": "))
+                            .filter(|(time_str, _)| proxmox_time::parse_rfc3339(time_str).is_ok())
+                            .map(|(_, rest)| rest)
+                        else {
+                            break;
+                        };
+                        if let Some(line) = rest.strip_prefix(TASK_SUMMARY_PREFIX) {
+                            result.summary.push(line.to_string());
+                            continue;
+                        }
+                        if let Some(usage) = rest.strip_prefix(TASK_RESOURCES_PREFIX) {
+                            result.resource_usage = serde_json::from_str(usage).ok();
+                        }
+                        break;
+                    }
+                    result.summary.reverse();
+                    return Ok(result);
                 }
             }
         }
     }
 
-    Ok(TaskState::Unknown { endtime }) // no last line with both, end-time and task-state, found.
+    // no last line with both, end-time and task-state, found.
+    Ok(TaskLogResult::new(TaskState::Unknown { endtime }))
+}
+
+/// A range of lines read from a task log, see [`read_task_log`].
+#[derive(Debug, Default)]
+pub struct TaskLogChunk {
+    /// The requested lines without line terminators.
+    pub lines: Vec<String>,
+    /// Number of the first returned line, counting from zero.
+    pub start: u64,
+    /// Total number of lines in the log so far.
+    pub total: u64,
+    /// Whether the task is still running, so that more lines may follow.
+    pub active: bool,
+}
+
+/// Number of lines between two remembered byte offsets in a [`TaskLogIndex`].
+const TASK_LOG_INDEX_STRIDE: u64 = 1024;
+
+/// Maximum number of task logs for which an index is cached.
+const TASK_LOG_INDEX_CACHE_SIZE: usize = 64;
+
+/// Remembers byte offsets into a task log file, so that repeated reads neither need to re-scan
+/// the whole file to count its lines nor to find the start of a requested line range.
+#[derive(Default)]
+struct TaskLogIndex {
+    /// Identifies the indexed file by `(device, inode)`.
+    file_id: (u64, u64),
+    /// Byte offset of every `TASK_LOG_INDEX_STRIDE`th line, starting with line zero.
+    checkpoints: Vec<u64>,
+    /// Number of complete (newline terminated) lines indexed so far.
+    lines: u64,
+    /// Byte offset right after the last complete line.
+    end: u64,
+    /// Sequence number of the last access, used for cache eviction.
+    last_used: u64,
+}
+
+impl TaskLogIndex {
+    fn new(file_id: (u64, u64)) -> Self {
+        Self {
+            file_id,
+            checkpoints: vec![0],
+            ..Default::default()
+        }
+    }
+
+    /// Index lines appended since the last update, returns the current size of the log.
+    fn update<R: BufRead + Seek>(&mut self, reader: &mut R) -> Result<u64, Error> {
+        // incomplete trailing lines are not indexed since they may still grow, so restart there
+        let mut pos = reader.seek(SeekFrom::Start(self.end))?;
+
+        loop {
+            let buffer = reader.fill_buf()?;
+            if buffer.is_empty() {
+                break;
+            }
+            for (offset, _) in buffer.iter().enumerate().filter(|(_, b)| **b == b'\n') {
+                self.end = pos + offset as u64 + 1;
+                self.lines += 1;
+                if self.lines % TASK_LOG_INDEX_STRIDE == 0 {
+                    self.checkpoints.push(self.end);
+                }
+            }
+            let len = buffer.len();
+            reader.consume(len);
+            pos += len as u64;
+        }
+
+        Ok(pos)
+    }
+
+    /// Read up to `limit` lines starting at line `start`.
+    ///
+    /// If `complete` is set, trailing data without a line terminator counts as last line.
+    fn read<R: BufRead + Seek>(
+        &mut self,
+        reader: &mut R,
+        start: u64,
+        limit: u64,
+        complete: bool,
+    ) -> Result<TaskLogChunk, Error> {
+        let size = self.update(reader)?;
+        let total = self.lines + u64::from(complete && size > self.end);
+
+        let mut chunk = TaskLogChunk {
+            start,
+            total,
+            ..Default::default()
+        };
+        if start >= total {
+            return Ok(chunk);
+        }
+
+        let checkpoint = start / TASK_LOG_INDEX_STRIDE;
+        reader.seek(SeekFrom::Start(self.checkpoints[checkpoint as usize]))?;
+
+        let mut line = Vec::new();
+        for _ in (checkpoint * TASK_LOG_INDEX_STRIDE)..start {
+            line.clear();
+            reader.read_until(b'\n', &mut line)?;
+        }
+
+        for _ in 0..limit.min(total - start) {
+            line.clear();
+            if reader.read_until(b'\n', &mut line)? == 0 {
+                break;
+            }
+            if line.last() == Some(&b'\n') {
+                line.pop();
+            }
+            chunk
+                .lines
+                .push(String::from_utf8_lossy(&line).into_owned());
+        }
+
+        Ok(chunk)
+    }
+}
+
+static TASK_LOG_INDEX: LazyLock<Mutex<(u64, HashMap<String, TaskLogIndex>)>> =
+    LazyLock::new(|| Mutex::new((0, HashMap::new())));
+
+/// Read a range of lines from a task log.
+///
+/// Returns at most `limit` lines starting at line `start_line` (counting from zero), together
+/// with the number of lines logged so far and whether the task is still running, so that clients
+/// know if they should keep polling for new lines.
+///
+/// Byte offsets into the log are remembered between calls, so polling only reads the newly
+/// appended data plus the requested range instead of the whole file. If the log file got
+/// replaced, for example by rotation, the remembered offsets are dropped and the log is indexed
+/// again. While the task is running, a last line without line terminator is not returned yet.
+pub fn read_task_log(upid: &UPID, start_line: u64, limit: u64) -> Result<TaskLogChunk, Error> {
+    use std::os::unix::fs::MetadataExt;
+
+    let setup = worker_task_setup()?;
+    let upid_str = upid.to_string();
+
+    // check this first, so that a finished task's log is known to be complete
+    let active = worker_is_active_local(upid);
+
+    let file = setup.open_log(upid)?;
+    let metadata = file.metadata()?;
+    let file_id = (metadata.dev(), metadata.ino());
+
+    let cached = TASK_LOG_INDEX.lock().unwrap().1.remove(&upid_str);
+    let mut index = match cached {
+        Some(index) if index.file_id == file_id && index.end <= metadata.len() => index,
+        _ => TaskLogIndex::new(file_id),
+    };
+
+    let mut reader = BufReader::new(file);
+    let mut chunk = index.read(&mut reader, start_line, limit, !active)?;
+    chunk.active = active;
+
+    let mut cache = TASK_LOG_INDEX.lock().unwrap();
+    cache.0 += 1;
+    index.last_used = cache.0;
+    if cache.1.len() >= TASK_LOG_INDEX_CACHE_SIZE {
+        let oldest = cache
+            .1
+            .iter()
+            .min_by_key(|(_, index)| index.last_used)
+            .map(|(upid, _)| upid.clone());
+        if let Some(oldest) = oldest {
+            cache.1.remove(&oldest);
+        }
+    }
+    cache.1.insert(upid_str, index);
+
+    Ok(chunk)
 }
 
 static WORKER_TASK_LIST: LazyLock<Mutex<HashMap<usize, Arc<WorkerTask>>>> =
@@ -514,6 +1165,12 @@ pub async fn worker_is_active(upid: &UPID) -> Result<bool, Error> {
     }
 
     let sock = proxmox_daemon::command_socket::path_from_pid(upid.pid);
+    match query_task_status_at(&sock, upid).await {
+        Ok(status) => return Ok(status.is_running()),
+        // the process may have been started before the `task-status` command existed
+        Err(err) => log::debug!("unable to query status of task {upid} - {err}"),
+    }
+
     let cmd = json!({
         "command": "worker-task-status",
         "args": {
@@ -529,6 +1186,17 @@ pub async fn worker_is_active(upid: &UPID) -> Result<bool, Error> {
     }
 }
 
+/// Test if a local task is waiting for a free slot because of its concurrency limit
+pub fn worker_is_queued_local(upid: &UPID) -> bool {
+    if !is_local_worker(upid) {
+        return false;
+    }
+    match WORKER_TASK_LIST.lock().unwrap().get(&upid.task_id) {
+        Some(worker) => worker.data.lock().unwrap().queued,
+        None => false,
+    }
+}
+
 /// Test if the task is still running (fast but inaccurate implementation)
 ///
 /// If the task is spawned from a different process, we simply return if
@@ -542,14 +1210,120 @@ pub fn worker_is_active_local(upid: &UPID) -> bool {
     }
 }
 
+/// Number of log lines included in a [`TaskStatus`].
+const TASK_STATUS_LOG_LINES: usize = 20;
+
+/// Status of a worker task, see [`query_task_status`].
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct TaskStatus {
+    /// The final state, `None` while the task is running.
+    pub state: Option<TaskState>,
+    /// The progress of a running task between 0 and 1, see [`WorkerTask::progress`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub progress: Option<f64>,
+    /// The last lines of the task log.
+    pub log_tail: Vec<String>,
+    /// The resources used by a finished task, if resource accounting was enabled, see
+    /// [`set_task_resource_accounting`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resource_usage: Option<TaskResourceUsage>,
+}
+
+impl TaskStatus {
+    /// Returns true if the task is still running (or queued).
+    pub fn is_running(&self) -> bool {
+        self.state.is_none()
+    }
+}
+
+/// Read the last `count` lines of a task log.
+fn read_task_log_tail(upid: &UPID, count: usize) -> Result<Vec<String>, Error> {
+    let mut file = worker_task_setup()?.open_log(upid)?;
+
+    // speedup - only read tail
+    let truncated = file.seek(SeekFrom::End(-8192)).is_ok();
+    let mut data = Vec::with_capacity(8192);
+    file.read_to_end(&mut data)?;
+
+    let data = String::from_utf8_lossy(&data);
+    let mut lines: Vec<&str> = data.lines().collect();
+    if truncated && !lines.is_empty() {
+        // the first line is most likely incomplete
+        lines.remove(0);
+    }
+    let start = lines.len().saturating_sub(count);
+    Ok(lines[start..].iter().map(|line| line.to_string()).collect())
+}
+
+/// Get the status of a task of this process.
+fn local_task_status(upid: &UPID) -> Result<TaskStatus, Error> {
+    // check this first, so that a finished task's log is known to be complete
+    let worker = WORKER_TASK_LIST.lock().unwrap().get(&upid.task_id).cloned();
+    let (result, progress) = match worker {
+        Some(worker) => (None, Some(worker.data.lock().unwrap().progress)),
+        None => (Some(upid_read_result(upid)?), None),
+    };
+
+    Ok(TaskStatus {
+        state: result.as_ref().map(|result| result.state.clone()),
+        progress,
+        log_tail: read_task_log_tail(upid, TASK_STATUS_LOG_LINES)?,
+        resource_usage: result.and_then(|result| result.resource_usage),
+    })
+}
+
+/// Get the status of a task, no matter which process started it.
+///
+/// Tasks of other processes are queried via the ``task-status`` command on the control socket of
+/// the process owning the task (see [register_task_control_commands]). If that process is gone,
+/// the task has finished and its state is read from the task log.
+pub async fn query_task_status(upid: &UPID) -> Result<TaskStatus, Error> {
+    if is_local_worker(upid) {
+        return local_task_status(upid);
+    }
+
+    if procfs::check_process_running_pstart(upid.pid, upid.pstart).is_some() {
+        let sock = proxmox_daemon::command_socket::path_from_pid(upid.pid);
+        return query_task_status_at(sock, upid).await;
+    }
+
+    let result = upid_read_result(upid)?;
+    Ok(TaskStatus {
+        state: Some(result.state),
+        progress: None,
+        log_tail: read_task_log_tail(upid, TASK_STATUS_LOG_LINES)?,
+        resource_usage: result.resource_usage,
+    })
+}
+
+/// Get the status of a task via the ``task-status`` command on the control socket at `path`,
+/// which must belong to the process owning the task.
+pub async fn query_task_status_at<P: AsRef<Path>>(
+    path: P,
+    upid: &UPID,
+) -> Result<TaskStatus, Error> {
+    let cmd = json!({
+        "command": "task-status",
+        "args": {
+            "upid": upid.to_string(),
+        },
+    });
+    let status = proxmox_daemon::command_socket::send(path, &cmd).await?;
+    serde_json::from_value(status)
+        .map_err(|err| format_err!("got unexpected task status result - {err}"))
+}
+
 /// Register task control command on a [CommandSocket].
 ///
-/// This create two commands:
+/// This create three commands:
 ///
 /// * ``worker-task-abort <UPID>``: calls [abort_local_worker]
 ///
 /// * ``worker-task-status <UPID>``: return true of false, depending on
 ///   whether the worker is running or stopped.
+///
+/// * ``task-status <UPID>``: return the [TaskStatus] of the task, see [query_task_status].
 pub fn register_task_control_commands(commando_sock: &mut CommandSocket) -> Result<(), Error> {
     fn get_upid(args: Option<&Value>) -> Result<UPID, Error> {
         let args = if let Some(args) = args {
@@ -582,6 +1356,11 @@ pub fn register_task_control_commands(commando_sock: &mut CommandSocket) -> Resu
 
         Ok(active.into())
     })?;
+    commando_sock.register_command("task-status".into(), move |args| {
+        let upid = get_upid(args)?;
+
+        Ok(serde_json::to_value(local_task_status(&upid)?)?)
+    })?;
 
     Ok(())
 }
@@ -613,24 +1392,72 @@ pub async fn abort_worker(upid: UPID) -> Result<(), Error> {
         .await
 }
 
-fn parse_worker_status_line(line: &str) -> Result<(String, UPID, Option<TaskState>), Error> {
+type WorkerStatus = (String, UPID, Option<TaskLogResult>);
+
+fn parse_worker_status_line(line: &str) -> Result<WorkerStatus, Error> {
     let data = line.splitn(3, ' ').collect::<Vec<&str>>();
 
     let len = data.len();
 
     match len {
         1 => Ok((data[0].to_owned(), data[0].parse::<UPID>()?, None)),
+        2 if data[1] == "queued" => Ok((data[0].to_owned(), data[0].parse::<UPID>()?, None)),
         3 => {
             let endtime = i64::from_str_radix(data[1], 16)?;
-            let state = TaskState::from_endtime_and_message(endtime, data[2])?;
-            Ok((data[0].to_owned(), data[0].parse::<UPID>()?, Some(state)))
+            let (status, resource_usage) = split_task_resource_usage(data[2]);
+            let (status, summary) = split_task_summary(status);
+            let state = TaskState::from_endtime_and_message(endtime, status)?;
+            Ok((
+                data[0].to_owned(),
+                data[0].parse::<UPID>()?,
+                Some(TaskLogResult {
+                    state,
+                    summary,
+                    resource_usage,
+                }),
+            ))
         }
         _ => bail!("wrong number of components"),
     }
 }
 
+/// Marks the task list field holding the resource usage as JSON object, see
+/// [`TASK_LIST_SUMMARY_FIELD`].
+const TASK_LIST_RESOURCES_FIELD: &str = "resources-v1:";
+
+// The resource usage is the last field, marked like the summary. The marker is required since all
+// fields of the usage are optional, so any JSON object would parse.
+fn split_task_resource_usage(status: &str) -> (&str, Option<TaskResourceUsage>) {
+    if let Some((status, field)) = status.rsplit_once('\t') {
+        if let Some(usage) = field.strip_prefix(TASK_LIST_RESOURCES_FIELD) {
+            if let Ok(usage) = serde_json::from_str(usage) {
+                return (status, Some(usage));
+            }
+        }
+    }
+    (status, None)
+}
+
+/// Marks the task list field holding the summary as JSON array. The version allows changing the
+/// encoding later without misreading the fields written before.
+const TASK_LIST_SUMMARY_FIELD: &str = "summary-v1:";
+
+// The summary follows the status after a tab, in its marked field. Statuses are written without
+// tabs, so a tab always starts such a field. Entries written before summaries existed never
+// contain the marker, so their whole remainder is their status, even if it contains tabs.
+fn split_task_summary(status: &str) -> (&str, Vec<String>) {
+    if let Some((status, field)) = status.rsplit_once('\t') {
+        if let Some(summary) = field.strip_prefix(TASK_LIST_SUMMARY_FIELD) {
+            if let Ok(summary) = serde_json::from_str::<Vec<String>>(summary) {
+                return (status, summary);
+            }
+        }
+    }
+    (status, Vec::new())
+}
+
 /// Task State
-#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum TaskState {
     /// The Task ended with an undefined state
     Unknown { endtime: i64 },
@@ -715,6 +1542,20 @@ pub struct TaskListInfo {
     pub upid_str: String,
     /// Task `(endtime, status)` if already finished
     pub state: Option<TaskState>, // endtime, status
+    /// Whether the task waits for a free slot because of its worker type's concurrency limit
+    pub queued: bool,
+    /// Summary attached with [`WorkerTask::final_summary`], empty if there is none
+    pub summary: Vec<String>,
+    /// The resources used by the task, if resource accounting was enabled, see
+    /// [`set_task_resource_accounting`]
+    pub resource_usage: Option<TaskResourceUsage>,
+}
+
+impl TaskListInfo {
+    /// The registered metadata of the task's worker type, e.g. to show a label.
+    pub fn task_type(&self) -> Option<TaskTypeInfo> {
+        lookup_task_type(&self.upid.worker_type)
+    }
 }
 
 fn render_task_line(info: &TaskListInfo) -> String {
@@ -722,9 +1563,25 @@ fn render_task_line(info: &TaskListInfo) -> String {
     if let Some(status) = &info.state {
         use std::fmt::Write as _;
 
-        let _ = writeln!(raw, "{} {:08X} {}", info.upid_str, status.endtime(), status);
+        // tabs separate the fields, see split_task_summary and split_task_resource_usage
+        let text = status.to_string().replace('\t', " ");
+        let _ = write!(raw, "{} {:08X} {text}", info.upid_str, status.endtime());
+        if !info.summary.is_empty() {
+            if let Ok(summary) = serde_json::to_string(&info.summary) {
+                let _ = write!(raw, "\t{TASK_LIST_SUMMARY_FIELD}{summary}");
+            }
+        }
+        if let Some(usage) = &info.resource_usage {
+            if let Ok(usage) = serde_json::to_string(usage) {
+                let _ = write!(raw, "\t{TASK_LIST_RESOURCES_FIELD}{usage}");
+            }
+        }
+        raw.push('\n');
     } else {
         raw.push_str(&info.upid_str);
+        if info.queued {
+            raw.push_str(" queued");
+        }
         raw.push('\n');
     }
 
@@ -747,11 +1604,21 @@ fn read_task_file<R: Read>(reader: R) -> Result<Vec<TaskListInfo>, Error> {
     for line in reader.lines() {
         let line = line?;
         match parse_worker_status_line(&line) {
-            Ok((upid_str, upid, state)) => list.push(TaskListInfo {
-                upid_str,
-                upid,
-                state,
-            }),
+            Ok((upid_str, upid, result)) => {
+                let queued = result.is_none() && line.ends_with(" queued");
+                let (state, summary, resource_usage) = match result {
+                    Some(result) => (Some(result.state), result.summary, result.resource_usage),
+                    None => (None, Vec::new(), None),
+                };
+                list.push(TaskListInfo {
+                    upid_str,
+                    upid,
+                    state,
+                    queued,
+                    summary,
+                    resource_usage,
+                })
+            }
             Err(err) => {
                 log::warn!("unable to parse worker status '{}' - {}", line, err);
                 continue;
@@ -869,8 +1736,17 @@ pub struct WorkerTask {
     upid: UPID,
     data: Mutex<WorkerTaskData>,
     abort_requested: AtomicBool,
+    warnings: AtomicU64,
 }
 
+/// Prefix of the task log lines holding the summary, directly before the final state line.
+const TASK_SUMMARY_PREFIX: &str = "TASK SUMMARY: ";
+/// Prefix of the task log line holding the resource usage, directly before the summary.
+const TASK_RESOURCES_PREFIX: &str = "TASK RESOURCES: ";
+/// Summaries are read from the tail of the task log, so keep them short.
+const TASK_SUMMARY_MAX_LINES: usize = 16;
+const TASK_SUMMARY_MAX_LINE_LEN: usize = 256;
+
 impl std::fmt::Display for WorkerTask {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         self.upid.fmt(f)
@@ -880,9 +1756,134 @@ impl std::fmt::Display for WorkerTask {
 struct WorkerTaskData {
     progress: f64, // 0..1
     pub abort_listeners: Vec<oneshot::Sender<()>>,
+    /// waiting for a free slot of its concurrency limit
+    queued: bool,
+    /// the concurrency limit slot held while running
+    slot: Option<String>,
+    /// summary logged with the final state
+    summary: Vec<String>,
+    /// resources used so far, if resource accounting is enabled
+    resource_usage: Option<TaskResourceUsage>,
+}
+
+type StartWorkerFn = Box<dyn FnOnce(Arc<WorkerTask>, FileLogger) + Send>;
+
+/// A task waiting for a free slot of its concurrency limit.
+struct QueuedTask {
+    key: String,
+    worker: Arc<WorkerTask>,
+    logger: FileLogger,
+    start: StartWorkerFn,
+}
+
+/// Concurrency limit accounting, running tasks per slot key and the queued tasks in order.
+#[derive(Default)]
+struct TaskSlots {
+    running: HashMap<String, usize>,
+    queue: VecDeque<QueuedTask>,
+}
+
+static TASK_SLOTS: LazyLock<Mutex<TaskSlots>> = LazyLock::new(Default::default);
+
+/// How often finishing a task tries to update the active task list.
+const ACTIVE_WORKERS_UPDATE_ATTEMPTS: usize = 3;
+
+enum SlotReservation {
+    Unlimited,
+    Acquired(String),
+    Queue(String, ConcurrencyLimit),
+}
+
+fn slot_key(worker_type: &str, worker_id: Option<&str>, limit: &ConcurrencyLimit) -> String {
+    match worker_id {
+        Some(id) if limit.per_worker_id => format!("{worker_type}:{id}"),
+        _ => worker_type.to_string(),
+    }
+}
+
+/// Reserve a slot for a new task, fails if the limit is reached and the task must not be queued.
+fn reserve_task_slot(worker_type: &str, worker_id: Option<&str>) -> Result<SlotReservation, Error> {
+    let limit = match proxmox_worker_task::concurrency_limit(worker_type) {
+        Some(limit) => limit,
+        None => return Ok(SlotReservation::Unlimited),
+    };
+    let key = slot_key(worker_type, worker_id, &limit);
+
+    let mut slots = TASK_SLOTS.lock().unwrap();
+    let running = slots.running.entry(key.clone()).or_default();
+    if *running < limit.max_tasks {
+        *running += 1;
+        return Ok(SlotReservation::Acquired(key));
+    }
+
+    match limit.policy {
+        QueuePolicy::Queue => Ok(SlotReservation::Queue(key, limit)),
+        QueuePolicy::Fail => bail!(
+            "too many running '{key}' tasks (limit is {})",
+            limit.max_tasks
+        ),
+    }
+}
+
+/// Release a slot of a finished task and hand it over to the next queued task, if any.
+fn release_task_slot(key: String) {
+    let next = {
+        let mut slots = TASK_SLOTS.lock().unwrap();
+        match slots.queue.iter().position(|task| task.key == key) {
+            Some(pos) => slots.queue.remove(pos),
+            None => {
+                if let Some(running) = slots.running.get_mut(&key) {
+                    *running = running.saturating_sub(1);
+                }
+                None
+            }
+        }
+    };
+
+    if let Some(task) = next {
+        {
+            let mut data = task.worker.data.lock().unwrap();
+            data.queued = false;
+            data.slot = Some(key);
+        }
+        let _ = task.worker.setup.update_active_workers(None);
+        (task.start)(task.worker, task.logger);
+    }
+}
+
+/// Check the worker type of a task started by an API call and get the user to run it as.
+fn api_task_auth_id(
+    worker_type: &str,
+    worker_id: Option<&str>,
+    rpcenv: &dyn RpcEnvironment,
+) -> Result<String, Error> {
+    check_task_type(worker_type, worker_id)?;
+    rpcenv
+        .get_auth_id()
+        .ok_or_else(|| format_err!("no authid available to start '{worker_type}' task"))
+}
+
+fn api_task_response(upid: String, rpcenv: &mut dyn RpcEnvironment) -> Value {
+    rpcenv["task"] = Value::String(upid.clone());
+    Value::String(upid)
+}
+
+/// Remove a task from the queue, returns `None` if it is not queued.
+fn dequeue_task(upid: &UPID) -> Option<QueuedTask> {
+    let mut slots = TASK_SLOTS.lock().unwrap();
+    let pos = slots
+        .queue
+        .iter()
+        .position(|task| task.worker.upid.task_id == upid.task_id)?;
+    let task = slots.queue.remove(pos)?;
+    task.worker.data.lock().unwrap().queued = false;
+    Some(task)
 }
 
 impl WorkerTask {
+    /// Create a new worker task and register it as running.
+    ///
+    /// Fails if the limit set with [`set_max_worker_tasks`] is reached.
     pub fn new(
         worker_type: &str,
         worker_id: Option<String>,
@@ -891,44 +1892,153 @@ impl WorkerTask {
     ) -> Result<(Arc<Self>, FileLogger), Error> {
         let setup = worker_task_setup()?;
 
+        if let Err(err) = check_task_type(worker_type, worker_id.as_deref()) {
+            if STRICT_TASK_TYPES.load(Ordering::Acquire) {
+                return Err(err);
+            }
+            warn!("{err}");
+        }
+
+        Self::create(setup, worker_type, worker_id, auth_id, to_stdout)
+    }
+
+    fn create(
+        setup: &'static WorkerTaskSetup,
+        worker_type: &str,
+        worker_id: Option<String>,
+        auth_id: String,
+        to_stdout: bool,
+    ) -> Result<(Arc<Self>, FileLogger), Error> {
         let upid = UPID::new(worker_type, worker_id, auth_id)?;
         let task_id = upid.task_id;
 
         let path = setup.create_and_get_log_path(&upid)?;
-
-        let logger_options = FileLogOptions {
-            to_stdout,
-            exclusive: true,
-            prefix_time: true,
-            read: true,
-            file_opts: setup.file_opts.clone(),
-            ..Default::default()
-        };
-        let logger = FileLogger::new(path, logger_options)?;
+        let logger = Self::create_logger(setup, &upid, path.clone(), to_stdout)?;
 
         let worker = Arc::new(Self {
             setup,
             upid: upid.clone(),
             abort_requested: AtomicBool::new(false),
+            warnings: AtomicU64::new(0),
             data: Mutex::new(WorkerTaskData {
                 progress: 0.0,
                 abort_listeners: vec![],
+                queued: false,
+                slot: None,
+                summary: Vec::new(),
+                resource_usage: RESOURCE_ACCOUNTING
+                    .load(Ordering::Acquire)
+                    .then(TaskResourceUsage::default),
             }),
         });
 
-        // scope to drop the lock again after inserting
+        // the list holds all existing tasks, so checking and inserting under its lock keeps the
+        // limit exact
         {
             let mut hash = WORKER_TASK_LIST.lock().unwrap();
+            if let Err(err) = check_task_limit(worker_type, hash.len()) {
+                // the task was never listed, so don't keep its log around either
+                let _ = std::fs::remove_file(&path);
+                return Err(err);
+            }
             hash.insert(task_id, worker.clone());
             set_worker_count(hash.len());
         }
 
-        setup.update_active_workers(Some(&upid))?;
+        if let Err(err) = setup.update_active_workers(Some(&upid)) {
+            let mut hash = WORKER_TASK_LIST.lock().unwrap();
+            hash.remove(&task_id);
+            set_worker_count(hash.len());
+            return Err(err);
+        }
 
         Ok((worker, logger))
     }
 
+    fn create_logger(
+        setup: &WorkerTaskSetup,
+        upid: &UPID,
+        path: PathBuf,
+        to_stdout: bool,
+    ) -> Result<FileLogger, Error> {
+        let logger_options = FileLogOptions {
+            to_stdout,
+            exclusive: true,
+            prefix_time: true,
+            read: true,
+            file_opts: setup.log_file_opts.clone(),
+            ..Default::default()
+        };
+        let mut logger = FileLogger::new(path, logger_options)?;
+        if let Some(mirror) = crate::task_journal::task_journal_mirror(upid) {
+            logger.set_mirror(mirror);
+        }
+
+        Ok(logger)
+    }
+
+    /// Create a new worker task, honoring the concurrency limit of its worker type.
+    ///
+    /// The task is started by calling `start`, either directly or once it leaves the queue.
+    fn new_limited(
+        worker_type: &str,
+        worker_id: Option<String>,
+        auth_id: String,
+        to_stdout: bool,
+        start: StartWorkerFn,
+    ) -> Result<String, Error> {
+        let reservation = reserve_task_slot(worker_type, worker_id.as_deref())?;
+
+        let (worker, mut logger) = match WorkerTask::new(worker_type, worker_id, auth_id, to_stdout)
+        {
+            Ok(res) => res,
+            Err(err) => {
+                if let SlotReservation::Acquired(key) = reservation {
+                    release_task_slot(key);
+                }
+                return Err(err);
+            }
+        };
+        let upid_str = worker.upid.to_string();
+
+        let key = match reservation {
+            SlotReservation::Unlimited => {
+                start(worker, logger);
+                return Ok(upid_str);
+            }
+            SlotReservation::Acquired(key) => key,
+            SlotReservation::Queue(key, limit) => {
+                let mut slots = TASK_SLOTS.lock().unwrap();
+                let running = slots.running.entry(key.clone()).or_default();
+                if *running < limit.max_tasks {
+                    // a slot got freed in the meantime
+                    *running += 1;
+                    key
+                } else {
+                    logger.log(format!("task queued, waiting for a free '{key}' slot"));
+                    worker.data.lock().unwrap().queued = true;
+                    slots.queue.push_back(QueuedTask {
+                        key,
+                        worker: Arc::clone(&worker),
+                        logger,
+                        start,
+                    });
+                    drop(slots);
+                    worker.setup.update_active_workers(None)?;
+                    return Ok(upid_str);
+                }
+            }
+        };
+
+        worker.data.lock().unwrap().slot = Some(key);
+        start(worker, logger);
+        Ok(upid_str)
+    }
+
     /// Spawn a new tokio task/future.
+    ///
+    /// If the worker type has a [`ConcurrencyLimit`] set, the task may get queued until a slot
+    /// is free, or fail to be created, depending on the limit's [`QueuePolicy`].
     pub fn spawn<F, T>(
         worker_type: &str,
         worker_id: Option<String>,
@@ -940,19 +2050,37 @@ impl WorkerTask {
         F: Send + 'static + FnOnce(Arc<WorkerTask>) -> T,
         T: Send + 'static + Future<Output = Result<(), Error>>,
     {
-        let (worker, logger) = WorkerTask::new(worker_type, worker_id, auth_id, to_stdout)?;
-        let upid_str = worker.upid.to_string();
-        let f = f(worker.clone());
-
-        tokio::spawn(LogContext::new(logger).scope(async move {
-            let result = f.await;
-            worker.log_result(&result);
-        }));
+        let runtime = tokio::runtime::Handle::current();
+        let start = move |worker: Arc<WorkerTask>, logger: FileLogger| {
+            let f = f(worker.clone());
+
+            runtime.spawn(LogContext::new(logger).scope(async move {
+                let start = Instant::now();
+                let mut poll_time = Duration::ZERO;
+
+                let mut f = std::pin::pin!(f);
+                let result = std::future::poll_fn(|cx| {
+                    let poll_start = Instant::now();
+                    let res = f.as_mut().poll(cx);
+                    poll_time += poll_start.elapsed();
+                    res
+                })
+                .await;
+
+                worker.account(|usage| {
+                    usage.wall_time_ms = start.elapsed().as_millis() as u64;
+                    usage.poll_time_ms = Some(poll_time.as_millis() as u64);
+                });
+                worker.log_result(&result);
+            }));
+        };
 
-        Ok(upid_str)
+        WorkerTask::new_limited(worker_type, worker_id, auth_id, to_stdout, Box::new(start))
     }
 
     /// Create a new worker thread.
+    ///
+    /// Concurrency limits are handled like for [`spawn`](WorkerTask::spawn).
     pub fn new_thread<F>(
         worker_type: &str,
         worker_id: Option<String>,
@@ -963,36 +2091,95 @@ impl WorkerTask {
     where
         F: Send + UnwindSafe + 'static + FnOnce(Arc<WorkerTask>) -> Result<(), Error>,
     {
-        let (worker, logger) = WorkerTask::new(worker_type, worker_id, auth_id, to_stdout)?;
-        let upid_str = worker.upid.to_string();
+        let start = move |worker: Arc<WorkerTask>, logger: FileLogger| {
+            let _child = std::thread::Builder::new()
+                .name(worker.upid.to_string())
+                .spawn(move || {
+                    LogContext::new(logger).sync_scope(|| {
+                        if let Some(cpus) = worker_thread_affinity(&worker.upid.worker_type) {
+                            if let Err(err) = process::set_affinity(Pid::from_raw(0), &cpus) {
+                                worker.log_warning(err.to_string());
+                            }
+                        }
 
-        let _child = std::thread::Builder::new()
-            .name(upid_str.clone())
-            .spawn(move || {
-                LogContext::new(logger).sync_scope(|| {
-                    let worker1 = worker.clone();
-
-                    let result = match std::panic::catch_unwind(move || f(worker1)) {
-                        Ok(r) => r,
-                        Err(panic) => match panic.downcast::<&str>() {
-                            Ok(panic_msg) => Err(format_err!("worker panicked: {}", panic_msg)),
-                            Err(_) => Err(format_err!("worker panicked: unknown type.")),
-                        },
-                    };
+                        let worker1 = worker.clone();
 
-                    worker.log_result(&result);
+                        let start = Instant::now();
+                        let start_usage = thread_resource_usage();
+
+                        let result = match std::panic::catch_unwind(move || f(worker1)) {
+                            Ok(r) => r,
+                            Err(panic) => match panic.downcast::<&str>() {
+                                Ok(panic_msg) => Err(format_err!("worker panicked: {}", panic_msg)),
+                                Err(_) => Err(format_err!("worker panicked: unknown type.")),
+                            },
+                        };
+
+                        let end_usage = thread_resource_usage();
+                        worker.account(|usage| {
+                            usage.wall_time_ms = start.elapsed().as_millis() as u64;
+                            if let (Some(start), Some(end)) = (start_usage, end_usage) {
+                                usage.add(&ResourceUsage {
+                                    user_time: end.user_time.saturating_sub(start.user_time),
+                                    system_time: end.system_time.saturating_sub(start.system_time),
+                                    max_rss_kib: end.max_rss_kib,
+                                });
+                            }
+                        });
+                        worker.log_result(&result);
+                    });
                 });
-            });
+        };
 
-        Ok(upid_str)
+        WorkerTask::new_limited(worker_type, worker_id, auth_id, to_stdout, Box::new(start))
+    }
+
+    /// Spawn a new tokio task/future for an API call, see [`spawn`](WorkerTask::spawn).
+    ///
+    /// The task runs as the authenticated user of `rpcenv`. Its UPID is stored in the `task`
+    /// result attribute of `rpcenv`, so output formatters and hooks can refer to it, and returned
+    /// as the response of the call.
+    ///
+    /// Contrary to [`spawn`](WorkerTask::spawn), the worker type and ID are always checked if
+    /// worker types are registered, see [`check_task_type`].
+    ///
+    /// The task is registered before this returns, so it is not affected by the client
+    /// disconnecting afterwards, see [`ClientDisconnect`](crate::ClientDisconnect).
+    pub fn spawn_api<F, T>(
+        worker_type: &str,
+        worker_id: Option<String>,
+        rpcenv: &mut dyn RpcEnvironment,
+        to_stdout: bool,
+        f: F,
+    ) -> Result<Value, Error>
+    where
+        F: Send + 'static + FnOnce(Arc<WorkerTask>) -> T,
+        T: Send + 'static + Future<Output = Result<(), Error>>,
+    {
+        let auth_id = api_task_auth_id(worker_type, worker_id.as_deref(), rpcenv)?;
+        let upid = WorkerTask::spawn(worker_type, worker_id, auth_id, to_stdout, f)?;
+        Ok(api_task_response(upid, rpcenv))
+    }
+
+    /// Create a new worker thread for an API call, see [`spawn_api`](WorkerTask::spawn_api).
+    pub fn new_thread_api<F>(
+        worker_type: &str,
+        worker_id: Option<String>,
+        rpcenv: &mut dyn RpcEnvironment,
+        to_stdout: bool,
+        f: F,
+    ) -> Result<Value, Error>
+    where
+        F: Send + UnwindSafe + 'static + FnOnce(Arc<WorkerTask>) -> Result<(), Error>,
+    {
+        let auth_id = api_task_auth_id(worker_type, worker_id.as_deref(), rpcenv)?;
+        let upid = WorkerTask::new_thread(worker_type, worker_id, auth_id, to_stdout, f)?;
+        Ok(api_task_response(upid, rpcenv))
     }
 
     /// create state from self and a result
     pub fn create_state(&self, result: &Result<(), Error>) -> TaskState {
-        let warn_count = match LogContext::current() {
-            Some(ctx) => ctx.state().lock().unwrap().warn_count,
-            None => 0,
-        };
+        let warn_count = self.warning_count();
 
         let endtime = proxmox_time::epoch_i64();
 
@@ -1012,13 +2199,47 @@ impl WorkerTask {
     }
 
     /// Log task result, remove task from running list
+    ///
+    /// This frees the task's concurrency limit slot and starts the next queued task, if any.
     pub fn log_result(&self, result: &Result<(), Error>) {
         let state = self.create_state(result);
+        let (summary, resource_usage) = {
+            let mut data = self.data.lock().unwrap();
+            (
+                std::mem::take(&mut data.summary),
+                data.resource_usage.take(),
+            )
+        };
+        if let Some(usage) = resource_usage.and_then(|usage| serde_json::to_string(&usage).ok()) {
+            self.log_message(format!("{TASK_RESOURCES_PREFIX}{usage}"));
+        }
+        for line in summary {
+            self.log_message(format!("{TASK_SUMMARY_PREFIX}{line}"));
+        }
         self.log_message(state.result_text());
 
-        WORKER_TASK_LIST.lock().unwrap().remove(&self.upid.task_id);
-        let _ = self.setup.update_active_workers(None);
-        set_worker_count(WORKER_TASK_LIST.lock().unwrap().len());
+        {
+            let mut hash = WORKER_TASK_LIST.lock().unwrap();
+            hash.remove(&self.upid.task_id);
+            set_worker_count(hash.len());
+        }
+
+        // a failed update is only retried, the slot is freed either way so that queued tasks don't
+        // wait for it, and the next update of the list drops this task anyway
+        for attempt in 1..=ACTIVE_WORKERS_UPDATE_ATTEMPTS {
+            match self.setup.update_active_workers(None) {
+                Ok(()) => break,
+                Err(err) if attempt < ACTIVE_WORKERS_UPDATE_ATTEMPTS => {
+                    warn!("unable to update the active task list, retrying - {err}");
+                }
+                Err(err) => error!("unable to update the active task list - {err}"),
+            }
+        }
+
+        let slot = self.data.lock().unwrap().slot.take();
+        if let Some(key) = slot {
+            release_task_slot(key);
+        }
     }
 
     /// Log a message.
@@ -1026,6 +2247,59 @@ impl WorkerTask {
         info!("{}", msg.as_ref());
     }
 
+    /// Log a warning, the task finishes with [`TaskState::Warning`] unless it fails.
+    pub fn log_warning<S: AsRef<str>>(&self, msg: S) {
+        // within the task's log context, the task log layer counts the warning
+        if LogContext::current().is_none() {
+            self.warnings.fetch_add(1, Ordering::SeqCst);
+        }
+        warn!("{}", msg.as_ref());
+    }
+
+    /// Number of warnings so far.
+    ///
+    /// This includes warnings logged with [`log_warning`](Self::log_warning) as well as
+    /// warnings and errors logged to the task log via `tracing`.
+    pub fn warning_count(&self) -> u64 {
+        let logged = match LogContext::current() {
+            Some(ctx) => ctx.state().lock().unwrap().warn_count,
+            None => 0,
+        };
+        self.warnings.load(Ordering::SeqCst) + logged
+    }
+
+    /// Attach a short summary, e.g. the amount of transferred data, to the task result.
+    ///
+    /// The summary is logged right before the final state and can be read with
+    /// [`upid_read_summary`] or from [`TaskListInfo::summary`] without reading the whole log.
+    /// Up to 16 lines are kept, each shortened to 256 bytes with control characters replaced by
+    /// spaces. Calling this again replaces the previous summary.
+    pub fn final_summary(&self, lines: Vec<String>) {
+        let summary = lines
+            .into_iter()
+            .take(TASK_SUMMARY_MAX_LINES)
+            .map(|line| {
+                let mut line = line.replace(char::is_control, " ");
+                if line.len() > TASK_SUMMARY_MAX_LINE_LEN {
+                    let mut end = TASK_SUMMARY_MAX_LINE_LEN;
+                    while !line.is_char_boundary(end) {
+                        end -= 1;
+                    }
+                    line.truncate(end);
+                }
+                line
+            })
+            .collect();
+        self.data.lock().unwrap().summary = summary;
+    }
+
+    /// Update the resource usage, if resource accounting is enabled.
+    fn account<F: FnOnce(&mut TaskResourceUsage)>(&self, f: F) {
+        if let Some(usage) = self.data.lock().unwrap().resource_usage.as_mut() {
+            f(usage);
+        }
+    }
+
     /// Set progress indicator
     pub fn progress(&self, progress: f64) {
         if (0.0..=1.0).contains(&progress) {
@@ -1037,6 +2311,8 @@ impl WorkerTask {
     }
 
     /// Request abort
+    ///
+    /// Queued tasks are removed from the queue and finish with an error without being started.
     pub fn request_abort(&self) {
         let prev_abort = self.abort_requested.swap(true, Ordering::SeqCst);
         if !prev_abort {
@@ -1054,6 +2330,14 @@ impl WorkerTask {
                 }
             }
         }
+        drop(data);
+
+        if let Some(task) = dequeue_task(&self.upid) {
+            LogContext::new(task.logger).sync_scope(|| {
+                task.worker
+                    .log_result(&Err(format_err!("task aborted before it was started")));
+            });
+        }
     }
 
     /// Get a future which resolves on task abort
@@ -1086,6 +2370,10 @@ impl WorkerTaskContext for WorkerTask {
     fn fail_on_shutdown(&self) -> Result<(), Error> {
         proxmox_daemon::fail_on_shutdown()
     }
+
+    fn add_resource_usage(&self, usage: &ResourceUsage) {
+        self.account(|task_usage| task_usage.add(usage));
+    }
 }
 
 /// Wait for a locally spanned worker task
@@ -1109,7 +2397,9 @@ pub async fn wait_for_local_worker(upid_str: &str) -> Result<(), Error> {
 
 /// Request abort of a local worker (if existing and running)
 pub fn abort_local_worker(upid: UPID) {
-    if let Some(worker) = WORKER_TASK_LIST.lock().unwrap().get(&upid.task_id) {
+    // do not hold the list lock here, aborting a queued task removes it from the list
+    let worker = WORKER_TASK_LIST.lock().unwrap().get(&upid.task_id).cloned();
+    if let Some(worker) = worker {
         worker.request_abort();
     }
 }
@@ -1135,3 +2425,482 @@ pub async fn handle_worker(upid_str: &str) -> Result<(), Error> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use std::io::{BufReader, Cursor, Read, Seek, SeekFrom};
+    use std::sync::mpsc;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use anyhow::Error;
+
+    use proxmox_schema::upid::UPID;
+    use proxmox_sys::fs::{replace_file, CreateOptions};
+    use proxmox_worker_task::{set_concurrency_limit, ConcurrencyLimit, QueuePolicy};
+
+    use super::*;
+
+    /// Counts the bytes read from the inner reader.
+    struct CountingReader<R> {
+        inner: R,
+        count: u64,
+    }
+
+    impl<R: Read> Read for CountingReader<R> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let len = self.inner.read(buf)?;
+            self.count += len as u64;
+            Ok(len)
+        }
+    }
+
+    impl<R: Seek> Seek for CountingReader<R> {
+        fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    fn log_line(nr: u64) -> String {
+        format!("2024-01-01T00:00:00+00:00: line {nr} with some padding to look like a log\n")
+    }
+
+    fn reader(data: &[u8]) -> BufReader<CountingReader<Cursor<&[u8]>>> {
+        BufReader::new(CountingReader {
+            inner: Cursor::new(data),
+            count: 0,
+        })
+    }
+
+    #[test]
+    fn test_task_log_chunks() {
+        let data = b"first\nsecond\nthird";
+        let mut index = TaskLogIndex::new((0, 0));
+
+        let chunk = index.read(&mut reader(data), 0, 10, false).unwrap();
+        assert_eq!(chunk.lines, ["first", "second"]);
+        assert_eq!(chunk.total, 2);
+
+        let chunk = index.read(&mut reader(data), 1, 10, true).unwrap();
+        assert_eq!(chunk.lines, ["second", "third"]);
+        assert_eq!(chunk.total, 3);
+
+        let chunk = index.read(&mut reader(data), 3, 10, true).unwrap();
+        assert!(chunk.lines.is_empty());
+        assert_eq!(chunk.start, 3);
+        assert_eq!(chunk.total, 3);
+
+        let mut index = TaskLogIndex::new((0, 0));
+        let chunk = index.read(&mut reader(b""), 0, 10, true).unwrap();
+        assert!(chunk.lines.is_empty());
+        assert_eq!(chunk.total, 0);
+    }
+
+    #[test]
+    fn test_task_log_repeated_reads() {
+        let line_count = 50_000;
+        let mut data = Vec::new();
+        for nr in 0..line_count {
+            data.extend_from_slice(log_line(nr).as_bytes());
+        }
+        let size = data.len() as u64;
+        assert!(size > 2 * 1024 * 1024);
+
+        let mut index = TaskLogIndex::new((0, 0));
+
+        // the first read has to index the whole log
+        let mut first = reader(&data);
+        let chunk = index.read(&mut first, 40_000, 10, false).unwrap();
+        assert_eq!(chunk.total, line_count);
+        assert_eq!(chunk.lines.len(), 10);
+        assert_eq!(chunk.lines[0], log_line(40_000).trim_end());
+        assert!(first.get_ref().count >= size);
+
+        // reading again only touches the requested range
+        let max_read = (TASK_LOG_INDEX_STRIDE + 100) * log_line(line_count).len() as u64 + 16384;
+        for start in [0, 12_345, 40_000, 49_990] {
+            let mut again = reader(&data);
+            let chunk = index.read(&mut again, start, 10, false).unwrap();
+            assert_eq!(chunk.total, line_count);
+            assert_eq!(chunk.lines[0], log_line(start).trim_end());
+            assert!(again.get_ref().count < max_read);
+        }
+
+        // polling a growing log only scans the appended data
+        for nr in line_count..line_count + 100 {
+            data.extend_from_slice(log_line(nr).as_bytes());
+        }
+        data.extend_from_slice(b"incomplete");
+        let mut poll = reader(&data);
+        let chunk = index.read(&mut poll, line_count, 1000, false).unwrap();
+        assert_eq!(chunk.total, line_count + 100);
+        assert_eq!(chunk.lines.len(), 100);
+        assert_eq!(chunk.lines[99], log_line(line_count + 99).trim_end());
+        assert!(poll.get_ref().count < max_read);
+
+        let chunk = index
+            .read(&mut reader(&data), line_count + 100, 1000, true)
+            .unwrap();
+        assert_eq!(chunk.total, line_count + 101);
+        assert_eq!(chunk.lines, ["incomplete"]);
+    }
+
+    fn wait_for(what: &str, check: impl Fn() -> bool) {
+        for _ in 0..500 {
+            if check() {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        panic!("timeout waiting for {what}");
+    }
+
+    fn task_list_entry(upid_str: &str) -> TaskListInfo {
+        TaskListInfoIterator::new(false)
+            .unwrap()
+            .map(Result::unwrap)
+            .find(|info| info.upid_str == upid_str)
+            .unwrap_or_else(|| panic!("task {upid_str} not in task list"))
+    }
+
+    /// Start a thread worker which records its start and then waits to be released.
+    fn spawn_gated(
+        worker_type: &str,
+        worker_id: Option<&str>,
+        nr: usize,
+        started: &Arc<Mutex<Vec<usize>>>,
+    ) -> Result<(UPID, mpsc::Sender<()>), Error> {
+        let (release, gate) = mpsc::channel::<()>();
+        let gate = Mutex::new(gate);
+        let started = Arc::clone(started);
+        let upid = WorkerTask::new_thread(
+            worker_type,
+            worker_id.map(str::to_string),
+            "root@pam".to_string(),
+            false,
+            move |_worker| {
+                started.lock().unwrap().push(nr);
+                let _ = gate.lock().unwrap().recv();
+                Ok(())
+            },
+        )?;
+        Ok((upid.parse()?, release))
+    }
+
+    #[test]
+    fn test_concurrency_limits() -> Result<(), Error> {
+        // task results are logged via the task's log context
+        proxmox_log::init_cli_logger("PROXMOX_DEBUG", proxmox_log::LevelFilter::INFO)?;
+
+        let runtime = tokio::runtime::Runtime::new()?;
+        let _guard = runtime.enter();
+
+        let basedir = std::env::temp_dir().join(format!(
+            "proxmox-rest-server-worker-test-{}",
+            std::process::id()
+        ));
+        init_worker_tasks(basedir.clone(), CreateOptions::new())?;
+
+        // queued tasks start in order once a slot is free
+        set_concurrency_limit(
+            "test-queue",
+            Some(ConcurrencyLimit::new(1, QueuePolicy::Queue)),
+        );
+        let started = Arc::new(Mutex::new(Vec::new()));
+        let mut tasks = Vec::new();
+        for nr in 0..4 {
+            tasks.push(spawn_gated("test-queue", None, nr, &started)?);
+        }
+
+        wait_for("first task", || *started.lock().unwrap() == [0]);
+        assert!(!worker_is_queued_local(&tasks[0].0));
+        for (upid, _) in &tasks[1..] {
+            assert!(worker_is_queued_local(upid));
+            assert!(worker_is_active_local(upid));
+            assert!(task_list_entry(&upid.to_string()).queued);
+        }
+        assert!(!task_list_entry(&tasks[0].0.to_string()).queued);
+
+        // aborting a queued task finishes it without ever starting it
+        abort_local_worker(tasks[2].0.clone());
+        assert!(!worker_is_active_local(&tasks[2].0));
+        assert_eq!(
+            upid_read_status(&tasks[2].0)?,
+            TaskState::Error {
+                message: "task aborted before it was started".to_string(),
+                endtime: upid_read_status(&tasks[2].0)?.endtime(),
+            },
+        );
+
+        tasks[0].1.send(())?;
+        wait_for("second task", || *started.lock().unwrap() == [0, 1]);
+        assert!(!worker_is_queued_local(&tasks[1].0));
+        assert!(!task_list_entry(&tasks[1].0.to_string()).queued);
+        assert!(worker_is_queued_local(&tasks[3].0));
+
+        tasks[1].1.send(())?;
+        wait_for("fourth task", || *started.lock().unwrap() == [0, 1, 3]);
+        tasks[3].1.send(())?;
+        wait_for("all tasks", || {
+            tasks.iter().all(|(upid, _)| !worker_is_active_local(upid))
+        });
+        assert_eq!(
+            upid_read_status(&tasks[3].0)?,
+            TaskState::OK {
+                endtime: upid_read_status(&tasks[3].0)?.endtime(),
+            }
+        );
+
+        // fail fast, with limits per worker ID
+        set_concurrency_limit(
+            "test-fail",
+            Some(ConcurrencyLimit::new(1, QueuePolicy::Fail).per_worker_id(true)),
+        );
+        let started = Arc::new(Mutex::new(Vec::new()));
+        let (first, release_first) = spawn_gated("test-fail", Some("a"), 0, &started)?;
+        let (other, release_other) = spawn_gated("test-fail", Some("b"), 1, &started)?;
+        assert!(spawn_gated("test-fail", Some("a"), 2, &started).is_err());
+        release_first.send(())?;
+        release_other.send(())?;
+        wait_for("fail fast tasks", || {
+            !worker_is_active_local(&first) && !worker_is_active_local(&other)
+        });
+        let (again, release_again) = spawn_gated("test-fail", Some("a"), 3, &started)?;
+        release_again.send(())?;
+        wait_for("restarted task", || !worker_is_active_local(&again));
+        let mut started = started.lock().unwrap().clone();
+        started.sort();
+        assert_eq!(started, [0, 1, 3]);
+
+        // warnings and the summary end up in the task log and the task list
+        let upid: UPID = WorkerTask::new_thread(
+            "test-summary",
+            None,
+            "root@pam".to_string(),
+            false,
+            |worker| {
+                worker.log_warning("warning from the task");
+                warn!("warning via tracing");
+                assert_eq!(worker.warning_count(), 2);
+                worker.final_summary(vec!["copied 3 files".to_string(), "a\tb".to_string()]);
+                Ok(())
+            },
+        )?
+        .parse()?;
+        wait_for("summary task", || !worker_is_active_local(&upid));
+        let state = upid_read_status(&upid)?;
+        assert!(matches!(state, TaskState::Warning { count: 2, .. }));
+        assert_eq!(upid_read_summary(&upid)?, ["copied 3 files", "a b"]);
+        let entry = task_list_entry(&upid.to_string());
+        assert_eq!(entry.state, Some(state));
+        assert_eq!(entry.summary, ["copied 3 files", "a b"]);
+
+        // thread based tasks of a worker type can be pinned to CPUs
+        let allowed = process::read_allowed_cpus()?;
+        let mut pinned = CpuSet::new();
+        pinned.insert(allowed.iter().last().unwrap())?;
+        set_worker_thread_affinity("test-affinity", Some(pinned.clone()));
+        let affinity = Arc::new(Mutex::new(None));
+        let task_affinity = Arc::clone(&affinity);
+        let upid: UPID = WorkerTask::new_thread(
+            "test-affinity",
+            None,
+            "root@pam".to_string(),
+            false,
+            move |_worker| {
+                *task_affinity.lock().unwrap() = Some(process::get_affinity(Pid::from_raw(0))?);
+                Ok(())
+            },
+        )?
+        .parse()?;
+        wait_for("pinned task", || !worker_is_active_local(&upid));
+        assert_eq!(affinity.lock().unwrap().as_ref(), Some(&pinned));
+        assert_eq!(process::get_affinity(Pid::from_raw(0))?, allowed);
+        set_worker_thread_affinity("test-affinity", None);
+
+        // a queued task of a stopped daemon is marked as failed
+        let setup = worker_task_setup()?;
+        let mut stale = UPID::new("test-queue", None, "root@pam".to_string())?;
+        stale.pid = i32::MAX;
+        setup.create_and_get_log_path(&stale)?;
+        replace_file(
+            &setup.active_tasks_fn,
+            format!("{stale} queued\n").as_bytes(),
+            CreateOptions::new(),
+            false,
+        )?;
+        let entry = task_list_entry(&stale.to_string());
+        assert!(!entry.queued);
+        assert!(matches!(entry.state, Some(TaskState::Error { .. })));
+        assert!(matches!(
+            upid_read_status(&stale)?,
+            TaskState::Error { message, .. } if message.contains("still queued"),
+        ));
+
+        let _ = std::fs::remove_dir_all(basedir);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_mixed_task_archive() -> Result<(), Error> {
+        let upid = |nr: u32| {
+            format!("UPID:node:00001234:00005678:0000000{nr}:65A0B0C0:backup:store:root@pam:")
+        };
+        let archive = format!(
+            "{} 65A0B0D0 OK\n\
+            {} 65A0B0D1 WARNINGS: 3\n\
+            {} 65A0B0D2 some error: with\t{{}}\t[\"array\"]\n\
+            {} 65A0B0D3 WARNINGS: 1\tsummary-v1:[\"copied 3 files\",\"took 1s\"]\n\
+            {} 65A0B0D4 unexpected EOF\tsummary-v1:[\"read 1 GiB\"]\n\
+            {} 65A0B0D5 OK\n\
+            {} 65A0B0D6 OK\tsummary-v1:[\"took 1s\"]\tresources-v1:{{\"wall-time-ms\":1200,\"poll-time-ms\":30,\"user-cpu-ms\":0,\"system-cpu-ms\":0,\"max-rss-kib\":0}}\n",
+            upid(1),
+            upid(2),
+            upid(3),
+            upid(4),
+            upid(5),
+            upid(6),
+            upid(7),
+        );
+
+        let list = read_task_file(archive.as_bytes())?;
+        let states: Vec<_> = list
+            .iter()
+            .map(|info| (info.state.as_ref().unwrap(), info.summary.clone()))
+            .collect();
+        assert_eq!(
+            states,
+            [
+                (
+                    &TaskState::OK {
+                        endtime: 0x65A0B0D0
+                    },
+                    vec![]
+                ),
+                (
+                    &TaskState::Warning {
+                        count: 3,
+                        endtime: 0x65A0B0D1
+                    },
+                    vec![]
+                ),
+                (
+                    &TaskState::Error {
+                        message: "some error: with\t{}\t[\"array\"]".to_string(),
+                        endtime: 0x65A0B0D2
+                    },
+                    vec![]
+                ),
+                (
+                    &TaskState::Warning {
+                        count: 1,
+                        endtime: 0x65A0B0D3
+                    },
+                    vec!["copied 3 files".to_string(), "took 1s".to_string()]
+                ),
+                (
+                    &TaskState::Error {
+                        message: "unexpected EOF".to_string(),
+                        endtime: 0x65A0B0D4
+                    },
+                    vec!["read 1 GiB".to_string()]
+                ),
+                (
+                    &TaskState::OK {
+                        endtime: 0x65A0B0D5
+                    },
+                    vec![]
+                ),
+                (
+                    &TaskState::OK {
+                        endtime: 0x65A0B0D6
+                    },
+                    vec!["took 1s".to_string()]
+                ),
+            ]
+        );
+        assert!(list[..6].iter().all(|info| info.resource_usage.is_none()));
+        assert_eq!(
+            list[6]
+                .resource_usage
+                .as_ref()
+                .map(|usage| usage.poll_time_ms),
+            Some(Some(30))
+        );
+
+        // rendering and reading again keeps old and new entries, except for tabs in statuses
+        let rendered = render_task_list(&list);
+        assert_eq!(rendered, archive.replace("with\t{}\t[", "with {} ["));
+        assert_eq!(
+            read_task_file(rendered.as_bytes())?[2].state,
+            Some(TaskState::Error {
+                message: "some error: with {} [\"array\"]".to_string(),
+                endtime: 0x65A0B0D2,
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_task_log_tail() -> Result<(), Error> {
+        let upid: UPID =
+            "UPID:node:00001234:00005678:00000001:65A0B0C0:backup:store:root@pam:".parse()?;
+
+        let old_log = "2024-01-12T10:00:00+01:00: starting\n\
+            2024-01-12T10:00:01+01:00: TASK WARNINGS: 2\n";
+        let TaskLogResult { state, summary, .. } =
+            parse_task_log_tail(&upid, old_log.as_bytes().to_vec())?;
+        assert!(matches!(state, TaskState::Warning { count: 2, .. }));
+        assert!(summary.is_empty());
+
+        let new_log = "2024-01-12T10:00:00+01:00: TASK SUMMARY: not directly before the end\n\
+            2024-01-12T10:00:00+01:00: done\n\
+            2024-01-12T10:00:01+01:00: TASK SUMMARY: copied 3 files\n\
+            2024-01-12T10:00:01+01:00: TASK SUMMARY: took 1s\n\
+            2024-01-12T10:00:01+01:00: TASK ERROR: failed\n\n";
+        let TaskLogResult { state, summary, .. } =
+            parse_task_log_tail(&upid, new_log.as_bytes().to_vec())?;
+        assert_eq!(
+            state,
+            TaskState::Error {
+                message: "failed".to_string(),
+                endtime: proxmox_time::parse_rfc3339("2024-01-12T10:00:01+01:00")?,
+            }
+        );
+        assert_eq!(summary, ["copied 3 files", "took 1s"]);
+
+        let accounted_log = "2024-01-12T10:00:00+01:00: done\n\
+            2024-01-12T10:00:01+01:00: TASK RESOURCES: {\"wall-time-ms\":1200,\"user-cpu-ms\":900,\"system-cpu-ms\":40,\"max-rss-kib\":2048}\n\
+            2024-01-12T10:00:01+01:00: TASK SUMMARY: took 1s\n\
+            2024-01-12T10:00:01+01:00: TASK OK\n";
+        let result = parse_task_log_tail(&upid, accounted_log.as_bytes().to_vec())?;
+        assert!(matches!(result.state, TaskState::OK { .. }));
+        assert_eq!(result.summary, ["took 1s"]);
+        assert_eq!(
+            result.resource_usage,
+            Some(TaskResourceUsage {
+                wall_time_ms: 1200,
+                poll_time_ms: None,
+                user_cpu_ms: 900,
+                system_cpu_ms: 40,
+                max_rss_kib: 2048,
+            })
+        );
+
+        // the resource usage line alone is not a final state
+        let running = "2024-01-12T10:00:01+01:00: TASK RESOURCES: {}\n";
+        let result = parse_task_log_tail(&upid, running.as_bytes().to_vec())?;
+        assert!(matches!(result.state, TaskState::Unknown { .. }));
+
+        // still running, no final state yet
+        let running = "2024-01-12T10:00:01+01:00: TASK SUMMARY: copied 3 files\n";
+        let TaskLogResult { state, summary, .. } =
+            parse_task_log_tail(&upid, running.as_bytes().to_vec())?;
+        assert!(matches!(state, TaskState::Unknown { .. }));
+        assert!(summary.is_empty());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,115 @@
+use anyhow::Error;
+use serde_json::{json, Value};
+
+use proxmox_rest_server::{init_worker_tasks, wait_for_local_worker, WorkerTask};
+use proxmox_router::{RpcEnvironment, RpcEnvironmentType};
+use proxmox_schema::upid::{register_task_type, TaskTypeInfo, UPID};
+use proxmox_schema::{IntegerSchema, Schema};
+use proxmox_sys::fs::CreateOptions;
+
+struct TestEnvironment {
+    result: Value,
+    auth_id: Option<String>,
+}
+
+impl RpcEnvironment for TestEnvironment {
+    fn result_attrib_mut(&mut self) -> &mut Value {
+        &mut self.result
+    }
+
+    fn result_attrib(&self) -> &Value {
+        &self.result
+    }
+
+    fn env_type(&self) -> RpcEnvironmentType {
+        RpcEnvironmentType::PRIVILEGED
+    }
+
+    fn set_auth_id(&mut self, auth_id: Option<String>) {
+        self.auth_id = auth_id;
+    }
+
+    fn get_auth_id(&self) -> Option<String> {
+        self.auth_id.clone()
+    }
+}
+
+const VMID_SCHEMA: Schema = IntegerSchema::new("A VM ID.").minimum(100).schema();
+
+#[test]
+fn test_api_task() -> Result<(), Error> {
+    let basedir = std::env::temp_dir().join(format!(
+        "proxmox-rest-server-api-task-test-{}",
+        std::process::id()
+    ));
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    let result = runtime.block_on(async {
+        init_worker_tasks(basedir.clone(), CreateOptions::new())?;
+
+        let mut rpcenv = TestEnvironment {
+            result: json!({}),
+            auth_id: Some("user@pam".to_string()),
+        };
+
+        let response =
+            WorkerTask::spawn_api("order", None, &mut rpcenv, false, |_| async { Ok(()) })?;
+        let Value::String(upid) = &response else {
+            panic!("expected the UPID as response, got {response}");
+        };
+        assert_eq!(rpcenv.result, json!({ "task": upid }));
+        assert_eq!(upid.parse::<UPID>()?.auth_id, "user@pam");
+        wait_for_local_worker(upid).await?;
+
+        let response =
+            WorkerTask::new_thread_api("deactivate", None, &mut rpcenv, false, |_| Ok(()))?;
+        let Value::String(upid) = &response else {
+            panic!("expected the UPID as response, got {response}");
+        };
+        assert_eq!(rpcenv.result["task"], response);
+        assert_eq!(upid.parse::<UPID>()?.worker_type, "deactivate");
+        wait_for_local_worker(upid).await?;
+
+        // the worker types are checked once some are registered
+        register_task_type(TaskTypeInfo::new("order", "Order").id_schema(&VMID_SCHEMA))?;
+        rpcenv.result = json!({});
+
+        let err = WorkerTask::new_thread_api("deactivate", None, &mut rpcenv, false, |_| Ok(()))
+            .unwrap_err();
+        assert_eq!(err.to_string(), "unregistered worker type 'deactivate'");
+        let err = WorkerTask::spawn_api("order", Some("1".into()), &mut rpcenv, false, |_| async {
+            Ok(())
+        })
+        .unwrap_err();
+        assert!(
+            err.to_string().starts_with("invalid worker id for 'order'"),
+            "{err}"
+        );
+        assert_eq!(rpcenv.result, json!({}));
+
+        let response =
+            WorkerTask::spawn_api("order", Some("100".into()), &mut rpcenv, false, |_| async {
+                Ok(())
+            })?;
+        assert_eq!(rpcenv.result["task"], response);
+        wait_for_local_worker(response.as_str().unwrap()).await?;
+
+        // API tasks always run as the authenticated user
+        rpcenv.set_auth_id(None);
+        assert!(WorkerTask::spawn_api(
+            "order",
+            Some("100".into()),
+            &mut rpcenv,
+            false,
+            |_| async { Ok(()) }
+        )
+        .is_err());
+
+        Ok(())
+    });
+
+    let _ = std::fs::remove_dir_all(&basedir);
+    result
+}
@@ -0,0 +1,150 @@
+use std::io::Write;
+use std::net::TcpStream;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::Error;
+use hyper::http::request::Parts;
+use hyper::Body;
+use serde_json::Value;
+
+use proxmox_rest_server::{ApiConfig, ClientDisconnect, RestEnvironment, RestServer};
+use proxmox_router::{
+    ApiHandler, ApiMethod, ApiResponseFuture, Permission, Router, RpcEnvironment,
+    RpcEnvironmentType, SubdirMap,
+};
+use proxmox_schema::ObjectSchema;
+
+static EVENTS: Mutex<Vec<&'static str>> = Mutex::new(Vec::new());
+
+fn record(event: &'static str) {
+    EVENTS.lock().unwrap().push(event);
+}
+
+fn wait_for_event(event: &'static str) -> bool {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while Instant::now() < deadline {
+        if EVENTS.lock().unwrap().contains(&event) {
+            return true;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    false
+}
+
+struct RecordDrop(&'static str);
+
+impl Drop for RecordDrop {
+    fn drop(&mut self) {
+        record(self.0);
+    }
+}
+
+fn compute(
+    _parts: Parts,
+    _req_body: Body,
+    _param: Value,
+    _info: &'static ApiMethod,
+    rpcenv: Box<dyn RpcEnvironment>,
+) -> ApiResponseFuture {
+    let rpcenv = (*rpcenv)
+        .as_any()
+        .downcast_ref::<RestEnvironment>()
+        .unwrap();
+    let disconnect = rpcenv.client_disconnected().clone();
+
+    // blocking work polls the signal
+    std::thread::spawn(move || {
+        while !disconnect.is_disconnected() {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        record("compute-observed");
+    });
+
+    Box::pin(async move {
+        let _dropped = RecordDrop("compute-dropped");
+        record("compute-started");
+        std::future::pending().await
+    })
+}
+
+fn register(
+    parts: Parts,
+    _req_body: Body,
+    _param: Value,
+    _info: &'static ApiMethod,
+    _rpcenv: Box<dyn RpcEnvironment>,
+) -> ApiResponseFuture {
+    let disconnect = parts.extensions.get::<ClientDisconnect>().unwrap().clone();
+    disconnect.keep_running();
+
+    Box::pin(async move {
+        let _dropped = RecordDrop("register-dropped");
+        record("register-started");
+        disconnect.disconnected().await;
+        record("register-finished");
+        Ok(hyper::Response::new(Body::empty()))
+    })
+}
+
+const API_METHOD_COMPUTE: ApiMethod = ApiMethod::new(
+    &ApiHandler::AsyncHttp(&compute),
+    &ObjectSchema::new("Compute until the client disconnects.", &[]),
+)
+.access(None, &Permission::World);
+
+const API_METHOD_REGISTER: ApiMethod = ApiMethod::new(
+    &ApiHandler::AsyncHttp(&register),
+    &ObjectSchema::new("Finish after the client disconnected.", &[]),
+)
+.access(None, &Permission::World);
+
+const SUBDIRS: SubdirMap = &[
+    ("compute", &Router::new().get(&API_METHOD_COMPUTE)),
+    ("register", &Router::new().get(&API_METHOD_REGISTER)),
+];
+const ROUTER: Router = Router::new().subdirs(SUBDIRS);
+
+fn abort_request(addr: std::net::SocketAddr, path: &str, started: &'static str) -> bool {
+    let mut client = TcpStream::connect(addr).unwrap();
+    write!(client, "GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+    let started = wait_for_event(started);
+    drop(client);
+    started
+}
+
+#[test]
+fn test_client_disconnect() -> Result<(), Error> {
+    let config = ApiConfig::new("/", RpcEnvironmentType::PUBLIC).default_api2_handler(&ROUTER);
+
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?;
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+    {
+        let _guard = runtime.enter();
+        let server = hyper::Server::from_tcp(listener)?.serve(RestServer::new(config));
+        runtime.spawn(server);
+    }
+
+    // the handler is dropped, and work elsewhere notices the disconnect
+    assert!(abort_request(addr, "/api2/json/compute", "compute-started"));
+    assert!(wait_for_event("compute-dropped"));
+    assert!(wait_for_event("compute-observed"));
+
+    // unless the handler keeps running
+    assert!(abort_request(
+        addr,
+        "/api2/json/register",
+        "register-started"
+    ));
+    assert!(wait_for_event("register-finished"));
+    assert!(wait_for_event("register-dropped"));
+
+    let events = EVENTS.lock().unwrap();
+    let position = |event| events.iter().position(|e| *e == event).unwrap();
+    assert!(position("register-finished") < position("register-dropped"));
+
+    Ok(())
+}
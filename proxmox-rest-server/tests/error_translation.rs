@@ -0,0 +1,91 @@
+use std::borrow::Cow;
+
+use anyhow::Error;
+use hyper::{Client, StatusCode};
+use serde_json::{json, Value};
+
+use proxmox_rest_server::{ApiConfig, RestServer};
+use proxmox_router::{
+    ApiHandler, ApiMethod, Permission, Router, RpcEnvironment, RpcEnvironmentType, SubdirMap,
+};
+use proxmox_schema::i18n::translate;
+use proxmox_schema::{set_schema_translator, ObjectSchema, StringSchema};
+
+fn pseudo_translate(text: &'static str) -> Cow<'static, str> {
+    match text {
+        // nested translations must not deadlock
+        "too-long" => Cow::Owned(format!("[max. {{max}} {}]", translate("characters"))),
+        "parameter verification errors" => Cow::Borrowed("[parameter errors]"),
+        _ => Cow::Owned(format!("<{text}>")),
+    }
+}
+
+fn set_name(
+    _param: Value,
+    _info: &ApiMethod,
+    _rpcenv: &mut dyn RpcEnvironment,
+) -> Result<Value, Error> {
+    Ok(Value::Null)
+}
+
+const API_METHOD_SET_NAME: ApiMethod = ApiMethod::new(
+    &ApiHandler::Sync(&set_name),
+    &ObjectSchema::new(
+        "Set the name.",
+        &[(
+            "name",
+            true,
+            &StringSchema::new("The name.").max_length(3).schema(),
+        )],
+    ),
+)
+.access(None, &Permission::World);
+
+const SUBDIRS: SubdirMap = &[("name", &Router::new().get(&API_METHOD_SET_NAME))];
+const ROUTER: Router = Router::new().subdirs(SUBDIRS);
+
+#[test]
+fn test_translated_error_body() -> Result<(), Error> {
+    set_schema_translator(Box::new(pseudo_translate));
+
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?
+        .block_on(async {
+            let config =
+                ApiConfig::new("/", RpcEnvironmentType::PUBLIC).default_api2_handler(&ROUTER);
+            let server = hyper::Server::try_bind(&([127, 0, 0, 1], 0).into())?
+                .serve(RestServer::new(config));
+            let addr = server.local_addr();
+            tokio::spawn(server);
+
+            let response = Client::new()
+                .get(format!("http://{addr}/api2/extjs/name?name=long").parse()?)
+                .await?;
+            assert_eq!(response.status(), StatusCode::OK);
+            let body = hyper::body::to_bytes(response.into_body()).await?;
+            let data: Value = serde_json::from_slice(&body)?;
+
+            // translated for the user, untranslated with the identifiers for clients
+            assert_eq!(
+                data,
+                json!({
+                    "success": false,
+                    "status": 400,
+                    "message": "[parameter errors]",
+                    "errors": { "name": "[max. 3 <characters>]" },
+                    "untranslated": {
+                        "message": "parameter verification errors",
+                        "errors": {
+                            "name": {
+                                "message": "value may only be 3 characters long",
+                                "id": "too-long",
+                            },
+                        },
+                    },
+                })
+            );
+
+            Ok(())
+        })
+}
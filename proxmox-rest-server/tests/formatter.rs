@@ -0,0 +1,184 @@
+use std::net::SocketAddr;
+
+use anyhow::Error;
+use hyper::{Body, Client, Response, StatusCode};
+use serde_json::{json, Value};
+
+use proxmox_rest_server::formatter::{
+    add_etag_header, add_result_attributes, json_data_response, json_data_response_streaming,
+    set_error_message, ErrorDetails, OutputFormatter,
+};
+use proxmox_rest_server::{ApiConfig, RestServer};
+use proxmox_router::{
+    http_bail, ApiHandler, ApiMethod, Permission, Router, RpcEnvironment, RpcEnvironmentType,
+    SerializableReturn, SubdirMap,
+};
+use proxmox_schema::{IntegerSchema, ObjectSchema};
+
+/// A product specific envelope, with the task of the call and the warnings it logged.
+struct ProductFormatter;
+
+impl OutputFormatter for ProductFormatter {
+    fn format_data(&self, data: Value, rpcenv: &dyn RpcEnvironment) -> Response<Body> {
+        let mut result = json!({ "data": data, "success": true, "warnings": [] });
+        add_result_attributes(&mut result, rpcenv);
+        let mut response = json_data_response(result);
+        add_etag_header(&mut response, rpcenv);
+        response
+    }
+
+    fn format_data_streaming(
+        &self,
+        data: Box<dyn SerializableReturn + Send>,
+        rpcenv: &dyn RpcEnvironment,
+    ) -> Result<Response<Body>, Error> {
+        let mut value = json!({ "success": true, "warnings": [] });
+        add_result_attributes(&mut value, rpcenv);
+        let mut response = json_data_response_streaming(value, data)?;
+        add_etag_header(&mut response, rpcenv);
+        Ok(response)
+    }
+
+    fn format_error(&self, err: Error) -> Response<Body> {
+        let details = ErrorDetails::from(err);
+        let result = json!({
+            "success": false,
+            "message": details.message,
+            "errors": details.errors,
+        });
+        let mut response = json_data_response(result);
+        *response.status_mut() = details.status;
+        set_error_message(&mut response, details.message);
+        response
+    }
+}
+
+static PRODUCT_FORMATTER: ProductFormatter = ProductFormatter;
+
+fn get_task(
+    _param: Value,
+    _info: &ApiMethod,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<Value, Error> {
+    rpcenv["task"] = "UPID:node:00000001".into();
+    Ok(json!({ "name": "test" }))
+}
+
+fn get_list(
+    _param: Value,
+    _info: &ApiMethod,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<Box<dyn SerializableReturn + Send>, Error> {
+    rpcenv["total"] = 2.into();
+    Ok(Box::new(vec![1, 2]))
+}
+
+fn get_missing(
+    _param: Value,
+    _info: &ApiMethod,
+    _rpcenv: &mut dyn RpcEnvironment,
+) -> Result<Value, Error> {
+    http_bail!(NOT_FOUND, "no such thing");
+}
+
+const API_METHOD_GET_TASK: ApiMethod = ApiMethod::new(
+    &ApiHandler::Sync(&get_task),
+    &ObjectSchema::new(
+        "Get a task.",
+        &[("count", true, &IntegerSchema::new("A count.").schema())],
+    ),
+)
+.access(None, &Permission::World);
+
+const API_METHOD_GET_LIST: ApiMethod = ApiMethod::new(
+    &ApiHandler::SerializingSync(&get_list),
+    &ObjectSchema::new("Get a list.", &[]),
+)
+.access(None, &Permission::World);
+
+const API_METHOD_GET_MISSING: ApiMethod = ApiMethod::new(
+    &ApiHandler::Sync(&get_missing),
+    &ObjectSchema::new("Fail.", &[]),
+)
+.access(None, &Permission::World);
+
+const SUBDIRS: SubdirMap = &[
+    ("list", &Router::new().get(&API_METHOD_GET_LIST)),
+    ("missing", &Router::new().get(&API_METHOD_GET_MISSING)),
+    ("task", &Router::new().get(&API_METHOD_GET_TASK)),
+];
+const ROUTER: Router = Router::new().subdirs(SUBDIRS);
+
+async fn get(addr: SocketAddr, path: &str) -> Result<(StatusCode, Value), Error> {
+    let response = Client::new()
+        .get(format!("http://{addr}{path}").parse()?)
+        .await?;
+    let status = response.status();
+    let body = hyper::body::to_bytes(response.into_body()).await?;
+    Ok((status, serde_json::from_slice(&body)?))
+}
+
+#[test]
+fn test_custom_formatter() -> Result<(), Error> {
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?
+        .block_on(async {
+            let config = ApiConfig::new("/", RpcEnvironmentType::PUBLIC)
+                .default_api2_handler(&ROUTER)
+                .register_formatter("product", &PRODUCT_FORMATTER)
+                .register_formatter("json", &PRODUCT_FORMATTER);
+            let server = hyper::Server::try_bind(&([127, 0, 0, 1], 0).into())?
+                .serve(RestServer::new(config));
+            let addr = server.local_addr();
+            tokio::spawn(server);
+
+            for prefix in ["product", "json"] {
+                let (status, data) = get(addr, &format!("/api2/{prefix}/task")).await?;
+                assert_eq!(status, StatusCode::OK);
+                assert_eq!(
+                    data,
+                    json!({
+                        "data": { "name": "test" },
+                        "success": true,
+                        "task": "UPID:node:00000001",
+                        "warnings": [],
+                    })
+                );
+
+                let (status, data) = get(addr, &format!("/api2/{prefix}/list")).await?;
+                assert_eq!(status, StatusCode::OK);
+                assert_eq!(
+                    data,
+                    json!({ "data": [1, 2], "success": true, "total": 2, "warnings": [] })
+                );
+
+                let (status, data) = get(addr, &format!("/api2/{prefix}/missing")).await?;
+                assert_eq!(status, StatusCode::NOT_FOUND);
+                assert_eq!(
+                    data,
+                    json!({ "success": false, "message": "no such thing", "errors": {} })
+                );
+
+                let (status, data) = get(addr, &format!("/api2/{prefix}/task?count=x")).await?;
+                assert_eq!(status, StatusCode::BAD_REQUEST);
+                assert_eq!(data["success"], false);
+                assert_eq!(data["message"], "parameter verification errors");
+                assert!(data["errors"]["count"].is_string(), "{data}");
+            }
+
+            // the other built-in format is unchanged
+            let (status, data) = get(addr, "/api2/extjs/task").await?;
+            assert_eq!(status, StatusCode::OK);
+            assert_eq!(data["status"], 200);
+            assert!(data.get("warnings").is_none());
+
+            // without a translator, errors carry no untranslated copy
+            let (status, data) = get(addr, "/api2/extjs/task?count=x").await?;
+            assert_eq!(status, StatusCode::OK);
+            assert_eq!(data["message"], "parameter verification errors");
+            assert!(data.get("untranslated").is_none(), "{data}");
+
+            Ok(())
+        })
+}
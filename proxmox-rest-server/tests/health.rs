@@ -0,0 +1,112 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{bail, Error};
+use hyper::{Body, Method, Request, StatusCode};
+use serde_json::Value;
+
+use proxmox_rest_server::{ApiConfig, HealthOptions};
+use proxmox_router::RpcEnvironmentType;
+
+async fn probe(config: &Arc<ApiConfig>, uri: &str) -> Result<(StatusCode, Value), Error> {
+    let request = Request::builder()
+        .method(Method::GET)
+        .uri(uri)
+        .body(Body::empty())?;
+    let peer = "127.0.0.1:8007".parse()?;
+    let response = Arc::clone(config).handle_request(request, &peer).await?;
+    let status = response.status();
+    let body = hyper::body::to_bytes(response.into_body()).await?;
+    Ok((status, serde_json::from_slice(&body)?))
+}
+
+#[test]
+fn test_health_endpoints() -> Result<(), Error> {
+    let database_up = Arc::new(AtomicBool::new(true));
+    let database = Arc::clone(&database_up);
+
+    let options = HealthOptions::new("1.2.3")
+        .readiness_check("database", Duration::from_secs(5), move || {
+            let up = database.load(Ordering::SeqCst);
+            async move {
+                if !up {
+                    bail!("connection refused");
+                }
+                Ok(())
+            }
+        })
+        .readiness_check("cluster", Duration::from_secs(5), || async {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+            Ok(())
+        });
+    let config =
+        Arc::new(ApiConfig::new("/", RpcEnvironmentType::PUBLIC).enable_health_endpoints(options));
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    runtime.block_on(async {
+        let (status, data) = probe(&config, "/health").await?;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(data["status"], "ok");
+        assert_eq!(data["version"], "1.2.3");
+        assert!(data["uptime"].is_u64());
+        assert_eq!(data["tasks"]["limit"], 512);
+
+        let (status, data) = probe(&config, "/ready").await?;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(data["status"], "ready");
+        assert_eq!(data["checks"][0]["name"], "database");
+        assert_eq!(data["checks"][0]["status"], "ok");
+        assert_eq!(data["checks"][1]["status"], "ok");
+
+        database_up.store(false, Ordering::SeqCst);
+        let (status, data) = probe(&config, "/ready").await?;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(data["status"], "unavailable");
+        assert_eq!(data["checks"][0]["status"], "failed");
+        assert_eq!(data["checks"][0]["message"], "connection refused");
+        database_up.store(true, Ordering::SeqCst);
+
+        // draining during a shutdown, while the process stays alive
+        proxmox_daemon::request_shutdown();
+        let (status, data) = probe(&config, "/ready").await?;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(data["status"], "draining");
+        assert_eq!(data["checks"][0]["status"], "ok");
+
+        let (status, _) = probe(&config, "/health").await?;
+        assert_eq!(status, StatusCode::OK);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_readiness_timeout() -> Result<(), Error> {
+    let options = HealthOptions::new("1.2.3")
+        .paths("/probe/live", "probe/ready/")
+        .readiness_check("slow", Duration::from_millis(10), || async {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            Ok(())
+        });
+    let config =
+        Arc::new(ApiConfig::new("/", RpcEnvironmentType::PUBLIC).enable_health_endpoints(options));
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    runtime.block_on(async {
+        let (status, data) = probe(&config, "/probe/live").await?;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(data["version"], "1.2.3");
+
+        let (status, data) = probe(&config, "//probe/ready/").await?;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(data["checks"][0]["status"], "timeout");
+        assert_eq!(data["checks"][0]["message"], "no result within 10ms");
+
+        Ok(())
+    })
+}
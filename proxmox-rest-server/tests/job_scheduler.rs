@@ -0,0 +1,161 @@
+use std::sync::atomic::{AtomicI64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Error;
+use tokio::sync::Notify;
+
+use proxmox_rest_server::{
+    init_worker_tasks, wait_for_local_worker, JobSchedule, JobScheduler, WorkerTask,
+};
+use proxmox_sys::fs::CreateOptions;
+
+// a multiple of 5 minutes for the calendar events
+const START: i64 = 1_699_999_800;
+const INTERVAL: i64 = 60;
+
+/// A job counting its runs, which only finish once released.
+#[derive(Clone, Default)]
+struct TestJob {
+    runs: Arc<AtomicUsize>,
+    release: Arc<Notify>,
+}
+
+impl TestJob {
+    fn start(&self) -> Result<String, Error> {
+        let runs = Arc::clone(&self.runs);
+        let release = Arc::clone(&self.release);
+        WorkerTask::spawn(
+            "test-job",
+            None,
+            "root@pam".to_string(),
+            false,
+            move |_worker| async move {
+                runs.fetch_add(1, Ordering::SeqCst);
+                release.notified().await;
+                Ok(())
+            },
+        )
+    }
+
+    fn runs(&self) -> usize {
+        self.runs.load(Ordering::SeqCst)
+    }
+}
+
+fn scheduler(
+    state_path: &std::path::Path,
+    clock: &Arc<AtomicI64>,
+    job: &TestJob,
+) -> Result<JobScheduler, Error> {
+    let time = Arc::clone(clock);
+    let scheduler = JobScheduler::new(state_path, CreateOptions::new())?
+        .with_clock(move || time.load(Ordering::SeqCst));
+
+    let test_job = job.clone();
+    scheduler.register_job(
+        "cleanup",
+        JobSchedule::interval(Duration::from_secs(INTERVAL as u64)),
+        move || test_job.start(),
+    )?;
+    Ok(scheduler)
+}
+
+async fn finish(job: &TestJob, upid: &str) -> Result<(), Error> {
+    job.release.notify_one();
+    wait_for_local_worker(upid).await
+}
+
+#[test]
+fn test_job_scheduler() -> Result<(), Error> {
+    proxmox_log::init_cli_logger("PROXMOX_DEBUG", proxmox_log::LevelFilter::INFO)?;
+
+    let basedir = std::env::temp_dir().join(format!(
+        "proxmox-rest-server-job-scheduler-test-{}",
+        std::process::id()
+    ));
+    let state_path = basedir.join("jobstate.json");
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    runtime.block_on(async {
+        init_worker_tasks(basedir.clone(), CreateOptions::new())?;
+
+        let clock = Arc::new(AtomicI64::new(START));
+        let set_time = |time: i64| clock.store(time, Ordering::SeqCst);
+        let job = TestJob::default();
+        let scheduler = scheduler(&state_path, &clock, &job)?;
+
+        // jobs which never ran are due immediately
+        assert_eq!(scheduler.run_pending(), ["cleanup"]);
+        let first_upid = scheduler.list()[0]["last-upid"]
+            .as_str()
+            .unwrap()
+            .to_string();
+
+        set_time(START + INTERVAL - 1);
+        assert!(scheduler.run_pending().is_empty());
+
+        // a running job is skipped until its next run
+        set_time(START + INTERVAL);
+        assert!(scheduler.run_pending().is_empty());
+        let list = scheduler.list();
+        assert_eq!(list[0]["running"], true);
+        assert_eq!(list[0]["next-run"], START + 2 * INTERVAL);
+        assert!(scheduler.run_now("cleanup").is_err());
+
+        finish(&job, &first_upid).await?;
+        assert_eq!(job.runs(), 1);
+        set_time(START + 2 * INTERVAL);
+        assert_eq!(scheduler.run_pending(), ["cleanup"]);
+        let list = scheduler.list();
+        assert_eq!(list[0]["last-run"], START + 2 * INTERVAL);
+        assert_eq!(list[0]["next-run"], START + 3 * INTERVAL);
+        finish(&job, list[0]["last-upid"].as_str().unwrap()).await?;
+        assert_eq!(job.runs(), 2);
+
+        // the result of the last run is recorded on the next check
+        scheduler.run_pending();
+        assert_eq!(scheduler.list()[0]["last-state"], "OK");
+
+        // jobs can be triggered manually
+        let upid = scheduler.run_now("cleanup")?;
+        assert_eq!(scheduler.list()[0]["last-upid"], upid.as_str());
+        finish(&job, &upid).await?;
+        assert_eq!(job.runs(), 3);
+        drop(scheduler);
+
+        // missed runs are caught up once after a downtime, based on the saved state
+        let downtime_end = START + 100 * INTERVAL + 30;
+        set_time(downtime_end);
+        let scheduler = self::scheduler(&state_path, &clock, &job)?;
+        assert_eq!(scheduler.list()[0]["last-run"], START + 2 * INTERVAL);
+        assert_eq!(scheduler.run_pending(), ["cleanup"]);
+        assert!(scheduler.run_pending().is_empty());
+        let list = scheduler.list();
+        assert_eq!(list[0]["next-run"], downtime_end + INTERVAL);
+        finish(&job, list[0]["last-upid"].as_str().unwrap()).await?;
+        assert_eq!(job.runs(), 4);
+
+        Ok::<_, Error>(())
+    })?;
+
+    let _ = std::fs::remove_dir_all(basedir);
+    Ok(())
+}
+
+#[test]
+fn test_job_schedule() -> Result<(), Error> {
+    let schedule = JobSchedule::interval(Duration::from_secs(300));
+    assert_eq!(schedule.next_run(START)?, Some(START + 300));
+    assert_eq!(schedule.to_string(), "every 300s");
+
+    let schedule = JobSchedule::calendar("*:0/5")?;
+    assert_eq!(schedule.next_run(START)?, Some(START + 300));
+    assert_eq!(schedule.next_run(START + 1)?, Some(START + 300));
+    assert_eq!(schedule.to_string(), "*:0/5");
+
+    assert!(JobSchedule::calendar("not a schedule").is_err());
+    Ok(())
+}
@@ -0,0 +1,158 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Error;
+use hyper::{header, Body, Method, Request, StatusCode};
+use serde_json::{json, Value};
+
+use proxmox_daemon::command_socket::{send_command_typed, CommandSocket};
+use proxmox_rest_server::{
+    ApiConfig, HealthOptions, Maintenance, MaintenanceMode, MaintenanceState,
+};
+use proxmox_router::{
+    ApiHandler, ApiMethod, Permission, Router, RpcEnvironment, RpcEnvironmentType, SubdirMap,
+};
+use proxmox_schema::ObjectSchema;
+
+fn ok(_param: Value, _info: &ApiMethod, _rpcenv: &mut dyn RpcEnvironment) -> Result<Value, Error> {
+    Ok(Value::Null)
+}
+
+const API_METHOD_OK: ApiMethod =
+    ApiMethod::new(&ApiHandler::Sync(&ok), &ObjectSchema::new("Succeed.", &[]))
+        .access(None, &Permission::World);
+
+const SUBDIRS: SubdirMap = &[
+    (
+        "config",
+        &Router::new().get(&API_METHOD_OK).post(&API_METHOD_OK),
+    ),
+    ("ticket", &Router::new().post(&API_METHOD_OK)),
+];
+const ROUTER: Router = Router::new().subdirs(SUBDIRS);
+
+async fn request(
+    config: &Arc<ApiConfig>,
+    method: Method,
+    uri: &str,
+) -> Result<(StatusCode, Option<String>, String), Error> {
+    let request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .body(Body::empty())?;
+    let peer = "127.0.0.1:8007".parse()?;
+    let response = Arc::clone(config).handle_request(request, &peer).await?;
+    let status = response.status();
+    let retry_after = response
+        .headers()
+        .get(header::RETRY_AFTER)
+        .map(|value| value.to_str().unwrap().to_string());
+    let body = hyper::body::to_bytes(response.into_body()).await?;
+    Ok((status, retry_after, String::from_utf8(body.to_vec())?))
+}
+
+async fn status(config: &Arc<ApiConfig>, method: Method, uri: &str) -> Result<StatusCode, Error> {
+    Ok(request(config, method, uri).await?.0)
+}
+
+#[test]
+fn test_maintenance_mode() -> Result<(), Error> {
+    let maintenance = Arc::new(
+        Maintenance::new()
+            .allow_path("/api2/json/ticket")
+            .retry_after(120),
+    );
+    assert_eq!(maintenance.state(), MaintenanceState::default());
+
+    let config = Arc::new(
+        ApiConfig::new("/", RpcEnvironmentType::PUBLIC)
+            .default_api2_handler(&ROUTER)
+            .enable_health_endpoints(HealthOptions::new("1.2.3"))
+            // answers `OPTIONS` requests without authentication
+            .api_self_description(&[])
+            .maintenance(Arc::clone(&maintenance)),
+    );
+
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?
+        .block_on(async {
+            let config_uri = "/api2/json/config";
+            let ticket_uri = "/api2/json/ticket";
+            assert_eq!(
+                status(&config, Method::GET, config_uri).await?,
+                StatusCode::OK
+            );
+            assert_eq!(
+                status(&config, Method::POST, config_uri).await?,
+                StatusCode::OK
+            );
+
+            // switch to read-only through the command socket
+            let path = format!("\0proxmox-rest-server-test/{}.sock", std::process::id());
+            let mut commando_sock = CommandSocket::with_path(&path, nix::unistd::Gid::current());
+            maintenance.register_command(&mut commando_sock)?;
+            commando_sock.spawn(std::future::pending())?;
+            let state: Value = send_command_typed(
+                &path,
+                "api-maintenance",
+                Some(json!({ "mode": "read-only", "message": "upgrade in progress" })),
+                Duration::from_secs(5),
+            )
+            .await?;
+            let read_only = json!({ "mode": "read-only", "message": "upgrade in progress" });
+            assert_eq!(state, read_only);
+
+            assert_eq!(
+                status(&config, Method::GET, config_uri).await?,
+                StatusCode::OK
+            );
+            // e.g. CORS preflight requests are not rejected
+            assert_eq!(
+                status(&config, Method::OPTIONS, config_uri).await?,
+                StatusCode::OK
+            );
+            let (code, retry_after, message) = request(&config, Method::POST, config_uri).await?;
+            assert_eq!(code, StatusCode::SERVICE_UNAVAILABLE);
+            assert_eq!(retry_after.as_deref(), Some("120"));
+            assert_eq!(message, "upgrade in progress");
+            assert_eq!(
+                status(&config, Method::POST, ticket_uri).await?,
+                StatusCode::OK
+            );
+
+            let (code, _, data) = request(&config, Method::GET, "/health").await?;
+            assert_eq!(code, StatusCode::OK);
+            let data: Value = serde_json::from_str(&data)?;
+            assert_eq!(data["maintenance"], read_only);
+
+            // full maintenance, without a message
+            maintenance.set_state(MaintenanceState {
+                mode: MaintenanceMode::Full,
+                message: None,
+            });
+            let (code, _, message) = request(&config, Method::GET, config_uri).await?;
+            assert_eq!(code, StatusCode::SERVICE_UNAVAILABLE);
+            assert_eq!(message, "node is in maintenance");
+            assert_eq!(
+                status(&config, Method::POST, ticket_uri).await?,
+                StatusCode::OK
+            );
+            assert_eq!(
+                status(&config, Method::GET, "/health").await?,
+                StatusCode::OK
+            );
+
+            let state: Value =
+                send_command_typed(&path, "api-maintenance", None, Duration::from_secs(5)).await?;
+            assert_eq!(state, json!({ "mode": "full" }));
+
+            maintenance.set_state(MaintenanceState::default());
+            assert_eq!(
+                status(&config, Method::POST, config_uri).await?,
+                StatusCode::OK
+            );
+
+            Ok(())
+        })
+}
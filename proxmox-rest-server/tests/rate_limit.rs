@@ -0,0 +1,191 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Error;
+use hyper::http::request::Parts;
+use hyper::{Body, Client, Method, Request, Response};
+use serde_json::{json, Value};
+
+use proxmox_daemon::command_socket::{send_command_typed, CommandSocket};
+use proxmox_rest_server::{
+    ApiConfig, BodyRateLimiter, BodyRateLimits, RequestRateLimit, RestServer,
+};
+use proxmox_router::{
+    ApiHandler, ApiMethod, ApiResponseFuture, Permission, Router, RpcEnvironment,
+    RpcEnvironmentType, SubdirMap,
+};
+use proxmox_schema::ObjectSchema;
+
+const MIB: usize = 1024 * 1024;
+
+fn download(
+    _parts: Parts,
+    _body: Body,
+    param: Value,
+    _info: &ApiMethod,
+    _rpcenv: Box<dyn RpcEnvironment>,
+) -> ApiResponseFuture {
+    Box::pin(async move {
+        let size = param["size"].as_str().unwrap_or("0").parse()?;
+        Ok(Response::new(Body::from(vec![0u8; size])))
+    })
+}
+
+fn upload(
+    parts: Parts,
+    body: Body,
+    _param: Value,
+    _info: &ApiMethod,
+    _rpcenv: Box<dyn RpcEnvironment>,
+) -> ApiResponseFuture {
+    Box::pin(async move {
+        // e.g. the limit of the authenticated user
+        if let Some(limit) = parts.extensions.get::<RequestRateLimit>() {
+            limit.set_upload(Some(2 * MIB as u64));
+        }
+        let data = hyper::body::to_bytes(body).await?;
+        Ok(Response::new(Body::from(data.len().to_string())))
+    })
+}
+
+const API_METHOD_DOWNLOAD: ApiMethod = ApiMethod::new(
+    &ApiHandler::AsyncHttp(&download),
+    &ObjectSchema::new("Download", &[]).additional_properties(true),
+)
+.access(None, &Permission::World);
+
+const API_METHOD_UPLOAD: ApiMethod = ApiMethod::new(
+    &ApiHandler::AsyncHttp(&upload),
+    &ObjectSchema::new("Upload", &[]),
+)
+.access(None, &Permission::World);
+
+const SUBDIRS: SubdirMap = &[
+    ("download", &Router::new().get(&API_METHOD_DOWNLOAD)),
+    ("upload", &Router::new().upload(&API_METHOD_UPLOAD)),
+];
+const ROUTER: Router = Router::new().subdirs(SUBDIRS);
+
+/// Serve the API on a local port.
+fn spawn_server(limiter: Arc<BodyRateLimiter>) -> Result<SocketAddr, Error> {
+    let config = ApiConfig::new("/", RpcEnvironmentType::PUBLIC)
+        .unformatted_router(&["api"], &ROUTER)
+        .body_rate_limiter(limiter);
+    let server =
+        hyper::Server::try_bind(&([127, 0, 0, 1], 0).into())?.serve(RestServer::new(config));
+    let addr = server.local_addr();
+    tokio::spawn(server);
+    Ok(addr)
+}
+
+/// Download `size` bytes and return the time it took.
+async fn timed_download(addr: SocketAddr, size: usize) -> Result<Duration, Error> {
+    let start = Instant::now();
+    let uri = format!("http://{addr}/api/download?size={size}").parse()?;
+    let response = Client::new().get(uri).await?;
+    assert!(response.status().is_success());
+    let data = hyper::body::to_bytes(response.into_body()).await?;
+    assert_eq!(data.len(), size);
+    Ok(start.elapsed())
+}
+
+/// The limiters start with an empty bucket, so a limited body never finishes early, the upper
+/// bound leaves room for the load of the machine.
+fn assert_duration(elapsed: Duration, expected: Duration) {
+    let (min, max) = (expected.mul_f64(0.95), expected.mul_f64(1.3));
+    assert!(
+        min <= elapsed && elapsed <= max,
+        "took {elapsed:?}, expected {expected:?}"
+    );
+}
+
+fn run<F: std::future::Future<Output = Result<(), Error>>>(test: F) -> Result<(), Error> {
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?
+        .block_on(test)
+}
+
+#[test]
+fn test_download_limit() -> Result<(), Error> {
+    run(async {
+        let per_request = BodyRateLimits {
+            upload: None,
+            download: Some(MIB as u64),
+        };
+        let addr = spawn_server(Arc::new(BodyRateLimiter::new(
+            BodyRateLimits::default(),
+            per_request,
+        )))?;
+
+        let elapsed = timed_download(addr, 5 * MIB).await?;
+        assert_duration(elapsed, Duration::from_secs(5));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_upload_limit_set_by_handler() -> Result<(), Error> {
+    run(async {
+        let limiter = Arc::new(BodyRateLimiter::new(
+            BodyRateLimits::default(),
+            BodyRateLimits::default(),
+        ));
+        let addr = spawn_server(limiter)?;
+
+        let start = Instant::now();
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(format!("http://{addr}/api/upload"))
+            .body(Body::from(vec![0u8; 4 * MIB]))?;
+        let response = Client::new().request(request).await?;
+        let data = hyper::body::to_bytes(response.into_body()).await?;
+        assert_eq!(data, (4 * MIB).to_string());
+        assert_duration(start.elapsed(), Duration::from_secs(2));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_global_limit_is_shared_fairly() -> Result<(), Error> {
+    run(async {
+        let global = BodyRateLimits {
+            upload: None,
+            download: Some(4 * MIB as u64),
+        };
+        let limiter = Arc::new(BodyRateLimiter::new(global, BodyRateLimits::default()));
+        let addr = spawn_server(Arc::clone(&limiter))?;
+
+        // without a per-request limit
+        let elapsed = timed_download(addr, MIB).await?;
+        assert!(elapsed < Duration::from_millis(500), "took {elapsed:?}");
+
+        // live reconfiguration through the command socket
+        let path = format!("\0proxmox-rest-server-test/{}.sock", std::process::id());
+        let mut commando_sock = CommandSocket::with_path(&path, nix::unistd::Gid::current());
+        limiter.register_command(&mut commando_sock)?;
+        commando_sock.spawn(std::future::pending())?;
+        let limits: Value = send_command_typed(
+            &path,
+            "api-rate-limit",
+            Some(json!({ "global": { "download": 2 * MIB } })),
+            Duration::from_secs(5),
+        )
+        .await?;
+        assert_eq!(
+            limits,
+            json!({ "global": { "download": 2 * MIB }, "per-request": {} })
+        );
+
+        // two concurrent downloads share the bandwidth and finish at about the same time
+        let (first, second) =
+            futures::try_join!(timed_download(addr, 2 * MIB), timed_download(addr, 2 * MIB))?;
+        assert_duration(first, Duration::from_secs(2));
+        assert_duration(second, Duration::from_secs(2));
+
+        Ok(())
+    })
+}
@@ -0,0 +1,91 @@
+use std::time::{Duration, Instant};
+
+use anyhow::Error;
+
+use proxmox_rest_server::{
+    init_worker_tasks, query_task_status, set_task_resource_accounting, wait_for_local_worker,
+    TaskListInfoIterator, WorkerTask,
+};
+use proxmox_schema::upid::UPID;
+use proxmox_sys::fs::CreateOptions;
+use proxmox_worker_task::{ResourceUsage, WorkerTaskContext};
+
+const BUSY_TIME: Duration = Duration::from_millis(300);
+
+fn busy_loop(duration: Duration) -> u64 {
+    let start = Instant::now();
+    let mut counter = 0u64;
+    while start.elapsed() < duration {
+        counter = std::hint::black_box(counter.wrapping_add(1));
+    }
+    counter
+}
+
+#[test]
+fn test_resource_usage() -> Result<(), Error> {
+    proxmox_log::init_cli_logger("PROXMOX_DEBUG", proxmox_log::LevelFilter::INFO)?;
+
+    let basedir = std::env::temp_dir().join(format!(
+        "proxmox-rest-server-resource-usage-test-{}",
+        std::process::id()
+    ));
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    runtime.block_on(async {
+        init_worker_tasks(basedir.clone(), CreateOptions::new())?;
+        set_task_resource_accounting(true);
+
+        let upid = WorkerTask::new_thread("busy", None, "root@pam".to_string(), false, |worker| {
+            busy_loop(BUSY_TIME);
+            // e.g. of an external command
+            worker.add_resource_usage(&ResourceUsage {
+                user_time: Duration::from_secs(10),
+                system_time: Duration::from_secs(1),
+                max_rss_kib: 1,
+            });
+            Ok(())
+        })?;
+        wait_for_local_worker(&upid).await?;
+
+        let status = query_task_status(&upid.parse::<UPID>()?).await?;
+        let usage = status.resource_usage.expect("no resource usage recorded");
+        // the thread's CPU time and the reported usage are summed up
+        assert!(
+            usage.user_cpu_ms >= 10_000 + BUSY_TIME.as_millis() as u64 / 2,
+            "{usage:?}"
+        );
+        assert!(
+            usage.user_cpu_ms + usage.system_cpu_ms < 11_000 + 2_000,
+            "{usage:?}"
+        );
+        assert!(usage.system_cpu_ms >= 1_000, "{usage:?}");
+        assert!(
+            usage.wall_time_ms >= BUSY_TIME.as_millis() as u64,
+            "{usage:?}"
+        );
+        assert!(usage.max_rss_kib > 1, "{usage:?}");
+        assert_eq!(usage.poll_time_ms, None);
+
+        let upid = WorkerTask::spawn("sleepy", None, "root@pam".to_string(), false, |_| async {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            Ok(())
+        })?;
+        wait_for_local_worker(&upid).await?;
+
+        let usage = TaskListInfoIterator::new(false)?
+            .map(Result::unwrap)
+            .find(|info| info.upid_str == upid)
+            .and_then(|info| info.resource_usage)
+            .expect("no resource usage in the task archive");
+        assert!(usage.wall_time_ms >= 100, "{usage:?}");
+        // sleeping does not keep the runtime busy
+        assert!(usage.poll_time_ms.unwrap() < 100, "{usage:?}");
+
+        Ok::<_, Error>(())
+    })?;
+
+    let _ = std::fs::remove_dir_all(basedir);
+    Ok(())
+}
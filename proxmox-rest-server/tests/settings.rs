@@ -0,0 +1,135 @@
+use std::time::Duration;
+
+use anyhow::Error;
+
+use proxmox_rest_server::{AccessLogFormat, ApiConfig, HandlerTimeouts, LogCompressionSetting};
+use proxmox_router::{ExpectedDuration, RpcEnvironmentType};
+use proxmox_sys::fs::TempDir;
+
+#[test]
+fn test_settings_precedence() -> Result<(), Error> {
+    let dir = TempDir::new_in("/tmp", None)?;
+    let path = dir.path().join("rest-server.toml");
+    std::fs::write(
+        &path,
+        "strict-audit = true\nmax-connections = 10\nlog-compression = \"zstd\"\nfuture-knob = 1\n\
+         fast-handler-timeout = 5\nlog-format = \"json\"\ntrusted-proxies = \"127.0.0.1\"\n",
+    )?;
+
+    let vars = [
+        ("SETTINGS_TEST_MAX_CONNECTIONS", "20"),
+        ("SETTINGS_TEST_SLOW_HANDLER_TIMEOUT", "60"),
+        ("SETTINGS_TEST_UNKNOWN", "1"),
+        ("SETTINGS_TEST_TRUSTED_PROXIES", "127.0.0.1, 10.0.0.0/8"),
+        ("OTHER_MAX_CONNECTIONS", "30"),
+    ]
+    .map(|(name, value)| (name.to_string(), value.to_string()));
+
+    // a missing file is no error
+    let builder = ApiConfig::builder().load_file(dir.path().join("missing.toml"))?;
+    assert_eq!(builder.settings().max_connections, None);
+
+    let builder = builder.load_file(&path)?;
+    assert_eq!(builder.settings().max_connections, Some(10));
+
+    // the environment overrides the file
+    let builder = builder.load_vars("SETTINGS_TEST_", vars)?;
+    let settings = builder.settings();
+    assert_eq!(settings.max_connections, Some(20));
+    assert_eq!(settings.strict_audit, Some(true));
+    assert_eq!(settings.log_compression, Some(LogCompressionSetting::Zstd));
+    assert_eq!(settings.upload_rate, None);
+    assert_eq!(settings.log_format, Some(AccessLogFormat::Json));
+    assert_eq!(
+        settings.trusted_proxies.as_deref(),
+        Some("127.0.0.1, 10.0.0.0/8")
+    );
+
+    let timeouts = settings.handler_timeouts().unwrap();
+    assert_eq!(
+        timeouts.get(ExpectedDuration::Fast),
+        Some(Duration::from_secs(5))
+    );
+    assert_eq!(
+        timeouts.get(ExpectedDuration::Slow),
+        Some(Duration::from_secs(60))
+    );
+    assert_eq!(timeouts.get(ExpectedDuration::Task), None);
+
+    let config = builder.build(dir.path(), RpcEnvironmentType::PUBLIC);
+    let timeouts = config.get_handler_timeouts().unwrap();
+    assert_eq!(
+        timeouts.get(ExpectedDuration::Fast),
+        Some(Duration::from_secs(5))
+    );
+
+    // values set programmatically afterwards take precedence, other loaded classes are kept
+    let config = config.handler_timeouts(
+        HandlerTimeouts::new()
+            .timeout(ExpectedDuration::Fast, Some(Duration::from_secs(1)))
+            .timeout(ExpectedDuration::Task, None),
+    );
+    let timeouts = config.get_handler_timeouts().unwrap();
+    assert_eq!(
+        timeouts.get(ExpectedDuration::Fast),
+        Some(Duration::from_secs(1))
+    );
+    assert_eq!(
+        timeouts.get(ExpectedDuration::Slow),
+        Some(Duration::from_secs(60))
+    );
+    assert_eq!(timeouts.get(ExpectedDuration::Task), None);
+
+    Ok(())
+}
+
+#[test]
+fn test_settings_validation() -> Result<(), Error> {
+    let dir = TempDir::new_in("/tmp", None)?;
+    let path = dir.path().join("rest-server.toml");
+    std::fs::write(&path, "max-worker-tasks = 0\n")?;
+
+    let err = ApiConfig::builder().load_file(&path).err().unwrap();
+    let message = err.to_string();
+    assert!(
+        message.starts_with(&format!("invalid settings in {path:?}")),
+        "{message}"
+    );
+    assert!(message.contains("max-worker-tasks"), "{message}");
+
+    std::fs::write(&path, "strict-audit = \"maybe\"\n")?;
+    assert!(ApiConfig::builder().load_file(&path).is_err());
+
+    let vars = [(
+        "VALIDATION_TEST_UPLOAD_RATE".to_string(),
+        "fast".to_string(),
+    )];
+    let err = ApiConfig::builder()
+        .load_vars("VALIDATION_TEST_", vars)
+        .err()
+        .unwrap();
+    assert!(
+        err.to_string()
+            .starts_with("invalid value in environment variable VALIDATION_TEST_UPLOAD_RATE"),
+        "{err}"
+    );
+
+    std::fs::write(&path, "privileged-addr = \"localhost\"\n")?;
+    assert!(ApiConfig::builder().load_file(&path).is_err());
+
+    std::fs::write(&path, "trusted-proxies = \"10.0.0.0/40\"\n")?;
+    let err = ApiConfig::builder().load_file(&path).err().unwrap();
+    assert!(err.to_string().contains("trusted-proxies"), "{err}");
+
+    std::fs::write(&path, "log-format = \"xml\"\n")?;
+    assert!(ApiConfig::builder().load_file(&path).is_err());
+
+    std::fs::write(&path, "privileged-addr = \"127.0.0.1:82\"\n")?;
+    let builder = ApiConfig::builder().load_file(&path)?;
+    assert_eq!(
+        builder.settings().privileged_addr.as_deref(),
+        Some("127.0.0.1:82")
+    );
+
+    Ok(())
+}
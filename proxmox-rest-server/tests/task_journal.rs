@@ -0,0 +1,168 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::Error;
+
+use proxmox_rest_server::{
+    init_worker_tasks, read_task_log, set_task_journal, wait_for_local_worker, TaskJournal,
+    TaskJournalEntry, TaskJournalSink, WorkerTask,
+};
+use proxmox_schema::upid::UPID;
+use proxmox_sys::fs::CreateOptions;
+
+#[derive(Clone, Debug, PartialEq)]
+struct Entry {
+    upid: String,
+    worker_type: String,
+    worker_id: Option<String>,
+    auth_id: String,
+    line: u64,
+    message: String,
+}
+
+#[derive(Clone, Default)]
+struct FakeJournal(Arc<Mutex<Vec<Entry>>>);
+
+impl TaskJournalSink for FakeJournal {
+    fn send(&self, entry: &TaskJournalEntry) {
+        self.0.lock().unwrap().push(Entry {
+            upid: entry.upid.to_string(),
+            worker_type: entry.worker_type.to_string(),
+            worker_id: entry.worker_id.map(str::to_string),
+            auth_id: entry.auth_id.to_string(),
+            line: entry.line,
+            message: entry.message.to_string(),
+        });
+    }
+}
+
+impl FakeJournal {
+    /// Wait for the entries of a finished task, the last ones are sent when its log is closed.
+    fn wait_for(&self, count: usize) -> Vec<(u64, String)> {
+        let start = Instant::now();
+        loop {
+            let entries = self.0.lock().unwrap().clone();
+            if entries.len() >= count || start.elapsed() > Duration::from_secs(5) {
+                return entries.into_iter().map(|e| (e.line, e.message)).collect();
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
+
+#[test]
+fn test_task_journal() -> Result<(), Error> {
+    proxmox_log::init_cli_logger("PROXMOX_DEBUG", proxmox_log::LevelFilter::INFO)?;
+
+    let basedir = std::env::temp_dir().join(format!(
+        "proxmox-rest-server-task-journal-test-{}",
+        std::process::id()
+    ));
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    runtime.block_on(async {
+        init_worker_tasks(basedir.clone(), CreateOptions::new())?;
+
+        // disabled by default
+        let upid = WorkerTask::new_thread("quiet", None, "root@pam".to_string(), false, |w| {
+            w.log_message("not forwarded");
+            Ok(())
+        })?;
+        wait_for_local_worker(&upid).await?;
+
+        let journal = FakeJournal::default();
+        set_task_journal(Some(
+            TaskJournal::new(Box::new(journal.clone())).max_lines_per_second(Some(5)),
+        ));
+
+        // structured fields
+        let upid = WorkerTask::new_thread(
+            "verify",
+            Some("store1".to_string()),
+            "user@pbs".to_string(),
+            false,
+            |worker| {
+                worker.log_message("first");
+                worker.log_warning("second\nand third");
+                worker.log_message("fourth");
+                Ok(())
+            },
+        )?;
+        wait_for_local_worker(&upid).await?;
+
+        let entries = journal.0.lock().unwrap().clone();
+        assert_eq!(
+            entries[0],
+            Entry {
+                upid: upid.clone(),
+                worker_type: "verify".to_string(),
+                worker_id: Some("store1".to_string()),
+                auth_id: "user@pbs".to_string(),
+                line: 1,
+                message: "first".to_string(),
+            }
+        );
+        let lines: Vec<(u64, String)> = entries.into_iter().map(|e| (e.line, e.message)).collect();
+        assert_eq!(
+            lines,
+            [
+                (1, "first".to_string()),
+                (2, "second\nand third".to_string()),
+                (4, "fourth".to_string()),
+                (5, "TASK WARNINGS: 1".to_string()),
+            ]
+        );
+
+        // a task flooding its log
+        journal.0.lock().unwrap().clear();
+        let upid = WorkerTask::new_thread("flood", None, "root@pam".to_string(), false, |w| {
+            for i in 1..=20 {
+                w.log_message(format!("line {i}"));
+            }
+            Ok(())
+        })?;
+        wait_for_local_worker(&upid).await?;
+
+        let mut expected: Vec<(u64, String)> = (1..=5).map(|i| (i, format!("line {i}"))).collect();
+        // including the result
+        expected.push((21, "suppressed 16 lines".to_string()));
+        assert_eq!(journal.wait_for(6), expected);
+
+        // the task log is complete
+        let log = read_task_log(&upid.parse::<UPID>()?, 0, 100)?;
+        assert_eq!(log.total, 21);
+
+        // the limit applies per second
+        journal.0.lock().unwrap().clear();
+        set_task_journal(Some(
+            TaskJournal::new(Box::new(journal.clone())).max_lines_per_second(Some(2)),
+        ));
+        let upid = WorkerTask::new_thread("slow", None, "root@pam".to_string(), false, |w| {
+            w.log_message("a");
+            w.log_message("b");
+            w.log_message("c");
+            std::thread::sleep(Duration::from_millis(1100));
+            w.log_message("d");
+            Ok(())
+        })?;
+        wait_for_local_worker(&upid).await?;
+        assert_eq!(
+            journal.wait_for(5),
+            [
+                (1, "a".to_string()),
+                (2, "b".to_string()),
+                (3, "suppressed 1 lines".to_string()),
+                (4, "d".to_string()),
+                (5, "TASK OK".to_string()),
+            ]
+        );
+
+        set_task_journal(None);
+        Ok::<_, Error>(())
+    })?;
+
+    let _ = std::fs::remove_dir_all(&basedir);
+    Ok(())
+}
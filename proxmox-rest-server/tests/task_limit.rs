@@ -0,0 +1,75 @@
+use std::sync::mpsc;
+
+use anyhow::Error;
+
+use proxmox_rest_server::{
+    init_worker_tasks, set_max_worker_tasks, set_task_limit_exempt, wait_for_local_worker,
+    worker_task_stats, WorkerTask,
+};
+use proxmox_sys::fs::CreateOptions;
+
+/// Start a worker thread which runs until the returned sender is dropped.
+fn start_task(worker_type: &str) -> Result<(String, mpsc::Sender<()>), Error> {
+    let (finish, wait) = mpsc::channel::<()>();
+    let upid = WorkerTask::new_thread(worker_type, None, "root@pam".into(), false, move |_| {
+        let _ = wait.recv();
+        Ok(())
+    })?;
+    Ok((upid, finish))
+}
+
+#[test]
+fn test_task_limit() -> Result<(), Error> {
+    let basedir = std::env::temp_dir().join(format!(
+        "proxmox-rest-server-task-limit-test-{}",
+        std::process::id()
+    ));
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    let result = runtime.block_on(async {
+        init_worker_tasks(basedir.clone(), CreateOptions::new())?;
+        set_max_worker_tasks(2);
+        set_task_limit_exempt("shutdown", true);
+
+        let (upid, finish) = start_task("backup")?;
+        let (_, _finish_sync) = start_task("sync")?;
+
+        let err = start_task("backup").unwrap_err();
+        assert_eq!(err.to_string(), "too many running tasks (2, limit is 2)");
+
+        // exempt tasks are counted, but not limited
+        let (_, finish_shutdown) = start_task("shutdown")?;
+        let stats = worker_task_stats();
+        assert_eq!(stats.running, 3);
+        assert_eq!(stats.limit, 2);
+        assert_eq!(
+            stats.per_type.into_iter().collect::<Vec<_>>(),
+            [
+                ("backup".to_string(), 1),
+                ("shutdown".to_string(), 1),
+                ("sync".to_string(), 1),
+            ]
+        );
+
+        // finishing tasks frees their slots
+        drop(finish);
+        wait_for_local_worker(&upid).await?;
+        assert!(start_task("backup").is_err());
+        drop(finish_shutdown);
+        while worker_task_stats().running > 1 {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        let (upid, finish) = start_task("backup")?;
+        assert_eq!(worker_task_stats().per_type["backup"], 1);
+
+        drop(finish);
+        wait_for_local_worker(&upid).await?;
+
+        Ok(())
+    });
+
+    let _ = std::fs::remove_dir_all(&basedir);
+    result
+}
@@ -0,0 +1,83 @@
+use anyhow::Error;
+
+use proxmox_rest_server::{
+    init_worker_tasks_with_options, migrate_task_logs, read_task_log, upid_log_path,
+    upid_read_status, wait_for_local_worker, TaskState, WorkerTask, WorkerTaskOptions,
+};
+use proxmox_schema::upid::UPID;
+use proxmox_sys::fs::CreateOptions;
+
+#[test]
+fn test_mixed_task_log_layouts() -> Result<(), Error> {
+    proxmox_log::init_cli_logger("PROXMOX_DEBUG", proxmox_log::LevelFilter::INFO)?;
+
+    let basedir = std::env::temp_dir().join(format!(
+        "proxmox-rest-server-task-log-layout-test-{}",
+        std::process::id()
+    ));
+    let taskdir = basedir.join("tasks");
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    let result = runtime.block_on(async {
+        init_worker_tasks_with_options(
+            basedir.clone(),
+            WorkerTaskOptions::new(CreateOptions::new()).sharded_logs(true),
+        )?;
+
+        // a log of an existing installation
+        let mut old = UPID::new("test-old", None, "root@pam".to_string())?;
+        old.pid = i32::MAX;
+        let old_path = taskdir
+            .join(format!("{:02X}", old.pstart & 255))
+            .join(old.to_string());
+        std::fs::create_dir_all(old_path.parent().unwrap())?;
+        std::fs::write(
+            &old_path,
+            "2024-01-12T10:00:00+01:00: old task\n\
+            2024-01-12T10:00:01+01:00: TASK OK\n",
+        )?;
+
+        // new tasks are logged in the sharded layout
+        let new: UPID =
+            WorkerTask::new_thread("test-new", None, "root@pam".into(), false, |worker| {
+                worker.log_message("new task");
+                Ok(())
+            })?
+            .parse()?;
+        wait_for_local_worker(&new.to_string()).await?;
+        let new_path = upid_log_path(&new)?;
+        assert!(new_path.starts_with(taskdir.join("logs")));
+        assert_eq!(new_path.iter().count(), taskdir.iter().count() + 4);
+        assert!(new_path.exists());
+
+        // both layouts are found
+        assert_eq!(upid_log_path(&old)?, old_path);
+        let endtime = proxmox_time::parse_rfc3339("2024-01-12T10:00:01+01:00")?;
+        assert_eq!(upid_read_status(&old)?, TaskState::OK { endtime });
+        assert_eq!(read_task_log(&old, 0, 10)?.lines.len(), 2);
+        assert!(matches!(upid_read_status(&new)?, TaskState::OK { .. }));
+        assert!(read_task_log(&new, 0, 10)?.lines[0].ends_with("new task"));
+
+        // the migration moves old logs into shards
+        let migration: UPID = migrate_task_logs("root@pam".to_string())?.parse()?;
+        wait_for_local_worker(&migration.to_string()).await?;
+        assert!(matches!(
+            upid_read_status(&migration)?,
+            TaskState::OK { .. }
+        ));
+        assert!(!old_path.exists());
+        assert!(!old_path.parent().unwrap().exists());
+        let migrated_path = upid_log_path(&old)?;
+        assert!(migrated_path.starts_with(taskdir.join("logs")));
+        assert!(migrated_path.exists());
+        assert_eq!(upid_read_status(&old)?, TaskState::OK { endtime });
+        assert_eq!(upid_log_path(&new)?, new_path);
+
+        Ok(())
+    });
+
+    let _ = std::fs::remove_dir_all(&basedir);
+    result
+}
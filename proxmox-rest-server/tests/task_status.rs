@@ -0,0 +1,170 @@
+use std::sync::{mpsc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use anyhow::Error;
+use nix::unistd::Gid;
+use tokio::sync::oneshot;
+
+use proxmox_daemon::command_socket::CommandSocket;
+use proxmox_rest_server::{
+    init_worker_tasks, query_task_status, query_task_status_at, register_task_control_commands,
+    upid_log_path, worker_is_active, TaskState, TaskStatus, WorkerTask,
+};
+use proxmox_schema::upid::UPID;
+use proxmox_sys::fs::CreateOptions;
+
+/// A simulated daemon with its own control socket, running a single task until released.
+struct Daemon {
+    socket: String,
+    upid: UPID,
+    release: mpsc::Sender<()>,
+    stop: oneshot::Sender<()>,
+    thread: JoinHandle<()>,
+}
+
+fn spawn_daemon(name: &str) -> Result<Daemon, Error> {
+    let socket = format!(
+        "\0/run/proxmox-rest-server-test/{}-{name}.sock",
+        std::process::id()
+    );
+    let (release, gate) = mpsc::channel::<()>();
+    let (stop, stopped) = oneshot::channel::<()>();
+    let (upid_sender, upid_receiver) = mpsc::channel();
+
+    let path = socket.clone();
+    let worker_type = format!("test-{name}");
+    let thread = std::thread::spawn(move || {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async move {
+            let mut commando_sock = CommandSocket::with_path(path, Gid::current());
+            register_task_control_commands(&mut commando_sock).unwrap();
+            commando_sock.spawn(std::future::pending()).unwrap();
+
+            let gate = Mutex::new(gate);
+            let upid = WorkerTask::new_thread(
+                &worker_type,
+                None,
+                "root@pam".to_string(),
+                false,
+                move |worker| {
+                    worker.log_message("first step");
+                    worker.progress(0.5);
+                    let _ = gate.lock().unwrap().recv();
+                    worker.log_message("second step");
+                    Ok(())
+                },
+            );
+            upid_sender.send(upid).unwrap();
+
+            // keep serving the control socket until stopped
+            let _ = stopped.await;
+        });
+    });
+
+    let upid = upid_receiver.recv()??.parse()?;
+    Ok(Daemon {
+        socket,
+        upid,
+        release,
+        stop,
+        thread,
+    })
+}
+
+/// Query the daemon for the status of its task until `check` succeeds.
+async fn wait_for_status(
+    daemon: &Daemon,
+    check: impl Fn(&TaskStatus) -> bool,
+) -> Result<TaskStatus, Error> {
+    for _ in 0..500 {
+        let status = query_task_status_at(&daemon.socket, &daemon.upid).await?;
+        if check(&status) {
+            return Ok(status);
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("timeout waiting for task {}", daemon.upid);
+}
+
+#[test]
+fn test_task_status() -> Result<(), Error> {
+    proxmox_log::init_cli_logger("PROXMOX_DEBUG", proxmox_log::LevelFilter::INFO)?;
+
+    let basedir = std::env::temp_dir().join(format!(
+        "proxmox-rest-server-task-status-test-{}",
+        std::process::id()
+    ));
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    runtime.block_on(async {
+        init_worker_tasks(basedir.clone(), CreateOptions::new())?;
+
+        let daemons = [spawn_daemon("api")?, spawn_daemon("privileged")?];
+
+        for daemon in &daemons {
+            let status = wait_for_status(daemon, |status| status.progress == Some(0.5)).await?;
+            assert!(status.is_running());
+            assert!(status.log_tail.last().unwrap().ends_with("first step"));
+        }
+
+        daemons[1].release.send(())?;
+        let status = wait_for_status(&daemons[1], |status| !status.is_running()).await?;
+        assert!(matches!(status.state, Some(TaskState::OK { .. })));
+        assert_eq!(status.progress, None);
+        let lines: Vec<&str> = status.log_tail.iter().map(String::as_str).collect();
+        assert!(lines[lines.len() - 3].ends_with("first step"));
+        assert!(lines[lines.len() - 2].ends_with("second step"));
+        assert!(lines[lines.len() - 1].ends_with("TASK OK"));
+
+        // the other daemon's task is not affected
+        let status = query_task_status_at(&daemons[0].socket, &daemons[0].upid).await?;
+        assert!(status.is_running());
+        assert!(worker_is_active(&daemons[0].upid).await?);
+
+        // tasks of processes which are gone are read from their log
+        let mut stale = UPID::new("test-stale", None, "root@pam".to_string())?;
+        stale.pid = i32::MAX;
+        let path = upid_log_path(&stale)?;
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        std::fs::write(
+            &path,
+            "2024-01-12T10:00:00+01:00: starting\n\
+            2024-01-12T10:00:01+01:00: TASK ERROR: failed\n",
+        )?;
+        let status = query_task_status(&stale).await?;
+        assert_eq!(
+            status,
+            TaskStatus {
+                state: Some(TaskState::Error {
+                    message: "failed".to_string(),
+                    endtime: proxmox_time::parse_rfc3339("2024-01-12T10:00:01+01:00")?,
+                }),
+                progress: None,
+                log_tail: vec![
+                    "2024-01-12T10:00:00+01:00: starting".to_string(),
+                    "2024-01-12T10:00:01+01:00: TASK ERROR: failed".to_string(),
+                ],
+                resource_usage: None,
+            }
+        );
+        assert!(!worker_is_active(&stale).await?);
+
+        for daemon in daemons {
+            daemon.release.send(()).ok();
+            wait_for_status(&daemon, |status| !status.is_running()).await?;
+            let _ = daemon.stop.send(());
+            daemon.thread.join().unwrap();
+        }
+
+        Ok::<_, Error>(())
+    })?;
+
+    let _ = std::fs::remove_dir_all(basedir);
+    Ok(())
+}
@@ -0,0 +1,295 @@
+//! Permission audit of routers
+//!
+//! Products can call [`audit_permissions`] from a unit test to find API methods with missing or
+//! too permissive access definitions.
+
+use std::fmt;
+
+use proxmox_schema::ObjectSchemaType;
+
+use crate::{ApiMethod, Permission, Router, SubRoute};
+
+/// The kind of problem found by [`audit_permissions`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AuditFindingKind {
+    /// The method has no explicit permission, so it defaults to [`Permission::Superuser`].
+    MissingPermission,
+    /// A method other than `GET` or `HEAD` is allowed for the whole world or any user, with the
+    /// name of the permission.
+    PermissiveWrite(&'static str),
+    /// The permission references a parameter the method does not have, e.g. `{store}` in a
+    /// privilege path. Such checks always fail.
+    UnknownParameter(&'static str),
+}
+
+/// A problem with the permission of an API method, see [`audit_permissions`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AuditFinding {
+    /// The path of the method, with path parameters as `{name}`.
+    pub path: String,
+    /// The HTTP method, e.g. `POST`.
+    pub method: &'static str,
+    pub kind: AuditFindingKind,
+}
+
+impl fmt::Display for AuditFinding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}: ", self.method, self.path)?;
+        match self.kind {
+            AuditFindingKind::MissingPermission => f.write_str("no permission defined"),
+            AuditFindingKind::PermissiveWrite(permission) => {
+                write!(f, "modifying method allowed for '{permission}'")
+            }
+            AuditFindingKind::UnknownParameter(name) => {
+                write!(f, "permission references unknown parameter '{name}'")
+            }
+        }
+    }
+}
+
+/// Check the permissions of all methods of `router` and its children.
+///
+/// Reports methods without explicit permission, modifying methods allowed for
+/// [`Permission::World`] or [`Permission::Anybody`], and permissions referencing parameters
+/// not defined in the method's parameter schema.
+pub fn audit_permissions(router: &Router) -> Vec<AuditFinding> {
+    let mut findings = Vec::new();
+    audit_router(router, &mut String::new(), &mut findings);
+    findings
+}
+
+fn audit_router(router: &Router, path: &mut String, findings: &mut Vec<AuditFinding>) {
+    let display_path = if path.is_empty() { "/" } else { path.as_str() };
+    for (method, info) in [
+        ("GET", router.get),
+        ("PUT", router.put),
+        ("POST", router.post),
+        ("DELETE", router.delete),
+        ("HEAD", router.head),
+    ] {
+        if let Some(info) = info {
+            audit_method(display_path, method, info, findings);
+        }
+    }
+
+    let len = path.len();
+    match router.subroute {
+        None => {}
+        Some(SubRoute::Map(dirs)) => {
+            for (name, child) in dirs.iter() {
+                path.push('/');
+                path.push_str(name);
+                audit_router(child, path, findings);
+                path.truncate(len);
+            }
+        }
+        Some(SubRoute::MatchAll {
+            router: child,
+            param_name,
+        }) => {
+            path.push_str("/{");
+            path.push_str(param_name);
+            path.push('}');
+            audit_router(child, path, findings);
+            path.truncate(len);
+        }
+    }
+}
+
+fn audit_method(
+    path: &str,
+    method: &'static str,
+    info: &ApiMethod,
+    findings: &mut Vec<AuditFinding>,
+) {
+    let mut report = |kind| {
+        findings.push(AuditFinding {
+            path: path.to_string(),
+            method,
+            kind,
+        })
+    };
+
+    if !info.access.explicit {
+        report(AuditFindingKind::MissingPermission);
+    }
+
+    if method != "GET" && method != "HEAD" {
+        if let Some(permission) = permissive(info.access.permission) {
+            report(AuditFindingKind::PermissiveWrite(permission));
+        }
+    }
+
+    let mut names = Vec::new();
+    referenced_parameters(info.access.permission, &mut names);
+    for name in names {
+        if info.parameters.lookup(name).is_none() {
+            report(AuditFindingKind::UnknownParameter(name));
+        }
+    }
+}
+
+/// The name of the permission if it allows the whole world or any user.
+fn permissive(permission: &Permission) -> Option<&'static str> {
+    match permission {
+        Permission::World => Some("World"),
+        Permission::Anybody => Some("Anybody"),
+        Permission::Or(list) => list.iter().find_map(|permission| permissive(permission)),
+        _ => None,
+    }
+}
+
+/// Collect the parameters a permission check looks up, without duplicates.
+fn referenced_parameters(permission: &'static Permission, names: &mut Vec<&'static str>) {
+    fn add(names: &mut Vec<&'static str>, name: &'static str) {
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+
+    match permission {
+        Permission::UserParam(name) => add(names, name),
+        Permission::WithParam(name, subtest) => {
+            add(names, name);
+            referenced_parameters(subtest, names);
+        }
+        Permission::Privilege(path, _, _) => {
+            // like `check_api_permission`, a component may contain a whole path
+            for comp in path.iter().flat_map(|outer| outer.split('/')) {
+                if let Some(name) = comp.strip_prefix('{').and_then(|c| c.strip_suffix('}')) {
+                    add(names, name);
+                }
+            }
+        }
+        Permission::And(list) | Permission::Or(list) => {
+            for subtest in list.iter() {
+                referenced_parameters(subtest, names);
+            }
+        }
+        Permission::Superuser
+        | Permission::World
+        | Permission::Anybody
+        | Permission::User(_)
+        | Permission::Group(_) => {}
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use proxmox_schema::{ObjectSchema, StringSchema};
+
+    use super::*;
+    use crate::{ApiHandler, RpcEnvironment, SubdirMap};
+
+    const PRIV_READ: u64 = 1;
+    const PRIV_MODIFY: u64 = 2;
+
+    fn dummy(
+        _param: serde_json::Value,
+        _info: &ApiMethod,
+        _rpcenv: &mut dyn RpcEnvironment,
+    ) -> Result<serde_json::Value, anyhow::Error> {
+        Ok(serde_json::Value::Null)
+    }
+
+    const STORE_SCHEMA: ObjectSchema = ObjectSchema::new(
+        "Datastore parameters.",
+        &[(
+            "store",
+            false,
+            &StringSchema::new("Datastore name.").schema(),
+        )],
+    );
+
+    const API_METHOD_READ_STORE: ApiMethod =
+        ApiMethod::new(&ApiHandler::Sync(&dummy), &STORE_SCHEMA).access(
+            None,
+            &Permission::Privilege(&["datastore", "{store}"], PRIV_READ, false),
+        );
+
+    const API_METHOD_UPDATE_STORE: ApiMethod =
+        ApiMethod::new(&ApiHandler::Sync(&dummy), &STORE_SCHEMA).access(
+            None,
+            &Permission::Privilege(&["datastore", "{datastore}"], PRIV_MODIFY, false),
+        );
+
+    const API_METHOD_DELETE_STORE: ApiMethod =
+        ApiMethod::new(&ApiHandler::Sync(&dummy), &STORE_SCHEMA);
+
+    const API_METHOD_PING: ApiMethod =
+        ApiMethod::new(&ApiHandler::Sync(&dummy), &ObjectSchema::new("Ping.", &[]))
+            .access(None, &Permission::World);
+
+    const API_METHOD_RESET: ApiMethod =
+        ApiMethod::new(&ApiHandler::Sync(&dummy), &ObjectSchema::new("Reset.", &[])).access(
+            None,
+            &Permission::Or(&[&Permission::Superuser, &Permission::Anybody]),
+        );
+
+    const STORE_ROUTER: Router = Router::new()
+        .get(&API_METHOD_READ_STORE)
+        .put(&API_METHOD_UPDATE_STORE)
+        .delete(&API_METHOD_DELETE_STORE);
+
+    const SUBDIRS: SubdirMap = &[
+        (
+            "datastore",
+            &Router::new().match_all("store", &STORE_ROUTER),
+        ),
+        (
+            "ping",
+            &Router::new().get(&API_METHOD_PING).post(&API_METHOD_RESET),
+        ),
+    ];
+
+    const ROUTER: Router = Router::new().subdirs(SUBDIRS);
+
+    #[test]
+    fn test_referenced_parameters() {
+        let mut names = Vec::new();
+        referenced_parameters(
+            &Permission::And(&[
+                &Permission::Privilege(&["datastore", "{store}", "{ns}"], PRIV_READ, true),
+                &Permission::WithParam("owner", &Permission::UserParam("owner")),
+                &Permission::Privilege(&["datastore/{store}"], PRIV_MODIFY, false),
+            ]),
+            &mut names,
+        );
+        assert_eq!(names, ["store", "ns", "owner"]);
+    }
+
+    #[test]
+    fn test_audit_permissions() {
+        let findings = audit_permissions(&ROUTER);
+        let finding = |path: &str, method, kind| AuditFinding {
+            path: path.to_string(),
+            method,
+            kind,
+        };
+
+        assert_eq!(
+            findings,
+            [
+                finding(
+                    "/datastore/{store}",
+                    "PUT",
+                    AuditFindingKind::UnknownParameter("datastore"),
+                ),
+                finding(
+                    "/datastore/{store}",
+                    "DELETE",
+                    AuditFindingKind::MissingPermission,
+                ),
+                finding(
+                    "/ping",
+                    "POST",
+                    AuditFindingKind::PermissiveWrite("Anybody")
+                ),
+            ]
+        );
+        assert_eq!(
+            findings[0].to_string(),
+            "PUT /datastore/{store}: permission references unknown parameter 'datastore'"
+        );
+    }
+}
@@ -11,10 +11,14 @@ use proxmox_schema::*;
 use super::environment::CliEnvironment;
 use super::getopts;
 use super::{
-    generate_nested_usage, generate_usage_str_do, print_help, print_nested_usage_error,
-    print_simple_usage_error_do, CliCommand, CliCommandMap, CommandLineInterface, GlobalOptions,
+    exit_code, generate_nested_usage, generate_usage_str_do, help_requested, print_command_help,
+    print_group_help, print_help, print_nested_usage_error, print_simple_usage_error_do,
+    CliCommand, CliCommandMap, CommandLineInterface, GlobalOptions, UsageError, EXIT_LEGACY_ERROR,
+};
+use crate::{
+    ApiFuture, ApiHandler, ApiMethod, DispatchHooks, HookRequest, RawBody, RawResponse,
+    RpcEnvironment,
 };
-use crate::{ApiFuture, ApiHandler, ApiMethod, RpcEnvironment};
 
 /// Command line output format.
 #[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
@@ -49,11 +53,77 @@ pub const OUTPUT_FORMAT: Schema = StringSchema::new("Output format.")
     ]))
     .schema();
 
+/// Schema of the ``--output-file`` option of commands returning raw data.
+pub(crate) const OUTPUT_FILE_SCHEMA: Schema =
+    StringSchema::new("Write the raw output to this file instead of standard output.").schema();
+
+/// Check if the command returns raw data and gets the ``--output-file`` option.
+pub(crate) fn has_output_file_option(cli_cmd: &CliCommand) -> bool {
+    matches!(
+        cli_cmd.info.handler,
+        ApiHandler::Raw(_) | ApiHandler::RawAsync(_)
+    ) && cli_cmd.info.parameters.lookup("output-file").is_none()
+}
+
+/// Remove the ``--output-file`` option from the arguments.
+fn take_output_file(cli_cmd: &CliCommand, args: &mut Vec<String>) -> Result<Option<String>, Error> {
+    if !has_output_file_option(cli_cmd) {
+        return Ok(None);
+    }
+
+    let mut output_file = None;
+    let mut i = 0;
+    while i < args.len() {
+        if args[i] == "--" {
+            break;
+        } else if args[i] == "--output-file" {
+            if i + 1 == args.len() {
+                bail!("missing value for option '--output-file'");
+            }
+            output_file = Some(args.remove(i + 1));
+            args.remove(i);
+        } else if let Some(path) = args[i].strip_prefix("--output-file=") {
+            output_file = Some(path.to_string());
+            args.remove(i);
+        } else {
+            i += 1;
+        }
+    }
+
+    Ok(output_file)
+}
+
+async fn write_raw_response(response: RawResponse, output_file: Option<&str>) -> Result<(), Error> {
+    use futures::StreamExt;
+    use std::io::Write;
+
+    let mut output: Box<dyn Write + Send> = match output_file {
+        Some(path) => Box::new(
+            std::fs::File::create(path)
+                .map_err(|err| format_err!("unable to create output file {path:?} - {err}"))?,
+        ),
+        None => Box::new(std::io::stdout()),
+    };
+
+    match response.body {
+        RawBody::Bytes(data) => output.write_all(&data)?,
+        RawBody::Stream(mut stream) => {
+            while let Some(chunk) = stream.next().await {
+                output.write_all(&chunk?)?;
+            }
+        }
+    }
+    output.flush()?;
+
+    Ok(())
+}
+
 fn parse_arguments<'cli>(
     prefix: &str,
     cli_cmd: &CliCommand,
     args: Vec<String>,
     global_options_iter: impl Iterator<Item = &'cli GlobalOptions>,
+    rpcenv: &CliEnvironment,
 ) -> Result<Value, Error> {
     let (params, remaining) = match getopts::parse_arguments(
         &args,
@@ -63,28 +133,94 @@ fn parse_arguments<'cli>(
     ) {
         Ok((p, r)) => (p, r),
         Err(err) => {
-            let err_msg = err.to_string();
-            print_simple_usage_error_do(prefix, cli_cmd, &err_msg, global_options_iter);
-            return Err(format_err!("{}", err_msg));
+            if !rpcenv.error_output.is_structured() {
+                let err_msg = err.to_string();
+                print_simple_usage_error_do(prefix, cli_cmd, &err_msg, global_options_iter);
+            }
+            return Err(err.into());
         }
     };
 
     if !remaining.is_empty() {
         let err_msg = format!("got additional arguments: {:?}", remaining);
-        print_simple_usage_error_do(prefix, cli_cmd, &err_msg, global_options_iter);
-        return Err(format_err!("{}", err_msg));
+        if !rpcenv.error_output.is_structured() {
+            print_simple_usage_error_do(prefix, cli_cmd, &err_msg, global_options_iter);
+        }
+        return Err(UsageError::new(format_err!("{}", err_msg)).into());
     }
 
     Ok(params)
 }
 
+/// Printed to standard error before and after the output of a dry run, see
+/// [`ApiMethod::supports_dry_run`].
+pub const DRY_RUN_BANNER: &str = "DRY RUN — no changes made";
+
+fn print_result(
+    result: Result<Value, Error>,
+    dry_run: bool,
+    rpcenv: &CliEnvironment,
+) -> Result<(), Error> {
+    let result = match result {
+        Ok(value) => {
+            if value != Value::Null {
+                println!("Result: {}", serde_json::to_string_pretty(&value).unwrap());
+            }
+            Ok(())
+        }
+        Err(err) => {
+            rpcenv.error_output.print_message(&err);
+            Err(err)
+        }
+    };
+
+    if dry_run {
+        rpcenv.error_output.print_notice(DRY_RUN_BANNER);
+    }
+
+    result
+}
+
+fn hook_request<'a>(
+    cli_cmd: &CliCommand,
+    params: Option<&'a Value>,
+    rpcenv: &CliEnvironment,
+) -> HookRequest<'a> {
+    HookRequest {
+        method: None,
+        api_method: cli_cmd.info,
+        params: params.unwrap_or(&Value::Null),
+        auth_id: rpcenv.get_auth_id(),
+    }
+}
+
 async fn handle_simple_command_future(
     prefix: &str,
     cli_cmd: &CliCommand,
-    args: Vec<String>,
+    mut args: Vec<String>,
     mut rpcenv: CliEnvironment,
+    hooks: &DispatchHooks,
 ) -> Result<(), Error> {
-    let params = parse_arguments(prefix, cli_cmd, args, [].into_iter())?;
+    if help_requested(&args, Some(&cli_cmd.info.parameters)) {
+        print_command_help(prefix, cli_cmd, [].into_iter());
+        return Ok(());
+    }
+
+    let output_file = take_output_file(cli_cmd, &mut args)?;
+    let params = parse_arguments(prefix, cli_cmd, args, [].into_iter(), &rpcenv)?;
+
+    let hook_params = (!hooks.is_empty()).then(|| params.clone());
+    let request = hook_request(cli_cmd, hook_params.as_ref(), &rpcenv);
+    if let Err(err) = hooks.pre_dispatch(&request, &mut rpcenv) {
+        hooks.post_dispatch(&request, Err(&err));
+        rpcenv.error_output.print_message(&err);
+        return Err(err);
+    }
+
+    let dry_run = cli_cmd.info.is_dry_run(&params);
+    if dry_run {
+        rpcenv.error_output.print_notice(DRY_RUN_BANNER);
+    }
 
     let result = match cli_cmd.info.handler {
         ApiHandler::Sync(handler) => (handler)(params, cli_cmd.info, &mut rpcenv),
@@ -103,25 +239,26 @@ async fn handle_simple_command_future(
                 Err(err) => Err(err),
             }
         }
+        ApiHandler::Raw(handler) => match (handler)(params, cli_cmd.info, &mut rpcenv) {
+            Ok(response) => write_raw_response(response, output_file.as_deref())
+                .await
+                .map(|()| Value::Null),
+            Err(err) => Err(err),
+        },
+        ApiHandler::RawAsync(handler) => match (handler)(params, cli_cmd.info, &mut rpcenv).await {
+            Ok(response) => write_raw_response(response, output_file.as_deref())
+                .await
+                .map(|()| Value::Null),
+            Err(err) => Err(err),
+        },
         #[cfg(feature = "server")]
         ApiHandler::AsyncHttp(_) => {
             bail!("CliHandler does not support ApiHandler::AsyncHttp - internal error")
         }
     };
+    hooks.post_dispatch(&request, result.as_ref().map(|_| ()));
 
-    match result {
-        Ok(value) => {
-            if value != Value::Null {
-                println!("Result: {}", serde_json::to_string_pretty(&value).unwrap());
-            }
-        }
-        Err(err) => {
-            eprintln!("Error: {err:?}");
-            return Err(err);
-        }
-    }
-
-    Ok(())
+    print_result(result, dry_run, &rpcenv)
 }
 
 pub(crate) fn handle_simple_command<'cli>(
@@ -131,8 +268,29 @@ pub(crate) fn handle_simple_command<'cli>(
     rpcenv: &mut CliEnvironment,
     run: Option<fn(ApiFuture) -> Result<Value, Error>>,
     global_options_iter: impl Iterator<Item = &'cli GlobalOptions>,
+    hooks: &DispatchHooks,
 ) -> Result<(), Error> {
-    let params = parse_arguments(prefix, cli_cmd, args, global_options_iter)?;
+    if help_requested(&args, Some(&cli_cmd.info.parameters)) {
+        print_command_help(prefix, cli_cmd, global_options_iter);
+        return Ok(());
+    }
+
+    let mut args = args;
+    let output_file = take_output_file(cli_cmd, &mut args)?;
+    let params = parse_arguments(prefix, cli_cmd, args, global_options_iter, rpcenv)?;
+
+    let hook_params = (!hooks.is_empty()).then(|| params.clone());
+    let request = hook_request(cli_cmd, hook_params.as_ref(), rpcenv);
+    if let Err(err) = hooks.pre_dispatch(&request, rpcenv) {
+        hooks.post_dispatch(&request, Err(&err));
+        rpcenv.error_output.print_message(&err);
+        return Err(err);
+    }
+
+    let dry_run = cli_cmd.info.is_dry_run(&params);
+    if dry_run {
+        rpcenv.error_output.print_notice(DRY_RUN_BANNER);
+    }
 
     let result = match cli_cmd.info.handler {
         ApiHandler::Sync(handler) => (handler)(params, cli_cmd.info, rpcenv),
@@ -155,37 +313,50 @@ pub(crate) fn handle_simple_command<'cli>(
         ApiHandler::StreamAsync(_handler) => {
             bail!("CliHandler does not support ApiHandler::StreamAsync - internal error");
         }
+        ApiHandler::Raw(handler) => (handler)(params, cli_cmd.info, rpcenv).and_then(|response| {
+            proxmox_async::runtime::block_on(write_raw_response(response, output_file.as_deref()))
+                .map(|()| Value::Null)
+        }),
+        ApiHandler::RawAsync(handler) => {
+            let run = run.ok_or_else(|| {
+                format_err!("CliHandler does not support ApiHandler::RawAsync - internal error")
+            })?;
+            let future = (handler)(params, cli_cmd.info, rpcenv);
+            (run)(Box::pin(async move {
+                let response = future.await?;
+                write_raw_response(response, output_file.as_deref()).await?;
+                Ok(Value::Null)
+            }))
+        }
         #[cfg(feature = "server")]
         ApiHandler::AsyncHttp(_) => {
             bail!("CliHandler does not support ApiHandler::AsyncHttp - internal error");
         }
     };
+    hooks.post_dispatch(&request, result.as_ref().map(|_| ()));
 
-    match result {
-        Ok(value) => {
-            if value != Value::Null {
-                println!("Result: {}", serde_json::to_string_pretty(&value).unwrap());
-            }
-        }
-        Err(err) => {
-            eprintln!("Error: {err:?}");
-            return Err(err);
-        }
-    }
-
-    Ok(())
+    print_result(result, dry_run, rpcenv)
 }
 
-fn parse_nested_command<'a>(
+/// Find the simple command to run.
+///
+/// Returns `None` if the ``--help`` option was given for a command group, after printing its
+/// help.
+pub(crate) fn parse_nested_command<'a>(
     prefix: &mut String,
     def: &'a CliCommandMap,
     args: &mut Vec<String>,
-) -> Result<&'a CliCommand, Error> {
+    hooks: &mut DispatchHooks,
+    rpcenv: &CliEnvironment,
+) -> Result<Option<&'a CliCommand>, Error> {
     let mut map = def;
 
     // Note: Avoid async recursive function, because current rust compiler can't handle that
     loop {
         replace_aliases(args, &map.aliases);
+        if let Some(map_hooks) = map.hooks {
+            hooks.push(map_hooks);
+        }
 
         if args.is_empty() {
             let mut cmds: Vec<&String> = map.commands.keys().collect();
@@ -200,8 +371,15 @@ fn parse_nested_command<'a>(
             });
 
             let err_msg = format!("no command specified.\nPossible commands: {}", list);
-            print_nested_usage_error(prefix, map, &err_msg);
-            return Err(format_err!("{}", err_msg));
+            if !rpcenv.error_output.is_structured() {
+                print_nested_usage_error(prefix, map, &err_msg);
+            }
+            return Err(UsageError::new(format_err!("{}", err_msg)).into());
+        }
+
+        if args[0] == "--help" {
+            print_group_help(prefix, map);
+            return Ok(None);
         }
 
         let command = args.remove(0);
@@ -210,8 +388,10 @@ fn parse_nested_command<'a>(
             Some(cmd) => cmd,
             None => {
                 let err_msg = format!("no such command '{}'", command);
-                print_nested_usage_error(prefix, map, &err_msg);
-                return Err(format_err!("{}", err_msg));
+                if !rpcenv.error_output.is_structured() {
+                    print_nested_usage_error(prefix, map, &err_msg);
+                }
+                return Err(UsageError::new(format_err!("{}", err_msg)).into());
             }
         };
 
@@ -220,7 +400,10 @@ fn parse_nested_command<'a>(
         match sub_cmd {
             CommandLineInterface::Simple(cli_cmd) => {
                 //return handle_simple_command(&prefix, cli_cmd, args).await;
-                return Ok(cli_cmd);
+                if let Some(cmd_hooks) = cli_cmd.hooks {
+                    hooks.push(cmd_hooks);
+                }
+                return Ok(Some(cli_cmd));
             }
             CommandLineInterface::Nested(new_map) => map = new_map,
         }
@@ -318,27 +501,47 @@ pub(crate) fn replace_aliases(
 ///
 /// This command gets the command line ``args`` and tries to invoke
 /// the corresponding API handler.
+///
+/// In JSON output mode, an [`ErrorReport`](super::ErrorReport) is printed before returning an
+/// error.
 pub async fn handle_command_future(
     def: Arc<CommandLineInterface>,
     prefix: &str,
     mut args: Vec<String>,
-    rpcenv: CliEnvironment,
+    mut rpcenv: CliEnvironment,
 ) -> Result<(), Error> {
     set_help_context(Some(def.clone()));
 
+    rpcenv.error_output.detect(&args);
+    let error_output = rpcenv.error_output.clone();
+    let legacy_exit_codes = rpcenv.legacy_exit_codes;
+
+    let mut hooks = DispatchHooks::default();
     let result = match &*def {
         CommandLineInterface::Simple(ref cli_cmd) => {
-            handle_simple_command_future(prefix, cli_cmd, args, rpcenv).await
+            if let Some(cmd_hooks) = cli_cmd.hooks {
+                hooks.push(cmd_hooks);
+            }
+            handle_simple_command_future(prefix, cli_cmd, args, rpcenv, &hooks).await
         }
         CommandLineInterface::Nested(ref map) => {
             let mut prefix = prefix.to_string();
-            let cli_cmd = parse_nested_command(&mut prefix, map, &mut args)?;
-            handle_simple_command_future(&prefix, cli_cmd, args, rpcenv).await
+            match parse_nested_command(&mut prefix, map, &mut args, &mut hooks, &rpcenv) {
+                Ok(Some(cli_cmd)) => {
+                    handle_simple_command_future(&prefix, cli_cmd, args, rpcenv, &hooks).await
+                }
+                Ok(None) => Ok(()),
+                Err(err) => Err(err),
+            }
         }
     };
 
     set_help_context(None);
 
+    if let Err(err) = &result {
+        error_output.print(err, error_exit_code(err, legacy_exit_codes));
+    }
+
     result
 }
 
@@ -346,6 +549,9 @@ pub async fn handle_command_future(
 ///
 /// This command gets the command line ``args`` and tries to invoke
 /// the corresponding API handler.
+///
+/// In JSON output mode, an [`ErrorReport`](super::ErrorReport) is printed before returning an
+/// error.
 pub fn handle_command(
     def: Arc<CommandLineInterface>,
     prefix: &str,
@@ -355,19 +561,50 @@ pub fn handle_command(
 ) -> Result<(), Error> {
     set_help_context(Some(def.clone()));
 
+    rpcenv.error_output.detect(&args);
+
+    let mut hooks = DispatchHooks::default();
     let result = match &*def {
         CommandLineInterface::Simple(ref cli_cmd) => {
-            handle_simple_command(prefix, cli_cmd, args, &mut rpcenv, run, [].into_iter())
+            if let Some(cmd_hooks) = cli_cmd.hooks {
+                hooks.push(cmd_hooks);
+            }
+            let global_options = [].into_iter();
+            handle_simple_command(
+                prefix,
+                cli_cmd,
+                args,
+                &mut rpcenv,
+                run,
+                global_options,
+                &hooks,
+            )
         }
         CommandLineInterface::Nested(ref map) => {
             let mut prefix = prefix.to_string();
-            let cli_cmd = parse_nested_command(&mut prefix, map, &mut args)?;
-            handle_simple_command(&prefix, cli_cmd, args, &mut rpcenv, run, [].into_iter())
+            match parse_nested_command(&mut prefix, map, &mut args, &mut hooks, &rpcenv) {
+                Ok(Some(cli_cmd)) => handle_simple_command(
+                    &prefix,
+                    cli_cmd,
+                    args,
+                    &mut rpcenv,
+                    run,
+                    [].into_iter(),
+                    &hooks,
+                ),
+                Ok(None) => Ok(()),
+                Err(err) => Err(err),
+            }
         }
     };
 
     set_help_context(None);
 
+    if let Err(err) = &result {
+        let exit_code = error_exit_code(err, rpcenv.legacy_exit_codes);
+        rpcenv.error_output.print(err, exit_code);
+    }
+
     result
 }
 
@@ -463,11 +700,9 @@ where
 
     let (prefix, args) = prepare_cli_command(&def, args.into_iter());
 
-    if handle_command_future(Arc::new(def), &prefix, args, rpcenv)
-        .await
-        .is_err()
-    {
-        std::process::exit(-1);
+    let legacy_exit_codes = rpcenv.legacy_exit_codes;
+    if let Err(err) = handle_command_future(Arc::new(def), &prefix, args, rpcenv).await {
+        std::process::exit(error_exit_code(&err, legacy_exit_codes));
     }
 }
 
@@ -492,7 +727,260 @@ pub fn run_cli_command_with_args<A, C>(
 
     let (prefix, args) = prepare_cli_command(&def, args.into_iter());
 
-    if handle_command(Arc::new(def), &prefix, args, rpcenv, run).is_err() {
-        std::process::exit(-1);
+    let legacy_exit_codes = rpcenv.legacy_exit_codes;
+    if let Err(err) = handle_command(Arc::new(def), &prefix, args, rpcenv, run) {
+        std::process::exit(error_exit_code(&err, legacy_exit_codes));
+    }
+}
+
+pub(crate) fn error_exit_code(err: &Error, legacy: bool) -> i32 {
+    if legacy {
+        EXIT_LEGACY_ERROR
+    } else {
+        exit_code(err)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use anyhow::Error;
+    use serde_json::{json, Value};
+
+    use proxmox_schema::format::DocumentationFormat;
+    use proxmox_schema::{ApiType, IntegerSchema, ObjectSchema, ReturnType};
+
+    use super::{error_exit_code, handle_command, OUTPUT_FORMAT};
+    use crate::cli::{
+        exit_code, generate_usage_str, CliCommand, CliCommandMap, CliEnvironment,
+        CommandLineInterface, ErrorCategory, ErrorReport, DRY_RUN_BANNER, EXIT_LEGACY_ERROR,
+        EXIT_NOT_FOUND, EXIT_USAGE,
+    };
+    use crate::{http_err, ApiHandler, ApiMethod, RawResponse, RpcEnvironment, DRY_RUN_SCHEMA};
+
+    fn get_data(
+        _param: Value,
+        _info: &ApiMethod,
+        _rpcenv: &mut dyn RpcEnvironment,
+    ) -> Result<RawResponse, Error> {
+        Ok(RawResponse::new(vec![0, 1, 2, 255]))
+    }
+
+    const API_METHOD_GET_DATA: ApiMethod = ApiMethod::new(
+        &ApiHandler::Raw(&get_data),
+        &ObjectSchema::new("Get some data.", &[]),
+    )
+    .returns(ReturnType::raw("application/octet-stream"));
+
+    #[test]
+    fn test_raw_output_file() -> Result<(), Error> {
+        let cmd = CliCommand::new(&API_METHOD_GET_DATA);
+        let usage = generate_usage_str("data", &cmd, DocumentationFormat::Long, "", &[]);
+        assert!(usage.contains("--output-file <string>"), "{usage}");
+
+        let path = std::env::temp_dir().join(format!("proxmox-router-raw-{}", std::process::id()));
+        for args in [
+            vec!["--output-file".to_string(), path.display().to_string()],
+            vec![format!("--output-file={}", path.display())],
+        ] {
+            let _ = std::fs::remove_file(&path);
+            handle_command(
+                Arc::new(CliCommand::new(&API_METHOD_GET_DATA).into()),
+                "data",
+                args,
+                CliEnvironment::new(),
+                None,
+            )?;
+            assert_eq!(std::fs::read(&path)?, [0, 1, 2, 255]);
+        }
+        std::fs::remove_file(&path)?;
+
+        let args = vec!["--output-file".to_string()];
+        let cli = Arc::new(CliCommand::new(&API_METHOD_GET_DATA).into());
+        assert!(handle_command(cli, "data", args, CliEnvironment::new(), None).is_err());
+
+        Ok(())
+    }
+
+    fn remove_data(
+        param: Value,
+        info: &ApiMethod,
+        _rpcenv: &mut dyn RpcEnvironment,
+    ) -> Result<Value, Error> {
+        assert_eq!(
+            info.is_dry_run(&param),
+            param["dry-run"].as_bool().unwrap_or(false)
+        );
+        Ok(Value::Null)
+    }
+
+    const API_METHOD_REMOVE_DATA: ApiMethod = ApiMethod::new(
+        &ApiHandler::Sync(&remove_data),
+        &ObjectSchema::new("Remove some data.", &[("dry-run", true, &DRY_RUN_SCHEMA)]),
+    );
+
+    const API_METHOD_WIPE_DATA: ApiMethod = ApiMethod::new(
+        &ApiHandler::Sync(&remove_data),
+        &ObjectSchema::new("Wipe some data.", &[]),
+    );
+
+    #[test]
+    fn test_dry_run() -> Result<(), Error> {
+        // runs the command, returning the error output
+        let run = |method: &'static ApiMethod, args: &[&str]| {
+            let args = args.iter().map(|arg| arg.to_string()).collect();
+            let cli = Arc::new(CliCommand::new(method).into());
+            let output = Arc::new(Mutex::new(Vec::<u8>::new()));
+            let mut rpcenv = CliEnvironment::new();
+            rpcenv.error_output.writer = Some(output.clone());
+            handle_command(cli, "data", args, rpcenv, None)?;
+            let output = String::from_utf8(output.lock().unwrap().clone())?;
+            Ok::<_, Error>(output)
+        };
+
+        let cmd = CliCommand::new(&API_METHOD_REMOVE_DATA);
+        let usage = generate_usage_str("data", &cmd, DocumentationFormat::Long, "", &[]);
+        assert!(usage.contains("--dry-run  <boolean>"), "{usage}");
+        assert!(usage.contains("without making any changes"), "{usage}");
+
+        assert!(API_METHOD_REMOVE_DATA.is_dry_run(&json!({ "dry-run": true })));
+        assert!(!API_METHOD_REMOVE_DATA.is_dry_run(&json!({ "dry-run": false })));
+        assert!(!API_METHOD_WIPE_DATA.is_dry_run(&json!({ "dry-run": true })));
+
+        // the banner is printed before and after the output of dry runs only
+        let banner = format!("{DRY_RUN_BANNER}\n{DRY_RUN_BANNER}\n");
+        assert_eq!(run(&API_METHOD_REMOVE_DATA, &["--dry-run"])?, banner);
+        assert_eq!(run(&API_METHOD_REMOVE_DATA, &["--dry-run", "0"])?, "");
+        assert_eq!(run(&API_METHOD_REMOVE_DATA, &[])?, "");
+
+        assert_eq!(run(&API_METHOD_WIPE_DATA, &[])?, "");
+        let err = run(&API_METHOD_WIPE_DATA, &["--dry-run"]).unwrap_err();
+        assert_eq!(exit_code(&err), EXIT_USAGE);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_usage_exit_code() {
+        let run = |cli: CommandLineInterface, args: &[&str]| {
+            let args = args.iter().map(|arg| arg.to_string()).collect();
+            let err = handle_command(Arc::new(cli), "data", args, CliEnvironment::new(), None)
+                .expect_err("command should fail");
+            exit_code(&err)
+        };
+
+        let cmd = || CliCommand::new(&API_METHOD_GET_DATA);
+        assert_eq!(run(cmd().into(), &["--no-such-option", "1"]), EXIT_USAGE);
+        assert_eq!(run(cmd().into(), &["extra"]), EXIT_USAGE);
+
+        let map = || CliCommandMap::new().insert("get", cmd()).into();
+        assert_eq!(run(map(), &[]), EXIT_USAGE);
+        assert_eq!(run(map(), &["put"]), EXIT_USAGE);
+    }
+
+    #[test]
+    fn test_help_option() -> Result<(), Error> {
+        let run = |cli: CommandLineInterface, args: &[&str]| {
+            let args = args.iter().map(|arg| arg.to_string()).collect();
+            handle_command(Arc::new(cli), "data", args, CliEnvironment::new(), None)
+        };
+
+        // the command is not executed, so it does not complain about the missing output file
+        let cmd = || CliCommand::new(&API_METHOD_GET_DATA);
+        run(cmd().into(), &["--output-file", "--help"])?;
+        run(cmd().into(), &["--help", "extra"])?;
+        assert!(run(cmd().into(), &["--", "--help"]).is_err());
+
+        let map = || CliCommandMap::new().insert("get", cmd()).into();
+        run(map(), &["--help"])?;
+        run(map(), &["get", "--help"])?;
+
+        Ok(())
+    }
+
+    fn get_node(
+        param: Value,
+        _info: &ApiMethod,
+        _rpcenv: &mut dyn RpcEnvironment,
+    ) -> Result<Value, Error> {
+        Err(http_err!(NOT_FOUND, "no such node '{}'", param["node"]))
+    }
+
+    const API_METHOD_GET_NODE: ApiMethod = ApiMethod::new(
+        &ApiHandler::Sync(&get_node),
+        &ObjectSchema::new(
+            "Get a node.",
+            &[
+                ("node", false, &IntegerSchema::new("The node.").schema()),
+                ("output-format", true, &OUTPUT_FORMAT),
+            ],
+        ),
+    );
+
+    #[test]
+    fn test_error_report() {
+        // runs the command, returning the error output and the exit code
+        let run = |cli: CommandLineInterface, args: &[&str], legacy: bool| {
+            let args = args.iter().map(|arg| arg.to_string()).collect();
+            let output = Arc::new(Mutex::new(Vec::<u8>::new()));
+            let mut rpcenv = CliEnvironment::new();
+            rpcenv.set_legacy_exit_codes(legacy);
+            rpcenv.error_output.writer = Some(output.clone());
+            let err = handle_command(Arc::new(cli), "node", args, rpcenv, None)
+                .expect_err("command should fail");
+            let output = String::from_utf8(output.lock().unwrap().clone()).unwrap();
+            (output, error_exit_code(&err, legacy))
+        };
+        let report = |output: &str| {
+            let value: Value = serde_json::from_str(output).unwrap();
+            ErrorReport::API_SCHEMA.verify_json(&value).unwrap();
+            value
+        };
+
+        let cmd = || CliCommand::new(&API_METHOD_GET_NODE).arg_param(&["node"]);
+
+        let (output, _) = run(cmd().into(), &["--output-format", "json", "one"], false);
+        let value = report(&output);
+        assert_eq!(value["category"], ErrorCategory::Usage.to_string());
+        assert_eq!(value["exit-code"], EXIT_USAGE);
+        assert_eq!(value["message"], "parameter verification failed");
+        assert!(value["errors"]["node"].is_string(), "{value}");
+        assert!(value.get("status").is_none());
+
+        // a single line without the human readable message
+        let (output, exit_code) = run(cmd().into(), &["--output-format=json", "1"], false);
+        assert_eq!(exit_code, EXIT_NOT_FOUND);
+        assert_eq!(
+            output,
+            "{\"category\":\"not-found\",\"message\":\"no such node '1'\",\"status\":404,\
+            \"exit-code\":5}\n"
+        );
+
+        let (pretty, _) = run(
+            cmd().into(),
+            &["--output-format", "json-pretty", "1"],
+            false,
+        );
+        assert!(
+            pretty.starts_with("{\n  \"category\": \"not-found\",\n"),
+            "{pretty}"
+        );
+        assert_eq!(report(&pretty), report(&output));
+
+        // the human readable message otherwise
+        let (output, _) = run(cmd().into(), &["1"], false);
+        assert!(output.starts_with("Error: no such node '1'"), "{output}");
+
+        // the exit code is reported as the calling process sees it
+        let (output, exit_code) = run(cmd().into(), &["--output-format=json", "1"], true);
+        assert_eq!(exit_code, EXIT_LEGACY_ERROR);
+        assert_eq!(report(&output)["exit-code"], 255);
+
+        let map = || CliCommandMap::new().insert("get", cmd()).into();
+        let (output, _) = run(map(), &["put", "--output-format", "json"], false);
+        let value = report(&output);
+        assert_eq!(value["category"], "usage");
+        assert_eq!(value["message"], "no such command 'put'");
     }
 }
@@ -119,6 +119,44 @@ fn get_simple_completion_do(
         if let Some((optional, schema)) = cli_cmd.info.parameters.lookup(prop_name) {
             let is_array_param = matches!(schema, Schema::Array(_));
 
+            if args[0] == "--" && args.len() > 1 {
+                // skip the separator, the following arguments are positional
+                return get_simple_completion_do(
+                    cli_cmd,
+                    global_option_schemas,
+                    completion_functions,
+                    done,
+                    arg_param,
+                    &args[1..],
+                );
+            }
+
+            if is_array_param && !args[0].starts_with('-') {
+                // the items of a trailing array argument, up to the next option
+                let count = args.iter().take_while(|arg| !arg.starts_with('-')).count();
+                if count == args.len() {
+                    let (last, items) = args.split_last().unwrap();
+                    return get_property_completion(
+                        schema,
+                        prop_name,
+                        completion_functions,
+                        last,
+                        done,
+                    )
+                    .into_iter()
+                    .filter(|value| !items.contains(value))
+                    .collect();
+                }
+                return get_simple_completion_do(
+                    cli_cmd,
+                    global_option_schemas,
+                    completion_functions,
+                    done,
+                    arg_param,
+                    &args[count..],
+                );
+            }
+
             if (optional || is_array_param) && args[0].starts_with('-') {
                 // argument parameter is optional (or array) , and arg
                 // looks like an option, so assume its empty and
@@ -126,25 +164,14 @@ fn get_simple_completion_do(
             } else {
                 record_done_argument(done, cli_cmd.info.parameters, prop_name, &args[0]);
                 if args.len() > 1 {
-                    if is_array_param {
-                        return get_simple_completion_do(
-                            cli_cmd,
-                            global_option_schemas,
-                            completion_functions,
-                            done,
-                            arg_param,
-                            &args[1..],
-                        );
-                    } else {
-                        return get_simple_completion_do(
-                            cli_cmd,
-                            global_option_schemas,
-                            completion_functions,
-                            done,
-                            &arg_param[1..],
-                            &args[1..],
-                        );
-                    }
+                    return get_simple_completion_do(
+                        cli_cmd,
+                        global_option_schemas,
+                        completion_functions,
+                        done,
+                        &arg_param[1..],
+                        &args[1..],
+                    );
                 }
 
                 if args.len() == 1 {
@@ -519,7 +546,8 @@ mod test {
     use serde_json::Value;
 
     use proxmox_schema::{
-        ApiStringFormat, ApiType, BooleanSchema, EnumEntry, ObjectSchema, Schema, StringSchema,
+        ApiStringFormat, ApiType, ArraySchema, BooleanSchema, EnumEntry, ObjectSchema, Schema,
+        StringSchema,
     };
 
     use crate::cli::{CliCommand, CliCommandMap, CommandLineInterface, GlobalOptions};
@@ -554,6 +582,35 @@ mod test {
         ),
     );
 
+    const API_METHOD_TAGS: ApiMethod = ApiMethod::new(
+        &ApiHandler::Sync(&dummy_method),
+        &ObjectSchema::new(
+            "API method with an array argument.",
+            &[
+                (
+                    "force",
+                    true,
+                    &BooleanSchema::new("Optional boolean argument.").schema(),
+                ),
+                (
+                    "tag",
+                    false,
+                    &ArraySchema::new(
+                        "Tags.",
+                        &StringSchema::new("Tag.")
+                            .format(&ApiStringFormat::Enum(&[
+                                EnumEntry::new("red", "Red."),
+                                EnumEntry::new("green", "Green."),
+                                EnumEntry::new("blue", "Blue."),
+                            ]))
+                            .schema(),
+                    )
+                    .schema(),
+                ),
+            ],
+        ),
+    );
+
     #[allow(dead_code)]
     struct GlobalOpts {
         global: String,
@@ -764,4 +821,21 @@ mod test {
 
         test_completions(&cmd_def, "help l0sub l1c3", 11, &[]);
     }
+
+    #[test]
+    fn test_variadic_completion() {
+        let cmd_def: CommandLineInterface = CliCommandMap::new()
+            .insert(
+                "remove",
+                CliCommand::new(&API_METHOD_TAGS).arg_param(&["tag"]),
+            )
+            .into();
+
+        test_completions(&cmd_def, "remove ", 7, &["red", "green", "blue"]);
+        test_completions(&cmd_def, "remove r", 7, &["red"]);
+        test_completions(&cmd_def, "remove red ", 11, &["green", "blue"]);
+        test_completions(&cmd_def, "remove red blue ", 16, &["green"]);
+        test_completions(&cmd_def, "remove red -", 11, &["--force"]);
+        test_completions(&cmd_def, "remove -- g", 10, &["green"]);
+    }
 }
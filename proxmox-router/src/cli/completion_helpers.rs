@@ -1,10 +1,14 @@
 use std::collections::HashMap;
 use std::path::PathBuf;
+use std::sync::Arc;
 
 use nix::dir::Dir;
 use nix::fcntl::{AtFlags, OFlag};
 use nix::sys::stat::{fstatat, Mode};
 
+use super::CliEnvironment;
+use crate::{Router, RpcEnvironment, UserInformation};
+
 pub fn complete_file_name(arg: &str, _param: &HashMap<String, String>) -> Vec<String> {
     let mut result = vec![];
 
@@ -51,3 +55,26 @@ pub fn complete_file_name(arg: &str, _param: &HashMap<String, String>) -> Vec<St
 
     result
 }
+
+/// Complete an API path of `router`, see [`Router::complete_path`].
+///
+/// The children of `MatchAll` routers are listed as permitted for `auth_id` by `user_info`. Meant
+/// to be called from a [completion function](super::CompletionFunction):
+///
+/// ```ignore
+/// fn complete_api_path(arg: &str, _param: &HashMap<String, String>) -> Vec<String> {
+///     proxmox_router::cli::complete_api_path(&API_ROUTER, arg, "root@pam", Arc::new(UserInfo))
+/// }
+/// ```
+pub fn complete_api_path(
+    router: &Router,
+    arg: &str,
+    auth_id: &str,
+    user_info: Arc<dyn UserInformation + Send + Sync>,
+) -> Vec<String> {
+    let mut rpcenv = CliEnvironment::new();
+    rpcenv.set_auth_id(Some(auth_id.to_string()));
+    rpcenv.set_user_info(Some(user_info));
+
+    proxmox_async::runtime::block_on(router.complete_path(arg, &mut rpcenv))
+}
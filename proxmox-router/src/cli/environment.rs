@@ -1,17 +1,23 @@
 use std::any::{Any, TypeId};
 use std::collections::HashMap;
+use std::sync::Arc;
 
 use serde_json::Value;
 
 use proxmox_schema::ApiType;
 
-use crate::{RpcEnvironment, RpcEnvironmentType};
+use super::error_report::ErrorOutput;
+use super::OutputFormat;
+use crate::{RpcEnvironment, RpcEnvironmentType, UserInformation};
 
 /// `RpcEnvironment` implementation for command line tools
 #[derive(Default)]
 pub struct CliEnvironment {
     result_attributes: Value,
     auth_id: Option<String>,
+    user_info: Option<Arc<dyn UserInformation + Send + Sync>>,
+    pub(crate) legacy_exit_codes: bool,
+    pub(crate) error_output: ErrorOutput,
     pub(crate) global_options: HashMap<TypeId, Box<dyn Any + Send + Sync + 'static>>,
 }
 
@@ -20,6 +26,28 @@ impl CliEnvironment {
         Default::default()
     }
 
+    /// Exit with `-1` on all errors instead of the [exit code](super::exit_code) derived from the
+    /// error.
+    pub fn set_legacy_exit_codes(&mut self, legacy: bool) {
+        self.legacy_exit_codes = legacy;
+    }
+
+    /// Print errors in this format, with JSON formats printing an [`ErrorReport`].
+    ///
+    /// By default, the format is taken from the ``--output-format`` option or the
+    /// [`PROXMOX_OUTPUT_FORMAT`](super::ENV_VAR_PROXMOX_OUTPUT_FORMAT) environment variable.
+    ///
+    /// [`ErrorReport`]: super::ErrorReport
+    pub fn set_error_format(&mut self, format: OutputFormat) {
+        self.error_output.format = Some(format);
+    }
+
+    /// Print an [`ErrorReport`](super::ErrorReport) to standard output instead of standard
+    /// error.
+    pub fn set_error_report_to_stdout(&mut self, stdout: bool) {
+        self.error_output.stdout = stdout;
+    }
+
     /// Get a specific command line argument type.
     pub fn global_option<T>(&self) -> Option<&T>
     where
@@ -81,4 +109,12 @@ impl RpcEnvironment for CliEnvironment {
     fn get_auth_id(&self) -> Option<String> {
         self.auth_id.clone()
     }
+
+    fn set_user_info(&mut self, user_info: Option<Arc<dyn UserInformation + Send + Sync>>) {
+        self.user_info = user_info;
+    }
+
+    fn get_user_info(&self) -> Option<Arc<dyn UserInformation + Send + Sync>> {
+        self.user_info.clone()
+    }
 }
@@ -0,0 +1,290 @@
+//! Machine readable errors for wrapper tools parsing the output of command line tools.
+//!
+//! If JSON output is requested with ``--output-format json`` (or ``json-pretty``), or with the
+//! [`PROXMOX_OUTPUT_FORMAT`](super::ENV_VAR_PROXMOX_OUTPUT_FORMAT) environment variable, a failed
+//! command prints a single [`ErrorReport`] instead of the human readable error message and usage.
+
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+use anyhow::Error;
+use serde::{Deserialize, Serialize};
+
+use proxmox_schema::{
+    ApiStringFormat, ApiType, EnumEntry, IntegerSchema, ObjectSchema, ParameterError, Schema,
+    StringSchema,
+};
+
+use super::exit_code::{find_cause, is_marker_error};
+use super::{ErrorCategory, OutputFormat, ENV_VAR_PROXMOX_OUTPUT_FORMAT};
+use crate::HttpError;
+
+/// Schema of the [`ErrorCategory`].
+pub const ERROR_CATEGORY_SCHEMA: Schema = StringSchema::new("The category of the error.")
+    .format(&ApiStringFormat::Enum(&[
+        EnumEntry::new("error", "Any other error."),
+        EnumEntry::new("usage", "Invalid usage or parameters."),
+        EnumEntry::new("permission-denied", "The permission was denied."),
+        EnumEntry::new("not-found", "Something does not exist."),
+        EnumEntry::new("transport", "The connection to a server failed."),
+        EnumEntry::new("task-failed", "A task finished with an error."),
+    ]))
+    .schema();
+
+impl ApiType for ErrorCategory {
+    const API_SCHEMA: Schema = ERROR_CATEGORY_SCHEMA;
+}
+
+/// Schema of the [`ErrorReport`].
+pub const ERROR_REPORT_SCHEMA: Schema = ObjectSchema::new(
+    "The error a command failed with.",
+    &[
+        ("category", false, &ERROR_CATEGORY_SCHEMA),
+        (
+            "errors",
+            true,
+            &ObjectSchema::new("The error message of each invalid parameter.", &[])
+                .additional_properties(true)
+                .schema(),
+        ),
+        (
+            "exit-code",
+            false,
+            &IntegerSchema::new("The exit code of the command.").schema(),
+        ),
+        (
+            "message",
+            false,
+            &StringSchema::new("The error message.").schema(),
+        ),
+        (
+            "status",
+            true,
+            &IntegerSchema::new("The HTTP status code, if the error was returned by a server.")
+                .minimum(100)
+                .maximum(599)
+                .schema(),
+        ),
+    ],
+)
+.schema();
+
+/// The error a command failed with, printed as JSON document in JSON output mode.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ErrorReport {
+    /// The category of the error.
+    pub category: ErrorCategory,
+    /// The error message.
+    pub message: String,
+    /// The error message of each invalid parameter of a [`ParameterError`].
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub errors: BTreeMap<String, String>,
+    /// The status code of a [`HttpError`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    /// The exit status of the command, as seen by the calling process.
+    pub exit_code: i32,
+}
+
+impl ApiType for ErrorReport {
+    const API_SCHEMA: Schema = ERROR_REPORT_SCHEMA;
+}
+
+impl ErrorReport {
+    /// Create the report for a command which failed with `err` and exits with `exit_code`.
+    ///
+    /// Like the exit status seen by the calling process, the reported exit code is truncated to
+    /// 8 bits, so e.g. [`EXIT_LEGACY_ERROR`](super::EXIT_LEGACY_ERROR) is reported as 255.
+    pub fn new(err: &Error, exit_code: i32) -> Self {
+        let message = match err.downcast_ref::<ParameterError>() {
+            // the message of a parameter error lists the parameters, which are in `errors`
+            Some(_) => "parameter verification failed".to_string(),
+            // like `{err:#}`, without repeating the message of marked errors
+            None => err
+                .chain()
+                .filter(|cause| !is_marker_error(*cause))
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(": "),
+        };
+
+        let errors = find_cause::<ParameterError>(err)
+            .map(|param_err| {
+                param_err
+                    .details()
+                    .into_iter()
+                    .map(|detail| (detail.name, detail.message))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Self {
+            category: ErrorCategory::of(err),
+            message,
+            errors,
+            status: find_cause::<HttpError>(err).map(|err| err.code.as_u16()),
+            exit_code: exit_code & 0xff,
+        }
+    }
+}
+
+/// A writer replacing standard output and standard error for errors.
+pub(crate) type ErrorWriter = Arc<Mutex<dyn Write + Send>>;
+
+/// How a failed command prints its error.
+#[derive(Clone, Default)]
+pub(crate) struct ErrorOutput {
+    /// The output format, `None` if not yet detected from the arguments.
+    pub format: Option<OutputFormat>,
+    /// Print error reports to standard output instead of standard error.
+    pub stdout: bool,
+    /// Print all errors and notices to this writer instead, e.g. to check the output in tests.
+    pub writer: Option<ErrorWriter>,
+}
+
+impl ErrorOutput {
+    /// Detect the output format from the ``--output-format`` option in `args` or the environment,
+    /// unless it was set explicitly.
+    pub fn detect(&mut self, args: &[String]) {
+        if self.format.is_none() {
+            self.format = Some(output_format_from_args(args));
+        }
+    }
+
+    /// Check if errors are printed as [`ErrorReport`] instead of a human readable message.
+    pub fn is_structured(&self) -> bool {
+        matches!(
+            self.format,
+            Some(OutputFormat::Json | OutputFormat::JsonPretty)
+        )
+    }
+
+    /// Print the human readable message of `err` to standard error, unless the output is
+    /// structured.
+    pub fn print_message(&self, err: &Error) {
+        if !self.is_structured() {
+            self.write(false, &format!("Error: {err:?}"));
+        }
+    }
+
+    /// Print a notice like the [`DRY_RUN_BANNER`](crate::cli::DRY_RUN_BANNER) to standard
+    /// error.
+    pub fn print_notice(&self, notice: &str) {
+        self.write(false, notice);
+    }
+
+    /// Print the report of `err` if the output is structured.
+    pub fn print(&self, err: &Error, exit_code: i32) {
+        let report = ErrorReport::new(err, exit_code);
+        let data = match self.format {
+            Some(OutputFormat::Json) => serde_json::to_string(&report),
+            Some(OutputFormat::JsonPretty) => serde_json::to_string_pretty(&report),
+            _ => return,
+        };
+        let data = data.unwrap(); // serializing plain data cannot fail
+
+        self.write(self.stdout, &data);
+    }
+
+    fn write(&self, stdout: bool, data: &str) {
+        if let Some(writer) = &self.writer {
+            let _ = writeln!(writer.lock().unwrap(), "{data}");
+        } else if stdout {
+            let _ = writeln!(std::io::stdout(), "{data}");
+        } else {
+            let _ = writeln!(std::io::stderr(), "{data}");
+        }
+    }
+}
+
+/// Get the output format of the ``--output-format`` option in `args`, falling back to the
+/// environment and plain text.
+fn output_format_from_args(args: &[String]) -> OutputFormat {
+    let mut args = args.iter().take_while(|arg| *arg != "--");
+    while let Some(arg) = args.next() {
+        let value = if arg == "--output-format" {
+            args.next().map(String::as_str)
+        } else {
+            arg.strip_prefix("--output-format=")
+        };
+        if let Some(value) = value {
+            return value.parse().unwrap_or_default();
+        }
+    }
+
+    std::env::var(ENV_VAR_PROXMOX_OUTPUT_FORMAT)
+        .ok()
+        .and_then(|format| format.parse().ok())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use anyhow::format_err;
+
+    use super::*;
+    use crate::cli::{TaskFailedError, UsageError, EXIT_TASK_FAILED, EXIT_USAGE};
+    use crate::http_err;
+
+    #[test]
+    fn test_error_category_schema() {
+        for category in [
+            ErrorCategory::Error,
+            ErrorCategory::Usage,
+            ErrorCategory::PermissionDenied,
+            ErrorCategory::NotFound,
+            ErrorCategory::Transport,
+            ErrorCategory::TaskFailed,
+        ] {
+            let value = serde_json::to_value(category).unwrap();
+            ERROR_CATEGORY_SCHEMA.verify_json(&value).unwrap();
+            assert_eq!(
+                category.to_string().parse::<ErrorCategory>().unwrap(),
+                category
+            );
+        }
+    }
+
+    #[test]
+    fn test_error_reports() {
+        let report = ErrorReport::new(&UsageError::new(format_err!("no such command")).into(), 2);
+        assert_eq!(report.category, ErrorCategory::Usage);
+        assert_eq!(report.message, "no such command");
+        assert_eq!(report.status, None);
+
+        let err = TaskFailedError::new(http_err!(FORBIDDEN, "permission check failed"));
+        let report = ErrorReport::new(&err.into(), EXIT_TASK_FAILED);
+        assert_eq!(report.category, ErrorCategory::TaskFailed);
+        assert_eq!(report.status, Some(403));
+
+        let err: Error = ParameterError::from(("name", format_err!("value too long"))).into();
+        let report = ErrorReport::new(&err.context("unable to create user"), EXIT_USAGE);
+        assert_eq!(report.category, ErrorCategory::Usage);
+        assert!(report.message.starts_with("unable to create user: "));
+        assert_eq!(report.errors["name"], "value too long");
+    }
+
+    #[test]
+    fn test_output_format_from_args() {
+        let args = |args: &[&str]| -> Vec<String> { args.iter().map(|s| s.to_string()).collect() };
+
+        assert_eq!(
+            output_format_from_args(&args(&["--output-format", "json"])),
+            OutputFormat::Json
+        );
+        assert_eq!(
+            output_format_from_args(&args(&["list", "--output-format=json-pretty"])),
+            OutputFormat::JsonPretty
+        );
+        assert_eq!(
+            output_format_from_args(&args(&["--output-format", "text"])),
+            OutputFormat::Text
+        );
+        assert_eq!(
+            output_format_from_args(&args(&["--", "--output-format", "json"])),
+            OutputFormat::Text
+        );
+    }
+}
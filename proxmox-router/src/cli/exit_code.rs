@@ -0,0 +1,239 @@
+//! Exit codes of command line tools, derived from the error a command failed with.
+//!
+//! | Code | Meaning                                                                 |
+//! |------|-------------------------------------------------------------------------|
+//! | 0    | success                                                                 |
+//! | 1    | any other error                                                         |
+//! | 2    | invalid usage or parameters ([`UsageError`], [`ParameterError`], HTTP 400) |
+//! | 4    | permission denied (HTTP 401 and 403, [`std::io::ErrorKind::PermissionDenied`]) |
+//! | 5    | not found (HTTP 404)                                                    |
+//! | 6    | connection to the server failed ([`TransportError`])                    |
+//! | 10   | a task finished with an error ([`TaskFailedError`])                     |
+//!
+//! The error types are also found when they are the cause of an error, e.g. when context was added
+//! to them with [`anyhow::Context`].
+//!
+//! Each code has an [`ErrorCategory`], which is also part of the [`ErrorReport`](super::ErrorReport)
+//! printed in JSON output mode.
+
+use std::fmt;
+
+use anyhow::Error;
+use serde::{Deserialize, Serialize};
+
+use proxmox_schema::ParameterError;
+
+use crate::HttpError;
+
+/// Exit code on success.
+pub const EXIT_SUCCESS: i32 = 0;
+/// Exit code for errors not falling into any other category.
+pub const EXIT_ERROR: i32 = 1;
+/// Exit code for invalid usage, e.g. unknown commands or invalid parameters.
+pub const EXIT_USAGE: i32 = 2;
+/// Exit code if the permission to do something was denied.
+pub const EXIT_PERMISSION_DENIED: i32 = 4;
+/// Exit code if something does not exist.
+pub const EXIT_NOT_FOUND: i32 = 5;
+/// Exit code if the connection to a server failed.
+pub const EXIT_TRANSPORT: i32 = 6;
+/// Exit code if a task finished with an error.
+pub const EXIT_TASK_FAILED: i32 = 10;
+/// Exit code on any error, for tools using the legacy behavior.
+pub const EXIT_LEGACY_ERROR: i32 = -1;
+
+/// The category of the error a command failed with, each with its own exit code.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ErrorCategory {
+    /// Any other error.
+    Error,
+    /// Invalid usage or parameters.
+    Usage,
+    /// The permission to do something was denied.
+    PermissionDenied,
+    /// Something does not exist.
+    NotFound,
+    /// The connection to a server failed.
+    Transport,
+    /// A task finished with an error.
+    TaskFailed,
+}
+serde_plain::derive_display_from_serialize!(ErrorCategory);
+serde_plain::derive_fromstr_from_deserialize!(ErrorCategory);
+
+impl ErrorCategory {
+    /// Get the category of the error a command failed with.
+    pub fn of(err: &Error) -> Self {
+        if find_cause::<TaskFailedError>(err).is_some() {
+            return Self::TaskFailed;
+        }
+        if find_cause::<TransportError>(err).is_some() {
+            return Self::Transport;
+        }
+        if find_cause::<UsageError>(err).is_some() || find_cause::<ParameterError>(err).is_some() {
+            return Self::Usage;
+        }
+        if let Some(err) = find_cause::<HttpError>(err) {
+            return match err.code.as_u16() {
+                400 => Self::Usage,
+                401 | 403 => Self::PermissionDenied,
+                404 => Self::NotFound,
+                _ => Self::Error,
+            };
+        }
+        match find_cause::<std::io::Error>(err) {
+            Some(err) if err.kind() == std::io::ErrorKind::PermissionDenied => {
+                Self::PermissionDenied
+            }
+            _ => Self::Error,
+        }
+    }
+
+    /// The exit code of this category.
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Self::Error => EXIT_ERROR,
+            Self::Usage => EXIT_USAGE,
+            Self::PermissionDenied => EXIT_PERMISSION_DENIED,
+            Self::NotFound => EXIT_NOT_FOUND,
+            Self::Transport => EXIT_TRANSPORT,
+            Self::TaskFailed => EXIT_TASK_FAILED,
+        }
+    }
+}
+
+/// Get the exit code for a command which failed with `err`.
+pub fn exit_code(err: &Error) -> i32 {
+    ErrorCategory::of(err).exit_code()
+}
+
+pub(crate) fn find_cause<T: std::error::Error + Send + Sync + 'static>(err: &Error) -> Option<&T> {
+    err.downcast_ref::<T>()
+        .or_else(|| err.chain().find_map(|cause| cause.downcast_ref::<T>()))
+}
+
+macro_rules! marker_error {
+    ($(#[$doc:meta])* $name:ident) => {
+        $(#[$doc])*
+        #[derive(Debug)]
+        pub struct $name(Error);
+
+        impl $name {
+            pub fn new<E: Into<Error>>(err: E) -> Self {
+                Self(err.into())
+            }
+
+            /// Get the marked error.
+            pub fn into_inner(self) -> Error {
+                self.0
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                fmt::Display::fmt(&self.0, f)
+            }
+        }
+
+        impl std::error::Error for $name {
+            fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+                Some(&*self.0)
+            }
+        }
+    };
+}
+
+/// Check whether `err` is one of the marker errors, which only repeat the message of the error
+/// they wrap.
+pub(crate) fn is_marker_error(err: &(dyn std::error::Error + 'static)) -> bool {
+    err.is::<UsageError>() || err.is::<TransportError>() || err.is::<TaskFailedError>()
+}
+
+marker_error!(
+    /// Marks errors in the usage of a command line tool, e.g. unknown commands.
+    UsageError
+);
+
+marker_error!(
+    /// Marks errors of the connection to a server, e.g. if it is unreachable or the TLS handshake
+    /// failed.
+    TransportError
+);
+
+marker_error!(
+    /// Marks the error of a task which finished unsuccessfully.
+    TaskFailedError
+);
+
+#[cfg(test)]
+mod test {
+    use anyhow::{format_err, Context, Error};
+
+    use proxmox_schema::ParameterError;
+
+    use super::*;
+    use crate::http_err;
+
+    #[test]
+    fn test_exit_codes() {
+        let code = |err: Error| exit_code(&err);
+
+        assert_eq!(code(format_err!("something failed")), EXIT_ERROR);
+
+        assert_eq!(
+            code(UsageError::new(format_err!("no such command")).into()),
+            EXIT_USAGE
+        );
+        let mut param_err = ParameterError::new();
+        param_err.push("name".to_string(), format_err!("value too long"));
+        assert_eq!(code(param_err.into()), EXIT_USAGE);
+        assert_eq!(
+            code(http_err!(BAD_REQUEST, "invalid parameter")),
+            EXIT_USAGE
+        );
+
+        assert_eq!(
+            code(http_err!(UNAUTHORIZED, "no ticket")),
+            EXIT_PERMISSION_DENIED
+        );
+        assert_eq!(
+            code(http_err!(FORBIDDEN, "permission check failed")),
+            EXIT_PERMISSION_DENIED
+        );
+        let io_err = std::io::Error::from(std::io::ErrorKind::PermissionDenied);
+        assert_eq!(code(io_err.into()), EXIT_PERMISSION_DENIED);
+        let io_err = std::io::Error::from(std::io::ErrorKind::NotFound);
+        assert_eq!(code(io_err.into()), EXIT_ERROR);
+
+        assert_eq!(
+            code(http_err!(NOT_FOUND, "no such datastore")),
+            EXIT_NOT_FOUND
+        );
+        assert_eq!(code(http_err!(INTERNAL_SERVER_ERROR, "oops")), EXIT_ERROR);
+
+        let transport_err = TransportError::new(format_err!("connection refused"));
+        assert_eq!(transport_err.to_string(), "connection refused");
+        assert_eq!(code(transport_err.into()), EXIT_TRANSPORT);
+
+        let task_err = TaskFailedError::new(format_err!("backup failed"));
+        assert_eq!(code(task_err.into()), EXIT_TASK_FAILED);
+    }
+
+    #[test]
+    fn test_exit_code_causes() {
+        let err = Err::<(), _>(http_err!(NOT_FOUND, "no such datastore"))
+            .context("unable to list snapshots")
+            .unwrap_err();
+        assert_eq!(exit_code(&err), EXIT_NOT_FOUND);
+
+        let err = Err::<(), _>(TransportError::new(format_err!("connection refused")))
+            .context("unable to connect")
+            .unwrap_err();
+        assert_eq!(exit_code(&err), EXIT_TRANSPORT);
+
+        // the task error takes precedence over the error which made the task fail
+        let err = TaskFailedError::new(http_err!(FORBIDDEN, "permission check failed"));
+        assert_eq!(exit_code(&err.into()), EXIT_TASK_FAILED);
+    }
+}
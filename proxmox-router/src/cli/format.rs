@@ -7,10 +7,12 @@ use serde::Serialize;
 use serde_json::Value;
 
 use proxmox_schema::format::{
-    get_property_description, get_schema_type_text, DocumentationFormat, ParameterDisplayStyle,
+    get_property_description, get_schema_type_text, wrap_text, DocumentationFormat,
+    ParameterDisplayStyle,
 };
 use proxmox_schema::*;
 
+use super::command::{has_output_file_option, OUTPUT_FILE_SCHEMA};
 use super::{value_to_text, TableFormatOptions};
 use super::{CliCommand, CliCommandMap, CommandLineInterface, GlobalOptions};
 
@@ -57,6 +59,14 @@ pub fn format_and_print_result_full(
     }
 }
 
+/// The placeholder of a positional argument, array arguments take all remaining arguments.
+fn positional_synopsis(name: &str, schema: &Schema) -> String {
+    match schema {
+        Schema::Array(_) => format!("<{name}>..."),
+        _ => format!("<{name}>"),
+    }
+}
+
 /// Helper to generate command usage text for simple commands.
 pub fn generate_usage_str(
     prefix: &str,
@@ -99,21 +109,13 @@ pub(crate) fn generate_usage_str_do<'cli>(
             Some((optional, param_schema)) => {
                 args.push(' ');
 
-                let is_array = matches!(param_schema, Schema::Array(_));
+                let synopsis = positional_synopsis(positional_arg, param_schema);
                 if optional {
                     args.push('[');
-                }
-                if is_array {
-                    args.push('{');
-                }
-                args.push('<');
-                args.push_str(positional_arg);
-                args.push('>');
-                if is_array {
-                    args.push('}');
-                }
-                if optional {
+                    args.push_str(&synopsis);
                     args.push(']');
+                } else {
+                    args.push_str(&synopsis);
                 }
 
                 done_hash.insert(positional_arg);
@@ -175,6 +177,18 @@ pub(crate) fn generate_usage_str_do<'cli>(
         done_hash.insert(prop);
     }
 
+    if has_output_file_option(cli_cmd) {
+        if !options.is_empty() {
+            options.push('\n');
+        }
+        options.push_str(&get_property_description(
+            "output-file",
+            &OUTPUT_FILE_SCHEMA,
+            ParameterDisplayStyle::Arg,
+            format,
+        ));
+    }
+
     let option_indicator = if !options.is_empty() {
         " [OPTIONS]"
     } else {
@@ -192,14 +206,14 @@ pub(crate) fn generate_usage_str_do<'cli>(
             prefix,
             args,
             option_indicator,
-            schema.description()
+            i18n::translate(schema.description())
         ),
         DocumentationFormat::ReST => format!(
             "``{}{}{}``\n\n{}",
             prefix,
             args,
             option_indicator,
-            schema.description()
+            i18n::translate(schema.description())
         ),
     };
 
@@ -432,7 +446,7 @@ fn generate_nested_usage_do<'cli>(
     usage
 }
 
-/// Print help text to ``stderr``.
+/// Print help text to ``stdout``.
 pub fn print_help(
     top_def: &CommandLineInterface,
     prefix: String,
@@ -440,17 +454,32 @@ pub fn print_help(
     verbose: Option<bool>,
 ) {
     let mut message = String::new();
-    match print_help_to(top_def, prefix, args, verbose, &mut message) {
+    match print_help_to_width(top_def, prefix, args, verbose, help_columns(), &mut message) {
         Ok(()) => print!("{message}"),
         Err(err) => eprintln!("{err}"),
     }
 }
 
+/// Write the help text to `to`, wrapped to the width of the terminal.
 pub fn print_help_to(
+    top_def: &CommandLineInterface,
+    prefix: String,
+    args: &[String],
+    verbose: Option<bool>,
+    to: impl std::fmt::Write,
+) -> Result<(), Error> {
+    print_help_to_width(top_def, prefix, args, verbose, help_columns(), to)
+}
+
+/// Write the help text to `to`, wrapped to `columns`.
+///
+/// This is the implementation of the ``help`` command and the ``--help`` option.
+pub fn print_help_to_width(
     top_def: &CommandLineInterface,
     mut prefix: String,
     args: &[String],
-    mut verbose: Option<bool>,
+    verbose: Option<bool>,
+    columns: usize,
     mut to: impl std::fmt::Write,
 ) -> Result<(), Error> {
     let mut iface = top_def;
@@ -477,40 +506,381 @@ pub fn print_help_to(
         }
     }
 
-    if verbose.is_none() {
-        if let CommandLineInterface::Simple(_) = iface {
-            verbose = Some(true);
+    let verbose = verbose.unwrap_or(false);
+
+    match iface {
+        CommandLineInterface::Nested(map) => {
+            to.write_str(&generate_group_help_do(
+                &mut usage_state,
+                &prefix,
+                map,
+                verbose,
+            ))?;
         }
+        CommandLineInterface::Simple(cli_cmd) => {
+            to.write_str(&generate_command_help(
+                &prefix,
+                cli_cmd,
+                verbose,
+                columns,
+                usage_state.global_options_iter(),
+            ))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Print the help text of a command group for the ``--help`` option.
+pub(crate) fn print_group_help(prefix: &str, def: &CliCommandMap) {
+    print!(
+        "{}",
+        generate_group_help_do(&mut UsageState::default(), prefix, def, false)
+    );
+}
+
+/// Print the help text of a simple command for the ``--help`` option.
+pub(crate) fn print_command_help<'cli>(
+    prefix: &str,
+    cli_cmd: &CliCommand,
+    global_options_iter: impl Iterator<Item = &'cli GlobalOptions>,
+) {
+    print!(
+        "{}",
+        generate_command_help(prefix, cli_cmd, false, help_columns(), global_options_iter)
+    );
+}
+
+/// Check if the ``--help`` option is given for a command with the parameter `schema`.
+pub(crate) fn help_requested(args: &[String], schema: Option<&ParameterSchema>) -> bool {
+    if schema.is_some_and(|schema| schema.lookup("help").is_some()) {
+        return false;
     }
+    args.iter()
+        .take_while(|arg| *arg != "--")
+        .any(|arg| arg == "--help")
+}
 
-    let format = match verbose.unwrap_or(false) {
+/// The width of the help output if it does not go to a terminal.
+const DEFAULT_HELP_COLUMNS: usize = 80;
+
+/// Descriptions narrower than this are moved below their parameter.
+const MIN_DESCRIPTION_WIDTH: usize = 20;
+
+/// Type texts longer than this do not widen the type column, e.g. property strings.
+const MAX_TYPE_COLUMN_WIDTH: usize = 24;
+
+/// Indentation of descriptions which do not fit next to their parameter.
+const DESCRIPTION_INDENT: &str = "        ";
+
+fn help_columns() -> usize {
+    use std::io::IsTerminal;
+
+    if std::io::stdout().is_terminal() {
+        let (_rows, columns) = super::text_table::stdout_terminal_size();
+        if columns > 0 {
+            return columns;
+        }
+    }
+    DEFAULT_HELP_COLUMNS
+}
+
+fn generate_group_help_do<'cli>(
+    state: &mut UsageState<'cli>,
+    prefix: &str,
+    def: &'cli CliCommandMap,
+    verbose: bool,
+) -> String {
+    let format = match verbose {
         true => DocumentationFormat::Full,
         false => DocumentationFormat::Short,
     };
+    format!(
+        "Usage:\n\n{}\n",
+        generate_nested_usage_do(state, prefix, def, format)
+    )
+}
 
-    match iface {
-        CommandLineInterface::Nested(map) => {
-            writeln!(
-                to,
-                "Usage:\n\n{}",
-                generate_nested_usage_do(&mut usage_state, &prefix, map, format)
-            )?;
+/// A row of the parameter table of the help text.
+struct HelpRow {
+    name: String,
+    type_text: String,
+    default: Option<String>,
+    description: std::borrow::Cow<'static, str>,
+}
+
+impl HelpRow {
+    fn new(name: String, schema: &Schema) -> Self {
+        let (description, default) = match schema {
+            Schema::Null => ("null", None),
+            Schema::Boolean(s) => (s.description, s.default.map(|v| v.to_string())),
+            Schema::Integer(s) => (s.description, s.default.map(|v| v.to_string())),
+            Schema::Uint64(s) => (s.description, s.default.map(|v| v.to_string())),
+            Schema::Number(s) => (s.description, s.default.map(|v| v.to_string())),
+            Schema::String(s) => (s.description, s.default.map(|v| v.to_string())),
+            Schema::Object(s) => (s.description, None),
+            Schema::Array(s) => (s.description, None),
+            Schema::AllOf(s) => (s.description, None),
+            Schema::OneOf(s) => (s.description, None),
+        };
+
+        Self {
+            name,
+            type_text: get_schema_type_text(schema, ParameterDisplayStyle::Arg),
+            default: default.map(|default| format!("(default={default})")),
+            description: i18n::translate(description),
         }
-        CommandLineInterface::Simple(cli_cmd) => {
-            writeln!(
-                to,
-                "Usage: {}",
-                generate_usage_str_do(
-                    &prefix,
-                    cli_cmd,
-                    format,
-                    "",
-                    &[],
-                    usage_state.global_options_iter()
-                )
-            )?;
+    }
+}
+
+/// Render the sections of the parameter table, with columns aligned across all sections.
+fn render_help_table(sections: &[(&str, Vec<HelpRow>)], columns: usize, out: &mut String) {
+    let rows = || sections.iter().flat_map(|(_, rows)| rows);
+
+    let name_width = rows().map(|row| row.name.len()).max().unwrap_or(0);
+    let type_width = rows()
+        .map(|row| row.type_text.len())
+        .filter(|len| *len <= MAX_TYPE_COLUMN_WIDTH)
+        .max()
+        .unwrap_or(0);
+    let default_width = rows()
+        .filter_map(|row| row.default.as_ref().map(String::len))
+        .max()
+        .unwrap_or(0);
+
+    for (title, rows) in sections {
+        if rows.is_empty() {
+            continue;
+        }
+
+        out.push('\n');
+        out.push_str(title);
+        out.push_str(":\n");
+
+        for row in rows {
+            let mut line = format!("  {:name_width$}  {:type_width$}", row.name, row.type_text);
+            if default_width > 0 {
+                let default = row.default.as_deref().unwrap_or("");
+                line.push_str(&format!("  {default:default_width$}"));
+            }
+            line.push_str("  ");
+
+            let indent = " ".repeat(line.len());
+            let fits =
+                row.type_text.len() <= type_width && line.len() + MIN_DESCRIPTION_WIDTH <= columns;
+
+            if fits && !row.description.is_empty() {
+                out.push_str(&wrap_text(&line, &indent, &row.description, columns));
+            } else {
+                out.push_str(line.trim_end());
+                if !row.description.is_empty() {
+                    out.push('\n');
+                    out.push_str(&wrap_text(
+                        DESCRIPTION_INDENT,
+                        DESCRIPTION_INDENT,
+                        &row.description,
+                        columns,
+                    ));
+                }
+            }
+            out.push('\n');
         }
     }
+}
 
-    Ok(())
+/// Quote an example value for the shell, if necessary.
+fn shell_quote(value: &str) -> String {
+    let plain = !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_.,:/=@+%".contains(c));
+    if plain {
+        value.to_string()
+    } else {
+        format!("'{}'", value.replace('\'', r"'\''"))
+    }
+}
+
+/// The example value of a parameter as shell argument, secrets are shown as placeholder.
+fn example_arg(schema: &Schema) -> Option<String> {
+    let example = schema.display_example()?;
+    Some(match schema.is_secret() {
+        true => example.to_string(),
+        false => shell_quote(example),
+    })
+}
+
+/// Build an example invocation from the example values of the parameter schemas.
+///
+/// Returns `None` if no parameter has an example value.
+fn example_invocation(prefix: &str, cli_cmd: &CliCommand) -> Option<String> {
+    let schema = cli_cmd.info.parameters;
+
+    let mut has_example = false;
+    let mut line = prefix.to_string();
+
+    for positional_arg in cli_cmd.arg_param {
+        let (optional, param_schema) = schema.lookup(positional_arg)?;
+        match example_arg(param_schema) {
+            Some(example) => {
+                has_example = true;
+                line.push(' ');
+                line.push_str(&example);
+            }
+            None if !optional => {
+                line.push(' ');
+                line.push_str(&positional_synopsis(positional_arg, param_schema));
+            }
+            None => (),
+        }
+    }
+
+    let mut properties: Vec<_> = schema.properties().collect();
+    properties.sort_by(|a, b| a.0.cmp(b.0));
+
+    for (prop, optional, param_schema) in properties {
+        if cli_cmd.arg_param.contains(prop) || cli_cmd.fixed_param.contains_key(prop) {
+            continue;
+        }
+        match example_arg(param_schema) {
+            Some(example) => {
+                has_example = true;
+                line.push_str(&format!(" --{prop} {example}"));
+            }
+            None if !optional => line.push_str(&format!(" --{prop} <{prop}>")),
+            None => (),
+        }
+    }
+
+    has_example.then_some(line)
+}
+
+fn return_description(returns: &ReturnType, columns: usize) -> Option<String> {
+    let title = match returns.optional {
+        true => "Returns (optionally)",
+        false => "Returns",
+    };
+
+    if let Some(content_type) = returns.content_type {
+        return Some(format!("{title}: raw data ({content_type})\n"));
+    }
+
+    let description = match returns.schema {
+        Schema::Null => return None,
+        schema => HelpRow::new(String::new(), schema),
+    };
+
+    let mut text = format!("{title}: {}\n", description.type_text);
+    if !description.description.is_empty() {
+        text.push('\n');
+        text.push_str(&wrap_text("  ", "  ", &description.description, columns));
+        text.push('\n');
+    }
+    Some(text)
+}
+
+/// Generate the help text of a simple command.
+///
+/// This consists of the synopsis, the description, a table of the parameters with their types,
+/// defaults and descriptions, the return value if `verbose` is set and an example invocation if
+/// the parameter schemas have example values.
+pub(crate) fn generate_command_help<'cli>(
+    prefix: &str,
+    cli_cmd: &CliCommand,
+    verbose: bool,
+    columns: usize,
+    global_options_iter: impl Iterator<Item = &'cli GlobalOptions>,
+) -> String {
+    let schema = cli_cmd.info.parameters;
+
+    let mut text = format!(
+        "Usage: {}\n",
+        generate_usage_str_do(
+            prefix,
+            cli_cmd,
+            DocumentationFormat::Short,
+            "",
+            &[],
+            [].into_iter(),
+        )
+    );
+
+    let description = wrap_text("", "", schema.description(), columns);
+    if !description.is_empty() {
+        text.push('\n');
+        text.push_str(&description);
+        text.push('\n');
+    }
+
+    let mut done_hash = HashSet::<&str>::new();
+
+    let mut arguments = Vec::new();
+    for positional_arg in cli_cmd.arg_param {
+        if let Some((_optional, param_schema)) = schema.lookup(positional_arg) {
+            arguments.push(HelpRow::new(
+                positional_synopsis(positional_arg, param_schema),
+                param_schema,
+            ));
+            done_hash.insert(positional_arg);
+        }
+    }
+
+    let mut required = Vec::new();
+    let mut optional = Vec::new();
+
+    let mut properties: Vec<_> = schema.properties().collect();
+    properties.sort_by(|a, b| a.0.cmp(b.0));
+
+    for (prop, is_optional, param_schema) in properties {
+        if done_hash.contains(prop) || cli_cmd.fixed_param.contains_key(prop) {
+            continue;
+        }
+        let row = HelpRow::new(format!("--{prop}"), param_schema);
+        match is_optional {
+            true => optional.push(row),
+            false => required.push(row),
+        }
+        done_hash.insert(prop);
+    }
+
+    if has_output_file_option(cli_cmd) {
+        optional.push(HelpRow::new("--output-file".into(), &OUTPUT_FILE_SCHEMA));
+    }
+
+    let mut global_properties: Vec<_> = global_options_iter
+        .flat_map(|o| o.schema.any_object().unwrap().properties())
+        .collect();
+    global_properties.sort_by(|a, b| a.0.cmp(b.0));
+
+    let global = global_properties
+        .into_iter()
+        .filter(|(name, _, _)| !done_hash.contains(name))
+        .map(|(name, _optional, param_schema)| HelpRow::new(format!("--{name}"), param_schema))
+        .collect();
+
+    render_help_table(
+        &[
+            ("Arguments", arguments),
+            ("Parameters", required),
+            ("Optional parameters", optional),
+            ("Inherited group parameters", global),
+        ],
+        columns,
+        &mut text,
+    );
+
+    if verbose {
+        if let Some(returns) = return_description(&cli_cmd.info.returns, columns) {
+            text.push('\n');
+            text.push_str(&returns);
+        }
+    }
+
+    if let Some(example) = example_invocation(prefix, cli_cmd) {
+        text.push_str("\nExample:\n  ");
+        text.push_str(&example);
+        text.push('\n');
+    }
+
+    text
 }
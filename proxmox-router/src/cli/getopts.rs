@@ -172,6 +172,21 @@ pub fn parse_arguments<T: AsRef<str>>(
                 errors.push(name.to_string(), format_err!("missing argument"));
             }
         } else if is_last_arg_param && last_arg_param_is_array {
+            // all remaining arguments are items, except for the separator which ended the options
+            if let Some(separator) = remaining.iter().position(|arg| arg == "--") {
+                remaining.remove(separator);
+            }
+            // report invalid items with their position
+            if let Some((_, Schema::Array(array_schema))) = schema.lookup(name) {
+                for (index, value) in remaining.iter().enumerate() {
+                    if let Err(err) = array_schema.items.parse_simple_value(value) {
+                        errors.push(
+                            name.to_string(),
+                            format_err!("invalid item {} '{value}' - {err}", index + 1),
+                        );
+                    }
+                }
+            }
             for value in remaining {
                 data.push((name.to_string(), value));
             }
@@ -214,6 +229,9 @@ fn test_boolean_arg() {
     variants.push((vec!["--enable", "no"], false));
     variants.push((vec!["--enable", "off"], false));
     variants.push((vec!["--enable", "false"], false));
+    variants.push((vec!["--enable", "OFF"], false));
+    variants.push((vec!["-enable=No"], false));
+    variants.push((vec!["-enable=FALSE"], false));
 
     for (args, expect) in variants {
         let res = parse_arguments(
@@ -228,6 +246,19 @@ fn test_boolean_arg() {
             assert!(remaining.is_empty());
         }
     }
+
+    let err = parse_arguments(
+        &["--enable=maybe"],
+        &[],
+        &HashMap::new(),
+        ParameterSchema::from(&PARAMETERS),
+    )
+    .unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("expected one of 1, on, yes, true, 0, off, no, false"),
+        "{err}"
+    );
 }
 
 #[test]
@@ -257,6 +288,111 @@ fn test_argument_parameter() {
     }
 }
 
+#[test]
+fn test_repeated_options() {
+    use proxmox_schema::*;
+
+    const PARAMETERS: ObjectSchema = ObjectSchema::new(
+        "Parameters:",
+        &[
+            (
+                "file",
+                true,
+                &ArraySchema::new("Files.", &StringSchema::new("File.").schema()).schema(),
+            ),
+            ("name", true, &StringSchema::new("Name.").schema()),
+            (
+                "tag",
+                true,
+                &ArraySchema::new("Tags.", &StringSchema::new("Tag.").schema()).schema(),
+            ),
+        ],
+    );
+
+    let parse = |args: &[&str]| {
+        parse_arguments(
+            args,
+            &["file"],
+            &HashMap::new(),
+            ParameterSchema::from(&PARAMETERS),
+        )
+    };
+
+    let (options, remaining) =
+        parse(&["--tag", "a", "--name", "n", "--tag=b", "f1", "f2"]).unwrap();
+    assert_eq!(
+        options,
+        serde_json::json!({ "name": "n", "tag": ["a", "b"], "file": ["f1", "f2"] })
+    );
+    assert!(remaining.is_empty());
+
+    let (options, _) = parse(&["--tag", "a"]).unwrap();
+    assert_eq!(options, serde_json::json!({ "tag": ["a"] }));
+
+    let err = parse(&["--name", "a", "--tag", "b", "--name", "c"]).unwrap_err();
+    assert_eq!(err.errors().len(), 1);
+    assert_eq!(err.errors()[0].0, "name");
+}
+
+#[test]
+fn test_variadic_argument() {
+    use proxmox_schema::*;
+
+    const PARAMETERS: ObjectSchema = ObjectSchema::new(
+        "Parameters:",
+        &[
+            ("force", true, &BooleanSchema::new("Force.").schema()),
+            ("name", false, &StringSchema::new("Name.").schema()),
+            (
+                "tag",
+                true,
+                &ArraySchema::new("Tags.", &StringSchema::new("Tag.").max_length(4).schema())
+                    .schema(),
+            ),
+        ],
+    );
+
+    let parse = |args: &[&str]| {
+        parse_arguments(
+            args,
+            &["name", "tag"],
+            &HashMap::new(),
+            ParameterSchema::from(&PARAMETERS),
+        )
+    };
+
+    let (options, remaining) = parse(&["n"]).unwrap();
+    assert_eq!(options, serde_json::json!({ "name": "n" }));
+    assert!(remaining.is_empty());
+
+    let (options, _) = parse(&["n", "t1"]).unwrap();
+    assert_eq!(options, serde_json::json!({ "name": "n", "tag": ["t1"] }));
+
+    let (options, _) = parse(&["n", "t1", "t2", "--force", "t3"]).unwrap();
+    assert_eq!(
+        options,
+        serde_json::json!({ "force": true, "name": "n", "tag": ["t1", "t2", "t3"] })
+    );
+
+    // everything after the separator is an item
+    let (options, _) = parse(&["n", "t1", "--", "--t2"]).unwrap();
+    assert_eq!(
+        options,
+        serde_json::json!({ "name": "n", "tag": ["t1", "--t2"] })
+    );
+
+    let err = parse(&["n", "t1", "too-long", "t3"]).unwrap_err();
+    assert_eq!(err.errors().len(), 1);
+    assert_eq!(err.errors()[0].0, "tag");
+    assert!(
+        err.errors()[0]
+            .1
+            .to_string()
+            .starts_with("invalid item 2 'too-long'"),
+        "{err}"
+    );
+}
+
 pub(crate) struct ParseOptions<'t, 'o> {
     target: &'t mut Vec<(String, String)>,
     option_schemas: &'o HashMap<&'o str, &'static Schema>,
@@ -8,8 +8,10 @@
 //! - Use declarative API schema to define the CLI
 //! - Automatic parameter verification
 //! - Automatically generate documentation and manual pages
+//! - Help texts with parameter tables and examples (``help`` command and ``--help`` option)
 //! - Automatically generate bash completion helpers
 //! - Ability to create interactive commands (using ``rustyline``)
+//! - Interactive shell with history and completion
 //! - Supports complex/nested commands
 
 use std::any::{Any, TypeId};
@@ -23,7 +25,7 @@ use serde_json::Value;
 
 use proxmox_schema::{ApiType, Schema};
 
-use crate::{ApiFuture, ApiMethod};
+use crate::{ApiFuture, ApiMethod, DispatchHooks, RouterHooks};
 
 mod environment;
 pub use environment::*;
@@ -48,9 +50,18 @@ pub use getopts::*;
 mod command;
 pub use command::*;
 
+mod exit_code;
+pub use exit_code::*;
+
+mod error_report;
+pub use error_report::*;
+
 mod readline;
 pub use readline::*;
 
+mod shell;
+pub use shell::*;
+
 /// Completion function for single parameters.
 ///
 /// Completion functions gets the current parameter value, and should
@@ -195,6 +206,13 @@ pub struct CliCommand {
     /// Each parameter may have an associated completion function,
     /// which is called by the shell completion handler.
     pub completion_functions: HashMap<String, CompletionFunction>,
+    /// Hooks run around the command.
+    pub hooks: Option<&'static RouterHooks>,
+    /// Parameters holding secrets like passwords.
+    ///
+    /// Command lines passing them are not recorded in the history of the
+    /// [interactive shell](InteractiveShell).
+    pub secret_param: &'static [&'static str],
 }
 
 impl CliCommand {
@@ -205,6 +223,8 @@ impl CliCommand {
             arg_param: &[],
             fixed_param: HashMap::new(),
             completion_functions: HashMap::new(),
+            hooks: None,
+            secret_param: &[],
         }
     }
 
@@ -225,6 +245,18 @@ impl CliCommand {
         self.completion_functions.insert(param_name.into(), cb);
         self
     }
+
+    /// Set the hooks run around the command, see [`RouterHooks`].
+    pub fn hooks(mut self, hooks: &'static RouterHooks) -> Self {
+        self.hooks = Some(hooks);
+        self
+    }
+
+    /// Set the parameters holding secrets.
+    pub fn secret_param(mut self, names: &'static [&'static str]) -> Self {
+        self.secret_param = names;
+        self
+    }
 }
 
 /// Define nested CLI commands.
@@ -239,6 +271,9 @@ pub struct CliCommandMap {
 
     /// A set of options common to all subcommands. Only object schemas can be used here.
     pub(crate) global_options: HashMap<TypeId, GlobalOptions>,
+
+    /// Hooks run around all subcommands.
+    pub hooks: Option<&'static RouterHooks>,
 }
 
 impl CliCommandMap {
@@ -263,6 +298,12 @@ impl CliCommandMap {
         self
     }
 
+    /// Set the hooks run around all subcommands, see [`RouterHooks`].
+    pub fn hooks(mut self, hooks: &'static RouterHooks) -> Self {
+        self.hooks = Some(hooks);
+        self
+    }
+
     /// Insert the help command.
     pub fn insert_help(mut self) -> Self {
         self.commands
@@ -417,6 +458,7 @@ struct CommandLineParseState<'cli> {
     global_option_types: HashMap<TypeId, &'cli GlobalOptions>,
     async_run: Option<fn(ApiFuture) -> Result<Value, Error>>,
     interface: Arc<CommandLineInterface>,
+    hooks: DispatchHooks,
 }
 
 impl CommandLine {
@@ -432,11 +474,16 @@ impl CommandLine {
         self
     }
 
+    /// Parse the command line `args`.
+    ///
+    /// In JSON output mode, an [`ErrorReport`] is printed before returning an error, this also
+    /// applies to errors of the returned [`Invocation`].
     pub fn parse<A>(&self, rpcenv: &mut CliEnvironment, args: A) -> Result<Invocation, Error>
     where
         A: IntoIterator<Item = String>,
     {
         let (prefix, args) = command::prepare_cli_command(&self.interface, args.into_iter());
+        rpcenv.error_output.detect(&args);
 
         let state = CommandLineParseState {
             prefix,
@@ -445,9 +492,12 @@ impl CommandLine {
             global_option_types: HashMap::new(),
             async_run: self.async_run,
             interface: Arc::clone(&self.interface),
+            hooks: DispatchHooks::default(),
         };
 
-        state.parse_do(&self.interface, rpcenv, args)
+        state
+            .parse_do(&self.interface, rpcenv, args)
+            .inspect_err(|err| print_error_report(rpcenv, err))
     }
 }
 
@@ -509,7 +559,20 @@ impl<'cli> CommandLineParseState<'cli> {
 
         command::replace_aliases(&mut args, &cli.aliases);
 
+        if args.first().is_some_and(|arg| arg == "--help") {
+            let prefix = self.prefix;
+            return Ok(Invocation {
+                call: Box::new(move |_rpcenv| {
+                    format::print_group_help(&prefix, cli);
+                    Ok(())
+                }),
+            });
+        }
+
         self.enable_global_options(cli);
+        if let Some(hooks) = cli.hooks {
+            self.hooks.push(hooks);
+        }
 
         let mut args = self.handle_current_global_options(args, true)?;
 
@@ -520,16 +583,20 @@ impl<'cli> CommandLineParseState<'cli> {
             let list = cmds.join(", ");
 
             let err_msg = format!("no command specified.\nPossible commands: {}", list);
-            print_nested_usage_error(&self.prefix, cli, &err_msg);
-            return Err(format_err!("{}", err_msg));
+            if !rpcenv.error_output.is_structured() {
+                print_nested_usage_error(&self.prefix, cli, &err_msg);
+            }
+            return Err(UsageError::new(format_err!("{}", err_msg)).into());
         }
 
         let (_, sub_cmd) = match cli.find_command(&args[0]) {
             Some(cmd) => cmd,
             None => {
                 let err_msg = format!("no such command '{}'", args[0]);
-                print_nested_usage_error(&self.prefix, cli, &err_msg);
-                return Err(format_err!("{}", err_msg));
+                if !rpcenv.error_output.is_structured() {
+                    print_nested_usage_error(&self.prefix, cli, &err_msg);
+                }
+                return Err(UsageError::new(format_err!("{}", err_msg)).into());
             }
         };
 
@@ -546,6 +613,9 @@ impl<'cli> CommandLineParseState<'cli> {
     ) -> Result<Invocation<'cli>, Error> {
         let args = self.handle_current_global_options(args, false)?;
         self.build_global_options(&mut *rpcenv)?;
+        if let Some(hooks) = cli.hooks {
+            self.hooks.push(hooks);
+        }
         let interface = Arc::clone(&self.interface);
         Ok(Invocation {
             call: Box::new(move |rpcenv| {
@@ -557,6 +627,7 @@ impl<'cli> CommandLineParseState<'cli> {
                     rpcenv,
                     self.async_run,
                     self.global_option_types.values().copied(),
+                    &self.hooks,
                 );
                 command::set_help_context(None);
                 out
@@ -584,6 +655,11 @@ pub struct Invocation<'cli> {
 
 impl Invocation<'_> {
     pub fn call(self, rpcenv: &mut CliEnvironment) -> Result<(), Error> {
-        (self.call)(rpcenv)
+        (self.call)(rpcenv).inspect_err(|err| print_error_report(rpcenv, err))
     }
 }
+
+fn print_error_report(rpcenv: &CliEnvironment, err: &Error) {
+    let exit_code = command::error_exit_code(err, rpcenv.legacy_exit_codes);
+    rpcenv.error_output.print(err, exit_code);
+}
@@ -0,0 +1,611 @@
+use std::io::{BufRead, IsTerminal, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{bail, format_err, Error};
+use serde_json::Value;
+
+use proxmox_schema::{ObjectSchemaType, Schema};
+
+use super::command::{
+    handle_simple_command, parse_nested_command, replace_aliases, set_help_context,
+};
+use super::{
+    shellword_split, shellword_split_unclosed, CliCommand, CliCommandMap, CliEnvironment,
+    CommandLineInterface,
+};
+use crate::{ApiFuture, DispatchHooks};
+
+// number of history entries kept in the history file
+const HISTORY_SIZE: usize = 1000;
+
+/// Interactive shell for a [`CommandLineInterface`].
+///
+/// Every input line is parsed and dispatched like the arguments of the command line tool. In
+/// addition, the shell understands these commands:
+///
+/// - ``cd [<path>]``: Change the command context to a group of sub-commands, e.g. ``cd
+///   access/users``, ``cd ..`` or ``cd /``. Commands are looked up relative to the context.
+/// - ``exit``, ``quit``: Leave the shell (just like ``Ctrl-D``).
+///
+/// ``Ctrl-C`` cancels the current line. If standard input is not a terminal, commands are read
+/// line by line without line editing.
+///
+/// The history is stored in ``$XDG_STATE_HOME/<name>/history`` by default. Command lines passing
+/// a [secret parameter](CliCommand::secret_param) or a parameter whose schema is marked as
+/// [secret](proxmox_schema::StringSchema::secret) are not recorded.
+pub struct InteractiveShell {
+    name: String,
+    interface: Arc<CommandLineInterface>,
+    async_run: Option<fn(ApiFuture) -> Result<Value, Error>>,
+    history_file: Option<PathBuf>,
+}
+
+impl InteractiveShell {
+    /// Create a new shell for the command line interface `def` of the tool called `name`.
+    ///
+    /// Like [`run_cli_command`](super::run_cli_command), this adds the help command.
+    pub fn new<C: Into<CommandLineInterface>>(name: &str, def: C) -> Self {
+        let def = match def.into() {
+            CommandLineInterface::Simple(cli_cmd) => CommandLineInterface::Simple(cli_cmd),
+            CommandLineInterface::Nested(map) => CommandLineInterface::Nested(map.insert_help()),
+        };
+
+        Self {
+            name: name.to_string(),
+            interface: Arc::new(def),
+            async_run: None,
+            history_file: default_history_file(name),
+        }
+    }
+
+    /// Set the function used to execute async commands.
+    pub fn with_async(mut self, async_run: fn(ApiFuture) -> Result<Value, Error>) -> Self {
+        self.async_run = Some(async_run);
+        self
+    }
+
+    /// Set the file the history is stored in, `None` disables storing the history.
+    pub fn history_file(mut self, path: Option<PathBuf>) -> Self {
+        self.history_file = path;
+        self
+    }
+
+    /// Run the shell on the standard input.
+    pub fn run(self, rpcenv: CliEnvironment) -> Result<(), Error> {
+        let stdin = std::io::stdin();
+        if !stdin.is_terminal() {
+            return self.run_with_input(stdin.lock(), rpcenv);
+        }
+
+        let mut editor = rustyline::Editor::<ShellHelper>::new();
+        editor.set_helper(Some(ShellHelper {
+            interface: Arc::clone(&self.interface),
+            context: Vec::new(),
+        }));
+        self.run_do(&mut EditorInput(editor), rpcenv)
+    }
+
+    /// Run the shell reading commands line by line from `input`, without prompts.
+    pub fn run_with_input<R: BufRead>(self, input: R, rpcenv: CliEnvironment) -> Result<(), Error> {
+        self.run_do(&mut PlainInput(input), rpcenv)
+    }
+
+    fn run_do(&self, input: &mut dyn LineInput, mut rpcenv: CliEnvironment) -> Result<(), Error> {
+        let mut history = match &self.history_file {
+            Some(path) => load_history(path)?,
+            None => Vec::new(),
+        };
+        for line in &history {
+            input.add_history(line);
+        }
+
+        let mut context = Vec::new();
+        loop {
+            let prompt = format!("{} /{}> ", self.name, context.join("/"));
+            let line = match input.read_line(&prompt, &context)? {
+                Input::Line(line) => line,
+                Input::Interrupted => continue,
+                Input::Eof => break,
+            };
+
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let args = match shellword_split(line) {
+                Ok(args) => args,
+                Err(err) => {
+                    eprintln!("Error: {err}");
+                    continue;
+                }
+            };
+
+            if !self.has_secret(&context, &args) {
+                input.add_history(line);
+                history.push(line.to_string());
+            }
+
+            match args[0].as_str() {
+                "exit" | "quit" => break,
+                "cd" => {
+                    if let Err(err) = self.change_context(&mut context, &args[1..]) {
+                        eprintln!("Error: {err}");
+                    }
+                }
+                _ => {
+                    // errors are already printed by the command handler
+                    let _ = self.dispatch(context_args(&context, args), &mut rpcenv);
+                }
+            }
+        }
+
+        if let Some(path) = &self.history_file {
+            let skip = history.len().saturating_sub(HISTORY_SIZE);
+            if let Err(err) = save_history(path, &history[skip..]) {
+                eprintln!("unable to save history to {path:?} - {err}");
+            }
+        }
+
+        Ok(())
+    }
+
+    fn dispatch(&self, mut args: Vec<String>, rpcenv: &mut CliEnvironment) -> Result<(), Error> {
+        set_help_context(Some(Arc::clone(&self.interface)));
+
+        let mut hooks = DispatchHooks::default();
+        let result = match &*self.interface {
+            CommandLineInterface::Simple(cli_cmd) => {
+                if let Some(cmd_hooks) = cli_cmd.hooks {
+                    hooks.push(cmd_hooks);
+                }
+                handle_simple_command(
+                    &self.name,
+                    cli_cmd,
+                    args,
+                    rpcenv,
+                    self.async_run,
+                    [].into_iter(),
+                    &hooks,
+                )
+            }
+            CommandLineInterface::Nested(map) => {
+                let mut prefix = self.name.clone();
+                match parse_nested_command(&mut prefix, map, &mut args, &mut hooks, rpcenv) {
+                    Ok(Some(cli_cmd)) => handle_simple_command(
+                        &prefix,
+                        cli_cmd,
+                        args,
+                        rpcenv,
+                        self.async_run,
+                        [].into_iter(),
+                        &hooks,
+                    ),
+                    Ok(None) => Ok(()),
+                    Err(err) => Err(err),
+                }
+            }
+        };
+
+        set_help_context(None);
+
+        result
+    }
+
+    fn change_context(&self, context: &mut Vec<String>, args: &[String]) -> Result<(), Error> {
+        let path = match args {
+            [] => "/",
+            [path] => path.as_str(),
+            _ => bail!("cd takes at most one argument"),
+        };
+
+        let mut new_context = if path.starts_with('/') {
+            Vec::new()
+        } else {
+            context.clone()
+        };
+
+        for component in path.split('/').filter(|c| !c.is_empty() && *c != ".") {
+            if component == ".." {
+                new_context.pop();
+                continue;
+            }
+
+            let map = find_map(&self.interface, &new_context)
+                .ok_or_else(|| format_err!("no command groups in '/{}'", new_context.join("/")))?;
+            match map.find_command(component) {
+                Some((name, CommandLineInterface::Nested(_))) => new_context.push(name),
+                Some((name, CommandLineInterface::Simple(_))) => {
+                    bail!("'{name}' is a command, not a command group")
+                }
+                None => bail!("no such command group '{component}'"),
+            }
+        }
+
+        *context = new_context;
+        Ok(())
+    }
+
+    /// Check if the command line passes a secret parameter.
+    fn has_secret(&self, context: &[String], args: &[String]) -> bool {
+        let mut args = context_args(context, args.to_vec());
+        let cli_cmd = match find_command(&self.interface, &mut args) {
+            Some(cli_cmd) => cli_cmd,
+            None => return false,
+        };
+
+        let mut secret_param = cli_cmd.secret_param.to_vec();
+        for (name, _optional, schema) in cli_cmd.info.parameters.properties() {
+            if matches!(schema, Schema::String(s) if s.secret) {
+                secret_param.push(name);
+            }
+        }
+
+        secret_param.iter().any(|name| {
+            // positional parameters cannot be told apart reliably, treat them as always passed
+            cli_cmd.arg_param.contains(name)
+                || args.iter().any(|arg| match arg.strip_prefix("--") {
+                    Some(option) => {
+                        option == *name
+                            || option
+                                .strip_prefix(name)
+                                .is_some_and(|value| value.starts_with('='))
+                    }
+                    None => false,
+                })
+        })
+    }
+}
+
+/// Run an [`InteractiveShell`] for the command line interface `def` of the tool called `name`.
+pub fn interactive_shell<C: Into<CommandLineInterface>>(
+    name: &str,
+    def: C,
+    rpcenv: CliEnvironment,
+) -> Result<(), Error> {
+    InteractiveShell::new(name, def).run(rpcenv)
+}
+
+/// Prepend the context to the arguments, the help command takes the context as its argument.
+fn context_args(context: &[String], mut args: Vec<String>) -> Vec<String> {
+    if args.first().is_some_and(|arg| arg == "help") {
+        args.splice(1..1, context.iter().cloned());
+        args
+    } else {
+        context.iter().cloned().chain(args).collect()
+    }
+}
+
+fn find_map<'a>(def: &'a CommandLineInterface, context: &[String]) -> Option<&'a CliCommandMap> {
+    let mut map = match def {
+        CommandLineInterface::Nested(map) => map,
+        CommandLineInterface::Simple(_) => return None,
+    };
+
+    for name in context {
+        match map.commands.get(name) {
+            Some(CommandLineInterface::Nested(sub_map)) => map = sub_map,
+            _ => return None,
+        }
+    }
+
+    Some(map)
+}
+
+/// Find the command called by `args`, leaving only its arguments in `args`.
+fn find_command<'a>(
+    def: &'a CommandLineInterface,
+    args: &mut Vec<String>,
+) -> Option<&'a CliCommand> {
+    let mut map = match def {
+        CommandLineInterface::Simple(cli_cmd) => return Some(cli_cmd),
+        CommandLineInterface::Nested(map) => map,
+    };
+
+    loop {
+        replace_aliases(args, &map.aliases);
+        if args.is_empty() {
+            return None;
+        }
+        match map.find_command(&args.remove(0))? {
+            (_, CommandLineInterface::Simple(cli_cmd)) => return Some(cli_cmd),
+            (_, CommandLineInterface::Nested(sub_map)) => map = sub_map,
+        }
+    }
+}
+
+fn default_history_file(name: &str) -> Option<PathBuf> {
+    let state_dir = match std::env::var_os("XDG_STATE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".local/state"),
+    };
+    Some(state_dir.join(name).join("history"))
+}
+
+fn load_history(path: &Path) -> Result<Vec<String>, Error> {
+    match std::fs::read_to_string(path) {
+        Ok(data) => Ok(data.lines().map(String::from).collect()),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => bail!("unable to read history from {path:?} - {err}"),
+    }
+}
+
+fn save_history(path: &Path, history: &[String]) -> Result<(), Error> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+    for line in history {
+        writeln!(file, "{line}")?;
+    }
+    Ok(())
+}
+
+enum Input {
+    Line(String),
+    Interrupted,
+    Eof,
+}
+
+trait LineInput {
+    fn read_line(&mut self, prompt: &str, context: &[String]) -> Result<Input, Error>;
+
+    fn add_history(&mut self, _line: &str) {}
+}
+
+struct PlainInput<R>(R);
+
+impl<R: BufRead> LineInput for PlainInput<R> {
+    fn read_line(&mut self, _prompt: &str, _context: &[String]) -> Result<Input, Error> {
+        let mut line = String::new();
+        match self.0.read_line(&mut line)? {
+            0 => Ok(Input::Eof),
+            _ => Ok(Input::Line(line)),
+        }
+    }
+}
+
+struct EditorInput(rustyline::Editor<ShellHelper>);
+
+impl LineInput for EditorInput {
+    fn read_line(&mut self, prompt: &str, context: &[String]) -> Result<Input, Error> {
+        if let Some(helper) = self.0.helper_mut() {
+            helper.context = context.to_vec();
+        }
+
+        match self.0.readline(prompt) {
+            Ok(line) => Ok(Input::Line(line)),
+            Err(rustyline::error::ReadlineError::Interrupted) => Ok(Input::Interrupted),
+            Err(rustyline::error::ReadlineError::Eof) => Ok(Input::Eof),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn add_history(&mut self, line: &str) {
+        self.0.add_history_entry(line);
+    }
+}
+
+/// ``rustyline`` helper completing relative to the current context.
+struct ShellHelper {
+    interface: Arc<CommandLineInterface>,
+    context: Vec<String>,
+}
+
+impl ShellHelper {
+    fn complete_context(&self, line: &str) -> (usize, Vec<String>) {
+        let (args, partial) = shellword_split_unclosed(line, false);
+        let (start, partial) = match partial {
+            Some((start, partial, _quote)) => (start, partial),
+            None => (line.len(), String::new()),
+        };
+
+        if args.len() != 1 {
+            return (start, Vec::new());
+        }
+
+        // complete the last path component only
+        let (parent, name) = match partial.rsplit_once('/') {
+            Some((parent, name)) => (Some(parent), name),
+            None => (None, partial.as_str()),
+        };
+
+        let mut context = match parent {
+            Some(parent) if parent.is_empty() || partial.starts_with('/') => Vec::new(),
+            _ => self.context.clone(),
+        };
+        for component in parent.into_iter().flat_map(|parent| parent.split('/')) {
+            match component {
+                "" | "." => (),
+                ".." => {
+                    context.pop();
+                }
+                component => context.push(component.to_string()),
+            }
+        }
+
+        let map = match find_map(&self.interface, &context) {
+            Some(map) => map,
+            None => return (start, Vec::new()),
+        };
+
+        let offset = partial.len() - name.len();
+        let mut completions: Vec<String> = map
+            .commands
+            .iter()
+            .filter(|(cmd, sub_cmd)| {
+                cmd.starts_with(name) && matches!(sub_cmd, CommandLineInterface::Nested(_))
+            })
+            .map(|(cmd, _)| format!("{cmd}/"))
+            .collect();
+        completions.sort();
+
+        (start + offset, completions)
+    }
+}
+
+impl rustyline::completion::Completer for ShellHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &rustyline::Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Self::Candidate>)> {
+        let line = &line[..pos];
+
+        if let Some(rest) = line.strip_prefix("cd ") {
+            let (start, completions) = self.complete_context(rest);
+            return Ok((start + 3, completions));
+        }
+
+        // complete the line as it is dispatched
+        let (args, _) = shellword_split_unclosed(line, false);
+        let prefix = if args.first().is_some_and(|arg| arg == "help") || self.context.is_empty() {
+            return Ok(self.interface.get_completions(line, false));
+        } else {
+            self.context.join(" ") + " "
+        };
+
+        let (start, completions) = self
+            .interface
+            .get_completions(&(prefix.clone() + line), false);
+
+        Ok((start.saturating_sub(prefix.len()), completions))
+    }
+}
+
+impl rustyline::hint::Hinter for ShellHelper {
+    type Hint = String;
+}
+impl rustyline::validate::Validator for ShellHelper {}
+impl rustyline::highlight::Highlighter for ShellHelper {}
+impl rustyline::Helper for ShellHelper {}
+
+#[cfg(test)]
+mod test {
+    use std::cell::RefCell;
+
+    use anyhow::Error;
+    use serde_json::Value;
+
+    use proxmox_schema::{ObjectSchema, StringSchema};
+
+    use super::InteractiveShell;
+    use crate::cli::{CliCommand, CliCommandMap, CliEnvironment};
+    use crate::{ApiHandler, ApiMethod, RpcEnvironment};
+
+    std::thread_local! {
+        static CALLS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+    }
+
+    fn handler(param: Value, _: &ApiMethod, _: &mut dyn RpcEnvironment) -> Result<Value, Error> {
+        CALLS.with(|calls| calls.borrow_mut().push(param.to_string()));
+        Ok(Value::Null)
+    }
+
+    const API_METHOD_HANDLER: ApiMethod = ApiMethod::new(
+        &ApiHandler::Sync(&handler),
+        &ObjectSchema::new(
+            "Test handler.",
+            &[
+                ("name", true, &StringSchema::new("Name.").schema()),
+                ("password", true, &StringSchema::new("Password.").schema()),
+                (
+                    "token",
+                    true,
+                    &StringSchema::new("Token.").secret().schema(),
+                ),
+            ],
+        ),
+    );
+
+    fn cli() -> CliCommandMap {
+        let users = CliCommandMap::new()
+            .insert("list", CliCommand::new(&API_METHOD_HANDLER))
+            .insert(
+                "create",
+                CliCommand::new(&API_METHOD_HANDLER)
+                    .arg_param(&["name"])
+                    .secret_param(&["password"]),
+            );
+
+        CliCommandMap::new()
+            .insert("access", CliCommandMap::new().insert("users", users))
+            .insert("version", CliCommand::new(&API_METHOD_HANDLER))
+    }
+
+    #[test]
+    fn test_shell_dispatch_and_history() -> Result<(), Error> {
+        let dir = std::env::temp_dir().join(format!("proxmox-router-shell-{}", std::process::id()));
+        let history_file = dir.join("history");
+
+        let input = [
+            "version --name v",
+            "cd access/users",
+            "list",
+            "list --token secret",
+            "create foo --password secret",
+            "create bar --password=secret",
+            "create baz",
+            "cd ../nothing",
+            "cd ..",
+            "users list --name x",
+            "cd /",
+            "access users list --name 'quoted name'",
+        ]
+        .join("\n");
+
+        let shell = InteractiveShell::new("test", cli()).history_file(Some(history_file.clone()));
+        shell.run_with_input(input.as_bytes(), CliEnvironment::new())?;
+
+        assert_eq!(
+            CALLS.with(|calls| calls.take()),
+            [
+                r#"{"name":"v"}"#,
+                r#"{}"#,
+                r#"{"token":"secret"}"#,
+                r#"{"name":"foo","password":"secret"}"#,
+                r#"{"name":"bar","password":"secret"}"#,
+                r#"{"name":"baz"}"#,
+                r#"{"name":"x"}"#,
+                r#"{"name":"quoted name"}"#,
+            ],
+        );
+
+        assert_eq!(
+            std::fs::read_to_string(&history_file)?,
+            [
+                "version --name v",
+                "cd access/users",
+                "list",
+                "create baz",
+                "cd ../nothing",
+                "cd ..",
+                "users list --name x",
+                "cd /",
+                "access users list --name 'quoted name'",
+                "",
+            ]
+            .join("\n"),
+        );
+
+        // the history is kept across sessions, exiting stops reading commands
+        let shell = InteractiveShell::new("test", cli()).history_file(Some(history_file.clone()));
+        shell.run_with_input("exit\nversion\n".as_bytes(), CliEnvironment::new())?;
+        assert!(CALLS.with(|calls| calls.take()).is_empty());
+        assert!(std::fs::read_to_string(&history_file)?.ends_with("'quoted name'\nexit\n"));
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
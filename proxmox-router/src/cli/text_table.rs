@@ -76,6 +76,10 @@ fn data_to_text(data: &Value, schema: &Schema) -> Result<String, Error> {
             Some(value) => Ok(format!("{}", value)),
             None => bail!("got unexpected data (expected integer)."),
         },
+        Schema::Uint64(_) => match data.as_u64() {
+            Some(value) => Ok(format!("{}", value)),
+            None => bail!("got unexpected data (expected unsigned integer)."),
+        },
         Schema::Number(_) => match data.as_f64() {
             Some(value) => Ok(format!("{}", value)),
             None => bail!("got unexpected data (expected number)."),
@@ -205,7 +209,7 @@ impl ColumnConfig {
 /// # Safety
 ///
 /// uses unsafe call to tty_ioctl, see man tty_ioctl(2).
-fn stdout_terminal_size() -> (usize, usize) {
+pub(crate) fn stdout_terminal_size() -> (usize, usize) {
     let mut winsize = libc::winsize {
         ws_row: 0,
         ws_col: 0,
@@ -386,7 +390,10 @@ fn format_table<W: Write>(
             Some(tup) => tup,
             None => bail!("property {} does not exist in schema.", sortkey),
         };
-        let numeric_sort = matches!(sort_prop_schema, Schema::Integer(_) | Schema::Number(_));
+        let numeric_sort = matches!(
+            sort_prop_schema,
+            Schema::Integer(_) | Schema::Uint64(_) | Schema::Number(_)
+        );
         sortinfo.push((sortkey, sort_order, numeric_sort));
     }
 
@@ -447,7 +454,7 @@ fn format_table<W: Write>(
 
         let is_numeric = matches!(
             prop_schema,
-            Schema::Integer(_) | Schema::Number(_) | Schema::Boolean(_)
+            Schema::Integer(_) | Schema::Uint64(_) | Schema::Number(_) | Schema::Boolean(_)
         );
 
         let (header, right_align, renderer) = options.lookup_column_info(name);
@@ -663,7 +670,7 @@ fn format_object_with_properties<W: Write>(
 
         let is_numeric = matches!(
             prop_schema,
-            Schema::Integer(_) | Schema::Number(_) | Schema::Boolean(_)
+            Schema::Integer(_) | Schema::Uint64(_) | Schema::Number(_) | Schema::Boolean(_)
         );
 
         let (header, right_align, renderer) = options.lookup_column_info(name);
@@ -805,6 +812,9 @@ pub fn value_to_text<W: Write>(
         Schema::Integer(_integer_schema) => {
             unimplemented!();
         }
+        Schema::Uint64(_uint64_schema) => {
+            unimplemented!();
+        }
         Schema::Number(_number_schema) => {
             unimplemented!();
         }
@@ -3,23 +3,180 @@
 use std::io::Write;
 
 use anyhow::Error;
+use serde_json::{json, Value};
 
 use proxmox_schema::format::*;
+use proxmox_schema::i18n::translate;
 use proxmox_schema::ObjectSchemaType;
 
 #[cfg(feature = "server")]
 use crate::ApiHandler;
-use crate::ApiMethod;
+use crate::{ApiAccess, ApiMethod, Permission};
 
-fn dump_method_definition(method: &str, path: &str, def: Option<&ApiMethod>) -> Option<String> {
+/// Get the names of the privileges contained in a `Permission::Privilege` bitmask.
+///
+/// `privileges` maps privilege names to their bits. Bits without a name are printed in
+/// hexadecimal notation.
+fn privilege_names(privs: u64, privileges: &[(&str, u64)]) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut remaining = privs;
+    for (name, value) in privileges {
+        if *value != 0 && (privs & value) == *value {
+            names.push(name.to_string());
+            remaining &= !value;
+        }
+    }
+    if remaining != 0 {
+        names.push(format!("{:#x}", remaining));
+    }
+    names
+}
+
+fn privilege_path(path: &[&str]) -> String {
+    format!("/{}", path.join("/"))
+}
+
+fn privilege_text(path: &[&str], privs: u64, partial: bool, privileges: &[(&str, u64)]) -> String {
+    let names = privilege_names(privs, privileges);
+    let prefix = if partial && names.len() > 1 {
+        "any of "
+    } else {
+        ""
+    };
+    format!("{prefix}{} on {}", names.join(", "), privilege_path(path))
+}
+
+/// Render a permission as a single line of text.
+///
+/// `privileges` maps privilege names to their bits and is used to resolve the bitmasks of
+/// `Permission::Privilege` entries. Nested `And`/`Or` lists are enclosed in parentheses.
+pub fn permission_text(perm: &Permission, privileges: &[(&str, u64)]) -> String {
+    let list_text = |list: &[&Permission], separator: &str| {
+        list.iter()
+            .map(|perm| match perm {
+                Permission::And(_) | Permission::Or(_) => {
+                    format!("({})", permission_text(perm, privileges))
+                }
+                _ => permission_text(perm, privileges),
+            })
+            .collect::<Vec<_>>()
+            .join(separator)
+    };
+
+    match perm {
+        Permission::Superuser => "superuser".to_string(),
+        Permission::World => "world (no authentication required)".to_string(),
+        Permission::Anybody => "any authenticated user".to_string(),
+        Permission::User(userid) => format!("user '{userid}'"),
+        Permission::UserParam(param_name) => format!("user matching parameter '{param_name}'"),
+        Permission::Group(group) => format!("member of group '{group}'"),
+        Permission::WithParam(param_name, subtest) => format!(
+            "user from parameter '{param_name}' with {}",
+            permission_text(subtest, privileges)
+        ),
+        Permission::Privilege(path, privs, partial) => {
+            privilege_text(path, *privs, *partial, privileges)
+        }
+        Permission::And(list) => list_text(list, " AND "),
+        Permission::Or(list) => list_text(list, " OR "),
+    }
+}
+
+/// Render a permission as indented text, with one line per check.
+///
+/// See [`permission_text`] for the meaning of `privileges`.
+pub fn permission_tree_text(perm: &Permission, privileges: &[(&str, u64)]) -> String {
+    fn dump(perm: &Permission, privileges: &[(&str, u64)], indent: usize, out: &mut String) {
+        let prefix = "  ".repeat(indent);
+        match perm {
+            Permission::And(list) | Permission::Or(list) => {
+                let op = if matches!(perm, Permission::And(_)) {
+                    "AND"
+                } else {
+                    "OR"
+                };
+                out.push_str(&format!("{prefix}{op}\n"));
+                for subtest in list.iter() {
+                    dump(subtest, privileges, indent + 1, out);
+                }
+            }
+            Permission::WithParam(param_name, subtest) => {
+                out.push_str(&format!(
+                    "{prefix}user from parameter '{param_name}' with\n"
+                ));
+                dump(subtest, privileges, indent + 1, out);
+            }
+            _ => {
+                out.push_str(&format!("{prefix}{}\n", permission_text(perm, privileges)));
+            }
+        }
+    }
+
+    let mut out = String::new();
+    dump(perm, privileges, 0, &mut out);
+    out
+}
+
+/// Convert a permission into a JSON value for machine consumption (e.g. an API viewer).
+///
+/// See [`permission_text`] for the meaning of `privileges`.
+pub fn permission_to_json(perm: &Permission, privileges: &[(&str, u64)]) -> Value {
+    match perm {
+        Permission::Superuser => json!({ "type": "superuser" }),
+        Permission::World => json!({ "type": "world" }),
+        Permission::Anybody => json!({ "type": "anybody" }),
+        Permission::User(userid) => json!({ "type": "user", "userid": userid }),
+        Permission::UserParam(param_name) => json!({ "type": "user-param", "param": param_name }),
+        Permission::Group(group) => json!({ "type": "group", "group": group }),
+        Permission::WithParam(param_name, subtest) => json!({
+            "type": "with-param",
+            "param": param_name,
+            "permission": permission_to_json(subtest, privileges),
+        }),
+        Permission::Privilege(path, privs, partial) => json!({
+            "type": "privilege",
+            "path": privilege_path(path),
+            "privileges": privilege_names(*privs, privileges),
+            "partial": partial,
+        }),
+        Permission::And(list) => json!({
+            "type": "and",
+            "list": list.iter().map(|perm| permission_to_json(perm, privileges)).collect::<Vec<_>>(),
+        }),
+        Permission::Or(list) => json!({
+            "type": "or",
+            "list": list.iter().map(|perm| permission_to_json(perm, privileges)).collect::<Vec<_>>(),
+        }),
+    }
+}
+
+fn dump_api_access(access: &ApiAccess, privileges: &[(&str, u64)]) -> String {
+    let mut res = format!(
+        "*Required permissions*: {}\n\n",
+        permission_text(access.permission, privileges)
+    );
+    if let Some(description) = access.description {
+        res.push_str(&wrap_text("", "", &translate(description), 80));
+    }
+    res
+}
+
+fn dump_method_definition(
+    method: &str,
+    path: &str,
+    def: Option<&ApiMethod>,
+    privileges: &[(&str, u64)],
+) -> Option<String> {
     let style = ParameterDisplayStyle::Config;
     match def {
         None => None,
         Some(api_method) => {
-            let description = wrap_text("", "", api_method.parameters.description(), 80);
+            let description =
+                wrap_text("", "", &translate(api_method.parameters.description()), 80);
             let param_descr = dump_properties(&api_method.parameters, "", style, &[]);
 
             let return_descr = dump_api_return_schema(&api_method.returns, style);
+            let access_descr = dump_api_access(&api_method.access, privileges);
 
             #[cfg(feature = "server")]
             let mut method = method;
@@ -33,20 +190,149 @@ fn dump_method_definition(method: &str, path: &str, def: Option<&ApiMethod>) ->
             }
 
             let res = format!(
-                "**{} {}**\n\n{}{}\n\n{}",
-                method, path, description, param_descr, return_descr
+                "**{} {}**\n\n{}{}\n\n{}\n\n{}",
+                method, path, description, param_descr, return_descr, access_descr
             );
             Some(res)
         }
     }
 }
 
+/// Convert an API method into a JSON value for machine consumption (e.g. an API viewer).
+///
+/// See [`permission_text`] for the meaning of `privileges`.
+pub fn api_method_to_json(api_method: &ApiMethod, privileges: &[(&str, u64)]) -> Value {
+    let mut permissions = json!({
+        "check": permission_to_json(api_method.access.permission, privileges),
+    });
+    if let Some(description) = api_method.access.description {
+        permissions["description"] = translate(description).into();
+    }
+
+    let mut method = json!({
+        "description": translate(api_method.parameters.description()),
+        "parameters": parameter_schema_to_json(api_method.parameters),
+        "returns": return_type_to_json(&api_method.returns),
+        "permissions": permissions,
+        "protected": api_method.protected,
+        "expected-duration": api_method.duration_class().to_string(),
+    });
+    if let Some(size) = api_method.response_size_hint() {
+        method["max-response-size"] = size.into();
+    }
+    method
+}
+
+/// Convert an API method into an OpenAPI 3.1 operation object.
+///
+/// The parameters are described as query parameters with `query_parameters`, e.g. for `GET`
+/// requests, and as JSON request body otherwise. The [expected duration](ApiMethod::duration_class)
+/// and the [response size hint](ApiMethod::response_size_hint) are added as the
+/// `x-expected-duration` and `x-max-response-size` extensions.
+pub fn api_method_to_openapi(api_method: &ApiMethod, query_parameters: bool) -> Value {
+    let content = match api_method.returns.content_type {
+        Some(content_type) => json!({ content_type: {} }),
+        None => {
+            let mut schema = api_method.returns.schema.to_json_schema();
+            if api_method.returns.optional {
+                schema = json!({ "oneOf": [{ "type": "null" }, schema] });
+            }
+            json!({ "application/json": { "schema": schema } })
+        }
+    };
+
+    let mut operation = json!({
+        "description": translate(api_method.parameters.description()),
+        "responses": {
+            "200": { "description": "Success.", "content": content },
+        },
+        "x-expected-duration": api_method.duration_class().to_string(),
+    });
+
+    if query_parameters {
+        let parameters: Vec<Value> = api_method
+            .parameters
+            .properties()
+            .map(|(name, optional, schema)| {
+                json!({
+                    "name": name,
+                    "in": "query",
+                    "required": !optional,
+                    "schema": schema.to_json_schema(),
+                })
+            })
+            .collect();
+        operation["parameters"] = parameters.into();
+    } else {
+        operation["requestBody"] = json!({
+            "content": {
+                "application/json": {
+                    "schema": parameter_schema_to_json_schema(api_method.parameters),
+                },
+            },
+        });
+    }
+
+    if let Some(size) = api_method.response_size_hint() {
+        operation["x-max-response-size"] = size.into();
+    }
+    operation
+}
+
+/// Describe a single node of a ``Router`` as JSON value: its methods and child directories.
+///
+/// Only methods for which `filter` returns `true` are included, e.g. to hide methods the caller
+/// has no permission for. Child directories matching any path component are listed as
+/// `{param_name}`. See [`permission_text`] for the meaning of `privileges`.
+pub fn router_node_to_json(
+    router: &crate::Router,
+    privileges: &[(&str, u64)],
+    filter: impl Fn(&ApiMethod) -> bool,
+) -> Value {
+    use crate::SubRoute;
+
+    let mut methods = serde_json::Map::new();
+    for (name, method) in [
+        ("GET", router.get),
+        ("POST", router.post),
+        ("PUT", router.put),
+        ("DELETE", router.delete),
+    ] {
+        if let Some(method) = method.filter(|method| filter(method)) {
+            methods.insert(name.to_string(), api_method_to_json(method, privileges));
+        }
+    }
+
+    let children: Vec<String> = match &router.subroute {
+        None => Vec::new(),
+        Some(SubRoute::Map(dirmap)) => dirmap.iter().map(|(name, _)| name.to_string()).collect(),
+        Some(SubRoute::MatchAll { param_name, .. }) => vec![format!("{{{param_name}}}")],
+    };
+
+    json!({ "methods": methods, "children": children })
+}
+
 /// Generate ReST Documentation for a complete API defined by a ``Router``.
+///
+/// Privileges in permission requirements are printed as bitmasks, use
+/// [`dump_api_with_privileges`] to print their names instead.
 pub fn dump_api(
+    output: &mut dyn Write,
+    router: &crate::Router,
+    path: &str,
+    pos: usize,
+) -> Result<(), Error> {
+    dump_api_with_privileges(output, router, path, pos, &[])
+}
+
+/// Generate ReST Documentation for a complete API defined by a ``Router``, using `privileges` to
+/// map privilege bits to their names.
+pub fn dump_api_with_privileges(
     output: &mut dyn Write,
     router: &crate::Router,
     path: &str,
     mut pos: usize,
+    privileges: &[(&str, u64)],
 ) -> Result<(), Error> {
     use crate::SubRoute;
 
@@ -61,10 +347,20 @@ pub fn dump_api(
         Ok(())
     };
 
-    cond_print(dump_method_definition("GET", path, router.get))?;
-    cond_print(dump_method_definition("POST", path, router.post))?;
-    cond_print(dump_method_definition("PUT", path, router.put))?;
-    cond_print(dump_method_definition("DELETE", path, router.delete))?;
+    cond_print(dump_method_definition("GET", path, router.get, privileges))?;
+    cond_print(dump_method_definition(
+        "POST",
+        path,
+        router.post,
+        privileges,
+    ))?;
+    cond_print(dump_method_definition("PUT", path, router.put, privileges))?;
+    cond_print(dump_method_definition(
+        "DELETE",
+        path,
+        router.delete,
+        privileges,
+    ))?;
 
     match &router.subroute {
         None => return Ok(()),
@@ -74,7 +370,7 @@ pub fn dump_api(
             } else {
                 format!("{}/<{}>", path, param_name)
             };
-            dump_api(output, router, &sub_path, pos)?;
+            dump_api_with_privileges(output, router, &sub_path, pos, privileges)?;
         }
         Some(SubRoute::Map(dirmap)) => {
             //let mut keys: Vec<&String> = map.keys().collect();
@@ -85,10 +381,147 @@ pub fn dump_api(
                 } else {
                     format!("{}/{}", path, key)
                 };
-                dump_api(output, sub_router, &sub_path, pos)?;
+                dump_api_with_privileges(output, sub_router, &sub_path, pos, privileges)?;
             }
         }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    const PRIVILEGES: &[(&str, u64)] = &[
+        ("Datastore.Audit", 1 << 0),
+        ("Datastore.Modify", 1 << 1),
+        ("Datastore.Backup", 1 << 2),
+    ];
+
+    const NESTED: Permission = Permission::And(&[
+        &Permission::Or(&[
+            &Permission::Privilege(&["datastore", "{store}"], 1 << 0, false),
+            &Permission::Privilege(&["datastore", "{store}"], (1 << 1) | (1 << 2), true),
+        ]),
+        &Permission::UserParam("owner"),
+    ]);
+
+    #[test]
+    fn test_permission_text() {
+        assert_eq!(
+            permission_text(&NESTED, PRIVILEGES),
+            "(Datastore.Audit on /datastore/{store} \
+             OR any of Datastore.Modify, Datastore.Backup on /datastore/{store}) \
+             AND user matching parameter 'owner'",
+        );
+
+        assert_eq!(
+            permission_tree_text(&NESTED, PRIVILEGES),
+            "AND\n  \
+             OR\n    \
+             Datastore.Audit on /datastore/{store}\n    \
+             any of Datastore.Modify, Datastore.Backup on /datastore/{store}\n  \
+             user matching parameter 'owner'\n",
+        );
+
+        assert_eq!(
+            permission_text(&Permission::Privilege(&[], 1 | 1 << 4, false), PRIVILEGES),
+            "Datastore.Audit, 0x10 on /",
+        );
+    }
+
+    #[test]
+    fn test_permission_json() {
+        assert_eq!(
+            permission_to_json(&NESTED, PRIVILEGES),
+            json!({
+                "type": "and",
+                "list": [
+                    {
+                        "type": "or",
+                        "list": [
+                            {
+                                "type": "privilege",
+                                "path": "/datastore/{store}",
+                                "privileges": ["Datastore.Audit"],
+                                "partial": false,
+                            },
+                            {
+                                "type": "privilege",
+                                "path": "/datastore/{store}",
+                                "privileges": ["Datastore.Modify", "Datastore.Backup"],
+                                "partial": true,
+                            },
+                        ],
+                    },
+                    { "type": "user-param", "param": "owner" },
+                ],
+            }),
+        );
+    }
+
+    fn list_tasks(
+        _: Value,
+        _: &ApiMethod,
+        _: &mut dyn crate::RpcEnvironment,
+    ) -> Result<Value, Error> {
+        Ok(Value::Null)
+    }
+
+    const PARAMETERS: proxmox_schema::ObjectSchema =
+        proxmox_schema::ObjectSchema::new("Test method.", &[]);
+
+    #[test]
+    fn test_method_duration_json() {
+        use crate::{ApiHandler, ExpectedDuration};
+        use proxmox_schema::upid::UPID_SCHEMA;
+        use proxmox_schema::ReturnType;
+
+        let method = ApiMethod::new(&ApiHandler::Sync(&list_tasks), &PARAMETERS);
+        let json = api_method_to_json(&method, &[]);
+        assert_eq!(json["expected-duration"], "fast");
+        assert!(json.get("max-response-size").is_none());
+
+        // methods returning a UPID spawn a task
+        let method = ApiMethod::new(&ApiHandler::Sync(&list_tasks), &PARAMETERS)
+            .returns(ReturnType::new(false, &UPID_SCHEMA));
+        assert_eq!(method.duration_class(), ExpectedDuration::Task);
+        assert_eq!(
+            api_method_to_json(&method, &[])["expected-duration"],
+            "task"
+        );
+
+        let method = ApiMethod::new(&ApiHandler::Sync(&list_tasks), &PARAMETERS)
+            .returns(ReturnType::new(false, &UPID_SCHEMA))
+            .expected_duration(ExpectedDuration::Slow)
+            .max_response_size(1 << 20);
+        let json = api_method_to_json(&method, &[]);
+        assert_eq!(json["expected-duration"], "slow");
+        assert_eq!(json["max-response-size"], 1 << 20);
+    }
+
+    #[test]
+    fn test_method_duration_openapi() {
+        use crate::{ApiHandler, ExpectedDuration};
+
+        let method = ApiMethod::new(&ApiHandler::Sync(&list_tasks), &PARAMETERS);
+        let operation = api_method_to_openapi(&method, true);
+        assert_eq!(operation["x-expected-duration"], "fast");
+        assert!(operation.get("x-max-response-size").is_none());
+        assert_eq!(operation["parameters"], json!([]));
+
+        let method = ApiMethod::new(&ApiHandler::Sync(&list_tasks), &PARAMETERS)
+            .expected_duration(ExpectedDuration::Slow)
+            .max_response_size(1 << 20);
+        let operation = api_method_to_openapi(&method, false);
+        assert_eq!(operation["x-expected-duration"], "slow");
+        assert_eq!(operation["x-max-response-size"], 1 << 20);
+        assert_eq!(
+            operation["requestBody"]["content"]["application/json"]["schema"]["type"],
+            "object"
+        );
+    }
+}
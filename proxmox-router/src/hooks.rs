@@ -0,0 +1,360 @@
+use anyhow::Error;
+use serde_json::Value;
+
+use crate::{ApiMethod, RpcEnvironment};
+
+/// The API call router hooks are run for.
+pub struct HookRequest<'a> {
+    /// The HTTP method (`GET`, `PUT`, `POST` or `DELETE`), `None` for CLI invocations.
+    pub method: Option<&'a str>,
+    /// The called API method.
+    pub api_method: &'static ApiMethod,
+    /// The parameters of the call.
+    pub params: &'a Value,
+    /// The authenticated user, if any.
+    pub auth_id: Option<String>,
+}
+
+/// Hook run before an API method is called, returning an error (e.g. an `HttpError`) rejects the
+/// call.
+pub type PreDispatchHook = fn(&HookRequest, &mut dyn RpcEnvironment) -> Result<(), Error>;
+
+/// Hook run after an API method was called (or rejected), with the result status.
+pub type PostDispatchHook = fn(&HookRequest, Result<(), &Error>);
+
+/// Hooks run around every API call below a [`Router`](crate::Router) or
+/// [`CliCommandMap`](crate::cli::CliCommandMap) node.
+///
+/// Hooks are inherited by all child nodes. The hooks of all nodes on the path are composed: the
+/// pre-dispatch hooks run from root to leaf, the post-dispatch hooks from leaf to root. A node can
+/// [`override_parent`](Self::override_parent) hooks to drop those of its ancestors.
+///
+///```
+/// # use anyhow::{bail, Error};
+/// use proxmox_router::{HookRequest, Router, RouterHooks, RpcEnvironment};
+///
+/// fn reject_writes(request: &HookRequest, _: &mut dyn RpcEnvironment) -> Result<(), Error> {
+///     if request.method.is_some_and(|method| method != "GET") {
+///         bail!("node is in maintenance mode");
+///     }
+///     Ok(())
+/// }
+///
+/// const MAINTENANCE_HOOKS: RouterHooks = RouterHooks::new().pre_dispatch(reject_writes);
+/// const ROUTER: Router = Router::new().hooks(&MAINTENANCE_HOOKS);
+///```
+pub struct RouterHooks {
+    /// Run before the API method is called.
+    pub pre_dispatch: Option<PreDispatchHook>,
+    /// Run after the API method was called.
+    pub post_dispatch: Option<PostDispatchHook>,
+    /// Whether the hooks of parent nodes run as well.
+    pub inherit: bool,
+}
+
+impl RouterHooks {
+    /// Create a new, empty set of hooks.
+    pub const fn new() -> Self {
+        Self {
+            pre_dispatch: None,
+            post_dispatch: None,
+            inherit: true,
+        }
+    }
+
+    /// Set the pre-dispatch hook.
+    pub const fn pre_dispatch(mut self, hook: PreDispatchHook) -> Self {
+        self.pre_dispatch = Some(hook);
+        self
+    }
+
+    /// Set the post-dispatch hook.
+    pub const fn post_dispatch(mut self, hook: PostDispatchHook) -> Self {
+        self.post_dispatch = Some(hook);
+        self
+    }
+
+    /// Do not run the hooks of parent nodes.
+    pub const fn override_parent(mut self) -> Self {
+        self.inherit = false;
+        self
+    }
+}
+
+impl Default for RouterHooks {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The hooks collected along the path to an API method, from root to leaf.
+#[derive(Clone, Default)]
+pub struct DispatchHooks {
+    hooks: Vec<&'static RouterHooks>,
+}
+
+impl DispatchHooks {
+    /// Add the hooks of the next node on the path.
+    pub fn push(&mut self, hooks: &'static RouterHooks) {
+        if !hooks.inherit {
+            self.hooks.clear();
+        }
+        self.hooks.push(hooks);
+    }
+
+    /// Check if there are no hooks to run.
+    pub fn is_empty(&self) -> bool {
+        self.hooks.is_empty()
+    }
+
+    /// Run the pre-dispatch hooks from root to leaf, stopping at the first error.
+    pub fn pre_dispatch(
+        &self,
+        request: &HookRequest,
+        rpcenv: &mut dyn RpcEnvironment,
+    ) -> Result<(), Error> {
+        for hook in self.hooks.iter().filter_map(|hooks| hooks.pre_dispatch) {
+            hook(request, rpcenv)?;
+        }
+        Ok(())
+    }
+
+    /// Run the post-dispatch hooks from leaf to root.
+    ///
+    /// They run for rejected calls too, with the error of the rejecting pre-dispatch hook.
+    pub fn post_dispatch(&self, request: &HookRequest, result: Result<(), &Error>) {
+        for hook in self
+            .hooks
+            .iter()
+            .rev()
+            .filter_map(|hooks| hooks.post_dispatch)
+        {
+            hook(request, result);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+
+    use anyhow::{bail, Error};
+    use serde_json::{json, Value};
+
+    use proxmox_schema::ObjectSchema;
+
+    use super::{HookRequest, RouterHooks};
+    use crate::{ApiHandler, ApiMethod, Router, RpcEnvironment, RpcEnvironmentType};
+
+    std::thread_local! {
+        static CALLS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+    }
+
+    fn record(call: String) {
+        CALLS.with(|calls| calls.borrow_mut().push(call));
+    }
+
+    fn take_calls() -> Vec<String> {
+        CALLS.with(|calls| calls.take())
+    }
+
+    fn result_str(result: Result<(), &Error>) -> String {
+        match result {
+            Ok(()) => "ok".to_string(),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    fn root_pre(_: &HookRequest, _: &mut dyn RpcEnvironment) -> Result<(), Error> {
+        record("root pre".into());
+        Ok(())
+    }
+
+    fn root_post(_: &HookRequest, result: Result<(), &Error>) {
+        record(format!("root post {}", result_str(result)));
+    }
+
+    fn access_pre(request: &HookRequest, _: &mut dyn RpcEnvironment) -> Result<(), Error> {
+        record(format!("access pre {}", request.params["user"]));
+        if request.method.is_some_and(|method| method != "GET") {
+            bail!("maintenance mode");
+        }
+        Ok(())
+    }
+
+    fn access_post(_: &HookRequest, result: Result<(), &Error>) {
+        record(format!("access post {}", result_str(result)));
+    }
+
+    fn nodes_pre(_: &HookRequest, _: &mut dyn RpcEnvironment) -> Result<(), Error> {
+        record("nodes pre".into());
+        Ok(())
+    }
+
+    const ROOT_HOOKS: RouterHooks = RouterHooks::new()
+        .pre_dispatch(root_pre)
+        .post_dispatch(root_post);
+
+    const ACCESS_HOOKS: RouterHooks = RouterHooks::new()
+        .pre_dispatch(access_pre)
+        .post_dispatch(access_post);
+
+    const NODES_HOOKS: RouterHooks = RouterHooks::new().pre_dispatch(nodes_pre).override_parent();
+
+    fn handler(param: Value, _: &ApiMethod, _: &mut dyn RpcEnvironment) -> Result<Value, Error> {
+        record(format!("handler {}", param["user"]));
+        Ok(Value::Null)
+    }
+
+    const API_METHOD_HANDLER: ApiMethod = ApiMethod::new(
+        &ApiHandler::Sync(&handler),
+        &ObjectSchema::new("Test handler.", &[]),
+    );
+
+    const USER_ROUTER: Router = Router::new().get(&API_METHOD_HANDLER);
+
+    const ACCESS_ROUTER: Router = Router::new()
+        .subdirs(&[("users", &USER_ROUTER)])
+        .hooks(&ACCESS_HOOKS);
+
+    const NODES_ROUTER: Router = Router::new().get(&API_METHOD_HANDLER).hooks(&NODES_HOOKS);
+
+    const ROUTER: Router = Router::new()
+        .subdirs(&[("access", &ACCESS_ROUTER), ("nodes", &NODES_ROUTER)])
+        .hooks(&ROOT_HOOKS);
+
+    struct TestEnvironment(Value);
+
+    impl RpcEnvironment for TestEnvironment {
+        fn result_attrib_mut(&mut self) -> &mut Value {
+            &mut self.0
+        }
+
+        fn result_attrib(&self) -> &Value {
+            &self.0
+        }
+
+        fn env_type(&self) -> RpcEnvironmentType {
+            RpcEnvironmentType::PUBLIC
+        }
+
+        fn set_auth_id(&mut self, _auth_id: Option<String>) {}
+
+        fn get_auth_id(&self) -> Option<String> {
+            None
+        }
+    }
+
+    fn dispatch(path: &[&str], method: &str) -> Result<(), Error> {
+        let (router, hooks) = ROUTER
+            .find_route_with_hooks(path, &mut HashMap::new())
+            .unwrap();
+        let api_method = router.get.unwrap();
+        let params = json!({ "user": "root" });
+
+        let request = HookRequest {
+            method: Some(method),
+            api_method,
+            params: &params,
+            auth_id: None,
+        };
+        let mut rpcenv = TestEnvironment(Value::Null);
+
+        let result =
+            hooks
+                .pre_dispatch(&request, &mut rpcenv)
+                .and_then(|()| match api_method.handler {
+                    ApiHandler::Sync(handler) => handler(params.clone(), api_method, &mut rpcenv),
+                    _ => unreachable!(),
+                });
+        hooks.post_dispatch(&request, result.as_ref().map(|_| ()));
+        result.map(|_| ())
+    }
+
+    #[test]
+    fn test_hook_order() -> Result<(), Error> {
+        dispatch(&["access", "users"], "GET")?;
+        assert_eq!(
+            take_calls(),
+            [
+                "root pre",
+                "access pre \"root\"",
+                "handler \"root\"",
+                "access post ok",
+                "root post ok",
+            ],
+        );
+
+        // hooks of the root node only, overridden below `/nodes`
+        assert!(ROUTER
+            .find_route_with_hooks(&[], &mut HashMap::new())
+            .is_some_and(|(_, hooks)| !hooks.is_empty()));
+        dispatch(&["nodes"], "GET")?;
+        assert_eq!(take_calls(), ["nodes pre", "handler \"root\""]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_hook_veto() {
+        let err = dispatch(&["access", "users"], "POST").unwrap_err();
+        assert_eq!(err.to_string(), "maintenance mode");
+        assert_eq!(
+            take_calls(),
+            [
+                "root pre",
+                "access pre \"root\"",
+                "access post maintenance mode",
+                "root post maintenance mode",
+            ],
+        );
+    }
+
+    #[cfg(feature = "cli")]
+    #[test]
+    fn test_cli_hooks() {
+        use std::sync::Arc;
+
+        use crate::cli::{handle_command, CliCommand, CliCommandMap, CliEnvironment};
+
+        let cli = CliCommandMap::new()
+            .insert(
+                "access",
+                CliCommandMap::new()
+                    .insert("user", CliCommand::new(&API_METHOD_HANDLER))
+                    .hooks(&ACCESS_HOOKS),
+            )
+            .insert(
+                "nodes",
+                CliCommand::new(&API_METHOD_HANDLER).hooks(&NODES_HOOKS),
+            )
+            .hooks(&ROOT_HOOKS);
+        let cli = Arc::new(cli.into());
+
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect();
+
+        handle_command(
+            Arc::clone(&cli),
+            "test",
+            args(&["access", "user"]),
+            CliEnvironment::new(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            take_calls(),
+            [
+                "root pre",
+                "access pre null",
+                "handler null",
+                "access post ok",
+                "root post ok",
+            ],
+        );
+
+        handle_command(cli, "test", args(&["nodes"]), CliEnvironment::new(), None).unwrap();
+        assert_eq!(take_calls(), ["nodes pre", "handler null"]);
+    }
+}
@@ -12,16 +12,24 @@ pub mod cli;
 #[cfg(feature = "server")]
 pub mod error;
 
+mod audit;
+mod hooks;
 mod permission;
+mod raw_response;
 mod router;
 mod rpc_environment;
 mod serializable_return;
 
+pub mod typed;
+
 #[doc(inline)]
 #[cfg(feature = "server")]
 pub use error::*;
 
+pub use audit::{audit_permissions, AuditFinding, AuditFindingKind};
+pub use hooks::*;
 pub use permission::*;
+pub use raw_response::{RawBody, RawResponse};
 pub use router::*;
 pub use rpc_environment::{RpcEnvironment, RpcEnvironmentType};
 pub use serializable_return::SerializableReturn;
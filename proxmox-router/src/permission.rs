@@ -70,6 +70,63 @@ pub trait UserInformation {
     fn is_superuser(&self, userid: &str) -> bool;
     fn is_group_member(&self, userid: &str, group: &str) -> bool;
     fn lookup_privs(&self, userid: &str, path: &[&str]) -> u64;
+
+    /// Check the privileges of a user on many paths at once, e.g. to filter API list results.
+    ///
+    /// Returns whether the user has all (or with `partial` any) of the `required` privileges, for
+    /// each path. Implementations should override this if they can answer it faster than with
+    /// one [`lookup_privs`](Self::lookup_privs) call per path.
+    fn check_privs_batch(
+        &self,
+        userid: &str,
+        paths: &[Vec<&str>],
+        required: u64,
+        partial: bool,
+    ) -> Vec<bool> {
+        paths
+            .iter()
+            .map(|path| privs_match(self.lookup_privs(userid, path), required, partial))
+            .collect()
+    }
+}
+
+/// Whether `privs` contain all (or with `partial` any) of the `required` privileges.
+pub fn privs_match(privs: u64, required: u64, partial: bool) -> bool {
+    if partial {
+        (privs & required) != 0
+    } else {
+        (privs & required) == required
+    }
+}
+
+/// Filter `items` by the privileges of a user on their ACL paths.
+///
+/// `acl_path` returns the ACL path of an item, e.g. `/datastore/store1`. All privileges are
+/// checked with a single [`UserInformation::check_privs_batch`] call.
+pub fn filter_by_priv<T, I, F>(
+    info: &dyn UserInformation,
+    userid: &str,
+    items: I,
+    acl_path: F,
+    required: u64,
+    partial: bool,
+) -> impl Iterator<Item = T>
+where
+    I: IntoIterator<Item = T>,
+    F: Fn(&T) -> String,
+{
+    let items: Vec<T> = items.into_iter().collect();
+    let acl_paths: Vec<String> = items.iter().map(acl_path).collect();
+    let paths: Vec<Vec<&str>> = acl_paths
+        .iter()
+        .map(|path| path.split('/').filter(|comp| !comp.is_empty()).collect())
+        .collect();
+
+    let allowed = info.check_privs_batch(userid, &paths, required, partial);
+    items
+        .into_iter()
+        .zip(allowed)
+        .filter_map(|(item, allowed)| allowed.then_some(item))
 }
 
 impl<T: UserInformation> UserInformation for std::sync::Arc<T> {
@@ -82,6 +139,16 @@ impl<T: UserInformation> UserInformation for std::sync::Arc<T> {
     fn lookup_privs(&self, userid: &str, path: &[&str]) -> u64 {
         self.deref().lookup_privs(userid, path)
     }
+    fn check_privs_batch(
+        &self,
+        userid: &str,
+        paths: &[Vec<&str>],
+        required: u64,
+        partial: bool,
+    ) -> Vec<bool> {
+        self.deref()
+            .check_privs_batch(userid, paths, required, partial)
+    }
 }
 
 /// Example implementation to check access permissions
@@ -444,4 +511,35 @@ mod test {
             false,
         );
     }
+
+    #[test]
+    fn test_filter_by_priv() {
+        let userinfo = MockedUserInfo {
+            privs: json!({
+                "/datastore/foo": { "user1": 0b01 },
+                "/datastore/bar": { "user1": 0b11, "user2": 0b10 },
+                "/datastore/baz": { "user2": 0b01 },
+            }),
+            groups: json!({}),
+        };
+
+        let stores = ["foo", "bar", "baz", "qux"];
+        let filter = |userid, required, partial| {
+            filter_by_priv(
+                &userinfo,
+                userid,
+                stores,
+                |store| format!("/datastore/{store}"),
+                required,
+                partial,
+            )
+            .collect::<Vec<_>>()
+        };
+
+        assert_eq!(filter("user1", 0b01, false), ["foo", "bar"]);
+        assert_eq!(filter("user1", 0b11, false), ["bar"]);
+        assert_eq!(filter("user1", 0b11, true), ["foo", "bar"]);
+        assert_eq!(filter("user2", 0b11, true), ["bar", "baz"]);
+        assert!(filter("user3", 0b01, true).is_empty());
+    }
 }
@@ -0,0 +1,70 @@
+use std::pin::Pin;
+
+use anyhow::Error;
+
+/// The body of a [`RawResponse`].
+pub enum RawBody {
+    /// The complete response data.
+    Bytes(Vec<u8>),
+    /// Response data produced chunk by chunk.
+    Stream(Pin<Box<dyn futures::Stream<Item = Result<Vec<u8>, Error>> + Send>>),
+}
+
+impl RawBody {
+    /// Create a body from a stream of data chunks.
+    pub fn stream<S>(stream: S) -> Self
+    where
+        S: futures::Stream<Item = Result<Vec<u8>, Error>> + Send + 'static,
+    {
+        RawBody::Stream(Box::pin(stream))
+    }
+}
+
+impl From<Vec<u8>> for RawBody {
+    fn from(data: Vec<u8>) -> Self {
+        RawBody::Bytes(data)
+    }
+}
+
+impl From<String> for RawBody {
+    fn from(data: String) -> Self {
+        RawBody::Bytes(data.into_bytes())
+    }
+}
+
+/// Raw (non-JSON) data returned by [`Raw`](crate::ApiHandler::Raw) and
+/// [`RawAsync`](crate::ApiHandler::RawAsync) API handlers.
+///
+/// The API method declares the content type via [`ReturnType::raw`](proxmox_schema::ReturnType::raw).
+/// The data is passed on as is, without going through an output formatter.
+pub struct RawResponse {
+    /// Overrides the content type declared by the API method.
+    pub content_type: Option<String>,
+    /// The response data.
+    pub body: RawBody,
+    /// Additional HTTP headers, e.g. `Content-Disposition`.
+    pub extra_headers: Vec<(String, String)>,
+}
+
+impl RawResponse {
+    /// Create a response with the content type declared by the API method.
+    pub fn new<B: Into<RawBody>>(body: B) -> Self {
+        Self {
+            content_type: None,
+            body: body.into(),
+            extra_headers: Vec::new(),
+        }
+    }
+
+    /// Override the content type.
+    pub fn content_type<S: Into<String>>(mut self, content_type: S) -> Self {
+        self.content_type = Some(content_type.into());
+        self
+    }
+
+    /// Add an HTTP header.
+    pub fn header<N: Into<String>, V: Into<String>>(mut self, name: N, value: V) -> Self {
+        self.extra_headers.push((name.into(), value.into()));
+        self
+    }
+}
@@ -11,14 +11,18 @@ use http::{Method, Response};
 #[cfg(feature = "server")]
 use hyper::Body;
 use percent_encoding::percent_decode_str;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
-use proxmox_schema::{ObjectSchema, ParameterSchema, ReturnType, Schema};
+use proxmox_schema::upid::PROXMOX_UPID_REGEX;
+use proxmox_schema::{
+    ApiStringFormat, BooleanSchema, ObjectSchema, ObjectSchemaType, ParameterError,
+    ParameterSchema, ReturnType, Schema,
+};
 
-use super::Permission;
-use crate::RpcEnvironment;
-use crate::SerializableReturn;
+use super::{check_api_permission, Permission};
+use crate::{DispatchHooks, RouterHooks, RpcEnvironment};
+use crate::{RawResponse, SerializableReturn};
 
 /// A synchronous API handler gets a json Value as input and returns a json Value as output.
 ///
@@ -386,6 +390,43 @@ impl IntoRecord for Result<Record, Error> {
     }
 }
 
+/// A synchronous API handler returning raw (non-JSON) data.
+///
+/// Unlike [`AsyncHttp`](ApiHandler::AsyncHttp) handlers, parameters are still verified against
+/// the schema. The content type is declared with [`ReturnType::raw`].
+/// ```
+/// # use anyhow::Error;
+/// # use serde_json::Value;
+/// use proxmox_router::{ApiHandler, ApiMethod, RawResponse, RpcEnvironment};
+/// use proxmox_schema::{ObjectSchema, ReturnType};
+///
+/// fn hello(
+///    param: Value,
+///    info: &ApiMethod,
+///    rpcenv: &mut dyn RpcEnvironment,
+/// ) -> Result<RawResponse, Error> {
+///     Ok(RawResponse::new("Hello world!".to_string()))
+/// }
+///
+/// const API_METHOD_HELLO: ApiMethod = ApiMethod::new(
+///    &ApiHandler::Raw(&hello),
+///    &ObjectSchema::new("Hello World Example (raw)", &[])
+/// )
+/// .returns(ReturnType::raw("text/plain"));
+/// ```
+pub type RawApiHandlerFn = &'static (dyn Fn(Value, &ApiMethod, &mut dyn RpcEnvironment) -> Result<RawResponse, Error>
+              + Send
+              + Sync
+              + 'static);
+
+/// An asynchronous API handler returning raw (non-JSON) data, see [`RawApiHandlerFn`].
+pub type RawApiAsyncHandlerFn = &'static (dyn for<'a> Fn(Value, &'static ApiMethod, &'a mut dyn RpcEnvironment) -> RawApiFuture<'a>
+              + Send
+              + Sync);
+
+pub type RawApiFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<RawResponse, anyhow::Error>> + Send + 'a>>;
+
 /// Asynchronous HTTP API handlers
 ///
 /// They get low level access to request and response data. Use this
@@ -444,6 +485,8 @@ pub enum ApiHandler {
     Async(ApiAsyncHandlerFn),
     SerializingAsync(SerializingApiAsyncHandlerFn),
     StreamAsync(StreamApiAsyncHandlerFn),
+    Raw(RawApiHandlerFn),
+    RawAsync(RawApiAsyncHandlerFn),
     #[cfg(feature = "server")]
     AsyncHttp(ApiAsyncHttpHandlerFn),
 }
@@ -474,6 +517,12 @@ impl PartialEq for ApiHandler {
                 (ApiHandler::StreamAsync(l), ApiHandler::StreamAsync(r)) => {
                     core::mem::transmute::<_, usize>(l) == core::mem::transmute::<_, usize>(r)
                 }
+                (ApiHandler::Raw(l), ApiHandler::Raw(r)) => {
+                    core::mem::transmute::<_, usize>(l) == core::mem::transmute::<_, usize>(r)
+                }
+                (ApiHandler::RawAsync(l), ApiHandler::RawAsync(r)) => {
+                    core::mem::transmute::<_, usize>(l) == core::mem::transmute::<_, usize>(r)
+                }
                 #[cfg(feature = "server")]
                 (ApiHandler::AsyncHttp(l), ApiHandler::AsyncHttp(r)) => {
                     core::mem::transmute::<_, usize>(l) == core::mem::transmute::<_, usize>(r)
@@ -516,9 +565,71 @@ pub enum SubRoute {
     },
 }
 
+/// A child of a router, as listed by [`Router::list_children`].
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ChildEntry {
+    /// The path component of the child.
+    #[serde(rename = "subdir")]
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+impl ChildEntry {
+    pub fn new<S: Into<String>>(name: S) -> Self {
+        Self {
+            name: name.into(),
+            description: None,
+        }
+    }
+
+    pub fn with_description<S: Into<String>>(mut self, description: S) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+}
+
+/// Lists the valid values of the parameter of a `MatchAll` router.
+///
+/// Gets the parameters of the parent path and the environment of the call.
+///
+/// ```
+/// # use serde_json::Value;
+/// use proxmox_router::{ChildEntry, EnumerateChildrenFuture, RpcEnvironment};
+///
+/// fn list_vms<'a>(_param: Value, _rpcenv: &'a mut dyn RpcEnvironment) -> EnumerateChildrenFuture<'a> {
+///     Box::pin(async move {
+///         Ok(vec![ChildEntry::new("100").with_description("web server")])
+///     })
+/// }
+/// ```
+pub type EnumerateChildrenFn = &'static (dyn for<'a> Fn(Value, &'a mut dyn RpcEnvironment) -> EnumerateChildrenFuture<'a>
+              + Send
+              + Sync
+              + 'static);
+
+pub type EnumerateChildrenFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<Vec<ChildEntry>, anyhow::Error>> + Send + 'a>>;
+
 /// Macro to create an ApiMethod to list entries from SubdirMap
+///
+/// With `children: ROUTER`, the children of the router are listed with
+/// [`Router::list_children`], which includes the values listed by the enumerator of a `MatchAll`
+/// router.
 #[macro_export]
 macro_rules! list_subdirs_api_method {
+    (children: $router:expr) => {
+        $crate::ApiMethod::new(
+            &$crate::ApiHandler::Async(&|param, _, rpcenv| {
+                ::std::boxed::Box::pin(async move {
+                    let children = $router.list_children(param, rpcenv).await?;
+                    Ok(::serde_json::to_value(children)?)
+                })
+            }),
+            &$crate::ListSubdirsObjectSchema::new("Directory index.", &[])
+                .additional_properties(true)
+        ).access(None, &$crate::Permission::Anybody)
+    };
     ($map:expr) => {
         $crate::ApiMethod::new(
             &$crate::ApiHandler::Sync( & |_, _, _| {
@@ -570,8 +681,15 @@ pub struct Router {
     pub post: Option<&'static ApiMethod>,
     /// DELETE requests
     pub delete: Option<&'static ApiMethod>,
+    /// HEAD requests, handled by the GET method if unset
+    pub head: Option<&'static ApiMethod>,
     /// Used to find the correct API endpoint.
     pub subroute: Option<SubRoute>,
+    /// Hooks run around all API calls of this node and its children.
+    pub hooks: Option<&'static RouterHooks>,
+    /// Lists the valid values of the `MatchAll` parameter, see
+    /// [`list_children`](Self::list_children).
+    pub enumerate_children: Option<EnumerateChildrenFn>,
 }
 
 impl Router {
@@ -582,7 +700,10 @@ impl Router {
             put: None,
             post: None,
             delete: None,
+            head: None,
             subroute: None,
+            hooks: None,
+            enumerate_children: None,
         }
     }
 
@@ -598,6 +719,13 @@ impl Router {
         self
     }
 
+    /// Configure the function listing the valid values of the `MatchAll` parameter, used to list
+    /// the children of this router, see [`list_children`](Self::list_children).
+    pub const fn enumerate_children(mut self, enumerate: EnumerateChildrenFn) -> Self {
+        self.enumerate_children = Some(enumerate);
+        self
+    }
+
     /// Configure the GET method.
     pub const fn get(mut self, m: &'static ApiMethod) -> Self {
         self.get = Some(m);
@@ -643,6 +771,22 @@ impl Router {
         self
     }
 
+    /// Configure the HEAD method.
+    ///
+    /// Without a HEAD method, HEAD requests run the GET method and its response body is discarded.
+    /// Set this for expensive GET methods which can produce the response headers cheaply. If the
+    /// handler does not set the `Content-Length` header, the length of its response body is used.
+    pub const fn head(mut self, m: &'static ApiMethod) -> Self {
+        self.head = Some(m);
+        self
+    }
+
+    /// Configure the hooks run around all API calls of this node and its children.
+    pub const fn hooks(mut self, hooks: &'static RouterHooks) -> Self {
+        self.hooks = Some(hooks);
+        self
+    }
+
     /// Find the router for a specific path.
     ///
     /// - `components`: Path, split into individual components.
@@ -652,6 +796,34 @@ impl Router {
         components: &[&str],
         uri_param: &mut HashMap<String, String>,
     ) -> Option<&Router> {
+        self.find_route_do(components, uri_param, &mut |_| ())
+    }
+
+    /// Find the router for a specific path, and the hooks of all nodes on the path.
+    ///
+    /// See [`find_route`](Self::find_route).
+    pub fn find_route_with_hooks(
+        &self,
+        components: &[&str],
+        uri_param: &mut HashMap<String, String>,
+    ) -> Option<(&Router, DispatchHooks)> {
+        let mut hooks = DispatchHooks::default();
+        let router = self.find_route_do(components, uri_param, &mut |router| {
+            if let Some(router_hooks) = router.hooks {
+                hooks.push(router_hooks);
+            }
+        })?;
+        Some((router, hooks))
+    }
+
+    fn find_route_do(
+        &self,
+        components: &[&str],
+        uri_param: &mut HashMap<String, String>,
+        visit: &mut dyn FnMut(&Router),
+    ) -> Option<&Router> {
+        visit(self);
+
         if components.is_empty() {
             return Some(self);
         };
@@ -669,13 +841,15 @@ impl Router {
                 if let Ok(ind) = dirmap.binary_search_by_key(&dir.as_str(), |(name, _)| name) {
                     let (_name, router) = dirmap[ind];
                     //println!("FOUND SUBDIR {}", dir);
-                    return router.find_route(remaining, uri_param);
+                    return router.find_route_do(remaining, uri_param, visit);
                 }
             }
-            Some(SubRoute::MatchAll { router, param_name }) => {
+            Some(SubRoute::MatchAll {
+                router, param_name, ..
+            }) => {
                 //println!("URI PARAM {} = {}", param_name, dir); // fixme: store somewhere
                 uri_param.insert(param_name.to_owned(), dir);
-                return router.find_route(remaining, uri_param);
+                return router.find_route_do(remaining, uri_param, visit);
             }
         }
 
@@ -693,16 +867,122 @@ impl Router {
         method: Method,
         uri_param: &mut HashMap<String, String>,
     ) -> Option<&ApiMethod> {
-        if let Some(info) = self.find_route(components, uri_param) {
-            return match method {
-                Method::GET => info.get,
-                Method::PUT => info.put,
-                Method::POST => info.post,
-                Method::DELETE => info.delete,
-                _ => None,
-            };
+        let info = self.find_route(components, uri_param)?;
+        info.method(&method)
+    }
+
+    /// Lookup the API method for a specific path, and the hooks of all nodes on the path.
+    ///
+    /// See [`find_method`](Self::find_method).
+    #[cfg(feature = "server")]
+    pub fn find_method_with_hooks(
+        &self,
+        components: &[&str],
+        method: Method,
+        uri_param: &mut HashMap<String, String>,
+    ) -> Option<(&ApiMethod, DispatchHooks)> {
+        let (info, hooks) = self.find_route_with_hooks(components, uri_param)?;
+        Some((info.method(&method)?, hooks))
+    }
+
+    /// List the children of this router.
+    ///
+    /// These are the entries of a static subdir map, or the values listed by the enumerator of a
+    /// `MatchAll` subroute, see [`enumerate_children`](Self::enumerate_children). `param` are the
+    /// parameters of the path of this router.
+    ///
+    /// Enumerated children are only listed if the environment provides the
+    /// [user information](RpcEnvironment::get_user_info) and the access definition of the GET
+    /// method of the child permits it. Without user information, or if the child has no GET
+    /// method, no enumerated children are listed.
+    pub async fn list_children(
+        &self,
+        param: Value,
+        rpcenv: &mut dyn RpcEnvironment,
+    ) -> Result<Vec<ChildEntry>, Error> {
+        let (router, param_name) = match self.subroute {
+            None => return Ok(Vec::new()),
+            Some(SubRoute::Map(map)) => {
+                return Ok(map.iter().map(|(name, _)| ChildEntry::new(*name)).collect())
+            }
+            Some(SubRoute::MatchAll { router, param_name }) => (router, param_name),
+        };
+
+        let (Some(enumerate), Some(user_info), Some(method)) =
+            (self.enumerate_children, rpcenv.get_user_info(), router.get)
+        else {
+            return Ok(Vec::new());
+        };
+
+        let mut uri_param: HashMap<String, String> = match &param {
+            Value::Object(map) => map
+                .iter()
+                .map(|(key, value)| match value {
+                    Value::String(value) => (key.clone(), value.clone()),
+                    value => (key.clone(), value.to_string()),
+                })
+                .collect(),
+            _ => HashMap::new(),
+        };
+
+        let auth_id = rpcenv.get_auth_id();
+        let mut children = enumerate(param, rpcenv).await?;
+
+        children.retain(|child| {
+            uri_param.insert(param_name.to_string(), child.name.clone());
+            check_api_permission(
+                method.access.permission,
+                auth_id.as_deref(),
+                &uri_param,
+                user_info.as_ref(),
+            )
+        });
+
+        Ok(children)
+    }
+
+    /// Complete an API path, e.g. for a command line tool calling API methods by path.
+    ///
+    /// Returns the paths of the children matching the last component of `path`, see
+    /// [`list_children`](Self::list_children).
+    pub async fn complete_path(&self, path: &str, rpcenv: &mut dyn RpcEnvironment) -> Vec<String> {
+        let (parent, prefix) = path.rsplit_once('/').unwrap_or(("", path));
+        let components: Vec<&str> = parent.split('/').filter(|c| !c.is_empty()).collect();
+
+        let mut uri_param = HashMap::new();
+        let Some(router) = self.find_route(&components, &mut uri_param) else {
+            return Vec::new();
+        };
+
+        let param = uri_param
+            .into_iter()
+            .map(|(key, value)| (key, Value::String(value)))
+            .collect();
+        let Ok(children) = router.list_children(Value::Object(param), rpcenv).await else {
+            return Vec::new();
+        };
+
+        let parent = match path.rsplit_once('/') {
+            Some((parent, _)) => format!("{parent}/"),
+            None => String::new(),
+        };
+        children
+            .into_iter()
+            .filter(|child| child.name.starts_with(prefix))
+            .map(|child| format!("{parent}{}", child.name))
+            .collect()
+    }
+
+    #[cfg(feature = "server")]
+    fn method(&self, method: &Method) -> Option<&'static ApiMethod> {
+        match *method {
+            Method::GET => self.get,
+            Method::PUT => self.put,
+            Method::POST => self.post,
+            Method::DELETE => self.delete,
+            Method::HEAD => self.head.or(self.get),
+            _ => None,
         }
-        None
     }
 }
 
@@ -731,8 +1011,79 @@ const DUMMY_HANDLER: ApiHandler = ApiHandler::Sync(&dummy_handler_fn);
 pub struct ApiAccess {
     pub description: Option<&'static str>,
     pub permission: &'static Permission,
+    /// Whether the permission was set explicitly instead of defaulting to
+    /// [`Permission::Superuser`], see [`audit_permissions`](crate::audit_permissions).
+    pub explicit: bool,
+}
+
+/// Response caching policy of an API method.
+///
+/// Servers may answer `GET` requests from a cache for up to `max_age` seconds. Authentication and
+/// permission checks still happen on every request.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct CachePolicy {
+    /// The number of seconds a response may be served from the cache.
+    pub max_age: u64,
+    /// Cache responses separately for each user.
+    pub vary_user: bool,
+    /// Cache responses separately for each set of parameters.
+    pub vary_parameters: bool,
+}
+
+impl CachePolicy {
+    /// Cache responses for `max_age` seconds, separately for each user and set of parameters.
+    pub const fn new(max_age: u64) -> Self {
+        Self {
+            max_age,
+            vary_user: true,
+            vary_parameters: true,
+        }
+    }
+
+    pub const fn vary_user(mut self, vary_user: bool) -> Self {
+        self.vary_user = vary_user;
+
+        self
+    }
+
+    pub const fn vary_parameters(mut self, vary_parameters: bool) -> Self {
+        self.vary_parameters = vary_parameters;
+
+        self
+    }
+}
+
+/// How long a call of an API method is expected to take, e.g. to choose client timeouts.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExpectedDuration {
+    /// Quick calls, like reading metadata or configuration.
+    Fast,
+    /// Calls which may take a while, like querying other hosts.
+    Slow,
+    /// Calls spawning a worker task, they return its UPID once it was started.
+    Task,
+    /// Calls streaming their request or response, possibly for minutes.
+    Streaming,
+}
+
+serde_plain::derive_display_from_serialize!(ExpectedDuration);
+
+impl ExpectedDuration {
+    /// All classes, from the fastest to the slowest.
+    pub const ALL: [ExpectedDuration; 4] = [Self::Fast, Self::Slow, Self::Task, Self::Streaming];
 }
 
+/// Name of the well-known parameter of methods supporting a dry run, see
+/// [`ApiMethod::supports_dry_run`].
+pub const DRY_RUN_PARAMETER: &str = "dry-run";
+
+/// Schema of the [`DRY_RUN_PARAMETER`].
+pub const DRY_RUN_SCHEMA: Schema =
+    BooleanSchema::new("Only show what would be done, without making any changes.")
+        .default(false)
+        .schema();
+
 /// This struct defines a synchronous API call which returns the result as json `Value`
 #[cfg_attr(feature = "test-harness", derive(Eq, PartialEq))]
 pub struct ApiMethod {
@@ -750,6 +1101,13 @@ pub struct ApiMethod {
     pub handler: &'static ApiHandler,
     /// Access Permissions
     pub access: ApiAccess,
+    /// Response caching policy, responses are not cached if unset
+    pub cache: Option<CachePolicy>,
+    /// Expected duration of calls, derived from the method if unset, see
+    /// [`duration_class`](Self::duration_class).
+    expected_duration: Option<ExpectedDuration>,
+    /// The largest reasonable response size in bytes, as a hint for clients.
+    max_response_size: Option<u64>,
 }
 
 impl std::fmt::Debug for ApiMethod {
@@ -774,7 +1132,11 @@ impl ApiMethod {
             access: ApiAccess {
                 description: None,
                 permission: &Permission::Superuser,
+                explicit: false,
             },
+            cache: None,
+            expected_duration: None,
+            max_response_size: None,
         }
     }
 
@@ -792,7 +1154,11 @@ impl ApiMethod {
             access: ApiAccess {
                 description: None,
                 permission: &Permission::Superuser,
+                explicit: false,
             },
+            cache: None,
+            expected_duration: None,
+            max_response_size: None,
         }
     }
 
@@ -814,6 +1180,32 @@ impl ApiMethod {
         self
     }
 
+    pub const fn cache(mut self, cache: CachePolicy) -> Self {
+        self.cache = Some(cache);
+
+        self
+    }
+
+    /// Set the expected duration of calls, see [`duration_class`](Self::duration_class).
+    pub const fn expected_duration(mut self, duration: ExpectedDuration) -> Self {
+        self.expected_duration = Some(duration);
+
+        self
+    }
+
+    /// Set the largest reasonable response size in bytes, as a hint for clients.
+    pub const fn max_response_size(mut self, bytes: u64) -> Self {
+        self.max_response_size = Some(bytes);
+
+        self
+    }
+
+    /// The largest reasonable response size in bytes, see
+    /// [`max_response_size`](Self::max_response_size).
+    pub const fn response_size_hint(&self) -> Option<u64> {
+        self.max_response_size
+    }
+
     pub const fn access(
         mut self,
         description: Option<&'static str>,
@@ -822,8 +1214,95 @@ impl ApiMethod {
         self.access = ApiAccess {
             description,
             permission,
+            explicit: true,
         };
 
         self
     }
+
+    /// The expected duration of calls of this method.
+    ///
+    /// Unless set explicitly, streaming methods and methods handling the HTTP request themselves,
+    /// e.g. to read an upload, are [`Streaming`](ExpectedDuration::Streaming), methods returning a
+    /// UPID, i.e. spawning a worker task, are
+    /// [`Task`](ExpectedDuration::Task), and all others are [`Fast`](ExpectedDuration::Fast).
+    pub fn duration_class(&self) -> ExpectedDuration {
+        if let Some(duration) = self.expected_duration {
+            return duration;
+        }
+
+        match self.handler {
+            ApiHandler::StreamSync(_) | ApiHandler::StreamAsync(_) => {
+                return ExpectedDuration::Streaming
+            }
+            #[cfg(feature = "server")]
+            ApiHandler::AsyncHttp(_) => return ExpectedDuration::Streaming,
+            _ => (),
+        }
+
+        match self.returns.schema {
+            Schema::String(schema) => match schema.format {
+                Some(ApiStringFormat::Pattern(pattern))
+                    if pattern.regex_string == PROXMOX_UPID_REGEX.regex_string =>
+                {
+                    ExpectedDuration::Task
+                }
+                _ => ExpectedDuration::Fast,
+            },
+            _ => ExpectedDuration::Fast,
+        }
+    }
+
+    /// Whether the method supports a dry run, by declaring the boolean [`DRY_RUN_PARAMETER`],
+    /// usually with the [`DRY_RUN_SCHEMA`].
+    ///
+    /// The handler has to honor the parameter itself. The CLI marks the output of dry runs, and
+    /// the REST server rejects the parameter for methods not declaring it.
+    pub fn supports_dry_run(&self) -> bool {
+        matches!(
+            self.parameters.lookup(DRY_RUN_PARAMETER),
+            Some((_, Schema::Boolean(_)))
+        )
+    }
+
+    /// Whether `params` request a dry run of the method.
+    pub fn is_dry_run(&self, params: &Value) -> bool {
+        self.supports_dry_run() && params[DRY_RUN_PARAMETER].as_bool() == Some(true)
+    }
+
+    /// Convert the parameters captured from the path by `MatchAll` routers according to the
+    /// parameter schema, like query parameters: integers, numbers and booleans are parsed and
+    /// stored in their normalized form, and all values are checked against their constraints.
+    ///
+    /// This should be done before checking permissions, so that parameters used in permission
+    /// checks have the same form as the ones passed to the handler. Parameters without schema are
+    /// left as they are.
+    pub fn normalize_path_params(
+        &self,
+        uri_param: &mut HashMap<String, String>,
+    ) -> Result<(), ParameterError> {
+        let mut names: Vec<String> = uri_param.keys().cloned().collect();
+        names.sort();
+
+        let mut errors = ParameterError::new();
+        for name in names {
+            let schema = match self.parameters.lookup(&name) {
+                Some((_optional, schema)) => schema,
+                None => continue,
+            };
+            match schema.parse_simple_value(&uri_param[&name]) {
+                Ok(Value::String(_)) => (),
+                Ok(value) => {
+                    uri_param.insert(name, value.to_string());
+                }
+                Err(err) => errors.push(name, err),
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
 }
@@ -1,7 +1,10 @@
 use std::any::Any;
+use std::sync::Arc;
 
 use serde_json::Value;
 
+use crate::UserInformation;
+
 /// Helper to get around `RpcEnvironment: Sized`
 pub trait AsAny {
     fn as_any(&self) -> &(dyn Any + Send);
@@ -45,6 +48,16 @@ pub trait RpcEnvironment: Any + AsAny + Send {
     fn get_client_ip(&self) -> Option<std::net::SocketAddr> {
         None // dummy no-op implementation, as most environments don't need this
     }
+
+    /// Set the information about the authenticated user, used to check permissions
+    fn set_user_info(&mut self, _user_info: Option<Arc<dyn UserInformation + Send + Sync>>) {
+        // dummy no-op implementation, as most environments don't need this
+    }
+
+    /// Get the information about the authenticated user, if the environment checks permissions
+    fn get_user_info(&self) -> Option<Arc<dyn UserInformation + Send + Sync>> {
+        None // dummy no-op implementation, as most environments don't need this
+    }
 }
 
 /// Environment Type
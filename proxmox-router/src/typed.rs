@@ -0,0 +1,150 @@
+//! Typed API handlers.
+//!
+//! Hand-written API methods usually have to pull their parameters out of a `Value`. The
+//! [`typed_api_handler!`](crate::typed_api_handler) macro instead wraps a function taking a
+//! deserialized parameter type and returning a serializable value into an [`ApiHandler`]:
+//!
+//! ```
+//! # use anyhow::Error;
+//! # use serde::{Deserialize, Serialize};
+//! use proxmox_router::{typed_api_handler, ApiMethod, RpcEnvironment};
+//! use proxmox_schema::{ApiType, ObjectSchema, Schema, StringSchema};
+//!
+//! #[derive(Deserialize)]
+//! struct HelloParams {
+//!     name: String,
+//! }
+//!
+//! impl ApiType for HelloParams {
+//!     const API_SCHEMA: Schema = ObjectSchema::new(
+//!         "Hello parameters.",
+//!         &[("name", false, &StringSchema::new("The name.").schema())],
+//!     )
+//!     .schema();
+//! }
+//!
+//! fn hello(param: HelloParams, _rpcenv: &mut dyn RpcEnvironment) -> Result<String, Error> {
+//!     Ok(format!("Hello {}!", param.name))
+//! }
+//!
+//! async fn hello_async(
+//!     param: HelloParams,
+//!     _rpcenv: &mut dyn RpcEnvironment,
+//! ) -> Result<String, Error> {
+//!     Ok(format!("Hello {}!", param.name))
+//! }
+//!
+//! const API_METHOD_HELLO: ApiMethod = ApiMethod::new(
+//!     &typed_api_handler!(hello),
+//!     HelloParams::API_SCHEMA.unwrap_object_schema(),
+//! );
+//!
+//! const API_METHOD_HELLO_ASYNC: ApiMethod = ApiMethod::new(
+//!     &typed_api_handler!(async hello_async),
+//!     HelloParams::API_SCHEMA.unwrap_object_schema(),
+//! );
+//! ```
+//!
+//! [`ApiHandler`]: crate::ApiHandler
+
+use std::future::Future;
+
+use anyhow::{format_err, Error};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+
+use proxmox_schema::de::ExtractValueDeserializer;
+use proxmox_schema::{ApiType, ParameterError};
+
+use crate::{ApiFuture, RpcEnvironment};
+
+/// Deserialize API call parameters into a typed parameter struct.
+///
+/// The parameters are verified against `T::API_SCHEMA` first, so that errors are reported as a
+/// [`ParameterError`] naming the offending parameters. Booleans given as strings are accepted,
+/// see [`Schema::normalize_booleans`](proxmox_schema::Schema::normalize_booleans).
+pub fn param_from_value<T>(param: Value) -> Result<T, Error>
+where
+    T: DeserializeOwned + ApiType,
+{
+    let mut param = match param {
+        Value::Null => Value::Object(serde_json::Map::new()),
+        param => param,
+    };
+
+    T::API_SCHEMA.normalize_booleans(&mut param);
+    T::API_SCHEMA.verify_json(&param)?;
+
+    let map = param
+        .as_object_mut()
+        .ok_or_else(|| format_err!("expected an object"))?;
+    let deserializer = ExtractValueDeserializer::try_new(map, &T::API_SCHEMA)
+        .ok_or_else(|| format_err!("parameter type does not have an object schema"))?;
+
+    T::deserialize(deserializer).map_err(|err| ParameterError::from(("", Error::from(err))).into())
+}
+
+/// Convert the result of a typed API handler back into a `Value`.
+pub fn result_to_value<R: Serialize>(result: R) -> Result<Value, Error> {
+    Ok(serde_json::to_value(result)?)
+}
+
+/// Call a typed synchronous handler with untyped parameters, see
+/// [`typed_api_handler!`](crate::typed_api_handler).
+#[doc(hidden)]
+pub fn call_sync<T, R, F>(
+    handler: F,
+    param: Value,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<Value, Error>
+where
+    T: DeserializeOwned + ApiType,
+    R: Serialize,
+    F: FnOnce(T, &mut dyn RpcEnvironment) -> Result<R, Error>,
+{
+    let param = param_from_value(param)?;
+    result_to_value(handler(param, rpcenv)?)
+}
+
+/// Call a typed asynchronous handler with untyped parameters, see
+/// [`typed_api_handler!`](crate::typed_api_handler).
+#[doc(hidden)]
+pub fn call_async<'a, T, R, F, Fut>(
+    handler: F,
+    param: Value,
+    rpcenv: &'a mut dyn RpcEnvironment,
+) -> ApiFuture<'a>
+where
+    T: DeserializeOwned + ApiType,
+    R: Serialize,
+    F: FnOnce(T, &'a mut dyn RpcEnvironment) -> Fut,
+    Fut: Future<Output = Result<R, Error>> + Send + 'a,
+{
+    let param = match param_from_value(param) {
+        Ok(param) => param,
+        Err(err) => return Box::pin(async move { Err(err) }),
+    };
+
+    let future = handler(param, rpcenv);
+    Box::pin(async move { result_to_value(future.await?) })
+}
+
+/// Create an [`ApiHandler`](crate::ApiHandler) from a typed handler function.
+///
+/// The handler takes its parameters as a type implementing `Deserialize` and `ApiType`, and
+/// returns a `Serialize` type. Prefix the function with `async` for asynchronous handlers. See
+/// the [`typed`](crate::typed) module for an example.
+#[macro_export]
+macro_rules! typed_api_handler {
+    (async $handler:expr) => {
+        $crate::ApiHandler::Async(&|param, _info, rpcenv| {
+            $crate::typed::call_async($handler, param, rpcenv)
+        })
+    };
+    ($handler:expr) => {
+        $crate::ApiHandler::Sync(&|param, _info, rpcenv| {
+            $crate::typed::call_sync($handler, param, rpcenv)
+        })
+    };
+}
@@ -0,0 +1,227 @@
+use std::sync::Arc;
+
+use serde_json::{json, Value};
+
+use proxmox_router::{
+    list_subdirs_api_method, ApiHandler, ApiMethod, ChildEntry, EnumerateChildrenFuture,
+    Permission, Router, RpcEnvironment, RpcEnvironmentType, SubdirMap, UserInformation,
+};
+use proxmox_schema::ObjectSchema;
+
+const PRIV_VM_AUDIT: u64 = 1;
+
+/// Grants `PRIV_VM_AUDIT` on all paths.
+struct AuditorUserInfo;
+
+impl UserInformation for AuditorUserInfo {
+    fn is_superuser(&self, _userid: &str) -> bool {
+        false
+    }
+
+    fn is_group_member(&self, _userid: &str, _group: &str) -> bool {
+        false
+    }
+
+    fn lookup_privs(&self, _userid: &str, _path: &[&str]) -> u64 {
+        PRIV_VM_AUDIT
+    }
+}
+
+/// Grants `PRIV_VM_AUDIT` on `/nodes/{node}/vms/100` only.
+struct TestUserInfo;
+
+impl UserInformation for TestUserInfo {
+    fn is_superuser(&self, _userid: &str) -> bool {
+        false
+    }
+
+    fn is_group_member(&self, _userid: &str, _group: &str) -> bool {
+        false
+    }
+
+    fn lookup_privs(&self, _userid: &str, path: &[&str]) -> u64 {
+        match path {
+            ["nodes", _, "vms", "100"] => PRIV_VM_AUDIT,
+            _ => 0,
+        }
+    }
+}
+
+struct TestEnvironment {
+    result: Value,
+    user_info: Option<Arc<dyn UserInformation + Send + Sync>>,
+}
+
+impl TestEnvironment {
+    fn new(user_info: Option<Arc<dyn UserInformation + Send + Sync>>) -> Self {
+        Self {
+            result: json!({}),
+            user_info,
+        }
+    }
+}
+
+impl RpcEnvironment for TestEnvironment {
+    fn result_attrib_mut(&mut self) -> &mut Value {
+        &mut self.result
+    }
+
+    fn result_attrib(&self) -> &Value {
+        &self.result
+    }
+
+    fn env_type(&self) -> RpcEnvironmentType {
+        RpcEnvironmentType::PUBLIC
+    }
+
+    fn set_auth_id(&mut self, _user: Option<String>) {}
+
+    fn get_auth_id(&self) -> Option<String> {
+        Some("user@pam".to_string())
+    }
+
+    fn get_user_info(&self) -> Option<Arc<dyn UserInformation + Send + Sync>> {
+        self.user_info.clone()
+    }
+}
+
+fn list_vms<'a>(param: Value, _rpcenv: &'a mut dyn RpcEnvironment) -> EnumerateChildrenFuture<'a> {
+    Box::pin(async move {
+        // the parameters of the parent path are passed along
+        assert_eq!(param["node"], "node1");
+        Ok(vec![
+            ChildEntry::new("100").with_description("web server"),
+            ChildEntry::new("101"),
+            ChildEntry::new("200"),
+        ])
+    })
+}
+
+const API_METHOD_GET_VM: ApiMethod = ApiMethod::new_dummy(&ObjectSchema::new("Get a VM.", &[]))
+    .access(
+        None,
+        &Permission::Privilege(&["nodes", "{node}", "vms", "{vmid}"], PRIV_VM_AUDIT, false),
+    );
+
+const VM_ROUTER: Router = Router::new().get(&API_METHOD_GET_VM);
+
+const VMS_ROUTER: Router = Router::new()
+    .get(&list_subdirs_api_method!(children: VMS_ROUTER))
+    .match_all("vmid", &VM_ROUTER)
+    .enumerate_children(&list_vms);
+
+const NODE_SUBDIRS: SubdirMap = &[("status", &Router::new()), ("vms", &VMS_ROUTER)];
+
+const NODE_ROUTER: Router = Router::new()
+    .get(&list_subdirs_api_method!(children: NODE_ROUTER))
+    .subdirs(NODE_SUBDIRS);
+
+const ROUTER: Router =
+    Router::new().subdirs(&[("nodes", &Router::new().match_all("node", &NODE_ROUTER))]);
+
+fn call_index(router: &Router, param: Value, rpcenv: &mut dyn RpcEnvironment) -> Value {
+    let ApiHandler::Async(handler) = router.get.unwrap().handler else {
+        panic!("expected an async index handler");
+    };
+    proxmox_async::runtime::block_on(handler(param, router.get.unwrap(), rpcenv)).unwrap()
+}
+
+#[test]
+fn test_index() {
+    let mut rpcenv = TestEnvironment::new(Some(Arc::new(AuditorUserInfo)));
+
+    assert_eq!(
+        call_index(&VMS_ROUTER, json!({ "node": "node1" }), &mut rpcenv),
+        json!([
+            { "subdir": "100", "description": "web server" },
+            { "subdir": "101" },
+            { "subdir": "200" },
+        ]),
+    );
+
+    assert_eq!(
+        call_index(&NODE_ROUTER, json!({ "node": "node1" }), &mut rpcenv),
+        json!([{ "subdir": "status" }, { "subdir": "vms" }]),
+    );
+}
+
+#[test]
+fn test_index_permissions() {
+    let mut rpcenv = TestEnvironment::new(Some(Arc::new(TestUserInfo)));
+
+    assert_eq!(
+        call_index(&VMS_ROUTER, json!({ "node": "node1" }), &mut rpcenv),
+        json!([{ "subdir": "100", "description": "web server" }]),
+    );
+}
+
+#[test]
+fn test_index_without_user_info() {
+    let mut rpcenv = TestEnvironment::new(None);
+
+    assert_eq!(
+        call_index(&VMS_ROUTER, json!({ "node": "node1" }), &mut rpcenv),
+        json!([]),
+    );
+    assert_eq!(
+        call_index(&NODE_ROUTER, json!({ "node": "node1" }), &mut rpcenv),
+        json!([{ "subdir": "status" }, { "subdir": "vms" }]),
+    );
+}
+
+#[test]
+fn test_complete_path() {
+    let complete = |path: &str| {
+        let mut rpcenv = TestEnvironment::new(Some(Arc::new(AuditorUserInfo)));
+        proxmox_async::runtime::block_on(ROUTER.complete_path(path, &mut rpcenv))
+    };
+
+    assert_eq!(complete(""), ["nodes"]);
+    assert_eq!(complete("/no"), ["/nodes"]);
+    assert_eq!(
+        complete("/nodes/node1/"),
+        ["/nodes/node1/status", "/nodes/node1/vms"]
+    );
+    assert_eq!(
+        complete("/nodes/node1/vms/"),
+        [
+            "/nodes/node1/vms/100",
+            "/nodes/node1/vms/101",
+            "/nodes/node1/vms/200"
+        ],
+    );
+    assert_eq!(
+        complete("nodes/node1/vms/10"),
+        ["nodes/node1/vms/100", "nodes/node1/vms/101"],
+    );
+    assert!(complete("/nodes/node1/vms/100/").is_empty());
+    assert!(complete("/unknown/").is_empty());
+
+    let mut rpcenv = TestEnvironment::new(Some(Arc::new(TestUserInfo)));
+    assert_eq!(
+        proxmox_async::runtime::block_on(ROUTER.complete_path("/nodes/node1/vms/", &mut rpcenv)),
+        ["/nodes/node1/vms/100"],
+    );
+}
+
+#[test]
+fn test_cli_complete_api_path() {
+    assert_eq!(
+        proxmox_router::cli::complete_api_path(
+            &ROUTER,
+            "/nodes/node1/vms/",
+            "user@pam",
+            Arc::new(TestUserInfo),
+        ),
+        ["/nodes/node1/vms/100"],
+    );
+    assert_eq!(
+        proxmox_router::cli::complete_api_path(
+            &ROUTER,
+            "/nodes/node1/vms/2",
+            "user@pam",
+            Arc::new(AuditorUserInfo),
+        ),
+        ["/nodes/node1/vms/200"],
+    );
+}
@@ -5,7 +5,8 @@ use proxmox_router::cli::{CliCommand, CliCommandMap, CommandLineInterface, Globa
 use proxmox_router::{ApiHandler, ApiMethod, RpcEnvironment};
 use proxmox_schema::format::DocumentationFormat;
 use proxmox_schema::{
-    ApiStringFormat, ApiType, BooleanSchema, EnumEntry, ObjectSchema, Schema, StringSchema,
+    ApiStringFormat, ApiType, BooleanSchema, EnumEntry, IntegerSchema, ObjectSchema, ReturnType,
+    Schema, StringSchema,
 };
 
 fn dummy_method(
@@ -42,6 +43,79 @@ const API_METHOD_SIMPLE1: ApiMethod = ApiMethod::new(
     ),
 );
 
+const API_METHOD_EXAMPLES: ApiMethod = ApiMethod::new(
+    &ApiHandler::Sync(&dummy_method),
+    &ObjectSchema::new(
+        "Create a snapshot of a guest, keeping older snapshots according to the retention \
+        settings of the storage.",
+        &[
+            (
+                "description",
+                true,
+                &StringSchema::new("A free-form description of the snapshot, shown in the GUI.")
+                    .example("before upgrade")
+                    .schema(),
+            ),
+            (
+                "keep",
+                true,
+                &IntegerSchema::new("Number of snapshots to keep.")
+                    .minimum(1)
+                    .default(3)
+                    .schema(),
+            ),
+            (
+                "mode",
+                true,
+                &StringSchema::new("Snapshot mode.")
+                    .format(&ApiStringFormat::Enum(&[
+                        EnumEntry::new("stop", "Stop the guest."),
+                        EnumEntry::new("suspend", "Suspend the guest."),
+                        EnumEntry::new("snapshot", "Live snapshot."),
+                    ]))
+                    .default("snapshot")
+                    .schema(),
+            ),
+            (
+                "vmid",
+                false,
+                &IntegerSchema::new("The ID of the guest.")
+                    .minimum(100)
+                    .example("100")
+                    .schema(),
+            ),
+        ],
+    ),
+)
+.returns(ReturnType::new(
+    false,
+    &StringSchema::new("The name of the created snapshot.").schema(),
+));
+
+const API_METHOD_SECRET_EXAMPLE: ApiMethod = ApiMethod::new(
+    &ApiHandler::Sync(&dummy_method),
+    &ObjectSchema::new(
+        "Log in.",
+        &[
+            (
+                "password",
+                false,
+                &StringSchema::new("The password.")
+                    .secret()
+                    .example("Hunter2!")
+                    .schema(),
+            ),
+            (
+                "username",
+                false,
+                &StringSchema::new("The user name.")
+                    .example("root@pam")
+                    .schema(),
+            ),
+        ],
+    ),
+);
+
 #[allow(dead_code)]
 struct GlobalOpts {
     global: String,
@@ -104,11 +178,18 @@ fn get_complex_test_cmddef() -> CliCommandMap {
         )
 }
 
+fn get_example_cmddef() -> CliCommandMap {
+    CliCommandMap::new().insert_help().insert(
+        "snapshot",
+        CliCommand::new(&API_METHOD_EXAMPLES).arg_param(&["vmid"]),
+    )
+}
+
 fn expected_toplevel_help_text() -> &'static str {
     r##"
 Usage:
 
-clicmd help [{<command>}] [OPTIONS]
+clicmd help [<command>...] [OPTIONS]
 clicmd l0c1 --another-required-arg <string> --required-arg <string> [OPTIONS]
 clicmd l0c2 <required-arg> --another-required-arg <string> [OPTIONS]
 clicmd l0sub l1c1 --another-required-arg <string> --required-arg <string> [OPTIONS]
@@ -123,30 +204,80 @@ Usage: clicmd l0sub l1c1 --another-required-arg <string> --required-arg <string>
 
 Simple API method with one required and one optional argument.
 
- --another-required-arg <string>
-             A second required string argument.
+Parameters:
+  --another-required-arg  <string>                    A second required string
+                                                      argument.
+  --required-arg          <string>                    Required string argument.
+
+Optional parameters:
+  --optional-arg          <boolean>  (default=false)  Optional boolean argument.
+
+Inherited group parameters:
+  --global1               one|two                     A global option.
+  --global2               <string>                    A second global option.
+"##
+    .trim_start()
+}
+
+fn expected_example_help_text() -> &'static str {
+    r##"
+Usage: clicmd snapshot <vmid> [OPTIONS]
+
+Create a snapshot of a guest, keeping older snapshots according to the retention
+settings of the storage.
 
- --required-arg <string>
-             Required string argument.
+Arguments:
+  <vmid>         <integer> (100 - N)                        The ID of the guest.
 
 Optional parameters:
+  --description  <string>                                   A free-form
+                                                            description of the
+                                                            snapshot, shown in
+                                                            the GUI.
+  --keep         <integer> (1 - N)      (default=3)         Number of snapshots
+                                                            to keep.
+  --mode         stop|suspend|snapshot  (default=snapshot)  Snapshot mode.
+
+Example:
+  clicmd snapshot 100 --description 'before upgrade'
+"##
+    .trim_start()
+}
+
+fn expected_verbose_narrow_help_text() -> &'static str {
+    r##"
+Usage: clicmd snapshot <vmid> [OPTIONS]
 
- --optional-arg <boolean>   (default=false)
-             Optional boolean argument.
+Create a snapshot of a guest, keeping
+older snapshots according to the
+retention settings of the storage.
 
-Inherited group parameters:
+Arguments:
+  <vmid>         <integer> (100 - N)
+        The ID of the guest.
+
+Optional parameters:
+  --description  <string>
+        A free-form description of the
+        snapshot, shown in the GUI.
+  --keep         <integer> (1 - N)      (default=3)
+        Number of snapshots to keep.
+  --mode         stop|suspend|snapshot  (default=snapshot)
+        Snapshot mode.
+
+Returns: <string>
 
- --global1  one|two
-             A global option.
- --global2  <string>
-             A second global option.
+  The name of the created snapshot.
+
+Example:
+  clicmd snapshot 100 --description 'before upgrade'
 "##
     .trim_start()
 }
 
 fn expected_nested_usage_text() -> &'static str {
     r##"
-``clicmd help [{<command>}] [OPTIONS]``
+``clicmd help [<command>...] [OPTIONS]``
 
 Get help about specified command (or sub-command).
 
@@ -284,11 +415,12 @@ fn test_toplevel_help() {
 #[test]
 fn test_group_help() {
     let mut help = String::new();
-    proxmox_router::cli::print_help_to(
+    proxmox_router::cli::print_help_to_width(
         &get_complex_test_cmddef().into(),
         "clicmd".to_string(),
         &["l0sub".to_string(), "l1c1".to_string()],
         None,
+        80,
         &mut help,
     )
     .expect("failed to format help string");
@@ -297,3 +429,51 @@ fn test_group_help() {
     // println!("--- END EXPECTED DOC OUTPUT ---");
     assert_eq!(help, expected_group_help_text());
 }
+
+#[test]
+fn test_example_help() {
+    let print_help = |verbose, columns| {
+        let mut help = String::new();
+        proxmox_router::cli::print_help_to_width(
+            &get_example_cmddef().into(),
+            "clicmd".to_string(),
+            &["snapshot".to_string()],
+            verbose,
+            columns,
+            &mut help,
+        )
+        .expect("failed to format help string");
+        help
+    };
+
+    assert_eq!(print_help(None, 80), expected_example_help_text());
+    assert_eq!(
+        print_help(Some(true), 40),
+        expected_verbose_narrow_help_text()
+    );
+}
+
+#[test]
+fn test_secret_example_help() {
+    let cmddef = CliCommandMap::new().insert(
+        "login",
+        CliCommand::new(&API_METHOD_SECRET_EXAMPLE).arg_param(&["username"]),
+    );
+
+    let mut help = String::new();
+    proxmox_router::cli::print_help_to_width(
+        &cmddef.into(),
+        "clicmd".to_string(),
+        &["login".to_string()],
+        None,
+        80,
+        &mut help,
+    )
+    .expect("failed to format help string");
+
+    assert!(
+        help.ends_with("Example:\n  clicmd login root@pam --password <redacted>\n"),
+        "{help}"
+    );
+    assert!(!help.contains("Hunter2"), "leaked secret: {help}");
+}
@@ -0,0 +1,171 @@
+use anyhow::Error;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use proxmox_router::{
+    typed_api_handler, ApiHandler, ApiMethod, Router, RpcEnvironment, RpcEnvironmentType,
+};
+use proxmox_schema::{
+    ApiType, BooleanSchema, IntegerSchema, ObjectSchema, ParameterError, Schema, StringSchema,
+};
+
+struct TestEnvironment(Value);
+
+impl RpcEnvironment for TestEnvironment {
+    fn result_attrib_mut(&mut self) -> &mut Value {
+        &mut self.0
+    }
+
+    fn result_attrib(&self) -> &Value {
+        &self.0
+    }
+
+    fn env_type(&self) -> RpcEnvironmentType {
+        RpcEnvironmentType::CLI
+    }
+
+    fn set_auth_id(&mut self, _user: Option<String>) {}
+
+    fn get_auth_id(&self) -> Option<String> {
+        None
+    }
+}
+
+#[derive(Deserialize)]
+struct GreetParams {
+    name: String,
+    count: Option<u32>,
+    shout: Option<bool>,
+}
+
+impl ApiType for GreetParams {
+    const API_SCHEMA: Schema = ObjectSchema::new(
+        "Greeting parameters.",
+        &[
+            (
+                "count",
+                true,
+                &IntegerSchema::new("Repetitions.").minimum(1).schema(),
+            ),
+            ("name", false, &StringSchema::new("The name.").schema()),
+            ("shout", true, &BooleanSchema::new("Shout.").schema()),
+        ],
+    )
+    .schema();
+}
+
+#[derive(Serialize)]
+struct Greeting {
+    text: String,
+}
+
+impl ApiType for Greeting {
+    const API_SCHEMA: Schema = ObjectSchema::new(
+        "A greeting.",
+        &[("text", false, &StringSchema::new("The greeting.").schema())],
+    )
+    .schema();
+}
+
+fn greet(text: &str, param: GreetParams) -> Greeting {
+    let text = text.repeat(param.count.unwrap_or(1) as usize) + &param.name;
+    Greeting {
+        text: match param.shout {
+            Some(true) => text.to_uppercase(),
+            _ => text,
+        },
+    }
+}
+
+fn greet_value(
+    param: Value,
+    _info: &ApiMethod,
+    _rpcenv: &mut dyn RpcEnvironment,
+) -> Result<Value, Error> {
+    let name = param["name"].as_str().unwrap();
+    Ok(json!({ "text": format!("hi {name}") }))
+}
+
+fn greet_typed(param: GreetParams, _rpcenv: &mut dyn RpcEnvironment) -> Result<Greeting, Error> {
+    Ok(greet("hello ", param))
+}
+
+async fn greet_async(
+    param: GreetParams,
+    rpcenv: &mut dyn RpcEnvironment,
+) -> Result<Greeting, Error> {
+    rpcenv["called"] = true.into();
+    Ok(greet("howdy ", param))
+}
+
+const API_METHOD_GREET_VALUE: ApiMethod = ApiMethod::new(
+    &ApiHandler::Sync(&greet_value),
+    GreetParams::API_SCHEMA.unwrap_object_schema(),
+);
+
+const API_METHOD_GREET_TYPED: ApiMethod = ApiMethod::new(
+    &typed_api_handler!(greet_typed),
+    GreetParams::API_SCHEMA.unwrap_object_schema(),
+);
+
+const API_METHOD_GREET_ASYNC: ApiMethod = ApiMethod::new(
+    &typed_api_handler!(async greet_async),
+    GreetParams::API_SCHEMA.unwrap_object_schema(),
+);
+
+const ROUTER: Router = Router::new()
+    .get(&API_METHOD_GREET_VALUE)
+    .put(&API_METHOD_GREET_TYPED)
+    .post(&API_METHOD_GREET_ASYNC);
+
+fn call(method: &'static ApiMethod, param: Value) -> Result<Value, Error> {
+    let mut rpcenv = TestEnvironment(json!({}));
+    let result = match method.handler {
+        ApiHandler::Sync(handler) => handler(param, method, &mut rpcenv),
+        ApiHandler::Async(handler) => {
+            futures::executor::block_on(handler(param, method, &mut rpcenv))
+        }
+        _ => panic!("unexpected handler type"),
+    };
+    if let (ApiHandler::Async(_), Ok(_)) = (method.handler, &result) {
+        assert_eq!(rpcenv.result_attrib()["called"], true);
+    }
+    result
+}
+
+#[test]
+fn test_typed_handlers() {
+    let get = ROUTER.get.unwrap();
+    let put = ROUTER.put.unwrap();
+    let post = ROUTER.post.unwrap();
+
+    let param = json!({ "name": "world", "count": 2 });
+    assert_eq!(
+        call(get, param.clone()).unwrap(),
+        json!({ "text": "hi world" })
+    );
+    assert_eq!(
+        call(put, param.clone()).unwrap(),
+        json!({ "text": "hello hello world" })
+    );
+    assert_eq!(
+        call(post, param).unwrap(),
+        json!({ "text": "howdy howdy world" })
+    );
+
+    // booleans given as strings are accepted
+    assert_eq!(
+        call(put, json!({ "name": "world", "shout": "yes" })).unwrap(),
+        json!({ "text": "HELLO WORLD" })
+    );
+    assert!(call(put, json!({ "name": "world", "shout": "maybe" })).is_err());
+
+    for method in [put, post] {
+        let err = call(method, json!({ "count": 0 })).unwrap_err();
+        let err = err
+            .downcast::<ParameterError>()
+            .expect("expected a parameter error");
+        let names: Vec<&str> = err.errors().iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["count", "name"]);
+    }
+}
@@ -0,0 +1,86 @@
+//! API types for RRD data endpoints, e.g. `/rrddata?timeframe=day&cf=AVERAGE`.
+//!
+//! The timeframes map to the archives of [`Cache::create_proxmox_backup_default_rrd`], see
+//! [`timeframe_parameters`].
+//!
+//! [`Cache::create_proxmox_backup_default_rrd`]: crate::Cache::create_proxmox_backup_default_rrd
+
+pub use proxmox_rrd_api_types::{RrdMode, RrdTimeframe};
+
+use crate::rrd::AggregationFn;
+
+impl From<RrdMode> for AggregationFn {
+    fn from(mode: RrdMode) -> Self {
+        match mode {
+            RrdMode::Average => AggregationFn::Average,
+            RrdMode::Max => AggregationFn::Maximum,
+        }
+    }
+}
+
+/// Resolution and number of data points of a timeframe.
+///
+/// The resolutions are the ones of the default archives, which hold at least the number of data
+/// points.
+fn timeframe_layout(timeframe: RrdTimeframe) -> (u64, usize) {
+    match timeframe {
+        RrdTimeframe::Hour => (60, 60),
+        RrdTimeframe::Day => (60, 1440),
+        RrdTimeframe::Week => (30 * 60, 7 * 48),
+        RrdTimeframe::Month => (30 * 60, 1440),
+        RrdTimeframe::Year => (6 * 3600, 1440),
+        RrdTimeframe::Decade => (7 * 86400, 522),
+    }
+}
+
+/// Get the start time, resolution and number of data points to extract for the `timeframe`
+/// ending at `end`, e.g. for [`Cache::extract_cached_data`].
+///
+/// [`Cache::extract_cached_data`]: crate::Cache::extract_cached_data
+pub fn timeframe_parameters(timeframe: RrdTimeframe, end: u64) -> (u64, u64, usize) {
+    let (resolution, points) = timeframe_layout(timeframe);
+    let start = end.saturating_sub(resolution * points as u64);
+    (start, resolution, points)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rrd::DataSourceType;
+
+    const TIMEFRAMES: [RrdTimeframe; 6] = [
+        RrdTimeframe::Hour,
+        RrdTimeframe::Day,
+        RrdTimeframe::Week,
+        RrdTimeframe::Month,
+        RrdTimeframe::Year,
+        RrdTimeframe::Decade,
+    ];
+
+    #[test]
+    fn test_timeframes_match_default_archives() {
+        #[allow(deprecated)]
+        let rrd = crate::Cache::create_proxmox_backup_default_rrd(DataSourceType::Gauge);
+        let end = 1_700_000_000;
+
+        for timeframe in TIMEFRAMES {
+            let (start, resolution, points) = timeframe_parameters(timeframe, end);
+            assert_eq!(end - start, resolution * points as u64, "{timeframe}");
+
+            for mode in [RrdMode::Average, RrdMode::Max] {
+                let cf = AggregationFn::from(mode);
+                let rra = rrd
+                    .rra_list
+                    .iter()
+                    .find(|rra| rra.cf == cf && rra.resolution == resolution);
+                let rra = rra.unwrap_or_else(|| panic!("no archive for {timeframe} ({mode})"));
+                assert!(rra.data.len() >= points, "{timeframe} exceeds the archive");
+
+                let entry = rrd
+                    .extract_data(cf, resolution, Some(start), Some(end))
+                    .unwrap();
+                assert_eq!(entry.resolution, resolution, "{timeframe} ({mode})");
+            }
+        }
+    }
+}
@@ -1,17 +1,18 @@
 use std::collections::BTreeSet;
-use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::os::unix::io::AsRawFd;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
 use std::sync::{Arc, RwLock};
 use std::thread::spawn;
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 
 use anyhow::{bail, format_err, Error};
 use crossbeam_channel::{bounded, TryRecvError};
 
 use proxmox_sys::fs::{create_path, CreateOptions};
 
+use crate::api_types::{timeframe_parameters, RrdMode, RrdTimeframe};
 use crate::rrd::{AggregationFn, DataSourceType, Database};
 use crate::Entry;
 
@@ -38,6 +39,58 @@ pub(crate) struct CacheConfig {
     dir_options: CreateOptions,
 }
 
+/// Summary of the journal replay at startup, see [`Cache::journal_replay_summary`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct JournalReplaySummary {
+    /// Number of journal entries applied.
+    pub applied: usize,
+    /// Number of corrupt journal lines skipped.
+    pub skipped: usize,
+    /// Time spent replaying the journal.
+    pub duration: Duration,
+}
+
+/// Number of journal writes and syncs since the cache got created, see [`Cache::journal_stats`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct JournalStats {
+    pub writes: u64,
+    pub syncs: u64,
+}
+
+/// The result of a single update of [`Cache::update_batch`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum BatchEntryResult {
+    /// The data point got applied.
+    Applied,
+    /// The data point got applied to a newly created RRD.
+    Created,
+    /// The data point is not newer than the last update of its RRD and got ignored.
+    OutOfOrder,
+    /// The data point got written to the journal, and gets applied with the journal.
+    Pending,
+    /// The update is invalid or its RRD could not be created.
+    Failed(String),
+}
+
+/// How the cache handles damaged RRD files when loading them, see [`Cache::corrupt_file_policy`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CorruptFilePolicy {
+    /// Fail loading the file.
+    Fail,
+    /// Rename the file aside and continue as if it did not exist, so a new RRD gets created.
+    Quarantine,
+    /// Load the undamaged RRAs only and replace the damaged ones by empty RRAs created with the
+    /// `create_rrd_cb` callback, their data is lost on the next write. Files which are damaged
+    /// as a whole are quarantined.
+    BestEffort,
+}
+
+/// The results of [`Cache::update_batch`], in the order of the updates.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BatchResult {
+    pub entries: Vec<BatchEntryResult>,
+}
+
 impl Cache {
     /// Creates a new instance
     ///
@@ -126,11 +179,43 @@ impl Cache {
         Database::new(dst, rra_list)
     }
 
+    /// Fail the journal replay on the first corrupt journal line.
+    ///
+    /// By default corrupt lines are logged and skipped, so the remaining entries are still
+    /// applied.
+    pub fn strict_journal_replay(self, strict: bool) -> Self {
+        self.state.write().unwrap().strict_replay = strict;
+        self
+    }
+
+    /// Verify RRD files before loading them and handle damaged files with `policy`.
+    ///
+    /// By default files are loaded without verification by the `load_rrd_cb` callback. Files
+    /// passing verification are still loaded by the callback.
+    pub fn corrupt_file_policy(self, policy: CorruptFilePolicy) -> Self {
+        self.rrd_map.write().unwrap().corrupt_file_policy = Some(policy);
+        self
+    }
+
+    /// The summary of the journal replay, available once the journal got applied.
+    pub fn journal_replay_summary(&self) -> Option<JournalReplaySummary> {
+        self.state.read().unwrap().replay_summary.clone()
+    }
+
     /// Sync the journal data to disk (using `fdatasync` syscall)
     pub fn sync_journal(&self) -> Result<(), Error> {
         self.state.read().unwrap().sync_journal()
     }
 
+    /// The number of journal writes and syncs.
+    pub fn journal_stats(&self) -> JournalStats {
+        let state = self.state.read().unwrap();
+        JournalStats {
+            writes: state.writes,
+            syncs: state.syncs.load(Ordering::Relaxed),
+        }
+    }
+
     /// Apply and commit the journal. Should be used at server startup.
     pub fn apply_journal(&self) -> Result<bool, Error> {
         let config = Arc::clone(&self.config);
@@ -235,6 +320,85 @@ impl Cache {
         Ok(())
     }
 
+    /// Update many data points at once: `(rel_path, time, value, dst)`
+    ///
+    /// The updates are written to the journal with a single write and sync. Like
+    /// `update_value_ignore_old`, data points not newer than the last update of their RRD are
+    /// ignored. A failing update does not fail the others, only journal errors are returned.
+    pub fn update_batch(
+        &self,
+        updates: &[(&str, f64, f64, DataSourceType)],
+    ) -> Result<BatchResult, Error> {
+        let journal_applied = self.apply_journal()?;
+
+        let mut results: Vec<Option<BatchEntryResult>> = updates
+            .iter()
+            .map(|&(rel_path, time, _, _)| {
+                check_batch_update(rel_path, time)
+                    .err()
+                    .map(|err| BatchEntryResult::Failed(err.to_string()))
+            })
+            .collect();
+
+        // group the updates by file, keeping their order within each file
+        let mut order: Vec<usize> = (0..updates.len())
+            .filter(|&i| results[i].is_none())
+            .collect();
+        order.sort_by_key(|&i| updates[i].0);
+
+        if !order.is_empty() {
+            let entries: Vec<JournalEntry> = order
+                .iter()
+                .map(|&i| {
+                    let (rel_path, time, value, dst) = updates[i];
+                    JournalEntry {
+                        time,
+                        value,
+                        dst,
+                        rel_path,
+                    }
+                })
+                .collect();
+            self.state.write().unwrap().append_journal_batch(&entries)?;
+        }
+
+        if journal_applied {
+            let mut rrd_map = self.rrd_map.write().unwrap();
+            for group in order.chunk_by(|&a, &b| updates[a].0 == updates[b].0) {
+                let (rel_path, _, _, dst) = updates[group[0]];
+                let (rrd, mut created) = match rrd_map.get_or_create(rel_path, dst) {
+                    Ok(rrd) => rrd,
+                    Err(err) => {
+                        for &i in group {
+                            results[i] = Some(BatchEntryResult::Failed(err.to_string()));
+                        }
+                        continue;
+                    }
+                };
+                for &i in group {
+                    let (_, time, value, _) = updates[i];
+                    results[i] = Some(if time > rrd.last_update() {
+                        rrd.update(time, value);
+                        if std::mem::take(&mut created) {
+                            BatchEntryResult::Created
+                        } else {
+                            BatchEntryResult::Applied
+                        }
+                    } else {
+                        BatchEntryResult::OutOfOrder
+                    });
+                }
+            }
+        }
+
+        Ok(BatchResult {
+            entries: results
+                .into_iter()
+                .map(|result| result.unwrap_or(BatchEntryResult::Pending))
+                .collect(),
+        })
+    }
+
     /// Extract data from cached RRD
     ///
     /// `start`: Start time. If not specified, we simply extract 10 data points.
@@ -268,6 +432,30 @@ impl Cache {
             }
         }
     }
+
+    /// Extract the data of the `timeframe` up to now from cached RRD, see
+    /// [`timeframe_parameters`].
+    pub fn extract_timeframe(
+        &self,
+        base: &str,
+        name: &str,
+        timeframe: RrdTimeframe,
+        mode: RrdMode,
+    ) -> Result<Option<Entry>, Error> {
+        let end = proxmox_time::epoch_f64() as u64;
+        let (start, resolution, _) = timeframe_parameters(timeframe, end);
+        self.extract_cached_data(base, name, mode.into(), resolution, Some(start), Some(end))
+    }
+}
+
+fn check_batch_update(rel_path: &str, time: f64) -> Result<(), Error> {
+    if rel_path.is_empty() || rel_path.contains('\n') {
+        bail!("invalid rrd path {:?}", rel_path);
+    }
+    if !time.is_finite() {
+        bail!("invalid time {}", time);
+    }
+    Ok(())
 }
 
 fn apply_and_commit_journal_thread(
@@ -279,16 +467,15 @@ fn apply_and_commit_journal_thread(
     if commit_only {
         state.write().unwrap().rotate_journal()?; // start new journal, keep old one
     } else {
-        let start_time = SystemTime::now();
         log::debug!("applying rrd journal");
 
         match apply_journal_impl(Arc::clone(&state), Arc::clone(&rrd_map)) {
-            Ok(entries) => {
-                let elapsed = start_time.elapsed().unwrap().as_secs_f64();
+            Ok(summary) => {
                 log::info!(
-                    "applied rrd journal ({} entries in {:.3} seconds)",
-                    entries,
-                    elapsed
+                    "applied rrd journal ({} entries, {} skipped, in {:.3} seconds)",
+                    summary.applied,
+                    summary.skipped,
+                    summary.duration.as_secs_f64(),
                 );
             }
             Err(err) => bail!("apply rrd journal failed - {}", err),
@@ -312,58 +499,193 @@ fn apply_and_commit_journal_thread(
     Ok(())
 }
 
-fn apply_journal_lines(
-    state: Arc<RwLock<JournalState>>,
-    rrd_map: Arc<RwLock<RRDMap>>,
-    journal_name: &str, // used for logging
-    reader: &mut BufReader<File>,
-    lock_read_line: bool,
-) -> Result<usize, Error> {
-    let mut linenr = 0;
+/// A journal file being replayed, the line buffer is reused for all lines.
+struct JournalReader<R> {
+    name: String, // used for logging
+    reader: R,
+    offset: u64,
+    line: Vec<u8>,
+    batch: Option<PendingBatch>,
+}
+
+/// A batch being read, its entries are applied once all of them got read.
+struct PendingBatch {
+    offset: u64,
+    remaining: usize,
+    entries: Vec<(String, f64, f64, DataSourceType)>,
+}
 
+impl<R: BufRead> JournalReader<R> {
+    fn new(name: impl Into<String>, reader: R) -> Self {
+        Self {
+            name: name.into(),
+            reader,
+            offset: 0,
+            line: Vec::new(),
+            batch: None,
+        }
+    }
+
+    /// Count a line of the current batch, and apply the batch once all lines got read.
+    fn batch_line_done(
+        &mut self,
+        rrd_map: &RwLock<RRDMap>,
+        summary: &mut JournalReplaySummary,
+    ) -> Result<(), Error> {
+        let Some(batch) = self.batch.as_mut() else {
+            return Ok(());
+        };
+        batch.remaining -= 1;
+        if batch.remaining > 0 {
+            return Ok(());
+        }
+
+        let batch = self.batch.take().unwrap();
+        let mut rrd_map = rrd_map.write().unwrap();
+        for (rel_path, time, value, dst) in batch.entries {
+            rrd_map.update(&rel_path, time, value, dst, true)?;
+            summary.applied += 1;
+        }
+        Ok(())
+    }
+
+    /// Skip a batch with missing entries, as left behind by a crash while writing it.
+    fn skip_incomplete_batch(
+        &mut self,
+        strict: bool,
+        summary: &mut JournalReplaySummary,
+    ) -> Result<(), Error> {
+        let Some(batch) = self.batch.take() else {
+            return Ok(());
+        };
+        if strict {
+            bail!(
+                "incomplete batch in rrd journal '{}' at offset {}",
+                self.name,
+                batch.offset,
+            );
+        }
+        log::warn!(
+            "incomplete batch in rrd journal '{}' at offset {} (skip)",
+            self.name,
+            batch.offset,
+        );
+        summary.skipped += batch.entries.len();
+        Ok(())
+    }
+}
+
+enum JournalRecord<'a> {
+    Entry(JournalEntry<'a>),
+    /// The start of a batch with the given number of entries.
+    Batch(usize),
+}
+
+fn parse_journal_line(line: &[u8]) -> Result<JournalRecord<'_>, Error> {
+    let line = line
+        .strip_suffix(b"\n")
+        .ok_or_else(|| format_err!("incomplete line"))?;
+    let line = std::str::from_utf8(line).map_err(|_| format_err!("invalid utf8"))?;
+    if let Some(count) = parse_batch_header(line) {
+        return Ok(JournalRecord::Batch(count?));
+    }
+    JournalEntry::parse(line).map(JournalRecord::Entry)
+}
+
+fn apply_journal_lines<R: BufRead>(
+    state: &RwLock<JournalState>,
+    rrd_map: &RwLock<RRDMap>,
+    journal: &mut JournalReader<R>,
+    lock_read_line: bool,
+    strict: bool,
+    summary: &mut JournalReplaySummary,
+) -> Result<(), Error> {
     loop {
-        linenr += 1;
-        let mut line = String::new();
+        journal.line.clear();
         let len = if lock_read_line {
             let _lock = state.read().unwrap(); // make sure we read entire lines
-            reader.read_line(&mut line)?
+            journal.reader.read_until(b'\n', &mut journal.line)?
         } else {
-            reader.read_line(&mut line)?
+            journal.reader.read_until(b'\n', &mut journal.line)?
         };
 
         if len == 0 {
             break;
         }
 
-        let entry: JournalEntry = match line.parse() {
-            Ok(entry) => entry,
+        let offset = journal.offset;
+        journal.offset += len as u64;
+
+        if offset == 0 && check_journal_header(&journal.line)? {
+            continue;
+        }
+
+        let record = match parse_journal_line(&journal.line) {
+            Ok(record) => record,
+            Err(err) if strict => bail!(
+                "unable to parse rrd journal '{}' at offset {} - {}",
+                journal.name,
+                offset,
+                err,
+            ),
             Err(err) => {
                 log::warn!(
-                    "unable to parse rrd journal '{}' line {} (skip) - {}",
-                    journal_name,
-                    linenr,
+                    "unable to parse rrd journal '{}' at offset {} (skip) - {}",
+                    journal.name,
+                    offset,
                     err,
                 );
+                summary.skipped += 1;
+                journal.batch_line_done(rrd_map, summary)?;
                 continue; // skip unparsable lines
             }
         };
 
-        rrd_map.write().unwrap().update(
-            &entry.rel_path,
-            entry.time,
-            entry.value,
-            entry.dst,
-            true,
-        )?;
+        match record {
+            JournalRecord::Batch(count) => {
+                journal.skip_incomplete_batch(strict, summary)?;
+                if count > 0 {
+                    journal.batch = Some(PendingBatch {
+                        offset,
+                        remaining: count,
+                        entries: Vec::with_capacity(count),
+                    });
+                }
+            }
+            JournalRecord::Entry(entry) => match journal.batch.as_mut() {
+                Some(batch) => {
+                    batch.entries.push((
+                        entry.rel_path.to_string(),
+                        entry.time,
+                        entry.value,
+                        entry.dst,
+                    ));
+                    journal.batch_line_done(rrd_map, summary)?;
+                }
+                None => {
+                    rrd_map.write().unwrap().update(
+                        entry.rel_path,
+                        entry.time,
+                        entry.value,
+                        entry.dst,
+                        true,
+                    )?;
+                    summary.applied += 1;
+                }
+            },
+        }
     }
-    Ok(linenr)
+    Ok(())
 }
 
 fn apply_journal_impl(
     state: Arc<RwLock<JournalState>>,
     rrd_map: Arc<RwLock<RRDMap>>,
-) -> Result<usize, Error> {
-    let mut lines = 0;
+) -> Result<JournalReplaySummary, Error> {
+    let start_time = SystemTime::now();
+    let mut summary = JournalReplaySummary::default();
+
+    let strict = state.read().unwrap().strict_replay;
 
     // Apply old journals first
     let journal_list = state.read().unwrap().list_old_journals()?;
@@ -371,45 +693,33 @@ fn apply_journal_impl(
     for entry in journal_list {
         log::info!("apply old journal log {}", entry.name);
         let file = std::fs::OpenOptions::new().read(true).open(&entry.path)?;
-        let mut reader = BufReader::new(file);
-        lines += apply_journal_lines(
-            Arc::clone(&state),
-            Arc::clone(&rrd_map),
-            &entry.name,
-            &mut reader,
-            false,
-        )?;
+        let mut journal = JournalReader::new(entry.name, BufReader::new(file));
+        apply_journal_lines(&state, &rrd_map, &mut journal, false, strict, &mut summary)?;
+        journal.skip_incomplete_batch(strict, &mut summary)?;
     }
 
-    let mut journal = state.read().unwrap().open_journal_reader()?;
+    let reader = state.read().unwrap().open_journal_reader()?;
+    let mut journal = JournalReader::new("rrd.journal", reader);
 
-    lines += apply_journal_lines(
-        Arc::clone(&state),
-        Arc::clone(&rrd_map),
-        "rrd.journal",
-        &mut journal,
-        true,
-    )?;
+    apply_journal_lines(&state, &rrd_map, &mut journal, true, strict, &mut summary)?;
 
     {
         let mut state_guard = state.write().unwrap(); // block other writers
 
-        lines += apply_journal_lines(
-            Arc::clone(&state),
-            Arc::clone(&rrd_map),
-            "rrd.journal",
-            &mut journal,
-            false,
-        )?;
+        apply_journal_lines(&state, &rrd_map, &mut journal, false, strict, &mut summary)?;
+        journal.skip_incomplete_batch(strict, &mut summary)?;
 
         state_guard.rotate_journal()?; // start new journal, keep old one
 
         // We need to apply the journal only once, because further updates
         // are always directly applied.
         state_guard.journal_applied = true;
+
+        summary.duration = start_time.elapsed().unwrap_or_default();
+        state_guard.replay_summary = Some(summary.clone());
     }
 
-    Ok(lines)
+    Ok(summary)
 }
 
 fn fsync_file_or_dir(path: &Path) -> Result<(), Error> {
@@ -490,3 +800,219 @@ fn commit_journal_impl(
 
     Ok(rrd_file_count)
 }
+
+#[cfg(test)]
+mod tests {
+    use nix::sys::stat::Mode;
+    use nix::unistd::{Gid, Uid};
+
+    use super::*;
+    use crate::rrd::Archive;
+
+    const HEADER: &str = "# proxmox-rrd journal version 2\n";
+
+    fn create_rrd(dst: DataSourceType) -> Database {
+        Database::new(dst, vec![Archive::new(AggregationFn::Average, 60, 10)])
+    }
+
+    fn make_basedir() -> Result<PathBuf, Error> {
+        let options = CreateOptions::new()
+            .owner(Uid::effective())
+            .group(Gid::effective())
+            .perm(Mode::from_bits_truncate(0o700));
+        proxmox_sys::fs::make_tmp_dir("/tmp", Some(options))
+    }
+
+    /// A cache with the journal in `basedir` applied, which does not commit on updates.
+    fn applied_cache(basedir: &Path) -> Result<Cache, Error> {
+        let cache = Cache::new(basedir, None, None, 3600.0, |_, _| None, create_rrd)?;
+        apply_journal_impl(Arc::clone(&cache.state), Arc::clone(&cache.rrd_map))?;
+        cache.state.write().unwrap().last_journal_flush = proxmox_time::epoch_f64();
+        Ok(cache)
+    }
+
+    fn replay_journal(journal: &[u8], strict: bool) -> Result<(Vec<String>, PathBuf), Error> {
+        let basedir = make_basedir()?;
+        std::fs::write(basedir.join("rrd.journal"), journal)?;
+
+        let cache = Cache::new(&basedir, None, None, 60.0, |_, _| None, create_rrd)?
+            .strict_journal_replay(strict);
+        let result = apply_journal_impl(Arc::clone(&cache.state), Arc::clone(&cache.rrd_map));
+        if let Err(err) = result {
+            std::fs::remove_dir_all(&basedir)?;
+            return Err(err);
+        }
+
+        let summary = cache.journal_replay_summary().unwrap();
+        let mut files = cache.rrd_map.read().unwrap().file_list();
+        files.sort();
+        files.push(format!("{}/{}", summary.applied, summary.skipped));
+        Ok((files, basedir))
+    }
+
+    #[test]
+    fn test_journal_replay_skips_corrupt_lines() -> Result<(), Error> {
+        let mut journal = HEADER.as_bytes().to_vec();
+        journal.extend_from_slice(b"1700000000:1:0:host/a\n");
+        journal.extend_from_slice(b"1700000060:\xff\xfe:0:host/x\n");
+        journal.extend_from_slice(b"garbage\n");
+        journal.extend_from_slice(b"1700000060:2:1:host/b\n");
+        journal.extend_from_slice(b"1700000120:3:0:host/c"); // incomplete
+
+        let (files, basedir) = replay_journal(&journal, false)?;
+        assert_eq!(files, ["host/a", "host/b", "2/3"]);
+
+        // the new journal starts with a version header
+        assert_eq!(
+            std::fs::read(basedir.join("rrd.journal"))?,
+            HEADER.as_bytes()
+        );
+        std::fs::remove_dir_all(&basedir)?;
+
+        let err = replay_journal(&journal, true).unwrap_err();
+        assert!(err.to_string().contains("at offset 54"), "{err}");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_journal_version_header() -> Result<(), Error> {
+        // journals without header are still supported
+        let (files, basedir) = replay_journal(b"1700000000:1:0:host/a\n", true)?;
+        assert_eq!(files, ["host/a", "1/0"]);
+        std::fs::remove_dir_all(&basedir)?;
+
+        let err = replay_journal(
+            b"# proxmox-rrd journal version 3\n1700000000:1:0:host/a\n",
+            false,
+        )
+        .unwrap_err();
+        assert!(
+            err.to_string().contains("unsupported journal version 3"),
+            "{err}"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_journal_replay_batches() -> Result<(), Error> {
+        let mut journal = HEADER.as_bytes().to_vec();
+        journal.extend_from_slice(b"#batch 3\n");
+        journal.extend_from_slice(b"1700000000:1:0:host/a\n");
+        journal.extend_from_slice(b"garbage\n");
+        journal.extend_from_slice(b"1700000000:2:0:host/b\n");
+        journal.extend_from_slice(b"1700000060:3:0:host/c\n");
+        journal.extend_from_slice(b"#batch 3\n"); // crashed while writing
+        journal.extend_from_slice(b"1700000120:4:0:host/d\n");
+        journal.extend_from_slice(b"1700000120:5:0:host/e");
+
+        let (files, basedir) = replay_journal(&journal, false)?;
+        assert_eq!(files, ["host/a", "host/b", "host/c", "3/3"]);
+        std::fs::remove_dir_all(&basedir)?;
+
+        let mut journal = HEADER.as_bytes().to_vec();
+        journal.extend_from_slice(b"#batch 2\n1700000000:1:0:host/a\n");
+        let err = replay_journal(&journal, true).unwrap_err();
+        assert!(
+            err.to_string().contains("incomplete batch") && err.to_string().contains("offset 32"),
+            "{err}"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_update_batch_journal_syncs() -> Result<(), Error> {
+        let basedir = make_basedir()?;
+        let cache = applied_cache(&basedir)?;
+
+        let count = 100;
+        for i in 0..count {
+            let rel_path = format!("host/{}", i % 10);
+            let time = 1700000000.0 + (i * 60) as f64;
+            cache.update_value(&rel_path, time, i as f64, DataSourceType::Gauge)?;
+            cache.sync_journal()?;
+        }
+        let single = cache.journal_stats();
+        assert_eq!(
+            single,
+            JournalStats {
+                writes: count,
+                syncs: count
+            }
+        );
+
+        let paths: Vec<String> = (0..count).map(|i| format!("host/{}", i % 10)).collect();
+        let updates: Vec<_> = paths
+            .iter()
+            .enumerate()
+            .map(|(i, rel_path)| {
+                let time = 1800000000.0 + (i * 60) as f64;
+                (rel_path.as_str(), time, i as f64, DataSourceType::Gauge)
+            })
+            .collect();
+        let result = cache.update_batch(&updates)?;
+        assert!(result
+            .entries
+            .iter()
+            .all(|entry| *entry == BatchEntryResult::Applied));
+
+        let batch = cache.journal_stats();
+        assert_eq!(batch.writes - single.writes, 1);
+        assert_eq!(batch.syncs - single.syncs, 1);
+
+        std::fs::remove_dir_all(&basedir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_update_batch_results() -> Result<(), Error> {
+        use BatchEntryResult::*;
+
+        let basedir = make_basedir()?;
+        let cache = applied_cache(&basedir)?;
+
+        let result = cache.update_batch(&[
+            ("host/a", 1700000060.0, 1.0, DataSourceType::Gauge),
+            ("host/b", 1700000000.0, 2.0, DataSourceType::Gauge),
+            ("host/a", 1700000120.0, 3.0, DataSourceType::Gauge),
+            ("host/a", 1700000000.0, 4.0, DataSourceType::Gauge),
+            ("host/\nx", 1700000000.0, 5.0, DataSourceType::Gauge),
+            ("host/c", f64::NAN, 6.0, DataSourceType::Gauge),
+        ])?;
+        assert_eq!(
+            result.entries,
+            [
+                Created,
+                Created,
+                Applied,
+                OutOfOrder,
+                Failed("invalid rrd path \"host/\\nx\"".to_string()),
+                Failed("invalid time NaN".to_string()),
+            ]
+        );
+
+        let result = cache.update_batch(&[("host/b", 1700000060.0, 7.0, DataSourceType::Gauge)])?;
+        assert_eq!(result.entries, [Applied]);
+        drop(cache);
+
+        // the batches are replayed from the journal, without the invalid updates
+        let cache = applied_cache(&basedir)?;
+        let summary = cache.journal_replay_summary().unwrap();
+        assert_eq!((summary.applied, summary.skipped), (5, 0));
+
+        let mut rrd_map = cache.rrd_map.write().unwrap();
+        let mut files = rrd_map.file_list();
+        files.sort();
+        assert_eq!(files, ["host/a", "host/b"]);
+        for (rel_path, last_update) in [("host/a", 1700000120.0), ("host/b", 1700000060.0)] {
+            let (rrd, _) = rrd_map.get_or_create(rel_path, DataSourceType::Gauge)?;
+            assert_eq!(rrd.last_update(), last_update);
+        }
+        drop(rrd_map);
+
+        std::fs::remove_dir_all(&basedir)?;
+        Ok(())
+    }
+}
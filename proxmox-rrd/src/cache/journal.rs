@@ -3,7 +3,7 @@ use std::fs::File;
 use std::io::{BufReader, Write};
 use std::os::unix::io::AsRawFd;
 use std::path::PathBuf;
-use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 
 use anyhow::{bail, format_err, Error};
@@ -14,7 +14,7 @@ use proxmox_sys::fs::atomic_open_or_create_file;
 
 const RRD_JOURNAL_NAME: &str = "rrd.journal";
 
-use crate::cache::CacheConfig;
+use crate::cache::{CacheConfig, JournalReplaySummary};
 use crate::rrd::DataSourceType;
 
 // shared state behind RwLock
@@ -24,19 +24,21 @@ pub struct JournalState {
     pub last_journal_flush: f64,
     pub journal_applied: bool,
     pub apply_thread_result: Option<Receiver<Result<(), String>>>,
+    pub strict_replay: bool,
+    pub replay_summary: Option<JournalReplaySummary>,
+    pub writes: u64,
+    pub syncs: AtomicU64,
 }
 
-pub struct JournalEntry {
+pub struct JournalEntry<'a> {
     pub time: f64,
     pub value: f64,
     pub dst: DataSourceType,
-    pub rel_path: String,
+    pub rel_path: &'a str,
 }
 
-impl FromStr for JournalEntry {
-    type Err = Error;
-
-    fn from_str(line: &str) -> Result<Self, Self::Err> {
+impl<'a> JournalEntry<'a> {
+    pub fn parse(line: &'a str) -> Result<Self, Error> {
         let line = line.trim();
 
         let parts: Vec<&str> = line.splitn(4, ':').collect();
@@ -60,17 +62,67 @@ impl FromStr for JournalEntry {
             _ => bail!("got strange value for data source type '{}'", dst),
         };
 
-        let rel_path = parts[3].to_string();
-
         Ok(JournalEntry {
             time,
             value,
             dst,
-            rel_path,
+            rel_path: parts[3],
         })
     }
 }
 
+/// The first line of a journal file, followed by the format version.
+///
+/// Journals written before the header was introduced (version 0) use the same entry format.
+const JOURNAL_HEADER_PREFIX: &str = "# proxmox-rrd journal version ";
+
+/// The journal format version written by this implementation.
+///
+/// Version 2 added batches, see [`BATCH_HEADER_PREFIX`].
+pub const JOURNAL_VERSION: u32 = 2;
+
+/// The line starting a batch of entries, followed by their number.
+///
+/// A batch is written at once, its entries are only applied if all of them are in the journal.
+const BATCH_HEADER_PREFIX: &str = "#batch ";
+
+/// Check if `line` starts a batch, returns the number of its entries.
+pub fn parse_batch_header(line: &str) -> Option<Result<usize, Error>> {
+    let count = line.strip_prefix(BATCH_HEADER_PREFIX)?;
+    Some(
+        count
+            .trim()
+            .parse()
+            .map_err(|_| format_err!("unable to parse batch size")),
+    )
+}
+
+fn journal_line(time: f64, value: f64, dst: DataSourceType, rel_path: &str) -> String {
+    format!("{}:{}:{}:{}\n", time, value, dst as u8, rel_path)
+}
+
+fn journal_header() -> String {
+    format!("{JOURNAL_HEADER_PREFIX}{JOURNAL_VERSION}\n")
+}
+
+/// Check if `line` is a journal header, fails if it announces an unsupported version.
+pub fn check_journal_header(line: &[u8]) -> Result<bool, Error> {
+    let version = match line.strip_prefix(JOURNAL_HEADER_PREFIX.as_bytes()) {
+        Some(version) => version,
+        None => return Ok(false),
+    };
+
+    let version: u32 = std::str::from_utf8(version)
+        .ok()
+        .and_then(|version| version.trim().parse().ok())
+        .ok_or_else(|| format_err!("unable to parse journal version"))?;
+    if version > JOURNAL_VERSION {
+        bail!("unsupported journal version {version}");
+    }
+
+    Ok(true)
+}
+
 pub struct JournalFileInfo {
     pub time: u64,
     pub name: String,
@@ -86,11 +138,16 @@ impl JournalState {
             last_journal_flush: 0.0,
             journal_applied: false,
             apply_thread_result: None,
+            strict_replay: false,
+            replay_summary: None,
+            writes: 0,
+            syncs: AtomicU64::new(0),
         })
     }
 
     pub fn sync_journal(&self) -> Result<(), Error> {
         nix::unistd::fdatasync(self.journal.as_raw_fd())?;
+        self.syncs.fetch_add(1, Ordering::Relaxed);
         Ok(())
     }
 
@@ -101,11 +158,28 @@ impl JournalState {
         dst: DataSourceType,
         rel_path: &str,
     ) -> Result<(), Error> {
-        let journal_entry = format!("{}:{}:{}:{}\n", time, value, dst as u8, rel_path);
+        let journal_entry = journal_line(time, value, dst, rel_path);
         self.journal.write_all(journal_entry.as_bytes())?;
+        self.writes += 1;
         Ok(())
     }
 
+    /// Append `entries` as a batch with a single write, and sync the journal.
+    pub fn append_journal_batch(&mut self, entries: &[JournalEntry]) -> Result<(), Error> {
+        let mut batch = format!("{BATCH_HEADER_PREFIX}{}\n", entries.len());
+        for entry in entries {
+            batch.push_str(&journal_line(
+                entry.time,
+                entry.value,
+                entry.dst,
+                entry.rel_path,
+            ));
+        }
+        self.journal.write_all(batch.as_bytes())?;
+        self.writes += 1;
+        self.sync_journal()
+    }
+
     pub fn open_journal_reader(&self) -> Result<BufReader<File>, Error> {
         // fixme : dup self.journal instead??
         let mut journal_path = self.config.basedir.clone();
@@ -115,7 +189,7 @@ impl JournalState {
         let journal = atomic_open_or_create_file(
             &journal_path,
             flags,
-            &[],
+            journal_header().as_bytes(),
             self.config.file_options.clone(),
             false,
         )?;
@@ -130,7 +204,7 @@ impl JournalState {
         let journal = atomic_open_or_create_file(
             &journal_path,
             flags,
-            &[],
+            journal_header().as_bytes(),
             config.file_options.clone(),
             false,
         )?;
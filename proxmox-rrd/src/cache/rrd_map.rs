@@ -1,21 +1,39 @@
 use std::collections::HashMap;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
-use anyhow::{bail, Error};
+use anyhow::{bail, format_err, Error};
 
 use proxmox_sys::fs::create_path;
 
 use crate::rrd::{AggregationFn, DataSourceType, Database};
+use crate::verify::verify_raw;
 
-use super::CacheConfig;
+use super::{CacheConfig, CorruptFilePolicy};
 use crate::Entry;
 
+/// A new name for a damaged file, not overwriting previously quarantined files.
+fn quarantine_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".corrupt-{}", proxmox_time::epoch_i64()));
+
+    let mut new_path = path.with_file_name(&name);
+    let mut count = 1;
+    while new_path.exists() {
+        let mut numbered = name.clone();
+        numbered.push(format!(".{count}"));
+        new_path = path.with_file_name(numbered);
+        count += 1;
+    }
+    new_path
+}
+
 pub struct RRDMap {
     config: Arc<CacheConfig>,
     map: HashMap<String, Database>,
     load_rrd_cb: fn(path: &Path, rel_path: &str) -> Option<Database>,
     create_rrd_cb: fn(dst: DataSourceType) -> Database,
+    pub(crate) corrupt_file_policy: Option<CorruptFilePolicy>,
 }
 
 impl RRDMap {
@@ -29,6 +47,59 @@ impl RRDMap {
             map: HashMap::new(),
             load_rrd_cb,
             create_rrd_cb,
+            corrupt_file_policy: None,
+        }
+    }
+
+    /// Load an RRD file, handling damaged files according to the corrupt file policy.
+    fn load_rrd(&self, path: &Path, rel_path: &str) -> Result<Option<Database>, Error> {
+        let Some(policy) = self.corrupt_file_policy else {
+            return Ok((self.load_rrd_cb)(path, rel_path));
+        };
+
+        let raw = match std::fs::read(path) {
+            Ok(raw) => raw,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                return Ok((self.load_rrd_cb)(path, rel_path));
+            }
+            Err(err) => bail!("unable to read rrd file {path:?} - {err}"),
+        };
+
+        let (rrd, report) = verify_raw(&raw, proxmox_time::epoch_f64());
+        if report.is_ok() {
+            if !report.warnings.is_empty() {
+                log::warn!("rrd file {path:?} - {report}");
+            }
+            return Ok((self.load_rrd_cb)(path, rel_path));
+        }
+
+        match (policy, rrd) {
+            (CorruptFilePolicy::Fail, _) => bail!("rrd file {path:?} is damaged - {report}"),
+            (CorruptFilePolicy::BestEffort, Some(mut rrd))
+                if !report.is_damaged() && !rrd.rra_list.is_empty() =>
+            {
+                log::warn!("recreating damaged RRAs of rrd file {path:?} - {report}");
+                // the damaged RRAs were dropped, add fresh ones for all RRAs missing now
+                let template = (self.create_rrd_cb)(rrd.source.dst);
+                for rra in template.rra_list {
+                    if !rrd
+                        .rra_list
+                        .iter()
+                        .any(|have| have.cf == rra.cf && have.resolution == rra.resolution)
+                    {
+                        rrd.rra_list.push(rra);
+                    }
+                }
+                Ok(Some(rrd))
+            }
+            _ => {
+                let new_path = quarantine_path(path);
+                std::fs::rename(path, &new_path).map_err(|err| {
+                    format_err!("unable to move damaged rrd file {path:?} aside - {err}")
+                })?;
+                log::warn!("moved damaged rrd file {path:?} to {new_path:?} - {report}");
+                Ok((self.load_rrd_cb)(path, rel_path))
+            }
         }
     }
 
@@ -40,14 +111,24 @@ impl RRDMap {
         dst: DataSourceType,
         new_only: bool,
     ) -> Result<(), Error> {
-        if let Some(rrd) = self.map.get_mut(rel_path) {
-            if !new_only || time > rrd.last_update() {
-                rrd.update(time, value);
-            }
-        } else {
+        let (rrd, _) = self.get_or_create(rel_path, dst)?;
+        if !new_only || time > rrd.last_update() {
+            rrd.update(time, value);
+        }
+        Ok(())
+    }
+
+    /// Get the RRD, loading or creating it if required. Also returns whether it was created.
+    pub fn get_or_create(
+        &mut self,
+        rel_path: &str,
+        dst: DataSourceType,
+    ) -> Result<(&mut Database, bool), Error> {
+        let mut created = false;
+        if !self.map.contains_key(rel_path) {
             let mut path = self.config.basedir.clone();
             path.push(rel_path);
-            let mut rrd = match (self.load_rrd_cb)(&path, rel_path) {
+            let rrd = match self.load_rrd(&path, rel_path)? {
                 None => {
                     create_path(
                         path.parent().unwrap(),
@@ -55,17 +136,14 @@ impl RRDMap {
                         Some(self.config.dir_options.clone()),
                     )?;
 
+                    created = true;
                     (self.create_rrd_cb)(dst)
                 }
                 Some(rrd) => rrd,
             };
-
-            if !new_only || time > rrd.last_update() {
-                rrd.update(time, value);
-            }
             self.map.insert(rel_path.to_string(), rrd);
         }
-        Ok(())
+        Ok((self.map.get_mut(rel_path).unwrap(), created))
     }
 
     pub fn file_list(&self) -> Vec<String> {
@@ -112,7 +190,7 @@ impl RRDMap {
         let mut path = self.config.basedir.clone();
         path.push(rel_path);
 
-        if let Some(rrd) = (self.load_rrd_cb)(&path, rel_path) {
+        if let Some(rrd) = self.load_rrd(&path, rel_path)? {
             self.map.insert(rel_path.to_string(), rrd);
             Ok(true)
         } else {
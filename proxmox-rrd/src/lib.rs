@@ -5,6 +5,7 @@
 //! * One file stores a single data source
 //! * Stores data for different time resolution
 //! * Simple cache implementation with journal support
+//! * Integrity checks to detect and repair damaged files
 
 #![cfg_attr(docsrs, feature(doc_cfg, doc_auto_cfg))]
 
@@ -15,5 +16,10 @@ pub mod rrd;
 #[doc(inline)]
 pub use rrd::Entry;
 
+pub mod api_types;
+
+pub mod verify;
+pub use verify::verify_directory;
+
 mod cache;
 pub use cache::*;
@@ -342,6 +342,14 @@ impl Archive {
     }
 }
 
+#[derive(Deserialize)]
+/// Round Robin Database with undecoded RRAs, so a damaged RRA does not prevent decoding the
+/// others.
+struct LenientDatabase {
+    source: DataSource,
+    rra_list: Vec<serde_cbor::Value>,
+}
+
 #[derive(Serialize, Deserialize)]
 /// Round Robin Database
 pub struct Database {
@@ -383,6 +391,37 @@ impl Database {
         Ok(rrd)
     }
 
+    /// Decode a file like [`Self::from_raw`], but keep decoding errors of single RRAs.
+    pub(crate) fn from_raw_lenient(
+        raw: &[u8],
+    ) -> Result<(DataSource, Vec<Result<Archive, Error>>), Error> {
+        if raw.len() < 8 {
+            bail!("not an rrd file - file is too small ({})", raw.len());
+        }
+
+        match &raw[0..8] {
+            #[cfg(feature = "rrd_v1")]
+            magic if magic == crate::rrd_v1::PROXMOX_RRD_MAGIC_1_0 => {
+                let rrd = Self::from_raw(raw)?;
+                Ok((rrd.source, rrd.rra_list.into_iter().map(Ok).collect()))
+            }
+            magic if magic == PROXMOX_RRD_MAGIC_2_0 => {
+                let rrd: LenientDatabase = serde_cbor::from_slice(&raw[8..])
+                    .map_err(|err| format_err!("unable to decode RRD file - {err}"))?;
+                let rra_list = rrd
+                    .rra_list
+                    .into_iter()
+                    .map(|value| {
+                        serde_cbor::value::from_value(value)
+                            .map_err(|err| format_err!("unable to decode RRA - {err}"))
+                    })
+                    .collect();
+                Ok((rrd.source, rra_list))
+            }
+            _ => bail!("not an rrd file - unknown magic number"),
+        }
+    }
+
     /// Load data from a file
     ///
     /// Setting `avoid_page_cache` uses
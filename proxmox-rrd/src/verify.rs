@@ -0,0 +1,315 @@
+//! Integrity checks for RRD files
+//!
+//! Files may get damaged by power loss, e.g. truncated or with zero-filled regions. The checks
+//! here find such damage, so it can be repaired instead of returning garbage data.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, format_err, Error};
+
+use crate::rrd::{AggregationFn, Archive, DataSource, DataSourceType, Database};
+
+/// Tolerated difference in seconds between a file's last update time and the current time.
+const MAX_CLOCK_SKEW: f64 = 86400.0;
+
+/// A problem found while verifying an RRD file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VerifyIssue {
+    /// The index of the affected RRA, `None` if the problem affects the whole file.
+    pub rra: Option<usize>,
+    /// Description of the problem.
+    pub message: String,
+}
+
+impl VerifyIssue {
+    fn file(message: impl Into<String>) -> Self {
+        Self {
+            rra: None,
+            message: message.into(),
+        }
+    }
+
+    fn rra(index: usize, message: impl Into<String>) -> Self {
+        Self {
+            rra: Some(index),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for VerifyIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.rra {
+            Some(index) => write!(f, "RRA {index}: {}", self.message),
+            None => f.write_str(&self.message),
+        }
+    }
+}
+
+/// The result of verifying an RRD file, see [`Database::verify`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct VerifyReport {
+    /// The number of RRAs stored in the file, 0 if it could not be decoded.
+    pub rra_count: usize,
+    /// The problems found.
+    pub issues: Vec<VerifyIssue>,
+    /// Suspicious findings which do not indicate damage, like a clock that was set wrong.
+    pub warnings: Vec<VerifyIssue>,
+}
+
+impl VerifyReport {
+    /// Whether no problems were found.
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    /// Whether the file as a whole is damaged, so that no RRA can be trusted.
+    pub fn is_damaged(&self) -> bool {
+        self.issues.iter().any(|issue| issue.rra.is_none())
+    }
+
+    /// The indices of the damaged RRAs.
+    pub fn damaged_rras(&self) -> Vec<usize> {
+        let mut list: Vec<usize> = self.issues.iter().filter_map(|issue| issue.rra).collect();
+        list.dedup();
+        list
+    }
+}
+
+impl fmt::Display for VerifyReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.issues.is_empty() {
+            f.write_str("ok")?;
+        }
+        for (i, issue) in self.issues.iter().enumerate() {
+            if i > 0 {
+                f.write_str("; ")?;
+            }
+            write!(f, "{issue}")?;
+        }
+        for warning in &self.warnings {
+            write!(f, "; warning: {warning}")?;
+        }
+        Ok(())
+    }
+}
+
+fn check_source(source: &DataSource, now: f64, report: &mut VerifyReport) {
+    let last_update = source.last_update;
+    if !last_update.is_finite() || last_update < 0.0 {
+        report.issues.push(VerifyIssue::file(format!(
+            "invalid last update time {last_update}"
+        )));
+    } else if last_update > now + MAX_CLOCK_SKEW {
+        // more likely a clock which was (or is) set wrong than damage
+        report.warnings.push(VerifyIssue::file(format!(
+            "last update time {last_update} is in the future"
+        )));
+    }
+
+    if source.dst != DataSourceType::Gauge && source.last_value.is_infinite() {
+        report
+            .issues
+            .push(VerifyIssue::file("invalid last counter value"));
+    }
+}
+
+fn check_archive(index: usize, rra: &Archive, last_update: f64, issues: &mut Vec<VerifyIssue>) {
+    if rra.resolution == 0 {
+        issues.push(VerifyIssue::rra(index, "resolution is zero"));
+        return;
+    }
+    if rra.data.is_empty() {
+        issues.push(VerifyIssue::rra(index, "no data slots"));
+        return;
+    }
+    if rra.resolution.checked_mul(rra.data.len() as u64).is_none() {
+        issues.push(VerifyIssue::rra(
+            index,
+            format!(
+                "{} slots with resolution {} exceed the time range",
+                rra.data.len(),
+                rra.resolution
+            ),
+        ));
+        return;
+    }
+
+    // slots are NaN unless they got a value, and computed values are always finite
+    if rra.data.iter().any(|value| value.is_infinite()) {
+        issues.push(VerifyIssue::rra(index, "contains infinite values"));
+    }
+    if last_update <= 0.0 {
+        if rra.data.iter().any(|value| !value.is_nan()) {
+            issues.push(VerifyIssue::rra(index, "contains data without any update"));
+        }
+    } else if rra.last_count > 0 && rra.data[rra.slot(last_update as u64)].is_nan() {
+        issues.push(VerifyIssue::rra(
+            index,
+            format!(
+                "slot of the last update is empty, but counted {} values",
+                rra.last_count
+            ),
+        ));
+    }
+}
+
+/// Verify a decoded database, the RRAs are `(index, archive)` pairs.
+fn verify_decoded(
+    source: &DataSource,
+    rra_list: &[(usize, &Archive)],
+    now: f64,
+    report: &mut VerifyReport,
+) {
+    check_source(source, now, report);
+    let issues = &mut report.issues;
+
+    let mut seen: Vec<(usize, AggregationFn, u64)> = Vec::new();
+    for &(index, rra) in rra_list {
+        check_archive(index, rra, source.last_update, issues);
+
+        if let Some((other, _, _)) = seen
+            .iter()
+            .find(|(_, cf, resolution)| *cf == rra.cf && *resolution == rra.resolution)
+        {
+            issues.push(VerifyIssue::rra(index, format!("duplicate of RRA {other}")));
+        } else {
+            seen.push((index, rra.cf, rra.resolution));
+        }
+    }
+
+    issues.sort_by_key(|issue| issue.rra);
+}
+
+/// Verify the raw content of a file.
+///
+/// Also returns the database with the undamaged RRAs if the file could be decoded.
+pub(crate) fn verify_raw(raw: &[u8], now: f64) -> (Option<Database>, VerifyReport) {
+    let (source, decoded) = match Database::from_raw_lenient(raw) {
+        Ok(result) => result,
+        Err(err) => {
+            let report = VerifyReport {
+                issues: vec![VerifyIssue::file(err.to_string())],
+                ..Default::default()
+            };
+            return (None, report);
+        }
+    };
+
+    let mut report = VerifyReport {
+        rra_count: decoded.len(),
+        ..Default::default()
+    };
+    let mut rra_list = Vec::new();
+    for (index, rra) in decoded.iter().enumerate() {
+        match rra {
+            Ok(rra) => rra_list.push((index, rra)),
+            Err(err) => report.issues.push(VerifyIssue::rra(index, err.to_string())),
+        }
+    }
+    if decoded.is_empty() {
+        report.issues.push(VerifyIssue::file("no RRAs"));
+    }
+    verify_decoded(&source, &rra_list, now, &mut report);
+    report.issues.sort_by_key(|issue| issue.rra);
+
+    let damaged = report.damaged_rras();
+    let rra_list = decoded
+        .into_iter()
+        .enumerate()
+        .filter(|(index, _)| !damaged.contains(index))
+        .filter_map(|(_, rra)| rra.ok())
+        .collect();
+
+    (Some(Database { source, rra_list }), report)
+}
+
+impl Database {
+    /// Verify the consistency of the data source and the RRAs.
+    ///
+    /// This checks for sane update times and counter values, the resolution and number of
+    /// slots of each RRA, duplicate RRAs and data slots which cannot result from updates, like
+    /// infinite values.
+    pub fn verify(&self) -> VerifyReport {
+        let rra_list: Vec<(usize, &Archive)> = self.rra_list.iter().enumerate().collect();
+        let mut report = VerifyReport {
+            rra_count: self.rra_list.len(),
+            ..Default::default()
+        };
+        verify_decoded(
+            &self.source,
+            &rra_list,
+            proxmox_time::epoch_f64(),
+            &mut report,
+        );
+        report
+    }
+
+    /// Verify a file, see [`Self::verify`].
+    ///
+    /// Unlike [`Self::load`], this also reports problems with the magic number and undecodable
+    /// RRAs instead of failing. Only errors reading the file are returned as error.
+    pub fn verify_file(path: &Path) -> Result<VerifyReport, Error> {
+        let raw =
+            std::fs::read(path).map_err(|err| format_err!("unable to read {path:?} - {err}"))?;
+        Ok(verify_raw(&raw, proxmox_time::epoch_f64()).1)
+    }
+
+    /// Load a file, dropping damaged RRAs.
+    ///
+    /// Fails if the file as a whole is damaged or no RRA is left. Returns the loaded database
+    /// and the report listing the dropped RRAs.
+    pub fn load_valid_rras(path: &Path) -> Result<(Database, VerifyReport), Error> {
+        let raw =
+            std::fs::read(path).map_err(|err| format_err!("unable to read {path:?} - {err}"))?;
+        match verify_raw(&raw, proxmox_time::epoch_f64()) {
+            (Some(rrd), report) if !report.is_damaged() && !rrd.rra_list.is_empty() => {
+                Ok((rrd, report))
+            }
+            (_, report) => bail!("rrd file {path:?} is damaged - {report}"),
+        }
+    }
+}
+
+/// Whether a file in an RRD directory is not an RRD file.
+fn is_auxiliary_file(name: &str) -> bool {
+    // journal, files being written and quarantined files
+    name.starts_with("rrd.journal") || name.contains(".tmp_") || name.contains(".corrupt-")
+}
+
+/// Verify all RRD files below `dir`, e.g. for periodic maintenance jobs.
+///
+/// Journal files, temporary files and files put aside by [`CorruptFilePolicy::Quarantine`] are
+/// skipped. Returns the reports of all files, sorted by path.
+///
+/// [`CorruptFilePolicy::Quarantine`]: crate::CorruptFilePolicy::Quarantine
+pub fn verify_directory(dir: &Path) -> Result<Vec<(PathBuf, VerifyReport)>, Error> {
+    let mut reports = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+
+    while let Some(dir) = pending.pop() {
+        let entries =
+            std::fs::read_dir(&dir).map_err(|err| format_err!("unable to read {dir:?} - {err}"))?;
+        for entry in entries {
+            let entry = entry.map_err(|err| format_err!("unable to read {dir:?} - {err}"))?;
+            let path = entry.path();
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                pending.push(path);
+                continue;
+            }
+            if !file_type.is_file() || is_auxiliary_file(&entry.file_name().to_string_lossy()) {
+                continue;
+            }
+            let report = Database::verify_file(&path).unwrap_or_else(|err| VerifyReport {
+                issues: vec![VerifyIssue::file(err.to_string())],
+                ..Default::default()
+            });
+            reports.push((path, report));
+        }
+    }
+
+    reports.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(reports)
+}
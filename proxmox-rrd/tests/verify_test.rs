@@ -0,0 +1,227 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Error;
+use serde_cbor::Value;
+
+use proxmox_rrd::rrd::{AggregationFn, Archive, DataSourceType, Database, PROXMOX_RRD_MAGIC_2_0};
+use proxmox_rrd::{verify_directory, Cache, CorruptFilePolicy};
+use proxmox_sys::fs::CreateOptions;
+
+const RRD_V2_FN: &str = "./tests/testdata/cpu.rrd_v2";
+
+const START: f64 = 1_700_000_000.0;
+
+fn create_rrd(dst: DataSourceType) -> Database {
+    Database::new(
+        dst,
+        vec![
+            Archive::new(AggregationFn::Average, 60, 10),
+            Archive::new(AggregationFn::Maximum, 60, 10),
+        ],
+    )
+}
+
+fn load_rrd(path: &Path, _rel_path: &str) -> Option<Database> {
+    Database::load(path, false).ok()
+}
+
+fn test_rrd() -> Database {
+    let mut rrd = create_rrd(DataSourceType::Gauge);
+    for i in 1..5 {
+        rrd.update(START + (i as f64) * 60.0, i as f64);
+    }
+    rrd
+}
+
+fn make_basedir() -> Result<PathBuf, Error> {
+    let basedir = proxmox_sys::fs::make_tmp_dir("/tmp", None)?;
+    std::fs::create_dir(basedir.join("host"))?;
+    Ok(basedir)
+}
+
+fn write_truncated(path: &Path) -> Result<(), Error> {
+    let raw = std::fs::read(RRD_V2_FN)?;
+    std::fs::write(path, &raw[..raw.len() / 2])?;
+    Ok(())
+}
+
+/// Write `rrd` with an invalid consolidation function in its second RRA.
+fn write_bad_cf(path: &Path, rrd: &Database) -> Result<(), Error> {
+    let mut value = serde_cbor::value::to_value(rrd)?;
+    if let Value::Map(map) = &mut value {
+        if let Some(Value::Array(rra_list)) = map.get_mut(&Value::Text("rra_list".into())) {
+            if let Value::Map(rra) = &mut rra_list[1] {
+                rra.insert(Value::Text("cf".into()), Value::Text("bogus".into()));
+            }
+        }
+    }
+    let mut raw = PROXMOX_RRD_MAGIC_2_0.to_vec();
+    raw.extend(serde_cbor::to_vec(&value)?);
+    std::fs::write(path, raw)?;
+    Ok(())
+}
+
+fn quarantined_files(dir: &Path, name: &str) -> Result<usize, Error> {
+    let prefix = format!("{name}.corrupt-");
+    let mut count = 0;
+    for entry in std::fs::read_dir(dir)? {
+        if entry?.file_name().to_string_lossy().starts_with(&prefix) {
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+fn extract(cache: &Cache, cf: AggregationFn) -> Result<Option<Vec<Option<f64>>>, Error> {
+    let entry = cache.extract_cached_data(
+        "host",
+        "rrd",
+        cf,
+        60,
+        Some(START as u64),
+        Some(START as u64 + 300),
+    )?;
+    Ok(entry.map(|entry| entry.data))
+}
+
+#[test]
+fn verify_files() -> Result<(), Error> {
+    let basedir = make_basedir()?;
+    let path = basedir.join("rrd");
+
+    let report = Database::verify_file(Path::new(RRD_V2_FN))?;
+    assert!(report.is_ok(), "{report}");
+    assert_eq!(report.rra_count, 8);
+
+    test_rrd().save(&path, CreateOptions::new(), false)?;
+    assert!(Database::verify_file(&path)?.is_ok());
+
+    write_truncated(&path)?;
+    assert!(Database::verify_file(&path)?.is_damaged());
+
+    // zero-filled regions, at the start and at the end of the file
+    let mut raw = std::fs::read(RRD_V2_FN)?;
+    raw[..4096].fill(0);
+    std::fs::write(&path, &raw)?;
+    let report = Database::verify_file(&path)?;
+    assert!(report.is_damaged());
+    assert!(
+        report.to_string().contains("unknown magic number"),
+        "{report}"
+    );
+
+    let mut raw = std::fs::read(RRD_V2_FN)?;
+    let len = raw.len();
+    raw[len / 2..].fill(0);
+    std::fs::write(&path, &raw)?;
+    assert!(Database::verify_file(&path)?.is_damaged());
+
+    write_bad_cf(&path, &test_rrd())?;
+    let report = Database::verify_file(&path)?;
+    assert!(!report.is_damaged(), "{report}");
+    assert_eq!(report.damaged_rras(), [1]);
+
+    let mut rrd = test_rrd();
+    rrd.rra_list[0].data[3] = f64::INFINITY;
+    let report = rrd.verify();
+    assert_eq!(report.damaged_rras(), [0]);
+    assert!(report.to_string().contains("infinite"), "{report}");
+
+    let mut rrd = test_rrd();
+    rrd.rra_list[1].resolution = 0;
+    assert_eq!(rrd.verify().damaged_rras(), [1]);
+
+    let mut rrd = test_rrd();
+    rrd.rra_list
+        .push(Archive::new(AggregationFn::Average, 60, 10));
+    assert_eq!(rrd.verify().damaged_rras(), [2]);
+
+    // a clock set wrong is no damage
+    let mut rrd = test_rrd();
+    rrd.source.last_update = proxmox_time::epoch_f64() + 10.0 * 86400.0;
+    let report = rrd.verify();
+    assert!(report.is_ok(), "{report}");
+    assert_eq!(report.warnings.len(), 1);
+    assert!(report.to_string().contains("in the future"), "{report}");
+
+    std::fs::remove_dir_all(&basedir)?;
+    Ok(())
+}
+
+#[test]
+fn corrupt_file_policies() -> Result<(), Error> {
+    let basedir = make_basedir()?;
+    let dir = basedir.join("host");
+    let path = dir.join("rrd");
+
+    let new_cache = |policy: Option<CorruptFilePolicy>| -> Result<Cache, Error> {
+        let cache = Cache::new(&basedir, None, None, 3600.0, load_rrd, create_rrd)?;
+        Ok(match policy {
+            Some(policy) => cache.corrupt_file_policy(policy),
+            None => cache,
+        })
+    };
+
+    // without policy, the load callback decides
+    write_truncated(&path)?;
+    assert_eq!(extract(&new_cache(None)?, AggregationFn::Average)?, None);
+    assert!(path.exists());
+
+    let err = extract(
+        &new_cache(Some(CorruptFilePolicy::Fail))?,
+        AggregationFn::Average,
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("is damaged"), "{err}");
+    assert!(path.exists());
+
+    // undamaged files are loaded by every policy
+    test_rrd().save(&path, CreateOptions::new(), false)?;
+    let cache = new_cache(Some(CorruptFilePolicy::Fail))?;
+    assert!(extract(&cache, AggregationFn::Maximum)?.is_some());
+
+    write_truncated(&path)?;
+    let cache = new_cache(Some(CorruptFilePolicy::Quarantine))?;
+    assert_eq!(extract(&cache, AggregationFn::Average)?, None);
+    assert!(!path.exists());
+    assert_eq!(quarantined_files(&dir, "rrd")?, 1);
+
+    write_bad_cf(&path, &test_rrd())?;
+    let cache = new_cache(Some(CorruptFilePolicy::BestEffort))?;
+    let data = extract(&cache, AggregationFn::Average)?.unwrap();
+    assert_eq!(data[1..5], [Some(1.0), Some(2.0), Some(3.0), Some(4.0)]);
+    // the damaged RRA is recreated empty from the template
+    let data = extract(&cache, AggregationFn::Maximum)?.unwrap();
+    assert!(data.iter().all(Option::is_none), "{data:?}");
+    assert!(path.exists());
+
+    // nothing to salvage from a file damaged as a whole
+    write_truncated(&path)?;
+    let cache = new_cache(Some(CorruptFilePolicy::BestEffort))?;
+    assert_eq!(extract(&cache, AggregationFn::Average)?, None);
+    assert!(!path.exists());
+    assert_eq!(quarantined_files(&dir, "rrd")?, 2);
+
+    std::fs::remove_dir_all(&basedir)?;
+    Ok(())
+}
+
+#[test]
+fn verify_directories() -> Result<(), Error> {
+    let basedir = make_basedir()?;
+
+    test_rrd().save(&basedir.join("host/good"), CreateOptions::new(), false)?;
+    write_truncated(&basedir.join("host/bad"))?;
+    write_truncated(&basedir.join("host/old.corrupt-1700000000"))?;
+    std::fs::write(basedir.join("rrd.journal"), b"")?;
+
+    let reports = verify_directory(&basedir)?;
+    assert_eq!(reports.len(), 2);
+    assert_eq!(reports[0].0, basedir.join("host/bad"));
+    assert!(reports[0].1.is_damaged());
+    assert_eq!(reports[1].0, basedir.join("host/good"));
+    assert!(reports[1].1.is_ok());
+
+    std::fs::remove_dir_all(&basedir)?;
+    Ok(())
+}
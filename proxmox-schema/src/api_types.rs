@@ -1,4 +1,5 @@
 //! The "basic" api types we generally require along with some of their macros.
+use anyhow::{bail, Error};
 use const_format::concatcp;
 
 use crate::{ApiStringFormat, ArraySchema, Schema, StringSchema};
@@ -115,6 +116,14 @@ const_regex! {
     /// Regex to match systemd date/time format.
     pub SYSTEMD_DATETIME_REGEX = r"^\d{4}-\d{2}-\d{2}( \d{2}:\d{2}(:\d{2})?)?$";
 
+    /// Regex to match RFC3339 timestamps, with optional fractional seconds and a `Z` or numeric
+    /// offset as time zone.
+    pub TIME_RFC3339_REGEX = concatcp!(
+        r"^\d{4}-(?:0[1-9]|1[0-2])-(?:0[1-9]|[12]\d|3[01])",
+        r"T(?:[01]\d|2[0-3]):[0-5]\d:(?:[0-5]\d|60)(?:\.\d+)?",
+        r"(?:Z|[+-](?:[01]\d|2[0-3]):[0-5]\d)$"
+    );
+
     /// Regex that (loosely) matches URIs according to [RFC 2396](https://www.rfc-editor.org/rfc/rfc2396.txt)
     /// This does not completely match a URI, but rather disallows all the prohibited characters
     /// specified in the RFC.
@@ -146,6 +155,13 @@ pub const BLOCKDEVICE_DISK_AND_PARTITION_NAME_FORMAT: ApiStringFormat =
 pub const SYSTEMD_DATETIME_FORMAT: ApiStringFormat =
     ApiStringFormat::Pattern(&SYSTEMD_DATETIME_REGEX);
 
+/// RFC3339 timestamp, use [`parse_time_rfc3339`] to get the epoch.
+pub const TIME_RFC3339_FORMAT: ApiStringFormat = ApiStringFormat::Pattern(&TIME_RFC3339_REGEX);
+
+/// Calendar event in systemd.time style, see [`proxmox_time::CalendarEvent`].
+pub const CALENDAR_EVENT_FORMAT: ApiStringFormat =
+    ApiStringFormat::VerifyFn(proxmox_time::verify_calendar_event);
+
 pub const HOSTNAME_FORMAT: ApiStringFormat = ApiStringFormat::Pattern(&HOSTNAME_REGEX);
 pub const HOST_PORT_FORMAT: ApiStringFormat = ApiStringFormat::Pattern(&HOST_PORT_REGEX);
 pub const HTTP_URL_FORMAT: ApiStringFormat = ApiStringFormat::Pattern(&HTTP_URL_REGEX);
@@ -225,6 +241,58 @@ pub const HTTP_URL_SCHEMA: Schema = StringSchema::new("HTTP(s) url with optional
     .format(&HTTP_URL_FORMAT)
     .schema();
 
+pub const CALENDAR_EVENT_SCHEMA: Schema = StringSchema::new(
+    "Calendar event in systemd.time style, e.g. 'mon..fri 02:30', '*/15' or 'daily'.",
+)
+.format(&CALENDAR_EVENT_FORMAT)
+.max_length(256)
+.schema();
+
+pub const TIME_RFC3339_SCHEMA: Schema = StringSchema::new(
+    "RFC3339 timestamp, e.g. '2024-05-01T12:30:00Z' or '2024-05-01T14:30:00.25+02:00'.",
+)
+.format(&TIME_RFC3339_FORMAT)
+.max_length(64)
+.schema();
+
+/// Parse a timestamp matching [`TIME_RFC3339_FORMAT`] into an epoch, fractional seconds are
+/// dropped.
+pub fn parse_time_rfc3339(value: &str) -> Result<i64, Error> {
+    if !TIME_RFC3339_REGEX.is_match(value) {
+        bail!("invalid RFC3339 timestamp {value:?}");
+    }
+
+    // proxmox-time does not support fractional seconds
+    let epoch = match value.split_once('.') {
+        Some((start, rest)) => {
+            let offset = rest.trim_start_matches(|c: char| c.is_ascii_digit());
+            proxmox_time::parse_rfc3339(&format!("{start}{offset}"))?
+        }
+        None => proxmox_time::parse_rfc3339(value)?,
+    };
+
+    // impossible dates like February 31st are normalized instead of rejected, so check that the
+    // epoch falls on the given date in the given offset (a leap second belongs to the day before)
+    let offset = match value.strip_suffix('Z') {
+        Some(_) => 0,
+        None => {
+            let offset = &value[value.len() - 6..];
+            let seconds = offset[1..3].parse::<i64>()? * 3600 + offset[4..6].parse::<i64>()? * 60;
+            match offset.starts_with('-') {
+                true => -seconds,
+                false => seconds,
+            }
+        }
+    };
+    let leap_second = i64::from(&value[17..19] == "60");
+    let date = proxmox_time::epoch_to_rfc3339_utc(epoch + offset - leap_second)?;
+    if date[..10] != value[..10] {
+        bail!("invalid RFC3339 timestamp {value:?} - no such date");
+    }
+
+    Ok(epoch)
+}
+
 pub const NODE_SCHEMA: Schema = StringSchema::new("Node name (or 'localhost')")
     .format(&HOSTNAME_FORMAT)
     .schema();
@@ -285,3 +353,75 @@ fn test_regexes() {
     assert!(IP_BRACKET_REGEX.is_match("[2014:b3a::192.168.0.1]"));
     assert!(IP_BRACKET_REGEX.is_match("[2014:b3a:0102:adf1:1234:4321:4afA:BCDF]"));
 }
+
+#[test]
+fn test_calendar_event_schema() {
+    for value in ["mon..fri 02:30", "*/15", "daily", "sat,sun 0/6:00 UTC"] {
+        CALENDAR_EVENT_SCHEMA
+            .parse_simple_value(value)
+            .unwrap_or_else(|err| panic!("'{value}' should be valid - {err}"));
+    }
+    for value in ["", "never", "mon 25:00:00", "mon..fri 02:30 foo"] {
+        assert!(
+            CALENDAR_EVENT_SCHEMA.parse_simple_value(value).is_err(),
+            "'{value}' should be invalid",
+        );
+    }
+}
+
+#[test]
+fn test_time_rfc3339_schema() {
+    for (value, epoch) in [
+        ("2024-05-01T12:30:00Z", 1714566600),
+        ("2024-05-01T12:30:00.5Z", 1714566600),
+        ("2024-05-01T14:30:00+02:00", 1714566600),
+        ("2024-05-01T10:00:00.123456-02:30", 1714566600),
+        ("1970-01-01T00:00:00Z", 0),
+    ] {
+        TIME_RFC3339_SCHEMA
+            .parse_simple_value(value)
+            .unwrap_or_else(|err| panic!("'{value}' should be valid - {err}"));
+        assert_eq!(parse_time_rfc3339(value).unwrap(), epoch, "{value}");
+    }
+
+    for value in [
+        "2024-13-01T00:00:00Z",
+        "2024-05-01T12:30:00",
+        "2024-05-01T12:30:00.5",
+        "2024-05-01T12:30:00+0200",
+        "2024-05-01 12:30:00Z",
+        "2024-05-01T24:00:00Z",
+        "2024-05-01T12:30:00.Z",
+        "2024-05-01",
+        "2024-13-01",
+    ] {
+        assert!(
+            TIME_RFC3339_SCHEMA.parse_simple_value(value).is_err(),
+            "'{value}' should be invalid",
+        );
+        assert!(
+            parse_time_rfc3339(value).is_err(),
+            "'{value}' should be invalid"
+        );
+    }
+
+    // the format only checks the ranges of the fields, not the days of a month
+    for value in [
+        "2024-02-31T00:00:00Z",
+        "2023-02-29T12:00:00+02:00",
+        "2024-04-31T23:30:00.5-01:00",
+    ] {
+        assert!(
+            parse_time_rfc3339(value).is_err(),
+            "'{value}' should be invalid"
+        );
+    }
+    assert_eq!(
+        parse_time_rfc3339("2024-02-29T00:30:00+01:00").unwrap(),
+        1709163000
+    );
+    assert_eq!(
+        parse_time_rfc3339("2016-12-31T23:59:60Z").unwrap(),
+        1483228800
+    );
+}
@@ -6,16 +6,19 @@ use std::fmt;
 use std::ops::Range;
 
 use serde::de::{self, IntoDeserializer};
+use serde_json::Value;
 
 use crate::schema::{self, ArraySchema, Schema};
 
 mod cow3;
 mod extract;
 mod no_schema;
+mod unknown_fields;
 
 pub mod verify;
 
 pub use extract::ExtractValueDeserializer;
+pub use unknown_fields::{UnknownFields, UnknownFieldsReport};
 
 use cow3::{str_slice_to_range, Cow3};
 
@@ -41,19 +44,43 @@ impl Drop for InPropertyStringGuard {
 }
 
 #[derive(Debug)]
-pub struct Error(Cow<'static, str>);
+pub struct Error {
+    msg: Cow<'static, str>,
+    path: Option<String>,
+}
 
 impl std::error::Error for Error {}
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        fmt::Display::fmt(&self.0, f)
+        match &self.path {
+            Some(path) => write!(f, "{path}: {}", self.msg),
+            None => fmt::Display::fmt(&self.msg, f),
+        }
     }
 }
 
 impl Error {
     pub(crate) fn msg<T: Into<Cow<'static, str>>>(msg: T) -> Self {
-        Self(msg.into())
+        Self {
+            msg: msg.into(),
+            path: None,
+        }
+    }
+
+    /// The path of the property the error occurred in, with nested property strings separated by
+    /// dots, e.g. `nested.name`.
+    pub fn path(&self) -> Option<&str> {
+        self.path.as_deref()
+    }
+
+    /// Prepend `key` to the property path of the error.
+    pub(crate) fn with_key(mut self, key: &str) -> Self {
+        self.path = Some(match self.path.take() {
+            Some(path) => format!("{key}.{path}"),
+            None => key.to_string(),
+        });
+        self
     }
 
     fn invalid<T: fmt::Display>(msg: T) -> Self {
@@ -63,13 +90,13 @@ impl Error {
 
 impl serde::de::Error for Error {
     fn custom<T: fmt::Display>(msg: T) -> Self {
-        Self(msg.to_string().into())
+        Self::msg(msg.to_string())
     }
 }
 
 impl From<serde_json::Error> for Error {
     fn from(error: serde_json::Error) -> Self {
-        Self(error.to_string().into())
+        Self::msg(error.to_string())
     }
 }
 
@@ -83,11 +110,19 @@ impl From<fmt::Error> for Error {
 pub struct SchemaDeserializer<'de, 'i> {
     input: Cow3<'de, 'i, str>,
     schema: &'static Schema,
+
+    /// The input was quoted in a property string, so an empty input is an empty value rather
+    /// than `None`.
+    quoted: bool,
 }
 
 impl<'de, 'i> SchemaDeserializer<'de, 'i> {
     pub fn new_cow(input: Cow3<'de, 'i, str>, schema: &'static Schema) -> Self {
-        Self { input, schema }
+        Self {
+            input,
+            schema,
+            quoted: false,
+        }
     }
 
     pub fn new<T>(input: T, schema: &'static Schema) -> Self
@@ -97,9 +132,29 @@ impl<'de, 'i> SchemaDeserializer<'de, 'i> {
         Self {
             input: Cow3::from_original(input.into()),
             schema,
+            quoted: false,
         }
     }
 
+    fn quoted(mut self, quoted: bool) -> Self {
+        self.quoted = quoted;
+        self
+    }
+
+    /// Deserialize a `T` with the given handling of properties unknown to the object schemas,
+    /// including those of nested property strings.
+    ///
+    /// Returns the unknown properties which were skipped along with the value.
+    pub fn deserialize_with_unknown_fields<T>(
+        self,
+        mode: UnknownFields,
+    ) -> Result<(T, UnknownFieldsReport), Error>
+    where
+        T: de::Deserialize<'de>,
+    {
+        unknown_fields::with_mode(mode, || T::deserialize(self))
+    }
+
     fn deserialize_str<V>(
         self,
         visitor: V,
@@ -169,8 +224,7 @@ impl<'de, 'i> de::Deserializer<'de> for SchemaDeserializer<'de, 'i> {
             Schema::Object(schema) => visitor.visit_map(MapAccess::new_cow(self.input, schema)),
             Schema::Null => Err(Error::msg("null")),
             Schema::Boolean(_) => visitor.visit_bool(
-                schema::parse_boolean(&self.input)
-                    .map_err(|_| Error::msg(format!("not a boolean: {:?}", self.input)))?,
+                schema::parse_boolean(&self.input).map_err(|err| Error::msg(err.to_string()))?,
             ),
             Schema::Integer(schema) => {
                 // FIXME: isize vs explicit i64, needs fixing in schema check_constraints api
@@ -190,6 +244,15 @@ impl<'de, 'i> de::Deserializer<'de> for SchemaDeserializer<'de, 'i> {
                     visitor.visit_i64(value)
                 }
             }
+            Schema::Uint64(schema) => {
+                let value: u64 = self.input.parse().map_err(|_| {
+                    Error::msg(format!("not an unsigned integer: {:?}", self.input))
+                })?;
+
+                schema.check_constraints(value).map_err(Error::invalid)?;
+
+                visitor.visit_u64(value)
+            }
             Schema::Number(schema) => {
                 let value: f64 = self
                     .input
@@ -213,7 +276,7 @@ impl<'de, 'i> de::Deserializer<'de> for SchemaDeserializer<'de, 'i> {
     where
         V: de::Visitor<'de>,
     {
-        if self.input.is_empty() {
+        if self.input.is_empty() && !self.quoted {
             visitor.visit_none()
         } else {
             visitor.visit_some(self)
@@ -395,6 +458,8 @@ pub struct SeqAccess<'o, 'i, 's> {
     has_null: bool,
     at: usize,
     count: usize,
+    /// The elements seen so far as JSON values, if they must be unique.
+    seen: Vec<Value>,
 }
 
 impl<'o, 'i, 's> SeqAccess<'o, 'i, 's> {
@@ -406,8 +471,26 @@ impl<'o, 'i, 's> SeqAccess<'o, 'i, 's> {
             input,
             at: 0,
             count: 0,
+            seen: Vec::new(),
         }
     }
+
+    /// Check that an element is not a duplicate of an earlier one.
+    fn check_unique(&mut self, element: &str) -> Result<(), Error> {
+        let value = self
+            .schema
+            .items
+            .parse_simple_value(element)
+            .unwrap_or_else(|_| Value::from(element));
+        if let Some(first) = self.seen.iter().position(|seen| *seen == value) {
+            return Err(Error::msg(format!(
+                "[{}]: duplicate item, same as [{first}]",
+                self.seen.len()
+            )));
+        }
+        self.seen.push(value);
+        Ok(())
+    }
 }
 
 impl<'de, 'i, 's> de::SeqAccess<'de> for SeqAccess<'de, 'i, 's> {
@@ -429,6 +512,10 @@ impl<'de, 'i, 's> de::SeqAccess<'de> for SeqAccess<'de, 'i, 's> {
             }
 
             self.count += 1;
+            if self.schema.unique_items {
+                let element = self.input[el_range.clone()].to_string();
+                self.check_unique(&element)?;
+            }
 
             return seed
                 .deserialize(SchemaDeserializer::new_cow(
@@ -496,8 +583,11 @@ pub struct MapAccess<'de, 'i> {
     /// strings.
     schema: &'static dyn schema::ObjectSchemaType,
 
-    /// The current next value's key, value and schema (if available).
-    value: Option<(Cow<'de, str>, Cow<'de, str>, Option<&'static Schema>)>,
+    /// The current next value's key, value, whether it was quoted, and schema (if available).
+    value: Option<(Cow<'de, str>, Cow<'de, str>, bool, Option<&'static Schema>)>,
+
+    /// The keys seen so far, used to check property constraints at the end.
+    seen_keys: Vec<String>,
 }
 
 impl<'de, 'i> MapAccess<'de, 'i> {
@@ -508,6 +598,7 @@ impl<'de, 'i> MapAccess<'de, 'i> {
             schema,
             input_at: 0,
             value: None,
+            seen_keys: Vec::new(),
         }
     }
 
@@ -521,6 +612,7 @@ impl<'de, 'i> MapAccess<'de, 'i> {
             schema,
             input_at: 0,
             value: None,
+            seen_keys: Vec::new(),
         }
     }
 
@@ -534,6 +626,26 @@ impl<'de, 'i> MapAccess<'de, 'i> {
             schema,
             input_at: 0,
             value: None,
+            seen_keys: Vec::new(),
+        }
+    }
+
+    /// Once all keys are known, check the `requires` and `conflicts` constraints of the schema.
+    /// When verifying, the verifier takes care of this.
+    fn check_property_constraints(&self) -> Result<(), Error> {
+        if verify::is_verifying() {
+            return Ok(());
+        }
+
+        let is_set = |name: &str| self.seen_keys.iter().any(|key| key == name);
+        match self
+            .schema
+            .check_property_constraints(&is_set)
+            .into_iter()
+            .next()
+        {
+            Some((_name, err)) => Err(Error::msg(err.to_string())),
+            None => Ok(()),
         }
     }
 }
@@ -552,62 +664,79 @@ impl<'de, 'i> de::MapAccess<'de> for MapAccess<'de, 'i> {
             return Ok(None);
         }
 
-        let (key, value, rem) = match next_property(&self.input[self.input_at..]) {
-            None => return Ok(None),
-            Some(entry) => entry?,
-        };
-
-        if rem.is_empty() {
-            self.input_at = self.input.len();
-        } else {
-            let ofs = unsafe { rem.as_ptr().offset_from(self.input.as_ptr()) };
-            if ofs < 0 || (ofs as usize) > self.input.len() {
-                // 'rem' is either an empty string (rem.is_empty() is true), or a valid offset into
-                // the input string...
-                panic!("unexpected remainder in next_property");
+        let (key, value, quoted, schema) = loop {
+            let (key, value, quoted, rem) = match next_property(&self.input[self.input_at..]) {
+                None => {
+                    self.check_property_constraints()?;
+                    return Ok(None);
+                }
+                Some(entry) => entry?,
+            };
+
+            if rem.is_empty() {
+                self.input_at = self.input.len();
+            } else {
+                let ofs = unsafe { rem.as_ptr().offset_from(self.input.as_ptr()) };
+                if ofs < 0 || (ofs as usize) > self.input.len() {
+                    // 'rem' is either an empty string (rem.is_empty() is true), or a valid offset
+                    // into the input string...
+                    panic!("unexpected remainder in next_property");
+                }
+                self.input_at = ofs as usize;
             }
-            self.input_at = ofs as usize;
-        }
 
-        let value = match value {
-            Cow::Owned(value) => Cow::Owned(value),
-            Cow::Borrowed(value) => match str_slice_to_range(&self.input, value) {
-                None => Cow::Owned(value.to_string()),
-                Some(range) => match &self.input {
-                    Cow3::Original(orig) => Cow::Borrowed(&orig[range]),
-                    _ => Cow::Owned(value.to_string()),
-                },
-            },
-        };
-
-        let (key, schema) = match key {
-            Some(key) => {
-                let schema = self.schema.lookup(key);
-                let key = match str_slice_to_range(&self.input, key) {
-                    None => Cow::Owned(key.to_string()),
+            let value = match value {
+                Cow::Owned(value) => Cow::Owned(value),
+                Cow::Borrowed(value) => match str_slice_to_range(&self.input, value) {
+                    None => Cow::Owned(value.to_string()),
                     Some(range) => match &self.input {
                         Cow3::Original(orig) => Cow::Borrowed(&orig[range]),
-                        _ => Cow::Owned(key.to_string()),
+                        _ => Cow::Owned(value.to_string()),
                     },
-                };
-                (key, schema)
-            }
-            None => match self.schema.default_key() {
+                },
+            };
+
+            let (key, schema) = match key {
                 Some(key) => {
-                    let schema = self
-                        .schema
-                        .lookup(key)
-                        .ok_or(Error::msg("bad default key"))?;
-                    (Cow::Borrowed(key), Some(schema))
+                    let schema = self.schema.lookup(key);
+                    let key = match str_slice_to_range(&self.input, key) {
+                        None => Cow::Owned(key.to_string()),
+                        Some(range) => match &self.input {
+                            Cow3::Original(orig) => Cow::Borrowed(&orig[range]),
+                            _ => Cow::Owned(key.to_string()),
+                        },
+                    };
+                    (key, schema)
                 }
-                None => {
-                    return Err(Error::msg(
-                        "value without key, but schema does not define a default key",
-                    ))
-                }
-            },
+                None => match self.schema.default_key() {
+                    Some(key) => {
+                        let schema = self
+                            .schema
+                            .lookup(key)
+                            .ok_or(Error::msg("bad default key"))?;
+                        (Cow::Borrowed(key), Some(schema))
+                    }
+                    None => {
+                        return Err(Error::msg(
+                            "value without key, but schema does not define a default key",
+                        ))
+                    }
+                },
+            };
+            let schema = schema.map(|(_optional, schema)| schema);
+
+            if schema.is_none()
+                && !verify::is_verifying()
+                && !self.schema.additional_properties()
+                && unknown_fields::skip_unknown(&key, &value)
+            {
+                continue;
+            }
+
+            break (key, value, quoted, schema);
         };
-        let schema = schema.map(|(_optional, schema)| schema);
+
+        self.seen_keys.push(key.to_string());
 
         let out = match &key {
             Cow::Borrowed(key) => {
@@ -618,7 +747,7 @@ impl<'de, 'i> de::MapAccess<'de> for MapAccess<'de, 'i> {
             }
         };
 
-        self.value = Some((key, value, schema));
+        self.value = Some((key, value, quoted, schema));
 
         Ok(Some(out))
     }
@@ -627,10 +756,12 @@ impl<'de, 'i> de::MapAccess<'de> for MapAccess<'de, 'i> {
     where
         V: de::DeserializeSeed<'de>,
     {
-        let (key, input, schema) = self.value.take().ok_or(Error::msg("bad map access"))?;
+        let (key, input, quoted, schema) = self.value.take().ok_or(Error::msg("bad map access"))?;
 
         if let Some(schema) = schema {
-            seed.deserialize(SchemaDeserializer::new(input, schema))
+            let _path = unknown_fields::enter_property(&key);
+            seed.deserialize(SchemaDeserializer::new(input, schema).quoted(quoted))
+                .map_err(|err| err.with_key(&key))
         } else {
             if !verify::is_verifying() && !self.schema.additional_properties() {
                 return Err(Error::msg(format!("unknown key {:?}", key.as_ref())));
@@ -116,7 +116,15 @@ impl<'de, 'i> de::Deserializer<'de> for NoSchemaDeserializer<'de, 'i> {
         deserialize_u64  : visit_u64  : u64  : "not an integer: {:?}",
         deserialize_f32  : visit_f32  : f32  : "not a number: {:?}",
         deserialize_f64  : visit_f64  : f64  : "not a number: {:?}",
-        deserialize_bool : visit_bool : bool : "not a boolean: {:?}",
+    }
+
+    fn deserialize_bool<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        let value =
+            crate::schema::parse_boolean(&self.input).map_err(|err| Error::msg(err.to_string()))?;
+        visitor.visit_bool(value)
     }
 
     fn deserialize_char<V>(self, visitor: V) -> Result<V::Value, Error>
@@ -0,0 +1,142 @@
+//! Selectable handling of properties unknown to an object schema.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+/// How to handle properties which are not part of an object schema which does not allow
+/// additional properties.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UnknownFields {
+    /// Fail with an "unknown key" error, which is what API input should do.
+    #[default]
+    Reject,
+
+    /// Skip unknown properties and report their names, so that the caller can log them.
+    IgnoreWithWarning,
+
+    /// Skip unknown properties and report them along with their values, so that they can be
+    /// written back out. Useful for config files written by a newer version of the software.
+    ///
+    /// Only the unknown properties of the outermost object are preserved, unknown properties of
+    /// nested property strings are ignored and reported like with `IgnoreWithWarning`.
+    Preserve,
+}
+
+/// The unknown properties skipped during a deserialization, see [`UnknownFields`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct UnknownFieldsReport {
+    /// The paths of the ignored properties. Properties of nested property strings are prefixed
+    /// with the properties containing them, separated by dots, e.g. `nested.name`.
+    pub ignored: Vec<String>,
+
+    /// The preserved properties with their unquoted values.
+    pub preserved: BTreeMap<String, String>,
+}
+
+impl UnknownFieldsReport {
+    /// Returns true if no unknown property was found.
+    pub fn is_empty(&self) -> bool {
+        self.ignored.is_empty() && self.preserved.is_empty()
+    }
+}
+
+struct State {
+    mode: UnknownFields,
+    path: Vec<String>,
+    report: UnknownFieldsReport,
+}
+
+thread_local! {
+    static STATE: RefCell<Option<State>> = const { RefCell::new(None) };
+}
+
+/// Restores the previous state when leaving a `with_mode` scope, even when panicking.
+struct ModeGuard(Option<Option<State>>);
+
+impl Drop for ModeGuard {
+    fn drop(&mut self) {
+        if let Some(prev) = self.0.take() {
+            STATE.with(|state| *state.borrow_mut() = prev);
+        }
+    }
+}
+
+/// Run `f` with `mode` applying to all object schemas it deserializes.
+pub(crate) fn with_mode<T, E>(
+    mode: UnknownFields,
+    f: impl FnOnce() -> Result<T, E>,
+) -> Result<(T, UnknownFieldsReport), E> {
+    let state = State {
+        mode,
+        path: Vec::new(),
+        report: UnknownFieldsReport::default(),
+    };
+    let mut guard = ModeGuard(Some(STATE.with(|s| s.replace(Some(state)))));
+
+    let result = f();
+
+    let prev = guard.0.take().unwrap();
+    let report = STATE
+        .with(|s| s.replace(prev))
+        .map(|state| state.report)
+        .unwrap_or_default();
+
+    result.map(|value| (value, report))
+}
+
+/// Check whether an unknown property should be skipped instead of failing the deserialization,
+/// recording it in the report if so.
+pub(crate) fn skip_unknown(key: &str, value: &str) -> bool {
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let Some(state) = state.as_mut() else {
+            return false;
+        };
+
+        match state.mode {
+            UnknownFields::Reject => return false,
+            UnknownFields::Preserve if state.path.is_empty() => {
+                state
+                    .report
+                    .preserved
+                    .insert(key.to_string(), value.to_string());
+            }
+            UnknownFields::Preserve | UnknownFields::IgnoreWithWarning => {
+                let mut path = state.path.join(".");
+                if !path.is_empty() {
+                    path.push('.');
+                }
+                path.push_str(key);
+                state.report.ignored.push(path);
+            }
+        }
+        true
+    })
+}
+
+/// Leaves the value of a property when dropped.
+pub(crate) struct PathGuard(bool);
+
+impl Drop for PathGuard {
+    fn drop(&mut self) {
+        if self.0 {
+            STATE.with(|state| {
+                if let Some(state) = state.borrow_mut().as_mut() {
+                    state.path.pop();
+                }
+            });
+        }
+    }
+}
+
+/// Enter the value of the property `key`, so that nested unknown properties are reported with
+/// their full path.
+pub(crate) fn enter_property(key: &str) -> PathGuard {
+    PathGuard(STATE.with(|state| match state.borrow_mut().as_mut() {
+        Some(state) => {
+            state.path.push(key.to_string());
+            true
+        }
+        None => false,
+    }))
+}
@@ -116,6 +116,7 @@ impl<'de> Deserialize<'de> for Verifier {
             match schema {
                 Schema::Boolean(_) => deserializer.deserialize_bool(visitor),
                 Schema::Integer(_) => deserializer.deserialize_i64(visitor),
+                Schema::Uint64(_) => deserializer.deserialize_u64(visitor),
                 Schema::Number(_) => deserializer.deserialize_f64(visitor),
                 Schema::String(_) => deserializer.deserialize_str(visitor),
                 Schema::Object(_) => deserializer.deserialize_map(visitor),
@@ -150,6 +151,7 @@ impl<'de> de::Visitor<'de> for Visitor {
         match self.0 {
             Schema::Boolean(_) => f.write_str("boolean"),
             Schema::Integer(_) => f.write_str("integer"),
+            Schema::Uint64(_) => f.write_str("unsigned integer"),
             Schema::Number(_) => f.write_str("number"),
             Schema::String(_) => f.write_str("string"),
             Schema::Object(_) => f.write_str("object"),
@@ -174,6 +176,10 @@ impl<'de> de::Visitor<'de> for Visitor {
                 Ok(()) => Ok(Verifier),
                 Err(err) => Err(E::custom(err)),
             },
+            Schema::Uint64(schema) if v >= 0 => match schema.check_constraints(v as u64) {
+                Ok(()) => Ok(Verifier),
+                Err(err) => Err(E::custom(err)),
+            },
             _ => Err(E::invalid_type(Unexpected::Signed(v), &self)),
         }
     }
@@ -184,6 +190,10 @@ impl<'de> de::Visitor<'de> for Visitor {
                 Ok(()) => Ok(Verifier),
                 Err(err) => Err(E::custom(err)),
             },
+            Schema::Uint64(schema) => match schema.check_constraints(v) {
+                Ok(()) => Ok(Verifier),
+                Err(err) => Err(E::custom(err)),
+            },
             _ => Err(E::invalid_type(Unexpected::Unsigned(v), &self)),
         }
     }
@@ -242,11 +252,13 @@ impl<'de> de::Visitor<'de> for Visitor {
         }
 
         let mut other_keys = HashSet::<String>::new();
+        let mut set_keys = HashSet::<String>::new();
         loop {
             let key: Cow<'de, str> = match map.next_key()? {
                 Some(key) => key,
                 None => break,
             };
+            set_keys.insert(key.clone().into_owned());
 
             let _guard = match schema.lookup(&key) {
                 Some((optional, schema)) => {
@@ -286,6 +298,10 @@ impl<'de> de::Visitor<'de> for Visitor {
             push_errstr_path(key, "property is missing and it is not optional");
         }
 
+        for (key, err) in schema.check_property_constraints(&|name| set_keys.contains(name)) {
+            push_errstr_path(&key, &err.to_string());
+        }
+
         Ok(Verifier)
     }
 
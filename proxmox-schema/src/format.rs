@@ -1,7 +1,13 @@
 //! Module to generate and format API Documentation
+//!
+//! Descriptions are translated with the [translator](crate::set_schema_translator), if any.
+
+use std::borrow::Cow;
 
 use anyhow::{bail, Error};
+use serde_json::{json, Value};
 
+use crate::i18n::translate;
 use crate::*;
 
 /// Enumerate different styles to display parameters/properties.
@@ -109,7 +115,7 @@ fn get_simple_type_text(schema: &Schema, list_enums: bool) -> String {
     match schema {
         Schema::Null => String::from("<null>"), // should not happen
         Schema::Boolean(_) => String::from("<1|0>"),
-        Schema::Integer(_) => String::from("<integer>"),
+        Schema::Integer(_) | Schema::Uint64(_) => String::from("<integer>"),
         Schema::Number(_) => String::from("<number>"),
         Schema::String(string_schema) => match string_schema {
             StringSchema {
@@ -147,7 +153,7 @@ pub fn dump_properties(
     let mut required_list: Vec<String> = Vec::new();
     let mut optional_list: Vec<String> = Vec::new();
 
-    for (prop, optional, schema) in param.properties() {
+    for (prop, optional, schema) in param.ordered_properties() {
         if skip.iter().any(|n| n == prop) {
             continue;
         }
@@ -155,6 +161,11 @@ pub fn dump_properties(
         let mut param_descr =
             get_property_description(prop, schema, style, DocumentationFormat::ReST);
 
+        if let Some(text) = get_property_dependency_text(param, prop) {
+            param_descr.push_str("\n\n");
+            param_descr.push_str(&wrap_text("  ", "  ", &text, 80));
+        }
+
         if !indent.is_empty() {
             param_descr = format!("{}{}", indent, param_descr); // indent first line
             param_descr = param_descr.replace('\n', &format!("\n{}", indent)); // indent rest
@@ -215,6 +226,147 @@ pub fn dump_properties(
     res
 }
 
+/// Describe the `requires` and `conflicts` constraints of a property.
+fn get_property_dependency_text(param: &dyn ObjectSchemaType, prop: &str) -> Option<String> {
+    let list_text = |list: &[&str]| {
+        list.iter()
+            .map(|name| format!("``{}``", name))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    let mut parts = Vec::new();
+
+    let requires = param.property_requires(prop);
+    if !requires.is_empty() {
+        parts.push(format!("Requires {}.", list_text(requires)));
+    }
+
+    let conflicts = param.property_conflicts(prop);
+    if !conflicts.is_empty() {
+        parts.push(format!("Conflicts with {}.", list_text(conflicts)));
+    }
+
+    (!parts.is_empty()).then(|| parts.join(" "))
+}
+
+#[test]
+fn test_dump_properties_weights() {
+    const SCHEMA: ObjectSchema = ObjectSchema::new(
+        "An object with ordering weights.",
+        &[
+            ("comment", true, &StringSchema::new("A comment.").schema()),
+            ("count", false, &IntegerSchema::new("A count.").schema()),
+            ("name", false, &StringSchema::new("The name.").schema()),
+            ("type", false, &StringSchema::new("The type.").schema()),
+        ],
+    )
+    .property_weights(&[("type", -2), ("name", -1)]);
+
+    let dump = dump_properties(&SCHEMA, "", ParameterDisplayStyle::Config, &[]);
+
+    let order: Vec<&str> = dump
+        .lines()
+        .filter_map(|line| line.strip_prefix("``"))
+        .filter_map(|line| line.split("``").next())
+        .collect();
+    assert_eq!(order, ["type", "name", "count", "comment"]);
+
+    let required = dump.find("*Required properties:*").unwrap();
+    let optional = dump.find("*Optional properties:*").unwrap();
+    assert!(required < dump.find("``type``").unwrap());
+    assert!(dump.find("``count``").unwrap() < optional);
+    assert!(optional < dump.find("``comment``").unwrap());
+
+    assert_eq!(
+        get_object_type_text(&SCHEMA),
+        "[type=<string> ,name=<string> ,count=<integer> [,comment=<string>]]",
+    );
+}
+
+#[test]
+fn test_dump_properties_dependencies() {
+    const SCHEMA: ObjectSchema = ObjectSchema::new(
+        "An object with property dependencies.",
+        &[
+            ("keyring", true, &StringSchema::new("A keyring.").schema()),
+            ("password", true, &StringSchema::new("A password.").schema()),
+            ("server1", true, &StringSchema::new("A server.").schema()),
+            ("server2", true, &StringSchema::new("A server.").schema()),
+        ],
+    )
+    .requires(&[("server2", &["server1"])])
+    .conflicts(&[("keyring", &["password"]), ("password", &["keyring"])]);
+
+    let dump = dump_properties(&SCHEMA, "", ParameterDisplayStyle::Config, &[]);
+
+    assert!(dump.contains("``server2`` : ``<string>``\n  A server.\n\n  Requires ``server1``.\n"));
+    assert!(dump.contains("  A keyring.\n\n  Conflicts with ``password``.\n"));
+    assert!(dump.contains("``server1`` : ``<string>``\n  A server.\n"));
+    assert!(!dump.contains("``server1`` : ``<string>``\n  A server.\n\n"));
+}
+
+#[test]
+fn test_dump_properties_secret() {
+    const SCHEMA: ObjectSchema = ObjectSchema::new(
+        "An object with a secret property.",
+        &[(
+            "password",
+            false,
+            &StringSchema::new("A password.")
+                .secret()
+                .example("Hunter2!")
+                .schema(),
+        )],
+    );
+
+    let dump = dump_properties(&SCHEMA, "", ParameterDisplayStyle::Config, &[]);
+    assert!(dump.contains("  A password. Sensitive value, it is never logged.\n"));
+    assert!(dump.contains("  Example: ``<redacted>``\n"), "{dump}");
+    assert!(!dump.contains("Hunter2"));
+
+    let json = schema_to_json(&SCHEMA.schema());
+    assert_eq!(json["properties"]["password"]["secret"], true);
+    assert_eq!(
+        json["properties"]["password"]["examples"],
+        json!(["<redacted>"])
+    );
+}
+
+#[test]
+fn test_dump_properties_example() {
+    const SCHEMA: ObjectSchema = ObjectSchema::new(
+        "An object with an example.",
+        &[(
+            "size",
+            false,
+            &IntegerSchema::new("The size.").example("32").schema(),
+        )],
+    );
+
+    let dump = dump_properties(&SCHEMA, "", ParameterDisplayStyle::Config, &[]);
+    assert!(
+        dump.contains("  The size.\n\n  Example: ``32``\n"),
+        "{dump}"
+    );
+
+    let text = get_property_description(
+        "size",
+        SCHEMA.properties[0].2,
+        ParameterDisplayStyle::Arg,
+        DocumentationFormat::Full,
+    );
+    assert!(
+        text.ends_with("The size.\n\n             Example: 32"),
+        "{text}"
+    );
+
+    assert_eq!(
+        schema_to_json(&SCHEMA.schema())["properties"]["size"]["examples"],
+        json!([32])
+    );
+}
+
 /// Helper to format an object property, including name, type and description.
 pub fn get_property_description(
     name: &str,
@@ -225,34 +377,45 @@ pub fn get_property_description(
     let type_text = get_schema_type_text(schema, style);
 
     let (descr, default, extra) = match schema {
-        Schema::Null => ("null", None, None),
+        Schema::Null => (Cow::Borrowed("null"), None, None),
         Schema::String(ref schema) => (
-            schema.description,
+            translate(schema.description),
             schema.default.map(|v| v.to_owned()),
-            None,
+            schema
+                .secret
+                .then(|| translate("Sensitive value, it is never logged.").into_owned()),
         ),
         Schema::Boolean(ref schema) => (
-            schema.description,
+            translate(schema.description),
             schema.default.map(|v| v.to_string()),
             None,
         ),
         Schema::Integer(ref schema) => (
-            schema.description,
+            translate(schema.description),
+            schema.default.map(|v| v.to_string()),
+            None,
+        ),
+        Schema::Uint64(ref schema) => (
+            translate(schema.description),
             schema.default.map(|v| v.to_string()),
             None,
         ),
         Schema::Number(ref schema) => (
-            schema.description,
+            translate(schema.description),
             schema.default.map(|v| v.to_string()),
             None,
         ),
-        Schema::Object(ref schema) => (schema.description, None, None),
-        Schema::AllOf(ref schema) => (schema.description, None, None),
-        Schema::OneOf(ref schema) => (schema.description, None, None),
+        Schema::Object(ref schema) => (translate(schema.description), None, None),
+        Schema::AllOf(ref schema) => (translate(schema.description), None, None),
+        Schema::OneOf(ref schema) => (translate(schema.description), None, None),
         Schema::Array(ref schema) => (
-            schema.description,
+            translate(schema.description),
             None,
-            Some(String::from("Can be specified more than once.")),
+            Some(if schema.unique_items {
+                translate("Can be specified more than once, values must be unique.").into_owned()
+            } else {
+                translate("Can be specified more than once.").into_owned()
+            }),
         ),
     };
 
@@ -261,11 +424,19 @@ pub fn get_property_description(
         None => String::new(),
     };
 
-    let descr = match extra {
+    let mut descr = match extra {
         Some(extra) => format!("{} {}", descr, extra),
-        None => String::from(descr),
+        None => descr.into_owned(),
     };
 
+    if let Some(example) = schema.display_example() {
+        if format == DocumentationFormat::ReST {
+            descr.push_str(&format!("\n\nExample: ``{example}``"));
+        } else {
+            descr.push_str(&format!("\n\nExample: {example}"));
+        }
+    }
+
     if format == DocumentationFormat::ReST {
         let mut text = match style {
             ParameterDisplayStyle::Config => {
@@ -344,6 +515,12 @@ pub fn get_schema_type_text(schema: &Schema, _style: ParameterDisplayStyle) -> S
             (None, Some(max)) => format!("<integer> (-N - {})", max),
             _ => String::from("<integer>"),
         },
+        Schema::Uint64(uint64_schema) => match (uint64_schema.minimum, uint64_schema.maximum) {
+            (Some(min), Some(max)) => format!("<integer> ({} - {})", min, max),
+            (Some(min), None) => format!("<integer> ({} - N)", min),
+            (None, Some(max)) => format!("<integer> (0 - {})", max),
+            _ => String::from("<integer>"),
+        },
         Schema::Number(number_schema) => match (number_schema.minimum, number_schema.maximum) {
             (Some(min), Some(max)) => format!("<number> ({} - {})", min, max),
             (Some(min), None) => format!("<number> ({} - N)", min),
@@ -391,8 +568,10 @@ fn get_object_type_text(object_schema: &ObjectSchema) -> String {
         add_part(default_key, optional, schema);
     }
 
+    let properties = object_schema.ordered_properties();
+
     // add required keys
-    for (name, optional, schema) in object_schema.properties {
+    for (name, optional, schema) in properties.iter().copied() {
         if *optional {
             continue;
         }
@@ -405,7 +584,7 @@ fn get_object_type_text(object_schema: &ObjectSchema) -> String {
     }
 
     // add options keys
-    for (name, optional, schema) in object_schema.properties {
+    for (name, optional, schema) in properties.iter().copied() {
         if !*optional {
             continue;
         }
@@ -437,7 +616,7 @@ pub fn dump_enum_properties(schema: &Schema) -> Result<String, Error> {
             use std::fmt::Write;
 
             let _ = write!(res, ":``{}``: ", item.value);
-            let descr = wrap_text("", "  ", item.description, 80);
+            let descr = wrap_text("", "  ", &translate(item.description), 80);
             res.push_str(&descr);
             res.push('\n');
         }
@@ -452,6 +631,10 @@ pub fn dump_api_return_schema(returns: &ReturnType, style: ParameterDisplayStyle
 
     let schema = &returns.schema;
 
+    if let Some(content_type) = returns.content_type {
+        return format!("*Returns*: **raw data** (``{content_type}``)\n\n");
+    }
+
     let mut res = if returns.optional {
         "*Returns* (optionally): ".to_string()
     } else {
@@ -466,37 +649,41 @@ pub fn dump_api_return_schema(returns: &ReturnType, style: ParameterDisplayStyle
             return res;
         }
         Schema::Boolean(schema) => {
-            let description = wrap_text("", "", schema.description, 80);
+            let description = wrap_text("", "", &translate(schema.description), 80);
             res.push_str(&description);
         }
         Schema::Integer(schema) => {
-            let description = wrap_text("", "", schema.description, 80);
+            let description = wrap_text("", "", &translate(schema.description), 80);
+            res.push_str(&description);
+        }
+        Schema::Uint64(schema) => {
+            let description = wrap_text("", "", &translate(schema.description), 80);
             res.push_str(&description);
         }
         Schema::Number(schema) => {
-            let description = wrap_text("", "", schema.description, 80);
+            let description = wrap_text("", "", &translate(schema.description), 80);
             res.push_str(&description);
         }
         Schema::String(schema) => {
-            let description = wrap_text("", "", schema.description, 80);
+            let description = wrap_text("", "", &translate(schema.description), 80);
             res.push_str(&description);
         }
         Schema::Array(schema) => {
-            let description = wrap_text("", "", schema.description, 80);
+            let description = wrap_text("", "", &translate(schema.description), 80);
             res.push_str(&description);
         }
         Schema::Object(obj_schema) => {
-            let description = wrap_text("", "", obj_schema.description, 80);
+            let description = wrap_text("", "", &translate(obj_schema.description), 80);
             res.push_str(&description);
             res.push_str(&dump_properties(obj_schema, "", style, &[]));
         }
         Schema::AllOf(all_of_schema) => {
-            let description = wrap_text("", "", all_of_schema.description, 80);
+            let description = wrap_text("", "", &translate(all_of_schema.description), 80);
             res.push_str(&description);
             res.push_str(&dump_properties(all_of_schema, "", style, &[]));
         }
         Schema::OneOf(all_of_schema) => {
-            let description = wrap_text("", "", all_of_schema.description, 80);
+            let description = wrap_text("", "", &translate(all_of_schema.description), 80);
             res.push_str(&description);
             res.push_str(&dump_properties(all_of_schema, "", style, &[]));
         }
@@ -506,3 +693,762 @@ pub fn dump_api_return_schema(returns: &ReturnType, style: ParameterDisplayStyle
 
     res
 }
+
+fn object_properties_to_json(schema: &dyn ObjectSchemaType) -> Value {
+    let mut properties = serde_json::Map::new();
+    for (name, optional, prop_schema) in schema.properties() {
+        let mut prop = schema_to_json(prop_schema);
+        if *optional {
+            prop["optional"] = true.into();
+        }
+        properties.insert(name.to_string(), prop);
+    }
+    Value::Object(properties)
+}
+
+/// Convert a schema into a JSON value for machine consumption (e.g. an API viewer).
+///
+/// Object properties carry an `"optional": true` member if they are optional, string formats
+/// are described by `enum`, `pattern` or, for property strings, a nested `format` schema. Named
+/// verification functions, which clients cannot run themselves, are listed as `verifier`.
+/// Example values are listed in `examples`, like in JSON Schema.
+pub fn schema_to_json(schema: &Schema) -> Value {
+    let mut data = schema_to_json_do(schema);
+    if let Some(example) = typed_example(schema) {
+        data["examples"] = json!([example]);
+    }
+    data
+}
+
+/// The example of a schema as typed value.
+fn typed_example(schema: &Schema) -> Option<Value> {
+    let example = schema.display_example()?;
+    // examples are given as text, but JSON Schema expects typed values
+    Some(match schema.is_secret() {
+        true => example.into(),
+        false => schema
+            .parse_simple_value(example)
+            .unwrap_or_else(|_| example.into()),
+    })
+}
+
+fn schema_to_json_do(schema: &Schema) -> Value {
+    match schema {
+        Schema::Null => json!({ "type": "null" }),
+        Schema::Boolean(schema) => boolean_schema_to_json(schema),
+        Schema::Integer(schema) => integer_schema_to_json(schema),
+        Schema::Uint64(schema) => {
+            let mut data =
+                json!({ "type": "integer", "description": translate(schema.description) });
+            if let Some(minimum) = schema.minimum {
+                data["minimum"] = minimum.into();
+            }
+            if let Some(maximum) = schema.maximum {
+                data["maximum"] = maximum.into();
+            }
+            if let Some(default) = schema.default {
+                data["default"] = default.into();
+            }
+            data
+        }
+        Schema::Number(schema) => number_schema_to_json(schema),
+        Schema::String(schema) => {
+            let mut data =
+                json!({ "type": "string", "description": translate(schema.description) });
+            if let Some(default) = schema.default {
+                data["default"] = default.into();
+            }
+            // JSON Schema counts characters, byte limits are added as extension and converted to
+            // the character limits they imply, a character takes up to 4 bytes in UTF-8
+            match schema.length_unit {
+                LengthUnit::Chars => {
+                    if let Some(min_length) = schema.min_length {
+                        data["minLength"] = min_length.into();
+                    }
+                    if let Some(max_length) = schema.max_length {
+                        data["maxLength"] = max_length.into();
+                    }
+                }
+                LengthUnit::Bytes => {
+                    if let Some(min_length) = schema.min_length {
+                        data["minLength"] = min_length.div_ceil(4).into();
+                        data["minLengthBytes"] = min_length.into();
+                    }
+                    if let Some(max_length) = schema.max_length {
+                        data["maxLength"] = max_length.into();
+                        data["maxLengthBytes"] = max_length.into();
+                    }
+                }
+            }
+            if let Some(type_text) = schema.type_text {
+                data["typetext"] = type_text.into();
+            }
+            if schema.secret {
+                data["secret"] = true.into();
+            }
+            match schema.format.map(ApiStringFormat::describe) {
+                Some(FormatDescription::Enum(variants)) => {
+                    data["enum"] = variants.iter().map(|entry| entry.value).collect();
+                }
+                Some(FormatDescription::Pattern(pattern)) => {
+                    data["pattern"] = pattern.into();
+                }
+                Some(FormatDescription::PropertyString(subschema)) => {
+                    data["format"] = schema_to_json(subschema);
+                }
+                Some(FormatDescription::Verifier(Some(name))) => {
+                    data["verifier"] = name.into();
+                }
+                Some(FormatDescription::Verifier(None)) | None => (),
+            }
+            data
+        }
+        Schema::Array(schema) => array_schema_to_json(
+            schema,
+            schema_to_json(schema.items),
+            "minLength",
+            "maxLength",
+        ),
+        Schema::Object(schema) => object_schema_to_json(schema),
+        Schema::AllOf(schema) => object_schema_to_json(schema),
+        Schema::OneOf(schema) => one_of_schema_to_json(schema),
+    }
+}
+
+// The simple types are described the same way in both formats.
+
+fn boolean_schema_to_json(schema: &BooleanSchema) -> Value {
+    let mut data = json!({ "type": "boolean", "description": translate(schema.description) });
+    if let Some(default) = schema.default {
+        data["default"] = default.into();
+    }
+    data
+}
+
+fn integer_schema_to_json(schema: &IntegerSchema) -> Value {
+    let mut data = json!({ "type": "integer", "description": translate(schema.description) });
+    if let Some(minimum) = schema.minimum {
+        data["minimum"] = minimum.into();
+    }
+    if let Some(maximum) = schema.maximum {
+        data["maximum"] = maximum.into();
+    }
+    if let Some(default) = schema.default {
+        data["default"] = default.into();
+    }
+    data
+}
+
+fn number_schema_to_json(schema: &NumberSchema) -> Value {
+    let mut data = json!({ "type": "number", "description": translate(schema.description) });
+    if let Some(minimum) = schema.minimum {
+        data["minimum"] = minimum.into();
+    }
+    if let Some(maximum) = schema.maximum {
+        data["maximum"] = maximum.into();
+    }
+    if let Some(default) = schema.default {
+        data["default"] = default.into();
+    }
+    data
+}
+
+/// The formats only differ in the names of the length limits and the description of the items.
+fn array_schema_to_json(schema: &ArraySchema, items: Value, min_key: &str, max_key: &str) -> Value {
+    let mut data = json!({
+        "type": "array",
+        "description": translate(schema.description),
+        "items": items,
+    });
+    if let Some(min_length) = schema.min_length {
+        data[min_key] = min_length.into();
+    }
+    if let Some(max_length) = schema.max_length {
+        data[max_key] = max_length.into();
+    }
+    if schema.unique_items {
+        data["uniqueItems"] = true.into();
+    }
+    data
+}
+
+fn object_schema_to_json(schema: &dyn ObjectSchemaType) -> Value {
+    json!({
+        "type": "object",
+        "description": translate(schema.description()),
+        "additionalProperties": schema.additional_properties(),
+        "properties": object_properties_to_json(schema),
+    })
+}
+
+fn one_of_schema_to_json(schema: &OneOfSchema) -> Value {
+    let variants: serde_json::Map<String, Value> = schema
+        .list
+        .iter()
+        .map(|(name, variant)| (name.to_string(), schema_to_json(variant)))
+        .collect();
+    json!({
+        "type": "object",
+        "description": translate(schema.description),
+        "typeProperty": schema.type_property(),
+        "variants": variants,
+    })
+}
+
+/// Convert a parameter schema into a JSON value, see [`schema_to_json`].
+pub fn parameter_schema_to_json(schema: ParameterSchema) -> Value {
+    match schema {
+        ParameterSchema::Object(schema) => object_schema_to_json(schema),
+        ParameterSchema::AllOf(schema) => object_schema_to_json(schema),
+        ParameterSchema::OneOf(schema) => one_of_schema_to_json(schema),
+    }
+}
+
+/// Convert a return type into a JSON value, see [`schema_to_json`].
+///
+/// Raw return data is described by its content type instead of a schema.
+pub fn return_type_to_json(returns: &ReturnType) -> Value {
+    if let Some(content_type) = returns.content_type {
+        return json!({ "type": "raw", "contentType": content_type });
+    }
+
+    let mut data = schema_to_json(returns.schema);
+    if returns.optional {
+        data["optional"] = true.into();
+    }
+    data
+}
+
+/// Convert a schema into a standard JSON Schema (draft 2020-12, as used by OpenAPI 3.1), see
+/// [`Schema::to_json_schema`].
+pub(crate) fn schema_to_json_schema(schema: &Schema) -> Value {
+    let mut data = schema_to_json_schema_do(schema);
+    if let Some(example) = typed_example(schema) {
+        data["examples"] = json!([example]);
+    }
+    data
+}
+
+fn schema_to_json_schema_do(schema: &Schema) -> Value {
+    match schema {
+        Schema::Null => json!({ "type": "null" }),
+        Schema::Boolean(schema) => boolean_schema_to_json(schema),
+        Schema::Integer(schema) => integer_schema_to_json(schema),
+        Schema::Uint64(schema) => {
+            let mut data = json!({
+                "type": "integer",
+                "description": translate(schema.description),
+                "minimum": schema.minimum.unwrap_or(0),
+            });
+            if let Some(maximum) = schema.maximum {
+                data["maximum"] = maximum.into();
+            }
+            if let Some(default) = schema.default {
+                data["default"] = default.into();
+            }
+            data
+        }
+        Schema::Number(schema) => number_schema_to_json(schema),
+        Schema::String(schema) => string_schema_to_json_schema(schema),
+        Schema::Array(schema) => array_schema_to_json(
+            schema,
+            schema_to_json_schema(schema.items),
+            "minItems",
+            "maxItems",
+        ),
+        Schema::Object(schema) => object_schema_to_json_schema(schema),
+        Schema::AllOf(schema) => all_of_schema_to_json_schema(schema),
+        Schema::OneOf(schema) => one_of_schema_to_json_schema(schema),
+    }
+}
+
+/// Convert a parameter schema into a standard JSON Schema, see [`Schema::to_json_schema`].
+pub fn parameter_schema_to_json_schema(schema: ParameterSchema) -> Value {
+    match schema {
+        ParameterSchema::Object(schema) => object_schema_to_json_schema(schema),
+        ParameterSchema::AllOf(schema) => all_of_schema_to_json_schema(schema),
+        ParameterSchema::OneOf(schema) => one_of_schema_to_json_schema(schema),
+    }
+}
+
+fn all_of_schema_to_json_schema(schema: &AllOfSchema) -> Value {
+    // the members cannot forbid additional properties on their own, since the properties of the
+    // other members would be rejected
+    let list: Vec<Value> = schema
+        .list
+        .iter()
+        .map(|member| {
+            let mut data = schema_to_json_schema(member);
+            allow_additional_properties(&mut data);
+            data
+        })
+        .collect();
+    json!({
+        "type": "object",
+        "description": translate(schema.description),
+        "allOf": list,
+        "unevaluatedProperties": schema.additional_properties(),
+    })
+}
+
+fn one_of_schema_to_json_schema(schema: &OneOfSchema) -> Value {
+    // the variants do not contain the type property, so it is added with the variant name as
+    // only valid value
+    let list: Vec<Value> = schema
+        .list
+        .iter()
+        .map(|(name, variant)| {
+            let mut data = schema_to_json_schema(variant);
+            let mut type_property = schema_to_json_schema(schema.type_schema());
+            type_property["const"] = (*name).into();
+            data["properties"][schema.type_property()] = type_property;
+            match data["required"].as_array_mut() {
+                Some(required) => required.insert(0, schema.type_property().into()),
+                None => data["required"] = json!([schema.type_property()]),
+            }
+            data
+        })
+        .collect();
+    json!({
+        "type": "object",
+        "description": translate(schema.description),
+        "oneOf": list,
+        "discriminator": { "propertyName": schema.type_property() },
+    })
+}
+
+/// Remove the restrictions on additional properties of an `allOf` member, including those of
+/// the `oneOf` variants and `allOf` members it consists of.
+fn allow_additional_properties(data: &mut Value) {
+    let Some(data) = data.as_object_mut() else {
+        return;
+    };
+    data.remove("additionalProperties");
+    data.remove("unevaluatedProperties");
+    for key in ["oneOf", "allOf"] {
+        if let Some(Value::Array(list)) = data.get_mut(key) {
+            list.iter_mut().for_each(allow_additional_properties);
+        }
+    }
+}
+
+fn string_schema_to_json_schema(schema: &StringSchema) -> Value {
+    let mut description = translate(schema.description).into_owned();
+    let mut data = json!({ "type": "string" });
+    if let Some(default) = schema.default {
+        data["default"] = default.into();
+    }
+    // byte limits are converted to the character limits they imply, a character takes up to 4
+    // bytes in UTF-8
+    let min_length = match schema.length_unit {
+        LengthUnit::Chars => schema.min_length,
+        LengthUnit::Bytes => schema.min_length.map(|min_length| min_length.div_ceil(4)),
+    };
+    if let Some(min_length) = min_length {
+        data["minLength"] = min_length.into();
+    }
+    if let Some(max_length) = schema.max_length {
+        data["maxLength"] = max_length.into();
+    }
+    if schema.secret {
+        data["format"] = "password".into();
+    }
+    match schema.format.map(ApiStringFormat::describe) {
+        Some(FormatDescription::Enum(variants)) => {
+            data["enum"] = variants.iter().map(|entry| entry.value).collect();
+        }
+        Some(FormatDescription::Pattern(pattern)) => {
+            data["pattern"] = pattern.into();
+        }
+        Some(FormatDescription::PropertyString(subschema)) => {
+            // there is no standard way to describe the format, so the schema of the encoded
+            // object is added as extension
+            data["x-property-string"] = schema_to_json_schema(subschema);
+        }
+        Some(FormatDescription::Verifier(Some(name))) => {
+            description.push_str(&format!(
+                "\n\nThe value is checked by the verification function '{name}' on the server."
+            ));
+            data["format"] = name.into();
+        }
+        Some(FormatDescription::Verifier(None)) => {
+            description
+                .push_str("\n\nThe value is checked by a verification function on the server.");
+        }
+        None => (),
+    }
+    data["description"] = description.into();
+    data
+}
+
+fn object_schema_to_json_schema(schema: &dyn ObjectSchemaType) -> Value {
+    let mut properties = serde_json::Map::new();
+    let mut required = Vec::new();
+    for (name, optional, prop_schema) in schema.properties() {
+        properties.insert(name.to_string(), schema_to_json_schema(prop_schema));
+        if !*optional {
+            required.push(Value::from(*name));
+        }
+    }
+
+    let mut data = json!({
+        "type": "object",
+        "description": translate(schema.description()),
+        "properties": properties,
+        "additionalProperties": schema.additional_properties(),
+    });
+    if !required.is_empty() {
+        data["required"] = required.into();
+    }
+    data
+}
+
+#[test]
+fn test_schema_to_json() {
+    const MODE: Schema = StringSchema::new("Mode.")
+        .format(&ApiStringFormat::Enum(&[
+            EnumEntry::new("fast", "Fast."),
+            EnumEntry::new("safe", "Safe."),
+        ]))
+        .schema();
+
+    const OBJECT: ObjectSchema = ObjectSchema::new(
+        "Options.",
+        &[
+            (
+                "count",
+                true,
+                &IntegerSchema::new("Count.").minimum(1).default(3).schema(),
+            ),
+            ("mode", false, &MODE),
+            (
+                "tags",
+                true,
+                &ArraySchema::new("Tags.", &StringSchema::new("Tag.").schema())
+                    .max_length(4)
+                    .schema(),
+            ),
+        ],
+    );
+
+    assert_eq!(
+        schema_to_json(&OBJECT.schema()),
+        json!({
+            "type": "object",
+            "description": "Options.",
+            "additionalProperties": false,
+            "properties": {
+                "count": {
+                    "type": "integer",
+                    "description": "Count.",
+                    "minimum": 1,
+                    "default": 3,
+                    "optional": true,
+                },
+                "mode": {
+                    "type": "string",
+                    "description": "Mode.",
+                    "enum": ["fast", "safe"],
+                },
+                "tags": {
+                    "type": "array",
+                    "description": "Tags.",
+                    "items": { "type": "string", "description": "Tag." },
+                    "maxLength": 4,
+                    "optional": true,
+                },
+            },
+        }),
+    );
+
+    const OPTIONS: Schema = StringSchema::new("Options string.")
+        .format(&ApiStringFormat::PropertyString(&OBJECT.schema()))
+        .schema();
+    let data = schema_to_json(&OPTIONS);
+    assert_eq!(data["format"], schema_to_json(&OBJECT.schema()));
+
+    const NAME: Schema = StringSchema::new("Name.")
+        .min_length(2)
+        .max_length(63)
+        .schema();
+    assert_eq!(
+        schema_to_json(&NAME),
+        json!({ "type": "string", "description": "Name.", "minLength": 2, "maxLength": 63 }),
+    );
+
+    const LABEL: Schema = StringSchema::new("Label.")
+        .min_length(5)
+        .max_length(63)
+        .length_unit(LengthUnit::Bytes)
+        .schema();
+    assert_eq!(
+        schema_to_json(&LABEL),
+        json!({
+            "type": "string",
+            "description": "Label.",
+            "minLength": 2,
+            "minLengthBytes": 5,
+            "maxLength": 63,
+            "maxLengthBytes": 63,
+        }),
+    );
+}
+
+#[test]
+fn test_schema_to_json_schema() {
+    crate::const_regex! {
+        NAME_REGEX = r"^[a-z][a-z0-9-]*$";
+    }
+
+    fn verify_size(_value: &str) -> Result<(), Error> {
+        Ok(())
+    }
+
+    const NAME: Schema = StringSchema::new("Name.")
+        .format(&ApiStringFormat::Pattern(&NAME_REGEX))
+        .max_length(32)
+        .schema();
+    const SIZE: Schema = StringSchema::new("Size.")
+        .format(&ApiStringFormat::VerifyFn(verify_size))
+        .schema();
+    const KIND: Schema = StringSchema::new("Kind.")
+        .format(&ApiStringFormat::Enum(&[
+            EnumEntry::new("disk", "Disk."),
+            EnumEntry::new("net", "Network."),
+        ]))
+        .schema();
+
+    const DISK: Schema = ObjectSchema::new("Disk.", &[("size", false, &SIZE)]).schema();
+    const NET: Schema = ObjectSchema::new(
+        "Network.",
+        &[(
+            "rate",
+            true,
+            &Uint64Schema::new("Rate limit.").default(0).schema(),
+        )],
+    )
+    .schema();
+    const DEVICE: Schema = OneOfSchema::new(
+        "Device.",
+        &("kind", false, &KIND),
+        &[("disk", &DISK), ("net", &NET)],
+    )
+    .schema();
+
+    const LIMITS: ObjectSchema = ObjectSchema::new(
+        "Limits.",
+        &[(
+            "cpu",
+            true,
+            &NumberSchema::new("CPU limit.").minimum(0.0).schema(),
+        )],
+    );
+    const BASE: Schema = ObjectSchema::new(
+        "Base.",
+        &[
+            ("name", false, &NAME),
+            (
+                "password",
+                true,
+                &StringSchema::new("Password.").secret().schema(),
+            ),
+        ],
+    )
+    .schema();
+    const CONFIG: Schema = ObjectSchema::new(
+        "Config.",
+        &[
+            (
+                "devices",
+                true,
+                &ArraySchema::new("Devices.", &DEVICE)
+                    .max_length(8)
+                    .unique_items(true)
+                    .schema(),
+            ),
+            (
+                "enabled",
+                true,
+                &BooleanSchema::new("Enabled.").default(true).schema(),
+            ),
+            (
+                "limits",
+                true,
+                &StringSchema::new("Limits.")
+                    .format(&ApiStringFormat::PropertyString(&LIMITS.schema()))
+                    .schema(),
+            ),
+            (
+                "priority",
+                false,
+                &IntegerSchema::new("Priority.")
+                    .minimum(-10)
+                    .maximum(10)
+                    .example("5")
+                    .schema(),
+            ),
+        ],
+    )
+    .schema();
+    const SCHEMA: Schema = AllOfSchema::new("Parameters.", &[&BASE, &CONFIG]).schema();
+
+    assert_eq!(
+        SCHEMA.to_json_schema(),
+        json!({
+            "type": "object",
+            "description": "Parameters.",
+            "unevaluatedProperties": false,
+            "allOf": [
+                {
+                    "type": "object",
+                    "description": "Base.",
+                    "properties": {
+                        "name": {
+                            "type": "string",
+                            "description": "Name.",
+                            "pattern": "^[a-z][a-z0-9-]*$",
+                            "maxLength": 32,
+                        },
+                        "password": {
+                            "type": "string",
+                            "description": "Password.",
+                            "format": "password",
+                        },
+                    },
+                    "required": ["name"],
+                },
+                {
+                    "type": "object",
+                    "description": "Config.",
+                    "properties": {
+                        "devices": {
+                            "type": "array",
+                            "description": "Devices.",
+                            "maxItems": 8,
+                            "uniqueItems": true,
+                            "items": {
+                                "type": "object",
+                                "description": "Device.",
+                                "discriminator": { "propertyName": "kind" },
+                                "oneOf": [
+                                    {
+                                        "type": "object",
+                                        "description": "Disk.",
+                                        "additionalProperties": false,
+                                        "properties": {
+                                            "kind": {
+                                                "type": "string",
+                                                "description": "Kind.",
+                                                "enum": ["disk", "net"],
+                                                "const": "disk",
+                                            },
+                                            "size": {
+                                                "type": "string",
+                                                "description": "Size.\n\nThe value is checked by a verification function on the server.",
+                                            },
+                                        },
+                                        "required": ["kind", "size"],
+                                    },
+                                    {
+                                        "type": "object",
+                                        "description": "Network.",
+                                        "additionalProperties": false,
+                                        "properties": {
+                                            "kind": {
+                                                "type": "string",
+                                                "description": "Kind.",
+                                                "enum": ["disk", "net"],
+                                                "const": "net",
+                                            },
+                                            "rate": {
+                                                "type": "integer",
+                                                "description": "Rate limit.",
+                                                "minimum": 0,
+                                                "default": 0,
+                                            },
+                                        },
+                                        "required": ["kind"],
+                                    },
+                                ],
+                            },
+                        },
+                        "enabled": {
+                            "type": "boolean",
+                            "description": "Enabled.",
+                            "default": true,
+                        },
+                        "limits": {
+                            "type": "string",
+                            "description": "Limits.",
+                            "x-property-string": {
+                                "type": "object",
+                                "description": "Limits.",
+                                "additionalProperties": false,
+                                "properties": {
+                                    "cpu": {
+                                        "type": "number",
+                                        "description": "CPU limit.",
+                                        "minimum": 0.0,
+                                    },
+                                },
+                            },
+                        },
+                        "priority": {
+                            "type": "integer",
+                            "description": "Priority.",
+                            "minimum": -10,
+                            "maximum": 10,
+                            "examples": [5],
+                        },
+                    },
+                    "required": ["priority"],
+                },
+            ],
+        }),
+    );
+
+    // named verification functions are given as format
+    crate::api_string_verifier! {
+        /// Test verifier.
+        VERIFY_SIZE = "disk-size", verify_size;
+    }
+    const NAMED_SIZE: Schema = StringSchema::new("Size.")
+        .format(&ApiStringFormat::Verifier(&VERIFY_SIZE))
+        .schema();
+    assert_eq!(
+        NAMED_SIZE.to_json_schema(),
+        json!({
+            "type": "string",
+            "description": "Size.\n\nThe value is checked by the verification function 'disk-size' on the server.",
+            "format": "disk-size",
+        }),
+    );
+}
+
+#[test]
+fn test_all_of_json_schema_nested() {
+    const NAME: Schema = StringSchema::new("Name.").schema();
+    const COUNT: Schema = IntegerSchema::new("Count.").schema();
+    const KIND: Schema = StringSchema::new("Kind.").schema();
+
+    const DISK: Schema = ObjectSchema::new("Disk.", &[("name", false, &NAME)]).schema();
+    const DEVICE: Schema =
+        OneOfSchema::new("Device.", &("kind", false, &KIND), &[("disk", &DISK)]).schema();
+    const COUNTER: Schema = ObjectSchema::new("Counter.", &[("count", true, &COUNT)]).schema();
+    const INNER: Schema = AllOfSchema::new("Inner.", &[&COUNTER]).schema();
+    const SCHEMA: Schema = AllOfSchema::new("Outer.", &[&DEVICE, &INNER]).schema();
+
+    let data = SCHEMA.to_json_schema();
+    assert_eq!(data["unevaluatedProperties"], false);
+
+    // only the outermost schema may restrict the properties of all members
+    let device = &data["allOf"][0];
+    assert!(device.get("additionalProperties").is_none());
+    assert!(device["oneOf"][0].get("additionalProperties").is_none());
+    assert_eq!(device["oneOf"][0]["description"], "Disk.");
+
+    let inner = &data["allOf"][1];
+    assert!(inner.get("unevaluatedProperties").is_none());
+    assert!(inner["allOf"][0].get("additionalProperties").is_none());
+    assert_eq!(inner["allOf"][0]["description"], "Counter.");
+}
@@ -0,0 +1,148 @@
+//! Translation of schema descriptions and validation errors.
+//!
+//! Descriptions are static English texts, so a translator registered with
+//! [`set_schema_translator`] maps them to the translated text. It is used by the documentation
+//! generators in [`format`](crate::format) and for the messages of a
+//! [`ParameterError`](crate::ParameterError). Without translator, the English texts are used.
+//!
+//! The built-in validation errors are [`ValidationError`]s, which are translated by the
+//! [identifier](ValidationErrorKind::id) of their kind instead of their English message.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::sync::{Arc, RwLock};
+
+use anyhow::Error;
+
+/// Maps an English text, or a [`ValidationErrorKind::id`], to its translation.
+pub type SchemaTranslator = Box<dyn Fn(&'static str) -> Cow<'static, str> + Send + Sync>;
+
+type SharedTranslator = Arc<dyn Fn(&'static str) -> Cow<'static, str> + Send + Sync>;
+
+static SCHEMA_TRANSLATOR: RwLock<Option<SharedTranslator>> = RwLock::new(None);
+
+/// Set the translator for schema descriptions and validation errors, replacing the previous one.
+///
+/// The translator should return its input for texts it cannot translate.
+pub fn set_schema_translator(translator: SchemaTranslator) {
+    *SCHEMA_TRANSLATOR.write().unwrap() = Some(Arc::from(translator));
+}
+
+/// Translate `text` with the registered translator, or return it as is.
+pub fn translate(text: &'static str) -> Cow<'static, str> {
+    // not called with the lock held, the translator may use schemas or replace itself
+    let translator = SCHEMA_TRANSLATOR.read().unwrap().clone();
+    match translator {
+        Some(translator) => translator(text),
+        None => Cow::Borrowed(text),
+    }
+}
+
+/// Get the message of an error, translated if it is a [`ValidationError`].
+pub fn translate_error(err: &Error) -> String {
+    match err.downcast_ref::<ValidationError>() {
+        Some(err) => err.translated(),
+        None => err.to_string(),
+    }
+}
+
+/// The kinds of built-in validation errors.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ValidationErrorKind {
+    /// A string is shorter than its minimum length, with the `{min}` length and its `{unit}`.
+    TooShort,
+    /// A string is longer than its maximum length, with the `{max}` length and its `{unit}`.
+    TooLong,
+    /// A number is below its `{min}`imum, with the `{value}`.
+    BelowMinimum,
+    /// A number is above its `{max}`imum, with the `{value}`.
+    AboveMaximum,
+    /// A string does not match the pattern of its format.
+    PatternMismatch,
+}
+
+impl ValidationErrorKind {
+    /// The stable identifier, passed to the translator to get the translated message template.
+    ///
+    /// The template contains the placeholders listed for the kind, e.g. `{max}`.
+    pub fn id(self) -> &'static str {
+        match self {
+            Self::TooShort => "too-short",
+            Self::TooLong => "too-long",
+            Self::BelowMinimum => "below-minimum",
+            Self::AboveMaximum => "above-maximum",
+            Self::PatternMismatch => "pattern-mismatch",
+        }
+    }
+
+    fn template(self) -> &'static str {
+        match self {
+            Self::TooShort => "value must be at least {min} {unit} long",
+            Self::TooLong => "value may only be {max} {unit} long",
+            Self::BelowMinimum => "value must have a minimum value of {min} (got {value})",
+            Self::AboveMaximum => "value must have a maximum value of {max} (got {value})",
+            Self::PatternMismatch => "value does not match the regex pattern",
+        }
+    }
+}
+
+/// A built-in validation error, displayed as English message.
+#[derive(Clone, Debug)]
+pub struct ValidationError {
+    kind: ValidationErrorKind,
+    args: Vec<(&'static str, String)>,
+}
+
+impl ValidationError {
+    pub(crate) fn new(
+        kind: ValidationErrorKind,
+        args: &[(&'static str, &dyn fmt::Display)],
+    ) -> Self {
+        Self {
+            kind,
+            args: args
+                .iter()
+                .map(|(name, value)| (*name, value.to_string()))
+                .collect(),
+        }
+    }
+
+    pub fn kind(&self) -> ValidationErrorKind {
+        self.kind
+    }
+
+    /// The value of a placeholder of the message.
+    pub fn arg(&self, name: &str) -> Option<&str> {
+        self.args
+            .iter()
+            .find(|(arg, _)| *arg == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The translated message, or the English one if there is no translation.
+    pub fn translated(&self) -> String {
+        let id = self.kind.id();
+        let template = translate(id);
+        if template == id {
+            self.to_string()
+        } else {
+            self.fill(&template)
+        }
+    }
+
+    fn fill(&self, template: &str) -> String {
+        let mut message = template.to_string();
+        for (name, value) in &self.args {
+            message = message.replace(&format!("{{{name}}}"), value);
+        }
+        message
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.fill(self.kind.template()))
+    }
+}
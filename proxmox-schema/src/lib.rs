@@ -27,7 +27,17 @@ pub mod property_string;
 mod schema;
 pub use schema::*;
 
+mod tristate;
+pub use tristate::Tristate;
+
+pub mod i18n;
+pub use i18n::{set_schema_translator, ValidationError, ValidationErrorKind};
+
+pub mod schema_compat;
+
 pub mod upid;
 
+pub mod variant_template;
+
 #[cfg(feature = "api-types")]
 pub mod api_types;
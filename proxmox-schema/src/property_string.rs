@@ -3,12 +3,14 @@
 //! strings.
 
 use std::borrow::Cow;
+use std::collections::BTreeMap;
 use std::fmt;
 use std::mem;
 
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
-use crate::de::Error;
+use crate::de::{Error, UnknownFields, UnknownFieldsReport};
 use crate::schema::ApiType;
 
 /// Iterate over the `key=value` pairs of a property string.
@@ -31,7 +33,7 @@ impl<'a> Iterator for PropertyIterator<'a> {
 
     fn next(&mut self) -> Option<Self::Item> {
         Some(match next_property(self.data)? {
-            Ok((key, value, data)) => {
+            Ok((key, value, _quoted, data)) => {
                 self.data = data;
                 Ok((key, value))
             }
@@ -40,9 +42,11 @@ impl<'a> Iterator for PropertyIterator<'a> {
     }
 }
 
-type NextProperty<'a> = (Option<&'a str>, Cow<'a, str>, &'a str);
+type NextProperty<'a> = (Option<&'a str>, Cow<'a, str>, bool, &'a str);
 
-/// Returns an optional key, its value, and the remainder of `data`.
+/// Returns an optional key, its value, whether the value was quoted, and the remainder of `data`.
+///
+/// A quoted empty value (`key=""`) is explicitly set, unlike an empty one (`key=`).
 pub(crate) fn next_property(mut data: &str) -> Option<Result<NextProperty, Error>> {
     if data.is_empty() {
         return None;
@@ -63,7 +67,7 @@ pub(crate) fn next_property(mut data: &str) -> Option<Result<NextProperty, Error
                 Some(pos) => ascii_split_off(&mut data, pos),
                 None => mem::take(&mut data),
             };
-            return Some(Ok((key, Cow::Borrowed(value), data)));
+            return Some(Ok((key, Cow::Borrowed(value), false, data)));
         }
 
         key
@@ -82,7 +86,7 @@ pub(crate) fn next_property(mut data: &str) -> Option<Result<NextProperty, Error
         }
     }
 
-    Some(Ok((key, value, data)))
+    Some(Ok((key, value, true, data)))
 }
 
 impl<'a> std::iter::FusedIterator for PropertyIterator<'a> {}
@@ -151,8 +155,26 @@ fn parse_quoted_string<'s>(data: &'_ mut &'s str) -> Result<Cow<'s, str>, Error>
     Ok(Cow::Owned(unsafe { String::from_utf8_unchecked(out) }))
 }
 
+/// Check if a property value must be quoted to be parsed back.
+///
+/// This is the case for empty values, values containing a separator and values containing a
+/// character which must be escaped.
+pub(crate) fn needs_quoting(value: &str) -> bool {
+    value.is_empty() || value.contains([',', '=', '"', '\\', '\n'])
+}
+
+/// Write a property value, quoted if [`needs_quoting`] says so.
+pub(crate) fn write_value<T: fmt::Write>(value: &str, out: &mut T) -> fmt::Result {
+    if needs_quoting(value) {
+        out.write_char('"')?;
+        quote(value, out)?;
+        out.write_char('"')
+    } else {
+        out.write_str(value)
+    }
+}
+
 /// Counterpart to `parse_quoted_string`, only supporting the above-supported escape sequences.
-/// Returns `true`
 pub(crate) fn quote<T: fmt::Write>(s: &str, out: &mut T) -> fmt::Result {
     for b in s.chars() {
         match b {
@@ -225,6 +247,36 @@ impl<T: Serialize + ApiType> PropertyString<T> {
     pub fn to_property_string(&self) -> Result<String, Error> {
         print(&self.0)
     }
+
+    /// Get the property string as JSON string value.
+    pub fn to_value(&self) -> Result<Value, Error> {
+        Ok(Value::String(self.to_property_string()?))
+    }
+}
+
+impl<T> PropertyString<T>
+where
+    T: ApiType + for<'de> Deserialize<'de>,
+{
+    /// Parse a property string from a JSON string value.
+    pub fn try_from_value(value: &Value) -> Result<Self, Error> {
+        match value {
+            Value::String(s) => s.parse(),
+            _ => Err(Error::msg(format!(
+                "expected a property string, got {value}"
+            ))),
+        }
+    }
+}
+
+/// Formats the property string, see [`to_property_string`](PropertyString::to_property_string)
+/// for a fallible variant.
+///
+/// Formatting fails if the value does not match its schema.
+impl<T: Serialize + ApiType> fmt::Display for PropertyString<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.to_property_string().map_err(|_| fmt::Error)?)
+    }
 }
 
 impl<T> From<T> for PropertyString<T> {
@@ -364,6 +416,172 @@ where
     T::deserialize(crate::de::SchemaDeserializer::new(value, schema))
 }
 
+/// Deserialize a value from a property string with the given handling of unknown properties.
+///
+/// Returns the skipped unknown properties along with the value, see [`print_with_preserved`]
+/// for writing preserved properties back out.
+pub fn parse_with_unknown_fields<T>(
+    value: &str,
+    schema: &'static crate::Schema,
+    mode: UnknownFields,
+) -> Result<(T, UnknownFieldsReport), Error>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    crate::de::SchemaDeserializer::new(value, schema).deserialize_with_unknown_fields(mode)
+}
+
+/// Serialize a value as a property string, followed by the `preserved` unknown properties of
+/// a [`parse_with_unknown_fields`] call using [`UnknownFields::Preserve`].
+pub fn print_with_preserved<T: Serialize + ApiType>(
+    value: &T,
+    preserved: &BTreeMap<String, String>,
+) -> Result<String, Error> {
+    let mut out = print(value)?;
+    for (key, value) in preserved {
+        push_property(&mut out, key, value);
+    }
+    Ok(out)
+}
+
+/// Iterate over the properties like [`PropertyIterator`], along with their original text.
+fn raw_properties(
+    mut data: &str,
+) -> impl Iterator<Item = Result<(Option<&str>, Cow<str>, &str), Error>> {
+    std::iter::from_fn(move || {
+        Some(next_property(data)?.map(|(key, value, _quoted, rest)| {
+            let raw = &data[..(data.len() - rest.len())];
+            let raw = raw.strip_suffix(',').unwrap_or(raw);
+            data = rest;
+            (key, value, raw)
+        }))
+    })
+}
+
+fn push_property(out: &mut String, key: &str, value: &str) {
+    if !out.is_empty() {
+        out.push(',');
+    }
+    out.push_str(key);
+    out.push('=');
+    let _ = write_value(value, out);
+}
+
+/// Get the value of a single `key` of a property string, without parsing the others.
+///
+/// Values without a key (for a schema's default key) are never matched. Invalid quoting fails
+/// like in the full parser, but only up to the requested key.
+pub fn get_value<'a>(raw: &'a str, key: &str) -> Result<Option<Cow<'a, str>>, Error> {
+    for property in PropertyIterator::new(raw) {
+        if let (Some(k), value) = property? {
+            if k == key {
+                return Ok(Some(value));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Set (or remove with `None`) the value of a single `key` of a property string.
+///
+/// All other properties keep their original text, a new key is appended at the end.
+pub fn set_value(raw: &str, key: &str, value: Option<&str>) -> Result<String, Error> {
+    let mut out = String::new();
+    let mut found = false;
+    for property in raw_properties(raw) {
+        let (k, _, text) = property?;
+        if k == Some(key) {
+            if let Some(value) = value.filter(|_| !found) {
+                push_property(&mut out, key, value);
+            }
+            found = true;
+        } else {
+            if !out.is_empty() {
+                out.push(',');
+            }
+            out.push_str(text);
+        }
+    }
+    if let (false, Some(value)) = (found, value) {
+        push_property(&mut out, key, value);
+    }
+    Ok(out)
+}
+
+/// The properties of a property string not known to the schema, see [`parse_partial`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct UnknownKeys {
+    /// The keys along with the original text of their property.
+    properties: Vec<(String, String)>,
+}
+
+impl UnknownKeys {
+    pub fn is_empty(&self) -> bool {
+        self.properties.is_empty()
+    }
+
+    /// The unknown keys in their original order.
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.properties.iter().map(|(key, _)| key.as_str())
+    }
+
+    /// The original `key=value` text of an unknown key.
+    pub fn raw(&self, key: &str) -> Option<&str> {
+        self.properties
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, raw)| raw.as_str())
+    }
+}
+
+/// Deserialize the properties known to the schema of `T`, returning the others untouched.
+///
+/// Contrary to [`parse_with_unknown_fields`], the unknown properties keep their original text,
+/// so [`print_partial`] writes them back exactly as they were, e.g. for properties of newer
+/// versions. Unknown properties of nested property strings are not handled.
+pub fn parse_partial<T>(raw: &str) -> Result<(T, UnknownKeys), Error>
+where
+    T: for<'de> Deserialize<'de> + ApiType,
+{
+    let schema = T::API_SCHEMA
+        .any_object()
+        .ok_or_else(|| Error::msg("partial parsing requires an object schema"))?;
+
+    let mut known = String::new();
+    let mut unknown = UnknownKeys::default();
+    for property in raw_properties(raw) {
+        match property? {
+            (Some(key), _, text) if schema.lookup(key).is_none() => {
+                unknown.properties.push((key.to_string(), text.to_string()));
+            }
+            (_, _, text) => {
+                if !known.is_empty() {
+                    known.push(',');
+                }
+                known.push_str(text);
+            }
+        }
+    }
+
+    Ok((parse(&known)?, unknown))
+}
+
+/// Serialize a value as a property string, followed by the `unknown` properties of a
+/// [`parse_partial`] call in their original text.
+pub fn print_partial<T: Serialize + ApiType>(
+    value: &T,
+    unknown: &UnknownKeys,
+) -> Result<String, Error> {
+    let mut out = print(value)?;
+    for (_, raw) in &unknown.properties {
+        if !out.is_empty() {
+            out.push(',');
+        }
+        out.push_str(raw);
+    }
+    Ok(out)
+}
+
 #[cfg(test)]
 mod test {
     use serde::{Deserialize, Serialize};
@@ -468,4 +686,281 @@ mod test {
 
         Ok(())
     }
+
+    impl ApiType for Weighted {
+        const API_SCHEMA: Schema = ObjectSchema::new(
+            "An object with ordering weights",
+            &[
+                // MUST BE SORTED
+                ("comment", true, &StringSchema::new("comment").schema()),
+                ("count", false, &IntegerSchema::new("count").schema()),
+                ("name", false, &StringSchema::new("name").schema()),
+                ("type", false, &StringSchema::new("type").schema()),
+            ],
+        )
+        .property_weights(&[("type", -2), ("name", -1)])
+        .schema();
+    }
+
+    #[derive(Debug, Deserialize, Serialize, PartialEq, Eq)]
+    pub struct Weighted {
+        count: u32,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        comment: Option<String>,
+        name: String,
+        #[serde(rename = "type")]
+        ty: String,
+    }
+
+    #[test]
+    fn test_property_weights() -> Result<(), super::Error> {
+        let obj = Weighted {
+            count: 3,
+            comment: Some("text".to_string()),
+            name: "foo".to_string(),
+            ty: "bar".to_string(),
+        };
+
+        let s = super::print(&obj)?;
+        assert_eq!(s, "type=bar,name=foo,count=3,comment=text");
+
+        let deserialized: Weighted = super::parse(&s).expect("failed to parse property string");
+        assert_eq!(obj, deserialized, "deserialized does not equal original");
+
+        Ok(())
+    }
+
+    impl ApiType for Disk {
+        const API_SCHEMA: Schema = ObjectSchema::new(
+            "A disk with a default key",
+            &[
+                // MUST BE SORTED
+                ("backup", true, &BooleanSchema::new("backup").schema()),
+                ("model", false, &StringSchema::new("model").schema()),
+                ("size", false, &IntegerSchema::new("size").schema()),
+            ],
+        )
+        .default_key("model")
+        .property_weights(&[("model", -1)])
+        .schema();
+    }
+
+    #[derive(Debug, Deserialize, Serialize, PartialEq, Eq)]
+    pub struct Disk {
+        model: String,
+        size: u32,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        backup: Option<bool>,
+    }
+
+    #[test]
+    fn test_property_string_wrapper() -> Result<(), super::Error> {
+        use serde_json::Value;
+
+        use super::PropertyString;
+
+        let disk: PropertyString<Disk> = "virtio,size=3".parse()?;
+        assert_eq!(disk.model, "virtio");
+        assert_eq!(disk.size, 3);
+        assert_eq!(disk.backup, None);
+        assert_eq!(disk.to_string(), "virtio,size=3");
+
+        // booleans may be given in the short form
+        let disk: PropertyString<Disk> = "size=3,backup=1,model=scsi".parse()?;
+        assert_eq!(disk.backup, Some(true));
+        assert_eq!(disk.to_string(), "scsi,size=3,backup=true");
+        assert_eq!(disk.to_string().parse::<PropertyString<Disk>>()?, disk);
+        let disk: PropertyString<Disk> = "scsi,size=3,backup=0".parse()?;
+        assert_eq!(disk.backup, Some(false));
+
+        let value = disk.to_value()?;
+        assert_eq!(value, Value::from("scsi,size=3,backup=false"));
+        assert_eq!(PropertyString::<Disk>::try_from_value(&value)?, disk);
+        assert!(PropertyString::<Disk>::try_from_value(&Value::from(3)).is_err());
+
+        let err = "virtio,size=3,backup=maybe"
+            .parse::<PropertyString<Disk>>()
+            .unwrap_err();
+        assert_eq!(err.path(), Some("backup"));
+        assert_eq!(
+            err.to_string(),
+            "backup: not a boolean: \"maybe\" - expected one of 1, on, yes, true, 0, off, no, false"
+        );
+
+        // errors in nested property strings carry the full path
+        let err =
+            super::parse::<Object>(r#"name=a,count=1,nested="name=b,third=\"name=c,count=x\"""#)
+                .unwrap_err();
+        assert_eq!(err.path(), Some("nested.third.count"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unknown_fields() -> Result<(), super::Error> {
+        use crate::de::{UnknownFields, UnknownFieldsReport};
+
+        let input = r#"name=a,future=1,count=2,nested="name=b,later=x",note="a,\"b\"""#;
+
+        let err = super::parse::<Object>(input).unwrap_err();
+        assert_eq!(err.to_string(), r#"unknown key "future""#);
+        let err = super::parse_with_unknown_fields::<Object>(
+            input,
+            &Object::API_SCHEMA,
+            UnknownFields::Reject,
+        )
+        .unwrap_err();
+        assert_eq!(err.to_string(), r#"unknown key "future""#);
+
+        let (obj, report) = super::parse_with_unknown_fields::<Object>(
+            input,
+            &Object::API_SCHEMA,
+            UnknownFields::IgnoreWithWarning,
+        )?;
+        assert_eq!(obj.name, "a");
+        assert_eq!(obj.count, 2);
+        assert_eq!(obj.nested.as_ref().unwrap().name, "b");
+        assert_eq!(report.ignored, ["future", "nested.later", "note"]);
+        assert!(report.preserved.is_empty());
+
+        let (obj, report) = super::parse_with_unknown_fields::<Object>(
+            input,
+            &Object::API_SCHEMA,
+            UnknownFields::Preserve,
+        )?;
+        assert_eq!(report.ignored, ["nested.later"]);
+        assert_eq!(report.preserved["future"], "1");
+        assert_eq!(report.preserved["note"], r#"a,"b""#);
+
+        // preserved properties survive a rewrite
+        let printed = super::print_with_preserved(&obj, &report.preserved)?;
+        assert_eq!(
+            printed,
+            r#"name=a,count=2,nested="name=b",future=1,note="a,\"b\"""#
+        );
+        let (reparsed, reparsed_report) = super::parse_with_unknown_fields::<Object>(
+            &printed,
+            &Object::API_SCHEMA,
+            UnknownFields::Preserve,
+        )?;
+        assert_eq!(reparsed, obj);
+        assert_eq!(reparsed_report.preserved, report.preserved);
+
+        // the mode does not leak into later deserializations
+        assert!(super::parse::<Object>(input).is_err());
+        let (_obj, report) = super::parse_with_unknown_fields::<Object>(
+            "name=a,count=2",
+            &Object::API_SCHEMA,
+            UnknownFields::Preserve,
+        )?;
+        assert_eq!(report, UnknownFieldsReport::default());
+        assert!(report.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_partial_parsing() -> Result<(), super::Error> {
+        use super::{get_value, parse_partial, print_partial, set_value};
+
+        let input = r#"virtio,size=3,future="a,\"b\"",later=x"#;
+
+        assert_eq!(get_value(input, "size")?.as_deref(), Some("3"));
+        assert_eq!(get_value(input, "future")?.as_deref(), Some(r#"a,"b""#));
+        assert_eq!(get_value(input, "model")?, None);
+        assert_eq!(get_value(input, "missing")?, None);
+        assert!(get_value(r#"future="a,size=4"#, "size").is_err());
+
+        let (mut disk, unknown) = parse_partial::<Disk>(input)?;
+        assert_eq!(disk.model, "virtio");
+        assert_eq!(disk.size, 3);
+        assert_eq!(unknown.keys().collect::<Vec<_>>(), ["future", "later"]);
+        assert_eq!(unknown.raw("future"), Some(r#"future="a,\"b\"""#));
+
+        // unknown keys are written back in their original text
+        disk.size = 4;
+        assert_eq!(
+            print_partial(&disk, &unknown)?,
+            r#"virtio,size=4,future="a,\"b\"",later=x"#
+        );
+
+        assert_eq!(
+            set_value(input, "size", Some("8"))?,
+            r#"virtio,size=8,future="a,\"b\"",later=x"#
+        );
+        assert_eq!(
+            set_value(input, "later", Some("y,z"))?,
+            r#"virtio,size=3,future="a,\"b\"",later="y,z""#
+        );
+        assert_eq!(set_value(input, "future", None)?, "virtio,size=3,later=x");
+        assert_eq!(set_value("virtio", "backup", Some("1"))?, "virtio,backup=1");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_quoted_values() -> Result<(), super::Error> {
+        let obj = Weighted {
+            count: 1,
+            comment: Some(String::new()),
+            name: "a=b".to_string(),
+            ty: "one, two".to_string(),
+        };
+        let s = super::print(&obj)?;
+        assert_eq!(s, r#"type="one, two",name="a=b",count=1,comment="""#);
+        assert_eq!(super::parse::<Weighted>(&s)?, obj);
+
+        // the value of the default key is not mistaken for a key
+        let disk = Disk {
+            model: "key=value".to_string(),
+            size: 1,
+            backup: None,
+        };
+        let s = super::print(&disk)?;
+        assert_eq!(s, r#""key=value",size=1"#);
+        assert_eq!(super::parse::<Disk>(&s)?, disk);
+
+        Ok(())
+    }
+
+    /// Strings biased towards separators, quotes and backslashes.
+    fn value_strategy() -> impl proptest::strategy::Strategy<Value = String> {
+        proptest::string::string_regex(r#"([a-z ,;="\\\n]|é){0,12}"#).unwrap()
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn proptest_round_trip(
+            comment in proptest::option::of(value_strategy()),
+            name in value_strategy(),
+            ty in value_strategy(),
+        ) {
+            let obj = Weighted { count: 1, comment, name, ty };
+            let s = super::print(&obj).unwrap();
+            proptest::prop_assert_eq!(super::parse::<Weighted>(&s).unwrap(), obj, "{}", s);
+        }
+
+        #[test]
+        fn proptest_round_trip_default_key(model in value_strategy(), size: u32) {
+            let disk = Disk { model, size, backup: Some(true) };
+            let s = super::print(&disk).unwrap();
+            proptest::prop_assert_eq!(super::parse::<Disk>(&s).unwrap(), disk, "{}", s);
+        }
+
+        #[test]
+        fn proptest_round_trip_nested(name in value_strategy(), third in value_strategy()) {
+            let obj = Object {
+                name: name.clone(),
+                count: 1,
+                optional: None,
+                nested: Some(Nested {
+                    name,
+                    count: vec![1, 2],
+                    third: Some(Third { name: third, count: 2 }),
+                }),
+            };
+            let s = super::print(&obj).unwrap();
+            proptest::prop_assert_eq!(super::parse::<Object>(&s).unwrap(), obj, "{}", s);
+        }
+    }
 }
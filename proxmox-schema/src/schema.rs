@@ -4,12 +4,13 @@
 //! completely static API definitions that can be included within the programs read-only text
 //! segment.
 
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 
 use anyhow::{bail, format_err, Error};
 use serde_json::{json, Value};
 
+use crate::i18n::{translate, translate_error, ValidationError, ValidationErrorKind};
 use crate::ConstRegexPattern;
 
 /// Error type for schema validation
@@ -22,6 +23,19 @@ pub struct ParameterError {
     error_list: Vec<(String, Error)>,
 }
 
+/// A single error of a [`ParameterError`], see [`ParameterError::details`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParameterErrorDetail {
+    /// The name of the parameter.
+    pub name: String,
+    /// The message, translated if a [translator](crate::set_schema_translator) is registered.
+    pub message: String,
+    /// The English message.
+    pub english: String,
+    /// The identifier of a built-in validation error, see [`ValidationErrorKind::id`].
+    pub id: Option<&'static str>,
+}
+
 /// Like anyhow's `format_err` but producing a `ParameterError`.
 #[macro_export]
 macro_rules! param_format_err {
@@ -93,6 +107,21 @@ impl ParameterError {
     pub(crate) fn from_list(error_list: Vec<(String, Error)>) -> Self {
         Self { error_list }
     }
+
+    /// The errors with their translated and English messages, e.g. for API clients.
+    pub fn details(&self) -> Vec<ParameterErrorDetail> {
+        self.error_list
+            .iter()
+            .map(|(name, err)| ParameterErrorDetail {
+                name: name.clone(),
+                message: translate_error(err),
+                english: err.to_string(),
+                id: err
+                    .downcast_ref::<ValidationError>()
+                    .map(|err| err.kind().id()),
+            })
+            .collect()
+    }
 }
 
 impl fmt::Display for ParameterError {
@@ -102,13 +131,14 @@ impl fmt::Display for ParameterError {
         let mut msg = String::new();
 
         if !self.is_empty() {
+            msg.push_str(&translate("parameter verification failed"));
             if self.len() == 1 {
-                msg.push_str("parameter verification failed - ");
-                let _ = write!(msg, "'{}': {}", self.error_list[0].0, self.error_list[0].1);
+                let (name, err) = &self.error_list[0];
+                let _ = write!(msg, " - '{}': {}", name, translate_error(err));
             } else {
-                msg.push_str("parameter verification failed:\n");
+                msg.push_str(":\n");
                 for (name, err) in self.error_list.iter() {
-                    let _ = writeln!(msg, "- '{}': {}", name, err);
+                    let _ = writeln!(msg, "- '{}': {}", name, translate_error(err));
                 }
             }
         }
@@ -207,11 +237,15 @@ impl BooleanSchema {
     }
 
     /// Verify JSON value using a `BooleanSchema`.
+    ///
+    /// Strings are accepted if [`parse_boolean`] accepts them, use
+    /// [`Schema::normalize_booleans`] to convert them so that the value can be deserialized.
     pub fn verify_json(&self, data: &Value) -> Result<(), Error> {
-        if !data.is_boolean() {
-            bail!("Expected boolean value.");
+        match data {
+            Value::Bool(_) => Ok(()),
+            Value::String(value) => parse_boolean(value).map(drop),
+            _ => bail!("Expected boolean value."),
         }
-        Ok(())
     }
 }
 
@@ -226,6 +260,8 @@ pub struct IntegerSchema {
     pub maximum: Option<isize>,
     /// Optional default.
     pub default: Option<isize>,
+    /// Optional example value (used to generate documentation).
+    pub example: Option<&'static str>,
 }
 
 impl IntegerSchema {
@@ -235,6 +271,7 @@ impl IntegerSchema {
             default: None,
             minimum: None,
             maximum: None,
+            example: None,
         }
     }
 
@@ -243,6 +280,12 @@ impl IntegerSchema {
         self
     }
 
+    /// Set an example value, see [`verify_examples`].
+    pub const fn example(mut self, example: &'static str) -> Self {
+        self.example = Some(example);
+        self
+    }
+
     pub const fn minimum(mut self, minimum: isize) -> Self {
         self.minimum = Some(minimum);
         self
@@ -260,21 +303,19 @@ impl IntegerSchema {
     pub fn check_constraints(&self, value: isize) -> Result<(), Error> {
         if let Some(minimum) = self.minimum {
             if value < minimum {
-                bail!(
-                    "value must have a minimum value of {} (got {})",
-                    minimum,
-                    value
-                );
+                bail!(ValidationError::new(
+                    ValidationErrorKind::BelowMinimum,
+                    &[("min", &minimum), ("value", &value)],
+                ));
             }
         }
 
         if let Some(maximum) = self.maximum {
             if value > maximum {
-                bail!(
-                    "value must have a maximum value of {} (got {})",
-                    maximum,
-                    value
-                );
+                bail!(ValidationError::new(
+                    ValidationErrorKind::AboveMaximum,
+                    &[("max", &maximum), ("value", &value)],
+                ));
             }
         }
 
@@ -291,6 +332,94 @@ impl IntegerSchema {
     }
 }
 
+/// Data type to describe unsigned 64 bit integer values.
+///
+/// Unlike [`IntegerSchema`], which is based on `isize`, this covers the full `u64` range, e.g.
+/// for byte counts or inode numbers. The `#[api]` macro uses it for `u64` fields.
+#[derive(Debug)]
+#[cfg_attr(feature = "test-harness", derive(Eq, PartialEq))]
+pub struct Uint64Schema {
+    pub description: &'static str,
+    /// Optional minimum.
+    pub minimum: Option<u64>,
+    /// Optional maximum.
+    pub maximum: Option<u64>,
+    /// Optional default.
+    pub default: Option<u64>,
+    /// Optional example value (used to generate documentation).
+    pub example: Option<&'static str>,
+}
+
+impl Uint64Schema {
+    pub const fn new(description: &'static str) -> Self {
+        Uint64Schema {
+            description,
+            default: None,
+            minimum: None,
+            maximum: None,
+            example: None,
+        }
+    }
+
+    pub const fn default(mut self, default: u64) -> Self {
+        self.default = Some(default);
+        self
+    }
+
+    /// Set an example value, see [`verify_examples`].
+    pub const fn example(mut self, example: &'static str) -> Self {
+        self.example = Some(example);
+        self
+    }
+
+    pub const fn minimum(mut self, minimum: u64) -> Self {
+        self.minimum = Some(minimum);
+        self
+    }
+
+    pub const fn maximum(mut self, maximum: u64) -> Self {
+        self.maximum = Some(maximum);
+        self
+    }
+
+    pub const fn schema(self) -> Schema {
+        Schema::Uint64(self)
+    }
+
+    pub fn check_constraints(&self, value: u64) -> Result<(), Error> {
+        if let Some(minimum) = self.minimum {
+            if value < minimum {
+                bail!(ValidationError::new(
+                    ValidationErrorKind::BelowMinimum,
+                    &[("min", &minimum), ("value", &value)],
+                ));
+            }
+        }
+
+        if let Some(maximum) = self.maximum {
+            if value > maximum {
+                bail!(ValidationError::new(
+                    ValidationErrorKind::AboveMaximum,
+                    &[("max", &maximum), ("value", &value)],
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Verify JSON value using an `Uint64Schema`.
+    pub fn verify_json(&self, data: &Value) -> Result<(), Error> {
+        if let Some(value) = data.as_u64() {
+            self.check_constraints(value)
+        } else if data.is_i64() {
+            bail!("Expected unsigned integer value.");
+        } else {
+            bail!("Expected integer value.");
+        }
+    }
+}
+
 /// Data type to describe (JSON like) number value
 #[derive(Debug)]
 pub struct NumberSchema {
@@ -301,6 +430,8 @@ pub struct NumberSchema {
     pub maximum: Option<f64>,
     /// Optional default.
     pub default: Option<f64>,
+    /// Optional example value (used to generate documentation).
+    pub example: Option<&'static str>,
 }
 
 impl NumberSchema {
@@ -310,6 +441,7 @@ impl NumberSchema {
             default: None,
             minimum: None,
             maximum: None,
+            example: None,
         }
     }
 
@@ -318,6 +450,12 @@ impl NumberSchema {
         self
     }
 
+    /// Set an example value, see [`verify_examples`].
+    pub const fn example(mut self, example: &'static str) -> Self {
+        self.example = Some(example);
+        self
+    }
+
     pub const fn minimum(mut self, minimum: f64) -> Self {
         self.minimum = Some(minimum);
         self
@@ -335,21 +473,19 @@ impl NumberSchema {
     pub fn check_constraints(&self, value: f64) -> Result<(), Error> {
         if let Some(minimum) = self.minimum {
             if value < minimum {
-                bail!(
-                    "value must have a minimum value of {} (got {})",
-                    minimum,
-                    value
-                );
+                bail!(ValidationError::new(
+                    ValidationErrorKind::BelowMinimum,
+                    &[("min", &minimum), ("value", &value)],
+                ));
             }
         }
 
         if let Some(maximum) = self.maximum {
             if value > maximum {
-                bail!(
-                    "value must have a maximum value of {} (got {})",
-                    maximum,
-                    value
-                );
+                bail!(ValidationError::new(
+                    ValidationErrorKind::AboveMaximum,
+                    &[("max", &maximum), ("value", &value)],
+                ));
             }
         }
 
@@ -384,6 +520,26 @@ impl PartialEq for NumberSchema {
             && f64_eq(self.minimum, rhs.minimum)
             && f64_eq(self.maximum, rhs.maximum)
             && f64_eq(self.default, rhs.default)
+            && self.example == rhs.example
+    }
+}
+
+/// The unit the length limits of a [`StringSchema`] are counted in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LengthUnit {
+    /// Unicode characters (code points), as entered by users.
+    #[default]
+    Chars,
+    /// Bytes of the UTF-8 encoding, e.g. for values stored in fields with a size limit.
+    Bytes,
+}
+
+impl LengthUnit {
+    fn name(self) -> &'static str {
+        match self {
+            LengthUnit::Chars => "characters",
+            LengthUnit::Bytes => "bytes",
+        }
     }
 }
 
@@ -398,10 +554,16 @@ pub struct StringSchema {
     pub min_length: Option<usize>,
     /// Optional maximal length.
     pub max_length: Option<usize>,
+    /// The unit of `min_length` and `max_length`.
+    pub length_unit: LengthUnit,
     /// Optional microformat.
     pub format: Option<&'static ApiStringFormat>,
     /// A text representation of the format/type (used to generate documentation).
     pub type_text: Option<&'static str>,
+    /// Marks sensitive values like passwords, which must not show up in logs or error messages.
+    pub secret: bool,
+    /// Optional example value (used to generate documentation).
+    pub example: Option<&'static str>,
 }
 
 impl StringSchema {
@@ -411,8 +573,11 @@ impl StringSchema {
             default: None,
             min_length: None,
             max_length: None,
+            length_unit: LengthUnit::Chars,
             format: None,
             type_text: None,
+            secret: false,
+            example: None,
         }
     }
 
@@ -441,34 +606,86 @@ impl StringSchema {
         self
     }
 
+    /// Mark the value as sensitive, see [`redact_secrets`]. Examples of secret values are shown
+    /// as [`REDACTED`] in the documentation.
+    pub const fn secret(mut self) -> Self {
+        self.secret = true;
+        self
+    }
+
+    /// Set the unit `min_length` and `max_length` are counted in, characters by default.
+    ///
+    /// Use [`LengthUnit::Bytes`] if the value ends up somewhere with a size limit, e.g. a fixed
+    /// size field of a file format or a kernel interface. Note that a value with multi-byte
+    /// characters then fits fewer characters than the limit suggests. Formats are checked
+    /// independently of the length, so a format must not rely on a byte limit set here unless it
+    /// only allows ASCII anyway.
+    pub const fn length_unit(mut self, length_unit: LengthUnit) -> Self {
+        self.length_unit = length_unit;
+        self
+    }
+
+    /// Set an example value, e.g. a property string, see [`verify_examples`].
+    pub const fn example(mut self, example: &'static str) -> Self {
+        self.example = Some(example);
+        self
+    }
+
     pub const fn schema(self) -> Schema {
         Schema::String(self)
     }
 
     pub(crate) fn check_length(&self, length: usize) -> Result<(), Error> {
+        let unit = self.length_unit.name();
+
         if let Some(min_length) = self.min_length {
             if length < min_length {
-                bail!("value must be at least {} characters long", min_length);
+                bail!(ValidationError::new(
+                    ValidationErrorKind::TooShort,
+                    &[("min", &min_length), ("unit", &unit)],
+                ));
             }
         }
 
         if let Some(max_length) = self.max_length {
             if length > max_length {
-                bail!("value may only be {} characters long", max_length);
+                bail!(ValidationError::new(
+                    ValidationErrorKind::TooLong,
+                    &[("max", &max_length), ("unit", &unit)],
+                ));
             }
         }
 
         Ok(())
     }
 
+    /// The length of `value` in the [unit](Self::length_unit) of the length limits.
+    pub fn length_of(&self, value: &str) -> usize {
+        match self.length_unit {
+            LengthUnit::Chars => value.chars().count(),
+            LengthUnit::Bytes => value.len(),
+        }
+    }
+
     pub fn check_constraints(&self, value: &str) -> Result<(), Error> {
-        self.check_length(value.chars().count())?;
+        self.check_length(self.length_of(value))?;
+
+        match self.check_format(value) {
+            // format errors may contain the value, e.g. from verify functions
+            Err(_) if self.secret => bail!("value does not match the required format"),
+            result => result,
+        }
+    }
 
+    fn check_format(&self, value: &str) -> Result<(), Error> {
         if let Some(ref format) = self.format {
             match format {
                 ApiStringFormat::Pattern(regex) => {
                     if !(regex.regex_obj)().is_match(value) {
-                        bail!("value does not match the regex pattern");
+                        bail!(ValidationError::new(
+                            ValidationErrorKind::PatternMismatch,
+                            &[]
+                        ));
                     }
                 }
                 ApiStringFormat::Enum(variants) => {
@@ -482,6 +699,9 @@ impl StringSchema {
                 ApiStringFormat::VerifyFn(verify_fn) => {
                     verify_fn(value)?;
                 }
+                ApiStringFormat::Verifier(verifier) => {
+                    (verifier.verify_fn)(value)?;
+                }
             }
         }
 
@@ -520,6 +740,8 @@ pub struct ArraySchema {
     pub min_length: Option<usize>,
     /// Optional maximal length.
     pub max_length: Option<usize>,
+    /// Elements must be unique, compared by their JSON value.
+    pub unique_items: bool,
 }
 
 impl ArraySchema {
@@ -529,6 +751,7 @@ impl ArraySchema {
             items: item_schema,
             min_length: None,
             max_length: None,
+            unique_items: false,
         }
     }
 
@@ -542,6 +765,12 @@ impl ArraySchema {
         self
     }
 
+    /// Reject arrays containing the same element more than once, e.g. lists of tags.
+    pub const fn unique_items(mut self, unique_items: bool) -> Self {
+        self.unique_items = unique_items;
+        self
+    }
+
     pub const fn schema(self) -> Schema {
         Schema::Array(self)
     }
@@ -562,6 +791,23 @@ impl ArraySchema {
         Ok(())
     }
 
+    /// Find the first element equal to an earlier one if the items must be unique, returns its
+    /// index and the error for it.
+    pub(crate) fn find_duplicate(&self, list: &[Value]) -> Option<(usize, Error)> {
+        if !self.unique_items {
+            return None;
+        }
+
+        // serialized values are comparable since the keys of objects are sorted
+        let mut seen = HashMap::new();
+        for (index, item) in list.iter().enumerate() {
+            if let Some(first) = seen.insert(item.to_string(), index) {
+                return Some((index, format_err!("duplicate item, same as [{first}]")));
+            }
+        }
+        None
+    }
+
     /// Verify JSON value using an `ArraySchema`.
     pub fn verify_json(&self, data: &Value) -> Result<(), Error> {
         let list = match data {
@@ -579,8 +825,43 @@ impl ArraySchema {
             }
         }
 
+        if let Some((index, err)) = self.find_duplicate(list) {
+            param_bail!(format!("[{index}]"), err);
+        }
+
         Ok(())
     }
+
+    fn verify_json_all_do(&self, data: &Value, path: &str, errors: &mut ParameterError) {
+        let list = match data {
+            Value::Array(ref list) => list,
+            Value::Object(_) => {
+                return errors.push(
+                    path.to_string(),
+                    format_err!("Expected array - got object."),
+                )
+            }
+            _ => {
+                return errors.push(
+                    path.to_string(),
+                    format_err!("Expected array - got scalar value."),
+                )
+            }
+        };
+
+        if let Err(err) = self.check_length(list.len()) {
+            errors.push(path.to_string(), err);
+        }
+
+        for (i, item) in list.iter().enumerate() {
+            self.items
+                .verify_json_all_do(item, &format!("{path}[{i}]"), errors);
+        }
+
+        if let Some((index, err)) = self.find_duplicate(list) {
+            errors.push(format!("{path}[{index}]"), err);
+        }
+    }
 }
 
 /// Property entry in an object schema:
@@ -616,6 +897,14 @@ pub struct ObjectSchema {
     pub properties: SchemaPropertyMap,
     /// Default key name - used by `parse_parameter_string()`
     pub default_key: Option<&'static str>,
+    /// Ordering weights used when printing properties (property strings, config files,
+    /// documentation). Properties are sorted by ascending weight, properties not listed here have
+    /// a weight of `0`. Properties with equal weights keep their current order.
+    pub property_weights: &'static [(&'static str, isize)],
+    /// Properties which have to be set if the property named by the first element is set.
+    pub property_requires: &'static [(&'static str, &'static [&'static str])],
+    /// Properties which must not be set if the property named by the first element is set.
+    pub property_conflicts: &'static [(&'static str, &'static [&'static str])],
 }
 
 impl ObjectSchema {
@@ -625,6 +914,9 @@ impl ObjectSchema {
             properties,
             additional_properties: false,
             default_key: None,
+            property_weights: &[],
+            property_requires: &[],
+            property_conflicts: &[],
         }
     }
 
@@ -638,6 +930,74 @@ impl ObjectSchema {
         self
     }
 
+    /// Set the ordering weights of properties.
+    ///
+    /// ```
+    /// # use proxmox_schema::{ObjectSchema, StringSchema};
+    /// const SCHEMA: ObjectSchema = ObjectSchema::new(
+    ///     "An object printing 'type' and 'name' first.",
+    ///     &[
+    ///         ("comment", true, &StringSchema::new("A comment.").schema()),
+    ///         ("name", false, &StringSchema::new("The name.").schema()),
+    ///         ("type", false, &StringSchema::new("The type.").schema()),
+    ///     ],
+    /// )
+    /// .property_weights(&[("type", -2), ("name", -1)]);
+    /// ```
+    pub const fn property_weights(mut self, weights: &'static [(&'static str, isize)]) -> Self {
+        self.property_weights = weights;
+        self
+    }
+
+    /// Set the properties required by other properties.
+    ///
+    /// Each entry lists the properties which have to be set along with the property it is named
+    /// after. A required property which is not set but has a default value is considered to be
+    /// set.
+    ///
+    /// ```
+    /// # use proxmox_schema::{ObjectSchema, StringSchema};
+    /// const SCHEMA: ObjectSchema = ObjectSchema::new(
+    ///     "An object where 'server2' can only be used along with 'server1'.",
+    ///     &[
+    ///         ("server1", true, &StringSchema::new("The first server.").schema()),
+    ///         ("server2", true, &StringSchema::new("The fallback server.").schema()),
+    ///     ],
+    /// )
+    /// .requires(&[("server2", &["server1"])]);
+    /// ```
+    pub const fn requires(
+        mut self,
+        requires: &'static [(&'static str, &'static [&'static str])],
+    ) -> Self {
+        self.property_requires = requires;
+        self
+    }
+
+    /// Set the properties conflicting with other properties.
+    ///
+    /// Each entry lists the properties which must not be set along with the property it is named
+    /// after. Default values are not taken into account.
+    ///
+    /// ```
+    /// # use proxmox_schema::{ObjectSchema, StringSchema};
+    /// const SCHEMA: ObjectSchema = ObjectSchema::new(
+    ///     "An object with mutually exclusive properties.",
+    ///     &[
+    ///         ("keyring", true, &StringSchema::new("A keyring file.").schema()),
+    ///         ("password", true, &StringSchema::new("A password.").schema()),
+    ///     ],
+    /// )
+    /// .conflicts(&[("keyring", &["password"]), ("password", &["keyring"])]);
+    /// ```
+    pub const fn conflicts(
+        mut self,
+        conflicts: &'static [(&'static str, &'static [&'static str])],
+    ) -> Self {
+        self.property_conflicts = conflicts;
+        self
+    }
+
     pub const fn schema(self) -> Schema {
         Schema::Object(self)
     }
@@ -654,6 +1014,25 @@ impl ObjectSchema {
         }
     }
 
+    /// Get the ordering weight of a property, `0` if none was set.
+    pub fn property_weight(&self, key: &str) -> isize {
+        self.property_weights
+            .iter()
+            .find(|(name, _)| *name == key)
+            .map(|(_, weight)| *weight)
+            .unwrap_or(0)
+    }
+
+    /// Get the properties which have to be set along with a property.
+    pub fn property_requires(&self, key: &str) -> &'static [&'static str] {
+        find_property_list(self.property_requires, key)
+    }
+
+    /// Get the properties which must not be set along with a property.
+    pub fn property_conflicts(&self, key: &str) -> &'static [&'static str] {
+        find_property_list(self.property_conflicts, key)
+    }
+
     /// Parse key/value pairs and verify with object schema
     ///
     /// - `test_required`: is set, checks if all required properties are
@@ -665,6 +1044,16 @@ impl ObjectSchema {
     ) -> Result<Value, ParameterError> {
         ParameterSchema::from(self).parse_parameter_strings(data, test_required)
     }
+
+    /// Like [`parse_parameter_strings`](Self::parse_parameter_strings), but also adds the default
+    /// values of missing optional properties.
+    pub fn parse_parameter_strings_with_defaults(
+        &'static self,
+        data: &[(String, String)],
+        test_required: bool,
+    ) -> Result<Value, ParameterError> {
+        ParameterSchema::from(self).parse_parameter_strings_with_defaults(data, test_required)
+    }
 }
 
 /// Combines multiple *object* schemas into one.
@@ -706,6 +1095,48 @@ impl AllOfSchema {
         None
     }
 
+    /// Get the ordering weight of a property from the schema defining it, `0` if none was set.
+    pub fn property_weight(&self, key: &str) -> isize {
+        for entry in self.list {
+            let schema = entry
+                .any_object()
+                .expect("non-object-schema in `AllOfSchema`");
+            if schema.lookup(key).is_some() {
+                return schema.property_weight(key);
+            }
+        }
+
+        0
+    }
+
+    /// Get the properties required by a property from the schema defining it.
+    pub fn property_requires(&self, key: &str) -> &'static [&'static str] {
+        for entry in self.list {
+            let schema = entry
+                .any_object()
+                .expect("non-object-schema in `AllOfSchema`");
+            if schema.lookup(key).is_some() {
+                return schema.property_requires(key);
+            }
+        }
+
+        &[]
+    }
+
+    /// Get the properties conflicting with a property from the schema defining it.
+    pub fn property_conflicts(&self, key: &str) -> &'static [&'static str] {
+        for entry in self.list {
+            let schema = entry
+                .any_object()
+                .expect("non-object-schema in `AllOfSchema`");
+            if schema.lookup(key).is_some() {
+                return schema.property_conflicts(key);
+            }
+        }
+
+        &[]
+    }
+
     /// Parse key/value pairs and verify with object schema
     ///
     /// - `test_required`: is set, checks if all required properties are
@@ -717,6 +1148,16 @@ impl AllOfSchema {
     ) -> Result<Value, ParameterError> {
         ParameterSchema::from(self).parse_parameter_strings(data, test_required)
     }
+
+    /// Like [`parse_parameter_strings`](Self::parse_parameter_strings), but also adds the default
+    /// values of missing optional properties.
+    pub fn parse_parameter_strings_with_defaults(
+        &'static self,
+        data: &[(String, String)],
+        test_required: bool,
+    ) -> Result<Value, ParameterError> {
+        ParameterSchema::from(self).parse_parameter_strings_with_defaults(data, test_required)
+    }
 }
 
 /// An object schema which is basically like a rust enum: exactly one variant may match.
@@ -783,6 +1224,49 @@ impl OneOfSchema {
         None
     }
 
+    /// Get the ordering weight of a property from the first variant defining it, `0` if none was
+    /// set.
+    pub fn property_weight(&self, key: &str) -> isize {
+        for (_variant, entry) in self.list {
+            let schema = entry
+                .any_object()
+                .expect("non-object-schema in `OneOfSchema`");
+            if schema.lookup(key).is_some() {
+                return schema.property_weight(key);
+            }
+        }
+
+        0
+    }
+
+    /// Get the properties required by a property from the first variant defining it.
+    pub fn property_requires(&self, key: &str) -> &'static [&'static str] {
+        for (_variant, entry) in self.list {
+            let schema = entry
+                .any_object()
+                .expect("non-object-schema in `OneOfSchema`");
+            if schema.lookup(key).is_some() {
+                return schema.property_requires(key);
+            }
+        }
+
+        &[]
+    }
+
+    /// Get the properties conflicting with a property from the first variant defining it.
+    pub fn property_conflicts(&self, key: &str) -> &'static [&'static str] {
+        for (_variant, entry) in self.list {
+            let schema = entry
+                .any_object()
+                .expect("non-object-schema in `OneOfSchema`");
+            if schema.lookup(key).is_some() {
+                return schema.property_conflicts(key);
+            }
+        }
+
+        &[]
+    }
+
     pub fn lookup_variant(&self, name: &str) -> Option<&Schema> {
         Some(
             self.list[self
@@ -804,6 +1288,16 @@ impl OneOfSchema {
     ) -> Result<Value, ParameterError> {
         ParameterSchema::from(self).parse_parameter_strings(data, test_required)
     }
+
+    /// Like [`parse_parameter_strings`](Self::parse_parameter_strings), but also adds the default
+    /// values of missing optional properties.
+    pub fn parse_parameter_strings_with_defaults(
+        &'static self,
+        data: &[(String, String)],
+        test_required: bool,
+    ) -> Result<Value, ParameterError> {
+        ParameterSchema::from(self).parse_parameter_strings_with_defaults(data, test_required)
+    }
 }
 
 mod private {
@@ -821,6 +1315,57 @@ pub trait ObjectSchemaType: private::Sealed + Send + Sync {
     fn properties(&self) -> ObjectPropertyIterator;
     fn additional_properties(&self) -> bool;
     fn default_key(&self) -> Option<&'static str>;
+    fn property_weight(&self, key: &str) -> isize;
+    fn property_requires(&self, key: &str) -> &'static [&'static str];
+    fn property_conflicts(&self, key: &str) -> &'static [&'static str];
+
+    /// The properties sorted by their ordering weight.
+    ///
+    /// Properties with equal weights stay in the order of [`properties()`](Self::properties).
+    fn ordered_properties(&self) -> Vec<&'static SchemaPropertyEntry> {
+        let mut list: Vec<_> = self.properties().collect();
+        list.sort_by_key(|(name, _, _)| self.property_weight(name));
+        list
+    }
+
+    /// Check the `requires` and `conflicts` constraints of all properties for which `is_set`
+    /// returns `true`.
+    ///
+    /// A required property which is not set is still accepted if it has a default value. Returns
+    /// the errors along with the name of the property they belong to.
+    fn check_property_constraints(&self, is_set: &dyn Fn(&str) -> bool) -> Vec<(String, Error)> {
+        let mut errors = Vec::new();
+
+        for (name, _optional, _schema) in self.properties() {
+            if !is_set(name) {
+                continue;
+            }
+
+            for required in self.property_requires(name) {
+                let has_default = match self.lookup(required) {
+                    Some((_optional, schema)) => schema_has_default(schema),
+                    None => false,
+                };
+                if !is_set(required) && !has_default {
+                    errors.push((
+                        name.to_string(),
+                        format_err!("property '{name}' requires '{required}' to be set"),
+                    ));
+                }
+            }
+
+            for conflict in self.property_conflicts(name) {
+                if is_set(conflict) {
+                    errors.push((
+                        name.to_string(),
+                        format_err!("property '{name}' conflicts with '{conflict}'"),
+                    ));
+                }
+            }
+        }
+
+        errors
+    }
 
     /// Verify JSON value using an object schema.
     fn verify_json(&self, data: &Value) -> Result<(), Error> {
@@ -856,12 +1401,134 @@ pub trait ObjectSchemaType: private::Sealed + Send + Sync {
             }
         }
 
+        for (name, err) in self.check_property_constraints(&|name| map.contains_key(name)) {
+            errors.push(name, err);
+        }
+
         if !errors.is_empty() {
             Err(errors.into())
         } else {
             Ok(())
         }
     }
+
+    /// Verify JSON value using an object schema, collecting all errors, see
+    /// [`Schema::verify_json_all`].
+    fn verify_json_all(&self, data: &Value) -> Result<(), ParameterError> {
+        let mut errors = ParameterError::new();
+        self.verify_json_all_do(data, "", &mut errors);
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    #[doc(hidden)]
+    fn verify_json_all_do(&self, data: &Value, path: &str, errors: &mut ParameterError) {
+        let map = match data {
+            Value::Object(ref map) => map,
+            Value::Array(_) => {
+                return errors.push(
+                    path.to_string(),
+                    format_err!("Expected object - got array."),
+                )
+            }
+            _ => {
+                return errors.push(
+                    path.to_string(),
+                    format_err!("Expected object - got scalar value."),
+                )
+            }
+        };
+
+        let additional_properties = self.additional_properties();
+
+        for (key, value) in map {
+            if let Some((_optional, prop_schema)) = self.lookup(key) {
+                prop_schema.verify_json_all_do(value, &join_parameter_path(path, key), errors);
+            } else if !additional_properties {
+                errors.push(
+                    join_parameter_path(path, key),
+                    format_err!("schema does not allow additional properties"),
+                );
+            }
+        }
+
+        for (name, optional, _prop_schema) in self.properties() {
+            if !(*optional) && data[name] == Value::Null {
+                errors.push(
+                    join_parameter_path(path, name),
+                    format_err!("property is missing and it is not optional"),
+                );
+            }
+        }
+
+        for (name, err) in self.check_property_constraints(&|name| map.contains_key(name)) {
+            errors.push(join_parameter_path(path, &name), err);
+        }
+    }
+
+    /// Replace the values of [secret](StringSchema::secret) properties, see [`redact_secrets`].
+    fn redact_secrets(&self, data: &mut Value) {
+        if let Value::Object(map) = data {
+            for (key, value) in map.iter_mut() {
+                if let Some((_optional, prop_schema)) = self.lookup(key) {
+                    prop_schema.redact_secrets(value);
+                }
+            }
+        }
+    }
+
+    /// Add the default values of missing optional properties, see [`Schema::apply_defaults`].
+    fn apply_defaults(&self, data: &mut Value) {
+        if let Value::Object(map) = data {
+            for (name, optional, prop_schema) in self.properties() {
+                match map.get_mut(*name) {
+                    Some(value) => prop_schema.apply_defaults(value),
+                    None if *optional => {
+                        if let Some(default) = prop_schema.default_value() {
+                            map.insert(name.to_string(), default);
+                        }
+                    }
+                    None => (),
+                }
+            }
+        }
+    }
+
+    /// Replace booleans given as strings with JSON booleans, see [`Schema::normalize_booleans`].
+    fn normalize_booleans(&self, data: &mut Value) {
+        if let Value::Object(map) = data {
+            for (key, value) in map.iter_mut() {
+                if let Some((_optional, prop_schema)) = self.lookup(key) {
+                    prop_schema.normalize_booleans(value);
+                }
+            }
+        }
+    }
+}
+
+fn find_property_list(
+    list: &'static [(&'static str, &'static [&'static str])],
+    key: &str,
+) -> &'static [&'static str] {
+    list.iter()
+        .find(|(name, _)| *name == key)
+        .map(|(_, list)| *list)
+        .unwrap_or(&[])
+}
+
+fn schema_has_default(schema: &Schema) -> bool {
+    schema.default_value().is_some()
+}
+
+/// Append a property name to the path of a nested parameter, like `netif.ip`.
+fn join_parameter_path(path: &str, name: &str) -> String {
+    match path {
+        "" => name.to_string(),
+        path => format!("{path}.{name}"),
+    }
 }
 
 #[doc(hidden)]
@@ -905,6 +1572,18 @@ impl ObjectSchemaType for ObjectSchema {
     fn default_key(&self) -> Option<&'static str> {
         self.default_key
     }
+
+    fn property_weight(&self, key: &str) -> isize {
+        ObjectSchema::property_weight(self, key)
+    }
+
+    fn property_requires(&self, key: &str) -> &'static [&'static str] {
+        ObjectSchema::property_requires(self, key)
+    }
+
+    fn property_conflicts(&self, key: &str) -> &'static [&'static str] {
+        ObjectSchema::property_conflicts(self, key)
+    }
 }
 
 impl ObjectSchemaType for AllOfSchema {
@@ -947,6 +1626,18 @@ impl ObjectSchemaType for AllOfSchema {
 
         None
     }
+
+    fn property_weight(&self, key: &str) -> isize {
+        AllOfSchema::property_weight(self, key)
+    }
+
+    fn property_requires(&self, key: &str) -> &'static [&'static str] {
+        AllOfSchema::property_requires(self, key)
+    }
+
+    fn property_conflicts(&self, key: &str) -> &'static [&'static str] {
+        AllOfSchema::property_conflicts(self, key)
+    }
 }
 
 #[doc(hidden)]
@@ -1013,6 +1704,18 @@ impl ObjectSchemaType for OneOfSchema {
         None
     }
 
+    fn property_weight(&self, key: &str) -> isize {
+        OneOfSchema::property_weight(self, key)
+    }
+
+    fn property_requires(&self, key: &str) -> &'static [&'static str] {
+        OneOfSchema::property_requires(self, key)
+    }
+
+    fn property_conflicts(&self, key: &str) -> &'static [&'static str] {
+        OneOfSchema::property_conflicts(self, key)
+    }
+
     fn verify_json(&self, data: &Value) -> Result<(), Error> {
         let map = match data {
             Value::Object(ref map) => map,
@@ -1033,6 +1736,43 @@ impl ObjectSchemaType for OneOfSchema {
 
         schema.verify_json(data)
     }
+
+    fn verify_json_all_do(&self, data: &Value, path: &str, errors: &mut ParameterError) {
+        if !data.is_object() {
+            if let Err(err) = self.verify_json(data) {
+                errors.push(path.to_string(), err);
+            }
+            return;
+        }
+
+        let type_path = join_parameter_path(path, self.type_property());
+        let variant = match data.get(self.type_property()) {
+            None => {
+                return errors.push(
+                    type_path,
+                    format_err!("property is missing and it is not optional"),
+                )
+            }
+            Some(Value::String(v)) => v,
+            _ => return errors.push(type_path, format_err!("Expected string.")),
+        };
+
+        match self.lookup_variant(variant) {
+            Some(schema) => schema.verify_json_all_do(data, path, errors),
+            None => errors.push(type_path, format_err!("invalid variant '{variant}'")),
+        }
+    }
+
+    /// Only the defaults of the variant selected by the type property are added.
+    fn apply_defaults(&self, data: &mut Value) {
+        let schema = match data.get(self.type_property()) {
+            Some(Value::String(variant)) => self.lookup_variant(variant),
+            _ => None,
+        };
+        if let Some(schema) = schema {
+            schema.apply_defaults(data);
+        }
+    }
 }
 
 #[doc(hidden)]
@@ -1109,6 +1849,7 @@ pub enum Schema {
     Null,
     Boolean(BooleanSchema),
     Integer(IntegerSchema),
+    Uint64(Uint64Schema),
     Number(NumberSchema),
     String(StringSchema),
     Object(ObjectSchema),
@@ -1130,6 +1871,7 @@ impl Schema {
             Schema::Array(s) => s.verify_json(data)?,
             Schema::Boolean(s) => s.verify_json(data)?,
             Schema::Integer(s) => s.verify_json(data)?,
+            Schema::Uint64(s) => s.verify_json(data)?,
             Schema::Number(s) => s.verify_json(data)?,
             Schema::String(s) => s.verify_json(data)?,
             Schema::AllOf(s) => s.verify_json(data)?,
@@ -1138,6 +1880,228 @@ impl Schema {
         Ok(())
     }
 
+    /// Verify JSON value with `schema`, collecting all errors instead of stopping at the first.
+    ///
+    /// The whole value is walked, and every error is listed with the path of the offending
+    /// value, like `netif.ip` or `disks[2].size`. Since this is slower than
+    /// [`verify_json`](Self::verify_json), it is meant for reporting errors to users, e.g. when
+    /// validating a configuration as a whole.
+    pub fn verify_json_all(&self, data: &Value) -> Result<(), ParameterError> {
+        let mut errors = ParameterError::new();
+        self.verify_json_all_do(data, "", &mut errors);
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    fn verify_json_all_do(&self, data: &Value, path: &str, errors: &mut ParameterError) {
+        match self {
+            Schema::Object(s) => s.verify_json_all_do(data, path, errors),
+            Schema::AllOf(s) => s.verify_json_all_do(data, path, errors),
+            Schema::OneOf(s) => s.verify_json_all_do(data, path, errors),
+            Schema::Array(s) => s.verify_json_all_do(data, path, errors),
+            _ => {
+                if let Err(err) = self.verify_json(data) {
+                    match err.downcast::<ParameterError>() {
+                        // e.g. from property strings
+                        Ok(param_err) => errors.extend(
+                            param_err
+                                .into_iter()
+                                .map(|(name, err)| (join_parameter_path(path, &name), err)),
+                        ),
+                        Err(err) => errors.push(path.to_string(), err),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Replace the values of [secret](StringSchema::secret) strings, see [`redact_secrets`].
+    pub fn redact_secrets(&self, data: &mut Value) {
+        match self {
+            Schema::Object(s) => s.redact_secrets(data),
+            Schema::AllOf(s) => s.redact_secrets(data),
+            Schema::OneOf(s) => s.redact_secrets(data),
+            Schema::Array(s) => {
+                if let Value::Array(list) = data {
+                    for item in list {
+                        s.items.redact_secrets(item);
+                    }
+                }
+            }
+            Schema::String(_) => {
+                if self.is_secret() && data.is_string() {
+                    *data = Value::from(REDACTED);
+                }
+            }
+            Schema::Null
+            | Schema::Boolean(_)
+            | Schema::Integer(_)
+            | Schema::Uint64(_)
+            | Schema::Number(_) => (),
+        }
+    }
+
+    /// The default value of simple schemas as JSON value.
+    pub fn default_value(&self) -> Option<Value> {
+        match self {
+            Schema::Boolean(s) => s.default.map(Value::from),
+            Schema::Integer(s) => s.default.map(Value::from),
+            Schema::Uint64(s) => s.default.map(Value::from),
+            Schema::Number(s) => s.default.map(Value::from),
+            Schema::String(s) => s.default.map(Value::from),
+            _ => None,
+        }
+    }
+
+    /// Add the default values of missing optional properties to objects.
+    ///
+    /// This recurses into the values of nested objects and arrays. Properties which are set,
+    /// even to `null`, are left unchanged. For one-of schemas only the defaults of the variant
+    /// selected by the type property are added.
+    pub fn apply_defaults(&self, data: &mut Value) {
+        match self {
+            Schema::Object(s) => s.apply_defaults(data),
+            Schema::AllOf(s) => s.apply_defaults(data),
+            Schema::OneOf(s) => s.apply_defaults(data),
+            Schema::Array(s) => {
+                if let Value::Array(list) = data {
+                    for item in list {
+                        s.items.apply_defaults(item);
+                    }
+                }
+            }
+            Schema::Null
+            | Schema::Boolean(_)
+            | Schema::Integer(_)
+            | Schema::Uint64(_)
+            | Schema::Number(_)
+            | Schema::String(_) => (),
+        }
+    }
+
+    /// Replace booleans given as strings with JSON booleans.
+    ///
+    /// Booleans may be given as strings in any spelling [`parse_boolean`] accepts, e.g. in
+    /// request bodies or configuration files, and are accepted by
+    /// [`verify_json`](Self::verify_json). This converts them so that the value can be
+    /// deserialized. Other values are left unchanged.
+    pub fn normalize_booleans(&self, data: &mut Value) {
+        match self {
+            Schema::Object(s) => s.normalize_booleans(data),
+            Schema::AllOf(s) => s.normalize_booleans(data),
+            Schema::OneOf(s) => s.normalize_booleans(data),
+            Schema::Array(s) => {
+                if let Value::Array(list) = data {
+                    for item in list {
+                        s.items.normalize_booleans(item);
+                    }
+                }
+            }
+            Schema::Boolean(_) => {
+                if let Some(Ok(value)) = data.as_str().map(parse_boolean) {
+                    *data = Value::Bool(value);
+                }
+            }
+            Schema::Null
+            | Schema::Integer(_)
+            | Schema::Uint64(_)
+            | Schema::Number(_)
+            | Schema::String(_) => (),
+        }
+    }
+
+    /// The example value of simple schemas, see [`verify_examples`].
+    pub fn example(&self) -> Option<&'static str> {
+        match self {
+            Schema::Integer(s) => s.example,
+            Schema::Uint64(s) => s.example,
+            Schema::Number(s) => s.example,
+            Schema::String(s) => s.example,
+            _ => None,
+        }
+    }
+
+    /// The example value to show in documentation, [`REDACTED`] for [secret](Self::is_secret)
+    /// values.
+    pub fn display_example(&self) -> Option<&'static str> {
+        let example = self.example()?;
+        Some(if self.is_secret() { REDACTED } else { example })
+    }
+
+    fn verify_examples_do(&self, path: &str, errors: &mut ParameterError) {
+        if let Some(example) = self.example() {
+            if let Err(err) = self.parse_simple_value(example) {
+                errors.push(
+                    path.to_string(),
+                    format_err!("invalid example {example:?} - {err}"),
+                );
+            }
+        }
+
+        let join = |name: &str| match path {
+            "" => name.to_string(),
+            path => format!("{path}.{name}"),
+        };
+
+        match self {
+            Schema::Object(s) => s
+                .properties()
+                .for_each(|(name, _, schema)| schema.verify_examples_do(&join(name), errors)),
+            Schema::AllOf(s) => s
+                .properties()
+                .for_each(|(name, _, schema)| schema.verify_examples_do(&join(name), errors)),
+            Schema::OneOf(s) => s
+                .properties()
+                .for_each(|(name, _, schema)| schema.verify_examples_do(&join(name), errors)),
+            Schema::Array(s) => s.items.verify_examples_do(&join("[]"), errors),
+            Schema::String(StringSchema {
+                format: Some(ApiStringFormat::PropertyString(subschema)),
+                ..
+            }) => subschema.verify_examples_do(path, errors),
+            _ => (),
+        }
+    }
+
+    /// Convert the schema into a standard JSON Schema (draft 2020-12), e.g. for an OpenAPI 3.1
+    /// document.
+    ///
+    /// Descriptions are translated like in the [`format`](crate::format) module. String formats
+    /// are given as `enum` or `pattern`, the object schema of a property string as
+    /// `x-property-string` extension. Verification functions cannot be described, such strings
+    /// only carry a note in their description. [`OneOfSchema`]s are described by a `oneOf` with
+    /// a `discriminator` for the type property.
+    pub fn to_json_schema(&self) -> Value {
+        crate::format::schema_to_json_schema(self)
+    }
+
+    /// Check if values of this schema are sensitive, i.e. [secret](StringSchema::secret) strings
+    /// and property strings containing a secret, which are redacted as a whole.
+    pub fn is_secret(&self) -> bool {
+        match self {
+            Schema::String(s) => {
+                s.secret
+                    || matches!(s.format, Some(ApiStringFormat::PropertyString(sub_schema)) if sub_schema.contains_secret())
+            }
+            _ => false,
+        }
+    }
+
+    fn contains_secret(&self) -> bool {
+        match self {
+            Schema::String(s) => s.secret,
+            Schema::Array(s) => s.items.contains_secret(),
+            _ => match self.any_object() {
+                Some(s) => s
+                    .properties()
+                    .any(|(_, _, schema)| schema.contains_secret()),
+                None => false,
+            },
+        }
+    }
+
     /// Parse a simple value (no arrays and no objects)
     pub fn parse_simple_value(&self, value_str: &str) -> Result<Value, Error> {
         let value = match self {
@@ -1153,6 +2117,11 @@ impl Schema {
                 integer_schema.check_constraints(res)?;
                 Value::Number(res.into())
             }
+            Schema::Uint64(uint64_schema) => {
+                let res: u64 = value_str.parse()?;
+                uint64_schema.check_constraints(res)?;
+                Value::Number(res.into())
+            }
             Schema::Number(number_schema) => {
                 let res: f64 = value_str.parse()?;
                 number_schema.check_constraints(res)?;
@@ -1215,6 +2184,9 @@ impl Schema {
                     }
                 }
                 array_schema.check_length(array.len())?;
+                if let Some((index, err)) = array_schema.find_duplicate(&array) {
+                    param_bail!(format!("[{index}]"), err);
+                }
 
                 Ok(array.into())
             }
@@ -1238,6 +2210,14 @@ impl Schema {
         }
     }
 
+    /// Gets the underlying [`Uint64Schema`], panics on different schemas.
+    pub const fn unwrap_uint64_schema(&self) -> &Uint64Schema {
+        match self {
+            Schema::Uint64(s) => s,
+            _ => panic!("unwrap_uint64_schema on different schema"),
+        }
+    }
+
     /// Gets the underlying [`NumberSchema`], panics on different schemas.
     pub const fn unwrap_number_schema(&self) -> &NumberSchema {
         match self {
@@ -1313,6 +2293,14 @@ impl Schema {
         }
     }
 
+    /// Gets the underlying [`Uint64Schema`].
+    pub const fn uint64(&self) -> Option<&Uint64Schema> {
+        match self {
+            Schema::Uint64(s) => Some(s),
+            _ => None,
+        }
+    }
+
     /// Gets the underlying [`NumberSchema`].
     pub const fn number(&self) -> Option<&NumberSchema> {
         match self {
@@ -1373,7 +2361,7 @@ impl Schema {
 }
 
 /// A string enum entry. An enum entry must have a value and a description.
-#[derive(Clone, Debug)]
+#[derive(Clone, Copy, Debug)]
 #[cfg_attr(feature = "test-harness", derive(Eq, PartialEq))]
 pub struct EnumEntry {
     pub value: &'static str,
@@ -1465,11 +2453,72 @@ pub enum ApiStringFormat {
     PropertyString(&'static Schema),
     /// Use a verification function.
     VerifyFn(ApiStringVerifyFn),
+    /// Use a verification function with a stable name, see [`api_string_verifier!`].
+    ///
+    /// [`api_string_verifier!`]: crate::api_string_verifier
+    Verifier(&'static ApiStringVerifier),
 }
 
 /// Type of a verification function for [`StringSchema`]s.
 pub type ApiStringVerifyFn = fn(&str) -> Result<(), Error>;
 
+/// A verification function with a stable name, so clients can refer to it.
+///
+/// Please use the [`api_string_verifier!`](crate::api_string_verifier) macro to create instances
+/// of this type.
+pub struct ApiStringVerifier {
+    /// The name of the verifier, e.g. `human-byte`.
+    pub name: &'static str,
+    /// The verification function.
+    pub verify_fn: ApiStringVerifyFn,
+}
+
+/// Macro to generate [`ApiStringVerifier`]s.
+///
+/// ```
+/// use anyhow::{bail, Error};
+/// use proxmox_schema::{api_string_verifier, ApiStringFormat};
+///
+/// fn verify_even_length(value: &str) -> Result<(), Error> {
+///     if value.len() % 2 != 0 {
+///         bail!("odd length");
+///     }
+///     Ok(())
+/// }
+///
+/// api_string_verifier! {
+///     pub EVEN_LENGTH_VERIFIER = "even-length", verify_even_length;
+/// }
+///
+/// const EVEN_LENGTH_FORMAT: ApiStringFormat = ApiStringFormat::Verifier(&EVEN_LENGTH_VERIFIER);
+/// ```
+#[macro_export]
+macro_rules! api_string_verifier {
+    ($(
+        $(#[$attr:meta])*
+        $vis:vis $name:ident = $verifier_name:expr, $verify_fn:expr;
+    )+) => { $(
+        $(#[$attr])* $vis const $name: $crate::ApiStringVerifier = $crate::ApiStringVerifier {
+            name: $verifier_name,
+            verify_fn: $verify_fn,
+        };
+    )+ };
+}
+
+/// Description of an [`ApiStringFormat`], e.g. for clients validating values before sending them.
+#[derive(Clone, Copy, Debug)]
+pub enum FormatDescription {
+    /// The valid values.
+    Enum(&'static [EnumEntry]),
+    /// The source of the regular expression valid values match.
+    Pattern(&'static str),
+    /// The schema of a property string.
+    PropertyString(&'static Schema),
+    /// A verification function, only available on the server. Its name is only known for
+    /// [`ApiStringFormat::Verifier`].
+    Verifier(Option<&'static str>),
+}
+
 impl ApiStringFormat {
     /// Gets the underlying [`&[EnumEntry]`](EnumEntry) list, panics on different formats.
     pub const fn unwrap_enum_format(&self) -> &'static [EnumEntry] {
@@ -1494,12 +2543,24 @@ impl ApiStringFormat {
             _ => panic!("unwrap_property_string_format on a different ApiStringFormat"),
         }
     }
+
+    /// Describe the format, see [`FormatDescription`].
+    pub const fn describe(&self) -> FormatDescription {
+        match self {
+            ApiStringFormat::Enum(variants) => FormatDescription::Enum(variants),
+            ApiStringFormat::Pattern(regex) => FormatDescription::Pattern(regex.regex_string),
+            ApiStringFormat::PropertyString(schema) => FormatDescription::PropertyString(schema),
+            ApiStringFormat::VerifyFn(_) => FormatDescription::Verifier(None),
+            ApiStringFormat::Verifier(verifier) => FormatDescription::Verifier(Some(verifier.name)),
+        }
+    }
 }
 
 impl std::fmt::Debug for ApiStringFormat {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             ApiStringFormat::VerifyFn(fnptr) => write!(f, "VerifyFn({:p}", fnptr),
+            ApiStringFormat::Verifier(verifier) => write!(f, "Verifier({:?})", verifier.name),
             ApiStringFormat::Enum(variants) => write!(f, "Enum({:?}", variants),
             ApiStringFormat::Pattern(regex) => write!(f, "Pattern({:?}", regex),
             ApiStringFormat::PropertyString(schema) => write!(f, "PropertyString({:?}", schema),
@@ -1518,6 +2579,7 @@ impl PartialEq for ApiStringFormat {
             (ApiStringFormat::Pattern(l), ApiStringFormat::Pattern(r)) => l == r,
             (ApiStringFormat::PropertyString(l), ApiStringFormat::PropertyString(r)) => l == r,
             (ApiStringFormat::VerifyFn(l), ApiStringFormat::VerifyFn(r)) => std::ptr::eq(l, r),
+            (ApiStringFormat::Verifier(l), ApiStringFormat::Verifier(r)) => l.name == r.name,
             (_, _) => false,
         }
     }
@@ -1543,7 +2605,17 @@ impl ParameterSchema {
         data: &[(String, String)],
         test_required: bool,
     ) -> Result<Value, ParameterError> {
-        do_parse_parameter_strings(self, data, test_required)
+        do_parse_parameter_strings(self, data, test_required, false)
+    }
+
+    /// Like [`parse_parameter_strings`](Self::parse_parameter_strings), but also adds the default
+    /// values of missing optional properties, see [`Schema::apply_defaults`].
+    pub fn parse_parameter_strings_with_defaults(
+        self,
+        data: &[(String, String)],
+        test_required: bool,
+    ) -> Result<Value, ParameterError> {
+        do_parse_parameter_strings(self, data, test_required, true)
     }
 }
 
@@ -1587,6 +2659,46 @@ impl ObjectSchemaType for ParameterSchema {
             ParameterSchema::OneOf(o) => o.default_key(),
         }
     }
+
+    fn property_weight(&self, key: &str) -> isize {
+        match self {
+            ParameterSchema::Object(o) => o.property_weight(key),
+            ParameterSchema::AllOf(o) => o.property_weight(key),
+            ParameterSchema::OneOf(o) => o.property_weight(key),
+        }
+    }
+
+    fn property_requires(&self, key: &str) -> &'static [&'static str] {
+        match self {
+            ParameterSchema::Object(o) => o.property_requires(key),
+            ParameterSchema::AllOf(o) => o.property_requires(key),
+            ParameterSchema::OneOf(o) => o.property_requires(key),
+        }
+    }
+
+    fn property_conflicts(&self, key: &str) -> &'static [&'static str] {
+        match self {
+            ParameterSchema::Object(o) => o.property_conflicts(key),
+            ParameterSchema::AllOf(o) => o.property_conflicts(key),
+            ParameterSchema::OneOf(o) => o.property_conflicts(key),
+        }
+    }
+
+    fn apply_defaults(&self, data: &mut Value) {
+        match self {
+            ParameterSchema::Object(o) => o.apply_defaults(data),
+            ParameterSchema::AllOf(o) => o.apply_defaults(data),
+            ParameterSchema::OneOf(o) => o.apply_defaults(data),
+        }
+    }
+
+    fn verify_json_all_do(&self, data: &Value, path: &str, errors: &mut ParameterError) {
+        match self {
+            ParameterSchema::Object(o) => o.verify_json_all_do(data, path, errors),
+            ParameterSchema::AllOf(o) => o.verify_json_all_do(data, path, errors),
+            ParameterSchema::OneOf(o) => o.verify_json_all_do(data, path, errors),
+        }
+    }
 }
 
 impl From<&'static ObjectSchema> for ParameterSchema {
@@ -1607,15 +2719,32 @@ impl From<&'static OneOfSchema> for ParameterSchema {
     }
 }
 
-/// Helper function to parse boolean values
+/// The accepted spellings of `true`, see [`parse_boolean`].
+pub const BOOLEAN_TRUE_VALUES: &[&str] = &["1", "on", "yes", "true"];
+
+/// The accepted spellings of `false`, see [`parse_boolean`].
+pub const BOOLEAN_FALSE_VALUES: &[&str] = &["0", "off", "no", "false"];
+
+/// Parse a boolean value given as string.
+///
+/// This is used wherever booleans are given as strings, e.g. in property strings, query
+/// parameters, on the command line, by [`BooleanSchema::verify_json`] and by
+/// [`Schema::normalize_booleans`]. Case is ignored.
 ///
 /// - true:  `1 | on | yes | true`
 /// - false: `0 | off | no | false`
 pub fn parse_boolean(value_str: &str) -> Result<bool, Error> {
-    match value_str.to_lowercase().as_str() {
-        "1" | "on" | "yes" | "true" => Ok(true),
-        "0" | "off" | "no" | "false" => Ok(false),
-        _ => bail!("Unable to parse boolean option."),
+    let value = value_str.to_lowercase();
+    if BOOLEAN_TRUE_VALUES.contains(&value.as_str()) {
+        Ok(true)
+    } else if BOOLEAN_FALSE_VALUES.contains(&value.as_str()) {
+        Ok(false)
+    } else {
+        bail!(
+            "not a boolean: {value_str:?} - expected one of {}, {}",
+            BOOLEAN_TRUE_VALUES.join(", "),
+            BOOLEAN_FALSE_VALUES.join(", "),
+        );
     }
 }
 
@@ -1641,13 +2770,14 @@ pub fn parse_parameter_strings<T: Into<ParameterSchema>>(
     schema: T,
     test_required: bool,
 ) -> Result<Value, ParameterError> {
-    do_parse_parameter_strings(schema.into(), data, test_required)
+    do_parse_parameter_strings(schema.into(), data, test_required, false)
 }
 
 fn do_parse_parameter_strings(
     schema: ParameterSchema,
     data: &[(String, String)],
     test_required: bool,
+    apply_defaults: bool,
 ) -> Result<Value, ParameterError> {
     let mut params = json!({});
 
@@ -1655,6 +2785,7 @@ fn do_parse_parameter_strings(
 
     let additional_properties = schema.additional_properties();
 
+    // repeated keys accumulate into arrays, a single occurrence still becomes an array
     for (key, value) in data {
         if let Some((_optional, prop_schema)) = schema.lookup(key) {
             match prop_schema {
@@ -1664,8 +2795,8 @@ fn do_parse_parameter_strings(
                     }
                     match params[key] {
                         Value::Array(ref mut array) => {
-                            match array_schema.items.parse_simple_value(value) {
-                                Ok(res) => array.push(res), // fixme: check_length??
+                            match parse_array_item(array_schema, value) {
+                                Ok(res) => array.push(res),
                                 Err(err) => errors.push(key.into(), err),
                             }
                         }
@@ -1677,7 +2808,10 @@ fn do_parse_parameter_strings(
                         if params[key] == Value::Null {
                             params[key] = res;
                         } else {
-                            errors.push(key.into(), format_err!("duplicate parameter."));
+                            errors.push(
+                                key.into(),
+                                format_err!("duplicate parameter - parameter is not an array."),
+                            );
                         }
                     }
                     Err(err) => errors.push(key.into(), err),
@@ -1707,6 +2841,17 @@ fn do_parse_parameter_strings(
         }
     }
 
+    for (name, _optional, prop_schema) in schema.properties() {
+        if let (Schema::Array(array_schema), Value::Array(array)) = (prop_schema, &params[name]) {
+            if let Err(err) = array_schema.check_length(array.len()) {
+                errors.push(name.to_string(), err);
+            }
+            if let Some((index, err)) = array_schema.find_duplicate(array) {
+                errors.push(format!("{name}[{index}]"), err);
+            }
+        }
+    }
+
     if test_required && errors.is_empty() {
         for (name, optional, _prop_schema) in schema.properties() {
             if !(*optional) && params[name] == Value::Null {
@@ -1719,9 +2864,23 @@ fn do_parse_parameter_strings(
     }
 
     if !errors.is_empty() {
-        Err(errors)
-    } else {
-        Ok(params)
+        return Err(errors);
+    }
+
+    if apply_defaults {
+        schema.apply_defaults(&mut params);
+    }
+
+    Ok(params)
+}
+
+// Array items describing objects are given as property strings.
+fn parse_array_item(array_schema: &ArraySchema, value: &str) -> Result<Value, Error> {
+    match array_schema.items {
+        Schema::Object(_) | Schema::AllOf(_) | Schema::OneOf(_) | Schema::Array(_) => {
+            array_schema.items.parse_property_string(value)
+        }
+        items => items.parse_simple_value(value),
     }
 }
 
@@ -1731,6 +2890,33 @@ pub fn verify_json(data: &Value, schema: &Schema) -> Result<(), Error> {
     schema.verify_json(data)
 }
 
+/// Check that the example values of `schema` and all nested schemas are valid.
+///
+/// Examples are set with the `example` builder methods of [`StringSchema`], [`IntegerSchema`]
+/// and [`NumberSchema`] and are given in the form used on the command line, e.g. a property
+/// string. They are only used for documentation, so this is meant to be called from tests, for
+/// example for the schemas of an API router. The error lists the paths of all invalid examples.
+pub fn verify_examples(schema: &Schema) -> Result<(), Error> {
+    let mut errors = ParameterError::new();
+    schema.verify_examples_do("", &mut errors);
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors.into())
+    }
+}
+
+/// The value [secret](StringSchema::secret) strings are replaced with by [`redact_secrets`], in
+/// logs and in documentation.
+pub const REDACTED: &str = "<redacted>";
+
+/// Replace the values of [secret](StringSchema::secret) strings in `value` with [`REDACTED`].
+///
+/// Use this before logging data which may contain sensitive values, like API parameters.
+pub fn redact_secrets(value: &mut Value, schema: &Schema) {
+    schema.redact_secrets(value)
+}
+
 /// Verify JSON value using a `StringSchema`.
 #[deprecated(note = "use the method string_schema.verify_json() instead")]
 pub fn verify_json_string(data: &Value, schema: &StringSchema) -> Result<(), Error> {
@@ -1851,11 +3037,16 @@ pub struct ReturnType {
 
     /// The method's return type.
     pub schema: &'static Schema,
+
+    /// The content type of raw (non-JSON) return data, see [`ReturnType::raw`].
+    pub content_type: Option<&'static str>,
 }
 
 impl std::fmt::Debug for ReturnType {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        if self.optional {
+        if let Some(content_type) = self.content_type {
+            write!(f, "raw {content_type}")
+        } else if self.optional {
             write!(f, "optional {:?}", self.schema)
         } else {
             write!(f, "{:?}", self.schema)
@@ -1865,6 +3056,19 @@ impl std::fmt::Debug for ReturnType {
 
 impl ReturnType {
     pub const fn new(optional: bool, schema: &'static Schema) -> Self {
-        Self { optional, schema }
+        Self {
+            optional,
+            schema,
+            content_type: None,
+        }
+    }
+
+    /// Raw data of the given content type (e.g. `application/x-tar`) instead of JSON.
+    pub const fn raw(content_type: &'static str) -> Self {
+        Self {
+            optional: false,
+            schema: &Schema::Null,
+            content_type: Some(content_type),
+        }
     }
 }
@@ -0,0 +1,741 @@
+//! Compatibility checks between versions of a schema.
+//!
+//! [`check`] reports changes which make a new version of a schema reject values the old version
+//! accepted, like new required properties or tightened bounds. The comparison works on the JSON
+//! description of the schemas (see [`schema_to_json`]), so a schema can also be checked against
+//! a snapshot committed to the repository, see [`check_snapshot`] and [`assert_schema_compat!`].
+//!
+//! [`assert_schema_compat!`]: crate::assert_schema_compat
+
+use std::fmt;
+use std::path::Path;
+
+use anyhow::{bail, format_err, Error};
+use serde_json::{Map, Value};
+
+use crate::format::schema_to_json;
+use crate::Schema;
+
+/// Set this environment variable to `1` to update the snapshots checked by [`check_snapshot`]
+/// after compatible changes.
+pub const UPDATE_SNAPSHOT_ENV: &str = "PROXMOX_SCHEMA_UPDATE_SNAPSHOT";
+
+/// The kind of a breaking change, see [`Incompatibility`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum IncompatibilityKind {
+    /// A new property is required.
+    AddedRequiredProperty,
+    /// An optional property became required.
+    PropertyBecameRequired,
+    /// A property, or a variant of a one-of schema, was removed.
+    RemovedProperty,
+    /// The object does not allow additional properties anymore.
+    AdditionalPropertiesRemoved,
+    /// The type changed, widening an integer to a number is compatible.
+    TypeChanged { old: String, new: String },
+    /// The pattern of a string changed. Patterns are compared textually, so any change counts.
+    PatternChanged { old: Option<String>, new: String },
+    /// A string without format got one, e.g. `enum`, `property-string` or `verifier <name>`.
+    FormatAdded(String),
+    /// A value was removed from an enum.
+    RemovedEnumValue(String),
+    /// A bound like `minimum` or `maxLength` was added or tightened.
+    TightenedBound {
+        bound: &'static str,
+        old: Option<f64>,
+        new: f64,
+    },
+}
+
+/// A breaking change between two versions of a schema.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Incompatibility {
+    /// Path to the offending property.
+    ///
+    /// Properties are separated by `.`, array items are denoted by `[]` and variants of one-of
+    /// schemas by their name in parentheses, e.g. `config.disks[].(scsi).size`. The path is empty
+    /// for the schema itself.
+    pub path: String,
+    /// What changed.
+    pub kind: IncompatibilityKind,
+}
+
+impl fmt::Display for Incompatibility {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let path = match self.path.as_str() {
+            "" => "<root>",
+            path => path,
+        };
+        write!(f, "{path}: ")?;
+
+        match &self.kind {
+            IncompatibilityKind::AddedRequiredProperty => f.write_str("added required property"),
+            IncompatibilityKind::PropertyBecameRequired => f.write_str("property became required"),
+            IncompatibilityKind::RemovedProperty => f.write_str("removed property"),
+            IncompatibilityKind::AdditionalPropertiesRemoved => {
+                f.write_str("additional properties are not allowed anymore")
+            }
+            IncompatibilityKind::TypeChanged { old, new } => {
+                write!(f, "type changed from {old} to {new}")
+            }
+            IncompatibilityKind::PatternChanged { old: None, new } => {
+                write!(f, "added pattern {new:?}")
+            }
+            IncompatibilityKind::PatternChanged {
+                old: Some(old),
+                new,
+            } => write!(f, "pattern changed from {old:?} to {new:?}"),
+            IncompatibilityKind::FormatAdded(format) => write!(f, "added format {format}"),
+            IncompatibilityKind::RemovedEnumValue(value) => {
+                write!(f, "removed enum value {value:?}")
+            }
+            IncompatibilityKind::TightenedBound {
+                bound,
+                old: None,
+                new,
+            } => write!(f, "added {bound} {new}"),
+            IncompatibilityKind::TightenedBound {
+                bound,
+                old: Some(old),
+                new,
+            } => write!(f, "tightened {bound} from {old} to {new}"),
+        }
+    }
+}
+
+/// Check if `new` accepts all values `old` accepts, and report the breaking changes otherwise.
+///
+/// Changes of descriptions, defaults and examples are ignored.
+pub fn check(old: &Schema, new: &Schema) -> Vec<Incompatibility> {
+    check_json(&schema_to_json(old), &schema_to_json(new))
+}
+
+/// Like [`check`], but for schemas described by [`schema_to_json`], e.g. read from a snapshot.
+pub fn check_json(old: &Value, new: &Value) -> Vec<Incompatibility> {
+    let mut checker = Checker::default();
+    checker.check(String::new(), old, new);
+    checker.list
+}
+
+#[derive(Default)]
+struct Checker {
+    list: Vec<Incompatibility>,
+}
+
+fn sub_path(path: &str, name: &str) -> String {
+    if path.is_empty() {
+        name.to_string()
+    } else {
+        format!("{path}.{name}")
+    }
+}
+
+fn is_optional(schema: &Value) -> bool {
+    schema["optional"].as_bool().unwrap_or(false)
+}
+
+impl Checker {
+    fn report(&mut self, path: &str, kind: IncompatibilityKind) {
+        self.list.push(Incompatibility {
+            path: path.to_string(),
+            kind,
+        });
+    }
+
+    fn check(&mut self, path: String, old: &Value, new: &Value) {
+        let old_type = old["type"].as_str().unwrap_or("null");
+        let new_type = new["type"].as_str().unwrap_or("null");
+
+        match (old_type, new_type) {
+            _ if old_type == new_type => (),
+            ("integer", "number") => (),
+            _ => {
+                self.report(
+                    &path,
+                    IncompatibilityKind::TypeChanged {
+                        old: old_type.to_string(),
+                        new: new_type.to_string(),
+                    },
+                );
+                return;
+            }
+        }
+
+        self.check_bound(&path, "minimum", old, new, true);
+        self.check_bound(&path, "maximum", old, new, false);
+        self.check_bound(&path, "minLength", old, new, true);
+        self.check_bound(&path, "maxLength", old, new, false);
+        self.check_bound(&path, "minLengthBytes", old, new, true);
+        self.check_bound(&path, "maxLengthBytes", old, new, false);
+
+        match new_type {
+            "string" => self.check_string(&path, old, new),
+            "array" => self.check(format!("{path}[]"), &old["items"], &new["items"]),
+            "object" => match (old.get("variants"), new.get("variants")) {
+                (Some(old), Some(new)) => self.check_variants(&path, old, new),
+                (None, None) => self.check_object(&path, old, new),
+                _ => self.report(
+                    &path,
+                    IncompatibilityKind::TypeChanged {
+                        old: describe_object(old).to_string(),
+                        new: describe_object(new).to_string(),
+                    },
+                ),
+            },
+            _ => (),
+        }
+    }
+
+    /// Report if the bound got added or moved into the direction restricting the values.
+    fn check_bound(
+        &mut self,
+        path: &str,
+        bound: &'static str,
+        old: &Value,
+        new: &Value,
+        is_lower: bool,
+    ) {
+        let Some(new_bound) = new[bound].as_f64() else {
+            return;
+        };
+        let old_bound = old[bound].as_f64();
+
+        let tightened = match old_bound {
+            None => true,
+            Some(old_bound) if is_lower => new_bound > old_bound,
+            Some(old_bound) => new_bound < old_bound,
+        };
+        if tightened {
+            self.report(
+                path,
+                IncompatibilityKind::TightenedBound {
+                    bound,
+                    old: old_bound,
+                    new: new_bound,
+                },
+            );
+        }
+    }
+
+    fn check_string(&mut self, path: &str, old: &Value, new: &Value) {
+        if let Some(new_enum) = new["enum"].as_array() {
+            match old["enum"].as_array() {
+                Some(old_enum) => {
+                    for value in old_enum.iter().filter(|value| !new_enum.contains(value)) {
+                        let value = value.as_str().unwrap_or_default().to_string();
+                        self.report(path, IncompatibilityKind::RemovedEnumValue(value));
+                    }
+                }
+                None => self.report(path, IncompatibilityKind::FormatAdded("enum".into())),
+            }
+        }
+
+        if let Some(new_pattern) = new["pattern"].as_str() {
+            let old_pattern = old["pattern"].as_str();
+            if old_pattern != Some(new_pattern) {
+                self.report(
+                    path,
+                    IncompatibilityKind::PatternChanged {
+                        old: old_pattern.map(str::to_string),
+                        new: new_pattern.to_string(),
+                    },
+                );
+            }
+        }
+
+        if let Some(new_format) = new.get("format") {
+            match old.get("format") {
+                Some(old_format) => self.check(path.to_string(), old_format, new_format),
+                None => self.report(
+                    path,
+                    IncompatibilityKind::FormatAdded("property-string".into()),
+                ),
+            }
+        }
+
+        if let Some(new_verifier) = new["verifier"].as_str() {
+            if old["verifier"].as_str() != Some(new_verifier) {
+                self.report(
+                    path,
+                    IncompatibilityKind::FormatAdded(format!("verifier {new_verifier}")),
+                );
+            }
+        }
+    }
+
+    fn check_object(&mut self, path: &str, old: &Value, new: &Value) {
+        let empty = Map::new();
+        let old_properties = old["properties"].as_object().unwrap_or(&empty);
+        let new_properties = new["properties"].as_object().unwrap_or(&empty);
+
+        let old_additional = old["additionalProperties"].as_bool().unwrap_or(false);
+        let new_additional = new["additionalProperties"].as_bool().unwrap_or(false);
+        if old_additional && !new_additional {
+            self.report(path, IncompatibilityKind::AdditionalPropertiesRemoved);
+        }
+
+        for (name, old_schema) in old_properties {
+            let prop_path = sub_path(path, name);
+            match new_properties.get(name) {
+                Some(new_schema) => {
+                    if is_optional(old_schema) && !is_optional(new_schema) {
+                        self.report(&prop_path, IncompatibilityKind::PropertyBecameRequired);
+                    }
+                    self.check(prop_path, old_schema, new_schema);
+                }
+                None => self.report(&prop_path, IncompatibilityKind::RemovedProperty),
+            }
+        }
+
+        for (name, new_schema) in new_properties {
+            if !old_properties.contains_key(name) && !is_optional(new_schema) {
+                self.report(
+                    &sub_path(path, name),
+                    IncompatibilityKind::AddedRequiredProperty,
+                );
+            }
+        }
+    }
+
+    fn check_variants(&mut self, path: &str, old: &Value, new: &Value) {
+        let empty = Map::new();
+        let old_variants = old.as_object().unwrap_or(&empty);
+        let new_variants = new.as_object().unwrap_or(&empty);
+
+        for (name, old_schema) in old_variants {
+            let variant_path = sub_path(path, &format!("({name})"));
+            match new_variants.get(name) {
+                Some(new_schema) => self.check(variant_path, old_schema, new_schema),
+                None => self.report(&variant_path, IncompatibilityKind::RemovedProperty),
+            }
+        }
+    }
+}
+
+fn describe_object(schema: &Value) -> &'static str {
+    match schema.get("variants") {
+        Some(_) => "one-of object",
+        None => "object",
+    }
+}
+
+/// Check named schemas against a JSON snapshot of their previous versions.
+///
+/// The snapshot at `path` is a JSON object mapping the names to the description of the schemas,
+/// see [`schema_to_json`]. Fails if a schema is incompatible with its snapshot or if a schema
+/// of the snapshot is missing. Schemas not in the snapshot yet are new and always compatible.
+///
+/// A missing snapshot file is created. Existing snapshots are only updated if the environment
+/// variable [`UPDATE_SNAPSHOT_ENV`] is set to `1`, so compatible changes have to be committed
+/// explicitly.
+pub fn check_snapshot<P: AsRef<Path>>(path: P, schemas: &[(&str, &Schema)]) -> Result<(), Error> {
+    let path = path.as_ref();
+
+    let current: Map<String, Value> = schemas
+        .iter()
+        .map(|(name, schema)| (name.to_string(), schema_to_json(schema)))
+        .collect();
+    let current = Value::Object(current);
+
+    let snapshot = match std::fs::read(path) {
+        Ok(data) => serde_json::from_slice::<Value>(&data)
+            .map_err(|err| format_err!("unable to parse schema snapshot {path:?} - {err}"))?,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            return write_snapshot(path, &current);
+        }
+        Err(err) => bail!("unable to read schema snapshot {path:?} - {err}"),
+    };
+
+    let empty = Map::new();
+    let snapshot_schemas = snapshot.as_object().unwrap_or(&empty);
+
+    let mut list = Vec::new();
+    for (name, old) in snapshot_schemas {
+        match current.get(name) {
+            Some(new) => list.extend(check_json(old, new).into_iter().map(|mut entry| {
+                entry.path = sub_path(name, &entry.path)
+                    .trim_end_matches('.')
+                    .to_string();
+                entry
+            })),
+            None => list.push(Incompatibility {
+                path: name.clone(),
+                kind: IncompatibilityKind::RemovedProperty,
+            }),
+        }
+    }
+
+    if !list.is_empty() {
+        let list: Vec<String> = list.iter().map(|entry| entry.to_string()).collect();
+        bail!(
+            "schemas are incompatible with snapshot {path:?}:\n{}",
+            list.join("\n")
+        );
+    }
+
+    if snapshot != current && std::env::var(UPDATE_SNAPSHOT_ENV).as_deref() == Ok("1") {
+        write_snapshot(path, &current)?;
+    }
+
+    Ok(())
+}
+
+fn write_snapshot(path: &Path, snapshot: &Value) -> Result<(), Error> {
+    let mut data = serde_json::to_string_pretty(snapshot)?;
+    data.push('\n');
+    std::fs::write(path, data)
+        .map_err(|err| format_err!("unable to write schema snapshot {path:?} - {err}"))
+}
+
+/// Assert that schema constants are compatible with their snapshot, see [`check_snapshot`].
+///
+/// The snapshot path is relative to the manifest directory of the calling crate, the schemas are
+/// named after their constants:
+///
+/// ```ignore
+/// #[test]
+/// fn schema_compatibility() {
+///     proxmox_schema::assert_schema_compat!("tests/schema-snapshot.json", CONFIG_SCHEMA, ID_SCHEMA);
+/// }
+/// ```
+///
+/// [`check_snapshot`]: crate::schema_compat::check_snapshot
+#[macro_export]
+macro_rules! assert_schema_compat {
+    ($path:literal, $($schema:path),+ $(,)?) => {
+        if let Err(err) = $crate::schema_compat::check_snapshot(
+            concat!(env!("CARGO_MANIFEST_DIR"), "/", $path),
+            &[$((stringify!($schema), &$schema)),+],
+        ) {
+            panic!("{err}");
+        }
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::*;
+
+    fn kinds(old: &Schema, new: &Schema) -> Vec<(String, IncompatibilityKind)> {
+        check(old, new)
+            .into_iter()
+            .map(|entry| (entry.path, entry.kind))
+            .collect()
+    }
+
+    const NAME: Schema = StringSchema::new("Name.").schema();
+    const COUNT: Schema = IntegerSchema::new("Count.").schema();
+
+    #[test]
+    fn test_compatible_changes() {
+        const OLD: Schema =
+            ObjectSchema::new("Old.", &[("count", false, &COUNT), ("name", true, &NAME)]).schema();
+        const NEW: Schema = ObjectSchema::new(
+            "New description.",
+            &[
+                (
+                    "count",
+                    false,
+                    &NumberSchema::new("Count, now fractional.").schema(),
+                ),
+                ("extra", true, &NAME),
+                (
+                    "name",
+                    true,
+                    &StringSchema::new("Name.").default("x").schema(),
+                ),
+            ],
+        )
+        .additional_properties(true)
+        .schema();
+
+        assert!(check(&OLD, &NEW).is_empty());
+        assert!(check(&OLD, &OLD).is_empty());
+    }
+
+    #[test]
+    fn test_properties() {
+        const OLD: Schema =
+            ObjectSchema::new("Old.", &[("count", true, &COUNT), ("name", false, &NAME)])
+                .additional_properties(true)
+                .schema();
+        const NEW: Schema =
+            ObjectSchema::new("New.", &[("count", false, &COUNT), ("id", false, &NAME)]).schema();
+
+        assert_eq!(
+            kinds(&OLD, &NEW),
+            [
+                (
+                    String::new(),
+                    IncompatibilityKind::AdditionalPropertiesRemoved
+                ),
+                (
+                    "count".to_string(),
+                    IncompatibilityKind::PropertyBecameRequired
+                ),
+                ("name".to_string(), IncompatibilityKind::RemovedProperty),
+                ("id".to_string(), IncompatibilityKind::AddedRequiredProperty),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_type_changes() {
+        const NUMBER: Schema = NumberSchema::new("Number.").schema();
+
+        assert_eq!(
+            kinds(&COUNT, &NAME),
+            [(
+                String::new(),
+                IncompatibilityKind::TypeChanged {
+                    old: "integer".into(),
+                    new: "string".into(),
+                }
+            )]
+        );
+        assert_eq!(
+            kinds(&NUMBER, &COUNT),
+            [(
+                String::new(),
+                IncompatibilityKind::TypeChanged {
+                    old: "number".into(),
+                    new: "integer".into(),
+                }
+            )]
+        );
+        assert!(kinds(&COUNT, &NUMBER).is_empty());
+    }
+
+    #[test]
+    fn test_patterns() {
+        const_regex! {
+            LOWER = r"^[a-z]+$";
+            LOWER_SHORT = r"^[a-z]{1,8}$";
+        }
+        const LOWER_NAME: Schema = StringSchema::new("Name.")
+            .format(&ApiStringFormat::Pattern(&LOWER))
+            .schema();
+        const LOWER_SHORT_NAME: Schema = StringSchema::new("Name.")
+            .format(&ApiStringFormat::Pattern(&LOWER_SHORT))
+            .schema();
+
+        assert_eq!(
+            kinds(&NAME, &LOWER_NAME),
+            [(
+                String::new(),
+                IncompatibilityKind::PatternChanged {
+                    old: None,
+                    new: "^[a-z]+$".into(),
+                }
+            )]
+        );
+        assert_eq!(
+            kinds(&LOWER_NAME, &LOWER_SHORT_NAME),
+            [(
+                String::new(),
+                IncompatibilityKind::PatternChanged {
+                    old: Some("^[a-z]+$".into()),
+                    new: "^[a-z]{1,8}$".into(),
+                }
+            )]
+        );
+        assert!(kinds(&LOWER_NAME, &NAME).is_empty());
+    }
+
+    #[test]
+    fn test_bounds() {
+        const OLD: Schema = IntegerSchema::new("Port.")
+            .minimum(1)
+            .maximum(65535)
+            .schema();
+        const NEW: Schema = IntegerSchema::new("Port.")
+            .minimum(1024)
+            .maximum(65535)
+            .schema();
+        const WIDER: Schema = IntegerSchema::new("Port.").minimum(0).schema();
+
+        assert_eq!(
+            kinds(&OLD, &NEW),
+            [(
+                String::new(),
+                IncompatibilityKind::TightenedBound {
+                    bound: "minimum",
+                    old: Some(1.0),
+                    new: 1024.0,
+                }
+            )]
+        );
+        assert!(kinds(&OLD, &WIDER).is_empty());
+
+        const SHORT: Schema = StringSchema::new("Name.").max_length(16).schema();
+        assert_eq!(
+            kinds(&NAME, &SHORT),
+            [(
+                String::new(),
+                IncompatibilityKind::TightenedBound {
+                    bound: "maxLength",
+                    old: None,
+                    new: 16.0,
+                }
+            )]
+        );
+
+        const LIST: Schema = ArraySchema::new("List.", &NAME).schema();
+        const SHORT_LIST: Schema = ArraySchema::new("List.", &SHORT).max_length(4).schema();
+        assert_eq!(
+            kinds(&LIST, &SHORT_LIST),
+            [
+                (
+                    String::new(),
+                    IncompatibilityKind::TightenedBound {
+                        bound: "maxLength",
+                        old: None,
+                        new: 4.0,
+                    }
+                ),
+                (
+                    "[]".to_string(),
+                    IncompatibilityKind::TightenedBound {
+                        bound: "maxLength",
+                        old: None,
+                        new: 16.0,
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_enums() {
+        const OLD: Schema = StringSchema::new("Mode.")
+            .format(&ApiStringFormat::Enum(&[
+                EnumEntry::new("fast", "Fast."),
+                EnumEntry::new("safe", "Safe."),
+            ]))
+            .schema();
+        const NEW: Schema = StringSchema::new("Mode.")
+            .format(&ApiStringFormat::Enum(&[
+                EnumEntry::new("safe", "Safe."),
+                EnumEntry::new("fastest", "Fastest."),
+            ]))
+            .schema();
+
+        assert_eq!(
+            kinds(&OLD, &NEW),
+            [(
+                String::new(),
+                IncompatibilityKind::RemovedEnumValue("fast".into())
+            )]
+        );
+        assert_eq!(
+            kinds(&NAME, &OLD),
+            [(
+                String::new(),
+                IncompatibilityKind::FormatAdded("enum".into())
+            )]
+        );
+        assert!(kinds(&OLD, &NAME).is_empty());
+    }
+
+    #[test]
+    fn test_nested_paths() {
+        const OPTIONS: ObjectSchema = ObjectSchema::new("Options.", &[("count", true, &COUNT)]);
+        const NEW_OPTIONS: ObjectSchema = ObjectSchema::new(
+            "Options.",
+            &[(
+                "count",
+                true,
+                &IntegerSchema::new("Count.").maximum(10).schema(),
+            )],
+        );
+        const OLD: Schema = ObjectSchema::new(
+            "Config.",
+            &[
+                (
+                    "list",
+                    true,
+                    &ArraySchema::new("List.", &OPTIONS.schema()).schema(),
+                ),
+                (
+                    "options",
+                    true,
+                    &StringSchema::new("Options.")
+                        .format(&ApiStringFormat::PropertyString(&OPTIONS.schema()))
+                        .schema(),
+                ),
+            ],
+        )
+        .schema();
+        const NEW: Schema = ObjectSchema::new(
+            "Config.",
+            &[
+                (
+                    "list",
+                    true,
+                    &ArraySchema::new("List.", &NEW_OPTIONS.schema()).schema(),
+                ),
+                (
+                    "options",
+                    true,
+                    &StringSchema::new("Options.")
+                        .format(&ApiStringFormat::PropertyString(&NEW_OPTIONS.schema()))
+                        .schema(),
+                ),
+            ],
+        )
+        .schema();
+
+        let paths: Vec<String> = check(&OLD, &NEW)
+            .iter()
+            .map(|entry| entry.to_string())
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                "list[].count: added maximum 10",
+                "options.count: added maximum 10",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_snapshot() -> Result<(), Error> {
+        const OLD: Schema = ObjectSchema::new("Config.", &[("name", true, &NAME)]).schema();
+        const NEW: Schema =
+            ObjectSchema::new("Config.", &[("count", true, &COUNT), ("name", true, &NAME)])
+                .schema();
+        const BROKEN: Schema = ObjectSchema::new("Config.", &[("count", false, &COUNT)]).schema();
+
+        let path = std::env::temp_dir().join(format!(
+            "proxmox-schema-snapshot-test-{}.json",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+
+        // a missing snapshot is created
+        check_snapshot(&path, &[("CONFIG", &OLD), ("NAME", &NAME)])?;
+        let snapshot: Value = serde_json::from_slice(&std::fs::read(&path)?)?;
+        assert_eq!(snapshot["CONFIG"], schema_to_json(&OLD));
+
+        // compatible changes and new schemas pass, but do not update the snapshot
+        check_snapshot(
+            &path,
+            &[("CONFIG", &NEW), ("NAME", &NAME), ("COUNT", &COUNT)],
+        )?;
+        let snapshot: Value = serde_json::from_slice(&std::fs::read(&path)?)?;
+        assert_eq!(snapshot["CONFIG"], schema_to_json(&OLD));
+
+        let err = check_snapshot(&path, &[("CONFIG", &BROKEN)]).unwrap_err();
+        let err = err.to_string();
+        assert!(
+            err.contains("CONFIG.count: added required property"),
+            "{err}"
+        );
+        assert!(err.contains("CONFIG.name: removed property"), "{err}");
+        assert!(err.contains("NAME: removed property"), "{err}");
+
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,270 @@
+//! Canonical serialization of schema-validated values.
+//!
+//! This produces a stable byte representation of a value which is suitable for computing
+//! digests: two semantically equal values produce the exact same bytes.
+
+use anyhow::{bail, Error};
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::schema::{ApiStringFormat, ObjectSchemaType, Schema};
+
+use super::PropertyStringSerializer;
+
+/// Serialize `value` into its canonical form after verifying it against `schema`.
+///
+/// The canonical form is compact JSON with the following normalizations applied:
+///
+/// - Object keys are sorted, no insignificant whitespace is emitted.
+/// - Integers are printed as integers, numbers are always printed as floating point values.
+/// - Property strings are parsed and printed again with sorted keys and deterministic quoting.
+/// - Optional properties which are equal to their schema's default value are omitted, so that a
+///   configuration explicitly containing a default value digests equal to one which does not.
+pub fn to_canonical_bytes(value: &Value, schema: &Schema) -> Result<Vec<u8>, Error> {
+    schema.verify_json(value)?;
+    let value = canonicalize(value, schema)?;
+    Ok(serde_json::to_vec(&value)?)
+}
+
+fn canonicalize(value: &Value, schema: &Schema) -> Result<Value, Error> {
+    Ok(match schema {
+        Schema::Null => Value::Null,
+        Schema::Boolean(_) => value.clone(),
+        Schema::Integer(_) => match value.as_i64() {
+            Some(value) => Value::from(value),
+            None => bail!("expected integer value"),
+        },
+        Schema::Uint64(_) => match value.as_u64() {
+            Some(value) => Value::from(value),
+            None => bail!("expected unsigned integer value"),
+        },
+        Schema::Number(_) => match value.as_f64() {
+            Some(value) => Value::from(value),
+            None => bail!("expected number value"),
+        },
+        Schema::String(string_schema) => match (string_schema.format, value) {
+            (Some(ApiStringFormat::PropertyString(subschema)), Value::String(text)) => {
+                let parsed = subschema.parse_property_string(text)?;
+                let parsed = canonicalize(&parsed, subschema)?;
+                let text =
+                    parsed.serialize(PropertyStringSerializer::new(String::new(), subschema))?;
+                Value::String(text)
+            }
+            _ => value.clone(),
+        },
+        Schema::Array(array_schema) => match value {
+            Value::Array(list) => list
+                .iter()
+                .map(|item| canonicalize(item, array_schema.items))
+                .collect::<Result<Vec<Value>, Error>>()?
+                .into(),
+            _ => bail!("expected array value"),
+        },
+        Schema::Object(object_schema) => canonicalize_object(value, object_schema)?,
+        Schema::AllOf(all_of_schema) => canonicalize_object(value, all_of_schema)?,
+        Schema::OneOf(one_of_schema) => canonicalize_object(value, one_of_schema)?,
+    })
+}
+
+fn canonicalize_object(value: &Value, schema: &dyn ObjectSchemaType) -> Result<Value, Error> {
+    let map = match value {
+        Value::Object(map) => map,
+        _ => bail!("expected object value"),
+    };
+
+    let mut keys: Vec<&String> = map.keys().collect();
+    keys.sort_unstable();
+
+    let mut out = Map::new();
+    for key in keys {
+        let value = &map[key];
+        let value = match schema.lookup(key) {
+            Some((true, prop_schema)) if is_schema_default(value, prop_schema) => continue,
+            Some((_optional, prop_schema)) => canonicalize(value, prop_schema)?,
+            None => sort_keys(value),
+        };
+        out.insert(key.clone(), value);
+    }
+
+    Ok(Value::Object(out))
+}
+
+/// Values not covered by a schema (additional properties) only get their keys sorted.
+fn sort_keys(value: &Value) -> Value {
+    match value {
+        Value::Object(map) => {
+            let mut keys: Vec<&String> = map.keys().collect();
+            keys.sort_unstable();
+            Value::Object(
+                keys.into_iter()
+                    .map(|key| (key.clone(), sort_keys(&map[key])))
+                    .collect(),
+            )
+        }
+        Value::Array(list) => list.iter().map(sort_keys).collect(),
+        other => other.clone(),
+    }
+}
+
+fn is_schema_default(value: &Value, schema: &Schema) -> bool {
+    match schema {
+        Schema::Boolean(s) => s.default.is_some() && s.default == value.as_bool(),
+        Schema::Integer(s) => s.default.is_some() && s.default.map(|d| d as i64) == value.as_i64(),
+        Schema::Uint64(s) => s.default.is_some() && s.default == value.as_u64(),
+        Schema::Number(s) => s.default.is_some() && s.default == value.as_f64(),
+        Schema::String(s) => s.default.is_some() && s.default == value.as_str(),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::{json, Value};
+
+    use super::to_canonical_bytes;
+    use crate::schema::*;
+
+    const SUB_SCHEMA: Schema = ObjectSchema::new(
+        "A property string.",
+        &[
+            (
+                "enable",
+                true,
+                &BooleanSchema::new("Enable.").default(true).schema(),
+            ),
+            ("name", false, &StringSchema::new("Name.").schema()),
+            ("size", true, &IntegerSchema::new("Size.").schema()),
+        ],
+    )
+    .default_key("name")
+    .schema();
+
+    const SCHEMA: Schema = ObjectSchema::new(
+        "A config object.",
+        &[
+            ("comment", true, &StringSchema::new("Comment.").schema()),
+            (
+                "count",
+                true,
+                &IntegerSchema::new("Count.").default(1).schema(),
+            ),
+            (
+                "list",
+                true,
+                &ArraySchema::new("A list.", &NumberSchema::new("Ratio.").schema()).schema(),
+            ),
+            ("ratio", true, &NumberSchema::new("Ratio.").schema()),
+            (
+                "sub",
+                true,
+                &StringSchema::new("Sub.")
+                    .format(&ApiStringFormat::PropertyString(&SUB_SCHEMA))
+                    .schema(),
+            ),
+        ],
+    )
+    .schema();
+
+    fn canonical(value: &Value) -> String {
+        String::from_utf8(to_canonical_bytes(value, &SCHEMA).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_canonical_form() {
+        assert_eq!(
+            canonical(&json!({
+                "sub": "size=3,enable=1,name=foo",
+                "ratio": 2,
+                "count": 1,
+                "comment": "a \"quoted\" comment",
+            })),
+            r#"{"comment":"a \"quoted\" comment","ratio":2.0,"sub":"foo,size=3"}"#,
+        );
+
+        assert_eq!(
+            canonical(&json!({ "count": 2, "sub": "\"a \\\"b\\\"\",enable=0" })),
+            r#"{"count":2,"sub":"enable=false,\"a \\\"b\\\"\""}"#,
+        );
+
+        assert!(to_canonical_bytes(&json!({ "count": "x" }), &SCHEMA).is_err());
+        assert!(to_canonical_bytes(&json!({ "unknown": 1 }), &SCHEMA).is_err());
+    }
+
+    /// Tiny deterministic pseudo random generator, good enough to produce test inputs.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn pick<'a>(&mut self, list: &[&'a str]) -> &'a str {
+            list[(self.next() % list.len() as u64) as usize]
+        }
+    }
+
+    fn random_value(rng: &mut Rng) -> Value {
+        let mut map = serde_json::Map::new();
+        if rng.next() % 2 == 0 {
+            let comment = rng.pick(&["", "text", "with space", "\"quoted\"", "a\\b", "a,b=c"]);
+            map.insert("comment".into(), comment.into());
+        }
+        if rng.next() % 2 == 0 {
+            map.insert("count".into(), (rng.next() % 4).into());
+        }
+        if rng.next() % 2 == 0 {
+            let list: Vec<Value> = (0..rng.next() % 4)
+                .map(|_| Value::from((rng.next() % 1000) as f64 / 8.0))
+                .collect();
+            map.insert("list".into(), list.into());
+        }
+        if rng.next() % 2 == 0 {
+            map.insert("ratio".into(), ((rng.next() % 100) as f64 / 4.0).into());
+        }
+        if rng.next() % 2 == 0 {
+            let name = rng.pick(&["foo", "with space", "\"quoted\"", "back\\slash"]);
+            let mut parts = vec![format!(
+                "name=\"{}\"",
+                name.replace('\\', "\\\\").replace('"', "\\\"")
+            )];
+            if rng.next() % 2 == 0 {
+                parts.push(format!(
+                    "enable={}",
+                    rng.pick(&["0", "1", "yes", "off", "true"])
+                ));
+            }
+            if rng.next() % 2 == 0 {
+                parts.push(format!("size={}", rng.next() % 16));
+            }
+            if rng.next() % 2 == 0 {
+                parts.reverse();
+            }
+            map.insert("sub".into(), parts.join(",").into());
+        }
+        Value::Object(map)
+    }
+
+    #[test]
+    fn test_canonical_fixed_point() {
+        let mut rng = Rng(0x5eed_cafe_f00d_beef);
+
+        for _ in 0..1000 {
+            let value = random_value(&mut rng);
+
+            let first = to_canonical_bytes(&value, &SCHEMA)
+                .unwrap_or_else(|err| panic!("failed to serialize {value}: {err}"));
+            let parsed: Value = serde_json::from_slice(&first).unwrap();
+            let second = to_canonical_bytes(&parsed, &SCHEMA)
+                .unwrap_or_else(|err| panic!("failed to serialize {parsed}: {err}"));
+
+            assert_eq!(
+                String::from_utf8_lossy(&first),
+                String::from_utf8_lossy(&second),
+                "canonical form of {value} is not a fixed point",
+            );
+        }
+    }
+}
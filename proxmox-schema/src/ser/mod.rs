@@ -8,6 +8,9 @@ use serde::ser::{self, Serialize, Serializer};
 use crate::de::Error;
 use crate::schema::{ArraySchema, ObjectSchemaType, Schema};
 
+mod canonical;
+pub use canonical::to_canonical_bytes;
+
 impl serde::ser::Error for Error {
     fn custom<T: fmt::Display>(msg: T) -> Self {
         Self::msg(msg.to_string())
@@ -190,23 +193,36 @@ impl<T: fmt::Write> Serializer for PropertyStringSerializer<T> {
 
 pub struct SerializeStruct<T> {
     inner: Option<T>,
-    comma: bool,
     schema: Option<&'static dyn ObjectSchemaType>,
     value_schema: Option<&'static Schema>,
+    /// The `key=value` entries along with their ordering weight, written out on `finish()`.
+    entries: Vec<(isize, String)>,
 }
 
 impl<T: fmt::Write> SerializeStruct<T> {
     fn new(inner: T, schema: Option<&'static dyn ObjectSchemaType>) -> Self {
         Self {
             inner: Some(inner),
-            comma: false,
             schema,
             value_schema: None,
+            entries: Vec::new(),
         }
     }
 
     fn finish(mut self) -> Result<T, Error> {
-        Ok(self.inner.take().unwrap())
+        let mut inner = self.inner.take().unwrap();
+
+        // stable sort, so entries of equal weight stay in serialization order
+        self.entries.sort_by_key(|(weight, _)| *weight);
+
+        for (i, (_weight, entry)) in self.entries.iter().enumerate() {
+            if i > 0 {
+                inner.write_char(',')?;
+            }
+            inner.write_str(entry)?;
+        }
+
+        Ok(inner)
     }
 
     fn do_key<K>(&mut self, key: &K) -> Result<(), Error>
@@ -215,10 +231,8 @@ impl<T: fmt::Write> SerializeStruct<T> {
     {
         let key = key.serialize(ElementSerializer::new(String::new(), None))?;
 
-        let inner = self.inner.as_mut().unwrap();
-        if mem::replace(&mut self.comma, true) {
-            inner.write_char(',')?;
-        }
+        let mut weight = 0;
+        let mut is_default_key = false;
 
         if let Some(schema) = self.schema {
             self.value_schema = schema.lookup(&key).map(|(_optional, schema)| schema);
@@ -227,13 +241,17 @@ impl<T: fmt::Write> SerializeStruct<T> {
                     "key {key:?} is not part of the schema and it does not allow additional properties"
                 )));
             }
-            if schema.default_key() == Some(&key[..]) {
-                return Ok(());
-            }
+            weight = schema.property_weight(&key);
+            is_default_key = schema.default_key() == Some(&key[..]);
+        }
+
+        let mut entry = String::new();
+        if !is_default_key {
+            entry.push_str(&key);
+            entry.push('=');
         }
+        self.entries.push((weight, entry));
 
-        inner.write_str(&key)?;
-        inner.write_char('=')?;
         Ok(())
     }
 
@@ -241,9 +259,14 @@ impl<T: fmt::Write> SerializeStruct<T> {
     where
         V: Serialize + ?Sized,
     {
-        let mut inner = self.inner.take().unwrap();
-        inner = value.serialize(ElementSerializer::new(inner, self.value_schema))?;
-        self.inner = Some(inner);
+        let (_weight, entry) = self
+            .entries
+            .last_mut()
+            .ok_or_else(|| Error::msg("property string serializer got a value without a key"))?;
+        *entry = value.serialize(ElementSerializer::new_value(
+            mem::take(entry),
+            self.value_schema,
+        ))?;
         Ok(())
     }
 }
@@ -284,7 +307,7 @@ impl<T: fmt::Write> ser::SerializeMap for SerializeStruct<T> {
     where
         V: Serialize + ?Sized,
     {
-        self.do_key(value)
+        self.do_value(value)
     }
 
     fn end(self) -> Result<Self::Ok, Self::Error> {
@@ -369,11 +392,25 @@ same_impl! {
 pub struct ElementSerializer<T> {
     inner: T,
     schema: Option<&'static Schema>,
+    /// Quote strings containing separators, for the values of `key=value` pairs.
+    is_value: bool,
 }
 
 impl<T> ElementSerializer<T> {
     fn new(inner: T, schema: Option<&'static Schema>) -> Self {
-        Self { inner, schema }
+        Self {
+            inner,
+            schema,
+            is_value: false,
+        }
+    }
+
+    fn new_value(inner: T, schema: Option<&'static Schema>) -> Self {
+        Self {
+            inner,
+            schema,
+            is_value: true,
+        }
     }
 }
 
@@ -444,11 +481,16 @@ impl<T: fmt::Write> Serializer for ElementSerializer<T> {
         serialize_u64(u64)
         serialize_f32(f32)
         serialize_f64(f64)
-        serialize_char(char)
+    }
+
+    fn serialize_char(self, v: char) -> Result<Self::Ok, Error> {
+        self.serialize_str(v.encode_utf8(&mut [0; 4]))
     }
 
     fn serialize_str(mut self, v: &str) -> Result<Self::Ok, Error> {
-        if v.contains(['"', '\\', '\n']) {
+        if self.is_value {
+            crate::property_string::write_value(v, &mut self.inner)?;
+        } else if v.contains(['"', '\\', '\n']) {
             self.inner.write_char('"')?;
             crate::property_string::quote(v, &mut self.inner)?;
             self.inner.write_char('"')?;
@@ -0,0 +1,139 @@
+use std::fmt;
+
+use anyhow::{bail, Error};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::schema::{parse_boolean, ApiStringFormat, ApiType, Schema, StringSchema, UpdaterType};
+
+/// An option which is either enabled, disabled, or chosen automatically.
+///
+/// Use this instead of `Option<bool>` for options where "not set" means "decide automatically",
+/// so that `auto` can also be set explicitly. Enabled and disabled are given like booleans, see
+/// [`parse_boolean`], and always written as `true` and `false`.
+///
+/// ```
+/// # use proxmox_schema::Tristate;
+/// let value: Tristate = "yes".parse().unwrap();
+/// assert_eq!(value, Tristate::True);
+/// assert_eq!(value.to_string(), "true");
+///
+/// let value: Tristate = "auto".parse().unwrap();
+/// // e.g. enable compression if the peer supports it
+/// assert!(value.resolve(|| true));
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Tristate {
+    True,
+    False,
+    #[default]
+    Auto,
+}
+
+fn verify_tristate(value: &str) -> Result<(), Error> {
+    value.parse::<Tristate>().map(drop)
+}
+
+impl Tristate {
+    /// The value, or `None` for [`Auto`](Tristate::Auto).
+    pub fn as_bool(self) -> Option<bool> {
+        match self {
+            Tristate::True => Some(true),
+            Tristate::False => Some(false),
+            Tristate::Auto => None,
+        }
+    }
+
+    /// The value, with [`Auto`](Tristate::Auto) decided by `auto`.
+    pub fn resolve<F: FnOnce() -> bool>(self, auto: F) -> bool {
+        self.as_bool().unwrap_or_else(auto)
+    }
+}
+
+impl ApiType for Tristate {
+    const API_SCHEMA: Schema = StringSchema::new(
+        "Enabled ('true', '1', 'on', 'yes'), disabled ('false', '0', 'off', 'no') or 'auto'.",
+    )
+    .format(&ApiStringFormat::VerifyFn(verify_tristate))
+    .default("auto")
+    .schema();
+}
+
+impl UpdaterType for Tristate {
+    type Updater = Option<Self>;
+}
+
+impl From<bool> for Tristate {
+    fn from(value: bool) -> Self {
+        if value {
+            Tristate::True
+        } else {
+            Tristate::False
+        }
+    }
+}
+
+impl From<Option<bool>> for Tristate {
+    fn from(value: Option<bool>) -> Self {
+        value.map_or(Tristate::Auto, Tristate::from)
+    }
+}
+
+impl From<Tristate> for Option<bool> {
+    fn from(value: Tristate) -> Self {
+        value.as_bool()
+    }
+}
+
+impl std::str::FromStr for Tristate {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        if s.eq_ignore_ascii_case("auto") {
+            return Ok(Tristate::Auto);
+        }
+        match parse_boolean(s) {
+            Ok(value) => Ok(value.into()),
+            Err(err) => bail!("{err} or auto"),
+        }
+    }
+}
+
+impl fmt::Display for Tristate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Tristate::True => "true",
+            Tristate::False => "false",
+            Tristate::Auto => "auto",
+        })
+    }
+}
+
+impl Serialize for Tristate {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Tristate {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+
+        impl serde::de::Visitor<'_> for Visitor {
+            type Value = Tristate;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a boolean or 'auto'")
+            }
+
+            fn visit_bool<E: serde::de::Error>(self, value: bool) -> Result<Tristate, E> {
+                Ok(value.into())
+            }
+
+            fn visit_str<E: serde::de::Error>(self, value: &str) -> Result<Tristate, E> {
+                value.parse().map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_any(Visitor)
+    }
+}
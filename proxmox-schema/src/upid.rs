@@ -1,4 +1,7 @@
-use anyhow::{bail, Error};
+use std::collections::HashMap;
+use std::sync::{LazyLock, RwLock};
+
+use anyhow::{bail, format_err, Error};
 
 use crate::{const_regex, ApiStringFormat, ApiType, Schema, StringSchema};
 
@@ -207,6 +210,117 @@ fn unescape_id(text: &str) -> Result<String, Error> {
     Ok(text)
 }
 
+/// Metadata of a worker type, used to describe tasks to users.
+///
+/// Products register their worker types at startup with [`register_task_type`].
+#[derive(Clone, Copy, Debug)]
+pub struct TaskTypeInfo {
+    /// The worker type as used in the UPID.
+    pub worker_type: &'static str,
+    /// A human-readable label, e.g. `Garbage Collection`.
+    pub label: &'static str,
+    /// The schema worker IDs must match, if tasks of this type have an ID.
+    pub id_schema: Option<&'static Schema>,
+    /// A category for grouping tasks or choosing an icon, e.g. `datastore`.
+    pub category: &'static str,
+}
+
+impl TaskTypeInfo {
+    pub const fn new(worker_type: &'static str, label: &'static str) -> Self {
+        Self {
+            worker_type,
+            label,
+            id_schema: None,
+            category: "",
+        }
+    }
+
+    pub const fn id_schema(mut self, schema: &'static Schema) -> Self {
+        self.id_schema = Some(schema);
+        self
+    }
+
+    pub const fn category(mut self, category: &'static str) -> Self {
+        self.category = category;
+        self
+    }
+
+    fn same_as(&self, other: &TaskTypeInfo) -> bool {
+        let same_schema = match (self.id_schema, other.id_schema) {
+            (Some(a), Some(b)) => std::ptr::eq(a, b),
+            (None, None) => true,
+            _ => false,
+        };
+        self.label == other.label && self.category == other.category && same_schema
+    }
+
+    /// Check if `worker_id` is a valid ID for tasks of this type.
+    ///
+    /// Without an ID schema any ID is accepted.
+    pub fn check_worker_id(&self, worker_id: Option<&str>) -> Result<(), Error> {
+        let Some(schema) = self.id_schema else {
+            return Ok(());
+        };
+        match worker_id {
+            Some(worker_id) => schema
+                .parse_simple_value(worker_id)
+                .map(drop)
+                .map_err(|err| format_err!("invalid worker id for '{}' - {err}", self.worker_type)),
+            None => bail!("missing worker id for '{}'", self.worker_type),
+        }
+    }
+}
+
+static TASK_TYPES: LazyLock<RwLock<HashMap<&'static str, TaskTypeInfo>>> =
+    LazyLock::new(|| RwLock::new(HashMap::new()));
+
+/// Register the metadata of a worker type.
+///
+/// Registering a worker type again is fine as long as the metadata is the same, otherwise this
+/// fails.
+pub fn register_task_type(info: TaskTypeInfo) -> Result<(), Error> {
+    let mut task_types = TASK_TYPES.write().unwrap();
+    match task_types.get(info.worker_type) {
+        Some(existing) if !existing.same_as(&info) => {
+            bail!(
+                "worker type '{}' is already registered with different metadata",
+                info.worker_type
+            );
+        }
+        Some(_) => (),
+        None => {
+            task_types.insert(info.worker_type, info);
+        }
+    }
+    Ok(())
+}
+
+/// Get the metadata of a worker type, e.g. to add labels to a task list.
+pub fn lookup_task_type(worker_type: &str) -> Option<TaskTypeInfo> {
+    TASK_TYPES.read().unwrap().get(worker_type).copied()
+}
+
+/// Check that a worker type is registered and `worker_id` matches its ID schema.
+///
+/// Returns `Ok(None)` if no worker type is registered at all, so products not using the registry
+/// are not affected.
+pub fn check_task_type(
+    worker_type: &str,
+    worker_id: Option<&str>,
+) -> Result<Option<TaskTypeInfo>, Error> {
+    let task_types = TASK_TYPES.read().unwrap();
+    if task_types.is_empty() {
+        return Ok(None);
+    }
+    match task_types.get(worker_type) {
+        Some(info) => {
+            info.check_worker_id(worker_id)?;
+            Ok(Some(*info))
+        }
+        None => bail!("unregistered worker type '{worker_type}'"),
+    }
+}
+
 #[cfg(feature = "upid-api-impl")]
 mod upid_impl {
     use std::os::unix::ffi::OsStrExt;
@@ -293,3 +407,56 @@ mod upid_impl {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{IntegerSchema, StringSchema};
+
+    const STORE_SCHEMA: Schema = StringSchema::new("Datastore name.")
+        .min_length(3)
+        .max_length(32)
+        .schema();
+
+    static STORE_ID_SCHEMA: Schema = STORE_SCHEMA;
+    static VMID_SCHEMA: Schema = IntegerSchema::new("VM ID.").minimum(100).schema();
+
+    #[test]
+    fn test_task_type_registry() -> Result<(), Error> {
+        let garbage_collection = TaskTypeInfo::new("test-gc", "Garbage Collection")
+            .id_schema(&STORE_ID_SCHEMA)
+            .category("datastore");
+        register_task_type(garbage_collection)?;
+        register_task_type(TaskTypeInfo::new("test-vzdump", "Backup").id_schema(&VMID_SCHEMA))?;
+        register_task_type(TaskTypeInfo::new(
+            "test-aptupdate",
+            "Update package database",
+        ))?;
+
+        // registering the same metadata again is fine
+        register_task_type(garbage_collection)?;
+        // but not differing metadata
+        assert!(register_task_type(TaskTypeInfo::new("test-gc", "GC")).is_err());
+        assert!(register_task_type(
+            TaskTypeInfo::new("test-gc", "Garbage Collection").category("datastore")
+        )
+        .is_err());
+
+        let info = lookup_task_type("test-gc").expect("registered task type");
+        assert_eq!(info.label, "Garbage Collection");
+        assert_eq!(info.category, "datastore");
+        assert!(lookup_task_type("test-unknown").is_none());
+
+        assert!(check_task_type("test-gc", Some("store1"))?.is_some());
+        assert!(check_task_type("test-gc", Some("s1")).is_err());
+        assert!(check_task_type("test-gc", None).is_err());
+        assert!(check_task_type("test-vzdump", Some("100"))?.is_some());
+        assert!(check_task_type("test-vzdump", Some("99")).is_err());
+        assert!(check_task_type("test-vzdump", Some("vm100")).is_err());
+        assert!(check_task_type("test-aptupdate", None)?.is_some());
+        assert!(check_task_type("test-aptupdate", Some("anything"))?.is_some());
+        assert!(check_task_type("test-unknown", None).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,138 @@
+//! Helpers for string enums with templated variants.
+//!
+//! The `#[api]` macro supports enums with single-field tuple variants, like
+//! `CephTest(CephReleaseCodename)` with the template `ceph-{}-test`. The data of such a variant
+//! must be a string enum itself. Its values are substituted into the template, so the generated
+//! string schema lists every possible value, e.g. `ceph-quincy-test` and `ceph-reef-test`.
+//!
+//! The `const fn`s in here build the entries of that schema at compile time, the other functions
+//! are used by the generated `Serialize` and `Deserialize` implementations.
+
+use anyhow::{bail, Error};
+use serde::de::{DeserializeOwned, IntoDeserializer};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{ApiStringFormat, EnumEntry, Schema, StringSchema};
+
+/// Get the entries of a string enum schema.
+///
+/// Panics (at compile time when used in a constant) if the schema is not a string enum.
+pub const fn enum_entries(schema: &'static Schema) -> &'static [EnumEntry] {
+    match schema {
+        Schema::String(StringSchema {
+            format: Some(ApiStringFormat::Enum(entries)),
+            ..
+        }) => *entries,
+        _ => panic!("templated enum variants require string enum data"),
+    }
+}
+
+/// The number of bytes needed for the expanded values of a template.
+pub const fn values_len(prefix: &str, suffix: &str, source: &[EnumEntry]) -> usize {
+    let mut len = 0;
+    let mut i = 0;
+    while i < source.len() {
+        len += prefix.len() + source[i].value.len() + suffix.len();
+        i += 1;
+    }
+    len
+}
+
+/// Expand a template with every value of `source`, concatenated into a single buffer.
+///
+/// `LEN` must be the result of [`values_len`].
+pub const fn values<const LEN: usize>(
+    prefix: &str,
+    suffix: &str,
+    source: &[EnumEntry],
+) -> [u8; LEN] {
+    let mut out = [0u8; LEN];
+    let mut pos = 0;
+    let mut i = 0;
+    while i < source.len() {
+        let parts = [
+            prefix.as_bytes(),
+            source[i].value.as_bytes(),
+            suffix.as_bytes(),
+        ];
+        let mut p = 0;
+        while p < parts.len() {
+            let part = parts[p];
+            let mut b = 0;
+            while b < part.len() {
+                out[pos] = part[b];
+                pos += 1;
+                b += 1;
+            }
+            p += 1;
+        }
+        i += 1;
+    }
+    if pos != LEN {
+        panic!("length of expanded template values does not match");
+    }
+    out
+}
+
+/// Split the buffer created by [`values`] into enum entries with the given description.
+///
+/// `N` must be the length of `source`.
+pub const fn entries<const N: usize>(
+    values: &'static [u8],
+    prefix: &str,
+    suffix: &str,
+    source: &[EnumEntry],
+    description: &'static str,
+) -> [EnumEntry; N] {
+    let mut out = [EnumEntry::new("", ""); N];
+    let mut rest = values;
+    let mut i = 0;
+    while i < N {
+        let len = prefix.len() + source[i].value.len() + suffix.len();
+        let (value, tail) = rest.split_at(len);
+        let value = match std::str::from_utf8(value) {
+            Ok(value) => value,
+            Err(_) => panic!("expanded template value is not valid UTF-8"),
+        };
+        out[i] = EnumEntry::new(value, description);
+        rest = tail;
+        i += 1;
+    }
+    out
+}
+
+/// Concatenate lists of enum entries, `N` must be their total length.
+pub const fn concat<const N: usize>(lists: &[&'static [EnumEntry]]) -> [EnumEntry; N] {
+    let mut out = [EnumEntry::new("", ""); N];
+    let mut pos = 0;
+    let mut l = 0;
+    while l < lists.len() {
+        let list = lists[l];
+        let mut i = 0;
+        while i < list.len() {
+            out[pos] = list[i];
+            pos += 1;
+            i += 1;
+        }
+        l += 1;
+    }
+    if pos != N {
+        panic!("number of concatenated enum entries does not match");
+    }
+    out
+}
+
+/// Format the data of a templated variant.
+pub fn format_value<T: Serialize>(prefix: &str, data: &T, suffix: &str) -> Result<String, Error> {
+    match serde_json::to_value(data)? {
+        Value::String(value) => Ok(format!("{prefix}{value}{suffix}")),
+        _ => bail!("data of templated enum variant does not serialize to a string"),
+    }
+}
+
+/// Parse the data of a templated variant, `None` if `value` does not match the template.
+pub fn parse_value<T: DeserializeOwned>(value: &str, prefix: &str, suffix: &str) -> Option<T> {
+    let data = value.strip_prefix(prefix)?.strip_suffix(suffix)?;
+    T::deserialize(IntoDeserializer::<serde::de::value::Error>::into_deserializer(data)).ok()
+}
@@ -0,0 +1,189 @@
+use anyhow::Error;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use proxmox_schema::property_string::parse_with_schema;
+use proxmox_schema::{
+    parse_boolean, ApiType, BooleanSchema, ObjectSchema, ObjectSchemaType, ParameterSchema, Schema,
+    Tristate,
+};
+
+/// All accepted spellings, in various cases.
+const ACCEPTED: &[(&str, bool)] = &[
+    ("1", true),
+    ("on", true),
+    ("On", true),
+    ("yes", true),
+    ("YES", true),
+    ("true", true),
+    ("True", true),
+    ("0", false),
+    ("off", false),
+    ("OFF", false),
+    ("no", false),
+    ("No", false),
+    ("false", false),
+    ("FALSE", false),
+];
+
+const REJECTED: &[&str] = &["", "2", "y", "n", "enabled", "ture", "yes ", "-1"];
+
+const EXPECTED_FORMS: &str = "expected one of 1, on, yes, true, 0, off, no, false";
+
+const FLAG_SCHEMA: Schema = BooleanSchema::new("A flag.").schema();
+
+const OBJECT_SCHEMA: ObjectSchema =
+    ObjectSchema::new("An object.", &[("flag", true, &FLAG_SCHEMA)]);
+
+const OBJECT: Schema = OBJECT_SCHEMA.schema();
+
+const ADDITIONAL_SCHEMA: Schema = ObjectSchema::new("An object.", &[])
+    .additional_properties(true)
+    .schema();
+
+#[derive(Deserialize)]
+struct Flag {
+    flag: bool,
+}
+
+#[test]
+fn test_parse_boolean() {
+    for (value, expected) in ACCEPTED {
+        assert_eq!(parse_boolean(value).unwrap(), *expected, "{value:?}");
+    }
+    for value in REJECTED {
+        let err = parse_boolean(value).unwrap_err().to_string();
+        assert_eq!(err, format!("not a boolean: {value:?} - {EXPECTED_FORMS}"));
+    }
+}
+
+#[test]
+fn test_query_parameters() {
+    let schema = ParameterSchema::from(&OBJECT_SCHEMA);
+    for (value, expected) in ACCEPTED {
+        let params = vec![("flag".to_string(), value.to_string())];
+        let parsed = schema.parse_parameter_strings(&params, true).unwrap();
+        assert_eq!(parsed, json!({ "flag": expected }), "{value:?}");
+    }
+    for value in REJECTED {
+        let params = vec![("flag".to_string(), value.to_string())];
+        let err = schema.parse_parameter_strings(&params, true).unwrap_err();
+        assert!(err.to_string().contains(EXPECTED_FORMS), "{value:?}: {err}");
+    }
+}
+
+#[test]
+fn test_property_strings() -> Result<(), Error> {
+    for (value, expected) in ACCEPTED {
+        let input = format!("flag={value}");
+
+        // the untyped parser
+        let parsed = OBJECT.parse_property_string(&input)?;
+        assert_eq!(parsed, json!({ "flag": expected }), "{value:?}");
+
+        // the deserializer, with and without a schema for the property
+        let flag: Flag = parse_with_schema(&input, &OBJECT)?;
+        assert_eq!(flag.flag, *expected, "{value:?}");
+        let flag: Flag = parse_with_schema(&input, &ADDITIONAL_SCHEMA)?;
+        assert_eq!(flag.flag, *expected, "{value:?}");
+    }
+    for value in REJECTED.iter().filter(|v| !v.is_empty()) {
+        let input = format!("flag={value}");
+        assert!(OBJECT.parse_property_string(&input).is_err(), "{value:?}");
+
+        for object_schema in [&OBJECT, &ADDITIONAL_SCHEMA] {
+            let err = match parse_with_schema::<Flag>(&input, object_schema) {
+                Ok(_) => panic!("{value:?} should be rejected"),
+                Err(err) => err.to_string(),
+            };
+            assert!(err.contains(EXPECTED_FORMS), "{value:?}: {err}");
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_verify_json() {
+    for (value, expected) in ACCEPTED {
+        let mut data = json!({ "flag": value });
+        OBJECT_SCHEMA.verify_json(&data).unwrap();
+        FLAG_SCHEMA.verify_json(&json!(value)).unwrap();
+
+        OBJECT_SCHEMA.normalize_booleans(&mut data);
+        assert_eq!(data, json!({ "flag": expected }), "{value:?}");
+        OBJECT_SCHEMA.verify_json(&data).unwrap();
+    }
+    for value in REJECTED {
+        let err = FLAG_SCHEMA.verify_json(&json!(value)).unwrap_err();
+        assert!(err.to_string().contains(EXPECTED_FORMS), "{value:?}: {err}");
+    }
+
+    FLAG_SCHEMA.verify_json(&Value::Bool(true)).unwrap();
+    assert!(FLAG_SCHEMA.verify_json(&json!(1)).is_err());
+
+    // other values are left alone
+    let mut data = json!({ "flag": "maybe", "other": "yes" });
+    OBJECT_SCHEMA.normalize_booleans(&mut data);
+    assert_eq!(data, json!({ "flag": "maybe", "other": "yes" }));
+}
+
+#[test]
+fn test_tristate() -> Result<(), Error> {
+    for (value, expected) in ACCEPTED {
+        assert_eq!(value.parse::<Tristate>()?, Tristate::from(*expected));
+        Tristate::API_SCHEMA.verify_json(&json!(value))?;
+    }
+    for value in ["auto", "Auto", "AUTO"] {
+        assert_eq!(value.parse::<Tristate>()?, Tristate::Auto);
+    }
+    for value in REJECTED {
+        let err = value.parse::<Tristate>().unwrap_err().to_string();
+        assert_eq!(
+            err,
+            format!("not a boolean: {value:?} - {EXPECTED_FORMS} or auto")
+        );
+        assert!(Tristate::API_SCHEMA.verify_json(&json!(value)).is_err());
+    }
+
+    assert_eq!(Tristate::default(), Tristate::Auto);
+    assert_eq!(Tristate::True.as_bool(), Some(true));
+    assert_eq!(Tristate::from(None), Tristate::Auto);
+    assert_eq!(Option::<bool>::from(Tristate::False), Some(false));
+    assert!(!Tristate::False.resolve(|| true));
+    assert!(Tristate::Auto.resolve(|| true));
+
+    // always written in the canonical form
+    for (value, text) in [
+        (Tristate::True, "true"),
+        (Tristate::False, "false"),
+        (Tristate::Auto, "auto"),
+    ] {
+        assert_eq!(value.to_string(), text);
+        assert_eq!(serde_json::to_value(value)?, json!(text));
+    }
+
+    // JSON booleans and strings
+    assert_eq!(
+        serde_json::from_value::<Tristate>(json!(true))?,
+        Tristate::True
+    );
+    assert_eq!(
+        serde_json::from_value::<Tristate>(json!("off"))?,
+        Tristate::False
+    );
+    assert!(serde_json::from_value::<Tristate>(json!(1)).is_err());
+
+    // in property strings
+    #[derive(Deserialize)]
+    struct Options {
+        compress: Tristate,
+    }
+    const OPTIONS_SCHEMA: Schema =
+        ObjectSchema::new("Options.", &[("compress", false, &Tristate::API_SCHEMA)]).schema();
+    let options: Options = parse_with_schema("compress=auto", &OPTIONS_SCHEMA)?;
+    assert_eq!(options.compress, Tristate::Auto);
+    let options: Options = parse_with_schema("compress=no", &OPTIONS_SCHEMA)?;
+    assert_eq!(options.compress, Tristate::False);
+
+    Ok(())
+}
@@ -0,0 +1,90 @@
+use std::borrow::Cow;
+
+use serde_json::json;
+
+use proxmox_schema::format::{
+    dump_properties, get_property_description, schema_to_json, DocumentationFormat,
+    ParameterDisplayStyle,
+};
+use proxmox_schema::{
+    set_schema_translator, IntegerSchema, ObjectSchema, ParameterError, Schema, StringSchema,
+};
+
+const NAME_SCHEMA: Schema = StringSchema::new("The name.").max_length(3).schema();
+
+const SCHEMA: ObjectSchema = ObjectSchema::new(
+    "Options.",
+    &[
+        (
+            "count",
+            false,
+            &IntegerSchema::new("The count.").minimum(1).schema(),
+        ),
+        ("name", false, &NAME_SCHEMA),
+        ("tag", true, &StringSchema::new("A tag.").schema()),
+    ],
+);
+
+fn pseudo_translate(text: &'static str) -> Cow<'static, str> {
+    match text {
+        "too-long" => Cow::Borrowed("[max. {max} {unit}]"),
+        "below-minimum" => Cow::Borrowed("[{value} < {min}]"),
+        "parameter verification failed" => Cow::Borrowed("[parameter check failed]"),
+        _ => Cow::Owned(format!("[{text}]")),
+    }
+}
+
+#[test]
+fn test_schema_translator() {
+    let english = get_property_description(
+        "name",
+        &NAME_SCHEMA,
+        ParameterDisplayStyle::Arg,
+        DocumentationFormat::Full,
+    );
+    assert!(english.ends_with("The name."), "{english}");
+
+    set_schema_translator(Box::new(pseudo_translate));
+
+    let text = get_property_description(
+        "name",
+        &NAME_SCHEMA,
+        ParameterDisplayStyle::Arg,
+        DocumentationFormat::Full,
+    );
+    assert_eq!(text, english.replace("The name.", "[The name.]"));
+
+    let dump = dump_properties(&SCHEMA, "", ParameterDisplayStyle::Config, &[]);
+    assert!(dump.contains("  [The count.]\n"), "{dump}");
+
+    let data = schema_to_json(&SCHEMA.schema());
+    assert_eq!(data["description"], "[Options.]");
+    assert_eq!(data["properties"]["name"]["description"], "[The name.]");
+
+    let err = SCHEMA
+        .schema()
+        .verify_json(&json!({ "count": 0, "name": "long", "tag": 1 }))
+        .unwrap_err()
+        .downcast::<ParameterError>()
+        .unwrap();
+    assert_eq!(
+        err.to_string(),
+        "[parameter check failed]:\n\
+        - 'count': [0 < 1]\n\
+        - 'name': [max. 3 characters]\n\
+        - 'tag': Expected string value."
+    );
+
+    let details = err.details();
+    assert_eq!(details[1].name, "name");
+    assert_eq!(details[1].message, "[max. 3 characters]");
+    assert_eq!(details[1].english, "value may only be 3 characters long");
+    assert_eq!(details[1].id, Some("too-long"));
+    assert_eq!(
+        details[0].english,
+        "value must have a minimum value of 1 (got 0)"
+    );
+    assert_eq!(details[0].id, Some("below-minimum"));
+    assert_eq!(details[2].message, "Expected string value.");
+    assert_eq!(details[2].id, None);
+}
@@ -25,6 +25,9 @@ fn test_schema1() {
         additional_properties: false,
         properties: &[],
         default_key: None,
+        property_weights: &[],
+        property_requires: &[],
+        property_conflicts: &[],
     });
 
     println!("TEST Schema: {:?}", schema);
@@ -153,6 +156,42 @@ fn test_query_string() {
     }
 }
 
+#[test]
+fn test_string_length_unit() {
+    const CHARS: StringSchema = StringSchema::new("Name.").min_length(2).max_length(4);
+    const BYTES: StringSchema = CHARS.length_unit(LengthUnit::Bytes);
+
+    // 'ä' takes 2 bytes, '€' 3 and '🦀' 4
+    for (value, chars_ok, bytes_ok) in [
+        ("a", false, false),
+        ("ä", false, true),
+        ("ab", true, true),
+        ("abcd", true, true),
+        ("abcde", false, false),
+        ("abä", true, true),
+        ("abcä", true, false),
+        ("€", false, true),
+        ("a€", true, true),
+        ("ab€", true, false),
+        ("🦀", false, true),
+        ("🦀a", true, false),
+        ("ääää", true, false),
+    ] {
+        assert_eq!(CHARS.check_constraints(value).is_ok(), chars_ok, "{value}");
+        assert_eq!(BYTES.check_constraints(value).is_ok(), bytes_ok, "{value}");
+    }
+
+    let err = BYTES.check_constraints("abcä").unwrap_err();
+    assert_eq!(err.to_string(), "value may only be 4 bytes long");
+    let err = CHARS.check_constraints("abcäe").unwrap_err();
+    assert_eq!(err.to_string(), "value may only be 4 characters long");
+
+    const SCHEMA: ObjectSchema =
+        ObjectSchema::new("Parameters.", &[("name", false, &BYTES.schema())]);
+    assert!(parse_query_string("name=a%E2%82%AC", &SCHEMA, true).is_ok());
+    assert!(parse_query_string("name=ab%E2%82%AC", &SCHEMA, true).is_err());
+}
+
 #[test]
 fn test_query_integer() {
     {
@@ -204,6 +243,56 @@ fn test_query_integer() {
     }
 }
 
+#[test]
+fn test_query_array() {
+    const TAGS: Schema = ArraySchema::new("Tags.", &StringSchema::new("Tag.").schema())
+        .min_length(1)
+        .max_length(3)
+        .schema();
+
+    const DISK: Schema = ObjectSchema::new(
+        "Disk.",
+        &[
+            ("name", false, &StringSchema::new("Name.").schema()),
+            ("size", true, &IntegerSchema::new("Size.").schema()),
+        ],
+    )
+    .default_key("name")
+    .schema();
+
+    const SCHEMA: ObjectSchema = ObjectSchema::new(
+        "Parameters.",
+        &[
+            ("disk", true, &ArraySchema::new("Disks.", &DISK).schema()),
+            ("name", true, &StringSchema::new("Name.").schema()),
+            ("tag", true, &TAGS),
+        ],
+    );
+
+    let res = parse_query_string("tag=a", &SCHEMA, true).unwrap();
+    assert_eq!(res, serde_json::json!({ "tag": ["a"] }));
+
+    let res = parse_query_string("tag=a&name=n&tag=b", &SCHEMA, true).unwrap();
+    assert_eq!(res, serde_json::json!({ "name": "n", "tag": ["a", "b"] }));
+
+    // array items describing objects are property strings
+    let res = parse_query_string("disk=a,size=1&disk=name=b", &SCHEMA, true).unwrap();
+    assert_eq!(
+        res,
+        serde_json::json!({ "disk": [{ "name": "a", "size": 1 }, { "name": "b" }] })
+    );
+    assert!(parse_query_string("disk=size=1", &SCHEMA, true).is_err());
+
+    // the array length is checked once all occurrences are collected
+    assert!(parse_query_string("tag=a&tag=b&tag=c", &SCHEMA, true).is_ok());
+    assert!(parse_query_string("tag=a&tag=b&tag=c&tag=d", &SCHEMA, true).is_err());
+
+    // repeated scalar parameters are an error instead of the last one winning
+    let err = parse_query_string("name=a&tag=a&name=b", &SCHEMA, true).unwrap_err();
+    assert_eq!(err.errors().len(), 1);
+    assert_eq!(err.errors()[0].0, "name");
+}
+
 #[test]
 fn test_query_boolean() {
     {
@@ -395,3 +484,134 @@ fn test_verify_complex_array() {
         assert!(res.is_err());
     }
 }
+
+#[test]
+fn test_apply_defaults() {
+    const OPTIONS_SCHEMA: Schema = ObjectSchema::new(
+        "Options.",
+        &[
+            (
+                "enabled",
+                true,
+                &BooleanSchema::new("Enabled.").default(true).schema(),
+            ),
+            (
+                "retries",
+                true,
+                &IntegerSchema::new("Retries.").default(3).schema(),
+            ),
+        ],
+    )
+    .schema();
+
+    const NAME_SCHEMA: Schema = ObjectSchema::new(
+        "Name.",
+        &[
+            (
+                "mode",
+                true,
+                &StringSchema::new("Mode.").default("fast").schema(),
+            ),
+            ("name", false, &StringSchema::new("Name.").schema()),
+            ("options", true, &OPTIONS_SCHEMA),
+        ],
+    )
+    .schema();
+
+    const SCHEMA: AllOfSchema = AllOfSchema::new("Parameters.", &[&NAME_SCHEMA, &OPTIONS_SCHEMA]);
+
+    let param_list = [("name".to_string(), "foo".to_string())];
+    let value = ParameterSchema::from(&SCHEMA)
+        .parse_parameter_strings_with_defaults(&param_list, true)
+        .unwrap();
+    assert_eq!(
+        value,
+        serde_json::json!({ "name": "foo", "mode": "fast", "enabled": true, "retries": 3 }),
+    );
+    SCHEMA.verify_json(&value).unwrap();
+
+    let value = parse_query_string("name=foo", &SCHEMA, true).unwrap();
+    assert_eq!(value, serde_json::json!({ "name": "foo" }));
+
+    // set values, explicit nulls and missing required properties are kept, nested objects are
+    // completed
+    let mut value =
+        serde_json::json!({ "mode": null, "retries": 0, "options": { "enabled": false } });
+    SCHEMA.apply_defaults(&mut value);
+    assert_eq!(
+        value,
+        serde_json::json!({
+            "mode": null,
+            "retries": 0,
+            "enabled": true,
+            "options": { "enabled": false, "retries": 3 },
+        }),
+    );
+}
+
+#[test]
+fn test_verify_json_all() {
+    const NETIF_SCHEMA: Schema = ObjectSchema::new(
+        "Network interface.",
+        &[
+            ("ip", false, &StringSchema::new("IP address.").schema()),
+            (
+                "mtu",
+                true,
+                &IntegerSchema::new("MTU.").minimum(576).schema(),
+            ),
+        ],
+    )
+    .schema();
+
+    const DISK_SCHEMA: Schema = ObjectSchema::new(
+        "Disk.",
+        &[(
+            "size",
+            false,
+            &IntegerSchema::new("Size.").minimum(1).schema(),
+        )],
+    )
+    .schema();
+
+    const SCHEMA: Schema = ObjectSchema::new(
+        "Parameters.",
+        &[
+            (
+                "disks",
+                true,
+                &ArraySchema::new("Disks.", &DISK_SCHEMA).schema(),
+            ),
+            (
+                "name",
+                false,
+                &StringSchema::new("Name.").max_length(8).schema(),
+            ),
+            ("netif", true, &NETIF_SCHEMA),
+        ],
+    )
+    .schema();
+
+    let value = serde_json::json!({
+        "name": "a-name-which-is-too-long",
+        "netif": { "ip": 10, "mtu": 1500 },
+        "disks": [{ "size": 1 }, { "size": 2 }, { "size": 0 }],
+    });
+
+    // the default stops at the first error of each property
+    assert!(SCHEMA.verify_json(&value).is_err());
+
+    let err = SCHEMA.verify_json_all(&value).unwrap_err();
+    let mut names: Vec<&str> = err.errors().iter().map(|(name, _)| name.as_str()).collect();
+    names.sort();
+    assert_eq!(names, ["disks[2].size", "name", "netif.ip"]);
+
+    let value = serde_json::json!({ "netif": { "mtu": 100 }, "disks": {} });
+    let err = SCHEMA.verify_json_all(&value).unwrap_err();
+    let mut names: Vec<&str> = err.errors().iter().map(|(name, _)| name.as_str()).collect();
+    names.sort();
+    assert_eq!(names, ["disks", "name", "netif.ip", "netif.mtu"]);
+
+    let value = serde_json::json!({ "name": "ok", "disks": [{ "size": 1 }] });
+    SCHEMA.verify_json_all(&value).unwrap();
+}
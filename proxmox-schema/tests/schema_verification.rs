@@ -1,6 +1,7 @@
 use anyhow::{bail, Error};
 use serde_json::{json, Value};
 
+use proxmox_schema::format::schema_to_json;
 use proxmox_schema::*;
 
 static STRING_SCHEMA: Schema = StringSchema::new("A test string").schema();
@@ -290,3 +291,495 @@ fn verify_all_of_schema_with_additional() -> Result<(), Error> {
 
     Ok(())
 }
+
+static DEPENDENT_OBJECT_SCHEMA: Schema = ObjectSchema::new(
+    "object with property dependencies",
+    &[
+        ("first", true, &STRING_SCHEMA),
+        ("keyring", true, &STRING_SCHEMA),
+        ("password", true, &STRING_SCHEMA),
+        ("port", true, &IntegerSchema::new("A port.").schema()),
+        (
+            "protocol",
+            true,
+            &StringSchema::new("A protocol.").default("tcp").schema(),
+        ),
+        ("second", true, &STRING_SCHEMA),
+        ("third", true, &STRING_SCHEMA),
+        (
+            "verify",
+            true,
+            &BooleanSchema::new("Verify.").default(true).schema(),
+        ),
+    ],
+)
+.requires(&[
+    ("first", &["second"]),
+    ("port", &["protocol"]),
+    ("second", &["third"]),
+])
+.conflicts(&[("keyring", &["password", "verify"])])
+.schema();
+
+#[test]
+fn verify_property_dependencies() -> Result<(), Error> {
+    let value = json!({ "first": "a", "second": "b", "third": "c" });
+    DEPENDENT_OBJECT_SCHEMA
+        .verify_json(&value)
+        .expect("complete requirement chain failed to verify");
+
+    // transitive chain, the last link is missing
+    test_verify(
+        &DEPENDENT_OBJECT_SCHEMA,
+        &json!({ "first": "a", "second": "b" }),
+        &[("second", "property 'second' requires 'third' to be set")],
+    )?;
+
+    // transitive chain, the first link is missing
+    test_verify(
+        &DEPENDENT_OBJECT_SCHEMA,
+        &json!({ "first": "a", "third": "c" }),
+        &[("first", "property 'first' requires 'second' to be set")],
+    )?;
+
+    test_verify(
+        &DEPENDENT_OBJECT_SCHEMA,
+        &json!({ "keyring": "a", "password": "b" }),
+        &[("keyring", "property 'keyring' conflicts with 'password'")],
+    )?;
+
+    Ok(())
+}
+
+#[test]
+fn verify_property_dependencies_with_defaults() -> Result<(), Error> {
+    // a required property with a default value counts as being set
+    DEPENDENT_OBJECT_SCHEMA
+        .verify_json(&json!({ "port": 22 }))
+        .expect("required property with default value failed to verify");
+
+    // a conflicting property only conflicts when it is explicitly set
+    DEPENDENT_OBJECT_SCHEMA
+        .verify_json(&json!({ "keyring": "a" }))
+        .expect("conflicting property with default value failed to verify");
+
+    test_verify(
+        &DEPENDENT_OBJECT_SCHEMA,
+        &json!({ "keyring": "a", "verify": true }),
+        &[("keyring", "property 'keyring' conflicts with 'verify'")],
+    )?;
+
+    Ok(())
+}
+
+#[test]
+fn deserialize_property_dependencies() -> Result<(), Error> {
+    use proxmox_schema::property_string::parse_with_schema;
+
+    let value: Value = parse_with_schema("third=c,second=b,first=a", &DEPENDENT_OBJECT_SCHEMA)?;
+    assert_eq!(value, json!({ "first": "a", "second": "b", "third": "c" }));
+
+    let err = parse_with_schema::<Value>("first=a,second=b", &DEPENDENT_OBJECT_SCHEMA)
+        .expect_err("incomplete requirement chain should fail to deserialize");
+    assert_eq!(
+        err.to_string(),
+        "property 'second' requires 'third' to be set"
+    );
+
+    let err = parse_with_schema::<Value>("password=b,keyring=a", &DEPENDENT_OBJECT_SCHEMA)
+        .expect_err("conflicting properties should fail to deserialize");
+    assert_eq!(
+        err.to_string(),
+        "property 'keyring' conflicts with 'password'"
+    );
+
+    Ok(())
+}
+
+const_regex! {
+    PASSWORD_REGEX = r"^[a-z]{8,}$";
+}
+
+const CREDENTIALS_OBJECT: ObjectSchema = ObjectSchema::new(
+    "object with secret properties",
+    &[
+        (
+            "password",
+            false,
+            &StringSchema::new("A password.")
+                .format(&ApiStringFormat::Pattern(&PASSWORD_REGEX))
+                .secret()
+                .schema(),
+        ),
+        (
+            "token",
+            true,
+            &StringSchema::new("A token.")
+                .format(&ApiStringFormat::VerifyFn(|value| {
+                    bail!("invalid token '{value}'")
+                }))
+                .secret()
+                .schema(),
+        ),
+        ("user", false, &StringSchema::new("A user.").schema()),
+    ],
+);
+
+static CREDENTIALS_SCHEMA: Schema = CREDENTIALS_OBJECT.schema();
+
+static CREDENTIALS_PROPERTY_SCHEMA: Schema = StringSchema::new("credentials property string")
+    .format(&ApiStringFormat::PropertyString(&CREDENTIALS_SCHEMA))
+    .schema();
+
+static REMOTE_SCHEMA: Schema = AllOfSchema::new(
+    "credentials with a host",
+    &[
+        &CREDENTIALS_SCHEMA,
+        &ObjectSchema::new("A host.", &[("host", false, &STRING_SCHEMA)]).schema(),
+    ],
+)
+.schema();
+
+static REMOTE_PROPERTY_SCHEMA: Schema = StringSchema::new("remote property string")
+    .format(&ApiStringFormat::PropertyString(&REMOTE_SCHEMA))
+    .schema();
+
+static NESTED_CREDENTIALS_SCHEMA: Schema = ObjectSchema::new(
+    "object with nested secrets",
+    &[
+        (
+            "list",
+            true,
+            &ArraySchema::new("Credential list.", &CREDENTIALS_SCHEMA).schema(),
+        ),
+        ("remote", true, &CREDENTIALS_PROPERTY_SCHEMA),
+        ("remote2", true, &REMOTE_PROPERTY_SCHEMA),
+        ("user", true, &STRING_SCHEMA),
+    ],
+)
+.schema();
+
+#[test]
+fn verify_secret_properties() -> Result<(), Error> {
+    test_verify(
+        &CREDENTIALS_SCHEMA,
+        &json!({ "password": "Hunter2!", "user": "root" }),
+        &[("password", "value does not match the required format")],
+    )?;
+
+    test_verify(
+        &CREDENTIALS_SCHEMA,
+        &json!({ "password": "correcthorse", "token": "tok-12345", "user": "root" }),
+        &[("token", "value does not match the required format")],
+    )?;
+
+    let param_list = vec![
+        ("password".to_string(), "Hunter2!".to_string()),
+        ("user".to_string(), "root".to_string()),
+    ];
+    let err = ParameterSchema::from(&CREDENTIALS_OBJECT)
+        .parse_parameter_strings(&param_list, true)
+        .expect_err("invalid password should fail to parse");
+    assert!(!err.to_string().contains("Hunter2"), "leaked secret: {err}");
+
+    let err = proxmox_schema::property_string::parse_with_schema::<Value>(
+        "password=Hunter2!,user=root",
+        &CREDENTIALS_SCHEMA,
+    )
+    .expect_err("invalid password should fail to deserialize");
+    assert!(!err.to_string().contains("Hunter2"), "leaked secret: {err}");
+
+    Ok(())
+}
+
+#[test]
+fn redact_secret_properties() {
+    let mut value = json!({
+        "list": [
+            { "password": "correcthorse", "user": "root" },
+            { "password": "batterystaple", "token": "tok-12345", "user": "admin" },
+        ],
+        "remote": "password=correcthorse,user=root",
+        "remote2": "host=example.com,password=correcthorse,user=root",
+        "user": "root",
+    });
+
+    redact_secrets(&mut value, &NESTED_CREDENTIALS_SCHEMA);
+
+    assert_eq!(
+        value,
+        json!({
+            "list": [
+                { "password": REDACTED, "user": "root" },
+                { "password": REDACTED, "token": REDACTED, "user": "admin" },
+            ],
+            "remote": REDACTED,
+            "remote2": REDACTED,
+            "user": "root",
+        }),
+    );
+
+    // members of combined schemas are checked too
+    assert!(REMOTE_PROPERTY_SCHEMA.is_secret());
+    assert!(!STRING_SCHEMA.is_secret());
+}
+
+const DISK_SCHEMA: Schema = ObjectSchema::new(
+    "A disk.",
+    &[
+        (
+            "size",
+            false,
+            &IntegerSchema::new("Size in GiB.")
+                .minimum(1)
+                .example("32")
+                .schema(),
+        ),
+        (
+            "storage",
+            false,
+            &StringSchema::new("The storage.").example("local").schema(),
+        ),
+    ],
+)
+.schema();
+
+const DISK_STRING_SCHEMA: Schema = StringSchema::new("A disk property string.")
+    .format(&ApiStringFormat::PropertyString(&DISK_SCHEMA))
+    .example("size=32,storage=local")
+    .schema();
+
+#[test]
+fn verify_schema_examples() -> Result<(), Error> {
+    const VM_SCHEMA: Schema = ObjectSchema::new(
+        "A VM.",
+        &[
+            (
+                "disks",
+                true,
+                &ArraySchema::new("The disks.", &DISK_STRING_SCHEMA).schema(),
+            ),
+            (
+                "memory",
+                false,
+                &NumberSchema::new("Memory in GiB.")
+                    .minimum(0.5)
+                    .example("1.5")
+                    .schema(),
+            ),
+        ],
+    )
+    .schema();
+
+    verify_examples(&VM_SCHEMA)?;
+
+    // the nested property string schema is checked too
+    const INVALID_DISK_SCHEMA: Schema = ObjectSchema::new(
+        "A disk with an invalid example.",
+        &[(
+            "size",
+            false,
+            &IntegerSchema::new("Size in GiB.")
+                .minimum(1)
+                .example("0")
+                .schema(),
+        )],
+    )
+    .schema();
+
+    const INVALID_SCHEMA: Schema = ObjectSchema::new(
+        "A VM with invalid examples.",
+        &[
+            (
+                "disk",
+                false,
+                &StringSchema::new("A disk property string.")
+                    .format(&ApiStringFormat::PropertyString(&INVALID_DISK_SCHEMA))
+                    .example("size=32,storage=local")
+                    .schema(),
+            ),
+            (
+                "memory",
+                false,
+                &NumberSchema::new("Memory in GiB.").example("lots").schema(),
+            ),
+        ],
+    )
+    .schema();
+
+    let err = verify_examples(&INVALID_SCHEMA).expect_err("invalid examples must be found");
+    let err = err
+        .downcast::<ParameterError>()
+        .expect("expected a parameter error");
+    let paths: Vec<&str> = err.errors().iter().map(|(path, _)| path.as_str()).collect();
+    assert_eq!(paths, ["disk", "disk.size", "memory"]);
+
+    Ok(())
+}
+
+fn verify_even_length(value: &str) -> Result<(), Error> {
+    if value.len() % 2 != 0 {
+        bail!("odd length");
+    }
+    Ok(())
+}
+
+api_string_verifier! {
+    EVEN_LENGTH_VERIFIER = "even-length", verify_even_length;
+}
+
+#[test]
+fn verify_format_description() -> Result<(), Error> {
+    const EVEN_SCHEMA: Schema = StringSchema::new("A string of even length.")
+        .format(&ApiStringFormat::Verifier(&EVEN_LENGTH_VERIFIER))
+        .schema();
+
+    EVEN_SCHEMA.unwrap_string_schema().check_constraints("ab")?;
+    assert!(EVEN_SCHEMA
+        .unwrap_string_schema()
+        .check_constraints("abc")
+        .is_err());
+    assert!(matches!(
+        ApiStringFormat::Verifier(&EVEN_LENGTH_VERIFIER).describe(),
+        FormatDescription::Verifier(Some("even-length")),
+    ));
+    assert!(matches!(
+        ApiStringFormat::VerifyFn(verify_even_length).describe(),
+        FormatDescription::Verifier(None),
+    ));
+    assert_eq!(schema_to_json(&EVEN_SCHEMA)["verifier"], "even-length");
+
+    const COLOR_SCHEMA: Schema = StringSchema::new("A color.")
+        .format(&ApiStringFormat::Enum(&[
+            EnumEntry::new("red", "Red."),
+            EnumEntry::new("green", "Green."),
+        ]))
+        .schema();
+    assert_eq!(
+        schema_to_json(&COLOR_SCHEMA)["enum"],
+        json!(["red", "green"])
+    );
+
+    let json = schema_to_json(&DISK_STRING_SCHEMA);
+    assert_eq!(json["format"], schema_to_json(&DISK_SCHEMA));
+
+    Ok(())
+}
+
+#[cfg(feature = "api-types")]
+#[test]
+fn verify_format_description_pattern() {
+    use proxmox_schema::api_types::{IPRE_STR, IP_REGEX, IP_SCHEMA};
+
+    let pattern = match IP_SCHEMA.unwrap_string_schema().format.unwrap().describe() {
+        FormatDescription::Pattern(pattern) => pattern,
+        other => panic!("unexpected format description {other:?}"),
+    };
+    assert_eq!(pattern, IP_REGEX.regex_string);
+    assert!(pattern.contains(IPRE_STR));
+
+    // the regex source survives into the dump, so clients can validate addresses up front
+    assert_eq!(schema_to_json(&IP_SCHEMA)["pattern"], pattern);
+}
+
+// consts rather than statics, so the `TAGGED_OBJECT` const can refer to them
+const TAG_SCHEMA: Schema = StringSchema::new("A tag.").schema();
+
+const TAG_LIST_SCHEMA: Schema = ArraySchema::new("Tags.", &TAG_SCHEMA)
+    .unique_items(true)
+    .schema();
+
+const TAGGED_OBJECT: ObjectSchema = ObjectSchema::new(
+    "object with unique tags",
+    &[
+        ("name", true, &StringSchema::new("A name.").schema()),
+        ("tags", true, &TAG_LIST_SCHEMA),
+    ],
+);
+
+static TAGGED_OBJECT_SCHEMA: Schema = TAGGED_OBJECT.schema();
+
+static UNIQUE_OBJECT_LIST_SCHEMA: Schema = ArraySchema::new("Object list.", &ANOTHER_OBJECT_SCHEMA)
+    .unique_items(true)
+    .schema();
+
+#[test]
+fn verify_unique_items() -> Result<(), Error> {
+    TAG_LIST_SCHEMA.verify_json(&json!(["a", "b", "c"]))?;
+    SIMPLE_ARRAY_SCHEMA.verify_json(&json!(["a", "a"]))?;
+
+    test_verify(
+        &TAG_LIST_SCHEMA,
+        &json!(["a", "b", "a", "b"]),
+        &[("[2]", "duplicate item, same as [0]")],
+    )?;
+
+    // objects are compared by value, independent of the order of their keys
+    UNIQUE_OBJECT_LIST_SCHEMA.verify_json(&json!([{ "another1": "x" }, { "another1": "y" }]))?;
+    test_verify(
+        &UNIQUE_OBJECT_LIST_SCHEMA,
+        &json!([
+            { "another1": "x", "another2": "y" },
+            { "another2": "y", "another1": "x" },
+        ]),
+        &[("[1]", "duplicate item, same as [0]")],
+    )?;
+
+    let err = TAGGED_OBJECT_SCHEMA
+        .verify_json_all(&json!({ "tags": ["a", "a"] }))
+        .unwrap_err();
+    compare_error(&[("tags[1]", "duplicate item, same as [0]")], err.into())?;
+
+    let param_list: Vec<(String, String)> = [("tags", "a"), ("tags", "b"), ("tags", "a")]
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+    let err = ParameterSchema::from(&TAGGED_OBJECT)
+        .parse_parameter_strings(&param_list, true)
+        .unwrap_err();
+    compare_error(&[("tags[2]", "duplicate item, same as [0]")], err.into())?;
+
+    let err = TAG_LIST_SCHEMA.parse_simple_value("a,b;b").unwrap_err();
+    compare_error(&[("[2]", "duplicate item, same as [1]")], err)?;
+    assert_eq!(
+        TAG_LIST_SCHEMA.parse_simple_value("a,b")?,
+        json!(["a", "b"])
+    );
+
+    Ok(())
+}
+
+#[test]
+fn deserialize_unique_items() -> Result<(), Error> {
+    let value: Value =
+        proxmox_schema::property_string::parse_with_schema("tags=a;b;c", &TAGGED_OBJECT_SCHEMA)?;
+    assert_eq!(value["tags"], json!(["a", "b", "c"]));
+
+    let err = proxmox_schema::property_string::parse_with_schema::<Value>(
+        "name=x,tags=a;b;a",
+        &TAGGED_OBJECT_SCHEMA,
+    )
+    .unwrap_err();
+    assert!(
+        err.to_string().contains("[2]: duplicate item, same as [0]"),
+        "{err}"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn document_unique_items() {
+    assert_eq!(schema_to_json(&TAG_LIST_SCHEMA)["uniqueItems"], true);
+    assert!(schema_to_json(&SIMPLE_ARRAY_SCHEMA)
+        .get("uniqueItems")
+        .is_none());
+
+    let text = format::get_property_description(
+        "tags",
+        &TAG_LIST_SCHEMA,
+        format::ParameterDisplayStyle::Arg,
+        format::DocumentationFormat::Full,
+    );
+    assert!(text.contains("values must be unique"), "{text}");
+}
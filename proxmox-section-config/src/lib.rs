@@ -30,6 +30,7 @@ use serde::ser::Serialize;
 use serde_json::{json, Value};
 
 use proxmox_lang::try_block;
+use proxmox_schema::de::UnknownFields;
 use proxmox_schema::format::{dump_properties, wrap_text, ParameterDisplayStyle};
 use proxmox_schema::*;
 
@@ -105,6 +106,7 @@ pub struct SectionConfig {
         fn(type_name: &str, section_id: &str, key: &str, value: &Value) -> Result<String, Error>,
 
     allow_unknown_sections: bool,
+    unknown_properties: UnknownFields,
     type_key: Option<&'static str>,
 }
 
@@ -119,6 +121,9 @@ enum ParseState<'a> {
 pub struct SectionConfigData {
     pub sections: HashMap<String, (String, Value)>,
     pub order: Vec<String>,
+    // section ID to the section type and the properties unknown to its schema
+    pub(crate) unknown_properties: HashMap<String, (String, Vec<(String, String)>)>,
+    pub(crate) ignored_properties: Vec<(String, String)>,
 }
 
 impl Default for SectionConfigData {
@@ -133,6 +138,8 @@ impl SectionConfigData {
         Self {
             sections: HashMap::new(),
             order: Vec::new(),
+            unknown_properties: HashMap::new(),
+            ignored_properties: Vec::new(),
         }
     }
 
@@ -149,9 +156,27 @@ impl SectionConfigData {
         let json = serde_json::to_value(config)?;
         self.sections
             .insert(section_id.to_string(), (type_name.to_string(), json));
+        // unknown properties of another section type don't belong to the new section
+        if matches!(self.unknown_properties.get(section_id), Some((ty, _)) if ty != type_name) {
+            self.unknown_properties.remove(section_id);
+        }
         Ok(())
     }
 
+    /// Properties unknown to the schema of a section, which are written back after the known
+    /// properties as long as the section keeps its type. See [`SectionConfig::unknown_properties`].
+    pub fn unknown_properties(&self, section_id: &str) -> &[(String, String)] {
+        match self.unknown_properties.get(section_id) {
+            Some((_, properties)) => properties,
+            None => &[],
+        }
+    }
+
+    /// The section IDs and names of unknown properties which were ignored while parsing.
+    pub fn ignored_properties(&self) -> &[(String, String)] {
+        &self.ignored_properties
+    }
+
     /// Lookup section data as json `Value`.
     pub fn lookup_json(&self, type_name: &str, id: &str) -> Result<Value, Error> {
         match self.sections.get(id) {
@@ -256,6 +281,7 @@ impl SectionConfig {
             format_section_header: Self::default_format_section_header,
             format_section_content: Self::default_format_section_content,
             allow_unknown_sections: false,
+            unknown_properties: UnknownFields::Preserve,
             type_key: None,
         }
     }
@@ -270,6 +296,7 @@ impl SectionConfig {
             format_section_header: Self::systemd_format_section_header,
             format_section_content: Self::systemd_format_section_content,
             allow_unknown_sections: false,
+            unknown_properties: UnknownFields::Preserve,
             type_key: None,
         }
     }
@@ -299,6 +326,7 @@ impl SectionConfig {
             format_section_header,
             format_section_content,
             allow_unknown_sections: false,
+            unknown_properties: UnknownFields::Preserve,
             type_key: None,
         }
     }
@@ -308,6 +336,16 @@ impl SectionConfig {
         self
     }
 
+    /// How to handle properties unknown to the schema of a section which does not allow
+    /// additional properties.
+    ///
+    /// Defaults to [`UnknownFields::Preserve`], so that a config file written by a newer version
+    /// can be parsed and written back without losing its new properties.
+    pub const fn unknown_properties(mut self, mode: UnknownFields) -> Self {
+        self.unknown_properties = mode;
+        self
+    }
+
     /// The default type key for all and unknown section types.
     pub const fn with_type_key(mut self, type_key: &'static str) -> Self {
         self.type_key = Some(type_key);
@@ -382,7 +420,10 @@ impl SectionConfig {
 
                     raw += &(self.format_section_header)(type_name, section_id, section_config)?;
 
-                    for (key, value) in section_config.as_object().unwrap() {
+                    let mut entries: Vec<_> = section_config.as_object().unwrap().iter().collect();
+                    entries.sort_by_key(|(key, _)| plugin.properties.property_weight(key));
+
+                    for (key, value) in entries {
                         if plugin.id_property.as_deref() == Some(key)
                             || plugin.type_key == Some(key)
                             || (plugin.type_key.is_none() && self.type_key == Some(key))
@@ -391,6 +432,18 @@ impl SectionConfig {
                         }
                         raw += &(self.format_section_content)(type_name, section_id, key, value)?;
                     }
+
+                    let unknown_properties = match config.unknown_properties.get(section_id) {
+                        Some((ty, properties)) if ty == type_name => &properties[..],
+                        _ => &[],
+                    };
+                    for (key, value) in unknown_properties {
+                        if section_config.get(key).is_some() {
+                            continue; // set explicitly in the meantime
+                        }
+                        let value = Value::from(value.as_str());
+                        raw += &(self.format_section_content)(type_name, section_id, key, &value)?;
+                    }
                 }
                 None if self.allow_unknown_sections => {
                     if section_id.chars().any(|c| c.is_control()) {
@@ -531,10 +584,28 @@ impl SectionConfig {
                                         (true, items)
                                     }
                                     Some((_optional, ref prop_schema)) => (false, prop_schema),
-                                    None => match plugin.properties.additional_properties() {
-                                        true => (false, &&ADDITIONAL_PROPERTY_SCHEMA),
-                                        false => bail!("unknown property '{}'", key),
-                                    },
+                                    None if plugin.properties.additional_properties() => {
+                                        (false, &&ADDITIONAL_PROPERTY_SCHEMA)
+                                    }
+                                    None => {
+                                        match self.unknown_properties {
+                                            UnknownFields::Reject => {
+                                                bail!("unknown property '{}'", key)
+                                            }
+                                            UnknownFields::IgnoreWithWarning => result
+                                                .ignored_properties
+                                                .push((section_id.clone(), key)),
+                                            UnknownFields::Preserve => result
+                                                .unknown_properties
+                                                .entry(section_id.clone())
+                                                .or_insert_with(|| {
+                                                    (plugin.type_name.clone(), Vec::new())
+                                                })
+                                                .1
+                                                .push((key, value)),
+                                        }
+                                        continue;
+                                    }
                                 };
 
                                 let value = match prop_schema.parse_simple_value(&value) {
@@ -1016,7 +1087,7 @@ fn test_section_config_with_additional_properties() {
     const ID_SCHEMA: Schema = StringSchema::new("default id schema.")
         .min_length(3)
         .schema();
-    let mut config = SectionConfig::new(&ID_SCHEMA);
+    let mut config = SectionConfig::new(&ID_SCHEMA).unknown_properties(UnknownFields::Reject);
     let mut config_with_additional = SectionConfig::new(&ID_SCHEMA);
 
     const PROPERTIES: [(&str, bool, &proxmox_schema::Schema); 2] = [
@@ -1039,6 +1110,9 @@ fn test_section_config_with_additional_properties() {
         properties: &PROPERTIES,
         additional_properties: false,
         default_key: None,
+        property_weights: &[],
+        property_requires: &[],
+        property_conflicts: &[],
     };
 
     const USER_PROPERTIES_WITH_ADDITIONAL: ObjectSchema = ObjectSchema {
@@ -1046,6 +1120,9 @@ fn test_section_config_with_additional_properties() {
         properties: &PROPERTIES,
         additional_properties: true,
         default_key: None,
+        property_weights: &[],
+        property_requires: &[],
+        property_conflicts: &[],
     };
 
     let plugin = SectionConfigPlugin::new(
@@ -1080,6 +1157,130 @@ user: root@pam
     assert!(config.write(filename, &res.unwrap()).is_err());
 }
 
+#[test]
+fn test_section_config_with_unknown_properties() {
+    let filename = "user.cfg";
+
+    const ID_SCHEMA: Schema = StringSchema::new("default id schema.")
+        .min_length(3)
+        .schema();
+
+    const USER_PROPERTIES: ObjectSchema = ObjectSchema::new(
+        "user properties",
+        &[
+            (
+                "email",
+                false,
+                &StringSchema::new("The e-mail of the user").schema(),
+            ),
+            (
+                "userid",
+                true,
+                &StringSchema::new("The id of the user (name@realm).").schema(),
+            ),
+        ],
+    );
+
+    const GROUP_PROPERTIES: ObjectSchema = ObjectSchema::new("group properties", &[]);
+
+    let config = |mode| {
+        let mut config = SectionConfig::new(&ID_SCHEMA).unknown_properties(mode);
+        config.register_plugin(SectionConfigPlugin::new(
+            "user".to_string(),
+            Some("userid".to_string()),
+            &USER_PROPERTIES,
+        ));
+        config
+    };
+
+    let raw = "\
+user: root@pam
+\temail root@example.com
+\tshinynewoption somevalue
+\tshinynewoption other value
+
+user: test@pam
+\temail test@example.com
+";
+
+    let err = config(UnknownFields::Reject)
+        .parse(filename, raw)
+        .unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("unknown property 'shinynewoption'"));
+
+    let res = config(UnknownFields::IgnoreWithWarning)
+        .parse(filename, raw)
+        .unwrap();
+    assert_eq!(
+        res.ignored_properties(),
+        [
+            ("root@pam".to_string(), "shinynewoption".to_string()),
+            ("root@pam".to_string(), "shinynewoption".to_string()),
+        ]
+    );
+    assert!(res.unknown_properties("root@pam").is_empty());
+    let written = config(UnknownFields::IgnoreWithWarning)
+        .write(filename, &res)
+        .unwrap();
+    assert!(!written.contains("shinynewoption"));
+
+    // preserving is the default
+    let mut config = SectionConfig::new(&ID_SCHEMA);
+    config.register_plugin(SectionConfigPlugin::new(
+        "user".to_string(),
+        Some("userid".to_string()),
+        &USER_PROPERTIES,
+    ));
+    config.register_plugin(SectionConfigPlugin::new(
+        "group".to_string(),
+        None,
+        &GROUP_PROPERTIES,
+    ));
+    let mut res = config.parse(filename, raw).unwrap();
+    assert!(res.ignored_properties().is_empty());
+    assert_eq!(
+        res.unknown_properties("root@pam"),
+        [
+            ("shinynewoption".to_string(), "somevalue".to_string()),
+            ("shinynewoption".to_string(), "other value".to_string()),
+        ]
+    );
+    assert_eq!(
+        res.lookup_json("user", "root@pam").unwrap()["shinynewoption"],
+        Value::Null
+    );
+
+    // a rewrite keeps the unknown properties
+    res.sections.get_mut("root@pam").unwrap().1["email"] = "admin@example.com".into();
+    let written = config.write(filename, &res).unwrap();
+    assert_eq!(
+        written,
+        raw.replace("root@example.com", "admin@example.com")
+    );
+    assert_eq!(
+        config
+            .parse(filename, &written)
+            .unwrap()
+            .unknown_properties("root@pam"),
+        res.unknown_properties("root@pam")
+    );
+
+    // but they are dropped if the section is re-created with another type
+    let mut changed = res.clone();
+    changed
+        .sections
+        .insert("root@pam".to_string(), ("group".to_string(), json!({})));
+    let written = config.write(filename, &changed).unwrap();
+    assert!(!written.contains("shinynewoption"));
+
+    res.set_data("root@pam", "group", json!({})).unwrap();
+    assert!(res.unknown_properties("root@pam").is_empty());
+    let written = config.write(filename, &res).unwrap();
+    assert!(!written.contains("shinynewoption"));
+}
+
 #[test]
 fn test_section_config_with_unknown_section_types() {
     let filename = "user.cfg";
@@ -1109,6 +1310,9 @@ fn test_section_config_with_unknown_section_types() {
         properties: &PROPERTIES,
         additional_properties: false,
         default_key: None,
+        property_weights: &[],
+        property_requires: &[],
+        property_conflicts: &[],
     };
 
     let plugin = SectionConfigPlugin::new(
@@ -144,6 +144,8 @@ fn to_pair(
 pub struct SectionConfigData<T> {
     pub sections: HashMap<String, T>,
     pub order: Vec<String>,
+    unknown_properties: HashMap<String, (String, Vec<(String, String)>)>,
+    ignored_properties: Vec<(String, String)>,
 }
 
 impl<T> Default for SectionConfigData<T> {
@@ -151,10 +153,27 @@ impl<T> Default for SectionConfigData<T> {
         Self {
             sections: HashMap::new(),
             order: Vec::new(),
+            unknown_properties: HashMap::new(),
+            ignored_properties: Vec::new(),
         }
     }
 }
 
+impl<T> SectionConfigData<T> {
+    /// See [`SectionConfigData::unknown_properties`](crate::SectionConfigData::unknown_properties).
+    pub fn unknown_properties(&self, section_id: &str) -> &[(String, String)] {
+        match self.unknown_properties.get(section_id) {
+            Some((_, properties)) => properties,
+            None => &[],
+        }
+    }
+
+    /// See [`SectionConfigData::ignored_properties`](crate::SectionConfigData::ignored_properties).
+    pub fn ignored_properties(&self) -> &[(String, String)] {
+        &self.ignored_properties
+    }
+}
+
 impl<T: ApiSectionDataEntry + DeserializeOwned> TryFrom<RawSectionConfigData>
     for SectionConfigData<T>
 {
@@ -171,6 +190,8 @@ impl<T: ApiSectionDataEntry + DeserializeOwned> TryFrom<RawSectionConfigData>
         Ok(Self {
             sections,
             order: data.order,
+            unknown_properties: data.unknown_properties,
+            ignored_properties: data.ignored_properties,
         })
     }
 }
@@ -204,6 +225,8 @@ impl<T: Serialize + ApiSectionDataEntry> TryFrom<SectionConfigData<T>> for RawSe
         Ok(Self {
             sections,
             order: data.order,
+            unknown_properties: data.unknown_properties,
+            ignored_properties: data.ignored_properties,
         })
     }
 }
@@ -223,6 +246,8 @@ impl<T: Serialize + ApiSectionDataEntry> TryFrom<&SectionConfigData<T>> for RawS
         Ok(Self {
             sections,
             order: data.order.clone(),
+            unknown_properties: data.unknown_properties.clone(),
+            ignored_properties: data.ignored_properties.clone(),
         })
     }
 }
@@ -232,7 +257,7 @@ impl<T: ApiSectionDataEntry> From<HashMap<String, T>> for SectionConfigData<T> {
     fn from(sections: HashMap<String, T>) -> Self {
         Self {
             sections,
-            order: Vec::new(),
+            ..Default::default()
         }
     }
 }
@@ -248,7 +273,11 @@ impl<T: ApiSectionDataEntry> FromIterator<(String, T)> for SectionConfigData<T>
             sections.insert(key, value);
         }
 
-        Self { sections, order }
+        Self {
+            sections,
+            order,
+            ..Default::default()
+        }
     }
 }
 
@@ -0,0 +1,251 @@
+use std::ffi::OsString;
+use std::fs::File;
+use std::ops::Range;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use anyhow::{bail, format_err, Error};
+use nix::fcntl::{FlockArg, OFlag};
+
+use crate::fs::{atomic_open_or_create_file, file_read_optional, replace_file, CreateOptions};
+
+// a counter file only contains a number and a newline
+const COUNTER_FILE_MAX_SIZE: u64 = 64;
+
+/// A persistent counter handing out unique, increasing sequence numbers.
+///
+/// The value is stored as text in the counter file and a backup copy next to it
+/// (`<path>.bak`). Both are updated via [`replace_file`] while holding an exclusive `flock(2)` on
+/// `<path>.lck`, so no value is handed out twice, even to concurrent processes. A counter file
+/// which got damaged (e.g. truncated after a crash) is repaired from the backup copy.
+///
+/// The first value handed out is `1`.
+pub struct AtomicCounterFile {
+    path: PathBuf,
+    backup_path: PathBuf,
+    lock_file: Mutex<File>,
+    options: CreateOptions,
+    batch_size: u64,
+    reserved: Mutex<Range<u64>>,
+}
+
+fn path_with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = OsString::from(path);
+    path.push(suffix);
+    path.into()
+}
+
+impl AtomicCounterFile {
+    /// Open the counter stored at `path`, the files are created with `options` on first use.
+    pub fn open<P: AsRef<Path>>(path: P, options: CreateOptions) -> Result<Self, Error> {
+        let path = path.as_ref();
+
+        let lock_path = path_with_suffix(path, ".lck");
+        let lock_file = atomic_open_or_create_file(
+            &lock_path,
+            OFlag::O_RDWR | OFlag::O_CLOEXEC,
+            &[],
+            options.clone(),
+            false,
+        )
+        .map_err(|err| format_err!("unable to open counter lock file {lock_path:?} - {err}"))?;
+
+        Ok(Self {
+            path: path.to_owned(),
+            backup_path: path_with_suffix(path, ".bak"),
+            lock_file: Mutex::new(lock_file),
+            options,
+            batch_size: 1,
+            reserved: Mutex::new(0..0),
+        })
+    }
+
+    /// Reserve `batch_size` values at once in [`next`](Self::next), to reduce the number of file
+    /// updates on hot paths.
+    ///
+    /// Values reserved but not handed out until the handle is dropped are lost, and values are
+    /// only increasing per handle, not across handles.
+    pub fn batch_size(mut self, batch_size: u64) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Get the next value.
+    pub fn next(&self) -> Result<u64, Error> {
+        let mut reserved = self.reserved.lock().unwrap();
+        if reserved.is_empty() {
+            *reserved = self.reserve(self.batch_size)?;
+        }
+        Ok(reserved.next().unwrap())
+    }
+
+    /// Reserve `count` consecutive values at once.
+    pub fn reserve(&self, count: u64) -> Result<Range<u64>, Error> {
+        if count == 0 {
+            bail!("cannot reserve zero values from counter {:?}", self.path);
+        }
+
+        self.locked(|| {
+            let current = self.read_value()?;
+            let last = current
+                .checked_add(count)
+                .ok_or_else(|| format_err!("counter {:?} overflowed", self.path))?;
+            self.write_value(last)?;
+            Ok(current + 1..last + 1)
+        })
+    }
+
+    /// The last value handed out by any handle, `0` if there was none.
+    pub fn current(&self) -> Result<u64, Error> {
+        self.locked(|| self.read_value())
+    }
+
+    fn locked<T>(&self, func: impl FnOnce() -> Result<T, Error>) -> Result<T, Error> {
+        let lock_file = self.lock_file.lock().unwrap();
+        nix::fcntl::flock(lock_file.as_raw_fd(), FlockArg::LockExclusive)
+            .map_err(|err| format_err!("unable to lock counter {:?} - {err}", self.path))?;
+
+        let result = func();
+
+        let _ = nix::fcntl::flock(lock_file.as_raw_fd(), FlockArg::Unlock);
+        result
+    }
+
+    // Both files are written on every update, so the larger valid value of the two is the last
+    // one handed out even if one update got interrupted.
+    fn read_value(&self) -> Result<u64, Error> {
+        let value = read_counter_file(&self.path);
+        let backup = read_counter_file(&self.backup_path);
+
+        match (value, backup) {
+            (Ok(value), Ok(backup)) => Ok(value.max(backup).unwrap_or(0)),
+            (Ok(Some(value)), Err(_)) => Ok(value),
+            (Err(_), Ok(Some(backup))) => {
+                log::warn!("counter file {:?} damaged, using backup copy", self.path);
+                Ok(backup)
+            }
+            (Err(err), _) | (_, Err(err)) => Err(err),
+        }
+    }
+
+    fn write_value(&self, value: u64) -> Result<(), Error> {
+        let data = format!("{value}\n");
+        for path in [&self.backup_path, &self.path] {
+            replace_file(path, data.as_bytes(), self.options.clone(), true)
+                .map_err(|err| format_err!("unable to update counter file {path:?} - {err}"))?;
+        }
+        Ok(())
+    }
+}
+
+fn read_counter_file(path: &Path) -> Result<Option<u64>, Error> {
+    let data = match file_read_optional(path, COUNTER_FILE_MAX_SIZE)? {
+        Some(data) => data,
+        None => return Ok(None),
+    };
+
+    std::str::from_utf8(&data)
+        .ok()
+        .and_then(|data| data.strip_suffix('\n'))
+        .and_then(|data| data.parse().ok())
+        .map(Some)
+        .ok_or_else(|| format_err!("counter file {path:?} is damaged"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use nix::sys::stat;
+    use nix::unistd;
+
+    use super::*;
+
+    fn tmp_dir() -> Result<PathBuf, Error> {
+        let options = CreateOptions::new()
+            .owner(unistd::Uid::effective())
+            .group(unistd::Gid::effective())
+            .perm(stat::Mode::from_bits_truncate(0o700));
+        crate::fs::make_tmp_dir("/tmp", Some(options))
+    }
+
+    fn collect_concurrent(path: &Path, batch_size: u64) -> Result<Vec<u64>, Error> {
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let counter = AtomicCounterFile::open(path, CreateOptions::new())?;
+                let counter = counter.batch_size(batch_size);
+                Ok(std::thread::spawn(move || {
+                    (0..50)
+                        .map(|_| counter.next().unwrap())
+                        .collect::<Vec<u64>>()
+                }))
+            })
+            .collect::<Result<_, Error>>()?;
+
+        let mut values = Vec::new();
+        for handle in handles {
+            let thread_values = handle.join().unwrap();
+            assert!(thread_values.windows(2).all(|pair| pair[0] < pair[1]));
+            values.extend(thread_values);
+        }
+        Ok(values)
+    }
+
+    #[test]
+    fn test_counter_concurrent_handles() -> Result<(), Error> {
+        let dir = tmp_dir()?;
+        let path = dir.join("counter");
+
+        let values = collect_concurrent(&path, 1)?;
+        let unique: HashSet<u64> = values.iter().copied().collect();
+        assert_eq!(unique.len(), 200);
+        assert_eq!(unique, (1..=200).collect());
+
+        let batched = collect_concurrent(&path, 16)?;
+        let mut unique: HashSet<u64> = values.into_iter().collect();
+        for value in batched {
+            assert!(value > 200);
+            assert!(unique.insert(value), "value {value} handed out twice");
+        }
+
+        let counter = AtomicCounterFile::open(&path, CreateOptions::new())?;
+        assert_eq!(counter.current()?, 200 + 4 * 64);
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_counter_repair() -> Result<(), Error> {
+        let dir = tmp_dir()?;
+        let path = dir.join("counter");
+
+        let counter = AtomicCounterFile::open(&path, CreateOptions::new())?;
+        assert_eq!(counter.current()?, 0);
+        assert_eq!(counter.reserve(10)?, 1..11);
+        assert!(counter.reserve(0).is_err());
+
+        // truncated counter file
+        std::fs::write(&path, b"1")?;
+        assert_eq!(counter.next()?, 11);
+        assert_eq!(std::fs::read(&path)?, b"11\n");
+
+        // damaged backup copy
+        std::fs::write(dir.join("counter.bak"), b"")?;
+        assert_eq!(counter.next()?, 12);
+
+        std::fs::remove_file(&path)?;
+        assert_eq!(counter.next()?, 13);
+
+        std::fs::write(&path, b"x")?;
+        std::fs::write(dir.join("counter.bak"), b"14")?;
+        assert!(counter.next().is_err());
+
+        std::fs::write(&path, format!("{}\n", u64::MAX))?;
+        assert!(counter.next().is_err());
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
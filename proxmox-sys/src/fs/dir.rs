@@ -8,16 +8,19 @@ use anyhow::{bail, format_err, Error};
 use nix::errno::Errno;
 use nix::fcntl::OFlag;
 use nix::sys::stat;
-use nix::unistd;
 
 use proxmox_lang::try_block;
 
-use crate::fs::{fchown, CreateOptions};
+use crate::fs::{ApplyTarget, CreateOptions};
 
 /// Creates directory at the provided path with specified ownership.
 ///
+/// The permissions (`0o750` by default) are subject to the umask, like for [`create_path`].
+///
 /// Errors if the directory already exists.
 pub fn create_dir<P: AsRef<Path>>(path: P, options: CreateOptions) -> Result<(), Error> {
+    options.validate()?;
+
     // clippy bug?: from_bits_truncate is actually a const fn...
     #[allow(clippy::or_fun_call)]
     let mode: stat::Mode = options
@@ -29,15 +32,13 @@ pub fn create_dir<P: AsRef<Path>>(path: P, options: CreateOptions) -> Result<(),
     nix::unistd::mkdir(path, mode)
         .map_err(|err| format_err!("unable to create directory {path:?} - {err}"))?;
 
-    unistd::chown(path, options.owner, options.group)
-        .map_err(|err| format_err!("unable to set ownership for directory {path:?} - {err}"))?;
-
-    Ok(())
+    options.apply(ApplyTarget::Path(path), path, None)
 }
 
 /// Ensure a directory exists.
 ///
-/// Like [create_dir], but does not fail if the directory already exists.
+/// Like [create_dir], but does not fail if the directory already exists. Contrary to
+/// [create_dir], the permissions are set exactly, without applying the umask.
 ///
 /// Directory permissions are verified and raise an error if enforce_permissions is set.
 pub fn ensure_dir_exists<P: AsRef<Path>>(
@@ -45,8 +46,7 @@ pub fn ensure_dir_exists<P: AsRef<Path>>(
     options: &CreateOptions,
     enforce_permissions: bool,
 ) -> Result<(), Error> {
-    let uid = options.owner;
-    let gid = options.group;
+    options.validate()?;
 
     let mode: stat::Mode = options
         .perm
@@ -69,13 +69,8 @@ pub fn ensure_dir_exists<P: AsRef<Path>>(
     let fd = nix::fcntl::open(path, OFlag::O_DIRECTORY, stat::Mode::empty())
         .map(|fd| unsafe { OwnedFd::from_raw_fd(fd) })
         .map_err(|err| format_err!("unable to open created directory {path:?} - {err}"))?;
-    // umask defaults to 022 so make sure the mode is fully honowed:
-    nix::sys::stat::fchmod(fd.as_raw_fd(), mode)
-        .map_err(|err| format_err!("unable to set mode for directory {path:?} - {err}"))?;
-    nix::unistd::fchown(fd.as_raw_fd(), uid, gid)
-        .map_err(|err| format_err!("unable to set ownership for directory {path:?} - {err}"))?;
-
-    Ok(())
+    // umask defaults to 022 so make sure the mode is fully honored
+    options.apply(ApplyTarget::Fd(fd.as_raw_fd()), path, Some(mode))
 }
 
 /// Recursively create a path with separately defined metadata for intermediate directories and the
@@ -84,6 +79,8 @@ pub fn ensure_dir_exists<P: AsRef<Path>>(
 /// Returns `true` if the final directory was created. Otherwise `false` is returned and no changes
 /// to the directory's metadata have been performed.
 ///
+/// The permissions (`0o755` by default) of created directories are subject to the umask.
+///
 /// ```no_run
 /// # use nix::sys::stat::Mode;
 /// # use nix::unistd::{Gid, Uid};
@@ -115,6 +112,10 @@ fn create_path_do(
 ) -> Result<bool, Error> {
     use std::path::Component;
 
+    for opts in intermediate_opts.iter().chain(final_opts.iter()) {
+        opts.validate()?;
+    }
+
     let mut iter = path.components().peekable();
     let at: OwnedFd = match iter.peek() {
         Some(Component::Prefix(_)) => bail!("illegal prefix path component encountered"),
@@ -178,9 +179,7 @@ fn create_path_at_do(
                 at = crate::fd::openat(&at, path, OFlag::O_DIRECTORY, stat::Mode::empty())?;
 
                 if let (true, Some(opts)) = (created, opts) {
-                    if opts.owner.is_some() || opts.group.is_some() {
-                        fchown(at.as_raw_fd(), opts.owner, opts.group)?;
-                    }
+                    opts.apply(ApplyTarget::Fd(at.as_raw_fd()), Path::new(path), None)?;
                 }
             }
 
@@ -238,6 +237,8 @@ pub fn make_tmp_dir<P: AsRef<Path>>(
 
 #[cfg(test)]
 mod tests {
+    use nix::unistd;
+
     use super::*;
 
     #[test]
@@ -270,4 +271,184 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_create_options_validate() -> Result<(), Error> {
+        let setuid = stat::Mode::from_bits_truncate(0o4755);
+        let setgid = stat::Mode::from_bits_truncate(0o2775);
+
+        assert!(CreateOptions::new().perm(setgid).validate().is_ok());
+        assert!(CreateOptions::new().perm(setuid).validate().is_err());
+        assert!(CreateOptions::new()
+            .perm(setuid)
+            .allow_setuid()
+            .validate()
+            .is_err());
+        assert!(CreateOptions::new()
+            .perm(setuid)
+            .owner(unistd::Uid::effective())
+            .allow_setuid()
+            .validate()
+            .is_ok());
+
+        // nothing is created with invalid options
+        let dir = make_tmp_dir("/tmp", None)?;
+        let path = dir.join("setuid");
+        assert!(create_dir(&path, CreateOptions::new().perm(setuid)).is_err());
+        assert!(!path.exists());
+        assert!(create_path(&path, None, Some(CreateOptions::new().perm(setuid))).is_err());
+        assert!(!path.exists());
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_create_options_permission_denied() -> Result<(), Error> {
+        use crate::fs::PermissionDeniedError;
+
+        // the failures are simulated, so the result does not depend on the test's privileges
+        let path = Path::new("/some/file");
+        let err = CreateOptions::new()
+            .check_applied(Err(Errno::EPERM), path, "chown")
+            .unwrap_err();
+        let err = err.downcast_ref::<PermissionDeniedError>().unwrap();
+        assert_eq!(err.path, path);
+        assert_eq!(err.operation, "chown");
+        assert_eq!(err.errno, Errno::EPERM);
+
+        let degraded = CreateOptions::new().allow_degraded();
+        degraded.check_applied(Err(Errno::EACCES), path, "chmod")?;
+        // other errors are not caused by missing privileges
+        let err = degraded
+            .check_applied(Err(Errno::EROFS), path, "chmod")
+            .unwrap_err();
+        assert!(!err.is::<PermissionDeniedError>());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_create_options_unprivileged() -> Result<(), Error> {
+        use std::os::unix::fs::PermissionsExt;
+        use std::os::unix::process::CommandExt;
+
+        use crate::fs::PermissionDeniedError;
+
+        if unistd::Uid::effective().is_root() {
+            // run this test again as `nobody`, without the privileges to change the owner, from a
+            // copy of the test binary, as its location may not be accessible for `nobody`
+            let dir = make_tmp_dir(
+                "/tmp",
+                Some(CreateOptions::new().perm(stat::Mode::from_bits_truncate(0o755))),
+            )?;
+            let exe = dir.join("test");
+            std::fs::copy(std::env::current_exe()?, &exe)?;
+            let status = std::process::Command::new(&exe)
+                .args([
+                    "--exact",
+                    "fs::dir::tests::test_create_options_unprivileged",
+                ])
+                .uid(65534)
+                .gid(65534)
+                .status();
+            std::fs::remove_dir_all(&dir)?;
+            assert!(status?.success());
+            return Ok(());
+        }
+
+        let dir = make_tmp_dir("/tmp", None)?;
+        let mode = |path: &Path| -> Result<u32, Error> {
+            Ok(std::fs::metadata(path)?.permissions().mode() & 0o7777)
+        };
+
+        let file = dir.join("file");
+        std::fs::write(&file, "data")?;
+        std::fs::set_permissions(&file, std::fs::Permissions::from_mode(0o640))?;
+
+        let err = CreateOptions::new()
+            .owner_root()
+            .apply_to_path(&file)
+            .unwrap_err();
+        let err = err.downcast_ref::<PermissionDeniedError>().unwrap();
+        assert_eq!(err.path, file);
+        assert_eq!(err.operation, "chown");
+        assert_eq!(err.errno, Errno::EPERM);
+        // the mode is kept without explicit permissions
+        assert_eq!(mode(&file)?, 0o640);
+
+        CreateOptions::new()
+            .perm(stat::Mode::from_bits_truncate(0o600))
+            .owner_root()
+            .allow_degraded()
+            .apply_to_path(&file)?;
+        assert_eq!(mode(&file)?, 0o600);
+
+        // the creation helpers behave the same
+        let options = CreateOptions::new()
+            .perm(stat::Mode::from_bits_truncate(0o700))
+            .root_only();
+        let err = create_dir(dir.join("strict"), options.clone()).unwrap_err();
+        assert!(err.is::<PermissionDeniedError>());
+        let err = ensure_dir_exists(dir.join("strict"), &options, false).unwrap_err();
+        assert!(err.is::<PermissionDeniedError>());
+        assert!(crate::fs::replace_file(dir.join("file"), b"", options.clone(), false).is_err());
+
+        let options = options.allow_degraded();
+        create_dir(dir.join("degraded"), options.clone())?;
+        assert_eq!(mode(&dir.join("degraded"))?, 0o700);
+        ensure_dir_exists(dir.join("degraded"), &options, false)?;
+        create_path(
+            dir.join("a/b"),
+            Some(options.clone()),
+            Some(options.clone()),
+        )?;
+        crate::fs::replace_file(dir.join("file"), b"new", options, false)?;
+        assert_eq!(std::fs::read(dir.join("file"))?, b"new");
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_create_options_mode() -> Result<(), Error> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mode = |path: &Path| -> Result<u32, Error> {
+            Ok(std::fs::metadata(path)?.permissions().mode() & 0o7777)
+        };
+        // read the umask without changing it for the tests running in parallel
+        let status = std::fs::read_to_string("/proc/self/status")?;
+        let umask = status
+            .lines()
+            .find_map(|line| line.strip_prefix("Umask:"))
+            .map(|umask| u32::from_str_radix(umask.trim(), 8))
+            .ok_or_else(|| format_err!("no umask in /proc/self/status"))??;
+
+        let dir = make_tmp_dir("/tmp", None)?;
+        let options = CreateOptions::new()
+            .perm(stat::Mode::from_bits_truncate(0o777))
+            .owner(unistd::Uid::effective())
+            .group(unistd::Gid::effective());
+
+        // like mkdir, the creation helpers apply the umask
+        create_dir(dir.join("dir"), options.clone())?;
+        assert_eq!(mode(&dir.join("dir"))?, 0o777 & !umask);
+        create_path(
+            dir.join("a/b"),
+            Some(options.clone()),
+            Some(options.clone()),
+        )?;
+        assert_eq!(mode(&dir.join("a"))?, 0o777 & !umask);
+        assert_eq!(mode(&dir.join("a/b"))?, 0o777 & !umask);
+
+        // while these set the mode exactly
+        ensure_dir_exists(dir.join("exact"), &options, true)?;
+        assert_eq!(mode(&dir.join("exact"))?, 0o777);
+        options.apply_to_path(dir.join("dir"))?;
+        assert_eq!(mode(&dir.join("dir"))?, 0o777);
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
 }
@@ -1,5 +1,5 @@
 use std::fs::File;
-use std::io::{self, BufRead, BufReader, Write};
+use std::io::{self, BufRead, BufReader, Read, Write};
 use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd};
 use std::path::{Path, PathBuf};
 #[cfg(feature = "timer")]
@@ -78,6 +78,118 @@ pub fn file_read_optional_string<P: AsRef<Path>>(path: P) -> Result<Option<Strin
     }
 }
 
+/// Error returned by the size limited read functions if a file is larger than allowed.
+///
+/// This is wrapped in an `anyhow::Error`, use `downcast_ref` to detect it.
+#[derive(Debug)]
+pub struct FileTooLargeError {
+    /// The file which was read.
+    pub path: PathBuf,
+    /// The size limit in bytes.
+    pub max_size: u64,
+}
+
+impl std::fmt::Display for FileTooLargeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "unable to read {:?} - file exceeds size limit of {} bytes",
+            self.path, self.max_size
+        )
+    }
+}
+
+impl std::error::Error for FileTooLargeError {}
+
+// read at most `max_size` bytes, a reader with more data fails with `FileTooLargeError`
+fn read_limited<R: io::Read>(reader: R, path: &Path, max_size: u64) -> Result<Vec<u8>, Error> {
+    let mut data = Vec::new();
+    match reader
+        .take(max_size.saturating_add(1))
+        .read_to_end(&mut data)
+    {
+        Ok(_) if data.len() as u64 > max_size => Err(FileTooLargeError {
+            path: path.to_path_buf(),
+            max_size,
+        }
+        .into()),
+        Ok(_) => Ok(data),
+        // non-blocking special files without any more data
+        Err(err) if err.kind() == io::ErrorKind::WouldBlock => Ok(data),
+        Err(err) => bail!("unable to read {path:?} - {err}"),
+    }
+}
+
+/// Read the entire contents of a file into a bytes vector, failing if it is larger than
+/// `max_size` bytes
+///
+/// Files exceeding the limit produce a [`FileTooLargeError`], the file is not read any further
+/// than the limit.
+pub fn file_get_contents_limited<P: AsRef<Path>>(path: P, max_size: u64) -> Result<Vec<u8>, Error> {
+    let path = path.as_ref();
+
+    let file = File::open(path).map_err(|err| format_err!("unable to open {path:?} - {err}"))?;
+    read_limited(file, path, max_size)
+}
+
+/// Read the entire contents of a file into a bytes vector if the file exists, failing if it is
+/// larger than `max_size` bytes
+///
+/// Same as [`file_get_contents_limited`], but returns `Ok(None)` if the file does not exist.
+/// All other errors, including permission errors, are still reported.
+pub fn file_read_optional<P: AsRef<Path>>(
+    path: P,
+    max_size: u64,
+) -> Result<Option<Vec<u8>>, Error> {
+    let path = path.as_ref();
+
+    match File::open(path) {
+        Ok(file) => read_limited(file, path, max_size).map(Some),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => bail!("unable to open {path:?} - {err}"),
+    }
+}
+
+/// Read the entire contents of a file without blocking on special files, failing if it is
+/// larger than `max_size` bytes
+///
+/// The file is opened with `O_NONBLOCK`, so opening a FIFO without writer does not hang. Reading
+/// from anything but a regular file, like FIFOs or devices, is refused unless
+/// `allow_special_files` is set, in which case only the currently available data is returned.
+pub fn file_get_contents_nonblocking<P: AsRef<Path>>(
+    path: P,
+    max_size: u64,
+    allow_special_files: bool,
+) -> Result<Vec<u8>, Error> {
+    use std::os::unix::fs::{FileTypeExt, OpenOptionsExt};
+
+    let path = path.as_ref();
+
+    let file = std::fs::OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NONBLOCK | libc::O_NOCTTY)
+        .open(path)
+        .map_err(|err| format_err!("unable to open {path:?} - {err}"))?;
+
+    let file_type = file
+        .metadata()
+        .map_err(|err| format_err!("unable to stat {path:?} - {err}"))?
+        .file_type();
+
+    if !file_type.is_file() && !allow_special_files {
+        let kind = if file_type.is_fifo() {
+            "FIFO"
+        } else if file_type.is_block_device() || file_type.is_char_device() {
+            "device"
+        } else {
+            "special file"
+        };
+        bail!("unable to read {path:?} - refusing to read from {kind}");
+    }
+
+    read_limited(file, path, max_size)
+}
+
 /// Read .json file into a ``Value``
 ///
 /// The optional ``default`` is used when the file does not exist.
@@ -443,3 +555,97 @@ pub fn file_get_non_comment_lines<P: AsRef<Path>>(
         Err(err) => Some(Err(err)),
     }))
 }
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    use super::*;
+
+    fn tmp_dir() -> Result<PathBuf, Error> {
+        let options = CreateOptions::new()
+            .owner(unistd::Uid::effective())
+            .group(unistd::Gid::effective())
+            .perm(stat::Mode::from_bits_truncate(0o700));
+        crate::fs::make_tmp_dir("/tmp", Some(options))
+    }
+
+    #[test]
+    fn test_file_get_contents_limited() -> Result<(), Error> {
+        let dir = tmp_dir()?;
+        let path = dir.join("data");
+        std::fs::write(&path, b"0123456789")?;
+
+        assert_eq!(file_get_contents_limited(&path, 10)?, b"0123456789");
+        assert_eq!(file_get_contents_limited(&path, 100)?, b"0123456789");
+
+        let err = file_get_contents_limited(&path, 9).unwrap_err();
+        let err = err.downcast_ref::<FileTooLargeError>().unwrap();
+        assert_eq!(err.path, path);
+        assert_eq!(err.max_size, 9);
+
+        assert!(file_get_contents_limited(dir.join("missing"), 10).is_err());
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_read_optional() -> Result<(), Error> {
+        let dir = tmp_dir()?;
+        let path = dir.join("data");
+
+        assert_eq!(file_read_optional(&path, 10)?, None);
+
+        std::fs::write(&path, b"data")?;
+        assert_eq!(
+            file_read_optional(&path, 10)?.as_deref(),
+            Some(&b"data"[..])
+        );
+        assert!(file_read_optional(&path, 3)
+            .unwrap_err()
+            .is::<FileTooLargeError>());
+
+        // other errors than a missing file are reported
+        assert!(file_read_optional(dir.join("data/sub"), 10).is_err());
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_get_contents_nonblocking() -> Result<(), Error> {
+        let dir = tmp_dir()?;
+
+        let path = dir.join("data");
+        std::fs::write(&path, b"data")?;
+        assert_eq!(file_get_contents_nonblocking(&path, 10, false)?, b"data");
+        assert!(file_get_contents_nonblocking(&path, 3, false)
+            .unwrap_err()
+            .is::<FileTooLargeError>());
+
+        // neither opening nor reading a FIFO without writer may block
+        let fifo = dir.join("fifo");
+        unistd::mkfifo(&fifo, stat::Mode::from_bits_truncate(0o600))?;
+        let err = file_get_contents_nonblocking(&fifo, 10, false).unwrap_err();
+        assert!(err.to_string().contains("refusing to read from FIFO"));
+        assert_eq!(file_get_contents_nonblocking(&fifo, 10, true)?, b"");
+
+        // only the available data is read from a FIFO with an idle writer, opening the writer
+        // needs a reader to not block
+        let _reader = std::fs::OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(&fifo)?;
+        let mut writer = std::fs::OpenOptions::new().write(true).open(&fifo)?;
+        writer.write_all(b"fifo")?;
+        assert_eq!(file_get_contents_nonblocking(&fifo, 10, true)?, b"fifo");
+        drop(writer);
+
+        assert!(file_get_contents_nonblocking("/dev/null", 10, false).is_err());
+        assert_eq!(file_get_contents_nonblocking("/dev/null", 10, true)?, b"");
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
@@ -1,8 +1,9 @@
 //! File system related utilities
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use anyhow::{bail, Context, Error};
 
+use nix::errno::Errno;
 use nix::sys::stat;
 use nix::unistd::{Gid, Uid};
 use std::os::unix::io::{AsRawFd, RawFd};
@@ -13,6 +14,9 @@ pub mod acl;
 mod file;
 pub use file::*;
 
+mod counter;
+pub use counter::*;
+
 mod dir;
 pub use dir::*;
 
@@ -22,19 +26,61 @@ pub use read_dir::*;
 mod fsx_attr;
 pub use fsx_attr::*;
 
+mod temp;
+pub use temp::*;
+
 pub mod xattr;
 
+#[cfg(feature = "watch")]
+mod watch;
+#[cfg(feature = "watch")]
+pub use watch::*;
+
 /// Change ownership of an open file handle
 pub fn fchown(fd: RawFd, owner: Option<Uid>, group: Option<Gid>) -> Result<(), Error> {
     nix::unistd::fchown(fd, owner, group).map_err(|err| err.into())
 }
 
+/// Error returned if the mode or ownership of a file could not be set due to missing privileges,
+/// e.g. when changing the owner without `CAP_CHOWN`.
+///
+/// This is wrapped in an `anyhow::Error`, use `downcast_ref` to detect it. Use
+/// [`CreateOptions::allow_degraded`] to only log such failures.
+#[derive(Debug)]
+pub struct PermissionDeniedError {
+    /// The file whose mode or ownership was changed.
+    pub path: PathBuf,
+    /// The failed operation, `chmod` or `chown`.
+    pub operation: &'static str,
+    /// The error of the operation, `EPERM` or `EACCES`.
+    pub errno: Errno,
+}
+
+impl std::fmt::Display for PermissionDeniedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {:?} failed: {}",
+            self.operation, self.path, self.errno
+        )
+    }
+}
+
+impl std::error::Error for PermissionDeniedError {}
+
+enum ApplyTarget<'a> {
+    Fd(RawFd),
+    Path(&'a Path),
+}
+
 /// Define permissions, owner and group when creating files/dirs
 #[derive(Clone, Default)]
 pub struct CreateOptions {
     perm: Option<stat::Mode>,
     owner: Option<Uid>,
     group: Option<Gid>,
+    allow_degraded: bool,
+    allow_setuid: bool,
 }
 
 impl CreateOptions {
@@ -44,6 +90,8 @@ impl CreateOptions {
             perm: None,
             owner: None,
             group: None,
+            allow_degraded: false,
+            allow_setuid: false,
         }
     }
 
@@ -67,23 +115,124 @@ impl CreateOptions {
         self.owner(nix::unistd::ROOT)
     }
 
+    /// Only log a warning instead of failing with a [`PermissionDeniedError`] if the mode or
+    /// ownership cannot be set due to missing privileges.
+    pub const fn allow_degraded(mut self) -> Self {
+        self.allow_degraded = true;
+        self
+    }
+
+    /// Allow the setuid bit in the permissions, which is refused by default since a setuid file
+    /// runs with the privileges of its owner.
+    pub const fn allow_setuid(mut self) -> Self {
+        self.allow_setuid = true;
+        self
+    }
+
+    /// Check that the options make sense.
+    ///
+    /// The setuid bit is refused unless allowed via [`allow_setuid`](Self::allow_setuid), and
+    /// additionally requires an explicit owner. The creation helpers of this module check this
+    /// before creating anything.
+    pub fn validate(&self) -> Result<(), Error> {
+        let perm = match self.perm {
+            Some(perm) => perm,
+            None => return Ok(()),
+        };
+
+        if perm.contains(stat::Mode::S_ISUID) {
+            if !self.allow_setuid {
+                bail!(
+                    "refusing to set the setuid bit (0o{:o}) without explicitly allowing it",
+                    perm.bits()
+                );
+            }
+            if self.owner.is_none() {
+                bail!("refusing to set the setuid bit without an explicit owner");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Set the mode (`0o644` by default) and ownership of an open file.
+    ///
+    /// `path` is only used for error messages. Failures due to missing privileges are reported as
+    /// [`PermissionDeniedError`], unless [`allow_degraded`](Self::allow_degraded) is set.
     pub fn apply_to<F: AsRawFd>(&self, file: &mut F, path: &Path) -> Result<(), Error> {
+        self.validate()?;
         // clippy bug?: from_bits_truncate is actually a const fn...
         #[allow(clippy::or_fun_call)]
-        let mode: stat::Mode = self.perm.unwrap_or(stat::Mode::from_bits_truncate(0o644));
+        let mode = self.perm.unwrap_or(stat::Mode::from_bits_truncate(0o644));
+        self.apply(ApplyTarget::Fd(file.as_raw_fd()), path, Some(mode))
+    }
 
-        if let Err(err) = stat::fchmod(file.as_raw_fd(), mode) {
-            bail!("fchmod {:?} failed: {}", path, err);
+    /// Set the mode and ownership of an existing file or directory, following symlinks.
+    ///
+    /// Contrary to [`apply_to`](Self::apply_to), the mode is left unchanged if no permissions
+    /// are set.
+    pub fn apply_to_path<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let path = path.as_ref();
+        self.validate()?;
+        self.apply(ApplyTarget::Path(path), path, self.perm)
+    }
+
+    /// Set `mode`, if any, and the ownership. Used by all helpers creating files and
+    /// directories.
+    ///
+    /// The mode is set as is, without applying the umask. Helpers which pass the mode to the
+    /// creating system call instead, so that the umask applies, pass `None`.
+    fn apply(
+        &self,
+        target: ApplyTarget,
+        path: &Path,
+        mode: Option<stat::Mode>,
+    ) -> Result<(), Error> {
+        if let Some(mode) = mode {
+            let result = match target {
+                ApplyTarget::Fd(fd) => stat::fchmod(fd, mode),
+                ApplyTarget::Path(path) => {
+                    stat::fchmodat(None, path, mode, stat::FchmodatFlags::FollowSymlink)
+                }
+            };
+            self.check_applied(result, path, "chmod")?;
         }
 
         if self.owner.is_some() || self.group.is_some() {
-            if let Err(err) = fchown(file.as_raw_fd(), self.owner, self.group) {
-                bail!("fchown {:?} failed: {}", path, err);
-            }
+            let result = match target {
+                ApplyTarget::Fd(fd) => nix::unistd::fchown(fd, self.owner, self.group),
+                ApplyTarget::Path(path) => nix::unistd::chown(path, self.owner, self.group),
+            };
+            self.check_applied(result, path, "chown")?;
         }
+
         Ok(())
     }
 
+    fn check_applied(
+        &self,
+        result: nix::Result<()>,
+        path: &Path,
+        operation: &'static str,
+    ) -> Result<(), Error> {
+        match result {
+            Ok(()) => Ok(()),
+            Err(errno @ (Errno::EPERM | Errno::EACCES)) => {
+                let err = PermissionDeniedError {
+                    path: path.to_owned(),
+                    operation,
+                    errno,
+                };
+                if !self.allow_degraded {
+                    return Err(err.into());
+                }
+                log::warn!("{err}");
+                Ok(())
+            }
+            Err(err) => bail!("{operation} {path:?} failed: {err}"),
+        }
+    }
+
     /// Check file/directory permissions.
     ///
     /// Make sure that the file or dir is owned by uid/gid and has the correct mode.
@@ -0,0 +1,409 @@
+use std::fs::File;
+use std::io::{self, Write};
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, FromRawFd, RawFd};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, format_err, Error};
+use nix::errno::Errno;
+use nix::fcntl::{OFlag, RenameFlags};
+use nix::sys::stat::Mode;
+use nix::unistd::{self, LinkatFlags};
+
+use crate::fs::{make_tmp_dir, CreateOptions};
+
+/// Number of random names tried before giving up.
+const MAX_NAME_ATTEMPTS: usize = 100;
+
+/// A random name for a temporary file in `dir`.
+fn random_name(dir: &Path) -> Result<PathBuf, Error> {
+    let name: String = crate::linux::random_data(6)?
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    Ok(dir.join(format!(".tmp_{name}")))
+}
+
+/// Call `func` with random names in `dir` until it does not fail with `EEXIST`.
+fn with_random_name<T, F>(dir: &Path, mut func: F) -> Result<(T, PathBuf), Error>
+where
+    F: FnMut(&Path) -> nix::Result<T>,
+{
+    for _ in 0..MAX_NAME_ATTEMPTS {
+        let path = random_name(dir)?;
+        match func(&path) {
+            Ok(value) => return Ok((value, path)),
+            Err(Errno::EEXIST) => continue,
+            Err(err) => bail!("failed to create temporary file {path:?} - {err}"),
+        }
+    }
+    bail!("failed to find an unused temporary file name in {dir:?}");
+}
+
+/// The directory containing `path`, for relative paths without directory the current one.
+fn parent_dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    }
+}
+
+fn fsync_dir(dir: &Path) -> Result<(), Error> {
+    let dir = crate::fd::open(dir, OFlag::O_DIRECTORY | OFlag::O_RDONLY, Mode::empty())
+        .map_err(|err| format_err!("failed to open directory {dir:?} - {err}"))?;
+    unistd::fsync(dir.as_raw_fd()).map_err(|err| format_err!("fsync of {dir:?} failed - {err}"))
+}
+
+/// Unlinks a file on drop unless disarmed.
+struct Unlink(Option<PathBuf>);
+
+impl Unlink {
+    fn disarm(&mut self) {
+        self.0 = None;
+    }
+}
+
+impl Drop for Unlink {
+    fn drop(&mut self) {
+        if let Some(path) = self.0.take() {
+            let _ = unistd::unlink(&path);
+        }
+    }
+}
+
+/// A temporary file which is removed when dropped, unless it was moved into place with
+/// [`persist`](Self::persist) or [`persist_new`](Self::persist_new).
+///
+/// The file is created with `O_TMPFILE` if the kernel and file system support it, so there is no
+/// file to clean up at all if the process dies before the file is persisted. Otherwise a file with
+/// a random name is created in the directory.
+///
+/// ```no_run
+/// # use std::io::Write;
+/// # use proxmox_sys::fs::{CreateOptions, TempFile};
+/// # fn main() -> Result<(), anyhow::Error> {
+/// let mut file = TempFile::new_in("/etc/example", CreateOptions::new())?;
+/// file.write_all(b"data")?; // the temporary file is removed if this fails
+/// file.persist("/etc/example/config", true)?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct TempFile {
+    file: File,
+    dir: PathBuf,
+    name: Unlink,
+}
+
+impl TempFile {
+    /// Create a temporary file in `dir`, preferably with `O_TMPFILE`.
+    ///
+    /// The file can only be persisted on the file system of `dir`. `options` are applied as with
+    /// [`CreateOptions::apply_to`].
+    pub fn new_in<P: AsRef<Path>>(dir: P, options: CreateOptions) -> Result<Self, Error> {
+        let dir = dir.as_ref();
+        options.validate()?;
+
+        let flags = OFlag::O_TMPFILE | OFlag::O_RDWR | OFlag::O_CLOEXEC;
+        match nix::fcntl::open(dir, flags, Mode::from_bits_truncate(0o600)) {
+            Ok(fd) => {
+                let mut file = unsafe { File::from_raw_fd(fd) };
+                options.apply_to(&mut file, dir)?;
+                return Ok(Self {
+                    file,
+                    dir: dir.to_owned(),
+                    name: Unlink(None),
+                });
+            }
+            // not supported by the file system or kernel
+            Err(Errno::EOPNOTSUPP | Errno::EISDIR | Errno::EINVAL) => (),
+            Err(err) => bail!("failed to create temporary file in {dir:?} - {err}"),
+        }
+
+        Self::new_named_in(dir, options)
+    }
+
+    /// Create a temporary file with a random name in `dir`, without trying `O_TMPFILE`.
+    pub fn new_named_in<P: AsRef<Path>>(dir: P, options: CreateOptions) -> Result<Self, Error> {
+        let dir = dir.as_ref();
+        options.validate()?;
+
+        let flags = OFlag::O_CREAT | OFlag::O_EXCL | OFlag::O_RDWR | OFlag::O_CLOEXEC;
+        let (fd, path) = with_random_name(dir, |path| {
+            nix::fcntl::open(path, flags, Mode::from_bits_truncate(0o600))
+        })?;
+        let mut file = unsafe { File::from_raw_fd(fd) };
+        // from here on the file is removed on errors
+        let name = Unlink(Some(path));
+
+        options.apply_to(&mut file, name.0.as_deref().unwrap())?;
+
+        Ok(Self {
+            file,
+            dir: dir.to_owned(),
+            name,
+        })
+    }
+
+    /// The path of the file, `None` if it was created with `O_TMPFILE` and has no name.
+    pub fn path(&self) -> Option<&Path> {
+        self.name.0.as_deref()
+    }
+
+    pub fn as_file(&self) -> &File {
+        &self.file
+    }
+
+    pub fn as_file_mut(&mut self) -> &mut File {
+        &mut self.file
+    }
+
+    /// Atomically replace `target` with the file, returning the open file.
+    ///
+    /// `fsync`: use `fsync(2)` on the file before and on the directory of `target` after moving
+    /// it, so the new file survives a power loss.
+    pub fn persist<P: AsRef<Path>>(self, target: P, fsync: bool) -> Result<File, Error> {
+        self.persist_do(target.as_ref(), fsync, true)
+    }
+
+    /// Like [`persist`](Self::persist), but fails if `target` already exists.
+    pub fn persist_new<P: AsRef<Path>>(self, target: P, fsync: bool) -> Result<File, Error> {
+        self.persist_do(target.as_ref(), fsync, false)
+    }
+
+    fn persist_do(self, target: &Path, fsync: bool, replace: bool) -> Result<File, Error> {
+        let TempFile {
+            file,
+            dir,
+            mut name,
+        } = self;
+
+        if fsync {
+            unistd::fsync(file.as_raw_fd()).map_err(|err| {
+                format_err!("fsync of temporary file for {target:?} failed - {err}")
+            })?;
+        }
+
+        if name.0.is_none() {
+            // link the unnamed file via procfs, linking the fd itself would require
+            // CAP_DAC_READ_SEARCH
+            let proc_path = PathBuf::from(format!("/proc/self/fd/{}", file.as_raw_fd()));
+            let link = |path: &Path| {
+                unistd::linkat(
+                    None,
+                    proc_path.as_path(),
+                    None,
+                    path,
+                    LinkatFlags::SymlinkFollow,
+                )
+            };
+
+            if !replace {
+                link(target)
+                    .map_err(|err| format_err!("failed to create file {target:?} - {err}"))?;
+                if fsync {
+                    fsync_dir(parent_dir(target))?;
+                }
+                return Ok(file);
+            }
+
+            let ((), path) = with_random_name(&dir, link)?;
+            name = Unlink(Some(path));
+        }
+        let path = name.0.as_deref().unwrap();
+
+        let flags = if replace {
+            RenameFlags::empty()
+        } else {
+            RenameFlags::RENAME_NOREPLACE
+        };
+        match nix::fcntl::renameat2(None, path, None, target, flags) {
+            Ok(()) => name.disarm(),
+            // some file systems don't support RENAME_NOREPLACE, link instead and remove the
+            // temporary file on drop
+            Err(Errno::EINVAL) if !replace => {
+                unistd::linkat(None, path, None, target, LinkatFlags::NoSymlinkFollow).map_err(
+                    |err| {
+                        format_err!("failed to link temporary file {path:?} to {target:?} - {err}")
+                    },
+                )?
+            }
+            Err(err) => bail!("failed to move temporary file {path:?} to {target:?} - {err}"),
+        }
+
+        if fsync {
+            fsync_dir(parent_dir(target))?;
+        }
+
+        Ok(file)
+    }
+}
+
+impl Write for TempFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl AsRawFd for TempFile {
+    fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
+
+impl AsFd for TempFile {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.file.as_fd()
+    }
+}
+
+/// A temporary directory which is removed recursively when dropped, unless it was moved into
+/// place with [`persist`](Self::persist) or kept with [`into_path`](Self::into_path).
+pub struct TempDir {
+    path: Option<PathBuf>,
+}
+
+impl TempDir {
+    /// Create a temporary directory in `dir`, see [`make_tmp_dir`].
+    pub fn new_in<P: AsRef<Path>>(dir: P, options: Option<CreateOptions>) -> Result<Self, Error> {
+        Ok(Self {
+            path: Some(make_tmp_dir(dir, options)?),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        self.path.as_deref().unwrap()
+    }
+
+    /// Keep the directory, returning its path.
+    pub fn into_path(mut self) -> PathBuf {
+        self.path.take().unwrap()
+    }
+
+    /// Rename the directory to `target`, which must not exist or be an empty directory.
+    pub fn persist<P: AsRef<Path>>(mut self, target: P) -> Result<PathBuf, Error> {
+        let target = target.as_ref();
+        let path = self.path();
+        std::fs::rename(path, target).map_err(|err| {
+            format_err!("failed to move temporary directory {path:?} to {target:?} - {err}")
+        })?;
+        self.path = None;
+        Ok(target.to_owned())
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        if let Some(path) = self.path.take() {
+            if let Err(err) = std::fs::remove_dir_all(&path) {
+                log::error!("failed to remove temporary directory {path:?} - {err}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use nix::sys::stat;
+
+    use super::*;
+
+    fn entries(dir: &Path) -> Vec<PathBuf> {
+        let mut entries: Vec<PathBuf> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        entries.sort();
+        entries
+    }
+
+    #[test]
+    fn test_temp_file_drop() -> Result<(), Error> {
+        let dir = TempDir::new_in(std::env::temp_dir(), None)?;
+
+        let mut file = TempFile::new_in(dir.path(), CreateOptions::new())?;
+        file.write_all(b"data")?;
+        drop(file);
+
+        let mut file = TempFile::new_named_in(dir.path(), CreateOptions::new())?;
+        let path = file.path().unwrap().to_owned();
+        assert!(path.starts_with(dir.path()));
+        file.write_all(b"data")?;
+        assert_eq!(entries(dir.path()), [path]);
+        drop(file);
+
+        assert!(entries(dir.path()).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_temp_file_persist() -> Result<(), Error> {
+        let dir = TempDir::new_in(std::env::temp_dir(), None)?;
+        let target = dir.path().join("target");
+        let options = CreateOptions::new().perm(stat::Mode::from_bits_truncate(0o640));
+
+        for named in [false, true] {
+            std::fs::write(&target, "old")?;
+
+            let mut file = if named {
+                TempFile::new_named_in(dir.path(), options.clone())?
+            } else {
+                TempFile::new_in(dir.path(), options.clone())?
+            };
+            file.write_all(b"new")?;
+            file.persist(&target, true)?;
+
+            assert_eq!(std::fs::read_to_string(&target)?, "new");
+            assert_eq!(entries(dir.path()), [target.as_path()]);
+            options.check(&target)?;
+
+            // persisting without replacing fails and cleans up
+            let mut file = if named {
+                TempFile::new_named_in(dir.path(), options.clone())?
+            } else {
+                TempFile::new_in(dir.path(), options.clone())?
+            };
+            file.write_all(b"other")?;
+            assert!(file.persist_new(&target, false).is_err());
+            assert_eq!(std::fs::read_to_string(&target)?, "new");
+            assert_eq!(entries(dir.path()), [target.as_path()]);
+
+            std::fs::remove_file(&target)?;
+            let mut file = if named {
+                TempFile::new_named_in(dir.path(), options.clone())?
+            } else {
+                TempFile::new_in(dir.path(), options.clone())?
+            };
+            file.write_all(b"created")?;
+            file.persist_new(&target, false)?;
+            assert_eq!(std::fs::read_to_string(&target)?, "created");
+            assert_eq!(entries(dir.path()), [target.as_path()]);
+            std::fs::remove_file(&target)?;
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_temp_dir() -> Result<(), Error> {
+        let parent = TempDir::new_in(std::env::temp_dir(), None)?;
+
+        let dir = TempDir::new_in(parent.path(), None)?;
+        std::fs::create_dir(dir.path().join("sub"))?;
+        std::fs::write(dir.path().join("sub/file"), "data")?;
+        drop(dir);
+        assert!(entries(parent.path()).is_empty());
+
+        let dir = TempDir::new_in(parent.path(), None)?;
+        std::fs::write(dir.path().join("file"), "data")?;
+        let target = dir.persist(parent.path().join("target"))?;
+        assert_eq!(entries(parent.path()), [target.as_path()]);
+        assert_eq!(std::fs::read_to_string(target.join("file"))?, "data");
+
+        Ok(())
+    }
+}
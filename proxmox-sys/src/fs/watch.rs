@@ -0,0 +1,281 @@
+//! Watch files for changes, e.g. to reload configuration files.
+
+use std::collections::BTreeSet;
+use std::ffi::OsString;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{format_err, Error};
+use futures::Stream;
+use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify, InotifyEvent, WatchDescriptor};
+use tokio::io::unix::AsyncFd;
+use tokio::time::Instant;
+
+// files are watched via their directory, which also catches files replaced by a rename
+const WATCH_FLAGS: AddWatchFlags = AddWatchFlags::IN_CLOSE_WRITE
+    .union(AddWatchFlags::IN_CREATE)
+    .union(AddWatchFlags::IN_DELETE)
+    .union(AddWatchFlags::IN_MOVED_FROM)
+    .union(AddWatchFlags::IN_MOVED_TO)
+    .union(AddWatchFlags::IN_DELETE_SELF)
+    .union(AddWatchFlags::IN_MOVE_SELF)
+    .union(AddWatchFlags::IN_ONLYDIR);
+
+/// How often to retry watching directories which do not exist.
+const REARM_INTERVAL: Duration = Duration::from_secs(1);
+
+struct WatchedDir {
+    dir: PathBuf,
+    /// File names in this directory and the paths they were passed as.
+    files: Vec<(OsString, PathBuf)>,
+    /// Unset while the directory does not exist.
+    wd: Option<WatchDescriptor>,
+}
+
+struct FileWatcher {
+    inotify: Inotify,
+    // owns the inotify file descriptor, `Inotify` does not close it
+    fd: AsyncFd<OwnedFd>,
+    dirs: Vec<WatchedDir>,
+}
+
+impl FileWatcher {
+    fn new(paths: &[PathBuf]) -> Result<Self, Error> {
+        let inotify = Inotify::init(InitFlags::IN_NONBLOCK | InitFlags::IN_CLOEXEC)
+            .map_err(|err| format_err!("unable to initialize inotify - {err}"))?;
+        let fd = AsyncFd::new(unsafe { OwnedFd::from_raw_fd(inotify.as_raw_fd()) })?;
+
+        let mut dirs: Vec<WatchedDir> = Vec::new();
+        for path in paths {
+            let file_name = path
+                .file_name()
+                .ok_or_else(|| format_err!("unable to watch {path:?} - not a file path"))?;
+            let dir = match path.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir,
+                _ => Path::new("."),
+            };
+
+            let file = (file_name.to_owned(), path.clone());
+            match dirs.iter_mut().find(|watched| watched.dir == dir) {
+                Some(watched) if watched.files.contains(&file) => (),
+                Some(watched) => watched.files.push(file),
+                None => dirs.push(WatchedDir {
+                    dir: dir.to_owned(),
+                    files: vec![file],
+                    wd: None,
+                }),
+            }
+        }
+
+        let mut watcher = Self { inotify, fd, dirs };
+        watcher.arm();
+        Ok(watcher)
+    }
+
+    /// Watch the directories which are not watched yet, returning the files in the newly watched
+    /// directories.
+    fn arm(&mut self) -> Vec<PathBuf> {
+        let mut rearmed = Vec::new();
+        for watched in self.dirs.iter_mut().filter(|watched| watched.wd.is_none()) {
+            // the directory may not exist (yet), so just try again later on errors
+            if let Ok(wd) = self.inotify.add_watch(&watched.dir, WATCH_FLAGS) {
+                watched.wd = Some(wd);
+                rearmed.extend(watched.files.iter().map(|(_, path)| path.clone()));
+            }
+        }
+        rearmed
+    }
+
+    fn all_armed(&self) -> bool {
+        self.dirs.iter().all(|watched| watched.wd.is_some())
+    }
+
+    async fn read_events(&self) -> Result<Vec<InotifyEvent>, Error> {
+        loop {
+            let mut guard = self.fd.readable().await?;
+            match guard.try_io(|_| self.inotify.read_events().map_err(std::io::Error::from)) {
+                Ok(events) => return Ok(events?),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+
+    fn handle_event(&mut self, event: InotifyEvent, changed: &mut BTreeSet<PathBuf>) {
+        if event.mask.contains(AddWatchFlags::IN_Q_OVERFLOW) {
+            // events were lost, so anything may have changed
+            for watched in &self.dirs {
+                changed.extend(watched.files.iter().map(|(_, path)| path.clone()));
+            }
+            return;
+        }
+
+        let Some(watched) = self
+            .dirs
+            .iter_mut()
+            .find(|watched| watched.wd == Some(event.wd))
+        else {
+            return;
+        };
+
+        if event
+            .mask
+            .intersects(AddWatchFlags::IN_IGNORED | AddWatchFlags::IN_MOVE_SELF)
+        {
+            // the directory is gone, and with it the files
+            if event.mask.contains(AddWatchFlags::IN_MOVE_SELF) {
+                let _ = self.inotify.rm_watch(event.wd);
+            }
+            watched.wd = None;
+            changed.extend(watched.files.iter().map(|(_, path)| path.clone()));
+            return;
+        }
+
+        if let Some(name) = event.name {
+            if let Some((_, path)) = watched.files.iter().find(|(file, _)| *file == name) {
+                changed.insert(path.clone());
+            }
+        }
+    }
+
+    /// Wait for changes to the watched files, collecting further changes for `debounce` after the
+    /// first one.
+    async fn next_batch(&mut self, debounce: Duration) -> Result<Vec<PathBuf>, Error> {
+        let mut changed = BTreeSet::new();
+
+        while changed.is_empty() {
+            if self.all_armed() {
+                for event in self.read_events().await? {
+                    self.handle_event(event, &mut changed);
+                }
+            } else {
+                match tokio::time::timeout(REARM_INTERVAL, self.read_events()).await {
+                    Ok(events) => {
+                        for event in events? {
+                            self.handle_event(event, &mut changed);
+                        }
+                    }
+                    Err(_elapsed) => changed.extend(self.arm()),
+                }
+            }
+        }
+
+        let deadline = Instant::now() + debounce;
+        while let Ok(events) = tokio::time::timeout_at(deadline, self.read_events()).await {
+            for event in events? {
+                self.handle_event(event, &mut changed);
+            }
+        }
+        // a removed directory may have been recreated within the debounce window
+        changed.extend(self.arm());
+
+        Ok(changed.into_iter().collect())
+    }
+}
+
+/// Watch `paths` for changes, e.g. to reload configuration files.
+///
+/// The returned stream yields the changed paths, as passed in `paths`, whenever files are written,
+/// created, replaced (e.g. by an editor renaming a temporary file over the original) or removed.
+/// Changes within `debounce` after the first change are reported as a single batch.
+///
+/// The files do not need to exist, the same goes for their directories, which are watched again
+/// once they are recreated. The stream ends if reading the inotify events fails.
+///
+/// Must be called from within a tokio runtime.
+pub fn watch_files(
+    paths: &[PathBuf],
+    debounce: Duration,
+) -> Result<impl Stream<Item = Vec<PathBuf>> + Send + 'static, Error> {
+    let watcher = FileWatcher::new(paths)?;
+
+    Ok(futures::stream::unfold(
+        watcher,
+        move |mut watcher| async move {
+            match watcher.next_batch(debounce).await {
+                Ok(changed) => Some((changed, watcher)),
+                Err(err) => {
+                    log::error!("stopped watching files - {err}");
+                    None
+                }
+            }
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    use anyhow::Error;
+    use futures::{Stream, StreamExt};
+    use nix::sys::stat;
+    use nix::unistd;
+
+    use super::watch_files;
+    use crate::fs::CreateOptions;
+
+    const DEBOUNCE: Duration = Duration::from_millis(100);
+
+    fn tmp_dir() -> Result<PathBuf, Error> {
+        let options = CreateOptions::new()
+            .owner(unistd::Uid::effective())
+            .group(unistd::Gid::effective())
+            .perm(stat::Mode::from_bits_truncate(0o700));
+        crate::fs::make_tmp_dir("/tmp", Some(options))
+    }
+
+    async fn next<S: Stream<Item = Vec<PathBuf>> + Unpin>(changes: &mut S) -> Vec<PathBuf> {
+        tokio::time::timeout(Duration::from_secs(5), changes.next())
+            .await
+            .expect("timeout waiting for changes")
+            .expect("watcher stopped")
+    }
+
+    #[test]
+    fn test_watch_files() -> Result<(), Error> {
+        let dir = tmp_dir()?;
+        let config = dir.join("config.cfg");
+        let other = dir.join("other.cfg");
+        std::fs::write(&config, "a")?;
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        runtime.block_on(async {
+            let mut changes = Box::pin(watch_files(&[config.clone(), other.clone()], DEBOUNCE)?);
+            // a burst of writes is a single batch
+            std::fs::write(&config, "b")?;
+            std::fs::write(&config, "c")?;
+            std::fs::write(dir.join("unrelated"), "x")?;
+            assert_eq!(next(&mut changes).await, vec![config.clone()]);
+
+            // atomic replacement
+            std::fs::write(dir.join("config.tmp"), "d")?;
+            std::fs::rename(dir.join("config.tmp"), &config)?;
+            assert_eq!(next(&mut changes).await, vec![config.clone()]);
+
+            // files may not exist
+            std::fs::remove_file(&config)?;
+            assert_eq!(next(&mut changes).await, vec![config.clone()]);
+            std::fs::write(&other, "e")?;
+            std::fs::write(&config, "f")?;
+            assert_eq!(next(&mut changes).await, [config.clone(), other.clone()]);
+
+            // neither may their directory
+            std::fs::remove_dir_all(&dir)?;
+            assert_eq!(next(&mut changes).await, [config.clone(), other.clone()]);
+            std::fs::create_dir(&dir)?;
+            std::fs::write(&config, "g")?;
+            assert_eq!(next(&mut changes).await, [config.clone(), other.clone()]);
+            std::fs::write(&other, "h")?;
+            assert_eq!(next(&mut changes).await, vec![other.clone()]);
+
+            Ok::<_, Error>(())
+        })?;
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
@@ -6,6 +6,7 @@ use proxmox_io::vec;
 
 pub mod magic;
 pub mod pid;
+pub mod process;
 pub mod procfs;
 pub mod socket;
 #[cfg(feature = "timer")]
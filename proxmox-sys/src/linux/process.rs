@@ -0,0 +1,279 @@
+//! CPU affinity of processes and threads.
+//!
+//! CPU sets are written in the list format used by cgroups and `/proc/<pid>/status`, for example
+//! `0-3,8,10-11`.
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{format_err, Error};
+use nix::sched::{sched_getaffinity, sched_setaffinity};
+use nix::unistd::Pid;
+
+/// Error for CPU sets with invalid CPU indices or syntax.
+///
+/// This is wrapped in an `anyhow::Error` by the affinity functions, use `downcast_ref` to detect
+/// it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CpuSetError {
+    /// The CPU index is not below [`CpuSet::MAX_CPUS`].
+    InvalidCpu(usize),
+    /// The end of the range is lower than its start.
+    InvalidRange(usize, usize),
+    /// The list entry is neither a CPU index nor a range of them.
+    InvalidEntry(String),
+}
+
+impl fmt::Display for CpuSetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CpuSetError::InvalidCpu(cpu) => write!(
+                f,
+                "invalid CPU index {cpu} (must be lower than {})",
+                CpuSet::MAX_CPUS
+            ),
+            CpuSetError::InvalidRange(start, end) => write!(f, "invalid CPU range {start}-{end}"),
+            CpuSetError::InvalidEntry(entry) => write!(f, "invalid CPU list entry '{entry}'"),
+        }
+    }
+}
+
+impl std::error::Error for CpuSetError {}
+
+/// A set of CPUs, e.g. for the affinity of a process or thread.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CpuSet {
+    cpus: BTreeSet<usize>,
+}
+
+impl CpuSet {
+    /// The number of CPUs the kernel interface supports, CPU indices must be lower.
+    pub const MAX_CPUS: usize = libc::CPU_SETSIZE as usize;
+
+    /// Create an empty set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a CPU to the set.
+    pub fn insert(&mut self, cpu: usize) -> Result<(), CpuSetError> {
+        if cpu >= Self::MAX_CPUS {
+            return Err(CpuSetError::InvalidCpu(cpu));
+        }
+        self.cpus.insert(cpu);
+        Ok(())
+    }
+
+    /// Remove a CPU from the set, returns whether it was contained.
+    pub fn remove(&mut self, cpu: usize) -> bool {
+        self.cpus.remove(&cpu)
+    }
+
+    /// Check if the set contains a CPU.
+    pub fn contains(&self, cpu: usize) -> bool {
+        self.cpus.contains(&cpu)
+    }
+
+    /// The number of CPUs in the set.
+    pub fn len(&self) -> usize {
+        self.cpus.len()
+    }
+
+    /// Check if the set is empty.
+    pub fn is_empty(&self) -> bool {
+        self.cpus.is_empty()
+    }
+
+    /// Iterate over the CPU indices in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.cpus.iter().copied()
+    }
+
+    fn to_nix(&self) -> Result<nix::sched::CpuSet, Error> {
+        let mut set = nix::sched::CpuSet::new();
+        for cpu in self.iter() {
+            set.set(cpu)?;
+        }
+        Ok(set)
+    }
+
+    fn from_nix(set: &nix::sched::CpuSet) -> Self {
+        let cpus = (0..Self::MAX_CPUS)
+            .filter(|cpu| set.is_set(*cpu).unwrap_or(false))
+            .collect();
+        Self { cpus }
+    }
+}
+
+impl FromStr for CpuSet {
+    type Err = CpuSetError;
+
+    /// Parse a list like `0-3,8,10-11`, an empty list is an empty set.
+    fn from_str(list: &str) -> Result<Self, Self::Err> {
+        let parse_cpu = |cpu: &str| {
+            cpu.trim()
+                .parse::<usize>()
+                .map_err(|_| CpuSetError::InvalidEntry(cpu.trim().to_string()))
+        };
+
+        let mut set = CpuSet::new();
+        let list = list.trim();
+        if list.is_empty() {
+            return Ok(set);
+        }
+
+        for entry in list.split(',') {
+            match entry.split_once('-') {
+                Some((start, end)) => {
+                    let (start, end) = (parse_cpu(start)?, parse_cpu(end)?);
+                    if end < start {
+                        return Err(CpuSetError::InvalidRange(start, end));
+                    }
+                    if end >= Self::MAX_CPUS {
+                        return Err(CpuSetError::InvalidCpu(end));
+                    }
+                    set.cpus.extend(start..=end);
+                }
+                None => set.insert(parse_cpu(entry)?)?,
+            }
+        }
+
+        Ok(set)
+    }
+}
+
+impl fmt::Display for CpuSet {
+    /// Format the set as list, with consecutive CPUs combined into ranges.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut cpus = self.iter().peekable();
+        let mut first = true;
+        while let Some(start) = cpus.next() {
+            let mut end = start;
+            while cpus.next_if_eq(&(end + 1)).is_some() {
+                end += 1;
+            }
+
+            if !first {
+                f.write_str(",")?;
+            }
+            first = false;
+
+            if start == end {
+                write!(f, "{start}")?;
+            } else {
+                write!(f, "{start}-{end}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Set the CPU affinity of a process or thread, `Pid::from_raw(0)` is the calling thread.
+///
+/// Use [`nix::unistd::gettid`] to pin another thread of this process.
+pub fn set_affinity(pid: Pid, cpuset: &CpuSet) -> Result<(), Error> {
+    let set = cpuset.to_nix()?;
+    sched_setaffinity(pid, &set)
+        .map_err(|err| format_err!("unable to set CPU affinity of {pid} to '{cpuset}' - {err}"))
+}
+
+/// Get the CPU affinity of a process or thread, `Pid::from_raw(0)` is the calling thread.
+pub fn get_affinity(pid: Pid) -> Result<CpuSet, Error> {
+    let set = sched_getaffinity(pid)
+        .map_err(|err| format_err!("unable to get CPU affinity of {pid} - {err}"))?;
+    Ok(CpuSet::from_nix(&set))
+}
+
+/// Read the CPUs the current process may run on from `/proc/self/status`.
+pub fn read_allowed_cpus() -> Result<CpuSet, Error> {
+    let status = std::fs::read_to_string("/proc/self/status")
+        .map_err(|err| format_err!("unable to read /proc/self/status - {err}"))?;
+    parse_allowed_cpus(&status)
+}
+
+fn parse_allowed_cpus(status: &str) -> Result<CpuSet, Error> {
+    let list = status
+        .lines()
+        .find_map(|line| line.strip_prefix("Cpus_allowed_list:"))
+        .ok_or_else(|| format_err!("no 'Cpus_allowed_list' in process status"))?;
+    Ok(list.parse()?)
+}
+
+#[cfg(test)]
+mod test {
+    use nix::unistd::Pid;
+
+    use super::{get_affinity, parse_allowed_cpus, read_allowed_cpus, set_affinity};
+    use super::{CpuSet, CpuSetError};
+
+    #[test]
+    fn test_cpuset_parse_and_format() {
+        let valid: &[(&str, &str, &[usize])] = &[
+            ("0-3,8,10-11", "0-3,8,10-11", &[0, 1, 2, 3, 8, 10, 11]),
+            ("", "", &[]),
+            ("5", "5", &[5]),
+            (" 1, 3-3 ,2", "1-3", &[1, 2, 3]),
+            ("0-1,1-2", "0-2", &[0, 1, 2]),
+            ("1023", "1023", &[1023]),
+        ];
+        for (list, formatted, cpus) in valid {
+            let set: CpuSet = list.parse().unwrap();
+            assert_eq!(set.iter().collect::<Vec<_>>(), *cpus, "{list}");
+            assert_eq!(set.to_string(), *formatted);
+            assert_eq!(formatted.parse::<CpuSet>().unwrap(), set);
+        }
+
+        for (list, err) in [
+            ("1024", CpuSetError::InvalidCpu(1024)),
+            ("0-4096", CpuSetError::InvalidCpu(4096)),
+            ("3-1", CpuSetError::InvalidRange(3, 1)),
+            ("-1", CpuSetError::InvalidEntry("".to_string())),
+            ("0,,1", CpuSetError::InvalidEntry("".to_string())),
+            ("a-b", CpuSetError::InvalidEntry("a".to_string())),
+            ("1-2-3", CpuSetError::InvalidEntry("2-3".to_string())),
+        ] {
+            assert_eq!(list.parse::<CpuSet>(), Err(err), "{list}");
+        }
+
+        let mut set = CpuSet::new();
+        assert_eq!(
+            set.insert(CpuSet::MAX_CPUS),
+            Err(CpuSetError::InvalidCpu(1024))
+        );
+        assert!(set.is_empty());
+    }
+
+    #[test]
+    fn test_parse_allowed_cpus() {
+        let status = "Name:\tcat\nCpus_allowed:\tff\nCpus_allowed_list:\t0-7\nVoluntary: 0\n";
+        assert_eq!(parse_allowed_cpus(status).unwrap().to_string(), "0-7");
+        assert!(parse_allowed_cpus("Name:\tcat\n").is_err());
+
+        let allowed = read_allowed_cpus().unwrap();
+        assert!(!allowed.is_empty());
+        assert_eq!(get_affinity(Pid::from_raw(0)).unwrap(), allowed);
+    }
+
+    #[test]
+    fn test_set_thread_affinity() {
+        let allowed = read_allowed_cpus().unwrap();
+        let cpu = allowed.iter().last().unwrap();
+
+        let (tid, affinity) = std::thread::spawn(move || {
+            let tid = nix::unistd::gettid();
+            let mut pinned = CpuSet::new();
+            pinned.insert(cpu).unwrap();
+            set_affinity(tid, &pinned).unwrap();
+            (tid, get_affinity(tid).unwrap())
+        })
+        .join()
+        .unwrap();
+
+        assert_eq!(affinity.iter().collect::<Vec<_>>(), [cpu]);
+        assert_ne!(tid, nix::unistd::gettid());
+
+        // the affinity of other threads is not changed
+        assert_eq!(get_affinity(Pid::from_raw(0)).unwrap(), allowed);
+    }
+}
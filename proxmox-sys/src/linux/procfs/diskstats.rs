@@ -0,0 +1,275 @@
+//! `/proc/diskstats` handling.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use anyhow::{bail, format_err, Error};
+use serde::{Deserialize, Serialize};
+
+use super::{counter_delta, per_second};
+
+const PROC_DISKSTATS: &str = "/proc/diskstats";
+
+/// The sector size used by `/proc/diskstats`, independent of the actual sector size of the disk.
+const SECTOR_SIZE: u64 = 512;
+
+/// The IO statistics of a block device from `/proc/diskstats`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiskStat {
+    /// Major device number.
+    pub major: u32,
+    /// Minor device number.
+    pub minor: u32,
+    /// Completed reads.
+    pub reads_completed: u64,
+    /// Reads merged with adjacent reads.
+    pub reads_merged: u64,
+    /// Read sectors of 512 bytes.
+    pub sectors_read: u64,
+    /// Time spent reading in milliseconds.
+    pub read_time_ms: u64,
+    /// Completed writes.
+    pub writes_completed: u64,
+    /// Writes merged with adjacent writes.
+    pub writes_merged: u64,
+    /// Written sectors of 512 bytes.
+    pub sectors_written: u64,
+    /// Time spent writing in milliseconds.
+    pub write_time_ms: u64,
+    /// IOs currently in progress.
+    pub io_in_progress: u64,
+    /// Time spent doing IO in milliseconds.
+    pub io_time_ms: u64,
+    /// Time spent doing IO weighted by the number of IOs in progress, in milliseconds.
+    pub weighted_io_time_ms: u64,
+}
+
+/// The IO rates of a block device per second, see [`DiskStat::rate`].
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct DiskRate {
+    /// Completed reads per second.
+    pub reads: f64,
+    /// Completed writes per second.
+    pub writes: f64,
+    /// Read bytes per second.
+    pub read_bytes: f64,
+    /// Written bytes per second.
+    pub write_bytes: f64,
+    /// The fraction of the time spent doing IO, from 0 to 1.
+    pub utilization: f64,
+}
+
+impl DiskStat {
+    /// Compute the rates per second from the `previous` statistics read `interval` ago.
+    ///
+    /// Counters smaller than before are assumed to have wrapped around if they fit into 32 bits,
+    /// and to have been reset otherwise, which yields a rate of 0.
+    pub fn rate(&self, previous: &Self, interval: Duration) -> DiskRate {
+        let io_time = Duration::from_millis(counter_delta(previous.io_time_ms, self.io_time_ms));
+        let utilization = if interval.is_zero() {
+            0.0
+        } else {
+            (io_time.as_secs_f64() / interval.as_secs_f64()).min(1.0)
+        };
+
+        DiskRate {
+            reads: per_second(
+                counter_delta(previous.reads_completed, self.reads_completed),
+                interval,
+            ),
+            writes: per_second(
+                counter_delta(previous.writes_completed, self.writes_completed),
+                interval,
+            ),
+            read_bytes: per_second(
+                counter_delta(previous.sectors_read, self.sectors_read).saturating_mul(SECTOR_SIZE),
+                interval,
+            ),
+            write_bytes: per_second(
+                counter_delta(previous.sectors_written, self.sectors_written)
+                    .saturating_mul(SECTOR_SIZE),
+                interval,
+            ),
+            utilization,
+        }
+    }
+}
+
+/// Returns true for virtual block devices like loop devices, device mapper and RAM disks.
+///
+/// Can be used to filter [`read_diskstats`]: `read_diskstats(|name| !is_virtual_disk(name))`.
+pub fn is_virtual_disk(name: &str) -> bool {
+    ["loop", "dm-", "ram", "zram"]
+        .iter()
+        .any(|prefix| name.starts_with(prefix))
+}
+
+/// Read the IO statistics of the block devices from `/proc/diskstats`, keyed by device name.
+///
+/// Only devices for which `filter` returns true are included.
+pub fn read_diskstats<F>(filter: F) -> Result<BTreeMap<String, DiskStat>, Error>
+where
+    F: Fn(&str) -> bool,
+{
+    let data = std::fs::read_to_string(PROC_DISKSTATS)
+        .map_err(|err| format_err!("unable to read {PROC_DISKSTATS} - {err}"))?;
+    parse_diskstats(&data, filter)
+}
+
+/// Parse the contents of `/proc/diskstats`, see [`read_diskstats`].
+///
+/// The discard and flush statistics of newer kernels are ignored.
+pub fn parse_diskstats<F>(data: &str, filter: F) -> Result<BTreeMap<String, DiskStat>, Error>
+where
+    F: Fn(&str) -> bool,
+{
+    let mut result = BTreeMap::new();
+
+    for line in data.lines() {
+        let mut fields = line.split_ascii_whitespace();
+        let (Some(major), Some(minor), Some(name)) = (fields.next(), fields.next(), fields.next())
+        else {
+            if line.trim().is_empty() {
+                continue;
+            }
+            bail!("missing device name in {PROC_DISKSTATS} line {line:?}");
+        };
+        if !filter(name) {
+            continue;
+        }
+
+        let parse_err = |err| format_err!("invalid statistics for device {name:?} - {err}");
+        let counters = fields
+            .take(11)
+            .map(|counter| counter.parse::<u64>())
+            .collect::<Result<Vec<u64>, _>>()
+            .map_err(parse_err)?;
+        if counters.len() < 11 {
+            bail!("missing statistics for device {name:?} in {PROC_DISKSTATS}");
+        }
+
+        result.insert(
+            name.to_string(),
+            DiskStat {
+                major: major.parse().map_err(parse_err)?,
+                minor: minor.parse().map_err(parse_err)?,
+                reads_completed: counters[0],
+                reads_merged: counters[1],
+                sectors_read: counters[2],
+                read_time_ms: counters[3],
+                writes_completed: counters[4],
+                writes_merged: counters[5],
+                sectors_written: counters[6],
+                write_time_ms: counters[7],
+                io_in_progress: counters[8],
+                io_time_ms: counters[9],
+                weighted_io_time_ms: counters[10],
+            },
+        );
+    }
+
+    Ok(result)
+}
+
+/// Compute the rates of the devices in both snapshots, read `interval` apart.
+pub fn disk_rates(
+    previous: &BTreeMap<String, DiskStat>,
+    current: &BTreeMap<String, DiskStat>,
+    interval: Duration,
+) -> BTreeMap<String, DiskRate> {
+    current
+        .iter()
+        .filter_map(|(name, stat)| {
+            let rate = stat.rate(previous.get(name)?, interval);
+            Some((name.clone(), rate))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const DISKSTATS: &str = "   7       0 loop0 55 0 2176 11 0 0 0 0 0 24 11 0 0 0 0 0 0
+ 259       0 nvme0n1 1183093 220133 81634770 282349 6201327 3986201 186712728 3515837 0 1916472 3895811 0 0 0 0 449212 97624
+ 259       1 nvme0n1p1 331 1000 14866 57 2 0 2 0 0 76 57 0 0 0 0 0 0
+   8       0 sda 2043 12 121012 3018 100 50 8000 400 1 5000 3418
+ 253       0 dm-0 40 0 2264 4 0 0 0 0 0 12 4 0 0 0 0 0 0
+";
+
+    #[test]
+    fn test_parse_diskstats() -> Result<(), Error> {
+        let stats = parse_diskstats(DISKSTATS, |_| true)?;
+        assert_eq!(
+            stats.keys().collect::<Vec<_>>(),
+            ["dm-0", "loop0", "nvme0n1", "nvme0n1p1", "sda"]
+        );
+        assert_eq!(
+            stats["nvme0n1"],
+            DiskStat {
+                major: 259,
+                minor: 0,
+                reads_completed: 1183093,
+                reads_merged: 220133,
+                sectors_read: 81634770,
+                read_time_ms: 282349,
+                writes_completed: 6201327,
+                writes_merged: 3986201,
+                sectors_written: 186712728,
+                write_time_ms: 3515837,
+                io_in_progress: 0,
+                io_time_ms: 1916472,
+                weighted_io_time_ms: 3895811,
+            }
+        );
+        // old kernels without discard and flush statistics
+        assert_eq!(stats["sda"].io_in_progress, 1);
+        assert_eq!(stats["sda"].weighted_io_time_ms, 3418);
+
+        let stats = parse_diskstats(DISKSTATS, |name| !is_virtual_disk(name))?;
+        assert_eq!(
+            stats.keys().collect::<Vec<_>>(),
+            ["nvme0n1", "nvme0n1p1", "sda"]
+        );
+
+        assert!(parse_diskstats("8 0 sda 1 2 3\n", |_| true).is_err());
+        assert!(parse_diskstats("8 0\n", |_| true).is_err());
+        assert!(parse_diskstats("8 0 sda 1 2 3 x 5 6 7 8 9 10 11\n", |_| true).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_disk_rates() -> Result<(), Error> {
+        let previous = parse_diskstats(DISKSTATS, |_| true)?;
+        let mut current = previous.clone();
+        let sda = current.get_mut("sda").unwrap();
+        sda.reads_completed += 100;
+        sda.sectors_read += 2048;
+        sda.writes_completed += 20;
+        sda.sectors_written += 8;
+        sda.io_time_ms += 2000;
+        let loop0 = current.get_mut("loop0").unwrap();
+        loop0.io_time_ms += 60_000;
+
+        let rates = disk_rates(&previous, &current, Duration::from_secs(4));
+        assert_eq!(
+            rates["sda"],
+            DiskRate {
+                reads: 25.0,
+                writes: 5.0,
+                read_bytes: 262144.0,
+                write_bytes: 1024.0,
+                utilization: 0.5,
+            }
+        );
+        assert_eq!(rates["loop0"].utilization, 1.0);
+        assert_eq!(rates["nvme0n1"], DiskRate::default());
+
+        // a reset 64 bit counter yields no activity
+        let mut reset = previous.clone();
+        reset.get_mut("sda").unwrap().sectors_written = 1 << 40;
+        let rates = disk_rates(&reset, &previous, Duration::from_secs(1));
+        assert_eq!(rates["sda"].write_bytes, 0.0);
+        Ok(())
+    }
+}
@@ -6,7 +6,7 @@ use std::io::{BufRead, BufReader};
 use std::net::{Ipv4Addr, Ipv6Addr};
 use std::str::FromStr;
 use std::sync::{LazyLock, RwLock};
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use anyhow::{bail, format_err, Error};
 use nix::unistd::Pid;
@@ -18,6 +18,14 @@ pub mod mountinfo;
 #[doc(inline)]
 pub use mountinfo::MountInfo;
 
+pub mod diskstats;
+#[doc(inline)]
+pub use diskstats::{read_diskstats, DiskRate, DiskStat};
+
+pub mod netdev;
+#[doc(inline)]
+pub use netdev::{read_net_dev, NetDevRate, NetDevStat};
+
 /// POSIX sysconf call
 pub fn sysconf(name: i32) -> i64 {
     extern "C" {
@@ -546,26 +554,36 @@ pub struct ProcFsNetDev {
 }
 
 pub fn read_proc_net_dev() -> Result<Vec<ProcFsNetDev>, Error> {
-    let path = "/proc/net/dev";
-    let file = OpenOptions::new().read(true).open(path)?;
+    Ok(read_net_dev(|_| true)?
+        .into_iter()
+        .map(|(device, stat)| ProcFsNetDev {
+            device,
+            receive: stat.rx_bytes,
+            send: stat.tx_bytes,
+        })
+        .collect())
+}
 
-    let mut result = Vec::new();
-    for line in BufReader::new(&file).lines().skip(2) {
-        let content = line?;
-        let mut iter = content.split_whitespace();
-        match (iter.next(), iter.next(), iter.nth(7)) {
-            (Some(device), Some(receive), Some(send)) => {
-                result.push(ProcFsNetDev {
-                    device: device[..device.len() - 1].to_string(),
-                    receive: receive.parse::<u64>()?,
-                    send: send.parse::<u64>()?,
-                });
-            }
-            _ => bail!("Error while parsing '{}'", path),
-        }
+/// The difference between two readings of a counter, which wraps around at 32 bits.
+///
+/// 64 bit counters do not wrap around in practice, so if they decrease, they were reset, e.g. by
+/// re-creating the device, and the difference is 0.
+fn counter_delta(old: u64, new: u64) -> u64 {
+    if new >= old {
+        new - old
+    } else if old <= u64::from(u32::MAX) {
+        u64::from(u32::MAX) - old + new + 1
+    } else {
+        0
     }
+}
 
-    Ok(result)
+/// The rate per second of a counter which increased by `delta` during `interval`.
+fn per_second(delta: u64, interval: Duration) -> f64 {
+    if interval.is_zero() {
+        return 0.0;
+    }
+    delta as f64 / interval.as_secs_f64()
 }
 
 // Parse a hexadecimal digit into a byte.
@@ -741,6 +759,17 @@ mod tests {
     fn test_read_proc_net_ipv6_route() {
         read_proc_net_ipv6_route().unwrap();
     }
+
+    #[test]
+    fn test_counter_delta() {
+        assert_eq!(counter_delta(10, 15), 5);
+        assert_eq!(counter_delta(u64::from(u32::MAX) - 1, 3), 5);
+        // a reset 64 bit counter
+        assert_eq!(counter_delta(u64::MAX - 1, 3), 0);
+        assert_eq!(counter_delta(1 << 40, 3), 0);
+        assert_eq!(per_second(10, Duration::from_millis(500)), 20.0);
+        assert_eq!(per_second(10, Duration::ZERO), 0.0);
+    }
 }
 
 /// Read the load avage from `/proc/loadavg`.
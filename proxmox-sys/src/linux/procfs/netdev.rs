@@ -0,0 +1,221 @@
+//! `/proc/net/dev` handling.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use anyhow::{bail, format_err, Error};
+use serde::{Deserialize, Serialize};
+
+use super::{counter_delta, per_second};
+
+const PROC_NET_DEV: &str = "/proc/net/dev";
+
+/// The counters of a network interface from `/proc/net/dev`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NetDevStat {
+    /// Received bytes.
+    pub rx_bytes: u64,
+    /// Received packets.
+    pub rx_packets: u64,
+    /// Receive errors.
+    pub rx_errors: u64,
+    /// Dropped received packets.
+    pub rx_drops: u64,
+    /// Sent bytes.
+    pub tx_bytes: u64,
+    /// Sent packets.
+    pub tx_packets: u64,
+    /// Send errors.
+    pub tx_errors: u64,
+    /// Dropped packets to send.
+    pub tx_drops: u64,
+}
+
+/// The rates of the counters of a network interface per second, see [`NetDevStat::rate`].
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct NetDevRate {
+    pub rx_bytes: f64,
+    pub rx_packets: f64,
+    pub rx_errors: f64,
+    pub rx_drops: f64,
+    pub tx_bytes: f64,
+    pub tx_packets: f64,
+    pub tx_errors: f64,
+    pub tx_drops: f64,
+}
+
+impl NetDevStat {
+    /// Compute the rates per second from the `previous` counters read `interval` ago.
+    ///
+    /// Counters smaller than before are assumed to have wrapped around if they fit into 32 bits,
+    /// and to have been reset otherwise, which yields a rate of 0.
+    pub fn rate(&self, previous: &Self, interval: Duration) -> NetDevRate {
+        let rate = |old, new| per_second(counter_delta(old, new), interval);
+        NetDevRate {
+            rx_bytes: rate(previous.rx_bytes, self.rx_bytes),
+            rx_packets: rate(previous.rx_packets, self.rx_packets),
+            rx_errors: rate(previous.rx_errors, self.rx_errors),
+            rx_drops: rate(previous.rx_drops, self.rx_drops),
+            tx_bytes: rate(previous.tx_bytes, self.tx_bytes),
+            tx_packets: rate(previous.tx_packets, self.tx_packets),
+            tx_errors: rate(previous.tx_errors, self.tx_errors),
+            tx_drops: rate(previous.tx_drops, self.tx_drops),
+        }
+    }
+}
+
+/// Returns true for virtual interfaces like the loopback interface and veth pairs.
+///
+/// Can be used to filter [`read_net_dev`]: `read_net_dev(|name| !is_virtual_interface(name))`.
+pub fn is_virtual_interface(name: &str) -> bool {
+    name == "lo" || name.starts_with("veth")
+}
+
+/// Read the counters of the network interfaces from `/proc/net/dev`, keyed by interface name.
+///
+/// Only interfaces for which `filter` returns true are included.
+pub fn read_net_dev<F>(filter: F) -> Result<BTreeMap<String, NetDevStat>, Error>
+where
+    F: Fn(&str) -> bool,
+{
+    let data = std::fs::read_to_string(PROC_NET_DEV)
+        .map_err(|err| format_err!("unable to read {PROC_NET_DEV} - {err}"))?;
+    parse_net_dev(&data, filter)
+}
+
+/// Parse the contents of `/proc/net/dev`, see [`read_net_dev`].
+pub fn parse_net_dev<F>(data: &str, filter: F) -> Result<BTreeMap<String, NetDevStat>, Error>
+where
+    F: Fn(&str) -> bool,
+{
+    let mut result = BTreeMap::new();
+
+    // the first two lines are headers
+    for line in data.lines().skip(2) {
+        // counters may follow the colon without a space, e.g. for long interface names
+        let (name, counters) = line
+            .split_once(':')
+            .ok_or_else(|| format_err!("missing interface name in {PROC_NET_DEV} line {line:?}"))?;
+        let name = name.trim();
+        if !filter(name) {
+            continue;
+        }
+
+        let counters = counters
+            .split_ascii_whitespace()
+            .map(|counter| counter.parse::<u64>())
+            .collect::<Result<Vec<u64>, _>>()
+            .map_err(|err| format_err!("invalid counter for interface {name:?} - {err}"))?;
+        if counters.len() < 16 {
+            bail!("missing counters for interface {name:?} in {PROC_NET_DEV}");
+        }
+
+        result.insert(
+            name.to_string(),
+            NetDevStat {
+                rx_bytes: counters[0],
+                rx_packets: counters[1],
+                rx_errors: counters[2],
+                rx_drops: counters[3],
+                tx_bytes: counters[8],
+                tx_packets: counters[9],
+                tx_errors: counters[10],
+                tx_drops: counters[11],
+            },
+        );
+    }
+
+    Ok(result)
+}
+
+/// Compute the rates of the interfaces in both snapshots, read `interval` apart.
+pub fn net_dev_rates(
+    previous: &BTreeMap<String, NetDevStat>,
+    current: &BTreeMap<String, NetDevStat>,
+    interval: Duration,
+) -> BTreeMap<String, NetDevRate> {
+    current
+        .iter()
+        .filter_map(|(name, stat)| {
+            let rate = stat.rate(previous.get(name)?, interval);
+            Some((name.clone(), rate))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const NET_DEV: &str = "\
+Inter-|   Receive                                                |  Transmit
+ face |bytes    packets errs drop fifo frame compressed multicast|bytes    packets errs drop fifo colls carrier compressed
+    lo: 2776770   25348    0    0    0     0          0         0  2776770   25348    0    0    0     0       0          0
+enp5s0: 1893466744 2063573    3   17    0     0          0     38226 142536717  870458    0    2    0     0       0          0
+vmbr0: 1746290367 1841442    0    0    0     0          0     49851 137924013  814417    0    0    0     0       0          0
+veth100i0:   73458     615    0    0    0     0          0         0   906187    9034    0    0    0     0       0          0
+fwpr100p0:12345678901234   10    0    0    0     0          0         0 5 1    0    0    0     0       0          0
+";
+
+    #[test]
+    fn test_parse_net_dev() -> Result<(), Error> {
+        let stats = parse_net_dev(NET_DEV, |_| true)?;
+        assert_eq!(
+            stats.keys().collect::<Vec<_>>(),
+            ["enp5s0", "fwpr100p0", "lo", "veth100i0", "vmbr0"]
+        );
+        assert_eq!(
+            stats["enp5s0"],
+            NetDevStat {
+                rx_bytes: 1893466744,
+                rx_packets: 2063573,
+                rx_errors: 3,
+                rx_drops: 17,
+                tx_bytes: 142536717,
+                tx_packets: 870458,
+                tx_errors: 0,
+                tx_drops: 2,
+            }
+        );
+        assert_eq!(stats["fwpr100p0"].rx_bytes, 12345678901234);
+        assert_eq!(stats["fwpr100p0"].tx_bytes, 5);
+
+        let stats = parse_net_dev(NET_DEV, |name| !is_virtual_interface(name))?;
+        assert_eq!(
+            stats.keys().collect::<Vec<_>>(),
+            ["enp5s0", "fwpr100p0", "vmbr0"]
+        );
+
+        assert!(parse_net_dev("header\nheader\neth0: 1 2 3\n", |_| true).is_err());
+        assert!(parse_net_dev("header\nheader\neth0 1 2 3\n", |_| true).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_net_dev_rates() -> Result<(), Error> {
+        let previous = parse_net_dev(NET_DEV, |_| true)?;
+        let mut current = previous.clone();
+        current.remove("lo");
+        current.insert("new0".to_string(), NetDevStat::default());
+        let vmbr0 = current.get_mut("vmbr0").unwrap();
+        vmbr0.rx_bytes += 2000;
+        vmbr0.tx_packets += 10;
+        // a 32 bit counter wrapping around
+        let enp5s0 = current.get_mut("enp5s0").unwrap();
+        enp5s0.rx_bytes = 99;
+
+        let rates = net_dev_rates(&previous, &current, Duration::from_secs(2));
+        assert_eq!(
+            rates.keys().collect::<Vec<_>>(),
+            ["enp5s0", "fwpr100p0", "veth100i0", "vmbr0"]
+        );
+        assert_eq!(rates["vmbr0"].rx_bytes, 1000.0);
+        assert_eq!(rates["vmbr0"].tx_packets, 5.0);
+        assert_eq!(rates["vmbr0"].rx_packets, 0.0);
+        assert_eq!(
+            rates["enp5s0"].rx_bytes,
+            (u64::from(u32::MAX) - 1893466744 + 100) as f64 / 2.0
+        );
+        Ok(())
+    }
+}
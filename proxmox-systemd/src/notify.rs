@@ -12,6 +12,7 @@ pub enum SystemdNotify {
     Stopping,
     Status(String),
     MainPid(libc::pid_t),
+    Watchdog,
 }
 
 impl SystemdNotify {
@@ -25,6 +26,7 @@ impl SystemdNotify {
             SystemdNotify::Ready => c"READY=1",
             SystemdNotify::Reloading => c"RELOADING=1",
             SystemdNotify::Stopping => c"STOPPING=1",
+            SystemdNotify::Watchdog => c"WATCHDOG=1",
             SystemdNotify::Status(msg) => {
                 cs = CString::new(msg)?;
                 &cs
@@ -35,10 +35,45 @@ pub struct CalendarEvent {
     pub(crate) year: Vec<DateTimeValue>,
 }
 
+impl CalendarEvent {
+    /// Returns true if the event is calculated in UTC instead of the local timezone.
+    pub fn is_utc(&self) -> bool {
+        self.utc
+    }
+
+    /// Returns the days in a week this event triggers, empty if it triggers on all days.
+    pub fn weekdays(&self) -> WeekDays {
+        self.days
+    }
+}
+
+/// Computes the next timestamp of `event` after `last`, in UTC if `utc` is true and the local
+/// timezone otherwise, regardless of whether `UTC` was part of the event specification.
+///
+/// Returns `None` if the event never triggers again.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn compute_next_event(
+    event: &CalendarEvent,
+    last: i64,
+    utc: bool,
+) -> Result<Option<i64>, Error> {
+    if event.utc == utc {
+        return event.compute_next_event(last);
+    }
+    CalendarEvent {
+        utc,
+        ..event.clone()
+    }
+    .compute_next_event(last)
+}
+
 #[cfg(not(target_arch = "wasm32"))]
 impl CalendarEvent {
     /// Computes the next timestamp after `last`. If `utc` is false, the local
     /// timezone will be used for the calculation.
+    ///
+    /// In local time, points in time skipped when switching to daylight saving time do not
+    /// trigger, and points in time repeated when switching back trigger only once.
     pub fn compute_next_event(&self, last: i64) -> Result<Option<i64>, Error> {
         let last = last + 1; // at least one second later
 
@@ -158,7 +193,7 @@ impl CalendarEvent {
                 }
             }
 
-            let next = t.into_epoch()?;
+            let next = t.into_epoch_not_before(last)?;
             return Ok(Some(next));
         }
     }
@@ -48,6 +48,45 @@ impl TmEditor {
         Ok(epoch)
     }
 
+    /// Converts back into Unix epoch, not earlier than `min`
+    ///
+    /// Local times which occur twice when switching back from daylight saving time are
+    /// converted to the earliest occurrence not before `min`, instead of letting libc guess.
+    pub fn into_epoch_not_before(self, min: i64) -> Result<i64, Error> {
+        if self.utc {
+            return self.into_epoch();
+        }
+
+        let same_time = |t: &libc::tm| {
+            (
+                t.tm_year, t.tm_mon, t.tm_mday, t.tm_hour, t.tm_min, t.tm_sec,
+            ) == (
+                self.t.tm_year,
+                self.t.tm_mon,
+                self.t.tm_mday,
+                self.t.tm_hour,
+                self.t.tm_min,
+                self.t.tm_sec,
+            )
+        };
+
+        let mut best = None;
+        for isdst in [1, 0] {
+            let mut t = self.t;
+            t.tm_isdst = isdst;
+            // mktime adjusts the time if `isdst` is wrong for it
+            let epoch = unsafe { libc::mktime(&mut t) };
+            if epoch != -1 && epoch >= min && same_time(&t) {
+                best = Some(best.map_or(epoch, |best: i64| best.min(epoch)));
+            }
+        }
+
+        match best {
+            Some(epoch) => Ok(epoch),
+            None => self.into_epoch(),
+        }
+    }
+
     /// increases the year by 'years' and resets all smaller fields to their minimum
     pub fn add_years(&mut self, years: libc::c_int) -> Result<(), Error> {
         if years == 0 {
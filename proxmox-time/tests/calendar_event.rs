@@ -0,0 +1,236 @@
+use anyhow::Error;
+
+use proxmox_time::{
+    compute_next_event, epoch_to_rfc3339_utc, parse_rfc3339, verify_calendar_event, CalendarEvent,
+    WeekDays,
+};
+
+const VALID: &[&str] = &[
+    // named shortcuts
+    "minutely",
+    "hourly",
+    "daily",
+    "weekly",
+    "monthly",
+    "yearly",
+    "annually",
+    "quarterly",
+    "semiannually",
+    "semi-annually",
+    "daily UTC",
+    // weekday ranges and lists
+    "mon",
+    "Monday",
+    "mon..fri",
+    "sat,sun",
+    "mon,wed..fri",
+    "fri..mon",
+    "mon..wed,sat 02:30",
+    // time lists and steps
+    "02:30",
+    "2:30:15",
+    "*/15",
+    "*:0/15",
+    "0,12:00",
+    "8..18/2:00",
+    "*:*:0/10",
+    "mon..fri 02:30 UTC",
+    // dates
+    "2024-12-24",
+    "*-12-24 18:00",
+    "*-*-1",
+    "2024..2026-01,07-01",
+];
+
+const INVALID: &[&str] = &[
+    "",
+    "UTC",
+    "never",
+    "mon..",
+    "foo 02:30",
+    "24:00:00",
+    "02:60",
+    "02:30:60",
+    "10..2:00",
+    "mon 02:30 extra",
+    "2024-13-01",
+    "2024-02-32",
+];
+
+/// Each entry is an event, a start time and the next events after it, all in UTC.
+const NEXT_EVENT_TESTS: &[(&str, &str, &[&str])] = &[
+    (
+        "minutely",
+        "2024-05-15T10:20:30Z",
+        &["2024-05-15T10:21:00Z", "2024-05-15T10:22:00Z"],
+    ),
+    (
+        "hourly",
+        "2024-05-15T23:20:00Z",
+        &["2024-05-16T00:00:00Z", "2024-05-16T01:00:00Z"],
+    ),
+    (
+        "daily",
+        "2024-05-15T10:20:00Z",
+        &["2024-05-16T00:00:00Z", "2024-05-17T00:00:00Z"],
+    ),
+    (
+        // 2024-05-15 is a Wednesday
+        "weekly",
+        "2024-05-15T10:20:00Z",
+        &["2024-05-20T00:00:00Z", "2024-05-27T00:00:00Z"],
+    ),
+    (
+        "monthly",
+        "2024-01-31T10:20:00Z",
+        &["2024-02-01T00:00:00Z", "2024-03-01T00:00:00Z"],
+    ),
+    (
+        "quarterly",
+        "2024-05-15T10:20:00Z",
+        &[
+            "2024-07-01T00:00:00Z",
+            "2024-10-01T00:00:00Z",
+            "2025-01-01T00:00:00Z",
+        ],
+    ),
+    (
+        "yearly",
+        "2024-05-15T10:20:00Z",
+        &["2025-01-01T00:00:00Z", "2026-01-01T00:00:00Z"],
+    ),
+    (
+        "mon..fri 02:30",
+        "2024-05-17T02:30:00Z",
+        &["2024-05-20T02:30:00Z", "2024-05-21T02:30:00Z"],
+    ),
+    (
+        "sat,sun 12:00",
+        "2024-05-15T00:00:00Z",
+        &[
+            "2024-05-18T12:00:00Z",
+            "2024-05-19T12:00:00Z",
+            "2024-05-25T12:00:00Z",
+        ],
+    ),
+    (
+        "fri..mon 0:00",
+        "2024-05-14T12:00:00Z",
+        &[
+            "2024-05-17T00:00:00Z",
+            "2024-05-18T00:00:00Z",
+            "2024-05-19T00:00:00Z",
+            "2024-05-20T00:00:00Z",
+            "2024-05-24T00:00:00Z",
+        ],
+    ),
+    (
+        "*/15",
+        "2024-05-15T10:50:00Z",
+        &["2024-05-15T11:00:00Z", "2024-05-15T11:15:00Z"],
+    ),
+    (
+        "*:7/15",
+        "2024-05-15T10:50:00Z",
+        &["2024-05-15T10:52:00Z", "2024-05-15T11:07:00Z"],
+    ),
+    (
+        "8..18/4:00",
+        "2024-05-15T10:20:00Z",
+        &[
+            "2024-05-15T12:00:00Z",
+            "2024-05-15T16:00:00Z",
+            "2024-05-16T08:00:00Z",
+        ],
+    ),
+    (
+        "6,18:00:30",
+        "2024-05-15T10:20:00Z",
+        &["2024-05-15T18:00:30Z", "2024-05-16T06:00:30Z"],
+    ),
+    (
+        "*:*:0/20",
+        "2024-05-15T10:20:50Z",
+        &["2024-05-15T10:21:00Z", "2024-05-15T10:21:20Z"],
+    ),
+    (
+        "*-02-29",
+        "2024-05-15T10:20:00Z",
+        &["2028-02-29T00:00:00Z", "2032-02-29T00:00:00Z"],
+    ),
+    (
+        "*-*-31 23:59",
+        "2024-04-01T00:00:00Z",
+        &["2024-05-31T23:59:00Z", "2024-07-31T23:59:00Z"],
+    ),
+    (
+        "2024..2026-01,07-01",
+        "2024-05-15T10:20:00Z",
+        &["2024-07-01T00:00:00Z", "2025-01-01T00:00:00Z"],
+    ),
+];
+
+fn check(event: &CalendarEvent, spec: &str, start: &str, expected: &[&str]) -> Result<(), Error> {
+    let mut last = parse_rfc3339(start)?;
+    for expected in expected {
+        let next = compute_next_event(event, last, true)?
+            .unwrap_or_else(|| panic!("'{spec}' after {start} never triggers"));
+        assert_eq!(
+            next,
+            parse_rfc3339(expected)?,
+            "'{spec}' after {start}: got {}, expected {expected}",
+            epoch_to_rfc3339_utc(next)?,
+        );
+        last = next;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_parse() {
+    for spec in VALID {
+        if let Err(err) = verify_calendar_event(spec) {
+            panic!("'{spec}' should be valid - {err}");
+        }
+    }
+    for spec in INVALID {
+        assert!(
+            verify_calendar_event(spec).is_err(),
+            "'{spec}' should be invalid",
+        );
+    }
+}
+
+#[test]
+fn test_parsed_event() -> Result<(), Error> {
+    let event: CalendarEvent = "mon..wed,sat 02:30 UTC".parse()?;
+    assert!(event.is_utc());
+    assert_eq!(
+        event.weekdays(),
+        WeekDays::MONDAY | WeekDays::TUESDAY | WeekDays::WEDNESDAY | WeekDays::SATURDAY,
+    );
+
+    let event: CalendarEvent = "daily".parse()?;
+    assert!(!event.is_utc());
+    assert!(event.weekdays().is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_next_event() -> Result<(), Error> {
+    for (spec, start, expected) in NEXT_EVENT_TESTS {
+        let event: CalendarEvent = spec.parse()?;
+        check(&event, spec, start, expected)?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_never() -> Result<(), Error> {
+    for spec in ["2021-02-29", "*-02-30", "2020-01-01"] {
+        let event: CalendarEvent = spec.parse()?;
+        let last = parse_rfc3339("2024-05-15T10:20:00Z")?;
+        assert_eq!(compute_next_event(&event, last, true)?, None, "'{spec}'");
+    }
+    Ok(())
+}
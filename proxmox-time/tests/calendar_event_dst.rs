@@ -0,0 +1,171 @@
+//! Calendar events in local time across daylight saving time transitions.
+//!
+//! This sets `TZ` for the whole process, so it is kept apart from the other tests.
+
+use anyhow::Error;
+
+use proxmox_time::{compute_next_event, epoch_to_rfc3339, parse_rfc3339, CalendarEvent};
+
+/// Central European Time, switching to summer time on the last Sunday of March at 02:00 and
+/// back on the last Sunday of October at 03:00.
+const TZ: &str = "CET-1CEST,M3.5.0,M10.5.0/3";
+
+/// Each entry is an event, a start time and the next events after it.
+const DST_TESTS: &[(&str, &str, &[&str])] = &[
+    // times skipped when switching to summer time do not trigger on that day
+    (
+        "2:30",
+        "2024-03-30T02:30:00+01:00",
+        &["2024-04-01T02:30:00+02:00", "2024-04-02T02:30:00+02:00"],
+    ),
+    (
+        "sun 2:00",
+        "2024-03-25T00:00:00+01:00",
+        &["2024-04-07T02:00:00+02:00", "2024-04-14T02:00:00+02:00"],
+    ),
+    (
+        "hourly",
+        "2024-03-31T00:30:00+01:00",
+        &[
+            "2024-03-31T01:00:00+01:00",
+            "2024-03-31T03:00:00+02:00",
+            "2024-03-31T04:00:00+02:00",
+        ],
+    ),
+    (
+        "*:0/20",
+        "2024-03-31T01:30:00+01:00",
+        &[
+            "2024-03-31T01:40:00+01:00",
+            "2024-03-31T03:00:00+02:00",
+            "2024-03-31T03:20:00+02:00",
+        ],
+    ),
+    // times repeated when switching back to standard time only trigger once
+    (
+        "2:30",
+        "2024-10-26T02:30:00+02:00",
+        &["2024-10-27T02:30:00+02:00", "2024-10-28T02:30:00+01:00"],
+    ),
+    (
+        "hourly",
+        "2024-10-27T01:30:00+02:00",
+        &[
+            "2024-10-27T02:00:00+02:00",
+            "2024-10-27T03:00:00+01:00",
+            "2024-10-27T04:00:00+01:00",
+        ],
+    ),
+    (
+        "*:30",
+        "2024-10-27T01:30:00+02:00",
+        &["2024-10-27T02:30:00+02:00", "2024-10-27T03:30:00+01:00"],
+    ),
+    (
+        "*:50",
+        "2024-10-27T02:40:00+01:00",
+        &["2024-10-27T02:50:00+01:00", "2024-10-27T03:50:00+01:00"],
+    ),
+    // days are calendar days, not 24 hours
+    (
+        "daily",
+        "2024-03-30T12:00:00+01:00",
+        &[
+            "2024-03-31T00:00:00+01:00",
+            "2024-04-01T00:00:00+02:00",
+            "2024-04-02T00:00:00+02:00",
+        ],
+    ),
+    (
+        "daily",
+        "2024-10-26T12:00:00+02:00",
+        &[
+            "2024-10-27T00:00:00+02:00",
+            "2024-10-28T00:00:00+01:00",
+            "2024-10-29T00:00:00+01:00",
+        ],
+    ),
+    (
+        "mon..fri 22:15",
+        "2024-03-29T23:00:00+01:00",
+        &["2024-04-01T22:15:00+02:00", "2024-04-02T22:15:00+02:00"],
+    ),
+    (
+        "monthly",
+        "2024-03-15T00:00:00+01:00",
+        &["2024-04-01T00:00:00+02:00", "2024-05-01T00:00:00+02:00"],
+    ),
+    (
+        "monthly",
+        "2024-09-15T00:00:00+02:00",
+        &[
+            "2024-10-01T00:00:00+02:00",
+            "2024-11-01T00:00:00+01:00",
+            "2024-12-01T00:00:00+01:00",
+        ],
+    ),
+    (
+        "*-04-01 6:00",
+        "2024-01-01T00:00:00+01:00",
+        &["2024-04-01T06:00:00+02:00", "2025-04-01T06:00:00+02:00"],
+    ),
+    // UTC events are not affected by the local timezone
+    (
+        "2:30 UTC",
+        "2024-03-30T02:30:00Z",
+        &["2024-03-31T02:30:00Z", "2024-04-01T02:30:00Z"],
+    ),
+    (
+        "hourly UTC",
+        "2024-10-27T00:30:00Z",
+        &["2024-10-27T01:00:00Z", "2024-10-27T02:00:00Z"],
+    ),
+];
+
+fn check(event: &CalendarEvent, spec: &str, start: &str, expected: &[&str]) -> Result<(), Error> {
+    let mut last = parse_rfc3339(start)?;
+    for expected in expected {
+        let next = event
+            .compute_next_event(last)?
+            .unwrap_or_else(|| panic!("'{spec}' after {start} never triggers"));
+        assert_eq!(
+            next,
+            parse_rfc3339(expected)?,
+            "'{spec}' after {start}: got {}, expected {expected}",
+            epoch_to_rfc3339(next)?,
+        );
+        last = next;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_dst_transitions() -> Result<(), Error> {
+    // must happen before anything uses the local timezone
+    std::env::set_var("TZ", TZ);
+
+    for (spec, start, expected) in DST_TESTS {
+        let event: CalendarEvent = spec.parse()?;
+        check(&event, spec, start, expected)?;
+    }
+
+    // overriding the timezone of the event
+    let event: CalendarEvent = "2:30".parse()?;
+    let last = parse_rfc3339("2024-03-30T02:30:00Z")?;
+    assert_eq!(
+        compute_next_event(&event, last, true)?,
+        Some(parse_rfc3339("2024-03-31T02:30:00Z")?),
+    );
+    assert_eq!(
+        compute_next_event(&event, last, false)?,
+        Some(parse_rfc3339("2024-04-01T02:30:00+02:00")?),
+    );
+
+    let event: CalendarEvent = "2:30 UTC".parse()?;
+    assert_eq!(
+        compute_next_event(&event, last, false)?,
+        Some(parse_rfc3339("2024-04-01T02:30:00+02:00")?),
+    );
+
+    Ok(())
+}
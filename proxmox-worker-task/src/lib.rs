@@ -1,3 +1,7 @@
+use std::collections::HashMap;
+use std::sync::{LazyLock, RwLock};
+use std::time::Duration;
+
 use anyhow::{bail, Error};
 
 /// Worker task abstraction
@@ -26,6 +30,11 @@ pub trait WorkerTaskContext: Send + Sync {
         }
         Ok(())
     }
+
+    /// Account resources used on behalf of the task, e.g. by external commands it ran.
+    ///
+    /// Implementations without resource accounting ignore this.
+    fn add_resource_usage(&self, _usage: &ResourceUsage) {}
 }
 
 /// Convenience implementation:
@@ -45,4 +54,85 @@ impl<T: WorkerTaskContext + ?Sized> WorkerTaskContext for std::sync::Arc<T> {
     fn fail_on_shutdown(&self) -> Result<(), Error> {
         <T as WorkerTaskContext>::fail_on_shutdown(self)
     }
+
+    fn add_resource_usage(&self, usage: &ResourceUsage) {
+        <T as WorkerTaskContext>::add_resource_usage(self, usage)
+    }
+}
+
+/// Resources used by a task, see [`WorkerTaskContext::add_resource_usage`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ResourceUsage {
+    /// CPU time spent in user mode.
+    pub user_time: Duration,
+    /// CPU time spent in kernel mode.
+    pub system_time: Duration,
+    /// Maximum resident set size in KiB.
+    pub max_rss_kib: u64,
+}
+
+impl ResourceUsage {
+    /// Add the usage of `other`, CPU times are summed up, the maximum RSS is the larger one.
+    pub fn add(&mut self, other: &ResourceUsage) {
+        self.user_time += other.user_time;
+        self.system_time += other.system_time;
+        self.max_rss_kib = self.max_rss_kib.max(other.max_rss_kib);
+    }
+}
+
+/// What to do with a new task when its concurrency limit is reached.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QueuePolicy {
+    /// Queue the task and start it as soon as a slot is free.
+    Queue,
+    /// Fail to create the task.
+    Fail,
+}
+
+/// Limits how many tasks of a worker type may run at the same time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ConcurrencyLimit {
+    /// Maximum number of concurrently running tasks.
+    pub max_tasks: usize,
+    /// Apply the limit separately for each worker ID instead of the whole worker type.
+    pub per_worker_id: bool,
+    /// What to do if the limit is reached.
+    pub policy: QueuePolicy,
+}
+
+impl ConcurrencyLimit {
+    /// Create a new limit for all tasks of a worker type.
+    pub const fn new(max_tasks: usize, policy: QueuePolicy) -> Self {
+        Self {
+            max_tasks,
+            per_worker_id: false,
+            policy,
+        }
+    }
+
+    /// Apply the limit separately for each worker ID.
+    pub const fn per_worker_id(mut self, per_worker_id: bool) -> Self {
+        self.per_worker_id = per_worker_id;
+        self
+    }
+}
+
+static CONCURRENCY_LIMITS: LazyLock<RwLock<HashMap<String, ConcurrencyLimit>>> =
+    LazyLock::new(|| RwLock::new(HashMap::new()));
+
+/// Set or remove the concurrency limit for tasks of a worker type.
+///
+/// The limit is honored by worker task implementations when new tasks are started, tasks which
+/// are already running or queued are not affected.
+pub fn set_concurrency_limit(worker_type: &str, limit: Option<ConcurrencyLimit>) {
+    let mut limits = CONCURRENCY_LIMITS.write().unwrap();
+    match limit {
+        Some(limit) => limits.insert(worker_type.to_string(), limit),
+        None => limits.remove(worker_type),
+    };
+}
+
+/// Get the concurrency limit for tasks of a worker type.
+pub fn concurrency_limit(worker_type: &str) -> Option<ConcurrencyLimit> {
+    CONCURRENCY_LIMITS.read().unwrap().get(worker_type).copied()
 }